@@ -0,0 +1,116 @@
+//! Parse and serialization of whole images. `test-data/test.dll` is the small fixture; set
+//! `RUSTBIN_BENCH_LARGE` to the path of a large image (e.g. a system DLL) to bench it too.
+//! Imports are also benched on built images with many of them.
+
+use std::{fs::File, io::{BufReader, Cursor}, sync::Arc};
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use rustbin::pe::{builder::PeBuilder, file::MachineType, ser::{full::FullPeImage, min::MinPeImage}, PeImage};
+
+fn paths() -> Vec<(String, String)> {
+    let mut paths = vec![("small".to_string(), "test-data/test.dll".to_string())];
+    if let Ok(path) = std::env::var("RUSTBIN_BENCH_LARGE") {
+        paths.push(("large".to_string(), path));
+    }
+    paths
+}
+
+fn fixtures() -> Vec<(String, Vec<u8>)> {
+    paths().into_iter().map(|(name, path)| (name, std::fs::read(path).unwrap())).collect()
+}
+
+fn bench_headers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_headers_only");
+    for (name, bytes) in fixtures() {
+        //Shared, so copying the input doesn't hide the cost of the headers.
+        let bytes: Arc<[u8]> = bytes.into();
+        group.bench_with_input(BenchmarkId::new("memory", name), &bytes, |b, bytes| {
+            b.iter(|| {
+                let mut pe = PeImage::new(Box::new(Cursor::new(black_box(bytes.clone()))));
+                pe.parse_headers_only(0).unwrap();
+                pe
+            })
+        });
+    }
+    //Each seek of a `BufReader` drops its buffer, so small reads cost a system call each.
+    for (name, path) in paths() {
+        group.bench_with_input(BenchmarkId::new("file", name), &path, |b, path| {
+            b.iter(|| {
+                let file = File::open(black_box(path)).unwrap();
+                let mut pe = PeImage::new(Box::new(BufReader::new(file)));
+                pe.parse_headers_only(0).unwrap();
+                pe
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_full(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_all_headers");
+    for (name, bytes) in fixtures() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &bytes, |b, bytes| {
+            b.iter(|| PeImage::parse_bytes(black_box(bytes.clone()), 0).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_imports(c: &mut Criterion) {
+    let functions: Vec<String> = (0..64).map(|i| format!("Function{i}")).collect();
+    let functions: Vec<&str> = functions.iter().map(String::as_str).collect();
+    let builder = (0..64).fold(PeBuilder::new(MachineType::AMD64), |builder, i| {
+        builder.with_import(&format!("module{i}.dll"), &functions)
+    });
+    let distinct = builder.build().unwrap();
+
+    //All descriptors point to the ILT of the first, so the names read repeat, as in images
+    //whose modules share names; the cache reads each once.
+    let mut shared = distinct.clone();
+    let pe = PeImage::parse_bytes(distinct.clone(), 0).unwrap();
+    let ilt = pe.imports.value[0].value.ilt.value.to_le_bytes();
+    for desc in &pe.imports.value {
+        let at = desc.value.ilt.offset.0 as usize;
+        shared[at..at + 4].copy_from_slice(&ilt);
+    }
+
+    //Read from files, where each read seeks a `BufReader` and costs a system call.
+    let mut group = c.benchmark_group("parse_import_directory");
+    for (name, bytes) in [("distinct", distinct), ("shared", shared)] {
+        let path = std::env::temp_dir().join(format!("rustbin-bench-{name}-{}.exe", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(name), &path, |b, path| {
+            b.iter_batched(
+                || {
+                    let mut pe = PeImage::new(Box::new(BufReader::new(File::open(path).unwrap())));
+                    pe.parse_headers_only(0).unwrap();
+                    pe
+                },
+                |mut pe| {
+                    pe.parse_import_directory().unwrap();
+                    pe
+                },
+                BatchSize::SmallInput,
+            )
+        });
+        std::fs::remove_file(path).unwrap();
+    }
+    group.finish();
+}
+
+fn bench_json(c: &mut Criterion) {
+    let mut group = c.benchmark_group("json");
+    for (name, bytes) in fixtures() {
+        let pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        group.bench_function(BenchmarkId::new("minimal", &name), |b| {
+            b.iter(|| serde_json::to_string(&MinPeImage::from(black_box(&pe))).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("full", &name), |b| {
+            b.iter(|| serde_json::to_string(&FullPeImage::from(black_box(&pe))).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_headers, bench_full, bench_imports, bench_json);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rustbin::{
+    pe::{section::{self, IndexedSections, SectionHeader, SectionLookup, SectionMap, SectionTable}, PeImage},
+    types::HeaderField,
+};
+
+fn make_sections(count: u32) -> SectionTable {
+    (0..count).map(|i| HeaderField {
+        value: SectionHeader {
+            virtual_address: HeaderField { value: 0x1000 + i * 0x2000, ..Default::default() },
+            virtual_size: HeaderField { value: 0x1800, ..Default::default() },
+            raw_data_ptr: HeaderField { value: 0x400 + i * 0x1800, ..Default::default() },
+            sizeof_raw_data: HeaderField { value: 0x1800, ..Default::default() },
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .collect()
+}
+
+fn bench_rva_to_offset(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rva_to_offset");
+
+    for count in [6u32, 32, 96] {
+        let sections = make_sections(count);
+        let map = SectionMap::new(&sections);
+        let indexed = IndexedSections::new(&sections, &map);
+        //Spread lookups over all sections, like a long import table would.
+        let rvas: Vec<u32> = (0..1024u32).map(|i| 0x1000 + (i * 0x1f3 * 0x2000 / 1024) % (count * 0x2000)).collect();
+
+        group.bench_with_input(BenchmarkId::new("linear", count), &rvas, |b, rvas| {
+            b.iter(|| rvas.iter().filter_map(|rva| section::rva_to_offset(&sections, black_box(*rva))).count())
+        });
+
+        group.bench_with_input(BenchmarkId::new("indexed", count), &rvas, |b, rvas| {
+            b.iter(|| rvas.iter().filter_map(|rva| indexed.rva_to_offset(black_box(*rva))).count())
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let bytes = std::fs::read("test-data/test.dll").unwrap();
+    c.bench_function("parse test.dll", |b| {
+        b.iter(|| PeImage::parse_bytes(black_box(bytes.clone()), 0).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_rva_to_offset, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,22 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "ffi")]
+    header();
+}
+
+/// Write the C header for `src/ffi.rs` to `$OUT_DIR/rustbin.h`; the source tree is not touched.
+/// A test checks that `include/rustbin.h` matches it.
+#[cfg(feature = "ffi")]
+fn header() {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let config = cbindgen::Config::from_file(format!("{dir}/cbindgen.toml")).expect("invalid cbindgen.toml");
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(format!("{dir}/src/ffi.rs"))
+        .generate()
+        .expect("failed to generate C header")
+        .write_to_file(format!("{}/rustbin.h", std::env::var("OUT_DIR").unwrap()));
+}
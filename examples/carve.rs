@@ -0,0 +1,40 @@
+//! Carve a PE image out of a larger buffer, like a memory dump.
+//!
+//! Usage: `cargo run --example carve [dump [base]]`. `base` is the offset (hex) of the dump in
+//! the input it was taken from, 0 by default. Without a dump, `test-data/test.dll` is embedded
+//! in filler bytes to make one.
+
+extern crate rustbin;
+
+use std::{env, fs};
+
+use rustbin::{pe::{embedded::find_candidates, PeImage}, utils::FragmentReader};
+
+fn main() {
+    let dump = match env::args().nth(1) {
+        Some(path) => fs::read(path).unwrap(),
+        None => {
+            let mut dump = vec![0xccu8; 0x3000];
+            dump.extend(fs::read("test-data/test.dll").unwrap());
+            dump.extend([0xcc; 0x1000]);
+            dump
+        },
+    };
+    let base = env::args().nth(2)
+        .map(|b| u64::from_str_radix(b.trim_start_matches("0x"), 16).unwrap())
+        .unwrap_or(0);
+
+    //`PeImage::find_embedded_pes` does the same for any reader.
+    for found in find_candidates(&dump) {
+        //Offsets given to the reader, `pos` included, are offsets in the input.
+        let pos = base + found;
+        let reader = FragmentReader::new(dump.clone(), base);
+        match PeImage::parse_readable(Box::new(reader), pos) {
+            Ok(pe) => println!(
+                "PE at {pos:#x}: {} sections, {} import modules, {} exports",
+                pe.sections.value.len(), pe.imports.value.len(), pe.exports.value.exports.len()
+            ),
+            Err(err) => println!("PE at {pos:#x}: {}", err.chain()),
+        }
+    }
+}
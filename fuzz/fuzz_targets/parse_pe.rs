@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustbin::pe::PeImage;
+
+//Default limits; parsing may fail but must never panic or allocate without bound.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut pe) = PeImage::parse_bytes(data.to_vec(), 0) {
+        let mut out = String::new();
+        let _ = pe.format_basic_headers(&mut out);
+        let _ = pe.format_sections(&mut out);
+        let _ = pe.format_imports(&mut out);
+        let _ = pe.format_exports(&mut out);
+        let _ = pe.format_relocations(&mut out);
+        let _ = pe.format_resource_tree(&mut out, &String::from("  "), 1);
+        let _ = pe.analyze();
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustbin::pe::hardened;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut pe) = hardened::parse_bytes(data.to_vec()) {
+        let _ = pe.export_pages(64).count();
+        let _ = pe.analyze();
+    }
+});
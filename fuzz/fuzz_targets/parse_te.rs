@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustbin::pe::hardened;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut te) = hardened::parse_te_bytes(data.to_vec()) {
+        let mut out = String::new();
+        let _ = te.format_basic_headers(&mut out);
+        let _ = te.format_sections(&mut out);
+        let _ = te.analyze();
+    }
+});
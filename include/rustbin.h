@@ -0,0 +1,190 @@
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#ifndef RUSTBIN_H
+#define RUSTBIN_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * A parsed image with the strings handed out for it.
+ */
+typedef struct RustbinPe RustbinPe;
+
+typedef struct {
+  uint16_t machine;
+  uint16_t characteristics;
+  uint32_t timestamp;
+  uint16_t number_of_sections;
+  /**
+   * `0x10b` for PE32, `0x20b` for PE32+.
+   */
+  uint16_t magic;
+  uint16_t subsystem;
+  uint16_t dll_characteristics;
+  uint64_t image_base;
+  uint32_t entry_point;
+  uint32_t size_of_image;
+  uint32_t checksum;
+} RustbinHeaders;
+
+typedef struct {
+  /**
+   * Name as stored, NUL terminated.
+   */
+  char name[9];
+  uint32_t virtual_address;
+  uint32_t virtual_size;
+  uint32_t raw_data_ptr;
+  uint32_t raw_data_size;
+  uint32_t characteristics;
+} RustbinSection;
+
+typedef struct {
+  /**
+   * Empty for imports by ordinal; owned by the handle.
+   */
+  const char *name;
+  uint16_t hint;
+  /**
+   * Valid if `by_ordinal`.
+   */
+  uint16_t ordinal;
+  bool by_ordinal;
+  uint32_t iat_rva;
+} RustbinImport;
+
+typedef struct {
+  /**
+   * Owned by the handle.
+   */
+  const char *name;
+  uint16_t ordinal;
+  uint32_t address;
+} RustbinExport;
+
+/**
+ * Message of the last error on this thread; empty if there was none. Valid until the next
+ * call that fails.
+ */
+const char *rustbin_last_error(void);
+
+/**
+ * Parse the PE image at `path`. Returns NULL on error.
+ *
+ * # Safety
+ * `path` must be a NUL terminated string.
+ */
+RustbinPe *rustbin_pe_open(const char *path);
+
+/**
+ * Parse a PE image from `len` bytes at `data`, which are copied. Returns NULL on error.
+ *
+ * # Safety
+ * `data` must point to `len` readable bytes.
+ */
+RustbinPe *rustbin_pe_parse(const uint8_t *data, size_t len);
+
+/**
+ * Free a handle and every string returned for it. NULL is ignored.
+ *
+ * # Safety
+ * `pe` must be NULL or a handle not freed yet.
+ */
+void rustbin_pe_free(RustbinPe *pe);
+
+/**
+ * Fill `out` with fields of the file and optional headers.
+ *
+ * # Safety
+ * `pe` must be a valid handle and `out` writable.
+ */
+bool rustbin_pe_headers(const RustbinPe *pe, RustbinHeaders *out);
+
+/**
+ * Number of section headers; 0 for a NULL handle.
+ *
+ * # Safety
+ * `pe` must be NULL or a valid handle.
+ */
+size_t rustbin_pe_section_count(const RustbinPe *pe);
+
+/**
+ * Fill `out` with section header `index`.
+ *
+ * # Safety
+ * `pe` must be a valid handle and `out` writable.
+ */
+bool rustbin_pe_section(const RustbinPe *pe, size_t index, RustbinSection *out);
+
+/**
+ * Number of modules in the import directory; 0 for a NULL handle.
+ *
+ * # Safety
+ * `pe` must be NULL or a valid handle.
+ */
+size_t rustbin_pe_import_module_count(const RustbinPe *pe);
+
+/**
+ * Name of import module `module`, owned by the handle; NULL if there is no such module.
+ *
+ * # Safety
+ * `pe` must be NULL or a valid handle.
+ */
+const char *rustbin_pe_import_module_name(const RustbinPe *pe, size_t module);
+
+/**
+ * Number of imports from module `module`.
+ *
+ * # Safety
+ * `pe` must be NULL or a valid handle.
+ */
+size_t rustbin_pe_import_count(const RustbinPe *pe, size_t module);
+
+/**
+ * Fill `out` with import `index` of module `module`.
+ *
+ * # Safety
+ * `pe` must be a valid handle and `out` writable.
+ */
+bool rustbin_pe_import(const RustbinPe *pe, size_t module, size_t index, RustbinImport *out);
+
+/**
+ * Number of named exports.
+ *
+ * # Safety
+ * `pe` must be NULL or a valid handle.
+ */
+size_t rustbin_pe_export_count(const RustbinPe *pe);
+
+/**
+ * Fill `out` with export `index`.
+ *
+ * # Safety
+ * `pe` must be a valid handle and `out` writable.
+ */
+bool rustbin_pe_export(const RustbinPe *pe, size_t index, RustbinExport *out);
+
+#if defined(RUSTBIN_JSON)
+/**
+ * The image as minimal JSON, or full JSON with offsets of every field if `full`. Free the
+ * result with `rustbin_string_free`; NULL on error.
+ *
+ * # Safety
+ * `pe` must be a valid handle.
+ */
+char *rustbin_pe_to_json(const RustbinPe *pe, bool full);
+#endif
+
+/**
+ * Free a string returned by `rustbin_pe_to_json`. NULL is ignored.
+ *
+ * # Safety
+ * `s` must be NULL or a string from this library, not freed yet.
+ */
+void rustbin_string_free(char *s);
+
+#endif /* RUSTBIN_H */
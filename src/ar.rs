@@ -0,0 +1,342 @@
+//! Archives (`!<arch>\n`): static libraries of COFF objects, and import libraries of import objects.
+//!
+//! Each member has a 60 byte text header followed by its data, padded to an even offset.
+//! Special members are the linker members (`/`, symbol indexes) and the long names member
+//! (`//`), which holds names longer than the 16 bytes of the header; members refer to them as
+//! `/<offset>`. BSD archives keep long names at the start of the data instead (`#1/<length>`).
+
+use std::{fmt::Display, fs::File, io::{BufReader, Cursor}};
+
+use derivative::Derivative;
+use serde::Serialize;
+
+use crate::{pe::{check_alloc, coff::{self, ImportObject, ObjectFile}, PeError, DEFAULT_MAX_ALLOC}, types::{BufReadExt, ReadExtError}};
+
+pub const MAGIC: &[u8; 8] = b"!<arch>\n";
+pub const MEMBER_HEADER_LENGTH: u64 = 60;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ArError {
+    #[error("not an archive; it doesn't start with `!<arch>\\n`")]
+    InvalidMagic,
+
+    #[error("invalid member header at offset {offset:#x}; {reason}")]
+    #[non_exhaustive]
+    InvalidMember {
+        offset: u64,
+        reason: String,
+    },
+
+    #[error("while parsing member {name} at {offset:#x}")]
+    #[non_exhaustive]
+    Member {
+        name: String,
+        offset: u64,
+        #[source]
+        source: PeError,
+    },
+
+    #[error("no member at index {0}")]
+    NoMember(usize),
+
+    #[error(transparent)]
+    ReadExt(#[from] ReadExtError),
+
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+
+    #[error(transparent)]
+    PE(#[from] PeError),
+}
+
+impl ArError {
+    /// The error followed by its sources, on one line. See `PeError::chain`.
+    pub fn chain(&self) -> String {
+        match self {
+            Self::Member { source, .. } => format!("{self}: {}", source.chain()),
+            Self::PE(err) => err.chain(),
+            err => err.to_string(),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ArError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum MemberKind {
+    /// Symbol index, `/`; the second one is Microsoft's sorted index.
+    LinkerMember,
+    /// Table of long member names, `//`.
+    LongNames,
+    ImportObject,
+    Object,
+    /// Anything else, e.g. objects which are not COFF.
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArchiveMember {
+    /// Name as in the header, trimmed, e.g. `/`, `/123` or `foo.obj/`.
+    pub raw_name: String,
+    /// Name resolved through the long names, without the trailing `/`.
+    pub name: String,
+    /// Modification time, seconds since the Unix epoch.
+    pub date: u64,
+    /// File mode, from octal.
+    pub mode: u32,
+    pub header_offset: u64,
+    /// Offset of the data.
+    pub offset: u64,
+    pub size: u64,
+    pub kind: MemberKind,
+}
+
+impl Display for ArchiveMember {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{ {}, {:?}, Offset: {:#x}, Size: {:#x} }}", self.name, self.kind, self.offset, self.size)
+    }
+}
+
+/// Trimmed text of a header field.
+fn field_str(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim_end().to_string()
+}
+
+/// Decimal (or octal) number of a header field; blank is 0.
+fn field_num(bytes: &[u8], radix: u32, name: &str, offset: u64) -> Result<u64> {
+    let text = field_str(bytes);
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(&text, radix)
+        .map_err(|_| ArError::InvalidMember { offset, reason: format!("{name} '{text}' is not a number") })
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct Archive {
+    pub members: Vec<ArchiveMember>,
+    /// Symbols of the first linker member, with the header offset of the member defining each.
+    pub symbols: Vec<(String, u64)>,
+    /// Data of the long names member, if any.
+    #[derivative(Debug="ignore")]
+    pub long_names: Vec<u8>,
+
+    #[derivative(Debug="ignore")]
+    reader: Box<dyn BufReadExt>,
+}
+
+impl Archive {
+    pub fn parse_file(file: File) -> Result<Self> {
+        Self::parse_readable(Box::new(BufReader::new(file)))
+    }
+
+    pub fn parse_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Self::parse_readable(Box::new(Cursor::new(bytes)))
+    }
+
+    /// Parse member headers, the first linker member and the long names member from `reader`.
+    pub fn parse_readable(mut reader: Box<dyn BufReadExt>) -> Result<Self> {
+        let end = reader.end_offset()?;
+        if end < MAGIC.len() as u64 || reader.read_bytes_at_offset(0, MAGIC.len())? != MAGIC {
+            return Err(ArError::InvalidMagic);
+        }
+
+        let mut archive = Self { members: Vec::new(), symbols: Vec::new(), long_names: Vec::new(), reader };
+        let mut offset = MAGIC.len() as u64;
+        while offset + MEMBER_HEADER_LENGTH <= end {
+            let member = archive.read_member(offset, end)?;
+            offset = (member.offset + member.size + 1) & !1;
+
+            match member.kind {
+                MemberKind::LongNames => {
+                    check_alloc("LongNames", member.size, DEFAULT_MAX_ALLOC)?;
+                    archive.long_names = archive.reader.read_bytes_at_offset(member.offset, member.size as usize)?;
+                },
+                MemberKind::LinkerMember if archive.members.is_empty() => {
+                    check_alloc("LinkerMember", member.size, DEFAULT_MAX_ALLOC)?;
+                    let data = archive.reader.read_bytes_at_offset(member.offset, member.size as usize)?;
+                    archive.symbols = parse_linker_member(&data);
+                },
+                _ => {},
+            }
+            archive.members.push(member);
+        }
+
+        //Names could be resolved only after the long names member was read.
+        let long_names = std::mem::take(&mut archive.long_names);
+        for member in archive.members.iter_mut() {
+            if let Some(name) = member.raw_name.strip_prefix('/').and_then(|n| n.parse::<usize>().ok()).and_then(|n| long_name_at(&long_names, n)) {
+                member.name = name;
+            }
+        }
+        archive.long_names = long_names;
+
+        Ok(archive)
+    }
+
+    fn read_member(&mut self, header_offset: u64, end: u64) -> Result<ArchiveMember> {
+        let header = self.reader.read_bytes_at_offset(header_offset, MEMBER_HEADER_LENGTH as usize)?;
+        if &header[58..60] != b"`\n" {
+            return Err(ArError::InvalidMember { offset: header_offset, reason: "header doesn't end with '`\\n'".into() });
+        }
+
+        let raw_name = field_str(&header[0..16]);
+        let mut offset = header_offset + MEMBER_HEADER_LENGTH;
+        let mut size = field_num(&header[48..58], 10, "size", header_offset)?;
+        if offset + size > end {
+            return Err(ArError::InvalidMember { offset: header_offset, reason: format!("size {size:#x} is beyond the end of input") });
+        }
+
+        let mut name = raw_name.trim_end_matches('/').to_string();
+        let kind = match raw_name.as_str() {
+            "/" | "__.SYMDEF" | "__.SYMDEF SORTED" => MemberKind::LinkerMember,
+            "//" => MemberKind::LongNames,
+            _ => {
+                if let Some(len) = raw_name.strip_prefix("#1/").and_then(|n| n.parse::<u64>().ok()).filter(|&n| n <= size) {
+                    name = field_str(&self.reader.read_bytes_at_offset(offset, len as usize)?).trim_end_matches('\0').to_string();
+                    offset += len;
+                    size -= len;
+                }
+
+                let head = self.reader.read_bytes_at_offset(offset, size.min(coff::IMPORT_OBJECT_HEADER_LENGTH) as usize)?;
+                if coff::is_import_object(&head) {
+                    MemberKind::ImportObject
+                } else if head.len() >= 2 && u16::from_le_bytes([head[0], head[1]]) != 0 && head.len() as u64 == coff::IMPORT_OBJECT_HEADER_LENGTH {
+                    //Objects start with the machine; can't tell more without parsing.
+                    MemberKind::Object
+                } else {
+                    MemberKind::Other
+                }
+            },
+        };
+
+        Ok(ArchiveMember {
+            raw_name,
+            name,
+            date: field_num(&header[16..28], 10, "date", header_offset)?,
+            mode: field_num(&header[40..48], 8, "mode", header_offset)? as u32,
+            header_offset,
+            offset,
+            size,
+            kind,
+        })
+    }
+
+    /// Data of member `index`.
+    pub fn member_data(&mut self, index: usize) -> Result<Vec<u8>> {
+        let member = self.members.get(index).ok_or(ArError::NoMember(index))?;
+        let (offset, size) = (member.offset, member.size);
+        check_alloc("ArchiveMember", size, DEFAULT_MAX_ALLOC)?;
+        Ok(self.reader.read_bytes_at_offset(offset, size as usize)?)
+    }
+
+    /// Parse member `index` as a COFF object; offsets are in the archive.
+    pub fn parse_object(&mut self, index: usize) -> Result<ObjectFile> {
+        let data = self.member_data(index)?;
+        let member = &self.members[index];
+        ObjectFile::parse_bytes(&data, member.offset).map_err(|source| self.member_error(index, source))
+    }
+
+    /// Parse member `index` as an import object; offsets are in the archive.
+    pub fn parse_import_object(&mut self, index: usize) -> Result<ImportObject> {
+        let data = self.member_data(index)?;
+        let member = &self.members[index];
+        ImportObject::parse_bytes(&data, member.offset).map_err(|source| self.member_error(index, source))
+    }
+
+    /// Import objects of all members, for import libraries.
+    pub fn import_objects(&mut self) -> Result<Vec<ImportObject>> {
+        (0..self.members.len())
+            .filter(|&i| self.members[i].kind == MemberKind::ImportObject)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|i| self.parse_import_object(i))
+            .collect()
+    }
+
+    fn member_error(&self, index: usize, source: PeError) -> ArError {
+        let member = &self.members[index];
+        ArError::Member { name: member.name.clone(), offset: member.header_offset, source }
+    }
+}
+
+impl Display for Archive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Members: [")?;
+        for member in &self.members {
+            writeln!(f, "  {member}")?;
+        }
+        write!(f, "]\nSymbols: {}", self.symbols.len())
+    }
+}
+
+/// Name at `offset` of the long names member; ends with `/\n` (GNU) or NUL (Microsoft).
+fn long_name_at(long_names: &[u8], offset: usize) -> Option<String> {
+    let tail = long_names.get(offset..)?;
+    let end = tail.iter().position(|&b| b == 0 || b == b'\n').unwrap_or(tail.len());
+    Some(String::from_utf8_lossy(&tail[..end]).trim_end_matches('/').to_string())
+}
+
+/// Symbols of the first linker member: a big endian count, as many member offsets, then as
+/// many NUL terminated names. Entries beyond the data are dropped.
+fn parse_linker_member(data: &[u8]) -> Vec<(String, u64)> {
+    let be_u32 = |at: usize| data.get(at..at + 4).map(|d| u32::from_be_bytes([d[0], d[1], d[2], d[3]]));
+    let Some(count) = be_u32(0) else {
+        return Vec::new();
+    };
+
+    let names_start = (count as usize).saturating_add(1).saturating_mul(4);
+    let names = data.get(names_start..).unwrap_or_default().split(|&b| b == 0);
+    (0..count as usize)
+        .map_while(|i| be_u32(4 + i * 4))
+        .zip(names)
+        .map(|(offset, name)| (String::from_utf8_lossy(name).into_owned(), offset as u64))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Archive, ArError, MemberKind, MAGIC};
+
+    fn member(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = format!("{name:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", 0, 0, 0, 644, data.len()).into_bytes();
+        bytes.extend(data);
+        if data.len() % 2 == 1 {
+            bytes.push(b'\n');
+        }
+        bytes
+    }
+
+    #[test]
+    fn parse_members() {
+        let import = [&[0u8, 0, 0xFF, 0xFF, 0, 0, 0x64, 0x86, 0, 0, 0, 0, 19, 0, 0, 0, 0, 0, 0x04, 0][..], b"Sleep\0KERNEL32.dll\0"].concat();
+        let mut linker = Vec::new();
+        linker.extend(1u32.to_be_bytes());
+        linker.extend(0x44u32.to_be_bytes());
+        linker.extend(b"__imp_Sleep\0");
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(member("/", &linker));
+        bytes.extend(member("//", b"a_rather_long_member_name.obj/\n"));
+        bytes.extend(member("/0", &import));
+        bytes.extend(member("#1/8", b"bsd.obj\0\x64\x86\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0"));
+
+        let mut archive = Archive::parse_bytes(bytes).unwrap();
+        let kinds: Vec<_> = archive.members.iter().map(|m| m.kind).collect();
+        assert_eq!(kinds, vec![MemberKind::LinkerMember, MemberKind::LongNames, MemberKind::ImportObject, MemberKind::Object]);
+        assert_eq!(archive.members[2].name, "a_rather_long_member_name.obj");
+        assert_eq!(archive.members[2].mode, 0o644);
+        assert_eq!(archive.members[3].name, "bsd.obj");
+        assert_eq!(archive.members[3].size, 20);
+        assert_eq!(archive.symbols, vec![("__imp_Sleep".to_string(), 0x44)]);
+
+        let imports = archive.import_objects().unwrap();
+        assert_eq!((imports[0].symbol.as_str(), imports[0].dll.as_str()), ("Sleep", "KERNEL32.dll"));
+
+        let err = archive.parse_import_object(3).unwrap_err();
+        assert!(err.chain().starts_with("while parsing member bsd.obj at "), "{}", err.chain());
+
+        assert!(matches!(Archive::parse_bytes(b"MZ".to_vec()), Err(ArError::InvalidMagic)));
+    }
+}
@@ -0,0 +1,141 @@
+//! What is supported for each format, so front-ends can hide views instead of guessing from errors.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Format {
+    PE,
+    TE,
+    /// COFF object files.
+    Coff,
+    /// Static and import libraries.
+    Archive,
+}
+
+/// Parts of an image which can be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Structure {
+    /// Fixed headers: DOS, NT, file and optional headers of PE, the header of TE, the file
+    /// header of COFF objects.
+    Headers,
+    DataDirectories,
+    Sections,
+    Imports,
+    DelayImports,
+    BoundImports,
+    Exports,
+    Relocations,
+    Resources,
+    VersionInfo,
+    LoadConfig,
+    Debug,
+    /// Hybrid metadata of ARM64EC/ARM64X images.
+    Chpe,
+    RichHeader,
+    /// COFF symbol and string tables.
+    Symbols,
+    /// Members, linker member symbols and import objects of archives.
+    ArchiveMembers,
+    /// Reports of `analyze`.
+    Analysis,
+    /// Import resolution against other modules.
+    Dependencies,
+}
+
+/// Forms the parsed image can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Output {
+    Text,
+    Debug,
+    MinimalJson,
+    FullJson,
+    /// Compact fixed-schema summary, `pe::ser::triage`.
+    TriageJson,
+    /// Strings of high-signal structures as a YARA rule.
+    Yara,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FormatCapabilities {
+    pub format: Format,
+    pub structures: Vec<Structure>,
+    pub outputs: Vec<Output>,
+}
+
+impl FormatCapabilities {
+    pub fn of(format: Format) -> Self {
+        use Structure::*;
+
+        let json = |outputs: &[Output]| if cfg!(feature="json") { outputs.to_vec() } else { Vec::new() };
+        let (structures, extra_outputs) = match format {
+            Format::PE => (
+                vec![Headers, DataDirectories, Sections, Imports, DelayImports, BoundImports, Exports,
+                    Relocations, Resources, VersionInfo, LoadConfig, Debug, Chpe, RichHeader, Symbols, Analysis, Dependencies],
+                [vec![Output::Yara], json(&[Output::MinimalJson, Output::FullJson, Output::TriageJson])].concat(),
+            ),
+            Format::TE => (
+                vec![Headers, Sections, Relocations, Analysis],
+                json(&[Output::MinimalJson]),
+            ),
+            Format::Coff => (vec![Headers, Sections, Symbols], Vec::new()),
+            Format::Archive => (vec![ArchiveMembers], Vec::new()),
+        };
+
+        let mut outputs = vec![Output::Text, Output::Debug];
+        outputs.extend(extra_outputs);
+        Self { format, structures, outputs }
+    }
+
+    #[inline]
+    pub fn supports(&self, structure: Structure) -> bool {
+        self.structures.contains(&structure)
+    }
+
+    #[inline]
+    pub fn supports_output(&self, output: Output) -> bool {
+        self.outputs.contains(&output)
+    }
+}
+
+impl Display for FormatCapabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:?}:", self.format)?;
+        writeln!(f, "  Structures: {:?}", self.structures)?;
+        write!(f, "  Outputs: {:?}", self.outputs)
+    }
+}
+
+/// Supported structures and outputs of every format.
+pub fn capabilities() -> Vec<FormatCapabilities> {
+    [Format::PE, Format::TE, Format::Coff, Format::Archive].into_iter().map(FormatCapabilities::of).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{capabilities, Format, FormatCapabilities, Output, Structure};
+
+    #[test]
+    fn matrix() {
+        let all = capabilities();
+        assert_eq!(all.iter().map(|c| c.format).collect::<Vec<_>>(), vec![Format::PE, Format::TE, Format::Coff, Format::Archive]);
+
+        let te = FormatCapabilities::of(Format::TE);
+        assert!(te.supports(Structure::Relocations));
+        assert!(!te.supports(Structure::Imports));
+        assert!(!te.supports_output(Output::FullJson));
+        assert!(!te.supports_output(Output::TriageJson));
+        assert_eq!(te.supports_output(Output::MinimalJson), cfg!(feature="json"));
+
+        let pe = &all[0];
+        assert!(pe.supports(Structure::Imports) && pe.supports(Structure::Chpe));
+        assert!(pe.supports_output(Output::Text) && pe.supports_output(Output::Yara));
+        assert!(!te.supports_output(Output::Yara));
+
+        let archive = FormatCapabilities::of(Format::Archive);
+        assert!(archive.supports(Structure::ArchiveMembers) && !archive.supports(Structure::Headers));
+        assert_eq!(archive.outputs, vec![Output::Text, Output::Debug]);
+        assert!(pe.to_string().starts_with("PE:\n  Structures: [Headers,"));
+    }
+}
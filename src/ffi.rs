@@ -0,0 +1,473 @@
+//! C ABI over `PeImage`, to embed the parser in tools not written in Rust. Needs the `ffi`
+//! feature; the C header `include/rustbin.h` is generated from this module with cbindgen.
+//!
+//! An image is parsed into an opaque `RustbinPe` handle, which owns every string returned for
+//! it. Functions returning a handle or `false` set an error message read with
+//! `rustbin_last_error`. Panics are caught at the boundary and returned the same way.
+
+use std::{cell::RefCell, ffi::{c_char, CStr, CString}, fs::File, panic::{self, AssertUnwindSafe}, path::Path, ptr, slice};
+
+use crate::pe::PeImage;
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_error(message: impl Into<Vec<u8>>) {
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|err| *err.borrow_mut() = message);
+}
+
+/// Run `f`, returning `failed` with the panic as the last error if it panics, so no panic
+/// unwinds into the caller.
+fn guard<T>(failed: T, f: impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(payload) => {
+            let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            set_error(format!("panicked: {message}"));
+            failed
+        },
+    }
+}
+
+/// `CString` of `s`, cut at the first NUL.
+fn c_string(s: &str) -> CString {
+    let bytes = s.as_bytes();
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    CString::new(&bytes[..end]).unwrap_or_default()
+}
+
+/// A parsed image with the strings handed out for it.
+pub struct RustbinPe {
+    pe: PeImage,
+    /// Import modules with the names of their imports; empty for imports by ordinal.
+    modules: Vec<(CString, Vec<CString>)>,
+    exports: Vec<CString>,
+}
+
+impl RustbinPe {
+    fn new(pe: PeImage) -> Self {
+        let modules = pe.imports.value.iter()
+            .map(|id| {
+                let names = id.value.imports.iter()
+                    .map(|i| i.import_name().map(|n| c_string(&n.name.value)).unwrap_or_default())
+                    .collect();
+                (c_string(id.value.name.as_deref().unwrap_or_default()), names)
+            })
+            .collect();
+        let exports = pe.exports.value.exports.iter().map(|e| c_string(&e.name.value)).collect();
+        Self { pe, modules, exports }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RustbinHeaders {
+    pub machine: u16,
+    pub characteristics: u16,
+    pub timestamp: u32,
+    pub number_of_sections: u16,
+    /// `0x10b` for PE32, `0x20b` for PE32+.
+    pub magic: u16,
+    pub subsystem: u16,
+    pub dll_characteristics: u16,
+    pub image_base: u64,
+    pub entry_point: u32,
+    pub size_of_image: u32,
+    pub checksum: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RustbinSection {
+    /// Name as stored, NUL terminated.
+    pub name: [c_char; 9],
+    pub virtual_address: u32,
+    pub virtual_size: u32,
+    pub raw_data_ptr: u32,
+    pub raw_data_size: u32,
+    pub characteristics: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RustbinImport {
+    /// Empty for imports by ordinal; owned by the handle.
+    pub name: *const c_char,
+    pub hint: u16,
+    /// Valid if `by_ordinal`.
+    pub ordinal: u16,
+    pub by_ordinal: bool,
+    pub iat_rva: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RustbinExport {
+    /// Owned by the handle.
+    pub name: *const c_char,
+    pub ordinal: u16,
+    pub address: u32,
+}
+
+/// Message of the last error on this thread; empty if there was none. Valid until the next
+/// call that fails.
+#[no_mangle]
+pub extern "C" fn rustbin_last_error() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|err| err.borrow().as_ptr())
+    })
+}
+
+fn into_handle(result: crate::pe::Result<PeImage>) -> *mut RustbinPe {
+    match result {
+        Ok(pe) => Box::into_raw(Box::new(RustbinPe::new(pe))),
+        Err(err) => {
+            set_error(err.chain());
+            ptr::null_mut()
+        },
+    }
+}
+
+/// Parse the PE image at `path`. Returns NULL on error.
+///
+/// # Safety
+/// `path` must be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_pe_open(path: *const c_char) -> *mut RustbinPe {
+    guard(ptr::null_mut(), || {
+        if path.is_null() {
+            set_error("path is NULL");
+            return ptr::null_mut();
+        }
+        let path = CStr::from_ptr(path).to_string_lossy().into_owned();
+        match File::open(Path::new(&path)) {
+            Ok(file) => into_handle(PeImage::parse_file(file, 0)),
+            Err(err) => {
+                set_error(format!("can't open {path}: {err}"));
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// Parse a PE image from `len` bytes at `data`, which are copied. Returns NULL on error.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_pe_parse(data: *const u8, len: usize) -> *mut RustbinPe {
+    guard(ptr::null_mut(), || {
+        if data.is_null() {
+            set_error("data is NULL");
+            return ptr::null_mut();
+        }
+        let bytes = slice::from_raw_parts(data, len).to_vec();
+        into_handle(PeImage::parse_bytes(bytes, 0))
+    })
+}
+
+/// Free a handle and every string returned for it. NULL is ignored.
+///
+/// # Safety
+/// `pe` must be NULL or a handle not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_pe_free(pe: *mut RustbinPe) {
+    guard((), || {
+        if !pe.is_null() {
+            drop(Box::from_raw(pe));
+        }
+    })
+}
+
+/// Fill `out` with fields of the file and optional headers.
+///
+/// # Safety
+/// `pe` must be a valid handle and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_pe_headers(pe: *const RustbinPe, out: *mut RustbinHeaders) -> bool {
+    guard(false, || {
+        let (Some(pe), Some(out)) = (pe.as_ref(), out.as_mut()) else {
+            set_error("handle or output is NULL");
+            return false;
+        };
+        let (file, opt) = (&pe.pe.file.value, &pe.pe.optional.value);
+        let (magic, subsystem, dll_characteristics, size_of_image) = match opt {
+            crate::pe::optional::OptionalHeader::X86(o) => (o.magic.value as u16, o.subsystem_raw, o.dll_charactristics.value, o.sizeof_image.value),
+            crate::pe::optional::OptionalHeader::X64(o) => (o.magic.value as u16, o.subsystem_raw, o.dll_charactristics.value, o.sizeof_image.value),
+        };
+
+        *out = RustbinHeaders {
+            machine: file.machine_raw,
+            characteristics: file.charactristics.value,
+            timestamp: file.timestamp.value.raw(),
+            number_of_sections: file.sections.value,
+            magic,
+            subsystem,
+            dll_characteristics,
+            image_base: opt.image_base(),
+            entry_point: opt.entry_point(),
+            size_of_image,
+            checksum: opt.checksum().value,
+        };
+        true
+    })
+}
+
+/// Number of section headers; 0 for a NULL handle.
+///
+/// # Safety
+/// `pe` must be NULL or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_pe_section_count(pe: *const RustbinPe) -> usize {
+    guard(0, || {
+        pe.as_ref().map_or(0, |pe| pe.pe.sections.value.len())
+    })
+}
+
+/// Fill `out` with section header `index`.
+///
+/// # Safety
+/// `pe` must be a valid handle and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_pe_section(pe: *const RustbinPe, index: usize, out: *mut RustbinSection) -> bool {
+    guard(false, || {
+        let (Some(pe), Some(out)) = (pe.as_ref(), out.as_mut()) else {
+            set_error("handle or output is NULL");
+            return false;
+        };
+        let Some(section) = pe.pe.sections.value.get(index) else {
+            set_error(format!("no section {index}"));
+            return false;
+        };
+
+        let s = &section.value;
+        let mut name = [0 as c_char; 9];
+        for (dst, src) in name.iter_mut().zip(s.name.value) {
+            *dst = src as c_char;
+        }
+        *out = RustbinSection {
+            name,
+            virtual_address: s.virtual_address.value,
+            virtual_size: s.virtual_size.value,
+            raw_data_ptr: s.raw_data_ptr.value,
+            raw_data_size: s.sizeof_raw_data.value,
+            characteristics: s.charactristics.value,
+        };
+        true
+    })
+}
+
+/// Number of modules in the import directory; 0 for a NULL handle.
+///
+/// # Safety
+/// `pe` must be NULL or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_pe_import_module_count(pe: *const RustbinPe) -> usize {
+    guard(0, || {
+        pe.as_ref().map_or(0, |pe| pe.modules.len())
+    })
+}
+
+/// Name of import module `module`, owned by the handle; NULL if there is no such module.
+///
+/// # Safety
+/// `pe` must be NULL or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_pe_import_module_name(pe: *const RustbinPe, module: usize) -> *const c_char {
+    guard(ptr::null(), || {
+        pe.as_ref()
+            .and_then(|pe| pe.modules.get(module))
+            .map_or(ptr::null(), |(name, _)| name.as_ptr())
+    })
+}
+
+/// Number of imports from module `module`.
+///
+/// # Safety
+/// `pe` must be NULL or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_pe_import_count(pe: *const RustbinPe, module: usize) -> usize {
+    guard(0, || {
+        pe.as_ref()
+            .and_then(|pe| pe.modules.get(module))
+            .map_or(0, |(_, names)| names.len())
+    })
+}
+
+/// Fill `out` with import `index` of module `module`.
+///
+/// # Safety
+/// `pe` must be a valid handle and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_pe_import(pe: *const RustbinPe, module: usize, index: usize, out: *mut RustbinImport) -> bool {
+    guard(false, || {
+        let (Some(pe), Some(out)) = (pe.as_ref(), out.as_mut()) else {
+            set_error("handle or output is NULL");
+            return false;
+        };
+        let import = pe.pe.imports.value.get(module).and_then(|id| id.value.imports.get(index));
+        let (Some(import), Some((_, names))) = (import, pe.modules.get(module)) else {
+            set_error(format!("no import {index} in module {module}"));
+            return false;
+        };
+
+        *out = RustbinImport {
+            name: names[index].as_ptr(),
+            hint: import.import_name().map_or(0, |n| n.hint.value),
+            ordinal: import.ordinal().unwrap_or_default(),
+            by_ordinal: import.ordinal().is_some(),
+            iat_rva: import.iat_rva(),
+        };
+        true
+    })
+}
+
+/// Number of named exports.
+///
+/// # Safety
+/// `pe` must be NULL or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_pe_export_count(pe: *const RustbinPe) -> usize {
+    guard(0, || {
+        pe.as_ref().map_or(0, |pe| pe.exports.len())
+    })
+}
+
+/// Fill `out` with export `index`.
+///
+/// # Safety
+/// `pe` must be a valid handle and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_pe_export(pe: *const RustbinPe, index: usize, out: *mut RustbinExport) -> bool {
+    guard(false, || {
+        let (Some(pe), Some(out)) = (pe.as_ref(), out.as_mut()) else {
+            set_error("handle or output is NULL");
+            return false;
+        };
+        let Some(export) = pe.pe.exports.value.exports.get(index) else {
+            set_error(format!("no export {index}"));
+            return false;
+        };
+
+        *out = RustbinExport { name: pe.exports[index].as_ptr(), ordinal: export.ordinal.value, address: export.address.value };
+        true
+    })
+}
+
+/// The image as minimal JSON, or full JSON with offsets of every field if `full`. Free the
+/// result with `rustbin_string_free`; NULL on error.
+///
+/// # Safety
+/// `pe` must be a valid handle.
+#[cfg(feature="json")]
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_pe_to_json(pe: *const RustbinPe, full: bool) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        use crate::pe::ser::{full::FullPeImage, min::MinPeImage};
+
+        let Some(pe) = pe.as_ref() else {
+            set_error("handle is NULL");
+            return ptr::null_mut();
+        };
+        let json = if full {
+            serde_json::to_string(&FullPeImage::from(&pe.pe))
+        } else {
+            serde_json::to_string(&MinPeImage::from(&pe.pe))
+        };
+        match json {
+            Ok(json) => c_string(&json).into_raw(),
+            Err(err) => {
+                set_error(err.to_string());
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// Free a string returned by `rustbin_pe_to_json`. NULL is ignored.
+///
+/// # Safety
+/// `s` must be NULL or a string from this library, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rustbin_string_free(s: *mut c_char) {
+    guard((), || {
+        if !s.is_null() {
+            drop(CString::from_raw(s));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, ffi::{CStr, CString}, fs, ptr};
+
+    use super::*;
+
+    #[test]
+    fn query_test_dll() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(&path).unwrap();
+        let path = CString::new(path.to_str().unwrap()).unwrap();
+
+        unsafe {
+            let pe = rustbin_pe_open(path.as_ptr());
+            assert!(!pe.is_null());
+
+            let mut headers = RustbinHeaders::default();
+            assert!(rustbin_pe_headers(pe, &mut headers));
+            assert_eq!(headers.machine, 0x8664);
+            assert_eq!(headers.magic, 0x20b);
+            assert_eq!(headers.number_of_sections as usize, rustbin_pe_section_count(pe));
+
+            let mut section = RustbinSection::default();
+            assert!(rustbin_pe_section(pe, 0, &mut section));
+            assert_eq!(CStr::from_ptr(section.name.as_ptr()).to_str().unwrap(), ".text");
+            assert!(!rustbin_pe_section(pe, 100, &mut section));
+            assert_eq!(CStr::from_ptr(rustbin_last_error()).to_str().unwrap(), "no section 100");
+
+            assert!(rustbin_pe_import_module_count(pe) > 0);
+            let module = CStr::from_ptr(rustbin_pe_import_module_name(pe, 0)).to_str().unwrap();
+            assert!(module.to_lowercase().ends_with(".dll"));
+            let mut import = RustbinImport { name: ptr::null(), hint: 0, ordinal: 0, by_ordinal: false, iat_rva: 0 };
+            assert!(rustbin_pe_import(pe, 0, 0, &mut import));
+            assert_ne!(import.iat_rva, 0);
+
+            let mut export = RustbinExport { name: ptr::null(), ordinal: 0, address: 0 };
+            assert!(rustbin_pe_export(pe, 0, &mut export));
+            assert!(!CStr::from_ptr(export.name).to_bytes().is_empty());
+
+            #[cfg(feature="json")]
+            {
+                let json = rustbin_pe_to_json(pe, false);
+                assert!(CStr::from_ptr(json).to_str().unwrap().starts_with('{'));
+                rustbin_string_free(json);
+            }
+            rustbin_pe_free(pe);
+
+            let pe = rustbin_pe_parse(bytes.as_ptr(), 0x100);
+            assert!(pe.is_null());
+            assert!(!CStr::from_ptr(rustbin_last_error()).to_bytes().is_empty());
+            rustbin_pe_free(pe);
+        }
+    }
+
+    #[test]
+    fn panics_become_errors() {
+        assert!(!guard(false, || panic!("bad input")));
+        unsafe {
+            assert_eq!(CStr::from_ptr(rustbin_last_error()).to_str().unwrap(), "panicked: bad input");
+        }
+        assert_eq!(guard(0, || 7), 7);
+    }
+
+    #[test]
+    fn header_is_current() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/rustbin.h"));
+        let path = env::current_dir().unwrap().join("include").join("rustbin.h");
+        assert_eq!(fs::read_to_string(path).unwrap(), generated, "copy $OUT_DIR/rustbin.h to include/rustbin.h");
+    }
+}
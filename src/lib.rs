@@ -1,17 +1,28 @@
-use std::{fs::{File, OpenOptions}, path::Path};
+use std::{fs::{File, OpenOptions}, io::{BufReader, Cursor, Read, Seek, SeekFrom}, path::Path};
 
-use pe::{PeImage, PeError};
+use ar::{Archive, ArError};
+use pe::{coff::ObjectFile, progress::ParseProgress, te::{self, TeImage}, PeImage, PeError};
+use types::BufReadExt;
+pub mod ar;
+pub mod capabilities;
+#[cfg(feature="ffi")]
+pub mod ffi;
 pub mod pe;
+#[cfg(feature="tui")]
+pub mod tui;
 pub mod types;
 pub mod utils;
+#[cfg(feature="wasm")]
+pub mod wasm;
 
+/// Top level error. Each format has its own error type, wrapped in `ParseError`.
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[non_exhaustive]
     #[error("failed to read file")]
     Read(#[from] std::io::Error),
 
-    #[error("failed to parse")]
+    #[error("failed to parse; {0}")]
     Parse(#[from] ParseError)
 }
 
@@ -19,25 +30,155 @@ pub enum Error {
 #[derive(Debug, thiserror::Error)]
 pub enum ParseError {
     #[error(transparent)]
-    PE(#[from] pe::PeError)
+    PE(#[from] PeError),
+
+    #[error(transparent)]
+    Ar(#[from] ArError),
+}
+
+impl Error {
+    /// The error followed by its sources, on one line. See `PeError::chain`.
+    pub fn chain(&self) -> String {
+        match self {
+            Self::Read(err) => format!("{self}: {err}"),
+            Self::Parse(ParseError::PE(err)) => format!("failed to parse; {}", err.chain()),
+            Self::Parse(ParseError::Ar(err)) => format!("failed to parse; {}", err.chain()),
+        }
+    }
+}
+
+impl From<PeError> for Error {
+    fn from(value: PeError) -> Self {
+        Self::Parse(value.into())
+    }
+}
+
+impl From<ArError> for Error {
+    fn from(value: ArError) -> Self {
+        Self::Parse(value.into())
+    }
 }
 
-pub type Result<T> = std::result::Result<T, PeError>;
+/// Result of top level functions. Format specific APIs use their own, like `pe::Result`.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+#[allow(clippy::large_enum_variant)]
 pub enum ParsedAs {
     PE(PeImage),
+    TE(TeImage),
+    Coff(ObjectFile),
+    Archive(Archive),
 }
 
 pub enum ParseAs {
+    /// PE image; a TE image (starting with "VZ") or an archive (starting with "!<arch>\n") is
+    /// detected and parsed as `TE` or `Archive`.
     PE,
+    TE,
+    /// COFF object file (`.obj`); it has no magic, so is never detected.
+    Coff,
+    /// Static or import library (`.lib`, `.a`).
+    Archive,
 }
 
 pub fn parse_file(f: File, parse_as: ParseAs) -> Result<ParsedAs>{
-    match parse_as {
-        ParseAs::PE => Ok(ParsedAs::PE(pe::PeImage::parse_file(f, 0)?)),
+    parse_input(Input::<Vec<u8>>::File(f), parse_as, false, None)
+}
+
+/// `parse_file`, collecting `PeImage::parse_stats` of a PE image.
+pub fn parse_file_with_stats(f: File, parse_as: ParseAs) -> Result<ParsedAs>{
+    parse_input(Input::<Vec<u8>>::File(f), parse_as, true, None)
+}
+
+/// `parse_file`, reporting progress of parsing a PE image to `progress`, which stays set on the
+/// image; see `PeImage::set_progress`. Stats are collected if `stats`.
+pub fn parse_file_with_progress(f: File, parse_as: ParseAs, stats: bool, progress: Box<dyn ParseProgress>) -> Result<ParsedAs>{
+    parse_input(Input::<Vec<u8>>::File(f), parse_as, stats, Some(progress))
+}
+
+/// `parse_file` of the whole input in memory, e.g. read from stdin. `bytes` can be a buffer
+/// shared with the caller, who uses it after parsing without a copy.
+pub fn parse_bytes(bytes: impl AsRef<[u8]> + 'static, parse_as: ParseAs) -> Result<ParsedAs>{
+    parse_input(Input::Bytes(bytes), parse_as, false, None)
+}
+
+/// `parse_file_with_stats` of the whole input in memory.
+pub fn parse_bytes_with_stats(bytes: impl AsRef<[u8]> + 'static, parse_as: ParseAs) -> Result<ParsedAs>{
+    parse_input(Input::Bytes(bytes), parse_as, true, None)
+}
+
+/// `parse_file_with_progress` of the whole input in memory.
+pub fn parse_bytes_with_progress(bytes: impl AsRef<[u8]> + 'static, parse_as: ParseAs, stats: bool, progress: Box<dyn ParseProgress>) -> Result<ParsedAs>{
+    parse_input(Input::Bytes(bytes), parse_as, stats, Some(progress))
+}
+
+/// Source of top level parse functions.
+enum Input<B> {
+    File(File),
+    Bytes(B),
+}
+
+impl<B: AsRef<[u8]> + 'static> Input<B> {
+    /// Up to the first 8 bytes; a file is rewound.
+    fn magic(&mut self) -> Result<Vec<u8>> {
+        match self {
+            Self::File(f) => read_magic(f),
+            Self::Bytes(bytes) => Ok(bytes.as_ref().iter().take(ar::MAGIC.len()).copied().collect()),
+        }
+    }
+
+    fn into_reader(self) -> Box<dyn BufReadExt> {
+        match self {
+            Self::File(f) => Box::new(BufReader::new(f)),
+            Self::Bytes(bytes) => Box::new(Cursor::new(bytes)),
+        }
     }
 }
 
+fn parse_pe(reader: Box<dyn BufReadExt>, stats: bool, progress: Option<Box<dyn ParseProgress>>) -> Result<PeImage> {
+    let mut pe = PeImage::new(reader);
+    if stats {
+        pe.enable_stats();
+    }
+    if let Some(progress) = progress {
+        pe.set_progress(progress);
+    }
+    pe.parse_all_headers(0)?;
+    Ok(pe)
+}
+
+fn parse_input<B: AsRef<[u8]> + 'static>(mut input: Input<B>, parse_as: ParseAs, stats: bool, progress: Option<Box<dyn ParseProgress>>) -> Result<ParsedAs>{
+    let parse_as = match parse_as {
+        ParseAs::PE => {
+            let magic = input.magic()?;
+            if te::is_te(&magic) {
+                ParseAs::TE
+            } else if magic.starts_with(ar::MAGIC) {
+                ParseAs::Archive
+            } else {
+                ParseAs::PE
+            }
+        },
+        parse_as => parse_as,
+    };
+
+    match (parse_as, input) {
+        (ParseAs::PE, input) => Ok(ParsedAs::PE(parse_pe(input.into_reader(), stats, progress)?)),
+        (ParseAs::TE, input) => Ok(ParsedAs::TE(TeImage::parse_readable(input.into_reader(), 0)?)),
+        (ParseAs::Coff, Input::File(f)) => Ok(ParsedAs::Coff(ObjectFile::parse_file(f)?)),
+        (ParseAs::Coff, Input::Bytes(bytes)) => Ok(ParsedAs::Coff(ObjectFile::parse_bytes(bytes.as_ref(), 0)?)),
+        (ParseAs::Archive, input) => Ok(ParsedAs::Archive(Archive::parse_readable(input.into_reader())?)),
+    }
+}
+
+/// Up to the first 8 bytes of `f`, which is rewound.
+fn read_magic(f: &mut File) -> Result<Vec<u8>> {
+    let mut magic = Vec::with_capacity(ar::MAGIC.len());
+    f.by_ref().take(ar::MAGIC.len() as u64).read_to_end(&mut magic)?;
+    f.seek(SeekFrom::Start(0))?;
+    Ok(magic)
+}
+
 pub fn parse_path(path: &Path, parse_as: ParseAs) -> Result<ParsedAs>{
     let f = OpenOptions::new()
         .read(true)
@@ -48,5 +189,25 @@ pub fn parse_path(path: &Path, parse_as: ParseAs) -> Result<ParsedAs>{
 
 #[cfg(test)]
 mod tests {
+    use std::path::Path;
 
+    use crate::{parse_bytes, parse_path, pe::{builder::PeBuilder, file::MachineType}, Error, ParseAs, ParseError, ParsedAs};
+
+    #[test]
+    fn read_and_parse_errors() {
+        let res = parse_path(Path::new("test-data/does-not-exist.dll"), ParseAs::PE);
+        assert!(matches!(res, Err(Error::Read(_))));
+
+        let res = parse_path(Path::new("Cargo.toml"), ParseAs::PE);
+        assert!(matches!(res, Err(Error::Parse(ParseError::PE(_)))));
+    }
+
+    #[test]
+    fn parse_bytes_detects_format() {
+        let bytes = PeBuilder::new(MachineType::AMD64).build().unwrap();
+        assert!(matches!(parse_bytes(bytes, ParseAs::PE), Ok(ParsedAs::PE(_))));
+        assert!(matches!(parse_bytes(b"!<arch>\n", ParseAs::PE), Ok(ParsedAs::Archive(_))));
+        assert!(matches!(parse_bytes(b"VZ", ParseAs::PE), Err(Error::Parse(ParseError::PE(_)))));
+        assert!(matches!(parse_bytes(Vec::new(), ParseAs::Coff), Err(Error::Parse(_))));
+    }
 }
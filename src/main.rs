@@ -1,10 +1,12 @@
 extern crate rustbin;
 
 use core::str;
-use std::{env, fs::{File, OpenOptions}, io::{stdout, BufWriter, Write}, path::{Path, PathBuf}, process::ExitCode};
+use std::{env, fmt::{Debug, Display}, fs::{File, OpenOptions}, io::{self, stderr, stdin, stdout, BufWriter, IsTerminal, Read, Write}, path::{Path, PathBuf}, process::ExitCode, rc::Rc};
 
-use clap::{ArgAction, Parser, ValueEnum};
-use rustbin::{parse_file, pe::{ser::min::MinPeImage, PeImage}, ParseAs, ParsedAs};
+use chrono::{DateTime, Utc};
+use clap::{builder::{PossibleValuesParser, TypedValueParser}, ArgAction, Parser, Subcommand, ValueEnum};
+use tracing::{debug, error, info, warn, Level};
+use rustbin::{parse_bytes, parse_bytes_with_progress, parse_bytes_with_stats, parse_file, parse_file_with_progress, parse_file_with_stats, pe::{progress::ParseProgress, analysis::{capability::CapabilityRules, entropy::EntropyProfile, AnalysisReport}, report::ReportSection, apiset::ApiSetSchema, deps::{DependencyTree, ExportMap, TreeOptions}, ser::{envelope::{Envelope, FileInfo, ReportOptions}, full::{FullOptions, FullPeImage}, min::{MinOptions, MinPeImage, MinTeImage}, triage::TriagePeImage, TimestampFormat}, te::TeImage, yara::format_rule, PeImage}, ParseAs, ParsedAs};
 
 /*
 #[derive(Debug, Error)]
@@ -19,8 +21,12 @@ enum CliError {
 */
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(help="File to parse; `-` reads it from stdin.")]
     target: Option<String>,
 
     #[arg(short, long, value_enum, default_value_t = Default::default(), help="Output format")]
@@ -34,8 +40,180 @@ struct Args {
 
     #[arg(num_args(0..), short='x', long, action=ArgAction::Append, help="Excluded portions/sections.", default_value = "relocs")]
     exclude: Vec<ExcludeOptions>,
+
+    #[arg(long, value_delimiter=',', value_parser=report_section_parser(), help="Parts of the display output, in this order; ignores --exclude (only TEXT mode).")]
+    only: Vec<ReportSection>,
+
+    #[arg(short, long, help="Run analysis passes and include the report.")]
+    analyze: bool,
+
+    #[cfg(feature="json")]
+    #[arg(long, value_name="FILE", requires="analyze", help="JSON object of categories to imported function names, added to the embedded capability rules.")]
+    capability_rules: Option<PathBuf>,
+
+    #[arg(long, help="Include on-disk values of decoded enums and flags (JSON only).")]
+    raw: bool,
+
+    #[arg(long, help="Include every DOS header field (minimal JSON only).")]
+    dos_fields: bool,
+
+    #[arg(long, default_value_t = rustbin::pe::ser::full::DEFAULT_DIRECTORY_BYTES, help="Bytes from the start of each data directory included in full JSON.")]
+    dir_bytes: usize,
+
+    #[arg(long, value_enum, default_value_t = Default::default(), help="Form of timestamps in minimal JSON.")]
+    timestamps: TimestampForm,
+
+    #[arg(long, help="Wrap JSON output with the rustbin version, options and hashes of the target. Set SOURCE_DATE_EPOCH to fix its time.")]
+    envelope: bool,
+
+    #[arg(long, help="Write the bytes of the target, once parsed, instead of a report; e.g. to pass it on in a pipeline. Not written to a terminal.")]
+    output_binary: bool,
+
+    #[arg(long, help="Print MD5, SHA1, SHA256 and ssdeep hashes of the target, and exit.")]
+    hashes: bool,
+
+    #[arg(long, help="Parse the target as a COFF object file (.obj). Archives (.lib) are detected.")]
+    coff: bool,
+
+    #[cfg(feature="disasm")]
+    #[arg(long, value_name="COUNT", num_args(0..=1), default_missing_value="16", help="Disassemble COUNT instructions at the entry point and each export (TEXT only).")]
+    disasm_entry: Option<usize>,
+
+    #[arg(long, help="Show structures and outputs supported for each format, and exit.")]
+    capabilities: bool,
+
+    #[cfg(feature="schema")]
+    #[arg(long, help="Print the JSON Schema of the minimal or full (with --level full) JSON output, and exit.")]
+    schema: bool,
+
+    #[arg(long, help="Print durations and bytes read of each parse step to stderr.")]
+    stats: bool,
+
+    #[arg(long, help="Show progress of parsing and hashing on stderr.")]
+    progress: bool,
+
+    #[arg(short, long, action=ArgAction::Count, help="Diagnostics on stderr: -v progress, -vv parse phases with timings, -vvv everything.")]
+    verbose: u8,
+}
+
+/// Target which reads the input from stdin.
+const STDIN_TARGET: &str = "-";
+
+/// Input of the main command.
+enum Target {
+    File(File),
+    Stdin(StdinBytes),
 }
 
+/// Bytes read from stdin, shared by the parsed image and `--output-binary`.
+#[derive(Clone)]
+struct StdinBytes(Rc<Vec<u8>>);
+
+impl AsRef<[u8]> for StdinBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    ///Check which imports resolve against modules in search paths. Exits with 5 if any is missing.
+    ///The directory of the target is searched first with `--recursive`.
+    Deps(DepsArgs),
+
+    ///List ASCII and UTF-16LE strings with their file offsets and RVAs.
+    Strings(StringsArgs),
+
+    ///Show entropy of the file in fixed windows, with regions of compressed or encrypted data.
+    Entropy(EntropyArgs),
+
+    ///Verify the Authenticode signature against trusted roots. Exits with 6 if it is invalid and
+    ///with 7 if it is untrusted.
+    #[cfg(feature="verify")]
+    Verify(VerifyArgs),
+
+    ///Browse headers, sections, imports, exports, resources and bytes of the target interactively.
+    #[cfg(feature="tui")]
+    Tui(TuiArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct DepsArgs {
+    target: String,
+
+    #[arg(short, long, action=ArgAction::Append, help="Directory to search for imported modules; repeatable, searched in order.")]
+    search_path: Vec<PathBuf>,
+
+    #[arg(short, long, help="Parse dependencies recursively and show the dependency tree.")]
+    recursive: bool,
+
+    #[arg(long, help="API set schema to map api-ms-win-* modules to their hosts with; apisetschema.dll or a JSON file.")]
+    apiset: Option<PathBuf>,
+
+    #[arg(long, default_value_t = rustbin::pe::deps::DEFAULT_MAX_DEPTH, help="Max depth of the dependency tree.")]
+    max_depth: usize,
+
+    #[arg(short, long, value_enum, default_value_t = Default::default(), help="Output format")]
+    format: OutputFormat,
+
+    #[arg(short, long, help="Output file. [default: stdout]")]
+    output: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct StringsArgs {
+    target: String,
+
+    #[arg(short, long, default_value_t = rustbin::utils::strings::DEFAULT_MIN_LENGTH, help="Minimum number of characters.")]
+    min_len: usize,
+
+    #[arg(short, long, action=ArgAction::Append, help="Section to scan; repeatable. [default: whole file]")]
+    section: Vec<String>,
+
+    #[arg(short, long, value_enum, default_value_t = Default::default(), help="Output format")]
+    format: OutputFormat,
+
+    #[arg(short, long, help="Output file. [default: stdout]")]
+    output: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct EntropyArgs {
+    target: String,
+
+    #[arg(short, long, default_value_t = rustbin::pe::analysis::entropy::DEFAULT_WINDOW, help="Bytes in each window.")]
+    window: u64,
+
+    #[arg(short, long, help="Bytes between starts of windows. [default: window]")]
+    step: Option<u64>,
+
+    #[arg(short, long, value_enum, default_value_t = Default::default(), help="Output format")]
+    format: OutputFormat,
+
+    #[arg(short, long, help="Output file. [default: stdout]")]
+    output: Option<String>,
+}
+
+#[cfg(feature="verify")]
+#[derive(clap::Args, Debug)]
+struct VerifyArgs {
+    target: String,
+
+    #[arg(short, long, action=ArgAction::Append, help="DER certificate of a trusted root; repeatable.")]
+    trust_root: Vec<PathBuf>,
+
+    #[arg(short, long, value_enum, default_value_t = Default::default(), help="Output format")]
+    format: OutputFormat,
+
+    #[arg(short, long, help="Output file. [default: stdout]")]
+    output: Option<String>,
+}
+
+#[cfg(feature="tui")]
+#[derive(clap::Args, Debug)]
+struct TuiArgs {
+    target: PathBuf,
+}
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Default)]
 enum OutputFormat {
@@ -58,15 +236,42 @@ enum OutputLevel {
     ////Show metadata for only for sturcts (most), skip field metadata.
     //TopLevel,
 
-    ////Show complete metadata.
-    //Full,
+    ///Show every field with its offset, rva and raw bytes (only JSON mode).
+    Full,
 
     ///Show impl Debug of headers (only TEXT mode)
     Debug,
 
     ///Use formatted Display (only TEXT mode).
     #[default]
-    Display
+    Display,
+
+    ///Import, export and version strings and the rich header as a YARA rule (only TEXT mode).
+    Yara,
+
+    ///Hashes, imphash, sections, imported modules and counts on one line of fixed-schema JSON (only JSON mode).
+    Triage,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum TimestampForm {
+    ///Object with both `epoch` and `iso8601`.
+    #[default]
+    Both,
+    ///RFC 3339 string.
+    Iso8601,
+    ///Seconds since the Unix epoch.
+    Epoch,
+}
+
+impl From<TimestampForm> for TimestampFormat {
+    fn from(value: TimestampForm) -> Self {
+        match value {
+            TimestampForm::Both => Self::Both,
+            TimestampForm::Iso8601 => Self::Iso8601,
+            TimestampForm::Epoch => Self::Epoch,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -84,87 +289,765 @@ impl std::fmt::Display for ExcludeOptions {
     }
 }
 
+/// Values of `--only`: the names of `ReportSection::ALL`.
+fn report_section_parser() -> impl TypedValueParser<Value = ReportSection> {
+    PossibleValuesParser::new(ReportSection::ALL.map(|part| part.name()))
+        .map(|name| ReportSection::from_name(&name).expect("checked by possible values"))
+}
+
+/// Progress on one line of stderr, cleared when a stage ends with 100%.
+#[derive(Default)]
+struct StderrProgress {
+    stage: String,
+}
+
+impl ParseProgress for StderrProgress {
+    fn stage(&mut self, stage: &str) {
+        self.stage = stage.to_string();
+    }
+
+    fn percent(&mut self, percent: u8) {
+        match percent {
+            100 => eprint!("\r{:40}\r", ""),
+            _ => eprint!("\r{percent:>3}% {:36}", self.stage),
+        }
+    }
+}
+
+/// Send diagnostics to stderr, keeping stdout for data. Warnings and errors are always shown.
+fn init_tracing(verbose: u8) {
+    let level = match verbose {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(stderr)
+        .with_target(false)
+        .without_time()
+        .init();
+}
+
+/// Writer from `open_output`; logs the error and returns exit code 1 if it can't be created.
+macro_rules! output {
+    ($output:expr) => {
+        match open_output($output) {
+            Ok(out) => out,
+            Err(err) => {
+                error!("Failed to create output: {err}");
+                return ExitCode::from(1);
+            }
+        }
+    };
+}
+
 fn main() -> ExitCode {
     let args = Args::parse();
+    init_tracing(args.verbose);
+
+    match &args.command {
+        Some(Command::Deps(deps_args)) => return deps(deps_args),
+        Some(Command::Strings(strings_args)) => return strings(strings_args),
+        Some(Command::Entropy(entropy_args)) => return entropy(entropy_args),
+        #[cfg(feature="verify")]
+        Some(Command::Verify(verify_args)) => return verify(verify_args),
+        #[cfg(feature="tui")]
+        Some(Command::Tui(tui_args)) => return tui(tui_args),
+        None => {},
+    }
+
+    if args.capabilities {
+        return capabilities(&args);
+    }
 
-    println!("target: {:?}", args.target);
-    println!("format: {:?}", args.format);
-    println!("exclude: {:?}", args.exclude);
+    #[cfg(feature="schema")]
+    if args.schema {
+        return schema(&args);
+    }
+
+    debug!(target = ?args.target, format = ?args.format, level = ?args.level, exclude = ?args.exclude, "arguments");
 
-    let binpath:PathBuf = if let Some(target) = args.target{
-        Path::new(&target).into()
+    let binpath:PathBuf = if let Some(ref target) = args.target{
+        Path::new(target).into()
     } else if cfg!(windows){
         env::current_exe().unwrap()
     } else {
-        println!("Target is required.");
+        error!("Target is required.");
         return ExitCode::from(1);
     };
 
-    if !binpath.is_file() {
-        println!("Target is not a file");
-        return ExitCode::from(2);
+    if args.output_binary && args.output.is_none() && stdout().is_terminal() {
+        error!("Refusing to write binary output to a terminal; use --output or a pipe.");
+        return ExitCode::from(1);
     }
 
-    println!("BinPath: {binpath:?}");
+    let target = if args.target.as_deref() == Some(STDIN_TARGET) {
+        info!("parsing stdin");
+        let mut bytes = Vec::new();
+        if let Err(err) = stdin().lock().read_to_end(&mut bytes) {
+            error!("Failed to read stdin: {err}");
+            return ExitCode::from(3);
+        }
+        Target::Stdin(StdinBytes(Rc::new(bytes)))
+    } else {
+        if !binpath.is_file() {
+            error!("Target is not a file");
+            return ExitCode::from(2);
+        }
 
-    let Ok(f) = OpenOptions::new()
-        .read(true)
-        .open(binpath)
-    else {
-        println!("Failed to open file in read mode.");
-        return ExitCode::from(3);
+        info!(path = ?binpath, "parsing");
+
+        let Ok(f) = OpenOptions::new()
+            .read(true)
+            .open(&binpath)
+        else {
+            error!("Failed to open file in read mode.");
+            return ExitCode::from(3);
+        };
+        Target::File(f)
     };
 
-    let Ok(parsed) = parse_file(f, ParseAs::PE) else {
-        println!("Failed to parse as `PE`.");
-        return ExitCode::from(4);
+    //Stdin can't be read again for --output-binary; the parsed image shares its bytes.
+    let passthrough = match &target {
+        Target::Stdin(bytes) if args.output_binary => Some(bytes.clone()),
+        _ => None,
     };
 
-    let ParsedAs::PE(pe) = parsed;
+    let parse_as = if args.coff { ParseAs::Coff } else { ParseAs::PE };
+    let progress = args.progress.then(|| Box::new(StderrProgress::default()) as Box<dyn ParseProgress>);
+    let parsed = match (target, progress) {
+        (Target::File(f), Some(progress)) => parse_file_with_progress(f, parse_as, args.stats, progress),
+        (Target::File(f), None) if args.stats => parse_file_with_stats(f, parse_as),
+        (Target::File(f), None) => parse_file(f, parse_as),
+        (Target::Stdin(bytes), Some(progress)) => parse_bytes_with_progress(bytes, parse_as, args.stats, progress),
+        (Target::Stdin(bytes), None) if args.stats => parse_bytes_with_stats(bytes, parse_as),
+        (Target::Stdin(bytes), None) => parse_bytes(bytes, parse_as),
+    };
+    let parsed = match parsed {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            error!("Failed to parse as `{}`: {}", if args.coff { "COFF" } else { "PE" }, err.chain());
+            return ExitCode::from(4);
+        }
+    };
 
-    let mut out = BufWriter::new(match args.output {
-        Some(ref x) => Box::new(File::create(&Path::new(x)).unwrap()) as Box<dyn Write>,
-        None => Box::new(stdout()) as Box<dyn Write>,
-    } as Box<dyn Write>);
+    let mut pe = match parsed {
+        ParsedAs::PE(pe) => pe,
+        _ if args.output_binary => return output_binary(passthrough.as_ref().map(AsRef::as_ref), &binpath, &args),
+        ParsedAs::TE(te) => return output_te(te, &args),
+        ParsedAs::Coff(obj) => return output_text(&obj, &args),
+        ParsedAs::Archive(archive) => return output_text(&archive, &args),
+    };
+
+    if let Some(stats) = pe.parse_stats() {
+        eprintln!("{stats}");
+    }
+
+    if args.output_binary {
+        return output_binary(passthrough.as_ref().map(AsRef::as_ref), &binpath, &args);
+    }
+
+    if args.hashes {
+        return hashes(&mut pe, &args);
+    }
+
+    let report = if args.analyze {
+        match pe.analyze() {
+            #[cfg(feature="json")]
+            Ok(mut report) => {
+                if let Some(path) = &args.capability_rules {
+                    match capability_rules(path) {
+                        Ok(rules) => report.capabilities = pe.capabilities(&rules),
+                        Err(err) => {
+                            error!(path = ?path, "Failed to load capability rules: {err}");
+                            return ExitCode::from(4);
+                        },
+                    }
+                }
+                Some(report)
+            },
+            #[cfg(not(feature="json"))]
+            Ok(report) => Some(report),
+            Err(err) => {
+                warn!("Analysis failed: {err}");
+                //Header checks read nothing, so they still apply.
+                Some(AnalysisReport { anomalies: pe.validate(), ..Default::default() })
+            }
+        }
+    } else { None };
+
+    let mut out = output!(&args.output);
 
     match (args.format, args.level){
         #[cfg(feature="json")]
         (OutputFormat::JSON, OutputLevel::Minimal) => {
-            let mut min_pe = MinPeImage::from(&pe);
+            let mut min_pe = MinPeImage::with_options(&pe, &min_options(&args));
             exclude_min_pe_parts(&mut min_pe, &args.exclude);
-            let jstr = serde_json::to_string_pretty(&min_pe).unwrap();
+            min_pe.analysis = report;
+            let jstr = match args.envelope {
+                true => serde_json::to_string_pretty(&envelope(min_pe, &binpath, &mut pe, ReportOptions::Minimal(min_options(&args)))),
+                false => serde_json::to_string_pretty(&min_pe),
+            }.unwrap();
+            writeln!(out, "{jstr}").unwrap();
+        },
+
+        #[cfg(feature="json")]
+        (OutputFormat::JSON, OutputLevel::Full) => {
+            let options = FullOptions { directory_bytes: args.dir_bytes };
+            let mut full_pe = FullPeImage::with_options(&mut pe, &options);
+            exclude_full_pe_parts(&mut full_pe, &args.exclude);
+            let jstr = match args.envelope {
+                true => serde_json::to_string_pretty(&envelope(full_pe, &binpath, &mut pe, ReportOptions::Full(options))),
+                false => serde_json::to_string_pretty(&full_pe),
+            }.unwrap();
             writeln!(out, "{jstr}").unwrap();
         },
 
+        #[cfg(feature="json")]
+        (OutputFormat::JSON, OutputLevel::Triage) => {
+            let triage = match TriagePeImage::new(&mut pe, args.timestamps.into()) {
+                Ok(triage) => triage,
+                Err(err) => {
+                    error!("Failed to summarize target: {}", err.chain());
+                    return ExitCode::from(4);
+                }
+            };
+            writeln!(out, "{}", serde_json::to_string(&triage).unwrap()).unwrap();
+        },
+
         (OutputFormat::TEXT, OutputLevel::Debug) => { writeln!(out, "{pe:#?}").unwrap(); },
         (OutputFormat::TEXT, OutputLevel::Display) => { 
-            let pe_text = format_pe_as_text(&pe, &args.exclude);
+            let pe_text = format_pe_as_text(&pe, &args.exclude, &args.only);
             writeln!(out, "{pe_text}").unwrap(); 
+            if let Some(report) = report {
+                writeln!(out, "{report}").unwrap();
+            }
+        },
+
+        (OutputFormat::TEXT, OutputLevel::Yara) => {
+            let strings = match pe.yara_strings() {
+                Ok(strings) => strings,
+                Err(err) => {
+                    error!("Failed to collect YARA strings: {}", err.chain());
+                    return ExitCode::from(4);
+                }
+            };
+            let name = binpath.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            writeln!(out, "{}", format_rule(&name, &strings)).unwrap();
         },
         
         _ => {
-            eprintln!("Unsupported combination {:?} + {:?}", args.format, args.level);
+            error!("Unsupported combination {:?} + {:?}", args.format, args.level);
+        },
+    };
+
+    #[cfg(feature="disasm")]
+    if let Some(count) = args.disasm_entry {
+        if args.format == OutputFormat::TEXT {
+            write_disasm(&mut pe, count, &mut out);
+        } else {
+            warn!("Disassembly is only shown in TEXT mode.");
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(feature="disasm")]
+fn write_disasm(pe: &mut PeImage, count: usize, out: &mut impl Write) {
+    writeln!(out, "Entry point:").unwrap();
+    match pe.disassemble_entry(count) {
+        Ok(instructions) => instructions.iter().for_each(|i| writeln!(out, "  {i}").unwrap()),
+        Err(err) => warn!("Failed to disassemble entry point: {}", err.chain()),
+    }
+
+    for (name, result) in pe.disassemble_exports(count) {
+        writeln!(out, "{name}:").unwrap();
+        match result {
+            Ok(instructions) => instructions.iter().for_each(|i| writeln!(out, "  {i}").unwrap()),
+            Err(err) => warn!("Failed to disassemble export {name}: {}", err.chain()),
+        }
+    }
+}
+
+
+fn capabilities(args: &Args) -> ExitCode {
+    let matrix = rustbin::capabilities::capabilities();
+    let mut out = output!(&args.output);
+
+    match args.format {
+        #[cfg(feature="json")]
+        OutputFormat::JSON => {
+            let jstr = serde_json::to_string_pretty(&matrix).unwrap();
+            writeln!(out, "{jstr}").unwrap();
+        },
+        OutputFormat::TEXT => {
+            for format in matrix {
+                writeln!(out, "{format}").unwrap();
+            }
+        },
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn hashes(pe: &mut PeImage, args: &Args) -> ExitCode {
+    let hashes = match pe.file_hashes(true) {
+        Ok(hashes) => hashes,
+        Err(err) => {
+            error!("Failed to hash target: {}", err.chain());
+            return ExitCode::from(4);
+        }
+    };
+    let mut out = output!(&args.output);
+
+    match args.format {
+        #[cfg(feature="json")]
+        OutputFormat::JSON => {
+            let jstr = serde_json::to_string_pretty(&hashes).unwrap();
+            writeln!(out, "{jstr}").unwrap();
+        },
+        OutputFormat::TEXT => writeln!(out, "{hashes}").unwrap(),
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(feature="schema")]
+fn schema(args: &Args) -> ExitCode {
+    use rustbin::pe::ser::schema::{schema, SchemaKind};
+
+    let kind = match args.level {
+        OutputLevel::Full => SchemaKind::Full,
+        OutputLevel::Triage => SchemaKind::Triage,
+        _ => SchemaKind::Minimal,
+    };
+    let jstr = serde_json::to_string_pretty(&schema(kind)).unwrap();
+    writeln!(output!(&args.output), "{jstr}").unwrap();
+    ExitCode::SUCCESS
+}
+
+fn deps(args: &DepsArgs) -> ExitCode {
+    info!(path = ?args.target, "parsing");
+
+    if args.recursive {
+        return deps_tree(args);
+    }
+
+    let pe = match rustbin::parse_path(Path::new(&args.target), ParseAs::PE) {
+        Ok(ParsedAs::PE(pe)) => pe,
+        Ok(ParsedAs::TE(_)) => {
+            error!("TE images have no imports.");
+            return ExitCode::from(4);
+        },
+        Ok(ParsedAs::Coff(_) | ParsedAs::Archive(_)) => {
+            error!("Objects and archives have no imports.");
+            return ExitCode::from(4);
+        },
+        Err(err) => {
+            error!("Failed to parse as `PE`: {}", err.chain());
+            return ExitCode::from(4);
+        }
+    };
+
+    let api_sets = match load_api_sets(args) {
+        Ok(api_sets) => api_sets,
+        Err(code) => return code,
+    };
+
+    let modules: Vec<String> = pe.imports.value.iter().filter_map(|id| id.value.name.clone()).collect();
+    let exports = match &api_sets {
+        Some(api_sets) => {
+            let importing = Path::new(&args.target).file_name().map(|n| n.to_string_lossy());
+            ExportMap::load_with_api_sets(modules.iter().map(String::as_str), &args.search_path, api_sets, importing.as_deref())
+        },
+        None => ExportMap::load(modules.iter().map(String::as_str), &args.search_path),
+    };
+    debug!(imported = modules.len(), found = exports.len(), "dependencies loaded");
+
+    let report = pe.resolve_imports(&exports);
+    let mut out = output!(&args.output);
+
+    match args.format {
+        #[cfg(feature="json")]
+        OutputFormat::JSON => {
+            let jstr = serde_json::to_string_pretty(&report).unwrap();
+            writeln!(out, "{jstr}").unwrap();
+        },
+        OutputFormat::TEXT => { writeln!(out, "{report}").unwrap(); },
+    }
+
+    if report.is_complete() { ExitCode::SUCCESS } else { ExitCode::from(5) }
+}
+
+fn strings(args: &StringsArgs) -> ExitCode {
+    info!(path = ?args.target, "parsing");
+
+    let mut pe = match rustbin::parse_path(Path::new(&args.target), ParseAs::PE) {
+        Ok(ParsedAs::PE(pe)) => pe,
+        Ok(_) => {
+            error!("Target is not a PE image.");
+            return ExitCode::from(4);
+        },
+        Err(err) => {
+            error!("Failed to parse as `PE`: {}", err.chain());
+            return ExitCode::from(4);
+        }
+    };
+
+    let sections: Vec<&str> = args.section.iter().map(String::as_str).collect();
+    let found = match pe.find_strings(args.min_len, &sections) {
+        Ok(found) => found,
+        Err(err) => {
+            error!("Failed to read strings: {}", err.chain());
+            return ExitCode::from(4);
+        }
+    };
+
+    let mut out = output!(&args.output);
+    match args.format {
+        #[cfg(feature="json")]
+        OutputFormat::JSON => {
+            let jstr = serde_json::to_string_pretty(&found).unwrap();
+            writeln!(out, "{jstr}").unwrap();
+        },
+        OutputFormat::TEXT => {
+            for s in found {
+                writeln!(out, "{s}").unwrap();
+            }
+        },
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Any file has entropy; PE images get section names.
+fn entropy(args: &EntropyArgs) -> ExitCode {
+    let step = args.step.unwrap_or(args.window);
+    let bytes = match std::fs::read(&args.target) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            error!("Failed to read target: {err}");
+            return ExitCode::from(3);
+        }
+    };
+
+    let pe = bytes.starts_with(b"MZ").then(|| PeImage::parse_bytes(bytes.clone(), 0).ok()).flatten();
+    let profile = match pe.map(|mut pe| pe.entropy_profile(args.window, step)) {
+        Some(Ok(profile)) => profile,
+        Some(Err(err)) => {
+            warn!("Section names are left out: {}", err.chain());
+            EntropyProfile::from_bytes(&bytes, 0, args.window, step)
+        },
+        None => EntropyProfile::from_bytes(&bytes, 0, args.window, step),
+    };
+
+    let mut out = output!(&args.output);
+    match args.format {
+        #[cfg(feature="json")]
+        OutputFormat::JSON => {
+            let jstr = serde_json::to_string_pretty(&profile.windows).unwrap();
+            writeln!(out, "{jstr}").unwrap();
+        },
+        OutputFormat::TEXT => { write!(out, "{profile}").unwrap(); },
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(feature="verify")]
+fn verify(args: &VerifyArgs) -> ExitCode {
+    use rustbin::pe::security::verify::VerifyStatus;
+
+    let mut roots = Vec::with_capacity(args.trust_root.len());
+    for path in &args.trust_root {
+        match std::fs::read(path) {
+            Ok(bytes) => roots.push(bytes),
+            Err(err) => {
+                error!("Failed to read {}: {err}", path.display());
+                return ExitCode::from(1);
+            }
+        }
+    }
+
+    info!(path = ?args.target, "parsing");
+    let mut pe = match rustbin::parse_path(Path::new(&args.target), ParseAs::PE) {
+        Ok(ParsedAs::PE(pe)) => pe,
+        Ok(_) => {
+            error!("Target is not a PE image.");
+            return ExitCode::from(4);
+        },
+        Err(err) => {
+            error!("Failed to parse as `PE`: {}", err.chain());
+            return ExitCode::from(4);
+        }
+    };
+
+    let roots: Vec<&[u8]> = roots.iter().map(Vec::as_slice).collect();
+    let result = match pe.verify_signature(&roots) {
+        Ok(result) => result,
+        Err(err) => {
+            error!("Failed to verify signature: {}", err.chain());
+            return ExitCode::from(4);
+        }
+    };
+
+    let mut out = output!(&args.output);
+    match args.format {
+        #[cfg(feature="json")]
+        OutputFormat::JSON => {
+            let jstr = serde_json::to_string_pretty(&result).unwrap();
+            writeln!(out, "{jstr}").unwrap();
+        },
+        OutputFormat::TEXT => { write!(out, "{result}").unwrap(); },
+    }
+
+    match result.status {
+        VerifyStatus::Valid => ExitCode::SUCCESS,
+        VerifyStatus::Invalid => ExitCode::from(6),
+        VerifyStatus::Untrusted => ExitCode::from(7),
+    }
+}
+
+#[cfg(feature="tui")]
+fn tui(args: &TuiArgs) -> ExitCode {
+    info!(path = ?args.target, "parsing");
+    let mut pe = match rustbin::parse_path(&args.target, ParseAs::PE) {
+        Ok(ParsedAs::PE(pe)) => pe,
+        Ok(_) => {
+            error!("Target is not a PE image.");
+            return ExitCode::from(4);
+        },
+        Err(err) => {
+            error!("Failed to parse as `PE`: {}", err.chain());
+            return ExitCode::from(4);
+        }
+    };
+
+    if let Err(err) = rustbin::tui::run(&mut pe, &args.target.display().to_string()) {
+        error!("Terminal error: {err}");
+        return ExitCode::from(1);
+    }
+    ExitCode::SUCCESS
+}
+
+/// Embedded capability rules extended with the JSON file at `path`.
+#[cfg(feature="json")]
+fn capability_rules(path: &Path) -> Result<CapabilityRules, String> {
+    let json = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let mut rules = CapabilityRules::default();
+    rules.extend_from_json(&json).map_err(|err| err.to_string())?;
+    Ok(rules)
+}
+
+/// Schema given with `--apiset`; a JSON file if it ends with `.json`, else `apisetschema.dll`.
+fn load_api_sets(args: &DepsArgs) -> Result<Option<ApiSetSchema>, ExitCode> {
+    let Some(path) = &args.apiset else {
+        return Ok(None);
+    };
+
+    let is_json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    let schema = if is_json {
+        #[cfg(feature="json")]
+        {
+            std::fs::read_to_string(path).map_err(|err| err.to_string())
+                .and_then(|json| ApiSetSchema::from_json(&json).map_err(|err| err.to_string()))
+        }
+        #[cfg(not(feature="json"))]
+        {
+            Err("JSON schemas need the `json` feature".to_string())
+        }
+    }
+    else {
+        match rustbin::parse_path(path, ParseAs::PE) {
+            Ok(ParsedAs::PE(mut pe)) => ApiSetSchema::from_image(&mut pe).map_err(|err| err.to_string()),
+            Ok(_) => Err("not a PE image".to_string()),
+            Err(err) => Err(err.chain()),
+        }
+    };
+
+    match schema {
+        Ok(schema) => {
+            debug!(path = ?path, sets = schema.len(), "API set schema loaded");
+            Ok(Some(schema))
+        },
+        Err(err) => {
+            error!(path = ?path, "Failed to load API set schema: {err}");
+            Err(ExitCode::from(4))
+        }
+    }
+}
+
+fn deps_tree(args: &DepsArgs) -> ExitCode {
+    let api_sets = match load_api_sets(args) {
+        Ok(api_sets) => api_sets,
+        Err(code) => return code,
+    };
+    let options = TreeOptions { search_paths: args.search_path.clone(), max_depth: args.max_depth, api_sets };
+    let tree = match DependencyTree::build(Path::new(&args.target), &options) {
+        Ok(tree) => tree,
+        Err(err) => {
+            error!("Failed to parse as `PE`: {}", err.chain());
+            return ExitCode::from(4);
+        }
+    };
+
+    let mut out = output!(&args.output);
+    match args.format {
+        #[cfg(feature="json")]
+        OutputFormat::JSON => {
+            let jstr = serde_json::to_string_pretty(&tree).unwrap();
+            writeln!(out, "{jstr}").unwrap();
+        },
+        OutputFormat::TEXT => { writeln!(out, "{tree}").unwrap(); },
+    }
+
+    if tree.is_complete() { ExitCode::SUCCESS } else { ExitCode::from(5) }
+}
+
+
+fn open_output(output: &Option<String>) -> io::Result<BufWriter<Box<dyn Write>>> {
+    Ok(BufWriter::new(match output {
+        Some(ref x) => Box::new(File::create(Path::new(x))?) as Box<dyn Write>,
+        None => Box::new(stdout()) as Box<dyn Write>,
+    }))
+}
+
+/// Write the target unchanged: `bytes` read from stdin or the file at `path`.
+fn output_binary(bytes: Option<&[u8]>, path: &Path, args: &Args) -> ExitCode {
+    let read;
+    let bytes = match bytes {
+        Some(bytes) => bytes,
+        None => match std::fs::read(path) {
+            Ok(bytes) => {
+                read = bytes;
+                &read
+            },
+            Err(err) => {
+                error!("Failed to read target: {err}");
+                return ExitCode::from(3);
+            }
+        },
+    };
+
+    let mut out = output!(&args.output);
+    if let Err(err) = out.write_all(bytes).and_then(|_| out.flush()) {
+        error!("Failed to write output: {err}");
+        return ExitCode::from(1);
+    }
+    ExitCode::SUCCESS
+}
+
+/// Objects and archives only have text output.
+fn output_text(parsed: &(impl Display + Debug), args: &Args) -> ExitCode {
+    let mut out = output!(&args.output);
+
+    match (args.format, args.level){
+        (OutputFormat::TEXT, OutputLevel::Debug) => { writeln!(out, "{parsed:#?}").unwrap(); },
+        (OutputFormat::TEXT, OutputLevel::Display) => { writeln!(out, "{parsed}").unwrap(); },
+
+        _ => {
+            error!("Unsupported combination {:?} + {:?}", args.format, args.level);
         },
     };
 
     ExitCode::SUCCESS
 }
 
+fn output_te(mut te: TeImage, args: &Args) -> ExitCode {
+    let report = if args.analyze { te.analyze().ok() } else { None };
+    let mut out = output!(&args.output);
+
+    match (args.format, args.level){
+        #[cfg(feature="json")]
+        (OutputFormat::JSON, OutputLevel::Minimal) => {
+            let mut min_te = MinTeImage::with_options(&te, &min_options(args));
+            if args.exclude.contains(&ExcludeOptions::Relocs) { min_te.relocations = None; }
+            min_te.analysis = report;
+            let jstr = serde_json::to_string_pretty(&min_te).unwrap();
+            writeln!(out, "{jstr}").unwrap();
+        },
+
+        (OutputFormat::TEXT, OutputLevel::Debug) => { writeln!(out, "{te:#?}").unwrap(); },
+        (OutputFormat::TEXT, OutputLevel::Display) => {
+            let mut te_text = String::new();
+            te.format_basic_headers(&mut te_text).unwrap();
+            te.format_sections(&mut te_text).unwrap();
+            if !args.exclude.contains(&ExcludeOptions::Relocs) { te.format_relocations(&mut te_text).unwrap(); }
+            writeln!(out, "{te_text}").unwrap();
+            if let Some(report) = report {
+                writeln!(out, "{report}").unwrap();
+            }
+        },
+
+        _ => {
+            error!("Unsupported combination {:?} + {:?}", args.format, args.level);
+        },
+    };
+
+    ExitCode::SUCCESS
+}
+
+fn format_pe_as_text(pe: &PeImage, exludes: &[ExcludeOptions], only: &[ReportSection]) -> String {
+    let parts: Vec<ReportSection> = if only.is_empty() {
+        ReportSection::ALL
+        .into_iter()
+        .filter(|part| !match part {
+            ReportSection::Imports => exludes.contains(&ExcludeOptions::Imports),
+            ReportSection::Exports => exludes.contains(&ExcludeOptions::Exports),
+            ReportSection::Relocations => exludes.contains(&ExcludeOptions::Relocs),
+            ReportSection::Resources => exludes.contains(&ExcludeOptions::Resources),
+            _ => false,
+        })
+        .collect()
+    } else {
+        only.to_vec()
+    };
 
-fn format_pe_as_text(pe: &PeImage, exludes: &Vec<ExcludeOptions>) -> String {
     let mut out_str = String::new();
-    pe.format_basic_headers(&mut out_str).unwrap();
-    pe.format_data_dirs(&mut out_str).unwrap();
-    pe.format_sections(&mut out_str).unwrap();
-    if !exludes.contains(&ExcludeOptions::Imports) && pe.has_imports() { pe.format_imports(&mut out_str).unwrap(); }
-    if !exludes.contains(&ExcludeOptions::Exports) && pe.has_exports() { pe.format_exports(&mut out_str).unwrap(); }
-    if !exludes.contains(&ExcludeOptions::Relocs) && pe.has_relocations() { pe.format_relocations(&mut out_str).unwrap(); }
-    if !exludes.contains(&ExcludeOptions::Resources) && pe.has_rsrc() { pe.format_resource_tree(&mut out_str, &String::from("  "), 1).unwrap(); }
-    
-    return out_str;
+    pe.format_report(&mut out_str, &parts).unwrap();
+    out_str
+}
+
+/// `report` of `pe`, parsed from `path`, in an envelope made now or at `SOURCE_DATE_EPOCH`.
+#[cfg(feature="json")]
+fn envelope<T: serde::Serialize>(report: T, path: &Path, pe: &mut PeImage, options: ReportOptions) -> Envelope<T> {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let file = FileInfo::of_image(&name, pe).unwrap_or_else(|err| {
+        warn!("Failed to hash target: {err}");
+        FileInfo { name, size: 0, hashes: Default::default() }
+    });
+    let time = env::var("SOURCE_DATE_EPOCH").ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .and_then(|epoch| DateTime::from_timestamp(epoch, 0))
+        .unwrap_or_else(Utc::now);
+    Envelope::new(report, file, options)
+        .with_limits(pe.limits)
+        .generated_at(time)
+}
+
+#[cfg(feature="json")]
+fn min_options(args: &Args) -> MinOptions {
+    MinOptions { raw_values: args.raw, timestamps: args.timestamps.into(), dos_fields: args.dos_fields, ..Default::default() }
 }
 
 fn exclude_min_pe_parts(pe: &mut MinPeImage, exludes: &Vec<ExcludeOptions>){
+    for exclude in exludes {
+        match exclude {
+            ExcludeOptions::Imports => {
+                pe.import_directories = None;
+                pe.import_counts = None;
+            },
+            ExcludeOptions::Exports => pe.export_directory = None,
+            ExcludeOptions::Relocs => pe.relocations = None,
+            ExcludeOptions::Resources => pe.resources = None,
+        }
+    }
+}
+
+fn exclude_full_pe_parts(pe: &mut FullPeImage, exludes: &Vec<ExcludeOptions>){
     for exclude in exludes {
         match exclude {
             ExcludeOptions::Imports => pe.import_directories = None,
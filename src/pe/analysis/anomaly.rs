@@ -0,0 +1,214 @@
+//! Structural oddities which parsing tolerates, but which change what gets parsed.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::pe::{file::MachineType, optional::{DataDirectory, DirectoryType, ImageType}, te::TeImage, PeImage};
+
+use super::entropy::{EntropyProfile, HIGH_ENTROPY};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum Anomaly {
+    /// PE header at an `e_lfanew` which is not 4-byte aligned.
+    MisalignedPeHeader { e_lfanew: u32 },
+    /// Optional header of the other width than `machine` needs, e.g. AMD64 with PE32. The
+    /// headers are parsed as `magic` says; the loader rejects such images.
+    MachineMagicMismatch { machine: MachineType, magic: ImageType, offset: u64 },
+    /// Directory has an rva but size 0; it is not parsed.
+    DirectoryWithoutSize { directory: DirectoryType, rva: u32, offset: u64 },
+    /// Directory has a size but rva 0; it is not parsed.
+    DirectoryWithoutRva { directory: DirectoryType, size: u32, offset: u64 },
+    /// IAT of a module differs from its import lookup table on disk; `bound` if the descriptor
+    /// says the IAT is bound, which explains the difference.
+    IatMismatch { module: String, slots: usize, bound: bool },
+    /// Import by ordinal with reserved bits set in its lookup table entry.
+    OrdinalReservedBits { module: String, iat_rva: u32, value: u64 },
+    /// File range of windows with entropy of compressed or encrypted data; `entropy` is the
+    /// highest of them. See `entropy::EntropyProfile::high_regions`.
+    HighEntropy { offset: u64, size: u64, entropy: f64 },
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MisalignedPeHeader { e_lfanew } =>
+                write!(f, "PE header @ {e_lfanew:#x} is not 4-byte aligned"),
+            Self::MachineMagicMismatch { machine, magic, offset } =>
+                write!(f, "Optional header @ {offset:#x} is {magic:?}, but machine is {machine:?}"),
+            Self::DirectoryWithoutSize { directory, rva, offset } =>
+                write!(f, "{directory:?} directory @ {offset:#x} has RVA {rva:#08x} but no size"),
+            Self::DirectoryWithoutRva { directory, size, offset } =>
+                write!(f, "{directory:?} directory @ {offset:#x} has size {size:#x} but no RVA"),
+            Self::IatMismatch { module, slots, bound } => {
+                write!(f, "IAT of {module} differs from its lookup table in {slots} slot(s)")?;
+                if *bound { write!(f, "; IAT is bound") } else { Ok(()) }
+            },
+            Self::OrdinalReservedBits { module, iat_rva, value } =>
+                write!(f, "Import of {module} @ {iat_rva:#08x} by ordinal has reserved bits set: {value:#x}"),
+            Self::HighEntropy { offset, size, entropy } =>
+                write!(f, "{size:#x} bytes @ {offset:#x} have high entropy, up to {entropy:.3}; packed or encrypted?"),
+        }
+    }
+}
+
+fn directory_anomalies<'a>(dirs: impl IntoIterator<Item = &'a DataDirectory>) -> Vec<Anomaly> {
+    dirs.into_iter()
+        .filter(|dir| dir.is_half_empty())
+        .map(|dir| if dir.size.value == 0 {
+            Anomaly::DirectoryWithoutSize { directory: dir.member, rva: dir.rva.value, offset: dir.rva.offset.into() }
+        } else {
+            Anomaly::DirectoryWithoutRva { directory: dir.member, size: dir.size.value, offset: dir.rva.offset.into() }
+        })
+        .collect()
+}
+
+fn machine_anomaly(pe: &PeImage) -> Option<Anomaly> {
+    let machine = pe.file.value.machine.value;
+    let magic = pe.optional.value.get_image_type();
+    let is_64 = match magic {
+        ImageType::PE64 => true,
+        ImageType::PE32 => false,
+        _ => return None,
+    };
+    (machine.is_64bit()? != is_64).then_some(Anomaly::MachineMagicMismatch { machine, magic, offset: pe.optional.offset.into() })
+}
+
+fn import_anomalies(pe: &PeImage) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    for desc in pe.imports.value.iter().map(|id| &id.value) {
+        let module = desc.name.clone().unwrap_or_default();
+        if !desc.iat_mismatches.is_empty() {
+            anomalies.push(Anomaly::IatMismatch { module: module.clone(), slots: desc.iat_mismatches.len(), bound: desc.is_bound() });
+        }
+        anomalies.extend(desc.imports.iter()
+            .filter(|import| import.has_reserved_bits())
+            .map(|import| Anomaly::OrdinalReservedBits { module: module.clone(), iat_rva: import.iat_rva(), value: import.value() }));
+    }
+    anomalies
+}
+
+pub(crate) fn analyze(pe: &PeImage) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    if !pe.dos.value.is_lfanew_aligned() {
+        anomalies.push(Anomaly::MisalignedPeHeader { e_lfanew: pe.dos.value.e_lfanew.value });
+    }
+    anomalies.extend(machine_anomaly(pe));
+    anomalies.extend(directory_anomalies(pe.data_dirs.value.iter().map(|dir| &dir.value)));
+    anomalies.extend(import_anomalies(pe));
+    anomalies
+}
+
+/// Regions of the file with high entropy. Windows overlapping the certificate table are left
+/// out, signatures have high entropy by nature.
+pub(crate) fn entropy_anomalies(pe: &PeImage, profile: &EntropyProfile) -> Vec<Anomaly> {
+    let certs = pe.certificate_range().unwrap_or_default();
+
+    let mut profile = profile.clone();
+    profile.windows.retain(|w| w.offset + w.size <= certs.start || w.offset >= certs.end);
+    profile.high_regions(HIGH_ENTROPY).into_iter()
+        .map(|(start, end, entropy)| Anomaly::HighEntropy { offset: start, size: end - start, entropy })
+        .collect()
+}
+
+pub(crate) fn analyze_te(te: &TeImage) -> Vec<Anomaly> {
+    let header = &te.header.value;
+    directory_anomalies([&header.relocations, &header.debug])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::pe::{file::MachineType, optional::{DirectoryType, ImageType}, PeImage};
+
+    use super::Anomaly;
+
+    #[test]
+    fn half_empty_directories() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+        assert!(pe.analyze().unwrap().anomalies.is_empty());
+
+        let exports = &mut pe.data_dirs.value[DirectoryType::Export as usize].value;
+        exports.size.value = 0;
+        let (rva, offset) = (exports.rva.value, u64::from(exports.rva.offset));
+        let debug = &mut pe.data_dirs.value[DirectoryType::Debug as usize].value;
+        debug.rva.value = 0;
+        let (size, debug_offset) = (debug.size.value, u64::from(debug.rva.offset));
+
+        assert!(!pe.has_exports());
+        assert!(pe.directory(DirectoryType::Export).is_none());
+
+        let anomalies = pe.analyze().unwrap().anomalies;
+        assert_eq!(anomalies, vec![
+            Anomaly::DirectoryWithoutSize { directory: DirectoryType::Export, rva, offset },
+            Anomaly::DirectoryWithoutRva { directory: DirectoryType::Debug, size, offset: debug_offset },
+        ]);
+    }
+
+    #[test]
+    fn misaligned_pe_header() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+        pe.dos.value.e_lfanew.value += 2;
+
+        let anomalies = pe.analyze().unwrap().anomalies;
+        assert_eq!(anomalies[0], Anomaly::MisalignedPeHeader { e_lfanew: pe.dos.value.e_lfanew.value });
+    }
+
+    #[test]
+    fn machine_magic_mismatch() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+        assert!(pe.validate().is_empty());
+
+        pe.file.value.machine.value = MachineType::I386;
+        let expected = Anomaly::MachineMagicMismatch { machine: MachineType::I386, magic: ImageType::PE64, offset: pe.optional.offset.into() };
+        assert_eq!(pe.validate(), vec![expected.clone()]);
+        assert!(pe.analyze().unwrap().anomalies.contains(&expected));
+        assert_eq!(expected.to_string(), format!("Optional header @ {:#x} is PE64, but machine is I386", pe.optional.offset));
+
+        pe.file.value.machine.value = MachineType::EBC;
+        assert!(pe.validate().is_empty());
+    }
+
+    #[test]
+    fn iat_differs_from_ilt() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut bytes = fs::read(path).unwrap();
+        let pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        assert!(pe.imports.value.iter().all(|id| id.value.iat_mismatches.is_empty()));
+
+        //Patch the second IAT slot of the second module, as a hook would.
+        let desc = &pe.imports.value[1].value;
+        let (module, iat_rva) = (desc.name.clone().unwrap(), desc.imports[1].iat_rva());
+        let offset = pe.rva_to_offset(iat_rva).unwrap() as usize;
+        bytes[offset..offset + 8].copy_from_slice(&0x7FFE_1234_5678u64.to_le_bytes());
+
+        let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        let desc = &pe.imports.value[1].value;
+        assert_eq!(desc.iat_mismatches.len(), 1);
+        assert_eq!(desc.iat_mismatches[0].iat_rva, iat_rva);
+        assert_eq!(desc.iat_mismatches[0].iat_value, 0x7FFE_1234_5678);
+        assert_eq!(desc.iat_mismatches[0].ilt_value, desc.imports[1].value());
+
+        let anomalies = pe.analyze().unwrap().anomalies;
+        assert_eq!(anomalies, vec![Anomaly::IatMismatch { module, slots: 1, bound: false }]);
+    }
+
+    #[test]
+    fn high_entropy_outside_certificates() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut bytes = fs::read(path).unwrap();
+        //Certificates have high entropy, but aren't reported.
+        assert!(!PeImage::parse_bytes(bytes.clone(), 0).unwrap().analyze().unwrap().anomalies.iter()
+            .any(|a| matches!(a, Anomaly::HighEntropy { .. })));
+
+        bytes.splice(0x2000..0x4000, (0..=255u8).cycle().take(0x2000));
+        let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        let anomalies = pe.analyze().unwrap().anomalies;
+        assert_eq!(anomalies, vec![Anomaly::HighEntropy { offset: 0x2000, size: 0x2000, entropy: 8.0 }]);
+    }
+}
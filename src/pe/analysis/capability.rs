@@ -0,0 +1,197 @@
+//! Capabilities suggested by imported functions, like networking or process injection.
+//!
+//! Rules map a category to function names. Names match exactly, or with the `A` or `W`
+//! suffix of the ANSI and wide variants. `CapabilityRules::default` is a small embedded
+//! ruleset; with the `json` feature more rules can be added from a JSON object of categories
+//! to function names, like `{"clipboard": ["GetClipboardData", "SetClipboardData"]}`.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::pe::{import::ImportedFunction, PeImage};
+
+const EMBEDDED_RULES: [(&str, &[&str]); 5] = [
+    ("networking", &[
+        "WSAStartup", "WSASocket", "socket", "connect", "bind", "listen", "accept", "send", "recv",
+        "sendto", "recvfrom", "gethostbyname", "getaddrinfo", "GetAddrInfo", "InternetOpen",
+        "InternetOpenUrl", "InternetConnect", "InternetReadFile", "HttpOpenRequest", "HttpSendRequest",
+        "URLDownloadToFile", "WinHttpOpen", "WinHttpConnect", "WinHttpSendRequest", "WinHttpReadData",
+    ]),
+    ("crypto", &[
+        "CryptAcquireContext", "CryptGenKey", "CryptImportKey", "CryptDeriveKey", "CryptEncrypt",
+        "CryptDecrypt", "CryptCreateHash", "CryptHashData", "CryptProtectData", "CryptUnprotectData",
+        "BCryptOpenAlgorithmProvider", "BCryptGenerateSymmetricKey", "BCryptEncrypt", "BCryptDecrypt",
+        "BCryptHashData", "NCryptOpenStorageProvider",
+    ]),
+    ("process_injection", &[
+        "VirtualAllocEx", "WriteProcessMemory", "CreateRemoteThread", "CreateRemoteThreadEx",
+        "NtCreateThreadEx", "RtlCreateUserThread", "QueueUserAPC", "NtQueueApcThread", "SetThreadContext",
+        "NtUnmapViewOfSection", "ZwUnmapViewOfSection", "NtMapViewOfSection", "SetWindowsHookEx",
+    ]),
+    ("registry", &[
+        "RegOpenKey", "RegOpenKeyEx", "RegCreateKey", "RegCreateKeyEx", "RegSetValue", "RegSetValueEx",
+        "RegQueryValueEx", "RegDeleteKey", "RegDeleteValue", "RegEnumKeyEx", "RegEnumValue",
+        "NtSetValueKey", "ZwSetValueKey",
+    ]),
+    ("anti_debug", &[
+        "IsDebuggerPresent", "CheckRemoteDebuggerPresent", "NtQueryInformationProcess",
+        "ZwQueryInformationProcess", "NtSetInformationThread", "ZwSetInformationThread",
+        "OutputDebugString", "DebugActiveProcess", "NtQueryObject",
+    ]),
+];
+
+/// Function names by category, in the order categories are reported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapabilityRules {
+    rules: Vec<(String, Vec<String>)>,
+}
+
+impl Default for CapabilityRules {
+    /// The embedded ruleset.
+    fn default() -> Self {
+        let mut rules = Self::empty();
+        for (category, functions) in EMBEDDED_RULES {
+            rules.add(category, functions.iter().copied());
+        }
+        rules
+    }
+}
+
+impl CapabilityRules {
+    /// Rules without any category.
+    pub fn empty() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Add `functions` to `category`, creating it if needed.
+    pub fn add<I, S>(&mut self, category: &str, functions: I) where I: IntoIterator<Item = S>, S: Into<String> {
+        let index = match self.rules.iter().position(|(c, _)| c == category) {
+            Some(index) => index,
+            None => {
+                self.rules.push((category.into(), Vec::new()));
+                self.rules.len() - 1
+            },
+        };
+        self.rules[index].1.extend(functions.into_iter().map(Into::into));
+    }
+
+    pub fn categories(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().map(|(c, _)| c.as_str())
+    }
+
+    /// Add rules from a JSON object of categories to arrays of function names.
+    #[cfg(feature="json")]
+    pub fn extend_from_json(&mut self, json: &str) -> serde_json::Result<()> {
+        let map: serde_json::Map<String, serde_json::Value> = serde_json::from_str(json)?;
+        for (category, functions) in map {
+            let functions: Vec<String> = serde_json::from_value(functions)?;
+            self.add(&category, functions);
+        }
+        Ok(())
+    }
+
+    /// Categories `function` falls in.
+    fn matches<'a>(&'a self, function: &'a str) -> impl Iterator<Item = &'a str> {
+        let base = function.strip_suffix(['A', 'W']);
+        self.rules.iter()
+            .filter(move |(_, functions)| functions.iter().any(|f| f == function || Some(f.as_str()) == base))
+            .map(|(c, _)| c.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct Capability {
+    pub category: String,
+    /// Imports in the category, as `module!function`.
+    pub imports: Vec<String>,
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} [{}]", self.category, self.imports.join(", "))
+    }
+}
+
+/// Categories of the imports of `modules`, as `(module, functions)`, in rule order.
+pub(crate) fn classify<'a>(modules: impl IntoIterator<Item = (&'a str, Vec<String>)>, rules: &CapabilityRules) -> Vec<Capability> {
+    let mut found: Vec<Capability> = rules.categories()
+        .map(|category| Capability { category: category.into(), imports: Vec::new() })
+        .collect();
+
+    for (module, functions) in modules {
+        for function in functions {
+            for category in rules.matches(&function) {
+                let capability = found.iter_mut().find(|c| c.category == category).unwrap();
+                let import = format!("{module}!{function}");
+                if !capability.imports.contains(&import) {
+                    capability.imports.push(import);
+                }
+            }
+        }
+    }
+
+    found.retain(|c| !c.imports.is_empty());
+    found
+}
+
+impl PeImage {
+    /// Capabilities suggested by static, bound and delay loaded imports, by `rules`.
+    /// Imports by ordinal are not matched.
+    pub fn capabilities(&self, rules: &CapabilityRules) -> Vec<Capability> {
+        let table = self.import_table();
+        let modules = table.iter().map(|m| {
+            let functions = m.imports.iter().filter_map(|i| match &i.function {
+                ImportedFunction::Name(name) => Some(name.clone()),
+                ImportedFunction::Ordinal(_) => None,
+            });
+            (m.module.as_str(), functions.collect())
+        });
+        classify(modules, rules)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{classify, CapabilityRules};
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn embedded_rules() {
+        let modules = vec![
+            ("ADVAPI32.dll", strings(&["RegOpenKeyExW", "RegCloseKey", "CryptAcquireContextA"])),
+            ("KERNEL32.dll", strings(&["IsDebuggerPresent", "WriteProcessMemory", "HeapAlloc", "CreateFileW"])),
+        ];
+        let found = classify(modules, &CapabilityRules::default());
+
+        let categories: Vec<_> = found.iter().map(|c| c.category.as_str()).collect();
+        assert_eq!(categories, vec!["crypto", "process_injection", "registry", "anti_debug"]);
+        assert_eq!(found[2].imports, strings(&["ADVAPI32.dll!RegOpenKeyExW"]));
+        assert_eq!(format!("{}", found[0]), "crypto [ADVAPI32.dll!CryptAcquireContextA]");
+    }
+
+    #[test]
+    fn suffix_is_only_a_or_w() {
+        let mut rules = CapabilityRules::empty();
+        rules.add("test", ["Send"]);
+        assert!(classify([("m", strings(&["SendW", "SendA", "SendX", "Sen"]))], &rules)[0].imports.len() == 2);
+    }
+
+    #[cfg(feature="json")]
+    #[test]
+    fn user_rules() {
+        let mut rules = CapabilityRules::default();
+        rules.extend_from_json(r#"{"clipboard": ["GetClipboardData"], "registry": ["SHSetValue"]}"#).unwrap();
+        assert_eq!(rules.categories().last(), Some("clipboard"));
+        assert!(rules.extend_from_json(r#"{"clipboard": "GetClipboardData"}"#).is_err());
+
+        let found = classify([("SHLWAPI.dll", strings(&["SHSetValueW"])), ("USER32.dll", strings(&["GetClipboardData"]))], &rules);
+        let categories: Vec<_> = found.iter().map(|c| c.category.as_str()).collect();
+        assert_eq!(categories, vec!["registry", "clipboard"]);
+    }
+}
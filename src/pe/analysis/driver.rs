@@ -0,0 +1,362 @@
+//! Checks and extraction specific to kernel-mode drivers.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::pe::{optional::{self, SubSystem}, section::Flags, PeImage, Result};
+
+use super::entry::read_code;
+
+/// Modules which only kernel-mode images import.
+pub const KERNEL_MODULES: [&str; 18] = [
+    "ntoskrnl.exe", "ntkrnlpa.exe", "ntkrnlmp.exe", "ntkrpamp.exe", "hal.dll", "wdfldr.sys",
+    "fltmgr.sys", "ndis.sys", "netio.sys", "tdi.sys", "ksecdd.sys", "cng.sys", "ci.dll",
+    "clfs.sys", "storport.sys", "scsiport.sys", "wmilib.sys", "ks.sys",
+];
+
+/// User-mode modules a driver can't load.
+const USER_MODULES: [&str; 5] = ["ntdll.dll", "kernel32.dll", "kernelbase.dll", "user32.dll", "advapi32.dll"];
+
+/// Imports used to build, send, complete or otherwise handle IRPs.
+const IRP_IMPORTS: [&str; 14] = [
+    "IoCompleteRequest", "IofCompleteRequest", "IoCallDriver", "IofCallDriver",
+    "IoCreateDevice", "IoDeleteDevice", "IoCreateSymbolicLink", "IoDeleteSymbolicLink",
+    "IoBuildDeviceIoControlRequest", "IoBuildSynchronousFsdRequest", "IoBuildAsynchronousFsdRequest",
+    "IoSetCompletionRoutineEx", "IoStartPacket", "IoStartNextPacket",
+];
+
+/// Imports only a Plug and Play (and so INF installed) driver needs.
+const PNP_IMPORTS: [&str; 9] = [
+    "IoRegisterDeviceInterface", "IoSetDeviceInterfaceState", "IoAttachDeviceToDeviceStack",
+    "IoAttachDeviceToDeviceStackSafe", "IoGetDeviceProperty", "IoOpenDeviceRegistryKey",
+    "IoRegisterPlugPlayNotification", "IoInvalidateDeviceRelations", "PoCallDriver",
+];
+
+/// `Component` name in the `WDF_BIND_INFO` emitted by the KMDF loader stub.
+const KMDF_COMPONENT: &str = "KmdfLibrary";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum EntrySource {
+    /// `AddressOfEntryPoint` as is.
+    #[default]
+    EntryPoint,
+    /// Target of the tail jump in a `GsDriverEntry` stub.
+    GsStub,
+    /// An export named `DriverEntry`.
+    Export,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct DriverEntry {
+    pub rva: u32,
+    pub source: EntrySource,
+}
+
+impl Display for DriverEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#08x} ({:?})", self.rva, self.source)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct WdfVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub build: u32,
+}
+
+impl Display for WdfVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.build)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct DriverInfo {
+    pub driver_entry: DriverEntry,
+    pub kernel_modules: Vec<String>,
+    pub irp_imports: Vec<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub wdf_version: Option<WdfVersion>,
+    /// No PnP or WDF imports; such legacy drivers are usually installed as a service without an INF.
+    pub inf_less: bool,
+    /// Has a discardable `INIT` section, freed once `DriverEntry` returns.
+    pub init_section: bool,
+    /// Sections named `PAGE*`, which the kernel may page out.
+    pub paged_sections: Vec<String>,
+    /// `FORCE_INTEGRITY` is set; Code Integrity checks the signature at load.
+    pub force_integrity: bool,
+    pub issues: Vec<String>,
+}
+
+impl Display for DriverInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "  Driver: {{")?;
+        writeln!(f, "    DriverEntry: {}", self.driver_entry)?;
+        writeln!(f, "    Kernel modules: [{}]", self.kernel_modules.join(", "))?;
+        writeln!(f, "    IRP imports: [{}]", self.irp_imports.join(", "))?;
+        if let Some(ver) = self.wdf_version {
+            writeln!(f, "    KMDF version: {ver}")?;
+        }
+        writeln!(f, "    INF-less: {}", self.inf_less)?;
+        writeln!(f, "    INIT: {}, Paged: [{}]", self.init_section, self.paged_sections.join(", "))?;
+        writeln!(f, "    ForceIntegrity: {}", self.force_integrity)?;
+        for issue in &self.issues {
+            writeln!(f, "    Issue: {issue}")?;
+        }
+        writeln!(f, "  }}")
+    }
+}
+
+pub(crate) fn analyze(pe: &mut PeImage) -> Result<DriverInfo> {
+    let mut info = DriverInfo::default();
+    let mut pnp = false;
+
+    for idesc in &pe.imports.value {
+        let dll = idesc.value.name.clone().unwrap_or_default();
+        let lower = dll.to_lowercase();
+
+        if KERNEL_MODULES.contains(&lower.as_str()) {
+            info.kernel_modules.push(dll.clone());
+        }
+        else if USER_MODULES.contains(&lower.as_str()) {
+            info.issues.push(format!("imports user-mode module {dll}"));
+        }
+
+        for name in idesc.value.get_imports_str() {
+            if IRP_IMPORTS.contains(&name.as_str()) {
+                info.irp_imports.push(name);
+            }
+            else if PNP_IMPORTS.contains(&name.as_str()) || name.starts_with("WdfVersion") {
+                pnp = true;
+            }
+        }
+    }
+
+    if info.kernel_modules.is_empty() {
+        info.issues.push("no kernel-mode module is imported".into());
+    }
+
+    info.driver_entry = find_driver_entry(pe)?;
+    info.wdf_version = find_kmdf_version(pe)?;
+    info.inf_less = !pnp && info.wdf_version.is_none();
+
+    let sections: Vec<(String, Flags)> = pe.sections.value.iter()
+        .map(|s| (s.value.name_lossy(), Flags::from_bits_truncate(s.value.charactristics.value)))
+        .collect();
+    check_code_integrity(&mut info, &sections, optional::Flags::from_bits_truncate(pe.optional.value.dll_charactristics()));
+
+    Ok(info)
+}
+
+/// Section layout and the flags Code Integrity and HVCI look at.
+pub(crate) fn check_code_integrity(info: &mut DriverInfo, sections: &[(String, Flags)], dll_flags: optional::Flags) {
+    for (name, flags) in sections {
+        if name == "INIT" {
+            info.init_section = true;
+        }
+        else if name.starts_with("PAGE") {
+            info.paged_sections.push(name.clone());
+        }
+
+        if flags.is_writable() && flags.is_executable() {
+            info.issues.push(format!("section {name} is writable and executable; HVCI won't load the driver"));
+        }
+    }
+
+    info.force_integrity = dll_flags.contains(optional::Flags::FORCE_INTEGRITY);
+    if !dll_flags.contains(optional::Flags::NX_COMPAT) {
+        info.issues.push("NX_COMPAT is not set; HVCI won't load the driver".into());
+    }
+}
+
+impl PeImage {
+    /// Image is a kernel-mode driver: a `NATIVE` image importing a kernel module. Native
+    /// user-mode programs, like `smss.exe`, import only `ntdll.dll`.
+    pub fn is_kernel_driver(&self) -> bool {
+        self.optional.value.subsystem() == SubSystem::NATIVE &&
+        self.imports.value.iter()
+            .filter_map(|id| id.value.name.as_deref())
+            .any(|dll| KERNEL_MODULES.contains(&dll.to_lowercase().as_str()))
+    }
+}
+
+fn find_driver_entry(pe: &mut PeImage) -> Result<DriverEntry> {
+    if let Some(export) = pe.exports.value.exports.iter().find(|e| e.name.value == "DriverEntry") {
+        return Ok(DriverEntry { rva: export.address.value, source: EntrySource::Export });
+    }
+
+    let entry = pe.optional.value.entry_point();
+    let code = read_code(pe, entry, GS_STUB_MAX)?;
+
+    Ok(match gs_stub_target(&code, entry) {
+        Some(target) => DriverEntry { rva: target, source: EntrySource::GsStub },
+        None => DriverEntry { rva: entry, source: EntrySource::EntryPoint },
+    })
+}
+
+const GS_STUB_MAX: u32 = 0x40;
+
+/// `GsDriverEntry` calls `__security_init_cookie` and then tail-jumps to `DriverEntry`.
+/// Returns the jump target if `code` looks like such a stub.
+pub(crate) fn gs_stub_target(code: &[u8], rva: u32) -> Option<u32> {
+    let call = code.iter().position(|b| *b == 0xE8)?;
+    let mut pos = call + 5;
+
+    while pos + 5 <= code.len() {
+        match code[pos] {
+            0xE9 => {
+                let rel = i32::from_le_bytes(code[pos+1..pos+5].try_into().ok()?);
+                let next = rva.checked_add(pos as u32 + 5)?;
+                return next.checked_add_signed(rel);
+            },
+            //Any other call or return means this isn't the stub.
+            0xE8 | 0xC3 | 0xC2 | 0xCC => return None,
+            _ => pos += 1,
+        }
+    }
+    None
+}
+
+fn find_kmdf_version(pe: &mut PeImage) -> Result<Option<WdfVersion>> {
+    let uses_wdf = pe.imports.value.iter()
+        .any(|id| id.value.name.as_deref().is_some_and(|n| n.eq_ignore_ascii_case("wdfldr.sys")));
+    if !uses_wdf {
+        return Ok(None);
+    }
+
+    let mut data = Vec::new();
+    for i in 0..pe.sections.value.len() {
+        let section = &pe.sections.value[i].value;
+        let flags = Flags::from_bits_truncate(section.charactristics.value);
+        if flags.contains(Flags::INITIALIZED_DATA) && section.sizeof_raw_data.value > 0 {
+            let rva = section.virtual_address.value;
+            data.push((rva, pe.section_data(i)?));
+        }
+    }
+
+    let is_64 = matches!(pe.optional.value, crate::pe::optional::OptionalHeader::X64(_));
+    Ok(find_bind_info_version(&data, pe.optional.value.image_base(), is_64))
+}
+
+/// Find the `WDF_BIND_INFO` whose `Component` points to `L"KmdfLibrary"` and return its `Version`.
+/// `sections` holds the start rva and raw content of each data section.
+pub(crate) fn find_bind_info_version(sections: &[(u32, impl AsRef<[u8]>)], image_base: u64, is_64: bool) -> Option<WdfVersion> {
+    let needle: Vec<u8> = KMDF_COMPONENT.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+    let ptr_size = if is_64 { 8 } else { 4 };
+
+    for (rva, content) in sections {
+        let content = content.as_ref();
+        let Some(pos) = content.windows(needle.len()).position(|w| w == needle.as_slice()) else {
+            continue;
+        };
+        let va = image_base + *rva as u64 + pos as u64;
+        let ptr = if is_64 { va.to_le_bytes().to_vec() } else { (va as u32).to_le_bytes().to_vec() };
+
+        for (_, content) in sections {
+            let content = content.as_ref();
+            //`Component` follows `ULONG Size` and is pointer aligned.
+            let hit = (0..content.len() / ptr_size)
+                .map(|i| i * ptr_size)
+                .find(|i| content[*i..*i + ptr_size] == ptr[..]);
+
+            if let Some(at) = hit {
+                let ver = at + ptr_size;
+                if ver + 12 > content.len() {
+                    continue;
+                }
+                let read = |o: usize| u32::from_le_bytes(content[o..o+4].try_into().unwrap());
+                return Some(WdfVersion { major: read(ver), minor: read(ver + 4), build: read(ver + 8) });
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pe::{builder::PeBuilder, file::MachineType, optional::{self, SubSystem}, section::Flags, PeImage};
+
+    use super::{check_code_integrity, find_bind_info_version, gs_stub_target, DriverInfo, WdfVersion};
+
+    #[test]
+    fn kernel_driver_imports_kernel() {
+        let image = |subsystem, dll| {
+            let bytes = PeBuilder::new(MachineType::AMD64).with_subsystem(subsystem).with_import(dll, &["Nt"]).build().unwrap();
+            PeImage::parse_bytes(bytes, 0).unwrap()
+        };
+        assert!(image(SubSystem::NATIVE, "ntoskrnl.exe").is_kernel_driver());
+        assert!(image(SubSystem::NATIVE, "FLTMGR.SYS").is_kernel_driver());
+
+        //Native user-mode program.
+        let mut smss = image(SubSystem::NATIVE, "ntdll.dll");
+        assert!(!smss.is_kernel_driver());
+        assert!(smss.analyze().unwrap().driver.is_none());
+
+        assert!(!image(SubSystem::WINDOWS_CUI, "ntoskrnl.exe").is_kernel_driver());
+    }
+
+    #[test]
+    fn gs_stub_x64() {
+        let code = [
+            0x48, 0x89, 0x5C, 0x24, 0x08, 0x57, 0x48, 0x83, 0xEC, 0x20, 0x48, 0x8B, 0xDA, 0x48, 0x8B, 0xF9,
+            0xE8, 0x10, 0x00, 0x00, 0x00, 0x48, 0x8B, 0xD3, 0x48, 0x8B, 0xCF, 0x48, 0x8B, 0x5C, 0x24, 0x30,
+            0x48, 0x83, 0xC4, 0x20, 0x5F, 0xE9, 0xD0, 0xFF, 0xFF, 0xFF,
+        ];
+        //jmp at 0x25, next at 0x2a; 0x502a - 0x30
+        assert_eq!(gs_stub_target(&code, 0x5000), Some(0x4ffa));
+    }
+
+    #[test]
+    fn not_a_gs_stub() {
+        let code = [0x55, 0x8B, 0xEC, 0xE8, 0x10, 0x00, 0x00, 0x00, 0x5D, 0xC3, 0xE9, 0x00, 0x00, 0x00, 0x00];
+        assert_eq!(gs_stub_target(&code, 0x1000), None);
+    }
+
+    #[test]
+    fn kmdf_bind_info_x64() {
+        let mut rdata = vec![0u8; 0x20];
+        rdata.extend("KmdfLibrary".encode_utf16().flat_map(|c| c.to_le_bytes()));
+        rdata.extend([0, 0]);
+
+        let mut data = vec![0u8; 0x10];
+        data.extend(0x38u32.to_le_bytes()); //Size
+        data.extend(0u32.to_le_bytes()); //padding
+        data.extend((0x1_4000_0000u64 + 0x2000 + 0x20).to_le_bytes());
+        data.extend([1u32, 15, 0].iter().flat_map(|v| v.to_le_bytes()));
+
+        let sections = vec![(0x2000u32, rdata), (0x3000u32, data)];
+        let ver = find_bind_info_version(&sections, 0x1_4000_0000, true).unwrap();
+        assert_eq!(ver, WdfVersion { major: 1, minor: 15, build: 0 });
+        assert_eq!(format!("{ver}"), "1.15.0");
+
+        assert_eq!(find_bind_info_version(&sections, 0x1_0000_0000, true), None);
+    }
+
+    #[test]
+    fn code_integrity_checks() {
+        let sections = vec![
+            (".text".to_string(), Flags::CODE | Flags::MEM_EXECUTE | Flags::MEM_READ),
+            ("PAGE".to_string(), Flags::CODE | Flags::MEM_EXECUTE | Flags::MEM_READ),
+            ("PAGEDATA".to_string(), Flags::MEM_READ | Flags::MEM_WRITE),
+            ("INIT".to_string(), Flags::MEM_EXECUTE | Flags::MEM_READ | Flags::MEM_WRITE | Flags::MEM_DISCARDABLE),
+        ];
+
+        let mut info = DriverInfo::default();
+        check_code_integrity(&mut info, &sections, optional::Flags::NX_COMPAT | optional::Flags::FORCE_INTEGRITY);
+        assert!(info.init_section && info.force_integrity);
+        assert_eq!(info.paged_sections, vec!["PAGE", "PAGEDATA"]);
+        assert_eq!(info.issues, vec!["section INIT is writable and executable; HVCI won't load the driver".to_string()]);
+
+        let mut info = DriverInfo::default();
+        check_code_integrity(&mut info, &sections[..1], optional::Flags::empty());
+        assert!(!info.init_section && !info.force_integrity);
+        assert_eq!(info.issues.len(), 1);
+        assert!(info.issues[0].starts_with("NX_COMPAT"));
+    }
+}
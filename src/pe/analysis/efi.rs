@@ -0,0 +1,229 @@
+//! Checks specific to UEFI images, both PE32(+) and TE.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::pe::{file::{self, MachineType}, optional::{OptionalHeader, SubSystem}, te::TeHeader, PeImage};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum EfiFormat {
+    #[default]
+    PE32,
+    #[serde(rename="PE32+")]
+    PE64,
+    TE,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct EfiInfo {
+    pub format: EfiFormat,
+    pub subsystem: SubSystem,
+    pub machine: MachineType,
+    /// UEFI name of the platform `machine` runs on, if UEFI supports it.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub platform: Option<&'static str>,
+    pub has_relocations: bool,
+    pub relocs_stripped: bool,
+    /// `None` for TE images, which drop the alignment fields.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub section_alignment: Option<u32>,
+    pub issues: Vec<String>,
+}
+
+impl Display for EfiInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "  EFI: {{")?;
+        writeln!(f, "    Format: {:?}, Subsystem: {:?}", self.format, self.subsystem)?;
+        writeln!(f, "    Machine: {:?}, Platform: {}", self.machine, self.platform.unwrap_or("unsupported"))?;
+        writeln!(f, "    Relocations: {}, RelocsStripped: {}", self.has_relocations, self.relocs_stripped)?;
+        if let Some(alignment) = self.section_alignment {
+            writeln!(f, "    SectionAlignment: {alignment:#x}")?;
+        }
+        for issue in &self.issues {
+            writeln!(f, "    Issue: {issue}")?;
+        }
+        writeln!(f, "  }}")
+    }
+}
+
+pub(crate) fn is_efi_subsystem(subsystem: SubSystem) -> bool {
+    matches!(subsystem,
+        SubSystem::EFI_APPLICATION | SubSystem::EFI_BOOT_SERVICE_DRIVER |
+        SubSystem::EFI_RUNTIME_DRIVER | SubSystem::EFI_ROM)
+}
+
+/// Page size firmware maps images with; per section memory protection needs sections
+/// aligned to it.
+const EFI_PAGE_SIZE: u32 = 0x1000;
+
+/// Alignment of runtime drivers on AArch64, which the OS may map with 64 KiB pages.
+const AARCH64_RUNTIME_ALIGNMENT: u32 = 0x10000;
+
+/// Platform name used by the UEFI specification for `machine`.
+pub fn platform_name(machine: MachineType) -> Option<&'static str> {
+    match machine {
+        MachineType::I386 => Some("IA32"),
+        MachineType::AMD64 => Some("X64"),
+        MachineType::IA64 => Some("IPF"),
+        MachineType::ARM | MachineType::THUMB | MachineType::ARMNT => Some("ARM"),
+        MachineType::ARM64 => Some("AARCH64"),
+        MachineType::RISCV32 => Some("RISCV32"),
+        MachineType::RISCV64 => Some("RISCV64"),
+        MachineType::LOONGARCH64 => Some("LOONGARCH64"),
+        MachineType::EBC => Some("EBC"),
+        _ => None,
+    }
+}
+
+/// Optional header format the platform expects; `None` if either works (EBC).
+fn expected_format(machine: MachineType) -> Option<EfiFormat> {
+    match machine {
+        MachineType::I386 | MachineType::ARM | MachineType::THUMB | MachineType::ARMNT |
+        MachineType::RISCV32 => Some(EfiFormat::PE32),
+        MachineType::AMD64 | MachineType::IA64 | MachineType::ARM64 |
+        MachineType::RISCV64 | MachineType::LOONGARCH64 => Some(EfiFormat::PE64),
+        _ => None,
+    }
+}
+
+fn check(info: &mut EfiInfo) {
+    info.platform = platform_name(info.machine);
+
+    match info.platform {
+        None => info.issues.push(format!("machine {:?} is not a UEFI platform", info.machine)),
+        Some(platform) => if info.format != EfiFormat::TE {
+            if let Some(expected) = expected_format(info.machine) {
+                if expected != info.format {
+                    info.issues.push(format!("{platform} images must be {expected:?}, found {:?}", info.format));
+                }
+            }
+        }
+    }
+
+    if !info.has_relocations {
+        let issue = if info.subsystem == SubSystem::EFI_RUNTIME_DRIVER {
+            "no base relocations; a runtime driver can't be relocated by SetVirtualAddressMap"
+        }
+        else {
+            "no base relocations; firmware may refuse to load the image away from its preferred base"
+        };
+        info.issues.push(issue.into());
+    }
+    else if info.relocs_stripped {
+        info.issues.push("RELOCS_STRIPPED is set but a relocation directory is present".into());
+    }
+
+    if let Some(alignment) = info.section_alignment {
+        let required = if info.subsystem == SubSystem::EFI_RUNTIME_DRIVER && info.machine == MachineType::ARM64 {
+            AARCH64_RUNTIME_ALIGNMENT
+        }
+        else {
+            EFI_PAGE_SIZE
+        };
+
+        if alignment == 0 || alignment % required != 0 {
+            info.issues.push(format!("section alignment {alignment:#x} is not a multiple of {required:#x}; firmware can't protect sections separately"));
+        }
+    }
+}
+
+pub(crate) fn analyze(pe: &PeImage) -> EfiInfo {
+    let format = match pe.optional.value {
+        OptionalHeader::X86(_) => EfiFormat::PE32,
+        OptionalHeader::X64(_) => EfiFormat::PE64,
+    };
+    let flags = file::Flags::from_bits_truncate(pe.file.value.charactristics.value);
+
+    let mut info = EfiInfo {
+        format,
+        subsystem: pe.optional.value.subsystem(),
+        machine: pe.file.value.machine.value,
+        has_relocations: pe.has_relocations(),
+        relocs_stripped: flags.stripped_relocs(),
+        section_alignment: Some(pe.optional.value.section_alignment()),
+        ..Default::default()
+    };
+    check(&mut info);
+    info
+}
+
+impl PeImage {
+    /// Image is a UEFI application or driver, including DXE and PEI modules in PE form.
+    pub fn is_efi(&self) -> bool {
+        is_efi_subsystem(self.optional.value.subsystem())
+    }
+}
+
+/// Run the EFI checks on a TE header. TE images are always UEFI images, so no subsystem
+/// check is done before.
+pub fn analyze_te(te: &TeHeader) -> EfiInfo {
+    let mut info = EfiInfo {
+        format: EfiFormat::TE,
+        subsystem: te.subsystem.value,
+        machine: te.machine.value,
+        has_relocations: te.has_relocations(),
+        ..Default::default()
+    };
+    check(&mut info);
+
+    if !is_efi_subsystem(info.subsystem) {
+        info.issues.push(format!("subsystem {:?} is not an EFI subsystem", info.subsystem));
+    }
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pe::{file::MachineType, optional::SubSystem, te::TeHeader}, types::HeaderField};
+
+    use super::{analyze_te, check, EfiFormat, EfiInfo};
+
+    #[test]
+    fn efi_format_mismatch() {
+        let mut info = EfiInfo { format: EfiFormat::PE32, machine: MachineType::AMD64, has_relocations: true, ..Default::default() };
+        check(&mut info);
+        assert_eq!(info.platform, Some("X64"));
+        assert_eq!(info.issues, vec!["X64 images must be PE64, found PE32".to_string()]);
+
+        let mut info = EfiInfo { format: EfiFormat::PE32, machine: MachineType::EBC, has_relocations: true, ..Default::default() };
+        check(&mut info);
+        assert!(info.issues.is_empty());
+    }
+
+    #[test]
+    fn efi_section_alignment() {
+        let mut info = EfiInfo {
+            format: EfiFormat::PE64, machine: MachineType::AMD64, has_relocations: true,
+            subsystem: SubSystem::EFI_RUNTIME_DRIVER, section_alignment: Some(0x1000), ..Default::default()
+        };
+        check(&mut info);
+        assert!(info.issues.is_empty());
+
+        let mut info = EfiInfo { section_alignment: Some(0x20), issues: Vec::new(), ..info };
+        check(&mut info);
+        assert_eq!(info.issues.len(), 1);
+        assert!(info.issues[0].starts_with("section alignment 0x20"));
+
+        let mut info = EfiInfo { machine: MachineType::ARM64, section_alignment: Some(0x1000), issues: Vec::new(), ..info };
+        check(&mut info);
+        assert_eq!(info.issues.len(), 1);
+        assert!(info.issues[0].contains("multiple of 0x10000"));
+    }
+
+    #[test]
+    fn efi_te_without_relocs() {
+        let mut te = TeHeader::new();
+        te.machine = HeaderField::at_offset(MachineType::ARM64, 2);
+        te.subsystem = HeaderField::at_offset(SubSystem::EFI_RUNTIME_DRIVER, 5);
+
+        let info = analyze_te(&te);
+        assert_eq!(info.format, EfiFormat::TE);
+        assert_eq!(info.platform, Some("AARCH64"));
+        assert!(!info.has_relocations);
+        assert_eq!(info.issues.len(), 1);
+        assert!(info.issues[0].contains("SetVirtualAddressMap"));
+    }
+}
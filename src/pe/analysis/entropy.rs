@@ -0,0 +1,153 @@
+//! Shannon entropy of file content in fixed windows, to plot or to find packed regions.
+//!
+//! Compressed or encrypted data is close to 8 bits per byte; code and text are well below.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::pe::{check_alloc, PeImage, Result};
+
+pub const DEFAULT_WINDOW: u64 = 4096;
+/// Entropy from which a window is taken as compressed or encrypted.
+pub const HIGH_ENTROPY: f64 = 7.2;
+
+/// Levels of the sparkline, from 0 to 8 bits per byte.
+const SPARK_LEVELS: &[u8] = b" .:-=+*#%@";
+
+/// Entropy in bits per byte, from 0 to 8.
+pub fn shannon(bytes: &[u8]) -> f64 {
+    if bytes.is_empty() {
+        return 0.0;
+    }
+
+    let mut counts = [0u64; 256];
+    for b in bytes {
+        counts[*b as usize] += 1;
+    }
+
+    let len = bytes.len() as f64;
+    counts.iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntropyWindow {
+    pub offset: u64,
+    pub size: u64,
+    pub entropy: f64,
+    /// Section with the first byte of the window, for profiles of a PE image.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub section: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct EntropyProfile {
+    pub window: u64,
+    /// Distance between the starts of windows; windows overlap if it is less than `window`.
+    pub step: u64,
+    pub windows: Vec<EntropyWindow>,
+}
+
+impl EntropyProfile {
+    /// Entropy of `window` bytes every `step` bytes of `bytes`, read at file offset `base`.
+    /// The last window may be shorter.
+    pub fn from_bytes(bytes: &[u8], base: u64, window: u64, step: u64) -> Self {
+        let (window, step) = (window.max(1), step.max(1));
+        let windows = (0..bytes.len() as u64).step_by(step as usize)
+            .map(|start| {
+                let end = (start + window).min(bytes.len() as u64);
+                let content = &bytes[start as usize..end as usize];
+                EntropyWindow { offset: base + start, size: end - start, entropy: shannon(content), section: None }
+            })
+            .collect();
+
+        Self { window, step, windows }
+    }
+
+    /// Ranges `[start, end)` of consecutive windows with entropy of at least `threshold`, with
+    /// the highest entropy in each.
+    pub fn high_regions(&self, threshold: f64) -> Vec<(u64, u64, f64)> {
+        let mut regions: Vec<(u64, u64, f64)> = Vec::new();
+        for w in self.windows.iter().filter(|w| w.entropy >= threshold) {
+            match regions.last_mut() {
+                Some((_, end, max)) if w.offset <= *end => {
+                    *end = (*end).max(w.offset + w.size);
+                    *max = max.max(w.entropy);
+                },
+                _ => regions.push((w.offset, w.offset + w.size, w.entropy)),
+            }
+        }
+        regions
+    }
+
+    /// One character per window, from ` ` for 0 to `@` for 8 bits per byte.
+    pub fn sparkline(&self) -> String {
+        let top = (SPARK_LEVELS.len() - 1) as f64;
+        self.windows.iter()
+            .map(|w| SPARK_LEVELS[(w.entropy / 8.0 * top).round().clamp(0.0, top) as usize] as char)
+            .collect()
+    }
+}
+
+impl Display for EntropyProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Entropy (Window: {:#x}, Step: {:#x}, Windows: {}):", self.window, self.step, self.windows.len())?;
+        writeln!(f, "  [{}]", self.sparkline())?;
+        for (start, end, max) in self.high_regions(HIGH_ENTROPY) {
+            writeln!(f, "  High: {start:#08x}..{end:#08x}, Max: {max:.3}")?;
+        }
+        Ok(())
+    }
+}
+
+impl PeImage {
+    /// Entropy profile of the whole file with windows named by their section. Headers and
+    /// overlay are part of it, so the file is read as is rather than through `section_data`.
+    pub fn entropy_profile(&mut self, window: u64, step: u64) -> Result<EntropyProfile> {
+        let size = self.reader.end_offset()?;
+        check_alloc("File", size, self.limits.max_alloc)?;
+        let bytes = self.reader.read_bytes_at_offset(0, size as usize)?;
+
+        let mut profile = EntropyProfile::from_bytes(&bytes, 0, window, step);
+        let sections = self.section_lookup();
+        for w in &mut profile.windows {
+            w.section = u32::try_from(w.offset).ok().and_then(|o| sections.section_for_offset(o)).map(|s| s.name_lossy());
+        }
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shannon, EntropyProfile};
+
+    #[test]
+    fn profile_and_regions() {
+        assert_eq!(shannon(&[]), 0.0);
+        assert_eq!(shannon(&[7; 100]), 0.0);
+        assert_eq!(shannon(&[0, 1, 2, 3]), 2.0);
+
+        //Zeros, all byte values, zeros.
+        let mut bytes = vec![0u8; 0x100];
+        bytes.extend((0..=255u8).cycle().take(0x200));
+        bytes.extend([0u8; 0x80]);
+
+        let profile = EntropyProfile::from_bytes(&bytes, 0x1000, 0x100, 0x100);
+        let entropy: Vec<f64> = profile.windows.iter().map(|w| w.entropy).collect();
+        assert_eq!(entropy, [0.0, 8.0, 8.0, 0.0]);
+        assert_eq!(profile.windows[3].offset, 0x1300);
+        assert_eq!(profile.windows[3].size, 0x80);
+        assert_eq!(profile.sparkline(), " @@ ");
+        assert_eq!(profile.high_regions(7.2), [(0x1100, 0x1300, 8.0)]);
+
+        let overlapping = EntropyProfile::from_bytes(&bytes, 0, 0x100, 0x80);
+        assert_eq!(overlapping.windows.len(), 7);
+        assert_eq!(overlapping.high_regions(7.2), [(0x100, 0x300, 8.0)]);
+    }
+}
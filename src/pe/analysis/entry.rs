@@ -0,0 +1,204 @@
+//! What runs at the entry point, and what the image is entered as besides.
+//!
+//! The entry point is matched against the first bytes of common CRT startup stubs; anything
+//! else is custom code. Exports like `DllRegisterServer` or `ServiceMain` and a few imports
+//! tell COM servers, services and drivers apart.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::{pe::{section::Flags, PeImage, Result}, types::Rva};
+
+use super::driver::gs_stub_target;
+
+/// Bytes read at the entry point to match stubs.
+const STUB_MAX: u32 = 0x40;
+
+/// First bytes of CRT startup stubs; `None` matches any byte.
+const CRT_STUBS: [(&str, &[Option<u8>]); 6] = [
+    //sub rsp, 28h; call __security_init_cookie; add rsp, 28h; jmp __scrt_common_main_seh
+    ("MSVC mainCRTStartup (x64)", &[
+        Some(0x48), Some(0x83), Some(0xEC), Some(0x28), Some(0xE8), None, None, None, None,
+        Some(0x48), Some(0x83), Some(0xC4), Some(0x28), Some(0xE9),
+    ]),
+    //Saves registers, then calls __security_init_cookie if reason is DLL_PROCESS_ATTACH.
+    ("MSVC _DllMainCRTStartup (x64)", &[
+        Some(0x48), Some(0x89), Some(0x5C), Some(0x24), Some(0x08), Some(0x48), Some(0x89), Some(0x74),
+        Some(0x24), Some(0x10), Some(0x57), Some(0x48), Some(0x83), Some(0xEC), Some(0x20), Some(0x49),
+        Some(0x8B), Some(0xF8), Some(0x8B), Some(0xDA), Some(0x48), Some(0x8B), Some(0xF1), Some(0x83),
+        Some(0xFA), Some(0x01), Some(0x75), Some(0x05), Some(0xE8),
+    ]),
+    //call __security_init_cookie; jmp __scrt_common_main_seh
+    ("MSVC mainCRTStartup (x86)", &[Some(0xE8), None, None, None, None, Some(0xE9)]),
+    //mov edi, edi; push ebp; mov ebp, esp; cmp [ebp+0Ch], 1; jnz; call __security_init_cookie
+    ("MSVC _DllMainCRTStartup (x86)", &[
+        Some(0x8B), Some(0xFF), Some(0x55), Some(0x8B), Some(0xEC), Some(0x83), Some(0x7D), Some(0x0C),
+        Some(0x01), Some(0x75), None, Some(0xE8),
+    ]),
+    //sub rsp, 28h; mov rax, [__mingw_app_type]; mov dword [rax], type; call __tmainCRTStartup
+    ("MinGW mainCRTStartup (x64)", &[
+        Some(0x48), Some(0x83), Some(0xEC), Some(0x28), Some(0x48), Some(0x8B), Some(0x05), None,
+        None, None, None, Some(0xC7), Some(0x00), None, Some(0x00), Some(0x00), Some(0x00), Some(0xE8),
+    ]),
+    //push ebp; mov ebp, esp; sub esp, 18h; mov dword [esp], type; call ___set_app_type
+    ("MinGW mainCRTStartup (x86)", &[
+        Some(0x55), Some(0x89), Some(0xE5), Some(0x83), Some(0xEC), Some(0x18), Some(0xC7), Some(0x04),
+        Some(0x24), None, Some(0x00), Some(0x00), Some(0x00), Some(0xE8),
+    ]),
+];
+
+/// Exports which make an image a COM server.
+const COM_EXPORTS: [&str; 4] = ["DllRegisterServer", "DllUnregisterServer", "DllGetClassObject", "DllCanUnloadNow"];
+
+/// Imports a service process needs to talk to the service control manager.
+const SERVICE_IMPORTS: [&str; 6] = [
+    "StartServiceCtrlDispatcherA", "StartServiceCtrlDispatcherW", "RegisterServiceCtrlHandlerA",
+    "RegisterServiceCtrlHandlerW", "RegisterServiceCtrlHandlerExA", "RegisterServiceCtrlHandlerExW",
+];
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum EntryKind {
+    /// `AddressOfEntryPoint` is 0; usual for resource only DLLs.
+    #[default]
+    None,
+    /// Startup stub of a C runtime, by name.
+    Crt(String),
+    Custom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum ImageRole {
+    /// Exports the `DllRegisterServer` family.
+    ComServer,
+    /// Exports `ServiceMain` or registers with the service control manager.
+    Service,
+    /// Exports `DriverEntry` or is a kernel driver.
+    Driver,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct EntryInfo {
+    pub rva: u32,
+    pub kind: EntryKind,
+    /// Exports named like well known entry points, e.g. `ServiceMain`.
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub named_entries: Vec<String>,
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub roles: Vec<ImageRole>,
+}
+
+impl Display for EntryInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#08x} ", self.rva)?;
+        match &self.kind {
+            EntryKind::None => write!(f, "(None)")?,
+            EntryKind::Crt(name) => write!(f, "(CRT: {name})")?,
+            EntryKind::Custom => write!(f, "(Custom)")?,
+        }
+        if !self.named_entries.is_empty() {
+            write!(f, ", Named: [{}]", self.named_entries.join(", "))?;
+        }
+        if !self.roles.is_empty() {
+            write!(f, ", Roles: {:?}", self.roles)?;
+        }
+        Ok(())
+    }
+}
+
+/// Name of the CRT stub `code` starts with.
+pub(crate) fn crt_stub(code: &[u8]) -> Option<&'static str> {
+    CRT_STUBS.iter()
+        .find(|(_, pattern)| code.len() >= pattern.len() &&
+            pattern.iter().zip(code).all(|(p, b)| p.is_none_or(|p| p == *b)))
+        .map(|(name, _)| *name)
+}
+
+/// Up to `max` bytes of executable code at `rva`; empty if `rva` is not in an executable section.
+pub(crate) fn read_code(pe: &mut PeImage, rva: u32, max: u32) -> Result<Vec<u8>> {
+    let Some(section) = pe.section_lookup().section_for_rva(rva) else {
+        return Ok(Vec::new());
+    };
+    let executable = Flags::from_bits_truncate(section.charactristics.value).is_executable();
+    let available = section.virtual_address.value.saturating_add(section.sizeof_raw_data.value.min(section.virtual_size.value));
+    let size = available.saturating_sub(rva).min(max) as usize;
+
+    if !executable || size == 0 {
+        return Ok(Vec::new());
+    }
+    pe.read_bytes_at_rva(rva, size)
+}
+
+/// Roles told by `exports` and `imports` names; `kernel` if the image is a kernel driver.
+pub(crate) fn roles(exports: &[&str], imports: &[String], kernel: bool) -> Vec<ImageRole> {
+    let mut roles = Vec::new();
+    if exports.iter().any(|e| COM_EXPORTS.contains(e)) {
+        roles.push(ImageRole::ComServer);
+    }
+    if exports.contains(&"ServiceMain") || imports.iter().any(|i| SERVICE_IMPORTS.contains(&i.as_str())) {
+        roles.push(ImageRole::Service);
+    }
+    if kernel || exports.contains(&"DriverEntry") {
+        roles.push(ImageRole::Driver);
+    }
+    roles
+}
+
+pub(crate) fn analyze(pe: &mut PeImage) -> Result<EntryInfo> {
+    let rva = pe.optional.value.entry_point();
+    let mut info = EntryInfo { rva, ..Default::default() };
+
+    if rva != 0 {
+        let code = read_code(pe, rva, STUB_MAX)?;
+        info.kind = match crt_stub(&code) {
+            Some(name) => EntryKind::Crt(name.into()),
+            None if gs_stub_target(&code, rva).is_some() => EntryKind::Crt("GsDriverEntry".into()),
+            None => EntryKind::Custom,
+        };
+    }
+
+    //Unnamed exports have a placeholder name without an rva.
+    let exports: Vec<&str> = pe.exports.value.exports.iter()
+        .filter(|e| e.name.rva != Rva(0))
+        .map(|e| e.name.value.as_str())
+        .collect();
+    info.named_entries = exports.iter()
+        .filter(|e| COM_EXPORTS.contains(e) || ["ServiceMain", "DriverEntry", "DllMain"].contains(e))
+        .map(|e| e.to_string())
+        .collect();
+
+    let imports: Vec<String> = pe.imports.value.iter().flat_map(|id| id.value.get_imports_str()).collect();
+    info.roles = roles(&exports, &imports, pe.is_kernel_driver());
+
+    Ok(info)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{crt_stub, roles, ImageRole};
+
+    #[test]
+    fn crt_stubs() {
+        let x64 = [0x48, 0x83, 0xEC, 0x28, 0xE8, 0x1B, 0x05, 0x00, 0x00, 0x48, 0x83, 0xC4, 0x28, 0xE9, 0x72, 0xFE, 0xFF, 0xFF];
+        assert_eq!(crt_stub(&x64), Some("MSVC mainCRTStartup (x64)"));
+        assert_eq!(crt_stub(&x64[..10]), None);
+
+        let x86 = [0x8B, 0xFF, 0x55, 0x8B, 0xEC, 0x83, 0x7D, 0x0C, 0x01, 0x75, 0x05, 0xE8, 0x00, 0x00];
+        assert_eq!(crt_stub(&x86), Some("MSVC _DllMainCRTStartup (x86)"));
+
+        assert_eq!(crt_stub(&[0x55, 0x8B, 0xEC, 0x83, 0xEC, 0x10, 0xC3]), None);
+    }
+
+    #[test]
+    fn image_roles() {
+        let imports = vec!["StartServiceCtrlDispatcherW".to_string()];
+        assert_eq!(roles(&["DllGetClassObject", "DllCanUnloadNow"], &imports, false), vec![ImageRole::ComServer, ImageRole::Service]);
+        assert_eq!(roles(&["ServiceMain"], &[], false), vec![ImageRole::Service]);
+        assert_eq!(roles(&[], &[], true), vec![ImageRole::Driver]);
+        assert!(roles(&["DllMain"], &[], false).is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+//! Heuristic passes over a parsed `PeImage`.
+//!
+//! Parsing only decodes structures; passes here interpret them (what kind of image
+//! it is, what it is likely to do) and may read extra bytes from the image to do so.
+
+pub mod anomaly;
+pub mod capability;
+pub mod driver;
+pub mod efi;
+pub mod entry;
+pub mod entropy;
+pub mod payload;
+pub mod slack;
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::pe::Result;
+
+use self::{anomaly::Anomaly, capability::{Capability, CapabilityRules}, driver::DriverInfo, efi::EfiInfo, entry::EntryInfo, payload::EmbeddedPayload, slack::SectionSlack};
+
+use super::{security::Signer, te::TeImage, PeImage};
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct AnalysisReport {
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub entry: Option<EntryInfo>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub driver: Option<DriverInfo>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub efi: Option<EfiInfo>,
+    /// Non-zero bytes in sections which are never mapped.
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub slack: Vec<SectionSlack>,
+    /// Archives, installers and scripts in the overlay and in `RC_DATA` resources.
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub payloads: Vec<EmbeddedPayload>,
+    /// Signers of Authenticode signatures, with their timestamps. Not verified.
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub signers: Vec<Signer>,
+    /// Imports by category, by the embedded `CapabilityRules`.
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub capabilities: Vec<Capability>,
+    /// `VersionInfo::product_key` of the version resource.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub product_key: Option<String>,
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl Display for AnalysisReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Analysis: {{")?;
+        if let Some(key) = &self.product_key {
+            writeln!(f, "  ProductKey: {key}")?;
+        }
+        if let Some(entry) = &self.entry {
+            writeln!(f, "  Entry: {entry}")?;
+        }
+        if let Some(driver) = &self.driver {
+            write!(f, "{driver}")?;
+        }
+        if let Some(efi) = &self.efi {
+            write!(f, "{efi}")?;
+        }
+        for capability in &self.capabilities {
+            writeln!(f, "  Capability: {capability}")?;
+        }
+        for slack in &self.slack {
+            writeln!(f, "  Slack: {slack}")?;
+        }
+        for payload in &self.payloads {
+            writeln!(f, "  Payload: {payload}")?;
+        }
+        for signer in &self.signers {
+            writeln!(f, "  Signer: {signer}")?;
+        }
+        for anomaly in &self.anomalies {
+            writeln!(f, "  Anomaly: {anomaly}")?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl PeImage {
+    /// Consistency checks of the parsed headers and directories, like a machine that doesn't
+    /// match the optional header. Reads nothing; `analyze` reports the same anomalies.
+    pub fn validate(&self) -> Vec<Anomaly> {
+        anomaly::analyze(self)
+    }
+
+    /// Run all analysis passes applicable to this image.
+    pub fn analyze(&mut self) -> Result<AnalysisReport> {
+        let _span = tracing::info_span!("analyze PE").entered();
+        let mut report = AnalysisReport { entry: Some(entry::analyze(self)?), ..Default::default() };
+
+        if self.is_kernel_driver() {
+            report.driver = Some(driver::analyze(self)?);
+        }
+        else if self.is_efi() {
+            report.efi = Some(efi::analyze(self));
+        }
+
+        report.capabilities = self.capabilities(&CapabilityRules::default());
+        report.slack = slack::analyze(self)?;
+        report.payloads = payload::analyze(self)?;
+        report.signers = match self.signers() {
+            Ok(signers) => signers,
+            Err(err) => {
+                tracing::warn!(%err, "skipped signers; certificate table can't be read");
+                Vec::new()
+            },
+        };
+        report.anomalies = anomaly::analyze(self);
+        match self.entropy_profile(entropy::DEFAULT_WINDOW, entropy::DEFAULT_WINDOW) {
+            Ok(profile) => report.anomalies.extend(anomaly::entropy_anomalies(self, &profile)),
+            Err(err) => tracing::warn!(%err, "skipped entropy; file can't be read"),
+        }
+
+        //A malformed version resource only leaves the key out.
+        report.product_key = match self.version_info() {
+            Ok(info) => info.and_then(|info| info.product_key()),
+            Err(err) => {
+                tracing::warn!(%err, "skipped product key; version resource is malformed");
+                None
+            },
+        };
+
+        Ok(report)
+    }
+}
+
+impl TeImage {
+    /// Run all analysis passes applicable to this image.
+    pub fn analyze(&mut self) -> Result<AnalysisReport> {
+        Ok(AnalysisReport {
+            efi: Some(efi::analyze_te(&self.header.value)),
+            anomalies: anomaly::analyze_te(self),
+            ..Default::default()
+        })
+    }
+}
+
@@ -0,0 +1,242 @@
+//! What kind of data is embedded in the overlay and in large `RC_DATA` resources, by magic.
+//!
+//! Installers and droppers keep their payload in either spot. Only the first bytes are read,
+//! so the kind is a hint, not a parse.
+
+use std::{fmt::Display, ops::Range};
+
+use serde::Serialize;
+
+use crate::pe::{rsrc::{ResourceDirectory, ResourceNode, ResourceType}, PeImage, Result};
+
+/// Resources smaller than this are not checked.
+pub const MIN_RESOURCE_SIZE: u32 = 0x400;
+/// Bytes read from the start of a payload.
+const MAGIC_LENGTH: u64 = 0x40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum PayloadKind {
+    Zip,
+    Cab,
+    /// Data of an NSIS installer.
+    Nsis,
+    SevenZip,
+    Rar,
+    Gzip,
+    /// OLE compound file, as MSI packages and legacy Office documents.
+    Msi,
+    /// Another PE image.
+    Pe,
+    /// Script starting with `#!`.
+    Shebang,
+    Batch,
+    PowerShell,
+    Xml,
+    Html,
+}
+
+/// Kind of data starting with `bytes`.
+pub fn identify(bytes: &[u8]) -> Option<PayloadKind> {
+    const MAGICS: &[(&[u8], PayloadKind)] = &[
+        (b"PK\x03\x04", PayloadKind::Zip),
+        (b"PK\x05\x06", PayloadKind::Zip),
+        (b"MSCF", PayloadKind::Cab),
+        (b"7z\xbc\xaf\x27\x1c", PayloadKind::SevenZip),
+        (b"Rar!\x1a\x07", PayloadKind::Rar),
+        (b"\x1f\x8b", PayloadKind::Gzip),
+        (b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", PayloadKind::Msi),
+        (b"MZ", PayloadKind::Pe),
+        (b"#!", PayloadKind::Shebang),
+        (b"<?xml", PayloadKind::Xml),
+    ];
+
+    if let Some((_, kind)) = MAGICS.iter().find(|(magic, _)| bytes.starts_with(magic)) {
+        return Some(*kind);
+    }
+    //First header of NSIS data: flags, then the signature.
+    if bytes.get(4..20) == Some(b"\xef\xbe\xad\xdeNullsoftInst") {
+        return Some(PayloadKind::Nsis);
+    }
+
+    //Text, without a UTF-8 BOM, compared case insensitively.
+    let text = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    let text = String::from_utf8_lossy(text).trim_start().to_lowercase();
+    if text.starts_with("@echo off") || text.starts_with("@echo on") {
+        Some(PayloadKind::Batch)
+    } else if text.starts_with("<!doctype html") || text.starts_with("<html") {
+        Some(PayloadKind::Html)
+    } else if text.starts_with("param(") || text.starts_with("[cmdletbinding") || text.starts_with("$erroractionpreference") {
+        Some(PayloadKind::PowerShell)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum PayloadSource {
+    Overlay,
+    /// Path of the resource like `RC_DATA/#101/1033`.
+    Resource(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct EmbeddedPayload {
+    pub source: PayloadSource,
+    pub kind: PayloadKind,
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl Display for EmbeddedPayload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.source {
+            PayloadSource::Overlay => write!(f, "Overlay")?,
+            PayloadSource::Resource(path) => write!(f, "Resource {path}")?,
+        }
+        write!(f, " @ {:#x}, Size: {:#x}, Kind: {:?}", self.offset, self.size, self.kind)
+    }
+}
+
+impl PeImage {
+    /// File range after headers and section data, without a certificate table at its start
+    /// or end. `None` if there is nothing there.
+    pub fn overlay(&mut self) -> Result<Option<Range<u64>>> {
+        let file_len = self.reader.end_offset()?;
+        let mut start = self.sections.value.iter()
+            .map(|s| s.value.raw_data_ptr.value as u64 + s.value.sizeof_raw_data.value as u64)
+            .fold(self.optional.value.sizeof_headers() as u64, u64::max);
+        let mut end = file_len;
+
+        if let Some(certs) = self.certificate_range() {
+            if certs.start <= start && certs.end > start {
+                start = certs.end;
+            } else if certs.start >= start && certs.start < end {
+                end = certs.start;
+            }
+        }
+
+        Ok((start < end).then_some(start..end))
+    }
+}
+
+/// `RC_DATA` resources of at least `MIN_RESOURCE_SIZE` bytes: path, rva and size.
+fn rcdata_resources(root: &ResourceDirectory) -> Vec<(String, u32, u32)> {
+    let mut found = Vec::new();
+
+    for typ in root.entries.iter().filter(|e| e.resource_type() == Some(ResourceType::RC_DATA)) {
+        let ResourceNode::Dir(names) = &typ.data else {
+            continue;
+        };
+        for name in &names.entries {
+            let ResourceNode::Dir(langs) = &name.data else {
+                continue;
+            };
+            for lang in &langs.entries {
+                match &lang.data {
+                    ResourceNode::Data(data) if data.size.value >= MIN_RESOURCE_SIZE =>
+                        found.push((format!("RC_DATA/{}/{}", name.key(), lang.key()), data.rva.value, data.size.value)),
+                    _ => {},
+                }
+            }
+        }
+    }
+    found
+}
+
+pub(crate) fn analyze(pe: &mut PeImage) -> Result<Vec<EmbeddedPayload>> {
+    let mut found = Vec::new();
+
+    if let Some(overlay) = pe.overlay()? {
+        match pe.reader.read_bytes_at_offset(overlay.start, MAGIC_LENGTH.min(overlay.end - overlay.start) as usize) {
+            Ok(bytes) => if let Some(kind) = identify(&bytes) {
+                found.push(EmbeddedPayload { source: PayloadSource::Overlay, kind, offset: overlay.start, size: overlay.end - overlay.start });
+            },
+            Err(err) => tracing::warn!(%err, offset = overlay.start, "skipped unreadable overlay"),
+        }
+    }
+
+    for (path, rva, size) in rcdata_resources(&pe.resources.value) {
+        let Some(offset) = pe.rva_to_offset(rva) else {
+            continue;
+        };
+        let bytes = match pe.reader.read_bytes_at_offset(offset.into(), MAGIC_LENGTH.min(size.into()) as usize) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                tracing::warn!(%err, %path, offset, "skipped unreadable resource");
+                continue;
+            },
+        };
+        if let Some(kind) = identify(&bytes) {
+            found.push(EmbeddedPayload { source: PayloadSource::Resource(path), kind, offset: offset.into(), size: size.into() });
+        }
+    }
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::pe::PeImage;
+
+    use super::{identify, EmbeddedPayload, PayloadKind, PayloadSource};
+
+    #[test]
+    fn identify_magic() {
+        assert_eq!(identify(b"PK\x03\x04\x14\0"), Some(PayloadKind::Zip));
+        assert_eq!(identify(b"MSCF\0\0\0\0"), Some(PayloadKind::Cab));
+        assert_eq!(identify(b"\0\0\0\0\xef\xbe\xad\xdeNullsoftInst\0\0"), Some(PayloadKind::Nsis));
+        assert_eq!(identify(b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1\0"), Some(PayloadKind::Msi));
+        assert_eq!(identify(b"\xef\xbb\xbf  @ECHO OFF\r\n"), Some(PayloadKind::Batch));
+        assert_eq!(identify(b"param($Path)"), Some(PayloadKind::PowerShell));
+        assert_eq!(identify(b"\0\0\0\0"), None);
+        assert_eq!(identify(b""), None);
+    }
+
+    #[test]
+    fn zip_in_overlay() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut bytes = fs::read(path).unwrap();
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        //test.dll only has a certificate table after its sections.
+        assert_eq!(pe.overlay().unwrap(), None);
+        assert!(pe.analyze().unwrap().payloads.is_empty());
+
+        let start = bytes.len() as u64;
+        bytes.extend(b"PK\x03\x04");
+        bytes.extend([0u8; 0x20]);
+        let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        assert_eq!(pe.overlay().unwrap(), Some(start..start + 0x24));
+        assert_eq!(pe.analyze().unwrap().payloads, vec![
+            EmbeddedPayload { source: PayloadSource::Overlay, kind: PayloadKind::Zip, offset: start, size: 0x24 },
+        ]);
+    }
+
+    #[test]
+    fn unreadable_resource() {
+        use std::io::Cursor;
+
+        use crate::pe::rsrc::ResourceNode;
+
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+
+        //Make the first resource a large RC_DATA and cut the input before its data.
+        let typ = &mut pe.resources.value.entries[0];
+        typ.id = Some(10);
+        let ResourceNode::Dir(names) = &mut typ.data else { panic!("not a directory") };
+        let ResourceNode::Dir(langs) = &mut names.entries[0].data else { panic!("not a directory") };
+        let ResourceNode::Data(data) = &mut langs.entries[0].data else { panic!("not data") };
+        data.size.value = super::MIN_RESOURCE_SIZE;
+        let rva = data.rva.value;
+        let offset = pe.rva_to_offset(rva).unwrap() as usize;
+        pe.reader = Box::new(Cursor::new(bytes[..offset].to_vec()));
+
+        assert_eq!(super::analyze(&mut pe).unwrap(), vec![]);
+    }
+}
@@ -0,0 +1,158 @@
+//! Bytes stored on disk in or next to a section which the loader never maps.
+//!
+//! Raw data past `VirtualSize` is dropped when the section is mapped, and memory past
+//! `SizeOfRawData` is zero-filled instead of being read from the file. Non-zero bytes
+//! in either spot are invisible at run time, which makes them a known hiding spot.
+
+use std::{fmt::Display, io::{Seek, SeekFrom}};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::pe::{check_alloc, PeImage, Result};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum SlackKind {
+    /// Raw data beyond `VirtualSize`.
+    #[default]
+    Unmapped,
+    /// File bytes following raw data, where memory is zero-filled up to `VirtualSize`.
+    ZeroFilled,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct SectionSlack {
+    pub section: String,
+    pub kind: SlackKind,
+    pub offset: u64,
+    pub rva: u32,
+    pub size: u64,
+    /// Number of non-zero bytes in the range.
+    pub non_zero: u64,
+    pub sha256: String,
+}
+
+impl Display for SectionSlack {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {:?} @ {:#08x} (RVA: {:#08x}), Size: {:#x}, NonZero: {:#x}, SHA256: {}",
+            self.section, self.kind, self.offset, self.rva, self.size, self.non_zero, self.sha256)
+    }
+}
+
+/// File range `[start, end)` and rva of slack of each kind in a section.
+fn slack_ranges(raw_ptr: u64, raw_size: u64, vsize: u64, va: u32) -> Vec<(SlackKind, u64, u64, u32)> {
+    let mut ranges = Vec::new();
+    if raw_size == 0 {
+        return ranges;
+    }
+
+    //VirtualSize 0 means the whole raw data is mapped.
+    if vsize != 0 && vsize < raw_size {
+        ranges.push((SlackKind::Unmapped, raw_ptr + vsize, raw_ptr + raw_size, va.wrapping_add(vsize as u32)));
+    }
+    else if vsize > raw_size {
+        ranges.push((SlackKind::ZeroFilled, raw_ptr + raw_size, raw_ptr + vsize, va.wrapping_add(raw_size as u32)));
+    }
+    ranges
+}
+
+pub(crate) fn analyze(pe: &mut PeImage) -> Result<Vec<SectionSlack>> {
+    let file_len = pe.reader.seek(SeekFrom::End(0))?;
+    let raw_starts: Vec<u64> = pe.sections.value.iter()
+        .map(|s| s.value.raw_data_ptr.value as u64)
+        .collect();
+
+    let mut found = Vec::new();
+
+    for index in 0..pe.sections.value.len() {
+        let sh = pe.sections.value[index].value.clone();
+        let raw_ptr = sh.raw_data_ptr.value as u64;
+        let ranges = slack_ranges(raw_ptr, sh.sizeof_raw_data.value.into(), sh.virtual_size.value.into(), sh.virtual_address.value);
+
+        for (kind, start, end, rva) in ranges {
+            let mut end = end.min(file_len);
+            //Bytes after raw data may be raw data of the next section.
+            if kind == SlackKind::ZeroFilled {
+                end = raw_starts.iter().copied().filter(|&s| s >= start).fold(end, u64::min);
+            }
+            if start >= end {
+                continue;
+            }
+
+            let size = end - start;
+            //Unmapped slack is the tail of raw data, shared with other readers of the section.
+            let cached = match kind {
+                SlackKind::Unmapped => pe.section_data(index).ok()
+                    .filter(|data| data.len() as u64 >= end - raw_ptr)
+                    .map(|data| data[(start - raw_ptr) as usize..(end - raw_ptr) as usize].to_vec()),
+                SlackKind::ZeroFilled => None,
+            };
+            let bytes = match cached {
+                Some(bytes) => bytes,
+                None => {
+                    check_alloc("Section slack", size, pe.limits.max_alloc)?;
+                    pe.reader.read_bytes_at_offset(start, size as usize)?
+                }
+            };
+
+            let non_zero = bytes.iter().filter(|&&b| b != 0).count() as u64;
+            if non_zero == 0 {
+                continue;
+            }
+
+            found.push(SectionSlack {
+                section: sh.name_lossy(),
+                kind,
+                offset: start,
+                rva,
+                size,
+                non_zero,
+                sha256: format!("{:x}", Sha256::digest(&bytes)),
+            });
+        }
+    }
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::pe::PeImage;
+
+    use super::{slack_ranges, SlackKind};
+
+    #[test]
+    fn slack_range_kinds() {
+        assert_eq!(slack_ranges(0x400, 0x200, 0x180, 0x1000), vec![(SlackKind::Unmapped, 0x580, 0x600, 0x1180)]);
+        assert_eq!(slack_ranges(0x400, 0x200, 0x300, 0x1000), vec![(SlackKind::ZeroFilled, 0x600, 0x700, 0x1200)]);
+        assert!(slack_ranges(0x400, 0x200, 0, 0x1000).is_empty());
+        assert!(slack_ranges(0, 0, 0x1000, 0x1000).is_empty());
+    }
+
+    #[test]
+    fn slack_in_text_section() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut bytes = fs::read(path).unwrap();
+
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        assert!(pe.analyze().unwrap().slack.is_empty());
+
+        //.text: raw data at 0x400, VirtualSize 0xac54, SizeOfRawData 0xae00.
+        bytes[0x400 + 0xac60..0x400 + 0xac64].copy_from_slice(b"HIDE");
+        let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        let slack = pe.analyze().unwrap().slack;
+
+        assert_eq!(slack.len(), 1);
+        assert_eq!(slack[0].section, ".text");
+        assert_eq!(slack[0].kind, SlackKind::Unmapped);
+        assert_eq!(slack[0].offset, 0x400 + 0xac54);
+        assert_eq!(slack[0].rva, 0x1000 + 0xac54);
+        assert_eq!(slack[0].size, 0xae00 - 0xac54);
+        assert_eq!(slack[0].non_zero, 4);
+        assert_eq!(slack[0].sha256.len(), 64);
+    }
+}
@@ -0,0 +1,233 @@
+//! API set schema, mapping `api-ms-win-*` and `ext-ms-*` module names to the modules hosting them.
+//!
+//! The schema is read from the `.apiset` section of `apisetschema.dll` (namespace version 6,
+//! Windows 10 and later) or, with the `json` feature, from a JSON object of API set names to
+//! host names, default host first. Like the loader, lookups ignore case, the `.dll` extension
+//! and the last version number of the name.
+
+use std::collections::HashMap;
+
+use super::{PeError, PeImage, Result};
+
+/// Only supported `Version` of `API_SET_NAMESPACE`.
+pub const SCHEMA_VERSION: u32 = 6;
+
+const NAMESPACE_LENGTH: usize = 28;
+const ENTRY_LENGTH: usize = 24;
+const VALUE_LENGTH: usize = 20;
+
+/// Host of an API set, for modules named `importing` or, if it is `None`, for any module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiSetHost {
+    pub importing: Option<String>,
+    pub host: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ApiSetSchema {
+    /// Hosts by lower case API set name without its last version number, default host first.
+    entries: HashMap<String, Vec<ApiSetHost>>,
+}
+
+impl ApiSetSchema {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Map the API set `name`, with or without version and extension, to `hosts`.
+    pub fn insert(&mut self, name: &str, hosts: Vec<ApiSetHost>) {
+        self.entries.insert(hash_name(name), hosts);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Host of the API set `module` as imported by `importing`. `None` if `module` is not an
+    /// API set in the schema or has no host, as for extension sets not present on the system.
+    pub fn resolve(&self, module: &str, importing: Option<&str>) -> Option<&str> {
+        let hosts = self.entries.get(&hash_name(module))?;
+        let exception = importing.and_then(|importing| hosts.iter()
+            .find(|h| h.importing.as_deref().is_some_and(|i| i.eq_ignore_ascii_case(importing))));
+
+        exception.or_else(|| hosts.iter().find(|h| h.importing.is_none()))
+            .or_else(|| hosts.first())
+            .map(|h| h.host.as_str())
+    }
+
+    /// Schema in the `.apiset` section of `apisetschema.dll`.
+    pub fn from_image(pe: &mut PeImage) -> Result<Self> {
+        let index = pe.sections.value.iter().position(|s| s.value.name_lossy() == ".apiset")
+            .ok_or(PeError::InvalidHeader {
+                name: "ApiSet".into(), offset: pe.sections.offset.into(), reason: "no .apiset section".into()
+            })?;
+        let offset = pe.sections.value[index].value.raw_data_ptr.value as u64;
+        let data = pe.section_data(index)?;
+        Self::parse_bytes(&data, offset)
+    }
+
+    /// Parse an `API_SET_NAMESPACE` at the start of `bytes`. `pos` is its offset, for errors.
+    pub fn parse_bytes(bytes: &[u8], pos: u64) -> Result<Self> {
+        let invalid = |offset: usize, reason: String| PeError::InvalidHeader {
+            name: "ApiSet".into(), offset: pos + offset as u64, reason
+        };
+        let u32_at = |offset: usize| -> Result<u32> {
+            bytes.get(offset..offset + 4)
+                .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+                .ok_or(PeError::BufferTooSmall { target: "ApiSet".into(), expected: (offset + 4) as u64, actual: bytes.len() as u64 })
+        };
+        let str_at = |offset: u32, length: u32| -> Result<String> {
+            let (start, end) = (offset as usize, offset as usize + length as usize);
+            let data = bytes.get(start..end)
+                .ok_or_else(|| invalid(start, format!("string of {length} bytes is beyond the schema")))?;
+            let words: Vec<u16> = data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+            Ok(String::from_utf16(&words)?)
+        };
+
+        if bytes.len() < NAMESPACE_LENGTH {
+            return Err(PeError::BufferTooSmall { target: "ApiSet".into(), expected: NAMESPACE_LENGTH as u64, actual: bytes.len() as u64 });
+        }
+        let version = u32_at(0)?;
+        if version != SCHEMA_VERSION {
+            return Err(invalid(0, format!("schema version {version} is not supported")));
+        }
+        let count = u32_at(12)?;
+        let entry_offset = u32_at(16)? as usize;
+        if (count as usize).saturating_mul(ENTRY_LENGTH) > bytes.len() {
+            return Err(invalid(12, format!("{count} entries don't fit in {} bytes", bytes.len())));
+        }
+
+        let mut schema = Self::new();
+        for i in 0..count as usize {
+            let entry = entry_offset + i * ENTRY_LENGTH;
+            let name = str_at(u32_at(entry + 4)?, u32_at(entry + 8)?)?;
+            let value_offset = u32_at(entry + 16)? as usize;
+            let value_count = u32_at(entry + 20)? as usize;
+
+            let mut hosts = Vec::with_capacity(value_count.min(bytes.len() / VALUE_LENGTH));
+            for j in 0..value_count {
+                let value = value_offset + j * VALUE_LENGTH;
+                let importing = str_at(u32_at(value + 4)?, u32_at(value + 8)?)?;
+                let host = str_at(u32_at(value + 12)?, u32_at(value + 16)?)?;
+                //Empty hosts stand for sets which are not implemented.
+                if !host.is_empty() {
+                    hosts.push(ApiSetHost { importing: (!importing.is_empty()).then_some(importing), host });
+                }
+            }
+            schema.insert(&name, hosts);
+        }
+
+        Ok(schema)
+    }
+
+    /// Schema from a JSON object like `{"api-ms-win-core-synch-l1-2-0": ["kernelbase.dll"]}`.
+    /// Hosts for specific importing modules can't be given this way.
+    #[cfg(feature="json")]
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let map: HashMap<String, Vec<String>> = serde_json::from_str(json)?;
+        let mut schema = Self::new();
+        for (name, hosts) in map {
+            let hosts = hosts.into_iter().map(|host| ApiSetHost { importing: None, host }).collect();
+            schema.insert(&name, hosts);
+        }
+        Ok(schema)
+    }
+}
+
+/// `name` as the loader hashes it: lower case, without extension and last version number.
+fn hash_name(name: &str) -> String {
+    let name = name.to_ascii_lowercase();
+    let name = name.strip_suffix(".dll").unwrap_or(&name);
+    match name.rfind('-') {
+        Some(pos) => name[..pos].to_string(),
+        None => name.to_string(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiSetHost, ApiSetSchema, ENTRY_LENGTH, NAMESPACE_LENGTH, VALUE_LENGTH};
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+    }
+
+    /// Namespace with one entry `name` hosted by `(importing, host)` pairs.
+    fn namespace(name: &str, hosts: &[(&str, &str)]) -> Vec<u8> {
+        let entry_offset = NAMESPACE_LENGTH;
+        let value_offset = entry_offset + ENTRY_LENGTH;
+        let mut strings = value_offset + hosts.len() * VALUE_LENGTH;
+
+        let mut bytes = Vec::new();
+        for v in [6u32, 0, 0, 1, entry_offset as u32, 0, 0] {
+            bytes.extend(v.to_le_bytes());
+        }
+
+        let mut pool = utf16(name);
+        for v in [0u32, strings as u32, pool.len() as u32, 0, value_offset as u32, hosts.len() as u32] {
+            bytes.extend(v.to_le_bytes());
+        }
+        strings += pool.len();
+
+        for (importing, host) in hosts {
+            let (importing, host) = (utf16(importing), utf16(host));
+            for v in [0u32, strings as u32, importing.len() as u32, (strings + importing.len()) as u32, host.len() as u32] {
+                bytes.extend(v.to_le_bytes());
+            }
+            strings += importing.len() + host.len();
+            pool.extend(importing);
+            pool.extend(host);
+        }
+        bytes.extend(pool);
+        bytes
+    }
+
+    #[test]
+    fn parse_namespace() {
+        let bytes = namespace("api-ms-win-core-com-l1-1-1", &[("", "combase.dll"), ("ole32.dll", "ole32impl.dll")]);
+        let schema = ApiSetSchema::parse_bytes(&bytes, 0).unwrap();
+        assert_eq!(schema.len(), 1);
+
+        assert_eq!(schema.resolve("API-MS-WIN-CORE-COM-L1-1-0.dll", None), Some("combase.dll"));
+        assert_eq!(schema.resolve("api-ms-win-core-com-l1-1-3", Some("OLE32.dll")), Some("ole32impl.dll"));
+        assert_eq!(schema.resolve("api-ms-win-core-com-l1-2-0.dll", None), None);
+        assert_eq!(schema.resolve("kernel32.dll", None), None);
+    }
+
+    #[test]
+    fn bad_namespace() {
+        let mut bytes = namespace("api-ms-win-core-com-l1-1-1", &[("", "combase.dll")]);
+        assert!(ApiSetSchema::parse_bytes(&bytes[..20], 0).is_err());
+
+        bytes[0] = 4;
+        assert!(ApiSetSchema::parse_bytes(&bytes, 0).is_err());
+
+        bytes[0] = 6;
+        bytes[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(ApiSetSchema::parse_bytes(&bytes, 0).is_err());
+    }
+
+    #[test]
+    fn unimplemented_set() {
+        let mut schema = ApiSetSchema::new();
+        schema.insert("ext-ms-win-ntuser-window-l1-1-0.dll", Vec::new());
+        schema.insert("api-ms-win-core-synch-l1-2-0", vec![ApiSetHost { importing: None, host: "kernelbase.dll".into() }]);
+        assert_eq!(schema.resolve("ext-ms-win-ntuser-window-l1-1-4.dll", None), None);
+        assert_eq!(schema.resolve("api-ms-win-core-synch-l1-2-0.dll", Some("kernel32.dll")), Some("kernelbase.dll"));
+    }
+
+    #[cfg(feature="json")]
+    #[test]
+    fn from_json() {
+        let schema = ApiSetSchema::from_json(r#"{"api-ms-win-core-synch-l1-2-0": ["kernelbase.dll"], "ext-ms-win-foo-l1-1-0": []}"#).unwrap();
+        assert_eq!(schema.len(), 2);
+        assert_eq!(schema.resolve("api-ms-win-core-synch-l1-2-0.dll", None), Some("kernelbase.dll"));
+        assert_eq!(schema.resolve("ext-ms-win-foo-l1-1-0.dll", None), None);
+        assert!(ApiSetSchema::from_json("[]").is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Authenticode image hash (authentihash), the digest a signature or a catalog signs.
+//!
+//! The hash covers the whole file except the checksum, the security directory entry and the
+//! certificate table, so signing or re-checksumming a file doesn't change it.
+
+use std::{fmt::Display, ops::Range};
+
+use md5::Md5;
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{digest::DynDigest, Digest, Sha256, Sha384, Sha512};
+
+use super::{optional::DirectoryType, PeImage, Result};
+
+/// Bytes hashed per read.
+const CHUNK_SIZE: u64 = 0x10000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    #[default]
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    pub(crate) fn hasher(&self) -> Box<dyn DynDigest> {
+        match self {
+            Self::Md5 => Box::new(Md5::new()),
+            Self::Sha1 => Box::new(Sha1::new()),
+            Self::Sha256 => Box::new(Sha256::new()),
+            Self::Sha384 => Box::new(Sha384::new()),
+            Self::Sha512 => Box::new(Sha512::new()),
+        }
+    }
+
+    /// Digest of `bytes`.
+    pub fn digest(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut hasher = self.hasher();
+        hasher.update(bytes);
+        hasher.finalize().to_vec()
+    }
+}
+
+impl Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// File ranges left out of the hash, ordered by offset.
+pub(crate) fn excluded_ranges(pe: &PeImage) -> Vec<Range<u64>> {
+    let checksum = u64::from(pe.optional.value.checksum().offset);
+    let mut ranges = Vec::with_capacity(3);
+    ranges.push(checksum..checksum + 4);
+
+    if let Some(entry) = pe.data_dirs.value.get(DirectoryType::Security as usize) {
+        let at = u64::from(entry.offset);
+        ranges.push(at..at + 8);
+        if let Some(certs) = pe.certificate_range() {
+            ranges.push(certs);
+        }
+    }
+
+    ranges.sort_by_key(|r| r.start);
+    ranges
+}
+
+impl PeImage {
+    /// Authenticode hash of the image with `algorithm`, as stored in signatures. Data after
+    /// the certificate table is included.
+    pub fn authentihash(&mut self, algorithm: HashAlgorithm) -> Result<Vec<u8>> {
+        let size = self.reader.end_offset()?;
+        let mut hasher = algorithm.hasher();
+        let mut pos = 0;
+
+        for skip in excluded_ranges(self).into_iter().chain(std::iter::once(size..size)) {
+            let end = skip.start.min(size);
+            while pos < end {
+                let len = (end - pos).min(CHUNK_SIZE);
+                hasher.update(&self.reader.read_bytes_at_offset(pos, len as usize)?);
+                pos += len;
+            }
+            pos = pos.max(skip.end);
+        }
+
+        Ok(hasher.finalize().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::pe::PeImage;
+
+    use super::HashAlgorithm;
+
+    #[test]
+    fn hash_matches_signature() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut bytes = fs::read(path).unwrap();
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+
+        let hash = pe.authentihash(HashAlgorithm::Sha256).unwrap();
+        let hex: String = hash.iter().map(|b| format!("{b:02x}")).collect();
+        assert_eq!(hex, "826c1b49fa57dcf8886a2fb259d6cb26a7204ff8e33867ee6f9e12852b026a70");
+        //The signature of test.dll has the same digest.
+        assert!(bytes[0x14400..].windows(hash.len()).any(|w| w == hash.as_slice()));
+        assert_eq!(pe.authentihash(HashAlgorithm::Sha1).unwrap().len(), 20);
+        assert_eq!(pe.authentihash(HashAlgorithm::Md5).unwrap().len(), 16);
+        assert_eq!(pe.authentihash(HashAlgorithm::Sha384).unwrap().len(), 48);
+
+        //Checksum and certificates are left out.
+        let checksum = pe.optional.value.checksum().offset.0 as usize;
+        bytes[checksum] ^= 0xff;
+        bytes[0x14500] ^= 0xff;
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        assert_eq!(pe.authentihash(HashAlgorithm::Sha256).unwrap(), hash);
+
+        bytes[0x400] ^= 0xff;
+        let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        assert_ne!(pe.authentihash(HashAlgorithm::Sha256).unwrap(), hash);
+    }
+}
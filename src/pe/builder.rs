@@ -0,0 +1,368 @@
+//! Construction of small, valid PE32 and PE32+ images from scratch, e.g. for loader tests
+//! and fixtures.
+//!
+//! `PeBuilder` lays out the headers, a `.text` section with the given code and, if any
+//! imports are added, an `.idata` section with the import directory and IAT. Offsets, sizes
+//! and alignments are computed; the image has no relocations, so it loads at `image_base`.
+
+use super::{
+    file::{self, MachineType}, optional::{self, image_checksum, DirectoryType, SubSystem, MAX_DIRS},
+    section, PeError, Result, SECTION_HEADER_LENGTH,
+};
+
+const DOS_HEADER_LENGTH: u32 = 0x40;
+const PE_HEADER_LENGTH: u32 = 24;
+/// Optional header without data directories.
+const OPTIONAL_LENGTH_32: u32 = 96;
+const OPTIONAL_LENGTH_64: u32 = 112;
+/// Offset of `CheckSum` in either optional header.
+const CHECKSUM_OFFSET: u32 = 64;
+const IMPORT_DESCRIPTOR_LENGTH: u32 = 20;
+
+pub const DEFAULT_SECTION_ALIGNMENT: u32 = 0x1000;
+pub const DEFAULT_FILE_ALIGNMENT: u32 = 0x200;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeBuilder {
+    machine: MachineType,
+    subsystem: SubSystem,
+    dll: bool,
+    image_base: Option<u64>,
+    timestamp: u32,
+    section_alignment: u32,
+    file_alignment: u32,
+    code: Vec<u8>,
+    entry_offset: u32,
+    imports: Vec<(String, Vec<String>)>,
+}
+
+impl PeBuilder {
+    /// Builder of an executable for `machine`; 64-bit machines get a PE32+ image.
+    pub fn new(machine: MachineType) -> Self {
+        Self {
+            machine,
+            subsystem: SubSystem::WINDOWS_CUI,
+            dll: false,
+            image_base: None,
+            timestamp: 0,
+            section_alignment: DEFAULT_SECTION_ALIGNMENT,
+            file_alignment: DEFAULT_FILE_ALIGNMENT,
+            code: vec![0xC3],
+            entry_offset: 0,
+            imports: Vec::new(),
+        }
+    }
+
+    /// Content of `.text`; the entry point is at `entry_offset` in it.
+    pub fn with_code(mut self, code: Vec<u8>, entry_offset: u32) -> Self {
+        self.code = code;
+        self.entry_offset = entry_offset;
+        self
+    }
+
+    /// Import `functions` by name from `dll`. Repeated `dll`s add to the same descriptor.
+    pub fn with_import(mut self, dll: &str, functions: &[&str]) -> Self {
+        let functions = functions.iter().map(|f| f.to_string());
+        match self.imports.iter_mut().find(|(name, _)| name.eq_ignore_ascii_case(dll)) {
+            Some((_, existing)) => existing.extend(functions),
+            None => self.imports.push((dll.into(), functions.collect())),
+        }
+        self
+    }
+
+    pub fn with_subsystem(mut self, subsystem: SubSystem) -> Self {
+        self.subsystem = subsystem;
+        self
+    }
+
+    /// Build a DLL instead of an executable.
+    pub fn dll(mut self) -> Self {
+        self.dll = true;
+        self
+    }
+
+    /// Defaults to what MSVC uses for the kind and width of the image. A PE32 image needs
+    /// a base that fits in 32 bits.
+    pub fn with_image_base(mut self, image_base: u64) -> Self {
+        self.image_base = Some(image_base);
+        self
+    }
+
+    /// `TimeDateStamp` of the file header; 0 by default, for reproducible output.
+    pub fn with_timestamp(mut self, timestamp: u32) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn with_alignment(mut self, section_alignment: u32, file_alignment: u32) -> Self {
+        self.section_alignment = section_alignment;
+        self.file_alignment = file_alignment;
+        self
+    }
+
+    fn invalid(&self, reason: String) -> PeError {
+        PeError::InvalidHeader { name: "PeBuilder".into(), offset: 0, reason }
+    }
+
+    /// Bytes of the image.
+    pub fn build(&self) -> Result<Vec<u8>> {
+        let is_64 = self.machine.is_64bit().ok_or(PeError::UnsupportedMachine(self.machine))?;
+        let (sa, fa) = (self.section_alignment, self.file_alignment);
+        if !fa.is_power_of_two() || !(0x200..=0x10000).contains(&fa) || !sa.is_power_of_two() || sa < fa {
+            return Err(self.invalid(format!("section alignment {sa:#x} and file alignment {fa:#x} are not valid")));
+        }
+        if self.code.is_empty() || self.entry_offset as usize >= self.code.len() {
+            return Err(self.invalid(format!("entry offset {:#x} is outside of {:#x} bytes of code", self.entry_offset, self.code.len())));
+        }
+        if let Some(base) = self.image_base.filter(|b| !is_64 && *b > u32::MAX.into()) {
+            return Err(self.invalid(format!("image base {base:#x} does not fit in a PE32 image")));
+        }
+
+        let section_count = if self.imports.is_empty() { 1 } else { 2 };
+        let optional_length = if is_64 { OPTIONAL_LENGTH_64 } else { OPTIONAL_LENGTH_32 } + MAX_DIRS as u32 * 8;
+        let headers_end = DOS_HEADER_LENGTH + PE_HEADER_LENGTH + optional_length + section_count * SECTION_HEADER_LENGTH as u32;
+        let sizeof_headers = align(headers_end, fa);
+
+        let text = Layout::new(sizeof_headers, align(sizeof_headers, sa), self.code.len() as u32, fa);
+        let (idata_bytes, import_dir, iat_dir) = match self.imports.is_empty() {
+            true => (Vec::new(), (0, 0), (0, 0)),
+            false => self.idata(align(text.rva + text.virtual_size, sa), is_64),
+        };
+        let idata = Layout::new(text.raw_ptr + text.raw_size, align(text.rva + text.virtual_size, sa), idata_bytes.len() as u32, fa);
+        let sizeof_image = align(if self.imports.is_empty() { text.end() } else { idata.end() }, sa);
+
+        let mut out = Vec::with_capacity((idata.raw_ptr + idata.raw_size) as usize);
+
+        //DOS header, without a stub.
+        out.extend(b"MZ");
+        out.resize(0x3C, 0);
+        out.extend(DOS_HEADER_LENGTH.to_le_bytes());
+
+        let mut characteristics = file::Flags::EXECUTABLE | file::Flags::RELOCS_STRIPPED;
+        characteristics |= if is_64 { file::Flags::LARGE_ADDRESS_AWARE } else { file::Flags::MACHINE_32BIT };
+        if self.dll {
+            characteristics |= file::Flags::DLL;
+        }
+        out.extend(b"PE\0\0");
+        out.extend((self.machine as u16).to_le_bytes());
+        out.extend((section_count as u16).to_le_bytes());
+        out.extend(self.timestamp.to_le_bytes());
+        out.extend([0u8; 8]);
+        out.extend((optional_length as u16).to_le_bytes());
+        out.extend(characteristics.bits().to_le_bytes());
+
+        let optional_start = out.len();
+        let image_base = self.image_base.unwrap_or(match (is_64, self.dll) {
+            (true, true) => 0x1_8000_0000,
+            (true, false) => 0x1_4000_0000,
+            (false, true) => 0x1000_0000,
+            (false, false) => 0x40_0000,
+        });
+        let mut dll_flags = optional::Flags::NX_COMPAT;
+        if !self.dll {
+            dll_flags |= optional::Flags::TERMINAL_SERVER_AWARE;
+        }
+
+        out.extend((if is_64 { 0x20Bu16 } else { 0x10B }).to_le_bytes());
+        out.extend([14u8, 0]);
+        out.extend(text.raw_size.to_le_bytes());
+        out.extend(idata.raw_size.to_le_bytes());
+        out.extend(0u32.to_le_bytes());
+        out.extend((text.rva + self.entry_offset).to_le_bytes());
+        out.extend(text.rva.to_le_bytes());
+        if is_64 {
+            out.extend(image_base.to_le_bytes());
+        }
+        else {
+            out.extend((if self.imports.is_empty() { 0 } else { idata.rva }).to_le_bytes());
+            out.extend((image_base as u32).to_le_bytes()); //Checked above
+        }
+        out.extend(sa.to_le_bytes());
+        out.extend(fa.to_le_bytes());
+        for version in [6u16, 0, 0, 0, 6, 0] {
+            out.extend(version.to_le_bytes());
+        }
+        out.extend(0u32.to_le_bytes());
+        out.extend(sizeof_image.to_le_bytes());
+        out.extend(sizeof_headers.to_le_bytes());
+        out.extend(0u32.to_le_bytes()); //CheckSum, set last
+        out.extend((self.subsystem as u16).to_le_bytes());
+        out.extend(dll_flags.bits().to_le_bytes());
+        for size in [0x10_0000u64, 0x1000, 0x10_0000, 0x1000] {
+            match is_64 {
+                true => out.extend(size.to_le_bytes()),
+                false => out.extend((size as u32).to_le_bytes()),
+            }
+        }
+        out.extend(0u32.to_le_bytes());
+        out.extend((MAX_DIRS as u32).to_le_bytes());
+
+        for dir in 0..MAX_DIRS {
+            let (rva, size) = match dir {
+                d if d == DirectoryType::Import as u8 => import_dir,
+                d if d == DirectoryType::ImportAddressTable as u8 => iat_dir,
+                _ => (0, 0),
+            };
+            out.extend(rva.to_le_bytes());
+            out.extend(size.to_le_bytes());
+        }
+
+        let code_flags = section::Flags::CODE | section::Flags::MEM_EXECUTE | section::Flags::MEM_READ;
+        text.write_header(&mut out, b".text", code_flags);
+        if !self.imports.is_empty() {
+            let data_flags = section::Flags::INITIALIZED_DATA | section::Flags::MEM_READ | section::Flags::MEM_WRITE;
+            idata.write_header(&mut out, b".idata", data_flags);
+        }
+
+        out.resize(text.raw_ptr as usize, 0);
+        out.extend(&self.code);
+        out.resize((text.raw_ptr + text.raw_size) as usize, 0);
+        out.extend(&idata_bytes);
+        out.resize((idata.raw_ptr + idata.raw_size) as usize, 0);
+
+        let checksum_at = optional_start + CHECKSUM_OFFSET as usize;
+        let checksum = image_checksum(&out, checksum_at);
+        out[checksum_at..checksum_at + 4].copy_from_slice(&checksum.to_le_bytes());
+        Ok(out)
+    }
+
+    /// Content of `.idata` at `rva`, with the `(rva, size)` of the import directory and IAT.
+    /// Descriptors come first, then lookup tables, IATs, hint/name entries and module names.
+    fn idata(&self, rva: u32, is_64: bool) -> (Vec<u8>, (u32, u32), (u32, u32)) {
+        let thunk = if is_64 { 8 } else { 4 };
+        let tables: u32 = self.imports.iter().map(|(_, f)| (f.len() as u32 + 1) * thunk).sum();
+        let descriptors = (self.imports.len() as u32 + 1) * IMPORT_DESCRIPTOR_LENGTH;
+        let ilt_start = descriptors;
+        let iat_start = ilt_start + tables;
+        let names_start = iat_start + tables;
+
+        let mut names = Vec::new();
+        let mut thunks = Vec::new();
+        let mut descs = Vec::new();
+        let mut table_pos = 0;
+        for (dll, functions) in &self.imports {
+            for function in functions {
+                let hint_name = rva + names_start + names.len() as u32;
+                thunks.extend(&hint_name.to_le_bytes()[..]);
+                thunks.resize(thunks.len() + thunk as usize - 4, 0);
+                names.extend([0u8, 0]);
+                names.extend(function.as_bytes());
+                names.push(0);
+                names.resize(align(names.len() as u32, 2) as usize, 0);
+            }
+            thunks.resize(thunks.len() + thunk as usize, 0);
+
+            let dll_name = rva + names_start + names.len() as u32;
+            names.extend(dll.as_bytes());
+            names.push(0);
+            names.resize(align(names.len() as u32, 2) as usize, 0);
+
+            let ilt = rva + ilt_start + table_pos;
+            let iat = rva + iat_start + table_pos;
+            for value in [ilt, 0, 0, dll_name, iat] {
+                descs.extend(value.to_le_bytes());
+            }
+            table_pos += (functions.len() as u32 + 1) * thunk;
+        }
+
+        let mut bytes = descs;
+        bytes.resize(descriptors as usize, 0);
+        bytes.extend(&thunks);
+        bytes.extend(&thunks);
+        bytes.extend(names);
+        (bytes, (rva, descriptors), (rva + iat_start, tables))
+    }
+}
+
+/// Where a section is in the file and in memory.
+struct Layout {
+    raw_ptr: u32,
+    raw_size: u32,
+    rva: u32,
+    virtual_size: u32,
+}
+
+impl Layout {
+    fn new(raw_ptr: u32, rva: u32, size: u32, file_alignment: u32) -> Self {
+        Self { raw_ptr, raw_size: align(size, file_alignment), rva, virtual_size: size }
+    }
+
+    fn end(&self) -> u32 {
+        self.rva + self.virtual_size
+    }
+
+    fn write_header(&self, out: &mut Vec<u8>, name: &[u8], flags: section::Flags) {
+        let mut padded = [0u8; 8];
+        padded[..name.len()].copy_from_slice(name);
+        out.extend(padded);
+        for value in [self.virtual_size, self.rva, self.raw_size, self.raw_ptr, 0, 0] {
+            out.extend(value.to_le_bytes());
+        }
+        out.extend([0u8; 4]);
+        out.extend(flags.bits().to_le_bytes());
+    }
+}
+
+fn align(value: u32, alignment: u32) -> u32 {
+    value.div_ceil(alignment) * alignment
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::pe::{file::MachineType, optional::{image_checksum, SubSystem}, PeImage};
+
+    use super::PeBuilder;
+
+    #[test]
+    fn minimal_pe64() {
+        let bytes = PeBuilder::new(MachineType::AMD64).with_code(vec![0x90, 0x31, 0xC0, 0xC3], 1).build().unwrap();
+        assert_eq!(bytes.len(), 0x400);
+
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        assert!(pe.validate().is_empty());
+        assert_eq!(pe.sections.value.len(), 1);
+        assert_eq!(pe.optional.value.entry_point(), 0x1001);
+        assert_eq!(pe.optional.value.image_base(), 0x1_4000_0000);
+        assert_eq!(pe.optional.value.sizeof_image(), 0x2000);
+        assert_eq!(pe.read_bytes_at_rva(0x1001, 3).unwrap(), vec![0x31, 0xC0, 0xC3]);
+        assert!(!pe.has_imports());
+
+        let checksum_at = pe.optional.offset.0 as usize + 64;
+        assert_eq!(pe.optional.value.checksum().value, image_checksum(&bytes, checksum_at));
+    }
+
+    #[test]
+    fn pe32_dll_with_imports() {
+        let bytes = PeBuilder::new(MachineType::I386)
+            .dll()
+            .with_subsystem(SubSystem::WINDOWS_GUI)
+            .with_import("KERNEL32.dll", &["ExitProcess"])
+            .with_import("USER32.dll", &["MessageBoxA", "MessageBoxW"])
+            .with_import("kernel32.dll", &["Sleep"])
+            .build().unwrap();
+
+        let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        assert!(pe.file.value.flags().unwrap().is_dll());
+        assert_eq!(pe.optional.value.image_base(), 0x1000_0000);
+        pe.parse_import_directory().unwrap();
+
+        let imports: Vec<_> = pe.imports.value.iter()
+            .map(|id| (id.value.name.clone().unwrap(), id.value.get_imports_str()))
+            .collect();
+        assert_eq!(imports, vec![
+            ("KERNEL32.dll".to_string(), vec!["ExitProcess".to_string(), "Sleep".to_string()]),
+            ("USER32.dll".to_string(), vec!["MessageBoxA".to_string(), "MessageBoxW".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn invalid_builds() {
+        assert!(PeBuilder::new(MachineType::UNKNOWN).build().is_err());
+        assert!(PeBuilder::new(MachineType::AMD64).with_code(vec![0xC3], 1).build().is_err());
+        assert!(PeBuilder::new(MachineType::AMD64).with_alignment(0x1000, 0x300).build().is_err());
+        assert!(PeBuilder::new(MachineType::AMD64).with_alignment(0x200, 0x1000).build().is_err());
+        assert!(PeBuilder::new(MachineType::I386).with_image_base(0x1_4000_0000).build().is_err());
+        assert!(PeBuilder::new(MachineType::AMD64).with_image_base(0x1_4000_0000).build().is_ok());
+    }
+}
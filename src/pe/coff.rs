@@ -0,0 +1,468 @@
+//! COFF symbol table and string table, located by `FileHeader::symbol_table_ptr`, and
+//! COFF object files (`.obj`) and import objects, as found in static and import libraries.
+//!
+//! The symbol table is deprecated for images, but still written by MinGW and always present
+//! in object files. Each symbol is followed by `number_of_aux_symbols` auxiliary records of the
+//! same length, which are kept raw. Names longer than 8 bytes are in the string table, right
+//! after the symbols.
+
+use std::{fmt::Display, fs::File, io::Read};
+
+use byteorder::{ByteOrder, LittleEndian};
+use serde::Serialize;
+
+use crate::types::HeaderField;
+
+use super::{check_alloc, file::{self, FileHeader, MachineType}, section::{self, SectionTable}, PeError, PeTimestamp, Result, DEFAULT_MAX_ALLOC};
+
+pub const SYMBOL_LENGTH: u64 = 18;
+
+/// Section number of symbols not defined in this file.
+pub const SYM_UNDEFINED: i16 = 0;
+/// Section number of symbols with an absolute value.
+pub const SYM_ABSOLUTE: i16 = -1;
+/// Section number of debugging symbols.
+pub const SYM_DEBUG: i16 = -2;
+
+/// Complex type (`IMAGE_SYM_DTYPE_*`) of functions, in bits 4-5 of the type.
+const DTYPE_FUNCTION: u16 = 2;
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum StorageClass {
+    END_OF_FUNCTION,
+    #[default]
+    NULL,
+    AUTOMATIC,
+    EXTERNAL,
+    STATIC,
+    REGISTER,
+    EXTERNAL_DEF,
+    LABEL,
+    UNDEFINED_LABEL,
+    MEMBER_OF_STRUCT,
+    ARGUMENT,
+    STRUCT_TAG,
+    MEMBER_OF_UNION,
+    UNION_TAG,
+    TYPE_DEFINITION,
+    UNDEFINED_STATIC,
+    ENUM_TAG,
+    MEMBER_OF_ENUM,
+    REGISTER_PARAM,
+    BIT_FIELD,
+    BLOCK,
+    FUNCTION,
+    END_OF_STRUCT,
+    FILE,
+    SECTION,
+    WEAK_EXTERNAL,
+    CLR_TOKEN,
+    OTHER(u8),
+}
+
+impl From<u8> for StorageClass {
+    fn from(value: u8) -> Self {
+        match value {
+            0xFF => Self::END_OF_FUNCTION,
+            0 => Self::NULL,
+            1 => Self::AUTOMATIC,
+            2 => Self::EXTERNAL,
+            3 => Self::STATIC,
+            4 => Self::REGISTER,
+            5 => Self::EXTERNAL_DEF,
+            6 => Self::LABEL,
+            7 => Self::UNDEFINED_LABEL,
+            8 => Self::MEMBER_OF_STRUCT,
+            9 => Self::ARGUMENT,
+            10 => Self::STRUCT_TAG,
+            11 => Self::MEMBER_OF_UNION,
+            12 => Self::UNION_TAG,
+            13 => Self::TYPE_DEFINITION,
+            14 => Self::UNDEFINED_STATIC,
+            15 => Self::ENUM_TAG,
+            16 => Self::MEMBER_OF_ENUM,
+            17 => Self::REGISTER_PARAM,
+            18 => Self::BIT_FIELD,
+            100 => Self::BLOCK,
+            101 => Self::FUNCTION,
+            102 => Self::END_OF_STRUCT,
+            103 => Self::FILE,
+            104 => Self::SECTION,
+            105 => Self::WEAK_EXTERNAL,
+            107 => Self::CLR_TOKEN,
+            _ => Self::OTHER(value),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct CoffSymbol {
+    /// Index in the table, counting auxiliary records; what relocations refer to.
+    pub index: u32,
+    pub name: String,
+    /// Offset of the name in the string table, for names longer than 8 bytes.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub name_offset: Option<u32>,
+    pub value: HeaderField<u32>,
+    /// One-based section index, or one of `SYM_UNDEFINED`, `SYM_ABSOLUTE` and `SYM_DEBUG`.
+    pub section_number: HeaderField<i16>,
+    #[serde(rename="type")]
+    pub symbol_type: HeaderField<u16>,
+    pub storage_class: HeaderField<StorageClass>,
+    pub number_of_aux_symbols: HeaderField<u8>,
+    /// Auxiliary records, as stored.
+    #[serde(skip)]
+    pub aux: Vec<u8>,
+}
+
+impl CoffSymbol {
+    #[inline]
+    pub fn is_function(&self) -> bool {
+        (self.symbol_type.value >> 4) & 0x3 == DTYPE_FUNCTION
+    }
+
+    #[inline]
+    pub fn is_undefined(&self) -> bool {
+        self.section_number.value == SYM_UNDEFINED
+    }
+
+    /// Zero-based index in the section table, for symbols defined in a section.
+    pub fn section_index(&self) -> Option<usize> {
+        (self.section_number.value > 0).then(|| self.section_number.value as usize - 1)
+    }
+}
+
+impl Display for CoffSymbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{ #{} {}, Value: {:#010x}, Section: {}, Type: {:#06x}, Class: {:?} }}",
+            self.index, self.name, self.value.value, self.section_number.value, self.symbol_type.value, self.storage_class.value)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct CoffSymbolTable {
+    pub symbols: Vec<CoffSymbol>,
+    /// Size of the string table, including the size field; 0 if there is none.
+    pub string_table_size: HeaderField<u32>,
+    /// The string table as read, from its size field.
+    #[serde(skip)]
+    pub strings: Vec<u8>,
+}
+
+impl CoffSymbolTable {
+    /// Parse `count` records from `bytes`, read from offset `pos`. The string table follows the
+    /// records in `bytes`; if it is cut short, names in it are read up to the end of `bytes`.
+    pub fn parse_bytes(bytes: &[u8], count: u32, pos: u64) -> Result<Self> {
+        let table_len = count as u64 * SYMBOL_LENGTH;
+        if (bytes.len() as u64) < table_len {
+            return Err(PeError::BufferTooSmall { target: "CoffSymbolTable".into(), expected: table_len, actual: bytes.len() as u64 });
+        }
+
+        let strings = &bytes[table_len as usize..];
+        let strings_pos = pos + table_len;
+        let string_table_size = HeaderField::at_offset(strings.get(..4).map(LittleEndian::read_u32).unwrap_or_default(), strings_pos);
+
+        let mut table = Self { symbols: Vec::new(), string_table_size, strings: strings.to_vec() };
+        let mut index = 0;
+        while index < count {
+            let start = (index as u64 * SYMBOL_LENGTH) as usize;
+            let record = &bytes[start..start + SYMBOL_LENGTH as usize];
+            let offset = pos + start as u64;
+            let field = |at: u64| offset + at;
+
+            let name_offset = (LittleEndian::read_u32(&record[0..4]) == 0).then(|| LittleEndian::read_u32(&record[4..8]));
+            let name = match name_offset {
+                Some(name_offset) => table.string_at(name_offset)
+                    .ok_or(PeError::InvalidOffset(strings_pos + name_offset as u64))?,
+                None => {
+                    let end = record[..8].iter().position(|&b| b == 0).unwrap_or(8);
+                    String::from_utf8_lossy(&record[..end]).into_owned()
+                },
+            };
+
+            let number_of_aux_symbols = record[17];
+            let aux_start = start + SYMBOL_LENGTH as usize;
+            let aux_end = (aux_start + number_of_aux_symbols as usize * SYMBOL_LENGTH as usize).min(table_len as usize);
+
+            table.symbols.push(CoffSymbol {
+                index,
+                name,
+                name_offset,
+                value: HeaderField::at_offset(LittleEndian::read_u32(&record[8..12]), field(8)),
+                section_number: HeaderField::at_offset(LittleEndian::read_i16(&record[12..14]), field(12)),
+                symbol_type: HeaderField::at_offset(LittleEndian::read_u16(&record[14..16]), field(14)),
+                storage_class: HeaderField::at_offset(StorageClass::from(record[16]), field(16)),
+                number_of_aux_symbols: HeaderField::at_offset(number_of_aux_symbols, field(17)),
+                aux: bytes[aux_start..aux_end].to_vec(),
+            });
+
+            index += 1 + number_of_aux_symbols as u32;
+        }
+
+        Ok(table)
+    }
+
+    /// String at `offset` of the string table, which counts from the size field.
+    pub fn string_at(&self, offset: u32) -> Option<String> {
+        let tail = self.strings.get(offset as usize..).filter(|_| offset >= 4)?;
+        let end = tail.iter().position(|&b| b == 0).unwrap_or(tail.len());
+        Some(String::from_utf8_lossy(&tail[..end]).into_owned())
+    }
+
+    /// Symbol at `index`, as counted in the table.
+    pub fn symbol(&self, index: u32) -> Option<&CoffSymbol> {
+        self.symbols.binary_search_by_key(&index, |s| s.index).ok().map(|i| &self.symbols[i])
+    }
+}
+
+/// A COFF object: file header, section table and symbols, without DOS or optional headers.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ObjectFile {
+    pub file: HeaderField<FileHeader>,
+    pub sections: HeaderField<SectionTable>,
+    pub symbols: HeaderField<CoffSymbolTable>,
+}
+
+impl ObjectFile {
+    /// Parse an object from `bytes`, read from offset `pos`; offsets in it are counted from `pos`.
+    pub fn parse_bytes(bytes: &[u8], pos: u64) -> Result<Self> {
+        if is_import_object(bytes) {
+            return Err(PeError::InvalidHeader { name: "ObjectFile".into(), offset: pos, reason: "an import object, not an object file".into() });
+        }
+
+        let file = FileHeader::parse_coff_bytes(bytes, pos)?;
+        let end = pos + bytes.len() as u64;
+        let sections_offset = file::COFF_HEADER_LENGTH + file.optional_header_size.value as u64;
+        let count = file.sections.value;
+        section::check_section_count(count, pos + sections_offset, section::DEFAULT_MAX_SECTIONS, end)?;
+        let sections = section::parse_sections(&bytes[sections_offset as usize..], count, pos + sections_offset)?;
+
+        let mut obj = Self {
+            file: HeaderField::at_offset(file, pos),
+            sections: HeaderField::at_offset(sections, pos + sections_offset),
+            symbols: Default::default(),
+        };
+
+        let (symbols_ptr, symbols) = (obj.file.value.symbol_table_ptr.value as usize, obj.file.value.symbols.value);
+        if symbols_ptr != 0 {
+            let table = bytes.get(symbols_ptr..).ok_or(PeError::InvalidOffset(pos + symbols_ptr as u64))?;
+            let offset = pos + symbols_ptr as u64;
+            obj.symbols = HeaderField::at_offset(CoffSymbolTable::parse_bytes(table, symbols, offset)?, offset);
+        }
+
+        Ok(obj)
+    }
+
+    /// Read the whole file and parse it as an object.
+    pub fn parse_file(mut file: File) -> Result<Self> {
+        check_alloc("ObjectFile", file.metadata()?.len(), DEFAULT_MAX_ALLOC)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        Self::parse_bytes(&bytes, 0)
+    }
+
+    /// Name of section `index`; names of the form `/123` are read from the string table.
+    pub fn section_name(&self, index: usize) -> Option<String> {
+        let name = self.sections.value.get(index)?.value.name_lossy();
+        match name.strip_prefix('/').and_then(|offset| offset.parse::<u32>().ok()) {
+            Some(offset) => self.symbols.value.string_at(offset),
+            None => Some(name),
+        }
+    }
+}
+
+impl Display for ObjectFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "FileHeader: {}", self.file.value)?;
+        writeln!(f, "Sections: [")?;
+        for (i, sh) in self.sections.value.iter().enumerate() {
+            writeln!(f, "  {}: {}", self.section_name(i).unwrap_or_default(), sh.value)?;
+        }
+        writeln!(f, "]")?;
+        writeln!(f, "Symbols: [")?;
+        for symbol in &self.symbols.value.symbols {
+            writeln!(f, "  {symbol}")?;
+        }
+        write!(f, "]")
+    }
+}
+
+pub const IMPORT_OBJECT_HEADER_LENGTH: u64 = 20;
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ImportType {
+    #[default]
+    CODE,
+    DATA,
+    CONST,
+    OTHER(u8),
+}
+
+/// How the name to import is derived from the symbol name (`IMPORT_OBJECT_NAME_TYPE`).
+#[allow(non_camel_case_types)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ImportNameType {
+    #[default]
+    ORDINAL,
+    NAME,
+    NAME_NOPREFIX,
+    NAME_UNDECORATE,
+    NAME_EXPORTAS,
+    OTHER(u8),
+}
+
+/// Short import object (`IMPORT_OBJECT_HEADER`), one per function of an import library.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ImportObject {
+    pub version: HeaderField<u16>,
+    pub machine: HeaderField<MachineType>,
+    pub timestamp: HeaderField<PeTimestamp>,
+    pub size_of_data: HeaderField<u32>,
+    /// Ordinal for `ImportNameType::ORDINAL`, hint otherwise.
+    pub ordinal_or_hint: HeaderField<u16>,
+    pub import_type: ImportType,
+    pub name_type: ImportNameType,
+    pub symbol: String,
+    pub dll: String,
+}
+
+/// `bytes` start with the signature of an import object: `0`, `0xFFFF` and version `0`.
+/// Anonymous objects (e.g. `/bigobj`) have the same signature with a later version.
+pub fn is_import_object(bytes: &[u8]) -> bool {
+    bytes.len() >= 6 && bytes[0..4] == [0, 0, 0xFF, 0xFF] && bytes[4..6] == [0, 0]
+}
+
+impl ImportObject {
+    /// Parse an import object from `bytes`, read from offset `pos`.
+    pub fn parse_bytes(bytes: &[u8], pos: u64) -> Result<Self> {
+        let header_len = IMPORT_OBJECT_HEADER_LENGTH as usize;
+        if bytes.len() < header_len {
+            return Err(PeError::BufferTooSmall { target: "ImportObject".into(), expected: IMPORT_OBJECT_HEADER_LENGTH, actual: bytes.len() as u64 });
+        }
+        if !is_import_object(bytes) {
+            return Err(PeError::InvalidHeader { name: "ImportObject".into(), offset: pos, reason: "signature is not 0, 0xFFFF, version 0".into() });
+        }
+
+        let field = |at: u64| pos + at;
+        let word = |at: usize| LittleEndian::read_u16(&bytes[at..at + 2]);
+        let flags = word(18);
+
+        //Symbol and DLL names follow, both NUL terminated.
+        let size_of_data = LittleEndian::read_u32(&bytes[12..16]);
+        let data = &bytes[header_len..bytes.len().min(header_len + size_of_data as usize)];
+        let mut names = data.split(|&b| b == 0).map(|s| String::from_utf8_lossy(s).into_owned());
+
+        Ok(Self {
+            version: HeaderField::at_offset(word(4), field(4)),
+            machine: HeaderField::at_offset(MachineType::from(word(6)), field(6)),
+            timestamp: HeaderField::at_offset(PeTimestamp::from(LittleEndian::read_u32(&bytes[8..12])), field(8)),
+            size_of_data: HeaderField::at_offset(size_of_data, field(12)),
+            ordinal_or_hint: HeaderField::at_offset(word(16), field(16)),
+            import_type: match flags & 0x3 {
+                0 => ImportType::CODE,
+                1 => ImportType::DATA,
+                2 => ImportType::CONST,
+                other => ImportType::OTHER(other as u8),
+            },
+            name_type: match (flags >> 2) & 0x7 {
+                0 => ImportNameType::ORDINAL,
+                1 => ImportNameType::NAME,
+                2 => ImportNameType::NAME_NOPREFIX,
+                3 => ImportNameType::NAME_UNDECORATE,
+                4 => ImportNameType::NAME_EXPORTAS,
+                other => ImportNameType::OTHER(other as u8),
+            },
+            symbol: names.next().unwrap_or_default(),
+            dll: names.next().unwrap_or_default(),
+        })
+    }
+}
+
+impl Display for ImportObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{ {}!{}, {:?}, {:?}, Ordinal/Hint: {} }}", self.dll, self.symbol, self.import_type, self.name_type, self.ordinal_or_hint.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::FileOffset;
+    use crate::pe::file::MachineType;
+
+    use super::{CoffSymbolTable, ImportNameType, ImportObject, ImportType, ObjectFile, StorageClass, SYMBOL_LENGTH};
+
+    #[test]
+    fn parse_with_long_names() {
+        #[rustfmt::skip]
+        let mut bytes: Vec<u8> = vec![
+            //.file, one aux record with the file name.
+            b'.', b'f', b'i', b'l', b'e', 0, 0, 0, 0, 0, 0, 0, 0xFE, 0xFF, 0, 0, 103, 1,
+            b'a', b'.', b'c', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            //long_function_name in the string table, a function in section 1.
+            0, 0, 0, 0, 4, 0, 0, 0, 0x10, 0, 0, 0, 1, 0, 0x20, 0, 2, 0,
+            //Undefined external.
+            b'p', b'u', b't', b's', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x20, 0, 2, 0,
+        ];
+        bytes.extend(23u32.to_le_bytes());
+        bytes.extend(b"long_function_name\0");
+
+        let table = CoffSymbolTable::parse_bytes(&bytes, 4, 0x400).unwrap();
+        assert_eq!(table.symbols.len(), 3);
+        assert_eq!(table.string_table_size.value, 23);
+        assert_eq!(table.string_table_size.offset, FileOffset(0x400 + 4 * SYMBOL_LENGTH));
+
+        let file = &table.symbols[0];
+        assert_eq!(file.name, ".file");
+        assert_eq!(file.storage_class.value, StorageClass::FILE);
+        assert_eq!(file.section_number.value, -2);
+        assert_eq!(&file.aux[..3], b"a.c");
+
+        let func = table.symbol(2).unwrap();
+        assert_eq!(func.name, "long_function_name");
+        assert_eq!(func.name_offset, Some(4));
+        assert_eq!(func.value.value, 0x10);
+        assert_eq!(func.value.offset, FileOffset(0x400 + 2 * SYMBOL_LENGTH + 8));
+        assert!(func.is_function());
+        assert_eq!(func.section_index(), Some(0));
+
+        let puts = table.symbol(3).unwrap();
+        assert!(puts.is_undefined() && puts.section_index().is_none());
+        assert_eq!(puts.storage_class.value, StorageClass::EXTERNAL);
+        assert!(table.symbol(1).is_none());
+
+        assert!(CoffSymbolTable::parse_bytes(&bytes[..40], 4, 0).is_err());
+    }
+
+    #[test]
+    fn parse_object_and_import_object() {
+        //AMD64, one section, symbol table after it with one symbol.
+        let mut bytes: Vec<u8> = vec![0x64, 0x86, 1, 0, 0, 0, 0, 0, 0x3c, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+        bytes.extend(b"/4\0\0\0\0\0\0");
+        bytes.extend([0u8; 32]);
+        bytes.extend(b"main\0\0\0\0");
+        bytes.extend([0, 0, 0, 0, 1, 0, 0x20, 0, 2, 0]);
+        bytes.extend(16u32.to_le_bytes());
+        bytes.extend(b".text$mn\0\0\0\0");
+
+        let obj = ObjectFile::parse_bytes(&bytes, 0x100).unwrap();
+        assert_eq!(obj.file.value.machine.value, MachineType::AMD64);
+        assert_eq!(obj.file.value.machine.offset, FileOffset(0x100));
+        assert_eq!(obj.sections.value.len(), 1);
+        assert_eq!(obj.section_name(0).as_deref(), Some(".text$mn"));
+        assert_eq!(obj.symbols.offset, FileOffset(0x13c));
+        assert_eq!(obj.symbols.value.symbols[0].name, "main");
+
+        let mut import = vec![0, 0, 0xFF, 0xFF, 0, 0, 0x4c, 0x01, 0, 0, 0, 0, 21, 0, 0, 0, 7, 0, 0x04, 0];
+        import.extend(b"_Sleep@4\0KERNEL32.dll\0");
+        assert!(ObjectFile::parse_bytes(&import, 0).is_err());
+
+        let import = ImportObject::parse_bytes(&import, 0).unwrap();
+        assert_eq!(import.machine.value, MachineType::I386);
+        assert_eq!((import.import_type, import.name_type), (ImportType::CODE, ImportNameType::NAME));
+        assert_eq!(import.ordinal_or_hint.value, 7);
+        assert_eq!(import.symbol, "_Sleep@4");
+        assert_eq!(import.dll, "KERNEL32.dll");
+    }
+}
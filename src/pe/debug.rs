@@ -0,0 +1,419 @@
+//! Debug directory (`IMAGE_DEBUG_DIRECTORY` entries).
+//!
+//! Only the data of `CODEVIEW` (RSDS format) and `EX_DLLCHARACTERISTICS` entries is read; other
+//! entries are kept as headers.
+
+use std::{fmt::Display, io::Cursor, mem::size_of, path::{Path, PathBuf}};
+
+use bitflags::bitflags;
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::{Serialize, Serializer};
+
+use crate::{new_header_field, types::{Header, HeaderField, Rva}, utils::flags_to_str};
+
+use super::{PeError, PeTimestamp};
+
+pub const ENTRY_LENGTH: u64 = 28;
+/// Signature of CodeView data naming a PDB by GUID and age.
+pub const RSDS_SIGNATURE: &[u8; 4] = b"RSDS";
+/// Signature, GUID and age before the PDB path.
+pub const RSDS_HEADER_LENGTH: u64 = 24;
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DebugType {
+    #[default]
+    UNKNOWN,
+    COFF,
+    CODEVIEW,
+    FPO,
+    MISC,
+    EXCEPTION,
+    FIXUP,
+    OMAP_TO_SRC,
+    OMAP_FROM_SRC,
+    BORLAND,
+    CLSID,
+    VC_FEATURE,
+    POGO,
+    ILTCG,
+    MPX,
+    REPRO,
+    EMBEDDED_PORTABLE_PDB,
+    PDB_CHECKSUM,
+    EX_DLLCHARACTERISTICS,
+    OTHER(u32),
+}
+
+impl From<u32> for DebugType {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => Self::UNKNOWN,
+            1 => Self::COFF,
+            2 => Self::CODEVIEW,
+            3 => Self::FPO,
+            4 => Self::MISC,
+            5 => Self::EXCEPTION,
+            6 => Self::FIXUP,
+            7 => Self::OMAP_TO_SRC,
+            8 => Self::OMAP_FROM_SRC,
+            9 => Self::BORLAND,
+            11 => Self::CLSID,
+            12 => Self::VC_FEATURE,
+            13 => Self::POGO,
+            14 => Self::ILTCG,
+            15 => Self::MPX,
+            16 => Self::REPRO,
+            17 => Self::EMBEDDED_PORTABLE_PDB,
+            19 => Self::PDB_CHECKSUM,
+            20 => Self::EX_DLLCHARACTERISTICS,
+            _ => Self::OTHER(value),
+        }
+    }
+}
+
+bitflags! {
+    /// `IMAGE_DLLCHARACTERISTICS_EX_*`, from the data of an `EX_DLLCHARACTERISTICS` entry.
+    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy, Serialize)]
+    pub struct ExDllFlags: u32 {
+        const CET_COMPAT = 0x0001;
+        const CET_COMPAT_STRICT_MODE = 0x0002;
+        const CET_SET_CONTEXT_IP_VALIDATION_RELAXED_MODE = 0x0004;
+        const CET_DYNAMIC_APIS_ALLOW_IN_PROC = 0x0008;
+        const CET_RESERVED_1 = 0x0010;
+        const CET_RESERVED_2 = 0x0020;
+        const FORWARD_CFI_COMPAT = 0x0040;
+        const HOTPATCH_COMPATIBLE = 0x0080;
+    }
+}
+
+impl Display for ExDllFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", flags_to_str(self))
+    }
+}
+
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct DebugEntry {
+    pub charactristics: HeaderField<u32>,
+    pub timestamp: HeaderField<PeTimestamp>,
+    pub major_version: HeaderField<u16>,
+    pub minor_version: HeaderField<u16>,
+    #[serde(rename="type")]
+    pub debug_type: HeaderField<DebugType>,
+    pub size_of_data: HeaderField<u32>,
+    pub address_of_raw_data: HeaderField<u32>,
+    pub pointer_to_raw_data: HeaderField<u32>,
+}
+
+impl Header for DebugEntry {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> {
+        let bytes_len = bytes.len() as u64;
+
+        if bytes_len < ENTRY_LENGTH {
+            return Err(
+                PeError::BufferTooSmall { target: "DebugEntry".into(), expected: ENTRY_LENGTH, actual: bytes_len }
+            );
+        }
+
+        let mut cursor = Cursor::new(bytes);
+        let mut offset = pos;
+        let mut entry = Self::new();
+
+        entry.charactristics = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+
+        let data = cursor.read_u32::<LittleEndian>()?;
+        entry.timestamp = HeaderField::at_offset(PeTimestamp::from(data), offset);
+        offset += size_of::<u32>() as u64;
+
+        entry.major_version = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+        entry.minor_version = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+
+        let data = cursor.read_u32::<LittleEndian>()?;
+        entry.debug_type = HeaderField::at_offset(DebugType::from(data), offset);
+        offset += size_of::<u32>() as u64;
+
+        entry.size_of_data = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        entry.address_of_raw_data = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        entry.pointer_to_raw_data = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+
+        Ok(entry)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.size_of_data.value != 0
+    }
+
+    fn length() -> usize {
+        ENTRY_LENGTH as usize
+    }
+}
+
+impl DebugEntry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set `rva` of all fields, `rva` being the rva of the entry.
+    pub fn fix_rvas(&mut self, rva: Rva) {
+        let base = self.charactristics.offset;
+        macro_rules! fix {
+            ($($field:ident),+) => { $( self.$field.rva = rva + (self.$field.offset - base) as u32; )+ };
+        }
+        fix!(charactristics, timestamp, major_version, minor_version, debug_type, size_of_data,
+            address_of_raw_data, pointer_to_raw_data);
+    }
+}
+
+impl Display for DebugEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{ Type: {:?}, Size: {:#x}, RVA: {:#08x}, Offset: {:#08x} }}",
+            self.debug_type.value, self.size_of_data.value, self.address_of_raw_data.value, self.pointer_to_raw_data.value)
+    }
+}
+
+
+fn guid_as_str<S: Serializer>(guid: &[u8; 16], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format_guid(guid, true))
+}
+
+/// `guid` in upper-case hex, its first three parts being little endian integers.
+fn format_guid(guid: &[u8; 16], dashes: bool) -> String {
+    let data1 = u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]);
+    let data2 = u16::from_le_bytes([guid[4], guid[5]]);
+    let data3 = u16::from_le_bytes([guid[6], guid[7]]);
+    let rest: Vec<String> = guid[8..].iter().map(|b| format!("{b:02X}")).collect();
+    if dashes {
+        format!("{data1:08X}-{data2:04X}-{data3:04X}-{}-{}", rest[..2].concat(), rest[2..].concat())
+    }
+    else {
+        format!("{data1:08X}{data2:04X}{data3:04X}{}", rest.concat())
+    }
+}
+
+/// CodeView data in the RSDS format, naming the PDB with the symbols of the image.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct CodeView {
+    #[serde(serialize_with="guid_as_str")]
+    pub guid: [u8; 16],
+    pub age: u32,
+    /// Path of the PDB when the image was linked.
+    pub pdb_path: String,
+}
+
+impl CodeView {
+    /// Parse RSDS data; `None` for other formats, like the older NB10.
+    pub fn parse_bytes(bytes: &[u8]) -> crate::pe::Result<Option<Self>> {
+        if !bytes.starts_with(RSDS_SIGNATURE) {
+            return Ok(None);
+        }
+        let bytes_len = bytes.len() as u64;
+        if bytes_len <= RSDS_HEADER_LENGTH {
+            return Err(
+                PeError::BufferTooSmall { target: "CodeView".into(), expected: RSDS_HEADER_LENGTH + 1, actual: bytes_len }
+            );
+        }
+
+        let mut guid = [0u8; 16];
+        guid.copy_from_slice(&bytes[4..20]);
+        let age = u32::from_le_bytes([bytes[20], bytes[21], bytes[22], bytes[23]]);
+        let path = &bytes[RSDS_HEADER_LENGTH as usize..];
+        let path = &path[..path.iter().position(|b| *b == 0).unwrap_or(path.len())];
+        Ok(Some(Self { guid, age, pdb_path: String::from_utf8_lossy(path).into_owned() }))
+    }
+
+    /// GUID in the registry format, e.g. `A94402A4-038E-4266-881F-F90496FADBDF`.
+    pub fn guid_str(&self) -> String {
+        format_guid(&self.guid, true)
+    }
+
+    /// File name of the PDB, without the directories of `pdb_path`.
+    pub fn pdb_name(&self) -> &str {
+        self.pdb_path.rsplit(['\\', '/']).next().unwrap_or_default()
+    }
+
+    /// Path of the PDB on a symbol server or in a symbol store: `{name}/{GUID}{age}/{name}`, with
+    /// the GUID in hex without dashes and the age in hex.
+    pub fn symbol_key(&self) -> String {
+        let name = self.pdb_name();
+        format!("{name}/{}{:X}/{name}", format_guid(&self.guid, false), self.age)
+    }
+
+    /// URL of the PDB on the symbol server at `server`, e.g. `https://msdl.microsoft.com/download/symbols`.
+    pub fn symbol_url(&self, server: &str) -> String {
+        format!("{}/{}", server.trim_end_matches('/'), self.symbol_key())
+    }
+
+    /// Path of the PDB in the local symbol store or cache at `cache`, if it is there.
+    /// `None` also if the name of the PDB is not a file name, e.g. `..`.
+    pub fn find_in_cache(&self, cache: &Path) -> Option<PathBuf> {
+        //The name comes from the image; it must not lead out of `cache`.
+        let name = Path::new(self.pdb_name()).file_name()?;
+        let path = cache.join(name).join(format!("{}{:X}", format_guid(&self.guid, false), self.age)).join(name);
+        path.is_file().then_some(path)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct DebugDirectory {
+    pub entries: Vec<HeaderField<DebugEntry>>,
+    /// Data of the first `CODEVIEW` entry, if it is in the RSDS format.
+    pub codeview: Option<HeaderField<CodeView>>,
+    /// Data of the `EX_DLLCHARACTERISTICS` entry, if there is one.
+    pub ex_dll_charactristics: Option<HeaderField<u32>>,
+}
+
+impl DebugDirectory {
+    /// Parse `bytes` of the directory, `pos` is offset of the first entry.
+    pub fn parse_entries(bytes: &[u8], pos: u64) -> crate::pe::Result<Self> {
+        let mut dir = Self::default();
+        for (i, chunk) in bytes.chunks_exact(ENTRY_LENGTH as usize).enumerate() {
+            let offset = pos + i as u64 * ENTRY_LENGTH;
+            let entry = DebugEntry::parse_bytes(chunk.to_vec(), offset)?;
+            dir.entries.push(HeaderField::at_offset(entry, offset));
+        }
+        Ok(dir)
+    }
+
+    /// Set `rva` of all entries, `rva` being the rva of the directory.
+    pub fn fix_rvas(&mut self, rva: Rva) {
+        let Some(base) = self.entries.first().map(|e| e.offset) else { return };
+        for entry in self.entries.iter_mut() {
+            entry.rva = rva + (entry.offset - base) as u32;
+            entry.value.fix_rvas(entry.rva);
+        }
+    }
+
+    pub fn entry(&self, debug_type: DebugType) -> Option<&DebugEntry> {
+        self.entries.iter().map(|e| &e.value).find(|e| e.debug_type.value == debug_type)
+    }
+
+    /// Extended DLL characteristics, like CET compatibility.
+    pub fn ex_dll_flags(&self) -> Option<ExDllFlags> {
+        self.ex_dll_charactristics.map(|ex| ExDllFlags::from_bits_retain(ex.value))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::types::{FileOffset, Rva};
+    use crate::pe::PeImage;
+
+    use super::{CodeView, DebugDirectory, DebugType, ExDllFlags};
+
+    #[test]
+    fn parse_debug_entries() {
+        let bytes = [
+            //CODEVIEW
+            0x00u8, 0x00, 0x00, 0x00, 0xC5, 0xE6, 0xE4, 0x61, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+            0x6E, 0x00, 0x00, 0x00, 0x40, 0x23, 0x00, 0x00, 0x40, 0x17, 0x00, 0x00,
+            //EX_DLLCHARACTERISTICS
+            0x00, 0x00, 0x00, 0x00, 0xC5, 0xE6, 0xE4, 0x61, 0x00, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00, 0x00,
+            0x04, 0x00, 0x00, 0x00, 0xB0, 0x23, 0x00, 0x00, 0xB0, 0x17, 0x00, 0x00,
+        ];
+
+        let mut dir = DebugDirectory::parse_entries(&bytes, 0x1000).unwrap();
+        dir.fix_rvas(Rva(0x5000));
+        assert_eq!(dir.entries.len(), 2);
+        assert_eq!(dir.entries[1].offset, FileOffset(0x1000 + 28));
+        assert_eq!(dir.entries[1].rva, Rva(0x5000 + 28));
+
+        let codeview = dir.entry(DebugType::CODEVIEW).unwrap();
+        assert_eq!(codeview.size_of_data.value, 0x6e);
+        assert_eq!(codeview.pointer_to_raw_data.value, 0x1740);
+        assert_eq!(codeview.timestamp.value.raw(), 0x61e4e6c5);
+
+        let ex = dir.entry(DebugType::EX_DLLCHARACTERISTICS).unwrap();
+        assert_eq!(ex.debug_type.offset, FileOffset(0x1000 + 28 + 12));
+        assert_eq!(ex.debug_type.rva, Rva(0x5000 + 28 + 12));
+        assert_eq!(dir.ex_dll_flags(), None);
+
+        dir.ex_dll_charactristics = Some(Default::default());
+        dir.ex_dll_charactristics.as_mut().unwrap().value = 0x41;
+        assert_eq!(dir.ex_dll_flags(), Some(ExDllFlags::CET_COMPAT | ExDllFlags::FORWARD_CFI_COMPAT));
+        assert_eq!(format!("{}", dir.ex_dll_flags().unwrap()), "CET_COMPAT | FORWARD_CFI_COMPAT");
+    }
+
+    #[test]
+    fn codeview_symbol_key() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+        let codeview = &pe.debug.value.codeview.as_ref().unwrap();
+        assert_eq!(codeview.offset, FileOffset(0x10650));
+
+        let cv = &codeview.value;
+        assert_eq!(cv.age, 1);
+        assert_eq!(cv.guid_str(), "A94402A4-038E-4266-881F-F90496FADBDF");
+        assert_eq!(cv.pdb_name(), "libgthread-2.0-0.pdb");
+        assert_eq!(cv.symbol_key(), "libgthread-2.0-0.pdb/A94402A4038E4266881FF90496FADBDF1/libgthread-2.0-0.pdb");
+        assert_eq!(
+            cv.symbol_url("https://msdl.microsoft.com/download/symbols/"),
+            "https://msdl.microsoft.com/download/symbols/libgthread-2.0-0.pdb/A94402A4038E4266881FF90496FADBDF1/libgthread-2.0-0.pdb"
+        );
+
+        let cache = env::temp_dir().join(format!("rustbin-symbols-{}", std::process::id()));
+        assert_eq!(cv.find_in_cache(&cache), None);
+        let pdb = cache.join(cv.symbol_key());
+        fs::create_dir_all(pdb.parent().unwrap()).unwrap();
+        fs::write(&pdb, b"Microsoft C/C++ MSF 7.00").unwrap();
+        assert_eq!(cv.find_in_cache(&cache), Some(pdb));
+
+        //Names from the image stay in the cache.
+        let outside = CodeView { pdb_path: "..".into(), ..cv.clone() };
+        assert_eq!(outside.find_in_cache(&cache), None);
+        let nested = CodeView { pdb_path: r"..\..\libgthread-2.0-0.pdb".into(), ..cv.clone() };
+        assert_eq!(nested.find_in_cache(&cache), Some(cache.join(cv.symbol_key())));
+        fs::remove_dir_all(cache).unwrap();
+    }
+
+    #[test]
+    fn unreadable_codeview() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+        let pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        let entry = pe.debug.value.entry(DebugType::CODEVIEW).unwrap();
+        let (size_at, pointer_at) = (entry.size_of_data.offset.0 as usize, entry.pointer_to_raw_data.offset.0 as usize);
+        let data_at = entry.pointer_to_raw_data.value as usize;
+        let patched = |at: usize, value: &[u8]| {
+            let mut bytes = bytes.clone();
+            bytes[at..at + value.len()].copy_from_slice(value);
+            PeImage::parse_bytes(bytes, 0).unwrap()
+        };
+
+        //Past the end of the input, or too large to read.
+        for pe in [patched(pointer_at, &0x7FFF_0000u32.to_le_bytes()), patched(size_at, &0xFFFF_FFF0u32.to_le_bytes())] {
+            assert!(pe.debug.value.codeview.is_none());
+            assert_eq!(pe.debug.value.entries.len(), 2);
+            assert!(pe.status.debug.is_parsed());
+        }
+
+        //NB10: signature, offset, timestamp, age and name.
+        let mut nb10 = b"NB10".to_vec();
+        nb10.extend(0u32.to_le_bytes());
+        nb10.extend(0x3B0D_5E5Au32.to_le_bytes());
+        nb10.extend(1u32.to_le_bytes());
+        nb10.extend(b"app.pdb\0");
+        let pe = patched(data_at, &nb10);
+        assert!(pe.debug.value.codeview.is_none());
+        assert!(pe.debug.value.entry(DebugType::CODEVIEW).is_some());
+        assert!(pe.status.debug.is_parsed());
+    }
+
+    #[test]
+    fn codeview_formats() {
+        assert_eq!(CodeView::parse_bytes(b"NB10\0\0\0\0").unwrap(), None);
+        assert!(CodeView::parse_bytes(b"RSDS\0\0\0\0").is_err());
+
+        let mut bytes = b"RSDS".to_vec();
+        bytes.extend([0x11; 16]);
+        bytes.extend(0x2au32.to_le_bytes());
+        bytes.extend(b"app.pdb\0");
+        let cv = CodeView::parse_bytes(&bytes).unwrap().unwrap();
+        assert_eq!(cv.pdb_name(), "app.pdb");
+        assert_eq!(cv.symbol_key(), "app.pdb/111111111111111111111111111111112A/app.pdb");
+    }
+}
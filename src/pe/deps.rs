@@ -0,0 +1,575 @@
+//! Static resolution of imports against exports of dependencies, without loading anything.
+//!
+//! Exports of dependencies come from an `ExportMap`, filled by hand or by parsing modules found
+//! in search paths (`ExportMap::load`). Module names are compared ignoring case.
+//! Forwarded exports are reported with their target; forwarder chains are not followed.
+//!
+//! `DependencyTree` applies this recursively, parsing each dependency found in search paths.
+//! API set names (`api-*`, `ext-*`) are kept as leaves, unless an `ApiSetSchema` maps them to
+//! their host modules.
+
+use std::{collections::{HashMap, HashSet}, fmt::Display, fs, path::{Path, PathBuf}};
+
+use serde::Serialize;
+
+use crate::{parse_path, types::Rva, ParseAs, ParsedAs};
+
+use super::{apiset::ApiSetSchema, import::ImportedFunction, optional::DirectoryType, PeError, PeImage, Result};
+
+/// Default `TreeOptions::max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 16;
+
+/// Exports of one module, as needed to resolve imports.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ModuleExports {
+    pub names: HashSet<String>,
+    /// Biased ordinals, as used by imports.
+    pub ordinals: HashSet<u16>,
+    /// Forwarded exports, by name and ordinal, to their target like `NTDLL.RtlAllocateHeap`.
+    pub forwarders: HashMap<ImportedFunction, String>,
+}
+
+impl ModuleExports {
+    /// Exports known only by name, e.g. from a listing.
+    pub fn from_names<I, S>(names: I) -> Self where I: IntoIterator<Item = S>, S: Into<String> {
+        Self { names: names.into_iter().map(Into::into).collect(), ..Default::default() }
+    }
+
+    /// Exports of a parsed image. Forwarder strings are read from the image.
+    pub fn from_image(pe: &mut PeImage) -> Result<Self> {
+        let Some(dir) = pe.directory(DirectoryType::Export) else {
+            return Ok(Self::default());
+        };
+        let range = dir.rva.value..dir.rva.value.saturating_add(dir.size.value);
+        let base = pe.exports.value.base.value;
+
+        let mut exports = Self::default();
+        let mut forwarded = Vec::new();
+        for export in &pe.exports.value.exports {
+            let ordinal = (export.ordinal.value as u32).wrapping_add(base) as u16;
+            //Unnamed exports have a placeholder name without an rva.
+            let name = (export.name.rva != Rva(0)).then(|| export.name.value.clone());
+
+            exports.ordinals.insert(ordinal);
+            if let Some(name) = &name {
+                exports.names.insert(name.clone());
+            }
+            if range.contains(&export.address.value) {
+                forwarded.push((name, ordinal, export.address.value));
+            }
+        }
+
+        for (name, ordinal, rva) in forwarded {
+            let target = pe.read_string_at_rva(rva)?;
+            if let Some(name) = name {
+                exports.forwarders.insert(ImportedFunction::Name(name), target.clone());
+            }
+            exports.forwarders.insert(ImportedFunction::Ordinal(ordinal), target);
+        }
+
+        Ok(exports)
+    }
+
+    fn status(&self, import: &ImportedFunction) -> ImportStatus {
+        if let Some(target) = self.forwarders.get(import) {
+            return ImportStatus::Forwarded(target.clone());
+        }
+
+        let found = match import {
+            ImportedFunction::Name(name) => self.names.contains(name),
+            ImportedFunction::Ordinal(ordinal) => self.ordinals.contains(ordinal),
+        };
+        if found { ImportStatus::Resolved } else { ImportStatus::MissingSymbol }
+    }
+}
+
+/// Exports of dependencies by module name, ignoring case.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExportMap {
+    modules: HashMap<String, ModuleExports>,
+}
+
+impl ExportMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, module: &str, exports: ModuleExports) {
+        self.modules.insert(module.to_ascii_lowercase(), exports);
+    }
+
+    pub fn get(&self, module: &str) -> Option<&ModuleExports> {
+        self.modules.get(&module.to_ascii_lowercase())
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    /// Parse `modules` found in `search_paths`, searched in order.
+    /// Modules not found, or failing to parse, are left out and resolve as missing.
+    pub fn load<'a>(modules: impl IntoIterator<Item = &'a str>, search_paths: &[PathBuf]) -> Self {
+        let index = ModuleIndex::new(search_paths);
+        let mut map = Self::new();
+        for module in modules {
+            if map.get(module).is_some() {
+                continue;
+            }
+            let Some(path) = index.find(module) else {
+                tracing::debug!(module, "module not found");
+                continue;
+            };
+            match module_exports(&path) {
+                Ok(exports) => map.insert(module, exports),
+                Err(err) => tracing::warn!(path = ?path, %err, "failed to read exports"),
+            }
+        }
+        map
+    }
+
+    /// Like `load`, but API sets in `modules` are loaded from their host, as imported by
+    /// `importing`, and keep their own name in the map.
+    pub fn load_with_api_sets<'a>(modules: impl IntoIterator<Item = &'a str>, search_paths: &[PathBuf],
+        api_sets: &ApiSetSchema, importing: Option<&str>) -> Self
+    {
+        let modules: Vec<&str> = modules.into_iter().collect();
+        let mut map = Self::load(modules.iter().map(|m| api_sets.resolve(m, importing).unwrap_or(m)), search_paths);
+
+        for module in modules {
+            let host = api_sets.resolve(module, importing).and_then(|host| map.get(host)).cloned();
+            if let Some(exports) = host {
+                map.insert(module, exports);
+            }
+        }
+        map
+    }
+}
+
+/// Entries of search paths by lower case name, listed once for all modules looked up.
+#[derive(Debug, Default)]
+struct ModuleIndex {
+    /// Paths of each name, in search path order.
+    paths: HashMap<String, Vec<PathBuf>>,
+}
+
+impl ModuleIndex {
+    fn new(search_paths: &[PathBuf]) -> Self {
+        let mut paths: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        let entries = search_paths.iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flat_map(|entries| entries.filter_map(|e| e.ok()));
+        for entry in entries {
+            paths.entry(entry.file_name().to_string_lossy().to_ascii_lowercase()).or_default().push(entry.path());
+        }
+        Self { paths }
+    }
+
+    /// First file named `module`, ignoring case.
+    fn find(&self, module: &str) -> Option<PathBuf> {
+        self.paths.get(&module.to_ascii_lowercase())?
+            .iter()
+            .find(|path| path.is_file())
+            .cloned()
+    }
+}
+
+fn module_exports(path: &Path) -> crate::Result<ModuleExports> {
+    let mut pe = parse_module(path)?;
+    Ok(ModuleExports::from_image(&mut pe)?)
+}
+
+fn parse_module(path: &Path) -> crate::Result<PeImage> {
+    match parse_path(path, ParseAs::PE)? {
+        ParsedAs::PE(pe) => Ok(pe),
+        ParsedAs::TE(_) => Err(PeError::InvalidHeader {
+            name: "PE".into(), offset: 0, reason: "TE images can't be dependencies".into()
+        }.into()),
+        ParsedAs::Coff(_) | ParsedAs::Archive(_) => Err(PeError::InvalidHeader {
+            name: "PE".into(), offset: 0, reason: "objects and archives can't be dependencies".into()
+        }.into()),
+    }
+}
+
+/// Whether `module` names an API set, resolved by the loader through the API set schema.
+pub fn is_api_set(module: &str) -> bool {
+    let module = module.to_ascii_lowercase();
+    module.starts_with("api-") || module.starts_with("ext-")
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ImportStatus {
+    Resolved,
+    /// Exported, but forwarded to the target.
+    Forwarded(String),
+    /// The module was found, but does not export the function.
+    MissingSymbol,
+    /// The module was not found.
+    MissingModule,
+}
+
+impl ImportStatus {
+    #[inline]
+    pub fn is_missing(&self) -> bool {
+        matches!(self, Self::MissingSymbol | Self::MissingModule)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportResolution {
+    pub module: String,
+    pub function: ImportedFunction,
+    pub status: ImportStatus,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct DependencyReport {
+    pub imports: Vec<ImportResolution>,
+    /// Imported modules not in the export map.
+    pub missing_modules: Vec<String>,
+}
+
+impl DependencyReport {
+    pub fn resolved(&self) -> impl Iterator<Item = &ImportResolution> {
+        self.imports.iter().filter(|i| i.status == ImportStatus::Resolved)
+    }
+
+    pub fn forwarded(&self) -> impl Iterator<Item = &ImportResolution> {
+        self.imports.iter().filter(|i| matches!(i.status, ImportStatus::Forwarded(_)))
+    }
+
+    pub fn missing(&self) -> impl Iterator<Item = &ImportResolution> {
+        self.imports.iter().filter(|i| i.status.is_missing())
+    }
+
+    /// Whether every import resolves, directly or through a forwarder.
+    pub fn is_complete(&self) -> bool {
+        self.missing().next().is_none()
+    }
+}
+
+impl Display for DependencyReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Dependencies: {{")?;
+        let mut module: Option<&str> = None;
+        for import in &self.imports {
+            if module != Some(import.module.as_str()) {
+                if module.is_some() {
+                    writeln!(f, "  ]")?;
+                }
+                writeln!(f, "  {}: [", import.module)?;
+                module = Some(&import.module);
+            }
+
+            write!(f, "    {}", import.function)?;
+            match &import.status {
+                ImportStatus::Resolved => writeln!(f)?,
+                ImportStatus::Forwarded(target) => writeln!(f, " -> {target}")?,
+                ImportStatus::MissingSymbol => writeln!(f, " (MISSING)")?,
+                ImportStatus::MissingModule => writeln!(f, " (MODULE NOT FOUND)")?,
+            }
+        }
+        if module.is_some() {
+            writeln!(f, "  ]")?;
+        }
+        writeln!(f, "  Resolved: {}, Forwarded: {}, Missing: {}, Modules not found: {}",
+            self.resolved().count(), self.forwarded().count(), self.missing().count(), self.missing_modules.len())?;
+        write!(f, "}}")
+    }
+}
+
+/// Resolve parsed imports of `pe` against `exports`.
+pub(crate) fn resolve(pe: &PeImage, exports: &ExportMap) -> DependencyReport {
+    let mut report = DependencyReport::default();
+
+    for desc in &pe.imports.value {
+        let module = desc.value.name.clone().unwrap_or_default();
+        let dep = exports.get(&module);
+        if dep.is_none() && !report.missing_modules.iter().any(|m| m.eq_ignore_ascii_case(&module)) {
+            report.missing_modules.push(module.clone());
+        }
+
+        for import in &desc.value.imports {
+            let function = ImportedFunction::from(import);
+            let status = match dep {
+                Some(dep) => dep.status(&function),
+                None => ImportStatus::MissingModule,
+            };
+            report.imports.push(ImportResolution { module: module.clone(), function, status });
+        }
+    }
+
+    report
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeOptions {
+    /// Directories searched for dependencies, in order, after the directory of the root image.
+    pub search_paths: Vec<PathBuf>,
+    /// Dependencies deeper than this are not parsed.
+    pub max_depth: usize,
+    /// Schema to map API sets to their hosts with; without it API sets are leaves.
+    pub api_sets: Option<ApiSetSchema>,
+}
+
+impl Default for TreeOptions {
+    fn default() -> Self {
+        Self { search_paths: Vec::new(), max_depth: DEFAULT_MAX_DEPTH, api_sets: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum NodeKind {
+    /// Parsed; `children` are its dependencies.
+    Module,
+    /// Already expanded elsewhere in the tree.
+    Seen,
+    /// Imported by one of its own dependencies.
+    Cycle,
+    /// API set stub; its host, if the schema has one, is the only child.
+    ApiSet,
+    NotFound,
+    /// Not parsed, because of `TreeOptions::max_depth`.
+    TooDeep,
+    /// Found, but failed to parse, with this error.
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DependencyNode {
+    pub name: String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub path: Option<PathBuf>,
+    pub kind: NodeKind,
+    /// Imports of this module not exported by a dependency that was found.
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub missing_imports: Vec<ImportResolution>,
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub children: Vec<DependencyNode>,
+}
+
+impl DependencyNode {
+    fn leaf(name: &str, path: Option<PathBuf>, kind: NodeKind) -> Self {
+        Self { name: name.into(), path, kind, missing_imports: Vec::new(), children: Vec::new() }
+    }
+
+    /// This node and all nodes below it, depth first.
+    pub fn iter(&self) -> impl Iterator<Item = &DependencyNode> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+
+    fn format(&self, f: &mut std::fmt::Formatter<'_>, level: usize) -> std::fmt::Result {
+        write!(f, "{:indent$}{}", "", self.name, indent = level * 2)?;
+        match (&self.kind, &self.path) {
+            (NodeKind::Module, Some(path)) => write!(f, " ({})", path.display())?,
+            (NodeKind::Failed(err), _) => write!(f, " (FAILED: {err})")?,
+            (NodeKind::NotFound, _) => write!(f, " (NOT FOUND)")?,
+            (NodeKind::TooDeep, _) => write!(f, " (TOO DEEP)")?,
+            (kind, _) => write!(f, " ({kind:?})")?,
+        }
+        writeln!(f)?;
+
+        for missing in &self.missing_imports {
+            writeln!(f, "{:indent$}! {}!{} (MISSING)", "", missing.module, missing.function, indent = level * 2 + 2)?;
+        }
+        for child in &self.children {
+            child.format(f, level + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Dependencies of an image, parsed recursively.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DependencyTree {
+    pub root: DependencyNode,
+}
+
+impl DependencyTree {
+    /// Parse the image at `path` and, recursively, its dependencies.
+    /// Fails only if the image at `path` can't be parsed; failing dependencies become nodes.
+    pub fn build(path: &Path, options: &TreeOptions) -> crate::Result<Self> {
+        let mut search_paths = Vec::new();
+        if let Some(dir) = path.parent() {
+            search_paths.push(dir.to_path_buf());
+        }
+        search_paths.extend(options.search_paths.iter().cloned());
+
+        let mut builder = TreeBuilder {
+            modules: ModuleIndex::new(&search_paths),
+            max_depth: options.max_depth,
+            api_sets: options.api_sets.clone(),
+            exports: ExportMap::new(),
+            expanded: HashSet::new(),
+            stack: Vec::new(),
+        };
+
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut pe = parse_module(path)?;
+        builder.exports.insert(&name, ModuleExports::from_image(&mut pe)?);
+        builder.expanded.insert(name.to_ascii_lowercase());
+        let root = builder.expand(&name, path.to_path_buf(), &pe, 0);
+        Ok(Self { root })
+    }
+
+    /// Whether every dependency was found and parsed, and every import resolves.
+    /// API sets are not checked.
+    pub fn is_complete(&self) -> bool {
+        self.root.iter().all(|node| {
+            node.missing_imports.is_empty() && !matches!(node.kind, NodeKind::NotFound | NodeKind::Failed(_))
+        })
+    }
+}
+
+impl Display for DependencyTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Dependency Tree: {{")?;
+        self.root.format(f, 1)?;
+        write!(f, "}}")
+    }
+}
+
+struct TreeBuilder {
+    /// Files in the directory of the root image and `TreeOptions::search_paths`.
+    modules: ModuleIndex,
+    max_depth: usize,
+    api_sets: Option<ApiSetSchema>,
+    /// Exports of every module parsed so far.
+    exports: ExportMap,
+    /// Lower case names of modules parsed so far.
+    expanded: HashSet<String>,
+    /// Lower case names of modules from the root to the one being expanded.
+    stack: Vec<String>,
+}
+
+impl TreeBuilder {
+    fn expand(&mut self, name: &str, path: PathBuf, pe: &PeImage, depth: usize) -> DependencyNode {
+        self.stack.push(name.to_ascii_lowercase());
+
+        let mut modules: Vec<String> = Vec::new();
+        for desc in &pe.imports.value {
+            let module = desc.value.name.clone().unwrap_or_default();
+            if !modules.iter().any(|m| m.eq_ignore_ascii_case(&module)) {
+                modules.push(module);
+            }
+        }
+
+        let children = modules.iter().map(|module| self.child(module, depth + 1)).collect();
+        self.stack.pop();
+
+        let missing_imports = pe.resolve_imports(&self.exports).imports.into_iter()
+            .filter(|i| i.status == ImportStatus::MissingSymbol)
+            .collect();
+
+        DependencyNode { name: name.into(), path: Some(path), kind: NodeKind::Module, missing_imports, children }
+    }
+
+    fn child(&mut self, module: &str, depth: usize) -> DependencyNode {
+        let lower = module.to_ascii_lowercase();
+        if is_api_set(module) {
+            return self.api_set(module, depth);
+        }
+        if self.stack.contains(&lower) {
+            return DependencyNode::leaf(module, None, NodeKind::Cycle);
+        }
+        if self.expanded.contains(&lower) {
+            return DependencyNode::leaf(module, None, NodeKind::Seen);
+        }
+
+        let Some(path) = self.modules.find(module) else {
+            return DependencyNode::leaf(module, None, NodeKind::NotFound);
+        };
+        if depth > self.max_depth {
+            return DependencyNode::leaf(module, Some(path), NodeKind::TooDeep);
+        }
+
+        self.expanded.insert(lower);
+        let exports = parse_module(&path)
+            .and_then(|mut pe| Ok((ModuleExports::from_image(&mut pe)?, pe)));
+        match exports {
+            Ok((exports, pe)) => {
+                self.exports.insert(module, exports);
+                self.expand(module, path, &pe, depth)
+            },
+            Err(err) => DependencyNode::leaf(module, Some(path), NodeKind::Failed(err.to_string())),
+        }
+    }
+
+    /// API set with its host as child. Imports from the set resolve against exports of the host.
+    fn api_set(&mut self, module: &str, depth: usize) -> DependencyNode {
+        let mut node = DependencyNode::leaf(module, None, NodeKind::ApiSet);
+        let importing = self.stack.last().map(String::as_str);
+        let Some(host) = self.api_sets.as_ref().and_then(|s| s.resolve(module, importing)).map(str::to_string) else {
+            return node;
+        };
+
+        node.children.push(self.child(&host, depth));
+        if let Some(exports) = self.exports.get(&host).cloned() {
+            self.exports.insert(module, exports);
+        }
+        node
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::pe::import::ImportedFunction;
+
+    use super::{is_api_set, ExportMap, ImportStatus, ModuleExports, ModuleIndex};
+
+    #[test]
+    fn export_lookup() {
+        let mut kernel32 = ModuleExports::from_names(["CreateFileW", "HeapAlloc"]);
+        kernel32.ordinals.insert(12);
+        kernel32.forwarders.insert(ImportedFunction::Name("HeapAlloc".into()), "NTDLL.RtlAllocateHeap".into());
+
+        let mut map = ExportMap::new();
+        map.insert("KERNEL32.dll", kernel32);
+        let dep = map.get("kernel32.DLL").unwrap();
+
+        assert_eq!(dep.status(&ImportedFunction::Name("CreateFileW".into())), ImportStatus::Resolved);
+        assert_eq!(dep.status(&ImportedFunction::Ordinal(12)), ImportStatus::Resolved);
+        assert_eq!(dep.status(&ImportedFunction::Name("HeapAlloc".into())), ImportStatus::Forwarded("NTDLL.RtlAllocateHeap".into()));
+        assert_eq!(dep.status(&ImportedFunction::Name("CreateFileA".into())), ImportStatus::MissingSymbol);
+        assert!(map.get("user32.dll").is_none());
+    }
+
+    #[test]
+    fn module_index() {
+        let root = env::temp_dir().join(format!("rustbin-index-{}", std::process::id()));
+        let (first, second) = (root.join("first"), root.join("second"));
+        fs::create_dir_all(first.join("USER32.dll")).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        fs::write(first.join("KERNEL32.DLL"), b"").unwrap();
+        fs::write(second.join("kernel32.dll"), b"").unwrap();
+        fs::write(second.join("user32.dll"), b"").unwrap();
+
+        let index = ModuleIndex::new(&[first.clone(), second.clone()]);
+        assert_eq!(index.find("Kernel32.dll"), Some(first.join("KERNEL32.DLL")));
+        //Directories are not modules.
+        assert_eq!(index.find("user32.dll"), Some(second.join("user32.dll")));
+        assert_eq!(index.find("ntdll.dll"), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn api_set_names() {
+        assert!(is_api_set("api-ms-win-core-synch-l1-2-0.dll"));
+        assert!(is_api_set("EXT-MS-WIN-NTUSER-WINDOW-L1-1-0.dll"));
+        assert!(!is_api_set("apphelp.dll"));
+        assert!(!is_api_set("KERNEL32.dll"));
+    }
+}
@@ -0,0 +1,99 @@
+//! Disassembly of x86 and x64 code with `iced-x86`, for a first look at the entry point and
+//! exports. Needs the `disasm` feature.
+
+use std::fmt::Display;
+
+use iced_x86::{Decoder, DecoderOptions, Formatter, IntelFormatter};
+use serde::Serialize;
+
+use super::file::MachineType;
+
+/// Instructions shown when no count is given.
+pub const DEFAULT_INSTRUCTION_COUNT: usize = 16;
+/// Longest x86 instruction.
+pub const MAX_INSTRUCTION_LENGTH: usize = 15;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Instruction {
+    pub rva: u32,
+    pub va: u64,
+    pub bytes: Vec<u8>,
+    /// Intel syntax; `(bad)` for bytes which don't decode.
+    pub text: String,
+}
+
+impl Instruction {
+    #[inline]
+    pub fn is_invalid(&self) -> bool {
+        self.text == "(bad)"
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hex: Vec<String> = self.bytes.iter().map(|b| format!("{b:02x}")).collect();
+        write!(f, "{:016x} {:<30} {}", self.va, hex.join(" "), self.text)
+    }
+}
+
+/// Bitness of code for `machine`, if it is x86 or x64.
+pub fn bitness(machine: MachineType) -> Option<u32> {
+    match machine {
+        MachineType::I386 => Some(32),
+        MachineType::AMD64 => Some(64),
+        _ => None,
+    }
+}
+
+/// Decode up to `count` instructions from `bytes`, the code at `rva` of an image loaded at
+/// `image_base`. Stops after the first invalid instruction or at the end of `bytes`.
+pub fn disassemble(bytes: &[u8], bitness: u32, image_base: u64, rva: u32, count: usize) -> Vec<Instruction> {
+    let ip = image_base + rva as u64;
+    let mut decoder = Decoder::with_ip(bitness, bytes, ip, DecoderOptions::NONE);
+    let mut formatter = IntelFormatter::new();
+    let mut instructions = Vec::with_capacity(count.min(bytes.len()));
+
+    while decoder.can_decode() && instructions.len() < count {
+        let decoded = decoder.decode();
+        let start = (decoded.ip() - ip) as usize;
+        let bytes = bytes[start..start + decoded.len()].to_vec();
+        let mut text = String::new();
+        if decoded.is_invalid() {
+            text.push_str("(bad)");
+        } else {
+            formatter.format(&decoded, &mut text);
+        }
+
+        instructions.push(Instruction { rva: rva + start as u32, va: decoded.ip(), bytes, text });
+        if decoded.is_invalid() {
+            break;
+        }
+    }
+
+    instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pe::file::MachineType;
+
+    use super::{bitness, disassemble};
+
+    #[test]
+    fn decode_x64() {
+        //sub rsp, 28h; xor ecx, ecx; call [rip+0x10]; ret; (bad)
+        let code = [0x48, 0x83, 0xec, 0x28, 0x31, 0xc9, 0xff, 0x15, 0x10, 0x00, 0x00, 0x00, 0xc3, 0x0f, 0xff];
+        let bits = bitness(MachineType::AMD64).unwrap();
+
+        let instructions = disassemble(&code, bits, 0x180000000, 0x1000, 16);
+        let text: Vec<&str> = instructions.iter().map(|i| i.text.as_str()).collect();
+        assert_eq!(text, ["sub rsp,28h", "xor ecx,ecx", "call qword ptr [18000101Ch]", "ret", "(bad)"]);
+        assert_eq!(instructions[2].rva, 0x1006);
+        assert_eq!(instructions[2].bytes.len(), 6);
+        assert!(instructions[4].is_invalid());
+        assert!(instructions[0].to_string().starts_with("0000000180001000 48 83 ec 28"));
+
+        assert_eq!(disassemble(&code, bits, 0x180000000, 0x1000, 2).len(), 2);
+        assert_eq!(bitness(MachineType::ARM64), None);
+    }
+}
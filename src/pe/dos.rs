@@ -10,26 +10,26 @@ use super::PeError;
 
 pub const HEADER_LENGTH: u64 = 64;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct DosHeader {
     pub e_magic: HeaderField<u16>,    // Magic number
-    e_cblp: HeaderField<u16>,         // Bytes on last page of file
-    e_cp: HeaderField<u16>,           // Pages in file
-    e_crlc: HeaderField<u16>,         // Relocations
-    e_cparhdr: HeaderField<u16>,      // Size of header in paragraphs
-    e_minalloc: HeaderField<u16>,     // Minimum extra paragraphs needed
-    e_maxalloc: HeaderField<u16>,     // Maximum extra paragraphs needed
-    e_ss: HeaderField<u16>,           // Initial (relative) SS value
-    e_sp: HeaderField<u16>,           // Initial SP value
-    e_csum: HeaderField<u16>,         // Checksum
-    e_ip: HeaderField<u16>,           // Initial IP value
-    e_cs: HeaderField<u16>,           // Initial (relative) CS value
-    e_lfarlc: HeaderField<u16>,       // File address of relocation table
-    e_ovno: HeaderField<u16>,         // Overlay number
-    e_res: HeaderField<[u16; 4]>,     // Reserved words
-    e_oemid:  HeaderField<u16>,       // OEM identifier (for e_oeminfo)
-    e_oeminfo: HeaderField<u16>,      // OEM information; e_oemid specific
-    e_res2: HeaderField<[u16; 10]>,   // Reserved words
+    pub(crate) e_cblp: HeaderField<u16>,         // Bytes on last page of file
+    pub(crate) e_cp: HeaderField<u16>,           // Pages in file
+    pub(crate) e_crlc: HeaderField<u16>,         // Relocations
+    pub(crate) e_cparhdr: HeaderField<u16>,      // Size of header in paragraphs
+    pub(crate) e_minalloc: HeaderField<u16>,     // Minimum extra paragraphs needed
+    pub(crate) e_maxalloc: HeaderField<u16>,     // Maximum extra paragraphs needed
+    pub(crate) e_ss: HeaderField<u16>,           // Initial (relative) SS value
+    pub(crate) e_sp: HeaderField<u16>,           // Initial SP value
+    pub(crate) e_csum: HeaderField<u16>,         // Checksum
+    pub(crate) e_ip: HeaderField<u16>,           // Initial IP value
+    pub(crate) e_cs: HeaderField<u16>,           // Initial (relative) CS value
+    pub(crate) e_lfarlc: HeaderField<u16>,       // File address of relocation table
+    pub(crate) e_ovno: HeaderField<u16>,         // Overlay number
+    pub(crate) e_res: HeaderField<[u16; 4]>,     // Reserved words
+    pub(crate) e_oemid:  HeaderField<u16>,       // OEM identifier (for e_oeminfo)
+    pub(crate) e_oeminfo: HeaderField<u16>,      // OEM information; e_oemid specific
+    pub(crate) e_res2: HeaderField<[u16; 10]>,   // Reserved words
     pub e_lfanew: HeaderField<u32>,   // File address of new exe header
 }
 
@@ -59,9 +59,31 @@ impl DosHeader {
     }
 }
 
+impl DosHeader {
+    /// Fails if the PE signature and file header at `e_lfanew` don't fit in `input_size` bytes,
+    /// counted from the start of this header.
+    pub fn validate_lfanew(&self, input_size: u64) -> crate::pe::Result<()> {
+        let lfanew = self.e_lfanew.value as u64;
+        if lfanew + super::file::HEADER_LENGTH > input_size {
+            return Err(PeError::InvalidHeader {
+                name: "DosHeader".into(),
+                offset: self.e_lfanew.offset.into(),
+                reason: format!("e_lfanew {lfanew:#x} points beyond the end of input ({input_size:#x} bytes)"),
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether `e_lfanew` is 4-byte aligned, as the loader expects.
+    #[inline]
+    pub fn is_lfanew_aligned(&self) -> bool {
+        self.e_lfanew.value.is_multiple_of(4)
+    }
+}
+
 impl Header for DosHeader {
-    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> {
-        let bytes_available = (bytes.len() as u64) - pos;
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> {
+        let bytes_available = bytes.len() as u64;
 
         if bytes_available < HEADER_LENGTH {
             return Err ( 
@@ -118,7 +140,7 @@ impl Display for DosHeader {
 
 #[cfg(test)]
 mod tests {
-    use crate::types::Header;
+    use crate::{pe::PeError, types::{FileOffset, Header, Rva}};
 
     use super::DosHeader;    
     const RAW_DOS_BYTES: [u8; 64] = [0x4D, 0x5A, 0x90, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0xFF, 0xFF, 
@@ -130,11 +152,24 @@ mod tests {
         let dos_header = DosHeader::parse_bytes(RAW_DOS_BYTES.to_vec(), 0).unwrap();
         assert!(dos_header.is_valid());
         assert_eq!(dos_header.e_magic.value, 0x5A4D);
-        assert_eq!(dos_header.e_magic.offset, 0);
-        assert_eq!(dos_header.e_magic.rva, 0);
+        assert_eq!(dos_header.e_magic.offset, FileOffset(0));
+        assert_eq!(dos_header.e_magic.rva, Rva(0));
         assert_eq!(dos_header.e_lfanew.value, 0x000000F8);
-        assert_eq!(dos_header.e_lfanew.offset, 60);
-        assert_eq!(dos_header.e_lfanew.rva, 60);
+        assert_eq!(dos_header.e_lfanew.offset, FileOffset(60));
+        assert_eq!(dos_header.e_lfanew.rva, Rva(60));
+    }
+
+    #[test]
+    fn lfanew_in_bounds() {
+        let dos_header = DosHeader::parse_bytes(RAW_DOS_BYTES.to_vec(), 0).unwrap();
+        assert!(dos_header.is_lfanew_aligned());
+        assert!(dos_header.validate_lfanew(0xF8 + 24).is_ok());
+        let err = dos_header.validate_lfanew(0x100).unwrap_err();
+        assert!(matches!(err.root(), PeError::InvalidHeader { offset: 60, .. }), "{err}");
+
+        let mut buf = RAW_DOS_BYTES.to_vec();
+        buf[60] = 0xFA;
+        assert!(!DosHeader::parse_bytes(buf, 0).unwrap().is_lfanew_aligned());
     }
 
     #[test]
@@ -0,0 +1,361 @@
+//! Adding and removing imported functions.
+//!
+//! The import directory is rebuilt in a section named `.idata2`, replaced on every change.
+//! Modules of the original image keep their IAT, so code calling through it still works;
+//! their lookup tables are copied to the new section when they change. Each added function
+//! gets a descriptor and an IAT of its own in the new section, even for modules already
+//! imported, as the loader accepts a module in more than one descriptor. These IATs come
+//! first in `.idata2` and keep their rva across changes; the slot of a removed function is
+//! left empty.
+
+use crate::pe::{import::IMPORT_DESCRIPTOR_SIZE, optional::{DirectoryType, ImageType}, section, PeError, Result};
+
+use super::PeEditor;
+
+/// Name of the section holding the rebuilt import directory.
+pub const IMPORT_SECTION: &str = ".idata2";
+
+/// Descriptor of the original image.
+#[derive(Debug)]
+struct Existing {
+    /// Index in the parsed import directory.
+    index: usize,
+    /// `OriginalFirstThunk`, `TimeDateStamp`, `ForwarderChain`, `Name` and `FirstThunk`.
+    fields: [u32; 5],
+    /// Lookup table to write in the new section, if it changed or lived there.
+    ilt: Option<Vec<u64>>,
+    /// Rvas of the IAT slots, in lookup table order.
+    iat: Vec<u32>,
+}
+
+/// Descriptor living in the new section.
+#[derive(Debug)]
+struct Added {
+    dll: String,
+    function: String,
+    /// Rva of the IAT slot, or `None` to place it after the others.
+    iat: Option<u32>,
+}
+
+/// Import directory of an image, as it will be rebuilt.
+#[derive(Debug, Default)]
+struct Plan {
+    existing: Vec<(String, Existing)>,
+    added: Vec<Added>,
+}
+
+impl PeEditor {
+    fn is_64(&self) -> bool {
+        self.pe.optional.value.get_image_type() == ImageType::PE64
+    }
+
+    /// Rva range of the `.idata2` section from an earlier change, if it is the last section.
+    fn import_section(&self) -> Option<(u32, u32)> {
+        self.pe.sections.value.last()
+            .map(|s| &s.value)
+            .filter(|s| s.name_lossy() == IMPORT_SECTION)
+            .map(|s| (s.virtual_address.value, s.virtual_address.value.saturating_add(s.virtual_size.value.max(s.sizeof_raw_data.value))))
+    }
+
+    fn import_plan(&self) -> Result<Plan> {
+        let section = self.import_section();
+        let in_section = |rva: u32| section.is_some_and(|(start, end)| (start..end).contains(&rva));
+        let mut plan = Plan::default();
+
+        for (index, desc) in self.pe.imports.value.iter().enumerate() {
+            let desc = &desc.value;
+            let dll = desc.name.clone().unwrap_or_default();
+            if in_section(desc.first_thunk.value) {
+                for import in &desc.imports {
+                    let name = import.import_name().ok_or(PeError::InvalidHeader {
+                        name: "ImportDescriptor".into(), offset: desc.ilt.offset.into(), reason: format!("{dll} in {IMPORT_SECTION} has imports by ordinal")
+                    })?;
+                    plan.added.push(Added { dll: dll.clone(), function: name.name.value.clone(), iat: Some(import.iat_rva()) });
+                }
+                continue;
+            }
+
+            let mut fields = [0u32; 5];
+            for (i, field) in fields.iter_mut().enumerate() {
+                *field = self.read_u32(desc.ilt.offset + i as u64 * 4)?;
+            }
+            let ilt = in_section(desc.ilt.value).then(|| desc.imports.iter().map(|i| i.value()).collect());
+            let iat = desc.imports.iter().map(|i| i.iat_rva()).collect();
+            plan.existing.push((dll, Existing { index, fields, ilt, iat }));
+        }
+        Ok(plan)
+    }
+
+    /// Import `function` by name from `dll`. Does nothing if it is already imported.
+    /// Returns the rva of the IAT slot the loader will write the address of `function` to,
+    /// which later changes of the imports keep as long as `.idata2` stays the last section.
+    pub fn add_import(&mut self, dll: &str, function: &str) -> Result<u32> {
+        if let Some(rva) = self.import_slot(dll, function) {
+            return Ok(rva);
+        }
+
+        let mut plan = self.import_plan()?;
+        plan.added.push(Added { dll: dll.into(), function: function.into(), iat: None });
+        self.write_imports(plan)?;
+
+        self.import_slot(dll, function).ok_or(PeError::NoImport(format!("{dll}!{function}")))
+    }
+
+    /// Stop importing `function` from `dll`. A module left without functions is dropped.
+    /// The IAT slot of a function of the original image is kept and gets the address of
+    /// another function of the module, so the layout of its IAT does not change.
+    pub fn remove_import(&mut self, dll: &str, function: &str) -> Result<()> {
+        let mut plan = self.import_plan()?;
+        let mut found = false;
+
+        let count = plan.added.len();
+        plan.added.retain(|a| !(a.dll.eq_ignore_ascii_case(dll) && a.function == function));
+        found |= plan.added.len() != count;
+
+        let is_64 = self.is_64();
+        let mut dropped = Vec::new();
+        for (position, (name, existing)) in plan.existing.iter_mut().enumerate() {
+            if !name.eq_ignore_ascii_case(dll) {
+                continue;
+            }
+            let desc = self.pe.imports.value[existing.index].value.clone();
+            if !desc.imports.iter().any(|i| i.import_name().is_some_and(|n| n.name.value == function)) {
+                continue;
+            }
+            found = true;
+
+            let mut ilt = existing.ilt.take().unwrap_or_else(|| desc.imports.iter().map(|i| i.value()).collect());
+            let replacement = desc.imports.iter().zip(&ilt)
+                .find(|(l, _)| l.import_name().is_none_or(|n| n.name.value != function))
+                .map(|(_, value)| *value);
+            let Some(replacement) = replacement else {
+                dropped.push(position);
+                continue;
+            };
+            for (i, lookup) in desc.imports.iter().enumerate() {
+                if lookup.import_name().is_some_and(|n| n.name.value == function) {
+                    ilt[i] = replacement;
+                }
+            }
+
+            //A bound descriptor without a lookup table has addresses in its IAT.
+            if existing.fields[0] == 0 && existing.fields[1] != 0 {
+                return Err(PeError::InvalidHeader {
+                    name: "ImportDescriptor".into(), offset: desc.ilt.offset.into(), reason: format!("{dll} is bound and has no lookup table")
+                });
+            }
+            for (rva, value) in existing.iat.iter().zip(&ilt) {
+                let offset = self.pe.rva_to_offset(*rva).ok_or(PeError::InvalidRVA((*rva).into()))?;
+                match is_64 {
+                    true => self.write_at(offset, &value.to_le_bytes())?,
+                    false => self.write_u32(offset, *value as u32)?,
+                }
+            }
+            existing.ilt = Some(ilt);
+        }
+
+        if !found {
+            return Err(PeError::NoImport(format!("{dll}!{function}")));
+        }
+        for position in dropped.into_iter().rev() {
+            plan.existing.remove(position);
+        }
+        self.write_imports(plan)
+    }
+
+    /// Rva of the IAT slot of `function` imported by name from `dll`.
+    pub fn import_slot(&self, dll: &str, function: &str) -> Option<u32> {
+        self.pe.imports.value.iter()
+            .map(|d| &d.value)
+            .filter(|d| d.name.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(dll)))
+            .flat_map(|d| &d.imports)
+            .find(|i| i.import_name().is_some_and(|n| n.name.value == function))
+            .map(|i| i.iat_rva())
+    }
+
+    /// Write the import directory of `plan` to a new `.idata2`, replacing the one of an
+    /// earlier change. IATs of added functions come first, then descriptors, lookup tables,
+    /// hint/name entries and module names.
+    fn write_imports(&mut self, mut plan: Plan) -> Result<()> {
+        if self.import_section().is_some() {
+            //The image must parse without the section in between.
+            self.set_directory(DirectoryType::Import, 0, 0)?;
+            self.remove_last_section()?;
+        }
+        let rva = self.next_section_rva();
+        let thunk = if self.is_64() { 8 } else { 4 };
+
+        //Each added function has an IAT of its slot and a terminator.
+        let mut iat_end = plan.added.iter()
+            .filter_map(|a| a.iat)
+            .map(|iat| (iat - rva) as usize + 2 * thunk)
+            .max()
+            .unwrap_or_default();
+        for added in plan.added.iter_mut().filter(|a| a.iat.is_none()) {
+            added.iat = Some((rva as usize + iat_end) as u32);
+            iat_end += 2 * thunk;
+        }
+
+        let descriptors = (plan.existing.len() + plan.added.len() + 1) * IMPORT_DESCRIPTOR_SIZE;
+        let rewritten: usize = plan.existing.iter().filter_map(|(_, e)| e.ilt.as_ref()).map(|ilt| (ilt.len() + 1) * thunk).sum();
+        let desc_start = iat_end;
+        let ilt_start = desc_start + descriptors;
+        let names_start = ilt_start + rewritten + plan.added.len() * 2 * thunk;
+
+        let mut iats = vec![0u8; iat_end];
+        let mut descs = Vec::with_capacity(descriptors);
+        let mut ilts = Vec::new();
+        let mut names = Vec::new();
+        let push_thunk = |table: &mut Vec<u8>, value: u64| table.extend(&value.to_le_bytes()[..thunk]);
+
+        for (_, existing) in &plan.existing {
+            let mut fields = existing.fields;
+            if let Some(ilt) = &existing.ilt {
+                fields[0] = (rva as usize + ilt_start + ilts.len()) as u32;
+                for value in ilt {
+                    push_thunk(&mut ilts, *value);
+                }
+                push_thunk(&mut ilts, 0);
+            }
+            //Bound addresses are resolved again, as the bound import directory is dropped.
+            if fields[1] != 0 && fields[0] != 0 {
+                fields[1] = 0;
+                fields[2] = 0;
+            }
+            for field in fields {
+                descs.extend(field.to_le_bytes());
+            }
+        }
+
+        for added in &plan.added {
+            let ilt = (rva as usize + ilt_start + ilts.len()) as u32;
+            let iat = added.iat.unwrap_or_default();
+            let hint_name = (rva as usize + names_start + names.len()) as u64;
+            push_thunk(&mut ilts, hint_name);
+            push_thunk(&mut ilts, 0);
+            let slot = (iat - rva) as usize;
+            iats[slot..slot + thunk].copy_from_slice(&hint_name.to_le_bytes()[..thunk]);
+
+            names.extend([0u8, 0]);
+            names.extend(added.function.as_bytes());
+            names.push(0);
+            names.resize(names.len().next_multiple_of(2), 0);
+
+            let dll_name = (rva as usize + names_start + names.len()) as u32;
+            names.extend(added.dll.as_bytes());
+            names.push(0);
+            names.resize(names.len().next_multiple_of(2), 0);
+
+            for value in [ilt, 0, 0, dll_name, iat] {
+                descs.extend(value.to_le_bytes());
+            }
+        }
+
+        let mut data = iats;
+        data.extend(descs);
+        data.resize(ilt_start, 0);
+        data.extend(ilts);
+        data.extend(names);
+
+        let flags = section::Flags::INITIALIZED_DATA | section::Flags::MEM_READ | section::Flags::MEM_WRITE;
+        self.add_section(IMPORT_SECTION, &data, flags)?;
+        self.set_directory(DirectoryType::Import, rva + desc_start as u32, descriptors as u32)?;
+        if self.pe.has_directory(DirectoryType::BoundImport) {
+            self.set_directory(DirectoryType::BoundImport, 0, 0)?;
+        }
+        self.commit()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::pe::{builder::PeBuilder, edit::PeEditor, file::MachineType, PeError};
+
+    use super::IMPORT_SECTION;
+
+    fn functions(editor: &PeEditor) -> Vec<String> {
+        editor.image().imports.value.iter().flat_map(|d| d.value.get_imports_str()).collect()
+    }
+
+    #[test]
+    fn add_to_image_without_imports() {
+        let bytes = PeBuilder::new(MachineType::AMD64).build().unwrap();
+        let mut editor = PeEditor::new(bytes).unwrap();
+
+        let slot = editor.add_import("KERNEL32.dll", "ExitProcess").unwrap();
+        assert_eq!(editor.add_import("kernel32.dll", "ExitProcess").unwrap(), slot);
+        editor.add_import("USER32.dll", "MessageBoxW").unwrap();
+
+        let pe = editor.image();
+        assert_eq!(pe.sections.value.len(), 2);
+        assert_eq!(pe.sections.value[1].value.name_lossy(), IMPORT_SECTION);
+        assert_eq!(pe.imports.value.len(), 2);
+        assert_eq!(pe.imports.value[1].value.name.as_deref(), Some("USER32.dll"));
+        assert!(pe.imports.value.iter().all(|d| d.value.iat_mismatches.is_empty()));
+        assert_eq!(editor.import_slot("KERNEL32.dll", "ExitProcess"), Some(0x2000));
+        assert_eq!(slot, 0x2000);
+    }
+
+    #[test]
+    fn added_slots_do_not_move() {
+        let bytes = PeBuilder::new(MachineType::I386)
+            .with_import("KERNEL32.dll", &["ExitProcess"])
+            .build()
+            .unwrap();
+        let mut editor = PeEditor::new(bytes).unwrap();
+
+        let create = editor.add_import("KERNEL32.dll", "CreateFileW").unwrap();
+        let message = editor.add_import("USER32.dll", "MessageBoxW").unwrap();
+        let close = editor.add_import("KERNEL32.dll", "CloseHandle").unwrap();
+        editor.remove_import("USER32.dll", "MessageBoxW").unwrap();
+        let sleep = editor.add_import("KERNEL32.dll", "Sleep").unwrap();
+        editor.remove_import("KERNEL32.dll", "ExitProcess").unwrap();
+
+        assert_eq!(editor.import_slot("KERNEL32.dll", "CreateFileW"), Some(create));
+        assert_eq!(editor.import_slot("KERNEL32.dll", "CloseHandle"), Some(close));
+        assert_eq!(editor.import_slot("KERNEL32.dll", "Sleep"), Some(sleep));
+        assert!(![create, close].contains(&sleep));
+        assert!(editor.import_slot("USER32.dll", "MessageBoxW").is_none());
+        assert_ne!(message, create);
+        assert_eq!(functions(&editor), vec!["CreateFileW", "CloseHandle", "Sleep"]);
+        assert!(editor.image().imports.value.iter().all(|d| d.value.iat_mismatches.is_empty()));
+    }
+
+    #[test]
+    fn original_iat_is_kept() {
+        let bytes = PeBuilder::new(MachineType::I386)
+            .with_import("KERNEL32.dll", &["ExitProcess", "Sleep", "GetTickCount"])
+            .build()
+            .unwrap();
+        let mut editor = PeEditor::new(bytes).unwrap();
+        let slots: Vec<_> = ["ExitProcess", "Sleep", "GetTickCount"].iter()
+            .map(|f| editor.import_slot("KERNEL32.dll", f).unwrap())
+            .collect();
+
+        editor.add_import("KERNEL32.dll", "CreateFileW").unwrap();
+        editor.remove_import("KERNEL32.dll", "Sleep").unwrap();
+        assert_eq!(functions(&editor), vec!["ExitProcess", "ExitProcess", "GetTickCount", "CreateFileW"]);
+        assert_eq!(editor.import_slot("KERNEL32.dll", "GetTickCount"), Some(slots[2]));
+        assert_eq!(editor.image().imports.value[0].value.imports[1].iat_rva(), slots[1]);
+        assert!(editor.image().imports.value.iter().all(|d| d.value.iat_mismatches.is_empty()));
+
+        editor.remove_import("KERNEL32.dll", "CreateFileW").unwrap();
+        assert_eq!(editor.image().imports.value.len(), 1);
+        assert!(matches!(editor.remove_import("KERNEL32.dll", "CreateFileW"), Err(PeError::NoImport(_))));
+    }
+
+    #[test]
+    fn remove_last_function_drops_module() {
+        let bytes = PeBuilder::new(MachineType::AMD64)
+            .with_import("KERNEL32.dll", &["ExitProcess"])
+            .with_import("USER32.dll", &["MessageBoxW"])
+            .build()
+            .unwrap();
+        let mut editor = PeEditor::new(bytes).unwrap();
+
+        editor.remove_import("USER32.dll", "MessageBoxW").unwrap();
+        assert_eq!(editor.image().imports.value.len(), 1);
+        assert_eq!(functions(&editor), vec!["ExitProcess"]);
+    }
+}
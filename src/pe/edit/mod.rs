@@ -0,0 +1,269 @@
+//! Changes to an image, made on a copy of its bytes.
+//!
+//! `PeEditor` holds the bytes of an image and the `PeImage` parsed from them. Each change
+//! writes the bytes and parses them again, so `image` always describes `bytes`. New data goes
+//! in sections appended after the last one, and header fields which depend on the layout,
+//! like `SizeOfImage` and `CheckSum`, are updated with them.
+
+pub mod imports;
+pub mod normalize;
+pub mod rsrc;
+pub mod strip;
+
+use crate::types::FileOffset;
+
+use super::{
+    optional::{image_checksum, DirectoryType}, section, PeError, PeImage, Result, SECTION_HEADER_LENGTH,
+};
+
+/// Offsets of fields in either optional header.
+pub(crate) const SIZEOF_CODE_OFFSET: u64 = 4;
+pub(crate) const SIZEOF_INITIALIZED_DATA_OFFSET: u64 = 8;
+pub(crate) const SIZEOF_IMAGE_OFFSET: u64 = 56;
+pub(crate) const CHECKSUM_OFFSET: u64 = 64;
+
+pub struct PeEditor {
+    bytes: Vec<u8>,
+    pe: PeImage,
+}
+
+impl PeEditor {
+    /// Editor of the image in `bytes`, which must parse as a PE image at offset 0.
+    pub fn new(bytes: Vec<u8>) -> Result<Self> {
+        let pe = PeImage::parse_bytes(bytes.clone(), 0)?;
+        Ok(Self { bytes, pe })
+    }
+
+    /// The image as parsed after the last change.
+    pub fn image(&self) -> &PeImage {
+        &self.pe
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Update `CheckSum` and parse the bytes again.
+    pub(crate) fn commit(&mut self) -> Result<()> {
+        let checksum_at = self.pe.optional.offset + CHECKSUM_OFFSET;
+        let checksum = image_checksum(&self.bytes, checksum_at.0 as usize);
+        self.write_at(checksum_at, &checksum.to_le_bytes())?;
+        self.pe = PeImage::parse_bytes(self.bytes.clone(), 0)?;
+        Ok(())
+    }
+
+    pub(crate) fn write_at(&mut self, offset: impl Into<FileOffset>, data: &[u8]) -> Result<()> {
+        let offset = offset.into().0;
+        let start = offset as usize;
+        let dest = self.bytes.get_mut(start..start.saturating_add(data.len()))
+            .ok_or(PeError::InvalidOffset(offset))?;
+        dest.copy_from_slice(data);
+        Ok(())
+    }
+
+    pub(crate) fn write_u32(&mut self, offset: impl Into<FileOffset>, value: u32) -> Result<()> {
+        self.write_at(offset, &value.to_le_bytes())
+    }
+
+    fn read_u32(&self, offset: impl Into<FileOffset>) -> Result<u32> {
+        let offset = offset.into().0;
+        let start = offset as usize;
+        self.bytes.get(start..start + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .ok_or(PeError::InvalidOffset(offset))
+    }
+
+    /// Add `delta` to the optional header field at `field`, an offset from its start.
+    fn adjust_optional(&mut self, field: u64, delta: i64) -> Result<()> {
+        let offset = self.pe.optional.offset + field;
+        let value = self.read_u32(offset)? as i64 + delta;
+        self.write_u32(offset, value.clamp(0, u32::MAX as i64) as u32)
+    }
+
+    /// Point data directory `dir` at `rva` and `size`.
+    pub(crate) fn set_directory(&mut self, dir: DirectoryType, rva: u32, size: u32) -> Result<()> {
+        let entry = self.pe.data_dirs.value.get(dir as usize)
+            .ok_or(PeError::InvalidHeader {
+                name: "DataDirectory".into(), offset: self.pe.data_dirs.offset.into(),
+                reason: format!("image has no entry for {dir:?}"),
+            })?;
+        let (rva_at, size_at) = (entry.value.rva.offset, entry.value.size.offset);
+        self.write_u32(rva_at, rva)?;
+        self.write_u32(size_at, size)
+    }
+
+    /// End of raw data of sections, where any overlay starts.
+    fn sections_end(&self) -> u32 {
+        self.pe.sections.value.iter()
+            .map(|s| s.value.raw_data_ptr.value.saturating_add(s.value.sizeof_raw_data.value))
+            .chain([self.pe.optional.value.sizeof_headers()])
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Insert `len` bytes at `at`, moving the overlay and the certificate table after it.
+    fn insert_bytes(&mut self, at: u32, data: Vec<u8>) -> Result<()> {
+        let at = (at as usize).min(self.bytes.len());
+        let len = data.len() as u32;
+        self.bytes.splice(at..at, data);
+        self.move_security(at as u32, len as i64)
+    }
+
+    fn remove_bytes(&mut self, at: u32, len: u32) -> Result<()> {
+        let start = (at as usize).min(self.bytes.len());
+        let end = start.saturating_add(len as usize).min(self.bytes.len());
+        self.bytes.drain(start..end);
+        self.move_security(at, -(len as i64))
+    }
+
+    fn move_security(&mut self, from: u32, delta: i64) -> Result<()> {
+        let Some(security) = self.pe.directory(DirectoryType::Security) else {
+            return Ok(());
+        };
+        let (offset, size) = (security.rva.value, security.size.value);
+        if offset >= from {
+            self.set_directory(DirectoryType::Security, (offset as i64 + delta) as u32, size)?;
+        }
+        Ok(())
+    }
+
+    /// Rva `add_section` will put the next section at.
+    pub fn next_section_rva(&self) -> u32 {
+        let sa = self.pe.optional.value.section_alignment().max(1);
+        self.pe.sections.value.iter()
+            .map(|s| s.value.virtual_address.value.saturating_add(s.value.virtual_size.value.max(s.value.sizeof_raw_data.value)))
+            .chain([self.pe.optional.value.sizeof_headers()])
+            .max()
+            .unwrap_or_default()
+            .next_multiple_of(sa)
+    }
+
+    /// Append a section named `name` holding `data` and return its rva.
+    /// Fails if there is no room for another section header before the first section.
+    pub fn add_section(&mut self, name: &str, data: &[u8], flags: section::Flags) -> Result<u32> {
+        if name.len() > 8 {
+            return Err(PeError::InvalidHeader {
+                name: "Section".into(), offset: self.pe.sections.offset.into(), reason: format!("name {name} is longer than 8 bytes")
+            });
+        }
+
+        let sections = &self.pe.sections.value;
+        let header_at = u64::from(self.pe.sections.offset) + sections.len() as u64 * SECTION_HEADER_LENGTH;
+        let first_data = sections.iter()
+            .filter(|s| s.value.sizeof_raw_data.value > 0)
+            .map(|s| s.value.raw_data_ptr.value as u64)
+            .chain([self.pe.optional.value.sizeof_headers() as u64])
+            .min()
+            .unwrap_or_default();
+        if header_at + SECTION_HEADER_LENGTH > first_data {
+            return Err(PeError::InvalidHeader {
+                name: "Section".into(), offset: header_at, reason: "no room for another section header".into()
+            });
+        }
+
+        let sa = self.pe.optional.value.section_alignment().max(1);
+        let fa = self.pe.optional.value.file_alignment().max(1);
+        let rva = self.next_section_rva();
+        let end = self.sections_end();
+        let raw_ptr = end.next_multiple_of(fa);
+        let raw_size = (data.len() as u32).next_multiple_of(fa);
+
+        let mut inserted = vec![0u8; (raw_ptr - end + raw_size) as usize];
+        inserted[(raw_ptr - end) as usize..][..data.len()].copy_from_slice(data);
+        self.insert_bytes(end, inserted)?;
+
+        let mut header = Vec::with_capacity(SECTION_HEADER_LENGTH as usize);
+        header.extend(name.as_bytes());
+        header.resize(8, 0);
+        for value in [data.len() as u32, rva, raw_size, raw_ptr, 0, 0, 0, flags.bits()] {
+            header.extend(value.to_le_bytes());
+        }
+        self.write_at(header_at, &header)?;
+
+        let count_at = self.pe.file.value.sections.offset;
+        self.write_at(count_at, &(self.pe.file.value.sections.value + 1).to_le_bytes())?;
+        self.write_u32(self.pe.optional.offset + SIZEOF_IMAGE_OFFSET, (rva + data.len() as u32).next_multiple_of(sa))?;
+        self.adjust_sizes(flags, raw_size as i64)?;
+
+        self.commit()?;
+        Ok(rva)
+    }
+
+    /// Remove the last section, its header and its raw data.
+    pub fn remove_last_section(&mut self) -> Result<()> {
+        let Some(last) = self.pe.sections.value.last().cloned() else {
+            return Ok(());
+        };
+        let last = last.value;
+        let flags = section::Flags::from_bits_truncate(last.charactristics.value);
+
+        self.write_at(last.name.offset, &[0u8; SECTION_HEADER_LENGTH as usize])?;
+        let count_at = self.pe.file.value.sections.offset;
+        self.write_at(count_at, &(self.pe.file.value.sections.value - 1).to_le_bytes())?;
+
+        let sa = self.pe.optional.value.section_alignment().max(1);
+        let sizeof_image = self.pe.sections.value.iter().rev().nth(1)
+            .map_or(self.pe.optional.value.sizeof_headers(), |s| {
+                s.value.virtual_address.value.saturating_add(s.value.virtual_size.value.max(s.value.sizeof_raw_data.value))
+            });
+        self.write_u32(self.pe.optional.offset + SIZEOF_IMAGE_OFFSET, sizeof_image.next_multiple_of(sa))?;
+        self.adjust_sizes(flags, -(last.sizeof_raw_data.value as i64))?;
+
+        if last.sizeof_raw_data.value > 0 {
+            self.remove_bytes(last.raw_data_ptr.value, last.sizeof_raw_data.value)?;
+        }
+        self.commit()
+    }
+
+    fn adjust_sizes(&mut self, flags: section::Flags, delta: i64) -> Result<()> {
+        if flags.contains(section::Flags::CODE) {
+            self.adjust_optional(SIZEOF_CODE_OFFSET, delta)?;
+        }
+        if flags.contains(section::Flags::INITIALIZED_DATA) {
+            self.adjust_optional(SIZEOF_INITIALIZED_DATA_OFFSET, delta)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::pe::{builder::PeBuilder, file::MachineType, optional::image_checksum, section::Flags};
+
+    use super::{PeEditor, CHECKSUM_OFFSET};
+
+    #[test]
+    fn add_and_remove_section() {
+        let bytes = PeBuilder::new(MachineType::AMD64).build().unwrap();
+        let mut editor = PeEditor::new(bytes.clone()).unwrap();
+
+        let rva = editor.add_section(".extra", &[1, 2, 3], Flags::INITIALIZED_DATA | Flags::MEM_READ).unwrap();
+        assert_eq!(rva, 0x2000);
+
+        let pe = editor.image();
+        assert_eq!(pe.sections.value.len(), 2);
+        assert_eq!(pe.sections.value[1].value.name_lossy(), ".extra");
+        assert_eq!(pe.sections.value[1].value.raw_data_ptr.value, 0x400);
+        assert_eq!(pe.optional.value.sizeof_image(), 0x3000);
+        assert_eq!(editor.bytes().len(), 0x600);
+        assert_eq!(&editor.bytes()[0x400..0x403], &[1, 2, 3]);
+
+        let checksum_at = (pe.optional.offset + CHECKSUM_OFFSET).0 as usize;
+        assert_eq!(pe.optional.value.checksum().value, image_checksum(editor.bytes(), checksum_at));
+
+        editor.remove_last_section().unwrap();
+        assert_eq!(editor.into_bytes(), bytes);
+    }
+
+    #[test]
+    fn section_name_too_long() {
+        let bytes = PeBuilder::new(MachineType::I386).build().unwrap();
+        let mut editor = PeEditor::new(bytes).unwrap();
+        assert!(editor.add_section(".toolongname", &[0], Flags::MEM_READ).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! Fixing header fields which follow from the layout of sections.
+//!
+//! After hand edits of section headers or data, `normalize` makes sizes in the optional
+//! header agree with the section table again, pads raw data of sections to the file
+//! alignment and updates `CheckSum`, as a linker would.
+
+use crate::pe::{section, PeImage, Result, SECTION_HEADER_LENGTH};
+
+use super::{PeEditor, SIZEOF_CODE_OFFSET, SIZEOF_IMAGE_OFFSET, SIZEOF_INITIALIZED_DATA_OFFSET};
+
+pub(crate) const SIZEOF_HEADERS_OFFSET: u64 = 60;
+
+impl PeEditor {
+    /// Recalculate `SizeOfImage`, `SizeOfHeaders`, `SizeOfCode`, `SizeOfInitializedData`,
+    /// raw sizes of sections and `CheckSum`. Raw sizes are aligned up to the file alignment
+    /// where the file has room or the section is the last one, which gets zeros appended.
+    pub fn normalize(&mut self) -> Result<()> {
+        //Crate internals may have written headers without parsing them again.
+        self.pe = PeImage::parse_bytes(self.bytes.clone(), 0)?;
+        let sa = self.pe.optional.value.section_alignment().max(1);
+        let fa = self.pe.optional.value.file_alignment().max(1);
+
+        let mut by_offset: Vec<_> = self.pe.sections.value.iter()
+            .map(|s| s.value.clone())
+            .filter(|s| s.sizeof_raw_data.value > 0)
+            .collect();
+        by_offset.sort_by_key(|s| s.raw_data_ptr.value);
+        for (i, header) in by_offset.iter().enumerate() {
+            let (ptr, size) = (header.raw_data_ptr.value, header.sizeof_raw_data.value);
+            let aligned = size.next_multiple_of(fa);
+            if aligned == size {
+                continue;
+            }
+            match by_offset.get(i + 1) {
+                Some(next) if next.raw_data_ptr.value < ptr.saturating_add(aligned) => continue,
+                Some(_) => {},
+                None => {
+                    let end = ptr.saturating_add(size);
+                    self.insert_bytes(end, vec![0; (aligned - size) as usize])?;
+                },
+            }
+            self.write_u32(header.sizeof_raw_data.offset, aligned)?;
+        }
+        self.pe = PeImage::parse_bytes(self.bytes.clone(), 0)?;
+
+        let sections = &self.pe.sections.value;
+        let headers_end = u64::from(self.pe.sections.offset) + sections.len() as u64 * SECTION_HEADER_LENGTH;
+        let first_data = sections.iter()
+            .map(|s| s.value.raw_data_ptr.value)
+            .filter(|ptr| *ptr > 0)
+            .min()
+            .unwrap_or(u32::MAX);
+        let sizeof_headers = (headers_end as u32).next_multiple_of(fa).min(first_data.max(headers_end as u32));
+
+        //The loader maps raw data when the virtual size is 0.
+        let sizeof_image = sections.iter()
+            .map(|s| {
+                let size = match s.value.virtual_size.value {
+                    0 => s.value.sizeof_raw_data.value,
+                    size => size,
+                };
+                s.value.virtual_address.value.saturating_add(size)
+            })
+            .chain([sizeof_headers])
+            .max()
+            .unwrap_or_default()
+            .next_multiple_of(sa);
+
+        let raw_size_of = |flag: section::Flags| -> u32 {
+            sections.iter()
+                .filter(|s| section::Flags::from_bits_truncate(s.value.charactristics.value).contains(flag))
+                .map(|s| s.value.sizeof_raw_data.value)
+                .sum()
+        };
+        let sizeof_code = raw_size_of(section::Flags::CODE);
+        let sizeof_initialized_data = raw_size_of(section::Flags::INITIALIZED_DATA);
+
+        let optional = self.pe.optional.offset;
+        self.write_u32(optional + SIZEOF_CODE_OFFSET, sizeof_code)?;
+        self.write_u32(optional + SIZEOF_INITIALIZED_DATA_OFFSET, sizeof_initialized_data)?;
+        self.write_u32(optional + SIZEOF_IMAGE_OFFSET, sizeof_image)?;
+        self.write_u32(optional + SIZEOF_HEADERS_OFFSET, sizeof_headers)?;
+        self.commit()
+    }
+}
+
+impl PeImage {
+    /// Bytes of the image with size fields and `CheckSum` fixed. See `PeEditor::normalize`.
+    pub fn normalize(&mut self) -> Result<Vec<u8>> {
+        let start = u64::from(self.dos.offset);
+        let end = self.reader.end_offset()?;
+        let bytes = self.reader.read_bytes_at_offset(start, end.saturating_sub(start) as usize)?;
+        let mut editor = PeEditor::new(bytes)?;
+        editor.normalize()?;
+        Ok(editor.into_bytes())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::pe::{builder::PeBuilder, edit::{PeEditor, CHECKSUM_OFFSET, SIZEOF_IMAGE_OFFSET}, file::MachineType, PeImage};
+
+    use super::SIZEOF_HEADERS_OFFSET;
+
+    #[test]
+    fn normalized_image_is_unchanged() {
+        let bytes = PeBuilder::new(MachineType::AMD64).with_import("KERNEL32.dll", &["ExitProcess"]).build().unwrap();
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        assert_eq!(pe.normalize().unwrap(), bytes);
+    }
+
+    #[test]
+    fn fix_sizes_and_checksum() {
+        let bytes = PeBuilder::new(MachineType::I386).with_code(vec![0xC3; 0x10], 0).build().unwrap();
+        let mut editor = PeEditor::new(bytes.clone()).unwrap();
+        let optional = editor.image().optional.offset;
+        let text = editor.image().sections.value[0].value.clone();
+
+        //Raw size cut short, with the file cut after it, and broken header sizes.
+        editor.write_u32(text.sizeof_raw_data.offset, 0x10).unwrap();
+        editor.bytes.truncate((text.raw_data_ptr.value + 0x10) as usize);
+        editor.write_u32(optional + SIZEOF_IMAGE_OFFSET, 0x1234).unwrap();
+        editor.write_u32(optional + SIZEOF_HEADERS_OFFSET, 0x10).unwrap();
+        editor.write_u32(optional + CHECKSUM_OFFSET, 0xDEAD).unwrap();
+
+        editor.normalize().unwrap();
+        assert_eq!(editor.into_bytes(), bytes);
+    }
+}
@@ -0,0 +1,120 @@
+//! Replacing the resource tree.
+//!
+//! The tree from `PeEditor::resources` is changed with `ResourceDirectory::insert` and
+//! `remove`, then written back with `set_resources`. The new resource section replaces the
+//! old one if that is the last section and holds nothing else; otherwise the old section
+//! stays, unused, and a new one is appended.
+
+use crate::{pe::{optional::DirectoryType, rsrc::{ResourceDirectory, ResourceNode}, section, PeError, Result}, types::FileOffset};
+
+use super::PeEditor;
+
+/// Name of the section holding the written resource tree.
+pub const RESOURCE_SECTION: &str = ".rsrc";
+
+impl PeEditor {
+    /// The resource tree with the data of every resource loaded.
+    pub fn resources(&self) -> Result<ResourceDirectory> {
+        let mut rsrc = self.pe.resources.value.clone();
+        self.load_resource_data(&mut rsrc)?;
+        Ok(rsrc)
+    }
+
+    fn load_resource_data(&self, dir: &mut ResourceDirectory) -> Result<()> {
+        for entry in &mut dir.entries {
+            match &mut entry.data {
+                ResourceNode::Dir(dir) => self.load_resource_data(dir)?,
+                ResourceNode::Data(data) => {
+                    let rva = data.rva.value;
+                    let offset = self.pe.rva_to_offset(rva).ok_or(PeError::NoSectionForRVA(rva.into()))? as usize;
+                    let bytes = self.bytes.get(offset..offset.saturating_add(data.size.value as usize))
+                        .ok_or(PeError::InvalidOffset(offset as u64))?;
+                    data.value.value = bytes.to_vec();
+                    data.value.offset = FileOffset(offset as u64);
+                    data.value.rva = rva.into();
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the last section holds the resource directory and no other directory.
+    fn resources_in_last_section(&self) -> bool {
+        let Some(last) = self.pe.sections.value.last().map(|s| &s.value) else {
+            return false;
+        };
+        let start = last.virtual_address.value;
+        let end = start.saturating_add(last.virtual_size.value.max(last.sizeof_raw_data.value));
+        let in_last = |dir: DirectoryType| self.pe.directory(dir).is_some_and(|d| (start..end).contains(&d.rva.value));
+
+        let mut others = self.pe.data_dirs.value.iter()
+            .map(|d| d.value.member)
+            .filter(|d| !matches!(d, DirectoryType::Resource | DirectoryType::Security));
+        in_last(DirectoryType::Resource) && !others.any(in_last)
+    }
+
+    /// Write `rsrc` as the resource tree of the image. An empty tree clears the resource
+    /// directory.
+    pub fn set_resources(&mut self, rsrc: &ResourceDirectory) -> Result<()> {
+        if self.resources_in_last_section() {
+            //The image must parse without the section in between.
+            self.set_directory(DirectoryType::Resource, 0, 0)?;
+            self.remove_last_section()?;
+        }
+        if rsrc.entries.is_empty() {
+            self.set_directory(DirectoryType::Resource, 0, 0)?;
+            return self.commit();
+        }
+
+        let rva = self.next_section_rva();
+        let data = rsrc.to_bytes(rva);
+        self.add_section(RESOURCE_SECTION, &data, section::Flags::INITIALIZED_DATA | section::Flags::MEM_READ)?;
+        self.set_directory(DirectoryType::Resource, rva, data.len() as u32)?;
+        self.commit()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::pe::{builder::PeBuilder, edit::PeEditor, file::MachineType, rsrc::ResourceType};
+
+    #[test]
+    fn add_replace_and_remove() {
+        let bytes = PeBuilder::new(MachineType::AMD64).build().unwrap();
+        let mut editor = PeEditor::new(bytes.clone()).unwrap();
+
+        let mut rsrc = editor.resources().unwrap();
+        rsrc.insert(ResourceType::MANIFEST, 1, 0x409, b"<assembly/>".to_vec());
+        rsrc.insert(ResourceType::RC_DATA, "CONFIG", 0, vec![1, 2, 3]);
+        rsrc.insert(ResourceType::RC_DATA, 7, 0, vec![4]);
+        editor.set_resources(&rsrc).unwrap();
+
+        let pe = editor.image();
+        assert_eq!(pe.sections.value.len(), 2);
+        let rc_data = pe.resources.value.entries.iter().map(|e| e.id).collect::<Vec<_>>();
+        assert_eq!(rc_data, vec![Some(10), Some(24)]);
+        let written = editor.resources().unwrap();
+        assert_eq!(written.find_resource(ResourceType::MANIFEST, 1, Some(0x409)).unwrap().value.value, b"<assembly/>");
+        assert_eq!(written.find_resource(ResourceType::RC_DATA, "config", None).unwrap().value.value, vec![1, 2, 3]);
+        assert_eq!(written.find_resource(ResourceType::RC_DATA, 7, None).unwrap().value.value, vec![4]);
+
+        let mut rsrc = written;
+        rsrc.insert(ResourceType::MANIFEST, 1, 0x409, b"<assembly></assembly>".to_vec());
+        assert!(rsrc.remove(ResourceType::RC_DATA, "CONFIG", None));
+        assert!(!rsrc.remove(ResourceType::RC_DATA, "CONFIG", None));
+        editor.set_resources(&rsrc).unwrap();
+
+        let written = editor.resources().unwrap();
+        assert_eq!(editor.image().sections.value.len(), 2);
+        assert_eq!(written.find_resource(ResourceType::MANIFEST, 1, None).unwrap().value.value, b"<assembly></assembly>");
+        assert!(written.find_resource(ResourceType::RC_DATA, "CONFIG", None).is_none());
+
+        let mut rsrc = written;
+        rsrc.remove(ResourceType::MANIFEST, 1, Some(0x409));
+        rsrc.remove(ResourceType::RC_DATA, 7, Some(0));
+        assert!(rsrc.entries.is_empty());
+        editor.set_resources(&rsrc).unwrap();
+        assert_eq!(editor.into_bytes(), bytes);
+    }
+}
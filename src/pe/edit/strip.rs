@@ -0,0 +1,87 @@
+//! Removing build details from an image, for reproducible or cleaned release binaries.
+//!
+//! Data is zeroed where it is, so the layout of the image does not change.
+
+use crate::pe::{optional::DirectoryType, Result};
+
+use super::PeEditor;
+
+impl PeEditor {
+    /// Zero the debug directory, the data of its entries, e.g. the PDB path, and its data
+    /// directory entry. Returns whether the image had a debug directory.
+    pub fn strip_debug_directory(&mut self) -> Result<bool> {
+        let Some(dir) = self.pe.directory(DirectoryType::Debug) else {
+            return Ok(false);
+        };
+        let size = dir.size.value;
+
+        let mut ranges: Vec<(u64, u32)> = self.pe.debug.value.entries.iter()
+            .map(|e| (e.value.pointer_to_raw_data.value.into(), e.value.size_of_data.value))
+            .filter(|(offset, size)| *offset != 0 && *size != 0)
+            .collect();
+        if let Some(first) = self.pe.debug.value.entries.first() {
+            ranges.push((first.offset.into(), size));
+        }
+        for (offset, size) in ranges {
+            let end = (offset as usize).saturating_add(size as usize).min(self.bytes.len());
+            let start = (offset as usize).min(end);
+            self.bytes[start..end].fill(0);
+        }
+
+        self.set_directory(DirectoryType::Debug, 0, 0)?;
+        self.commit()?;
+        Ok(true)
+    }
+
+    /// Zero the rich header. Returns whether the image had one.
+    pub fn strip_rich_header(&mut self) -> Result<bool> {
+        let Some(rich) = self.pe.rich_header()? else {
+            return Ok(false);
+        };
+        self.write_at(rich.offset, &vec![0; rich.raw.len()])?;
+        self.commit()?;
+        Ok(true)
+    }
+
+    /// Zero the timestamps of the image: of the file header, and of export, debug, resource
+    /// and load config directories. Times of imported modules, kept by bound imports, are
+    /// left as they are. Returns the number of timestamps zeroed.
+    pub fn zero_timestamps(&mut self) -> Result<usize> {
+        let offsets: Vec<u64> = self.pe.timestamps().iter()
+            .filter(|t| t.source.is_own() && t.value.raw() != 0)
+            .map(|t| t.offset)
+            .collect();
+        for offset in &offsets {
+            self.write_u32(*offset, 0)?;
+        }
+        self.commit()?;
+        Ok(offsets.len())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::pe::{builder::PeBuilder, edit::PeEditor, file::MachineType, timestamps::TimestampSource};
+
+    #[test]
+    fn zero_file_header_timestamp() {
+        let bytes = PeBuilder::new(MachineType::AMD64).with_timestamp(0x5F00_0000).build().unwrap();
+        let mut editor = PeEditor::new(bytes).unwrap();
+
+        assert_eq!(editor.zero_timestamps().unwrap(), 1);
+        assert_eq!(editor.image().file.value.timestamp.value.raw(), 0);
+        assert_eq!(editor.zero_timestamps().unwrap(), 0);
+        assert!(editor.image().timestamps().iter().all(|t| t.source != TimestampSource::FileHeader || t.value.raw() == 0));
+    }
+
+    #[test]
+    fn nothing_to_strip() {
+        let bytes = PeBuilder::new(MachineType::I386).build().unwrap();
+        let mut editor = PeEditor::new(bytes.clone()).unwrap();
+
+        assert!(!editor.strip_debug_directory().unwrap());
+        assert!(!editor.strip_rich_header().unwrap());
+        assert_eq!(editor.into_bytes(), bytes);
+    }
+}
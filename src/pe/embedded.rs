@@ -0,0 +1,94 @@
+//! Images embedded in larger inputs, like droppers, installers or memory dumps.
+//!
+//! Candidates are found by a DOS header whose `e_lfanew` points to a PE signature, then parsed
+//! in place with `pos` set to their offset.
+
+use std::{io::Cursor, sync::Arc};
+
+use crate::types::BufReadExt;
+
+use super::{check_alloc, limits::ParseLimits, PeImage, Result};
+
+const MAX_CANDIDATES: usize = 256;
+
+/// An image found in the input; `image` is the result of parsing it.
+#[derive(Debug)]
+pub struct EmbeddedPe {
+    pub pos: u64,
+    pub image: Result<PeImage>,
+}
+
+/// Offsets of `MZ` headers whose `e_lfanew` points to `PE\0\0` within `bytes`.
+pub fn find_candidates(bytes: &[u8]) -> Vec<u64> {
+    let mut found = Vec::new();
+    let mut start = 0;
+    while let Some(i) = bytes[start..].windows(2).position(|w| w == b"MZ").map(|i| start + i) {
+        start = i + 1;
+        let Some(lfanew) = bytes.get(i + 0x3c..i + 0x40) else {
+            break;
+        };
+        let lfanew = u32::from_le_bytes([lfanew[0], lfanew[1], lfanew[2], lfanew[3]]) as usize;
+        let nt = i.saturating_add(lfanew);
+        if lfanew >= 0x40 && bytes.get(nt..nt.saturating_add(4)) == Some(b"PE\0\0") {
+            found.push(i as u64);
+            if found.len() == MAX_CANDIDATES {
+                break;
+            }
+        }
+    }
+    found
+}
+
+impl PeImage {
+    /// Find images anywhere in `reader`, including one at offset 0, and parse each of them.
+    /// The input is read whole; its size is checked against the default `max_alloc`.
+    pub fn find_embedded_pes(mut reader: Box<dyn BufReadExt>) -> Result<Vec<EmbeddedPe>> {
+        let size = reader.end_offset()?;
+        check_alloc("embedded image scan", size, ParseLimits::default().max_alloc)?;
+        let bytes: Arc<[u8]> = reader.read_bytes_at_offset(0, size as usize)?.into();
+
+        let found = find_candidates(&bytes).into_iter()
+            .map(|pos| {
+                let image = Self::parse_readable(Box::new(Cursor::new(bytes.clone())), pos);
+                if let Err(err) = &image {
+                    tracing::warn!(pos, err = err.chain(), "failed to parse embedded image");
+                }
+                EmbeddedPe { pos, image }
+            })
+            .collect();
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::Cursor};
+
+    use crate::types::FileOffset;
+    use crate::pe::{authenticode::HashAlgorithm, PeImage};
+
+    use super::find_candidates;
+
+    #[test]
+    fn find_appended_image() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let dll = fs::read(path).unwrap();
+        let mut expected = PeImage::parse_bytes(dll.clone(), 0).unwrap();
+
+        let mut bytes = dll.clone();
+        bytes.extend([0xccu8; 0x1234]);
+        bytes.extend(&dll);
+        let pos = (dll.len() + 0x1234) as u64;
+        assert!(find_candidates(&bytes).contains(&pos));
+
+        let found = PeImage::find_embedded_pes(Box::new(Cursor::new(bytes))).unwrap();
+        let embedded = found.into_iter().find(|e| e.pos == pos).unwrap();
+        let mut pe = embedded.image.unwrap();
+        assert_eq!(pe.pos, pos);
+        assert_eq!(pe.dos.offset, FileOffset(0));
+        assert_eq!(pe.imports.value.len(), expected.imports.value.len());
+        assert_eq!(pe.exports.value.exports.len(), expected.exports.value.exports.len());
+        assert_eq!(pe.authentihash(HashAlgorithm::Sha256).unwrap(), expected.authentihash(HashAlgorithm::Sha256).unwrap());
+        assert_eq!(pe.signers().unwrap().len(), 1);
+    }
+}
@@ -1,13 +1,12 @@
-use std::{fmt::Display, io::{Error, Cursor}, mem::size_of};
+use std::{fmt::Display, io::Cursor, mem::size_of};
 
 use byteorder::{ReadBytesExt, LittleEndian};
-use chrono::{DateTime, Utc};
 
-use crate::{new_header_field, types::{Header, HeaderField, BufReadExt}};
+use crate::{new_header_field, types::{BufReadExt, FileOffset, Header, HeaderField, Rva}};
 
-use super::{section::{self, offset_to_rva, SectionTable}, PeError};
+use super::{check_alloc, section::{self, offset_to_rva, IndexedSections, SectionLookup, SectionTable}, PeError, PeImage, PeTimestamp};
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Export {
     pub name: HeaderField<String>,
     pub address: HeaderField<u32>,
@@ -24,10 +23,10 @@ impl Display for Export {
 
 pub const HEADER_LENGTH: u64 = 40;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct ExportDirectory {
     pub charatristics: HeaderField<u32>,
-    pub timestamp: HeaderField<DateTime<Utc>>,
+    pub timestamp: HeaderField<PeTimestamp>,
     pub major_version: HeaderField<u16>,
     pub minor_version: HeaderField<u16>,
     pub name_rva: HeaderField<u32>,
@@ -46,7 +45,7 @@ impl ExportDirectory {
         Default::default()
     }
 
-    pub fn parse_exports(&mut self, sections: &SectionTable, reader: &mut impl BufReadExt) -> crate::Result<()> {
+    pub fn parse_exports(&mut self, sections: &impl SectionLookup, reader: &mut impl BufReadExt) -> crate::pe::Result<()> {
         let mut offset = section::rva_to_offset(sections, self.name_rva.value)
             .ok_or(PeError::InvalidRVA(self.name_rva.value.into()))?;
         self.name = reader.read_string_at_offset(offset.into())?;
@@ -83,15 +82,15 @@ impl ExportDirectory {
             let mut offset = (i as usize * size_of::<u32>()) as u64;
             export.address = HeaderField {
                 value: fn_cursor.read_u32::<LittleEndian>()?, 
-                rva: self.address_of_functions.value as u64 + offset,
-                offset: fn_offset as u64 + offset,
+                rva: Rva(self.address_of_functions.value.wrapping_add(offset as u32)),
+                offset: FileOffset::from(fn_offset) + offset,
             };
 
             offset = (i as usize * size_of::<u16>()) as u64;
             export.ordinal = HeaderField {
                 value: ord_cursor.read_u16::<LittleEndian>()?,
-                rva: self.address_of_name_ordinals.value as u64 + offset,
-                offset: ord_offset as u64 + offset,
+                rva: Rva(self.address_of_name_ordinals.value.wrapping_add(offset as u32)),
+                offset: FileOffset::from(ord_offset) + offset,
             };
 
             self.exports.push(export);
@@ -100,20 +99,20 @@ impl ExportDirectory {
         if self.number_of_functions.value > self.number_of_names.value {
             for i in 0..self.number_of_names.value {
                 let mut export = Export::default();
-                export.name = HeaderField{ value: "NO_NAME".to_string(), rva:0, offset:0 };
+                export.name = HeaderField{ value: "NO_NAME".to_string(), rva: Rva(0), offset: FileOffset(0) };
     
                 let mut offset = (i as usize * size_of::<u32>()) as u64;
                 export.address = HeaderField {
                     value: fn_cursor.read_u32::<LittleEndian>()?, 
-                    rva: self.address_of_functions.value as u64 + offset,
-                    offset: fn_offset as u64 + offset,
+                    rva: Rva(self.address_of_functions.value.wrapping_add(offset as u32)),
+                    offset: FileOffset::from(fn_offset) + offset,
                 };
     
                 offset = (i as usize * size_of::<u16>()) as u64;
                 export.ordinal = HeaderField {
                     value: ord_cursor.read_u16::<LittleEndian>()?,
-                    rva: self.address_of_name_ordinals.value as u64 + offset,
-                    offset: ord_offset as u64 + offset,
+                    rva: Rva(self.address_of_name_ordinals.value.wrapping_add(offset as u32)),
+                    offset: FileOffset::from(ord_offset) + offset,
                 };
     
                 self.exports.push(export);
@@ -123,48 +122,149 @@ impl ExportDirectory {
         Ok(())
     }
 
-    pub fn fix_rvas(&mut self, sections: &SectionTable) -> crate::Result<()> {
-        self.charatristics.rva = offset_to_rva(sections, self.charatristics.offset as u32)
+    /// Number of exported functions, named or not, known from the directory header alone.
+    #[inline]
+    pub fn export_count(&self) -> u32 {
+        self.number_of_functions.value
+    }
+
+    /// Exports at function table indexes `start..start + count`, in function table order.
+    /// Only the ordinal table and the names of returned exports are read, so a large export
+    /// table can be walked one page at a time. Exports without a name are named `NO_NAME`.
+    pub fn read_exports_page(&self, sections: &impl SectionLookup, reader: &mut impl BufReadExt, start: u32, count: u32) -> crate::pe::Result<Vec<Export>> {
+        if start >= self.export_count() || count == 0 {
+            return Ok(Vec::new());
+        }
+        let ordinals = self.read_name_ordinals(sections, reader)?;
+        self.read_exports_page_with(sections, reader, start, count, &ordinals)
+    }
+
+    /// Ordinal table: index in the function table of each name.
+    pub(crate) fn read_name_ordinals(&self, sections: &impl SectionLookup, reader: &mut impl BufReadExt) -> crate::pe::Result<Vec<u16>> {
+        if self.number_of_names.value == 0 {
+            return Ok(Vec::new());
+        }
+        let ord_offset = section::rva_to_offset(sections, self.address_of_name_ordinals.value)
+            .ok_or(PeError::InvalidRVA(self.address_of_name_ordinals.value.into()))?;
+        let ordinal_table = reader.read_bytes_at_offset(ord_offset.into(),
+            self.number_of_names.value as usize * size_of::<u16>())?;
+        Ok(ordinal_table.chunks_exact(2).map(|ord| u16::from_le_bytes([ord[0], ord[1]])).collect())
+    }
+
+    /// Same as `read_exports_page`, with the ordinal table read by `read_name_ordinals`.
+    pub(crate) fn read_exports_page_with(&self, sections: &impl SectionLookup, reader: &mut impl BufReadExt, start: u32, count: u32, ordinals: &[u16]) -> crate::pe::Result<Vec<Export>> {
+        let end = start.saturating_add(count).min(self.export_count());
+        if start >= end {
+            return Ok(Vec::new());
+        }
+        let count = end - start;
+
+        let fn_offset = section::rva_to_offset(sections, self.address_of_functions.value)
+            .ok_or(PeError::InvalidRVA(self.address_of_functions.value.into()))?;
+        let page_offset = fn_offset as u64 + start as u64 * size_of::<u32>() as u64;
+        let function_table = reader.read_bytes_at_offset(page_offset, count as usize * size_of::<u32>())?;
+
+        //Index in name table for each function in page.
+        let mut name_index = vec![None; count as usize];
+        let mut ord_offset = 0;
+        let mut names_offset = 0;
+        if self.number_of_names.value > 0 {
+            ord_offset = section::rva_to_offset(sections, self.address_of_name_ordinals.value)
+                .ok_or(PeError::InvalidRVA(self.address_of_name_ordinals.value.into()))?;
+            names_offset = section::rva_to_offset(sections, self.address_of_names.value)
+                .ok_or(PeError::InvalidRVA(self.address_of_names.value.into()))?;
+
+            for (i, ord) in ordinals.iter().enumerate() {
+                let ord = *ord as u32;
+                if (start..end).contains(&ord) {
+                    name_index[(ord - start) as usize] = Some(i as u32);
+                }
+            }
+        }
+
+        let mut exports = Vec::with_capacity(count as usize);
+        let mut fn_cursor = Cursor::new(function_table);
+
+        for (i, name_idx) in name_index.into_iter().enumerate() {
+            let mut export = Export::default();
+            let index = start + i as u32;
+            let offset = index as u64 * size_of::<u32>() as u64;
+            export.address = HeaderField {
+                value: fn_cursor.read_u32::<LittleEndian>()?,
+                rva: Rva(self.address_of_functions.value.wrapping_add(offset as u32)),
+                offset: FileOffset::from(fn_offset) + offset,
+            };
+
+            if let Some(k) = name_idx {
+                let name_ptr = names_offset as u64 + k as u64 * size_of::<u32>() as u64;
+                let name_rva = u32::from_le_bytes(reader.read_bytes_at_offset(name_ptr, size_of::<u32>())?
+                    .try_into()
+                    .map_err(|_| PeError::InvalidOffset(name_ptr))?);
+                let name_offset = section::rva_to_offset(sections, name_rva)
+                    .ok_or(PeError::InvalidRVA(name_rva.into()))?;
+                let name = reader.read_string_at_offset(name_offset.into())?;
+                export.name = HeaderField{ value: name, rva: name_rva.into(), offset: name_offset.into() };
+
+                let offset = k as u64 * size_of::<u16>() as u64;
+                export.ordinal = HeaderField {
+                    value: index as u16,
+                    rva: Rva(self.address_of_name_ordinals.value.wrapping_add(offset as u32)),
+                    offset: FileOffset::from(ord_offset) + offset,
+                };
+            }
+            else {
+                export.name = HeaderField{ value: "NO_NAME".to_string(), rva: Rva(0), offset: FileOffset(0) };
+                export.ordinal = HeaderField { value: index as u16, rva: Rva(0), offset: FileOffset(0) };
+            }
+
+            exports.push(export);
+        }
+
+        Ok(exports)
+    }
+
+    pub fn fix_rvas(&mut self, sections: &SectionTable) -> crate::pe::Result<()> {
+        self.charatristics.rva = offset_to_rva(sections, self.charatristics.offset)
             .ok_or(PeError::InvalidOffset(self.charatristics.offset.into()))?
             .into();
         
-        self.timestamp.rva = offset_to_rva(sections, self.timestamp.offset as u32)
+        self.timestamp.rva = offset_to_rva(sections, self.timestamp.offset)
             .ok_or(PeError::InvalidOffset(self.timestamp.offset.into()))?
             .into();
 
-        self.major_version.rva = offset_to_rva(sections, self.major_version.offset as u32)
+        self.major_version.rva = offset_to_rva(sections, self.major_version.offset)
             .ok_or(PeError::InvalidOffset(self.major_version.offset.into()))?
             .into();
         
-        self.minor_version.rva = offset_to_rva(sections, self.minor_version.offset as u32)
+        self.minor_version.rva = offset_to_rva(sections, self.minor_version.offset)
             .ok_or(PeError::InvalidOffset(self.minor_version.offset.into()))?
             .into();
         
-        self.name_rva.rva = offset_to_rva(sections, self.name_rva.offset as u32)
+        self.name_rva.rva = offset_to_rva(sections, self.name_rva.offset)
             .ok_or(PeError::InvalidOffset(self.name_rva.offset.into()))?
             .into();
         
-        self.base.rva = offset_to_rva(sections, self.base.offset as u32)
+        self.base.rva = offset_to_rva(sections, self.base.offset)
             .ok_or(PeError::InvalidOffset(self.base.offset.into()))?
             .into();
 
-        self.number_of_functions.rva = offset_to_rva(sections, self.number_of_functions.offset as u32)
+        self.number_of_functions.rva = offset_to_rva(sections, self.number_of_functions.offset)
             .ok_or(PeError::InvalidOffset(self.number_of_functions.offset.into()))?
             .into();
         
-        self.number_of_names.rva = offset_to_rva(sections, self.number_of_names.offset as u32)
+        self.number_of_names.rva = offset_to_rva(sections, self.number_of_names.offset)
             .ok_or(PeError::InvalidOffset(self.number_of_names.offset.into()))?
             .into();
 
-        self.address_of_functions.rva = offset_to_rva(sections, self.address_of_functions.offset as u32)
+        self.address_of_functions.rva = offset_to_rva(sections, self.address_of_functions.offset)
             .ok_or(PeError::InvalidOffset(self.address_of_functions.offset.into()))?
             .into();
 
-        self.address_of_names.rva = offset_to_rva(sections, self.address_of_names.offset as u32)
+        self.address_of_names.rva = offset_to_rva(sections, self.address_of_names.offset)
             .ok_or(PeError::InvalidOffset(self.address_of_names.offset.into()))?
             .into();
 
-        self.address_of_name_ordinals.rva = offset_to_rva(sections, self.address_of_name_ordinals.offset as u32)
+        self.address_of_name_ordinals.rva = offset_to_rva(sections, self.address_of_name_ordinals.offset)
             .ok_or(PeError::InvalidOffset(self.address_of_name_ordinals.offset.into()))?
             .into();
 
@@ -174,16 +274,238 @@ impl ExportDirectory {
 }
 
 
+/// Iterator over exports of a `PeImage` one page at a time. Created by `PeImage::export_pages`.
+/// The directory and ordinal table are read once, with the first page.
+pub struct ExportPages<'a> {
+    pe: &'a mut PeImage,
+    page_size: u32,
+    next: u32,
+    total: Option<u32>,
+    /// Directory read for the pages if exports are not parsed.
+    dir: Option<ExportDirectory>,
+    ordinals: Vec<u16>,
+}
+
+impl<'a> ExportPages<'a> {
+    pub(crate) fn new(pe: &'a mut PeImage, page_size: u32) -> Self {
+        Self { pe, page_size: page_size.max(1), next: 0, total: None, dir: None, ordinals: Vec::new() }
+    }
+
+    /// Read what all pages need and return the number of exports.
+    fn load(&mut self) -> crate::pe::Result<u32> {
+        if !self.pe.exports.value.is_valid() {
+            self.dir = self.pe.read_export_directory()?;
+        }
+        let pe = &mut *self.pe;
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None if pe.exports.value.is_valid() => &pe.exports.value,
+            None => return Ok(0),
+        };
+        check_alloc("Export ordinals", dir.number_of_names.value as u64 * size_of::<u16>() as u64, pe.limits.max_alloc)?;
+        let sections = IndexedSections::new(&pe.sections.value, &pe.section_map);
+        self.ordinals = dir.read_name_ordinals(&sections, &mut pe.reader)?;
+        Ok(dir.export_count())
+    }
+
+    fn page(&mut self) -> crate::pe::Result<Vec<Export>> {
+        let pe = &mut *self.pe;
+        check_alloc("Export page", self.page_size as u64 * 4, pe.limits.max_alloc)?;
+        let dir = self.dir.as_ref().unwrap_or(&pe.exports.value);
+        let sections = IndexedSections::new(&pe.sections.value, &pe.section_map);
+        dir.read_exports_page_with(&sections, &mut pe.reader, self.next, self.page_size, &self.ordinals)
+    }
+}
+
+impl Iterator for ExportPages<'_> {
+    type Item = crate::pe::Result<Vec<Export>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let total = match self.total {
+            Some(total) => total,
+            None => match self.load() {
+                Ok(total) => *self.total.insert(total),
+                Err(err) => {
+                    self.total = Some(0);
+                    return Some(Err(err));
+                }
+            },
+        };
+
+        if self.next >= total {
+            return None;
+        }
+
+        let page = self.page();
+        self.next = match page {
+            Ok(_) => self.next.saturating_add(self.page_size),
+            Err(_) => total, //stop after an error
+        };
+        Some(page)
+    }
+}
+
+
+/// File offsets and sizes of the export tables, read once by `ExportStream`.
+struct ExportTables {
+    number_of_functions: u32,
+    number_of_names: u32,
+    functions_rva: u32,
+    ordinals_rva: u32,
+    functions_offset: u64,
+    names_offset: u64,
+    ordinals_offset: u64,
+}
+
+/// Iterator over exports of a `PeImage`, read one at a time. Created by `PeImage::exports_stream`.
+/// Named exports come first, in name table order, followed by exports without a name.
+/// Each named export costs only a few small reads, so stopping early skips the rest of the table.
+pub struct ExportStream<'a> {
+    pe: &'a mut PeImage,
+    tables: Option<ExportTables>,
+    next: u32,
+    /// Function indexes without a name; found only after all named exports are read.
+    unnamed: Option<Vec<u32>>,
+    done: bool,
+}
+
+fn read_u32_at(reader: &mut impl BufReadExt, offset: u64) -> crate::pe::Result<u32> {
+    let bytes = reader.read_bytes_at_offset(offset, size_of::<u32>())?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u16_at(reader: &mut impl BufReadExt, offset: u64) -> crate::pe::Result<u16> {
+    let bytes = reader.read_bytes_at_offset(offset, size_of::<u16>())?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+impl<'a> ExportStream<'a> {
+    pub(crate) fn new(pe: &'a mut PeImage) -> Self {
+        Self { pe, tables: None, next: 0, unnamed: None, done: false }
+    }
+
+    fn tables(&mut self) -> crate::pe::Result<Option<&ExportTables>> {
+        if self.tables.is_none() {
+            let parsed;
+            let dir = if self.pe.exports.value.is_valid() {
+                &self.pe.exports.value
+            }
+            else {
+                match self.pe.read_export_directory()? {
+                    Some(dir) => { parsed = dir; &parsed },
+                    None => return Ok(None),
+                }
+            };
+
+            let sections = self.pe.section_lookup();
+            let offset_of = |rva: u32| sections.rva_to_offset(rva).map(u64::from).ok_or(PeError::InvalidRVA(rva.into()));
+            let has_names = dir.number_of_names.value > 0;
+
+            self.tables = Some(ExportTables {
+                number_of_functions: dir.number_of_functions.value,
+                number_of_names: dir.number_of_names.value,
+                functions_rva: dir.address_of_functions.value,
+                ordinals_rva: dir.address_of_name_ordinals.value,
+                functions_offset: if dir.number_of_functions.value > 0 { offset_of(dir.address_of_functions.value)? } else { 0 },
+                names_offset: if has_names { offset_of(dir.address_of_names.value)? } else { 0 },
+                ordinals_offset: if has_names { offset_of(dir.address_of_name_ordinals.value)? } else { 0 },
+            });
+        }
+        Ok(self.tables.as_ref())
+    }
+
+    fn address(&mut self, index: u32) -> crate::pe::Result<HeaderField<u32>> {
+        let Some(tables) = self.tables.as_ref() else { return Ok(Default::default()) };
+        let delta = index as u64 * size_of::<u32>() as u64;
+        let offset = tables.functions_offset + delta;
+        let rva = Rva(tables.functions_rva.wrapping_add(delta as u32));
+        Ok(HeaderField { value: read_u32_at(&mut self.pe.reader, offset)?, offset: FileOffset(offset), rva })
+    }
+
+    fn read_next(&mut self) -> crate::pe::Result<Option<Export>> {
+        let Some(tables) = self.tables()? else {
+            return Ok(None);
+        };
+        let (number_of_functions, number_of_names) = (tables.number_of_functions, tables.number_of_names);
+        let names_offset = tables.names_offset;
+        let (ordinals_offset, ordinals_rva) = (tables.ordinals_offset, tables.ordinals_rva);
+
+        if self.next < number_of_names {
+            let i = self.next as u64;
+            let name_rva = read_u32_at(&mut self.pe.reader, names_offset + i * size_of::<u32>() as u64)?;
+            let name_offset = self.pe.section_lookup().rva_to_offset(name_rva)
+                .ok_or(PeError::InvalidRVA(name_rva.into()))?;
+            let name = self.pe.reader.read_string_at_offset(name_offset.into())?;
+
+            let ordinal_offset = ordinals_offset + i * size_of::<u16>() as u64;
+            let index = read_u16_at(&mut self.pe.reader, ordinal_offset)?;
+            if index as u32 >= number_of_functions {
+                return Err(PeError::InvalidHeader {
+                    name: "Export".into(),
+                    offset: ordinal_offset,
+                    reason: format!("ordinal {index} is beyond {number_of_functions} functions"),
+                });
+            }
+
+            self.next += 1;
+            return Ok(Some(Export {
+                name: HeaderField { value: name, offset: name_offset.into(), rva: name_rva.into() },
+                address: self.address(index.into())?,
+                ordinal: HeaderField { value: index, offset: FileOffset(ordinal_offset), rva: Rva(ordinals_rva.wrapping_add(i as u32 * size_of::<u16>() as u32)) },
+            }));
+        }
+
+        if self.unnamed.is_none() {
+            let mut named = vec![false; number_of_functions as usize];
+            if number_of_names > 0 {
+                check_alloc("Export ordinals", number_of_names as u64 * 2, self.pe.limits.max_alloc)?;
+                let ordinals = self.pe.reader.read_bytes_at_offset(ordinals_offset, number_of_names as usize * size_of::<u16>())?;
+                for ord in ordinals.chunks_exact(2) {
+                    if let Some(n) = named.get_mut(u16::from_le_bytes([ord[0], ord[1]]) as usize) {
+                        *n = true;
+                    }
+                }
+            }
+            //Reversed to pop from the end in function order.
+            self.unnamed = Some((0..number_of_functions).rev().filter(|&i| !named[i as usize]).collect());
+        }
+
+        let Some(index) = self.unnamed.as_mut().and_then(|u| u.pop()) else {
+            return Ok(None);
+        };
+
+        Ok(Some(Export {
+            name: HeaderField::at_offset("NO_NAME".to_string(), 0),
+            address: self.address(index)?,
+            ordinal: HeaderField::at_offset(index as u16, 0),
+        }))
+    }
+}
+
+impl Iterator for ExportStream<'_> {
+    type Item = crate::pe::Result<Export>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let next = self.read_next();
+        if !matches!(next, Ok(Some(_))) {
+            self.done = true; //stop at end or after an error
+        }
+        next.transpose()
+    }
+}
+
+
 impl Header for ExportDirectory {
-    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> where Self: Sized {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> where Self: Sized {
         let bytes_len = bytes.len() as u64;
 
         if bytes_len < HEADER_LENGTH {
             return Err(
-                Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("Not enough data. Expected {HEADER_LENGTH}, Found {bytes_len}")
-                ).into()
+                PeError::BufferTooSmall { target: "ExportDirectory".into(), expected: HEADER_LENGTH, actual: bytes_len }
             );
         }
 
@@ -194,8 +516,7 @@ impl Header for ExportDirectory {
         exdir.charatristics = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
         
         let dt = cursor.read_u32::<LittleEndian>()?;
-        let ts = DateTime::<Utc>::from_timestamp(dt.into(), 0).ok_or(PeError::InvalidTimestamp(dt.into()))?; //TODO: map to header specific error?
-        exdir.timestamp = HeaderField{ value: ts, rva: offset, offset };
+        exdir.timestamp = HeaderField::at_offset(PeTimestamp::from(dt), offset);
         offset += size_of::<u32>() as u64;
 
         exdir.major_version = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
@@ -223,7 +544,9 @@ impl Header for ExportDirectory {
 
 #[cfg(test)]
 mod tests {
-    use crate::{pe::section::{parse_sections, SectionTable}, types::{Header, HeaderField}, utils::FragmentReader};
+    use std::{env, fs, io::Cursor};
+
+    use crate::{pe::{section::{parse_sections, SectionTable}, PeImage}, types::{FileOffset, Header, HeaderField, Rva}, utils::FragmentReader};
 
     use super::{ExportDirectory, Export};
 
@@ -234,7 +557,7 @@ mod tests {
         let ed = ExportDirectory::parse_bytes(raw_export_data.to_vec(), 0x3A00).unwrap();
         
         assert_eq!(ed.charatristics.value, 0);
-        assert_eq!(ed.timestamp.value.format("%Y-%m-%d %H:%M:%S").to_string(), "2018-01-12 10:16:01");
+        assert_eq!(ed.timestamp.value.as_datetime().unwrap().format("%Y-%m-%d %H:%M:%S").to_string(), "2018-01-12 10:16:01");
         assert_eq!(ed.major_version.value, 0);
         assert_eq!(ed.minor_version.value, 0);
         assert_eq!(ed.name_rva.value, 0x000090b4);
@@ -253,104 +576,104 @@ mod tests {
         let mut ed = ExportDirectory::parse_bytes(raw_export_data.to_vec(), 0x3A00).unwrap();
         ed.fix_rvas(&sections).unwrap();
 
-        assert_eq!(ed.charatristics.rva, 0x00009000);
-        assert_eq!(ed.timestamp.rva, 0x00009004);
-        assert_eq!(ed.major_version.rva, 0x00009008);
-        assert_eq!(ed.minor_version.rva, 0x0000900a);
-        assert_eq!(ed.name_rva.rva, 0x0000900c);
-        assert_eq!(ed.base.rva, 0x00009010);
-        assert_eq!(ed.number_of_functions.rva, 0x00009014);
-        assert_eq!(ed.number_of_names.rva, 0x00009018);
-        assert_eq!(ed.address_of_functions.rva, 0x0000901c);
-        assert_eq!(ed.address_of_names.rva, 0x00009020);
-        assert_eq!(ed.address_of_name_ordinals.rva, 0x00009024);
+        assert_eq!(ed.charatristics.rva, Rva(0x00009000));
+        assert_eq!(ed.timestamp.rva, Rva(0x00009004));
+        assert_eq!(ed.major_version.rva, Rva(0x00009008));
+        assert_eq!(ed.minor_version.rva, Rva(0x0000900a));
+        assert_eq!(ed.name_rva.rva, Rva(0x0000900c));
+        assert_eq!(ed.base.rva, Rva(0x00009010));
+        assert_eq!(ed.number_of_functions.rva, Rva(0x00009014));
+        assert_eq!(ed.number_of_names.rva, Rva(0x00009018));
+        assert_eq!(ed.address_of_functions.rva, Rva(0x0000901c));
+        assert_eq!(ed.address_of_names.rva, Rva(0x00009020));
+        assert_eq!(ed.address_of_name_ordinals.rva, Rva(0x00009024));
     }
 
     #[test]
     fn parse_exports() {
         let exported_names = [
             Export {
-                name: HeaderField { value: "__chk_fail".to_string(), offset: 0x3ac1, rva: 0x90c1 },
-                address: HeaderField { value: 0x14b0, offset: 0x3a28, rva:0x9028 },
-                ordinal: HeaderField { value: 0, offset: 0x3a98, rva: 0x9098 },
+                name: HeaderField { value: "__chk_fail".to_string(), offset: FileOffset(0x3ac1), rva: Rva(0x90c1) },
+                address: HeaderField { value: 0x14b0, offset: FileOffset(0x3a28), rva:Rva(0x9028) },
+                ordinal: HeaderField { value: 0, offset: FileOffset(0x3a98), rva: Rva(0x9098) },
             },
 
             Export {
-                name: HeaderField { value: "__gets_chk".to_string(), offset: 0x3acc, rva: 0x90cc },
-                address: HeaderField { value: 0x14e0, offset: 0x3a2c, rva: 0x902c },
-                ordinal: HeaderField { value: 1, offset: 0x3a9a, rva: 0x909a },
+                name: HeaderField { value: "__gets_chk".to_string(), offset: FileOffset(0x3acc), rva: Rva(0x90cc) },
+                address: HeaderField { value: 0x14e0, offset: FileOffset(0x3a2c), rva: Rva(0x902c) },
+                ordinal: HeaderField { value: 1, offset: FileOffset(0x3a9a), rva: Rva(0x909a) },
             },
 
             Export {
-                name: HeaderField { value: "__memcpy_chk".to_string(), offset: 0x3ad7, rva: 0x90d7 },
-                address: HeaderField { value: 0x1610, offset: 0x3a30, rva: 0x9030 },
-                ordinal: HeaderField { value: 2, offset: 0x3a9c, rva: 0x909c },
+                name: HeaderField { value: "__memcpy_chk".to_string(), offset: FileOffset(0x3ad7), rva: Rva(0x90d7) },
+                address: HeaderField { value: 0x1610, offset: FileOffset(0x3a30), rva: Rva(0x9030) },
+                ordinal: HeaderField { value: 2, offset: FileOffset(0x3a9c), rva: Rva(0x909c) },
             },
 
             Export {
-                name: HeaderField { value: "__memmove_chk".to_string(), offset: 0x3ae4, rva: 0x90e4 },
-                address: HeaderField { value: 0x1630, offset: 0x3a34, rva: 0x9034 },
-                ordinal: HeaderField { value: 3, offset: 0x3a9e, rva: 0x909e },
+                name: HeaderField { value: "__memmove_chk".to_string(), offset: FileOffset(0x3ae4), rva: Rva(0x90e4) },
+                address: HeaderField { value: 0x1630, offset: FileOffset(0x3a34), rva: Rva(0x9034) },
+                ordinal: HeaderField { value: 3, offset: FileOffset(0x3a9e), rva: Rva(0x909e) },
             },
            
             Export {
-                name: HeaderField { value: "__mempcpy_chk".to_string(), offset: 0x3af2, rva: 0x90f2 },
-                address: HeaderField { value: 0x1650, offset: 0x3a38, rva: 0x9038 },
-                ordinal: HeaderField { value: 4, offset: 0x3aa0, rva: 0x90a0 },
+                name: HeaderField { value: "__mempcpy_chk".to_string(), offset: FileOffset(0x3af2), rva: Rva(0x90f2) },
+                address: HeaderField { value: 0x1650, offset: FileOffset(0x3a38), rva: Rva(0x9038) },
+                ordinal: HeaderField { value: 4, offset: FileOffset(0x3aa0), rva: Rva(0x90a0) },
             },
            
             Export {
-                name: HeaderField { value: "__memset_chk".to_string(), offset: 0x3b00, rva: 0x9100 },
-                address: HeaderField { value: 0x1680, offset: 0x3a3c, rva: 0x903c },
-                ordinal: HeaderField { value: 5, offset: 0x3aa2, rva: 0x90a2 },
+                name: HeaderField { value: "__memset_chk".to_string(), offset: FileOffset(0x3b00), rva: Rva(0x9100) },
+                address: HeaderField { value: 0x1680, offset: FileOffset(0x3a3c), rva: Rva(0x903c) },
+                ordinal: HeaderField { value: 5, offset: FileOffset(0x3aa2), rva: Rva(0x90a2) },
             },
 
             Export {
-                name: HeaderField { value: "__stack_chk_fail".to_string(), offset: 0x3b0d, rva: 0x910d },
-                address: HeaderField { value: 0x1490, offset: 0x3a40, rva: 0x9040 },
-                ordinal: HeaderField { value: 6, offset: 0x3aa4, rva: 0x90a4 },
+                name: HeaderField { value: "__stack_chk_fail".to_string(), offset: FileOffset(0x3b0d), rva: Rva(0x910d) },
+                address: HeaderField { value: 0x1490, offset: FileOffset(0x3a40), rva: Rva(0x9040) },
+                ordinal: HeaderField { value: 6, offset: FileOffset(0x3aa4), rva: Rva(0x90a4) },
             },
 
             Export {
-                name: HeaderField { value: "__stack_chk_fail_local".to_string(), offset: 0x3b1e, rva: 0x911e },
-                address: HeaderField { value: 0x14d0, offset: 0x3a44, rva: 0x9044 },
-                ordinal: HeaderField { value: 7, offset: 0x3aa6, rva: 0x90a6 },
+                name: HeaderField { value: "__stack_chk_fail_local".to_string(), offset: FileOffset(0x3b1e), rva: Rva(0x911e) },
+                address: HeaderField { value: 0x14d0, offset: FileOffset(0x3a44), rva: Rva(0x9044) },
+                ordinal: HeaderField { value: 7, offset: FileOffset(0x3aa6), rva: Rva(0x90a6) },
             },
 
             Export {
-                name: HeaderField { value: "__stack_chk_guard".to_string(), offset: 0x3b35, rva: 0x9135 },
-                address: HeaderField { value: 0x8020, offset: 0x3a48, rva: 0x9048 },
-                ordinal: HeaderField { value: 8, offset: 0x3aa8, rva: 0x90a8 },
+                name: HeaderField { value: "__stack_chk_guard".to_string(), offset: FileOffset(0x3b35), rva: Rva(0x9135) },
+                address: HeaderField { value: 0x8020, offset: FileOffset(0x3a48), rva: Rva(0x9048) },
+                ordinal: HeaderField { value: 8, offset: FileOffset(0x3aa8), rva: Rva(0x90a8) },
             },
 
             Export {
-                name: HeaderField { value: "__stpcpy_chk".to_string(), offset: 0x3b47, rva: 0x9147 },
-                address: HeaderField { value: 0x16a0, offset: 0x3a4c, rva: 0x904c },
-                ordinal: HeaderField { value: 9, offset: 0x3aaa, rva: 0x90aa },
+                name: HeaderField { value: "__stpcpy_chk".to_string(), offset: FileOffset(0x3b47), rva: Rva(0x9147) },
+                address: HeaderField { value: 0x16a0, offset: FileOffset(0x3a4c), rva: Rva(0x904c) },
+                ordinal: HeaderField { value: 9, offset: FileOffset(0x3aaa), rva: Rva(0x90aa) },
             },
 
             Export {
-                name: HeaderField { value: "__strcat_chk".to_string(), offset: 0x3b54, rva: 0x9154 },
-                address: HeaderField { value: 0x16f0, offset: 0x3a50, rva: 0x9050 },
-                ordinal: HeaderField { value: 10, offset: 0x3aac, rva: 0x90ac },
+                name: HeaderField { value: "__strcat_chk".to_string(), offset: FileOffset(0x3b54), rva: Rva(0x9154) },
+                address: HeaderField { value: 0x16f0, offset: FileOffset(0x3a50), rva: Rva(0x9050) },
+                ordinal: HeaderField { value: 10, offset: FileOffset(0x3aac), rva: Rva(0x90ac) },
             },
 
             Export {
-                name: HeaderField { value: "__strcpy_chk".to_string(), offset: 0x3b61, rva: 0x9161 },
-                address: HeaderField { value: 0x1750, offset: 0x3a54, rva: 0x9054 },
-                ordinal: HeaderField { value: 11, offset: 0x3aae, rva: 0x90ae },
+                name: HeaderField { value: "__strcpy_chk".to_string(), offset: FileOffset(0x3b61), rva: Rva(0x9161) },
+                address: HeaderField { value: 0x1750, offset: FileOffset(0x3a54), rva: Rva(0x9054) },
+                ordinal: HeaderField { value: 11, offset: FileOffset(0x3aae), rva: Rva(0x90ae) },
             },
 
             Export {
-                name: HeaderField { value: "__strncat_chk".to_string(), offset: 0x3b6e, rva: 0x916e },
-                address: HeaderField { value: 0x1790, offset: 0x3a58, rva: 0x9058 },
-                ordinal: HeaderField { value: 12, offset: 0x3ab0, rva: 0x90b0 },
+                name: HeaderField { value: "__strncat_chk".to_string(), offset: FileOffset(0x3b6e), rva: Rva(0x916e) },
+                address: HeaderField { value: 0x1790, offset: FileOffset(0x3a58), rva: Rva(0x9058) },
+                ordinal: HeaderField { value: 12, offset: FileOffset(0x3ab0), rva: Rva(0x90b0) },
             },
 
             Export {
-                name: HeaderField { value: "__strncpy_chk".to_string(), offset: 0x3b7c, rva: 0x917c },
-                address: HeaderField { value: 0x18d0, offset: 0x3a5c, rva: 0x905c },
-                ordinal: HeaderField { value: 13, offset: 0x3ab2, rva: 0x90b2 },
+                name: HeaderField { value: "__strncpy_chk".to_string(), offset: FileOffset(0x3b7c), rva: Rva(0x917c) },
+                address: HeaderField { value: 0x18d0, offset: FileOffset(0x3a5c), rva: Rva(0x905c) },
+                ordinal: HeaderField { value: 13, offset: FileOffset(0x3ab2), rva: Rva(0x90b2) },
             },
         ];
 
@@ -388,6 +711,41 @@ mod tests {
 
     }
 
+    #[test]
+    fn read_exports_page() {
+        let sections = parse_section_header();
+        let mut reader = FragmentReader::new(EXPORTS_RAW.to_vec(), 0x3A00);
+        let mut ed = ExportDirectory::parse_bytes(EXPORTS_RAW[0..40].to_vec(), 0x3A00).unwrap();
+        assert_eq!(ed.export_count(), 14);
+
+        let page = ed.read_exports_page(&sections, &mut reader, 3, 4).unwrap();
+        ed.parse_exports(&sections, &mut reader).unwrap();
+        assert_eq!(page.len(), 4);
+        assert_eq!(page.as_slice(), &ed.exports[3..7]);
+
+        let last = ed.read_exports_page(&sections, &mut reader, 12, 100).unwrap();
+        assert_eq!(last.len(), 2);
+        assert_eq!(last[1].name.value, "__strncpy_chk");
+        assert!(ed.read_exports_page(&sections, &mut reader, 14, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn pages_read_ordinals_once() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut bytes = fs::read(path).unwrap();
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        let parsed = pe.exports.value.exports.clone();
+        let ordinals = pe.rva_to_offset(pe.exports.value.address_of_name_ordinals.value).unwrap() as usize;
+
+        let mut pages = pe.export_pages(1);
+        assert_eq!(pages.next().unwrap().unwrap()[0], parsed[0]);
+        //Later pages use the ordinal table read with the first.
+        bytes[ordinals..ordinals + 4].fill(0);
+        pages.pe.reader = Box::new(Cursor::new(bytes));
+        assert_eq!(pages.next().unwrap().unwrap()[0], parsed[1]);
+        assert!(pages.next().is_none());
+    }
+
     fn parse_section_header() -> SectionTable {
         parse_sections(&SECTION_RAW, 11, 0x188).unwrap()
     }
@@ -1,26 +1,35 @@
-use std::{fmt::{Display, Formatter}, io::Cursor, mem::size_of};
+use std::{fmt::{Display, Formatter}, io::{Cursor, Read}, mem::size_of};
 
 use byteorder::{ReadBytesExt, LittleEndian};
-use chrono::prelude::*;
 use bitflags::bitflags;
 use serde::Serialize;
 
 use crate::{new_header_field, types::{Header, HeaderField}, utils::flags_to_str};
 
-use super::PeError;
+use super::{PeError, PeTimestamp};
 
 pub const HEADER_LENGTH: u64 = 24;
+/// Length of the header without the signature.
+pub const COFF_HEADER_LENGTH: u64 = 20;
 
 #[derive(Debug, PartialEq, Default, Serialize, Clone, Copy)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 pub enum MachineType {   
     #[default]
     UNKNOWN = 0x0,    
     AMD64   = 0x8664,
     ARM     = 0x1c0,
     ARM64   = 0xaa64,
+    ARM64EC = 0xa641,
+    ARM64X  = 0xa64e,
+    ARMNT   = 0x1c4,
     I386    = 0x14c,
     IA64    = 0x200,
     THUMB   = 0x1c2,    
+    EBC     = 0xebc,
+    RISCV32 = 0x5032,
+    RISCV64 = 0x5064,
+    LOONGARCH64 = 0x6264,
 }
 
 impl From<u16> for MachineType {
@@ -29,26 +38,54 @@ impl From<u16> for MachineType {
             0x8664 => Self::AMD64,
             0x01c0 => Self::ARM,
             0xaa64 => Self::ARM64,
+            0xa641 => Self::ARM64EC,
+            0xa64e => Self::ARM64X,
+            0x01c4 => Self::ARMNT,
             0x014c => Self::I386,
             0x0200 => Self::IA64,
             0x01c2 => Self::THUMB,
+            0x0ebc => Self::EBC,
+            0x5032 => Self::RISCV32,
+            0x5064 => Self::RISCV64,
+            0x6264 => Self::LOONGARCH64,
             _ => Self::UNKNOWN
         }
     }
 }
 
+impl MachineType {
+    /// ARM (32-bit, incl. Thumb) machine types.
+    pub fn is_arm32(&self) -> bool {
+        matches!(self, Self::ARM | Self::THUMB | Self::ARMNT)
+    }
+
+    /// ARM64 machine types, incl. ARM64EC and ARM64X hybrids.
+    pub fn is_arm64(&self) -> bool {
+        matches!(self, Self::ARM64 | Self::ARM64EC | Self::ARM64X)
+    }
+
+    /// Whether the machine needs a PE32+ optional header; `None` if it takes either, like EBC.
+    pub fn is_64bit(&self) -> Option<bool> {
+        match self {
+            Self::AMD64 | Self::ARM64 | Self::ARM64EC | Self::ARM64X | Self::IA64 | Self::RISCV64 | Self::LOONGARCH64 => Some(true),
+            Self::I386 | Self::ARM | Self::ARMNT | Self::THUMB | Self::RISCV32 => Some(false),
+            Self::UNKNOWN | Self::EBC => None,
+        }
+    }
+}
+
 bitflags! {
     #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy, Serialize)]
     pub struct Flags: u16 {
         const UNKNOWN = 0x0000;
         const RELOCS_STRIPPED = 0x0001;
         const EXECUTABLE = 0x0002;
-        //const //LINE_NUMS_STRIPPED = 0x0004,
-        //const //LOCAL_SYMS_STRIPPED = 0x0008,
-        //const //AGGRESSIVE_WS_TRIM = 0x0010,
+        const LINE_NUMS_STRIPPED = 0x0004;
+        const LOCAL_SYMS_STRIPPED = 0x0008;
+        const AGGRESSIVE_WS_TRIM = 0x0010;
         const LARGE_ADDRESS_AWARE = 0x0020;
         const RESERVED = 0x0040;
-        //const //BYTES_REVERSED_LO = 0x0080,
+        const BYTES_REVERSED_LO = 0x0080;
         const MACHINE_32BIT = 0x0100;
         const DEBUG_STRIPPED = 0x0200;
         const REMOVABLE_RUN_FROM_SWAP = 0x0400;
@@ -56,7 +93,38 @@ bitflags! {
         const SYSTEM = 0x1000;
         const DLL = 0x2000;
         const UP_SYSTEM_ONLY = 0x4000;
-        //const //BYTES_REVERSED_HI = 0x8000,
+        const BYTES_REVERSED_HI = 0x8000;
+    }
+}
+
+impl Flags {
+    pub fn is_dll(&self) -> bool {
+        self.contains(Self::DLL)
+    }
+
+    /// Image can be run; not set for object files and images with link errors.
+    pub fn is_executable(&self) -> bool {
+        self.contains(Self::EXECUTABLE)
+    }
+
+    /// A system file like a driver, not a user program.
+    pub fn is_system(&self) -> bool {
+        self.contains(Self::SYSTEM)
+    }
+
+    /// Can handle addresses above 2 GB.
+    pub fn is_large_address_aware(&self) -> bool {
+        self.contains(Self::LARGE_ADDRESS_AWARE)
+    }
+
+    /// Relocations were removed, so the image loads only at its preferred base.
+    pub fn stripped_relocs(&self) -> bool {
+        self.contains(Self::RELOCS_STRIPPED)
+    }
+
+    /// Debug info was removed to a separate file.
+    pub fn stripped_debug(&self) -> bool {
+        self.contains(Self::DEBUG_STRIPPED)
     }
 }
 
@@ -67,16 +135,19 @@ impl Display for Flags {
 }
 
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
 pub struct FileHeader {
+    /// The NT headers signature, also in `NtHeaders::signature`.
     pub magic: HeaderField<u32>,
     pub machine: HeaderField<MachineType>,
     pub sections: HeaderField<u16>,
-    pub timestamp: HeaderField<DateTime<Utc>>,
+    pub timestamp: HeaderField<PeTimestamp>,
     pub symbol_table_ptr: HeaderField<u32>,
     pub symbols: HeaderField<u32>,
     pub optional_header_size: HeaderField<u16>,
     pub charactristics: HeaderField<u16>,
+    /// Machine as stored; `machine` is `UNKNOWN` for types it doesn't list.
+    pub machine_raw: u16,
 }
 
 impl FileHeader {
@@ -87,11 +158,49 @@ impl FileHeader {
     pub fn flags(&self) -> Option<Flags> {
         Flags::from_bits(self.charactristics.value)
     }
+
+    /// Parse a COFF file header, without the signature, as at the start of object files.
+    /// `magic` is left 0.
+    pub fn parse_coff_bytes(bytes: &[u8], pos: u64) -> crate::pe::Result<Self> {
+        let bytes_len = bytes.len() as u64;
+        if bytes_len < COFF_HEADER_LENGTH {
+            return Err(
+                PeError::BufferTooSmall { target: "CoffFileHeader".into(), expected: COFF_HEADER_LENGTH, actual: bytes_len }
+            );
+        }
+
+        let mut file_hdr = Self::new();
+        file_hdr.read_coff_fields(&mut Cursor::new(bytes), pos)?;
+        Ok(file_hdr)
+    }
+
+    /// Read fields after the signature from `cursor`, the first at `pos`.
+    fn read_coff_fields(&mut self, cursor: &mut impl Read, pos: u64) -> crate::pe::Result<()> {
+        let mut offset = pos;
+
+        let data = cursor.read_u16::<LittleEndian>()?;
+        self.machine = HeaderField::at_offset(MachineType::from(data), offset);
+        self.machine_raw = data;
+        offset += size_of::<u16>() as u64;
+
+        self.sections = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+        
+        let data = cursor.read_u32::<LittleEndian>()?;
+        self.timestamp = HeaderField::at_offset(PeTimestamp::from(data), offset) ;
+        offset += size_of::<u32>() as u64;
+
+        self.symbol_table_ptr = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        self.symbols = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        self.optional_header_size = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+        self.charactristics = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+
+        Ok(())
+    }
 }
 
 impl Display for FileHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{{Magic: '{}', Machine: {:?}, Sections: {}, Timestamp: {:?}, Charactristics: {}}}", 
+        write!(f, "{{Magic: '{}', Machine: {:?}, Sections: {}, Timestamp: {}, Charactristics: {}}}", 
             std::str::from_utf8(&self.magic.value.to_le_bytes()).unwrap_or("ERR"), 
             self.machine.value, self.sections.value, self.timestamp.value, 
             self.flags().unwrap_or(Flags::UNKNOWN))
@@ -99,7 +208,7 @@ impl Display for FileHeader {
 }
 
 impl Header for FileHeader {
-    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> {
         let bytes_len = bytes.len() as u64;
 
         if bytes_len < HEADER_LENGTH {
@@ -114,22 +223,7 @@ impl Header for FileHeader {
         let mut file_hdr = Self::new();
 
         file_hdr.magic = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
-
-        let data = cursor.read_u16::<LittleEndian>()?;
-        file_hdr.machine = HeaderField { value: MachineType::from(data), offset: offset, rva: offset };
-        offset += size_of::<u16>() as u64;
-
-        file_hdr.sections = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
-        
-        let data = cursor.read_u32::<LittleEndian>()?;
-        let ts = DateTime::<Utc>::from_timestamp(data.into(), 0).ok_or(PeError::InvalidTimestamp(data.into()))?; //TODO: map to FileParseError?
-        file_hdr.timestamp = HeaderField { value: ts, offset: offset, rva: offset} ;
-        offset += size_of::<u32>() as u64;
-
-        file_hdr.symbol_table_ptr = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
-        file_hdr.symbols = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
-        file_hdr.optional_header_size = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
-        file_hdr.charactristics = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+        file_hdr.read_coff_fields(&mut cursor, offset)?;
 
         Ok(file_hdr)
     }
@@ -158,7 +252,7 @@ mod tests {
         // eprintln!("{:?}", file_hdr.flags());
         assert!(file_hdr.is_valid());
         assert_eq!(file_hdr.machine.value, MachineType::AMD64);
-        assert_eq!(file_hdr.timestamp.value.format("%Y-%m-%d %H:%M:%S").to_string(), "2022-01-17 03:46:45");
+        assert_eq!(file_hdr.timestamp.value.as_datetime().unwrap().format("%Y-%m-%d %H:%M:%S").to_string(), "2022-01-17 03:46:45");
         assert_eq!(file_hdr.sections.value, 5);
         assert_eq!(file_hdr.optional_header_size.value, 0x00f0);
         assert_eq!(file_hdr.charactristics.value, 0x22);
@@ -168,6 +262,29 @@ mod tests {
         assert!(format!("{file_hdr}").contains("EXECUTABLE | LARGE_ADDRESS_AWARE"));
     }
 
+    #[test]
+    fn flag_helpers() {
+        let flags = FileHeader::parse_bytes(RAW_BYTES.to_vec(), 0).unwrap().flags().unwrap();
+        assert!(flags.is_executable() && flags.is_large_address_aware());
+        assert!(!flags.is_dll() && !flags.is_system() && !flags.stripped_relocs());
+
+        //Every bit is a known flag, as in images from older linkers.
+        let flags = Flags::from_bits(0x210f).unwrap();
+        assert!(flags.is_dll() && flags.stripped_relocs());
+        assert!(flags.contains(Flags::LINE_NUMS_STRIPPED | Flags::LOCAL_SYMS_STRIPPED));
+        assert!(Flags::from_bits(u16::MAX).is_some());
+    }
+
+    #[test]
+    fn arm_machine_types() {
+        assert_eq!(MachineType::from(0x1c4), MachineType::ARMNT);
+        assert_eq!(MachineType::from(0xa641), MachineType::ARM64EC);
+        assert_eq!(MachineType::from(0xa64e), MachineType::ARM64X);
+        assert!(MachineType::THUMB.is_arm32() && !MachineType::THUMB.is_arm64());
+        assert!(MachineType::ARM64EC.is_arm64() && !MachineType::ARM64EC.is_arm32());
+        assert!(!MachineType::AMD64.is_arm32() && !MachineType::AMD64.is_arm64());
+    }
+
     #[test]
     fn parse_invalid_header() {
         let mut buf = RAW_BYTES.to_vec();
@@ -0,0 +1,131 @@
+//! Parsing profile for untrusted input.
+//!
+//! Uses `ParseLimits::strict()` and, before following any directory, checks header values
+//! against the length of the input, so truncated or inflated sizes fail early.
+
+use std::{fs::File, io::{BufReader, Cursor}};
+
+use crate::{pe::Result, types::BufReadExt};
+
+use super::{limits::ParseLimits, optional::DirectoryType, te::TeImage, PeError, PeImage};
+
+/// Parse an in-memory PE image with the hardened profile.
+pub fn parse_bytes(bytes: Vec<u8>) -> Result<PeImage> {
+    let len = bytes.len() as u64;
+    parse_readable(Box::new(Cursor::new(bytes)), len)
+}
+
+/// Parse a PE image from file with the hardened profile.
+pub fn parse_file(file: File) -> Result<PeImage> {
+    let len = file.metadata()?.len();
+    parse_readable(Box::new(BufReader::new(file)), len)
+}
+
+/// Parse a PE image of `len` bytes from `reader` with the hardened profile.
+pub fn parse_readable(reader: Box<dyn BufReadExt>, len: u64) -> Result<PeImage> {
+    let mut pe = PeImage::new(reader);
+    pe.limits = ParseLimits::strict();
+
+    let offset = pe.parse_fixed_headers(0)?;
+    pe.parse_sections(offset)?;
+    check_bounds(&pe, len)?;
+    pe.parse_dynamic_headers()?;
+
+    Ok(pe)
+}
+
+/// Parse an in-memory TE image with the hardened profile.
+pub fn parse_te_bytes(bytes: Vec<u8>) -> Result<TeImage> {
+    let len = bytes.len() as u64;
+    let mut te = TeImage::new(Box::new(Cursor::new(bytes)));
+    te.limits = ParseLimits::strict();
+    te.parse_all_headers(0)?;
+
+    for section in &te.sections.value {
+        let start = (section.value.raw_data_ptr.value as i64 - te.header.value.adjustment()).max(0) as u64;
+        check_range("section", start, section.value.sizeof_raw_data.value.into(), len)?;
+    }
+    Ok(te)
+}
+
+fn check_range(name: &str, start: u64, size: u64, len: u64) -> Result<()> {
+    let end = start.saturating_add(size);
+    if end > len {
+        return Err(PeError::BeyondRange { name: "input".into(), typ: format!("{name} end"), value: end, start: 0, end: len });
+    }
+    Ok(())
+}
+
+/// Check that sections and directories parsed so far fit in an input of `len` bytes.
+pub fn check_bounds(pe: &PeImage, len: u64) -> Result<()> {
+    let lfanew = pe.dos.value.e_lfanew.value as u64;
+    check_range("NT headers", lfanew, super::file::HEADER_LENGTH, len)?;
+
+    for section in &pe.sections.value {
+        let section = &section.value;
+        check_range("section", section.raw_data_ptr.value.into(), section.sizeof_raw_data.value.into(), len)?;
+    }
+
+    for dir in &pe.data_dirs.value {
+        let dir = &dir.value;
+        if dir.rva.value == 0 {
+            continue;
+        }
+        //Security directory holds a file offset, all others an rva.
+        if dir.member == DirectoryType::Security {
+            check_range("Security directory", dir.rva.value.into(), dir.size.value.into(), len)?;
+        }
+        else if dir.rva.value.checked_add(dir.size.value).is_none() {
+            return Err(PeError::InvalidHeader {
+                name: format!("{:?} directory", dir.member),
+                offset: dir.rva.offset.into(),
+                reason: format!("rva {:#x} + size {:#x} overflows", dir.rva.value, dir.size.value),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::pe::PeError;
+
+    use super::parse_bytes;
+
+    fn test_dll() -> Vec<u8> {
+        fs::read(env::current_dir().unwrap().join("test-data").join("test.dll")).unwrap()
+    }
+
+    #[test]
+    fn hardened_parses_valid_image() {
+        let pe = parse_bytes(test_dll()).unwrap();
+        assert_eq!(pe.sections.value.len(), 6);
+        assert!(pe.has_imports());
+    }
+
+    #[test]
+    fn hardened_rejects_truncated_image() {
+        let bytes = test_dll();
+        let err = parse_bytes(bytes[..bytes.len() / 2].to_vec()).unwrap_err();
+        assert!(matches!(err.root(), PeError::BeyondRange { .. }), "{err}");
+    }
+
+    #[test]
+    fn hardened_rejects_many_sections() {
+        let mut bytes = test_dll();
+        let lfanew = u32::from_le_bytes(bytes[0x3c..0x40].try_into().unwrap()) as usize;
+        bytes[lfanew + 6..lfanew + 8].copy_from_slice(&2000u16.to_le_bytes());
+        let err = parse_bytes(bytes.clone()).unwrap_err();
+        assert!(matches!(err.root(), PeError::InvalidSectionCount { count: 2000, .. }), "{err}");
+        assert!(err.to_string().ends_with("more than allowed 1024"), "{err}");
+
+        //Within the limit, but the table goes beyond the input.
+        bytes[lfanew + 6..lfanew + 8].copy_from_slice(&200u16.to_le_bytes());
+        let err = parse_bytes(bytes[..0x1000].to_vec()).unwrap_err();
+        assert!(matches!(err.root(), PeError::InvalidSectionCount { count: 200, .. }), "{err}");
+        assert!(err.to_string().contains("bytes remain in input"), "{err}");
+    }
+}
@@ -0,0 +1,352 @@
+//! Hashes of the whole file, to look it up or find similar files.
+//!
+//! The file is read in chunks through the reader of the image. Besides MD5, SHA1 and SHA256,
+//! the ssdeep (spamsum) fuzzy hash is computed on request; it is the same as `ssdeep` prints.
+
+use std::fmt::Display;
+
+use md5::Md5;
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use super::{import::ordinals, PeImage, Result};
+
+/// Bytes hashed per read.
+const CHUNK_SIZE: u64 = 0x10000;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct FileHashes {
+    pub md5: String,
+    pub sha1: String,
+    pub sha256: String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub ssdeep: Option<String>,
+}
+
+impl FileHashes {
+    /// Hashes of `bytes`; the ssdeep hash only if `fuzzy`.
+    pub fn of_bytes(bytes: &[u8], fuzzy: bool) -> Self {
+        let mut hasher = Hasher::new(bytes.len() as u64, fuzzy);
+        hasher.update(bytes);
+        hasher.finalize()
+    }
+}
+
+impl Display for FileHashes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Hashes:")?;
+        writeln!(f, "  MD5:    {}", self.md5)?;
+        writeln!(f, "  SHA1:   {}", self.sha1)?;
+        write!(f, "  SHA256: {}", self.sha256)?;
+        if let Some(ssdeep) = &self.ssdeep {
+            write!(f, "\n  ssdeep: {ssdeep}")?;
+        }
+        Ok(())
+    }
+}
+
+/// All hashes of `FileHashes`, fed in chunks.
+struct Hasher {
+    md5: Md5,
+    sha1: Sha1,
+    sha256: Sha256,
+    ssdeep: Option<Spamsum>,
+}
+
+impl Hasher {
+    fn new(size: u64, fuzzy: bool) -> Self {
+        Self { md5: Md5::new(), sha1: Sha1::new(), sha256: Sha256::new(), ssdeep: fuzzy.then(|| Spamsum::new(size)) }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.md5.update(bytes);
+        self.sha1.update(bytes);
+        self.sha256.update(bytes);
+        if let Some(ssdeep) = &mut self.ssdeep {
+            ssdeep.update(bytes);
+        }
+    }
+
+    fn finalize(self) -> FileHashes {
+        FileHashes {
+            md5: format!("{:x}", self.md5.finalize()),
+            sha1: format!("{:x}", self.sha1.finalize()),
+            sha256: format!("{:x}", self.sha256.finalize()),
+            ssdeep: self.ssdeep.map(Spamsum::finalize),
+        }
+    }
+}
+
+const ROLLING_WINDOW: usize = 7;
+const MIN_BLOCKSIZE: u32 = 3;
+const SPAMSUM_LENGTH: usize = 64;
+const HASH_INIT: u32 = 0x2802_1967;
+const HASH_PRIME: u32 = 0x0100_0193;
+const BLOCK_SIZES: usize = 31;
+const B64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Rolling hash of the last `ROLLING_WINDOW` bytes; its value decides where pieces end.
+#[derive(Default)]
+struct Roll {
+    window: [u8; ROLLING_WINDOW],
+    h1: u32,
+    h2: u32,
+    h3: u32,
+    n: usize,
+}
+
+impl Roll {
+    fn update(&mut self, c: u8) {
+        let c32 = c as u32;
+        self.h2 = self.h2.wrapping_sub(self.h1).wrapping_add(ROLLING_WINDOW as u32 * c32);
+        self.h1 = self.h1.wrapping_add(c32).wrapping_sub(self.window[self.n] as u32);
+        self.window[self.n] = c;
+        self.n = (self.n + 1) % ROLLING_WINDOW;
+        self.h3 = (self.h3 << 5) ^ c32;
+    }
+
+    fn sum(&self) -> u32 {
+        self.h1.wrapping_add(self.h2).wrapping_add(self.h3)
+    }
+}
+
+/// Digest at one block size. Pieces after the last one that fits overwrite `tail`.
+struct BlockHash {
+    h: u32,
+    /// Hash of pieces after the first half of the digest.
+    half_h: u32,
+    digest: Vec<u8>,
+    tail: Option<u8>,
+    half_tail: Option<u8>,
+}
+
+impl BlockHash {
+    fn new() -> Self {
+        Self { h: HASH_INIT, half_h: HASH_INIT, digest: Vec::with_capacity(SPAMSUM_LENGTH), tail: None, half_tail: None }
+    }
+
+    fn end_piece(&mut self) {
+        self.half_tail = Some(B64[self.half_h as usize % 64]);
+        if self.digest.len() < SPAMSUM_LENGTH - 1 {
+            self.digest.push(B64[self.h as usize % 64]);
+            self.h = HASH_INIT;
+            if self.digest.len() < SPAMSUM_LENGTH / 2 {
+                self.half_h = HASH_INIT;
+                self.half_tail = None;
+            }
+        }
+        else {
+            self.tail = Some(B64[self.h as usize % 64]);
+        }
+    }
+}
+
+/// ssdeep hash of input of a known size, fed in chunks. Digests of every block size up to the
+/// one the size calls for are kept, since a smaller one is taken if it is too short.
+struct Spamsum {
+    roll: Roll,
+    blocks: Vec<BlockHash>,
+    /// Index of the block size the size of the input calls for.
+    start: usize,
+}
+
+impl Spamsum {
+    fn new(size: u64) -> Self {
+        let mut start = 0;
+        while start < BLOCK_SIZES - 2 && (Self::block_size(start) as u64) * (SPAMSUM_LENGTH as u64) < size {
+            start += 1;
+        }
+        Self { roll: Roll::default(), blocks: (0..start + 2).map(|_| BlockHash::new()).collect(), start }
+    }
+
+    fn block_size(index: usize) -> u32 {
+        MIN_BLOCKSIZE << index
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &c in bytes {
+            self.roll.update(c);
+            for block in &mut self.blocks {
+                block.h = block.h.wrapping_mul(HASH_PRIME) ^ c as u32;
+                block.half_h = block.half_h.wrapping_mul(HASH_PRIME) ^ c as u32;
+            }
+            //A piece ending at a block size also ends at every smaller one.
+            let h = self.roll.sum();
+            for (i, block) in self.blocks.iter_mut().enumerate() {
+                let bs = Self::block_size(i);
+                if h % bs != bs - 1 {
+                    break;
+                }
+                block.end_piece();
+            }
+        }
+    }
+
+    fn finalize(self) -> String {
+        let mut index = self.start;
+        while index > 0 && self.blocks[index].digest.len() < SPAMSUM_LENGTH / 2 {
+            index -= 1;
+        }
+        let h = self.roll.sum();
+
+        let first = &self.blocks[index];
+        let mut digest = first.digest.clone();
+        match h {
+            0 => digest.extend(first.tail),
+            _ => digest.push(B64[first.h as usize % 64]),
+        }
+        digest.push(b':');
+
+        let second = &self.blocks[index + 1];
+        digest.extend(second.digest.iter().take(SPAMSUM_LENGTH / 2 - 1));
+        match h {
+            0 => digest.extend(second.half_tail),
+            _ => digest.push(B64[second.half_h as usize % 64]),
+        }
+
+        format!("{}:{}", Self::block_size(index), String::from_utf8_lossy(&digest))
+    }
+}
+
+impl PeImage {
+    /// Hashes of the whole input, not only of the image if it starts at a non-zero `pos`;
+    /// the ssdeep hash only if `fuzzy`. See `BufReadExt::read_input_at`.
+    pub fn file_hashes(&mut self, fuzzy: bool) -> Result<FileHashes> {
+        let size = self.reader.input_end()?;
+        let mut hasher = Hasher::new(size, fuzzy);
+        let mut pos = 0;
+        self.progress.reset();
+        self.progress.stage("hashes");
+        while pos < size {
+            self.progress.update(pos, size);
+            let len = (size - pos).min(CHUNK_SIZE);
+            hasher.update(&self.reader.read_input_at(pos, len as usize)?);
+            pos += len;
+        }
+        self.progress.update(size, size);
+        Ok(hasher.finalize())
+    }
+
+    /// Import hash as pefile computes it: MD5 of `module.function` of every import, in lower
+    /// case and joined by commas. Modules lose a `.dll`, `.ocx` or `.sys` extension. Functions
+    /// imported by ordinal are named with `import::ordinals`, or are `ord<N>`; pefile also names
+    /// ordinals of `oleaut32.dll`, so hashes of images importing those by ordinal differ.
+    /// `None` without named imports.
+    pub fn imphash(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        for desc in &self.imports.value {
+            let Some(dll_name) = &desc.value.name else {
+                continue;
+            };
+            let dll = dll_name.to_lowercase();
+            let module = match dll.rsplit_once('.') {
+                Some((module, "dll" | "ocx" | "sys")) => module,
+                _ => &dll,
+            };
+            for import in &desc.value.imports {
+                let function = match (import.import_name(), import.ordinal()) {
+                    (Some(name), _) => name.name.value.to_lowercase(),
+                    (None, Some(ordinal)) => match ordinals::lookup(dll_name, ordinal) {
+                        Some(name) => name.to_lowercase(),
+                        None => format!("ord{ordinal}"),
+                    },
+                    (None, None) => continue,
+                };
+                parts.push(format!("{module}.{function}"));
+            }
+        }
+
+        match parts.is_empty() {
+            true => None,
+            false => Some(format!("{:x}", Md5::digest(parts.join(",")))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::pe::{builder::PeBuilder, file::MachineType, PeImage};
+
+    use md5::Md5;
+    use sha2::Digest;
+
+    use super::FileHashes;
+
+    #[test]
+    fn hashes_of_test_dll() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+
+        let hashes = pe.file_hashes(true).unwrap();
+        assert_eq!(hashes.md5, "dc183fed61b2d993027302ff97ede9d4");
+        assert_eq!(hashes.sha256, "6dea26270e891756ca78422c289cf77c6780194e541598ff91c3b73c124c249c");
+        assert_eq!(hashes, FileHashes::of_bytes(&bytes, true));
+        assert!(pe.file_hashes(false).unwrap().ssdeep.is_none());
+
+        let ssdeep = hashes.ssdeep.unwrap();
+        let parts: Vec<&str> = ssdeep.split(':').collect();
+        assert_eq!(parts.len(), 3);
+        assert!((1..=64).contains(&parts[1].len()) && parts[2].len() <= 32);
+
+        //The whole input, also of an image at a non-zero position.
+        let mut embedded = vec![0xCC; 0x200];
+        embedded.extend(&bytes);
+        let mut pe = PeImage::parse_bytes(embedded.clone(), 0x200).unwrap();
+        assert_eq!(pe.file_hashes(true).unwrap(), FileHashes::of_bytes(&embedded, true));
+    }
+
+    #[test]
+    fn ssdeep_reference_output() {
+        //Examples of the python-ssdeep documentation, which wraps libfuzzy.
+        let hash = |text: &str| FileHashes::of_bytes(text.as_bytes(), true).ssdeep.unwrap();
+        assert_eq!(hash("Also called fuzzy hashes, Ctph can match inputs that have homologies."), "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C");
+        assert_eq!(hash("Also called fuzzy hashes, CTPH can match inputs that have homologies."), "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2C");
+    }
+
+    #[test]
+    fn imphash_of_imports() {
+        let bytes = PeBuilder::new(MachineType::AMD64).build().unwrap();
+        assert!(PeImage::parse_bytes(bytes, 0).unwrap().imphash().is_none());
+
+        let bytes = PeBuilder::new(MachineType::AMD64).with_import("KERNEL32.dll", &["ExitProcess"]).build().unwrap();
+        let pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        assert_eq!(pe.imphash().unwrap(), "f9ade0aa18f660a34a4fa23392e21838");
+    }
+
+    #[test]
+    fn imphash_of_winsock_ordinal() {
+        use crate::pe::import::ImportLookup;
+
+        let bytes = PeBuilder::new(MachineType::AMD64).with_import("WS2_32.dll", &["WSAStartup"]).build().unwrap();
+        let by_name = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        assert_eq!(by_name.imphash().unwrap(), format!("{:x}", Md5::digest("ws2_32.wsastartup")));
+
+        //Import WSAStartup by its ordinal, 115, in the lookup table and the IAT.
+        let ImportLookup::X64(lookup) = &by_name.imports.value[0].value.imports[0] else { panic!("not PE32+") };
+        let iat_offset = by_name.rva_to_offset(lookup.iat_rva).unwrap() as usize;
+        let mut bytes = bytes;
+        for at in [lookup.value.offset.0 as usize, iat_offset] {
+            bytes[at..at + 8].copy_from_slice(&(1u64 << 63 | 115).to_le_bytes());
+        }
+        let by_ordinal = PeImage::parse_bytes(bytes, 0).unwrap();
+        assert_eq!(by_ordinal.imports.value[0].value.imports[0].ordinal(), Some(115));
+        assert_eq!(by_ordinal.imphash(), by_name.imphash());
+    }
+
+    #[test]
+    fn ssdeep_of_small_inputs() {
+        assert_eq!(FileHashes::of_bytes(&[], true).ssdeep.as_deref(), Some("3::"));
+        //Changing a few bytes keeps most of the hash.
+        let text: Vec<u8> = (0..4096u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+        let mut changed = text.clone();
+        changed[2000..2004].copy_from_slice(b"PE\0\0");
+        let (a, b) = (FileHashes::of_bytes(&text, true).ssdeep.unwrap(), FileHashes::of_bytes(&changed, true).ssdeep.unwrap());
+        assert_ne!(a, b);
+        let common = a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count();
+        assert!(common > a.len() / 3, "{a} vs {b}");
+    }
+}
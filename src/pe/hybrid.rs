@@ -0,0 +1,167 @@
+//! Separate native and x64-compatible views of hybrid (ARM64X and ARM64EC) images.
+//!
+//! A hybrid image has one set of headers, but the loader presents it differently to ARM64
+//! and to x64/ARM64EC processes. Views are derived from the CHPE metadata:
+//! - entry point: the header entry point, or `AlternateEntryPoint` for the ARM64EC view of ARM64X.
+//! - imports: the ARM64EC view resolves imports through the auxiliary IAT, when there is one.
+//! - exports: split by the code type at their address; exports outside code belong to both.
+//!
+//! Export directories swapped by dynamic value relocations of ARM64X are not applied.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use super::{loadcfg::chpe::CodeType, optional::DirectoryType, PeImage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum HybridArch {
+    /// View of ARM64 processes.
+    Arm64,
+    /// View of x64 and ARM64EC processes.
+    Arm64EC,
+}
+
+impl Display for HybridArch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Arm64 => write!(f, "ARM64 (native)"),
+            Self::Arm64EC => write!(f, "ARM64EC (x64 compatible)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct ViewImport {
+    pub module: String,
+    pub name: String,
+    /// RVA of the slot holding the resolved address in this view.
+    pub iat_rva: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct ViewExport {
+    pub name: String,
+    pub ordinal: u16,
+    pub address: u32,
+    /// `None` if the address is not in any code range, e.g. exported data.
+    pub code_type: Option<CodeType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct HybridView {
+    pub arch: HybridArch,
+    pub entry_point: u32,
+    pub imports: Vec<ViewImport>,
+    pub exports: Vec<ViewExport>,
+}
+
+impl Display for HybridView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} view: {{", self.arch)?;
+        writeln!(f, "  EntryPoint: {:#08x}", self.entry_point)?;
+        writeln!(f, "  Imports: [")?;
+        for import in &self.imports {
+            writeln!(f, "    {}!{} @ {:#08x}", import.module, import.name, import.iat_rva)?;
+        }
+        writeln!(f, "  ]")?;
+        writeln!(f, "  Exports: [")?;
+        for export in &self.exports {
+            write!(f, "    {} (Ord: {}) @ {:#08x}", export.name, export.ordinal, export.address)?;
+            match export.code_type {
+                Some(code_type) => writeln!(f, " {code_type:?}")?,
+                None => writeln!(f)?,
+            }
+        }
+        writeln!(f, "  ]")?;
+        write!(f, "}}")
+    }
+}
+
+impl HybridArch {
+    /// Whether code of `code_type` runs in this view; code-less addresses are in all views.
+    fn runs(&self, code_type: Option<CodeType>) -> bool {
+        match (self, code_type) {
+            (_, None) => true,
+            (Self::Arm64, Some(ct)) => ct == CodeType::Arm64,
+            (Self::Arm64EC, Some(ct)) => matches!(ct, CodeType::Arm64EC | CodeType::X64),
+        }
+    }
+}
+
+/// Views of a parsed image; empty unless it has CHPE metadata.
+/// ARM64X images (ARM64 machine) get both views, ARM64EC images (x64 machine) only the ARM64EC view.
+pub(crate) fn views(pe: &PeImage) -> Vec<HybridView> {
+    if !pe.has_chpe() {
+        return Vec::new();
+    }
+
+    let chpe = &pe.chpe.value;
+    let header_entry = pe.optional.value.entry_point();
+    let is_arm64x = pe.file.value.machine.value.is_arm64();
+
+    let iat_rva = pe.directory(DirectoryType::ImportAddressTable).map(|dir| dir.rva.value);
+    let aux_iat = chpe.auxiliary_iat.value;
+
+    let mut arches = Vec::new();
+    if is_arm64x {
+        arches.push((HybridArch::Arm64, header_entry));
+        let alternate = chpe.alternate_entry_point.value;
+        arches.push((HybridArch::Arm64EC, if alternate != 0 { alternate } else { header_entry }));
+    }
+    else {
+        arches.push((HybridArch::Arm64EC, header_entry));
+    }
+
+    arches.into_iter().map(|(arch, entry_point)| {
+        //The auxiliary IAT mirrors the layout of the IAT.
+        let slot = |rva: u32| match (arch, iat_rva) {
+            (HybridArch::Arm64EC, Some(iat)) if aux_iat != 0 && rva >= iat => aux_iat + (rva - iat),
+            _ => rva,
+        };
+
+        let imports = pe.imports.value.iter()
+            .flat_map(|id| {
+                let id = &id.value;
+                let module = id.name.clone().unwrap_or_default();
+                id.imports.iter().map(move |import| ViewImport {
+                    module: module.clone(),
+                    name: import.to_string(),
+                    iat_rva: slot(import.iat_rva()),
+                })
+            })
+            .collect();
+
+        let exports = pe.exports.value.exports.iter()
+            .map(|e| ViewExport {
+                name: e.name.value.clone(),
+                ordinal: e.ordinal.value,
+                address: e.address.value,
+                code_type: chpe.code_type(e.address.value),
+            })
+            .filter(|e| arch.runs(e.code_type))
+            .collect();
+
+        HybridView { arch, entry_point, imports, exports }
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HybridArch, CodeType};
+
+    #[test]
+    fn exports_by_code_type() {
+        assert!(HybridArch::Arm64.runs(Some(CodeType::Arm64)));
+        assert!(!HybridArch::Arm64.runs(Some(CodeType::X64)));
+        assert!(HybridArch::Arm64EC.runs(Some(CodeType::Arm64EC)));
+        assert!(HybridArch::Arm64EC.runs(Some(CodeType::X64)));
+        assert!(!HybridArch::Arm64EC.runs(Some(CodeType::Arm64)));
+        assert!(HybridArch::Arm64.runs(None) && HybridArch::Arm64EC.runs(None));
+    }
+}
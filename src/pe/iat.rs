@@ -0,0 +1,300 @@
+//! Rebuild of imports from the IAT of a dumped image, whose import directory is gone.
+//!
+//! After loading, each IAT slot holds the address of an import. Addresses are resolved against
+//! exports of the modules loaded in the dumped process, given as an `AddressMap`; runs of slots
+//! resolving to one module become one import descriptor. Slots are read from the
+//! `ImportAddressTable` directory, or from every section if the image has none.
+
+use std::collections::HashMap;
+
+use crate::types::{FileOffset, HeaderField, Rva};
+
+use super::{
+    check_alloc, import::{x64::ImportLookup64, x86::ImportLookup32, ImportDescriptor, ImportDirectory, ImportLookup, ImportName, ImportedFunction},
+    optional::{DirectoryType, ImageType}, PeImage, Result,
+};
+
+/// Slots of a run found outside the `ImportAddressTable` directory, below which it is ignored.
+pub const MIN_HEURISTIC_RUN: usize = 2;
+
+/// A module as loaded in the dumped process.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LoadedModule {
+    pub name: String,
+    pub base: u64,
+    /// Exports by RVA; named exports are kept by name, others by biased ordinal.
+    pub exports: HashMap<u32, ImportedFunction>,
+}
+
+impl LoadedModule {
+    pub fn new(name: &str, base: u64) -> Self {
+        Self { name: name.into(), base, ..Default::default() }
+    }
+
+    /// Exports of `pe`, loaded at `base`. Forwarded exports are left out, their address is
+    /// that of the target.
+    pub fn from_image(name: &str, base: u64, pe: &PeImage) -> Self {
+        let forwarders = pe.directory(DirectoryType::Export)
+            .map(|dir| dir.rva.value..dir.rva.value.saturating_add(dir.size.value))
+            .unwrap_or_default();
+        let ordinal_base = pe.exports.value.base.value;
+
+        let mut module = Self::new(name, base);
+        for export in &pe.exports.value.exports {
+            let rva = export.address.value;
+            if forwarders.contains(&rva) {
+                continue;
+            }
+            //Unnamed exports have a placeholder name without an rva.
+            let key = match export.name.rva {
+                Rva(0) => ImportedFunction::Ordinal((export.ordinal.value as u32).wrapping_add(ordinal_base) as u16),
+                _ => ImportedFunction::Name(export.name.value.clone()),
+            };
+            //Aliases share an address; the first named one wins.
+            let entry = module.exports.entry(rva).or_insert(key.clone());
+            if matches!(entry, ImportedFunction::Ordinal(_)) {
+                *entry = key;
+            }
+        }
+        module
+    }
+}
+
+/// Exports of loaded modules by their address in the process.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AddressMap {
+    modules: Vec<String>,
+    exports: HashMap<u64, (usize, ImportedFunction)>,
+}
+
+impl AddressMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add(&mut self, module: LoadedModule) {
+        let index = self.modules.len();
+        for (rva, export) in module.exports {
+            self.exports.insert(module.base.wrapping_add(rva.into()), (index, export));
+        }
+        self.modules.push(module.name);
+    }
+
+    /// Module and export at `va`.
+    pub fn resolve(&self, va: u64) -> Option<(&str, &ImportedFunction)> {
+        self.exports.get(&va).map(|(index, export)| (self.modules[*index].as_str(), export))
+    }
+
+    /// Number of exports.
+    pub fn len(&self) -> usize {
+        self.exports.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exports.is_empty()
+    }
+}
+
+/// Slots resolving to one module, from `rva` on.
+struct Run {
+    rva: u32,
+    offset: u64,
+    module: String,
+    imports: Vec<ImportLookup>,
+}
+
+impl Run {
+    fn into_descriptor(self) -> HeaderField<ImportDescriptor> {
+        let desc = ImportDescriptor {
+            first_thunk: HeaderField { value: self.rva, offset: self.offset.into(), rva: self.rva.into() },
+            name: Some(self.module),
+            imports: self.imports,
+            ..Default::default()
+        };
+        HeaderField { value: desc, offset: self.offset.into(), rva: self.rva.into() }
+    }
+}
+
+/// Lookup entry of an import at `iat_rva`, with the value a rebuilt lookup table would hold.
+/// Named imports have no hint/name entry yet; their value is 0.
+fn synthesize_lookup(export: &ImportedFunction, iat_rva: u32, offset: u64, is_64: bool) -> ImportLookup {
+    let (ordinal, iname) = match export {
+        ImportedFunction::Ordinal(ordinal) => (Some(*ordinal), None),
+        ImportedFunction::Name(name) => {
+            let name = ImportName { name: HeaderField { value: name.clone(), ..Default::default() }, ..Default::default() };
+            (None, Some(HeaderField { value: name, ..Default::default() }))
+        },
+    };
+    if is_64 {
+        let value = ordinal.map_or(0, |o| 0x8000_0000_0000_0000 | o as u64);
+        ImportLookup::X64(ImportLookup64 {
+            value: HeaderField { value, offset: FileOffset(offset), rva: iat_rva.into() },
+            is_ordinal: ordinal.is_some(), ordinal, iname, iat_rva,
+        })
+    }
+    else {
+        let value = ordinal.map_or(0, |o| 0x8000_0000 | o as u32);
+        ImportLookup::X86(ImportLookup32 {
+            value: HeaderField { value, offset: FileOffset(offset), rva: iat_rva.into() },
+            is_ordinal: ordinal.is_some(), ordinal, iname, iat_rva,
+        })
+    }
+}
+
+/// Runs of slots in `bytes`, found at `rva` and `offset`, resolving to one module each.
+/// A zero, an unresolved address or another module ends a run.
+fn find_runs(bytes: &[u8], rva: u32, offset: u64, is_64: bool, modules: &AddressMap, min_run: usize) -> Vec<Run> {
+    let slot_size = if is_64 { 8 } else { 4 };
+    let mut runs = Vec::new();
+    let mut current: Option<Run> = None;
+
+    for (i, slot) in bytes.chunks_exact(slot_size).enumerate() {
+        let va = if is_64 {
+            u64::from_le_bytes(slot.try_into().unwrap_or_default())
+        }
+        else {
+            u32::from_le_bytes(slot.try_into().unwrap_or_default()).into()
+        };
+        let delta = (i * slot_size) as u32;
+        let resolved = modules.resolve(va);
+
+        let continues = matches!((&current, resolved), (Some(run), Some((module, _))) if run.module == module);
+        if !continues {
+            runs.extend(current.take().filter(|run| run.imports.len() >= min_run));
+        }
+        let Some((module, export)) = resolved else {
+            continue;
+        };
+        let run = current.get_or_insert_with(|| Run {
+            rva: rva + delta, offset: offset + delta as u64, module: module.into(), imports: Vec::new(),
+        });
+        run.imports.push(synthesize_lookup(export, rva + delta, offset + delta as u64, is_64));
+    }
+    runs.extend(current.filter(|run| run.imports.len() >= min_run));
+    runs
+}
+
+impl PeImage {
+    /// Rebuild imports from IAT slots holding addresses of exports in `modules`, e.g. of an
+    /// image dumped from memory. Assign the result to `imports` to use it like parsed imports.
+    ///
+    /// The `ImportAddressTable` directory is scanned if the image has one; otherwise every
+    /// section is, and runs shorter than `MIN_HEURISTIC_RUN` are ignored as chance matches.
+    pub fn reconstruct_imports(&mut self, modules: &AddressMap) -> Result<ImportDirectory> {
+        let is_64 = self.optional.value.get_image_type() == ImageType::PE64;
+        let slot_size = if is_64 { 8 } else { 4 };
+
+        let (regions, min_run) = match self.directory(DirectoryType::ImportAddressTable) {
+            Some(dir) => (vec![(dir.rva.value, dir.size.value)], 1),
+            None => {
+                let sections = self.sections.value.iter()
+                    .map(|s| (s.value.virtual_address.value, s.value.sizeof_raw_data.value))
+                    .collect();
+                (sections, MIN_HEURISTIC_RUN)
+            },
+        };
+
+        let mut imports = ImportDirectory::new();
+        for (rva, size) in regions {
+            let Some(offset) = self.rva_to_offset(rva) else {
+                tracing::warn!(rva, "skipped IAT region without a section");
+                continue;
+            };
+            //Slots are aligned to their size from the start of the image.
+            let skip = rva.wrapping_neg() % slot_size;
+            if size <= skip {
+                continue;
+            }
+            let read = check_alloc("IAT region", size.into(), self.limits.max_alloc)
+                .and_then(|_| self.read_bytes_at_rva(rva + skip, (size - skip) as usize));
+            let bytes = match read {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    tracing::warn!(err = err.chain(), rva, "skipped unreadable IAT region");
+                    continue;
+                },
+            };
+            let runs = find_runs(&bytes, rva + skip, (offset + skip).into(), is_64, modules, min_run);
+            imports.extend(runs.into_iter().map(Run::into_descriptor));
+        }
+
+        tracing::debug!(modules = imports.len(), "rebuilt imports");
+        Ok(imports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::pe::{import::ImportedFunction, optional::DirectoryType, PeImage};
+
+    use super::{AddressMap, LoadedModule};
+
+    #[test]
+    fn rebuild_imports_of_dump() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut bytes = fs::read(path).unwrap();
+        let pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+
+        //Load each module at its own base, with its imports 0x10 bytes apart, and fill the IAT.
+        let mut modules = AddressMap::new();
+        for (i, id) in pe.imports.value.iter().enumerate() {
+            let desc = &id.value;
+            let mut module = LoadedModule::new(desc.name.as_deref().unwrap(), 0x7ff0_0000_0000 + i as u64 * 0x100_0000);
+            for (j, import) in desc.imports.iter().enumerate() {
+                let rva = 0x1000 + j as u32 * 0x10;
+                let export = match import.import_name() {
+                    Some(iname) => ImportedFunction::Name(iname.name.value.clone()),
+                    None => ImportedFunction::Ordinal(import.ordinal().unwrap()),
+                };
+                module.exports.insert(rva, export);
+                let offset = pe.rva_to_offset(import.iat_rva()).unwrap() as usize;
+                bytes[offset..offset + 8].copy_from_slice(&(module.base + rva as u64).to_le_bytes());
+            }
+            modules.add(module);
+        }
+
+        let mut dump = PeImage::parse_bytes(bytes, 0).unwrap();
+        let rebuilt = dump.reconstruct_imports(&modules).unwrap();
+        assert_eq!(rebuilt.len(), pe.imports.value.len());
+        //Descriptors come in the order of their IATs.
+        for rebuilt in rebuilt.iter().map(|id| &id.value) {
+            let desc = &pe.imports.value.iter().find(|id| id.value.first_thunk.value == rebuilt.first_thunk.value).unwrap().value;
+            assert_eq!(rebuilt.name, desc.name);
+            assert_eq!(rebuilt.imports.len(), desc.imports.len());
+            for (r, i) in rebuilt.imports.iter().zip(&desc.imports) {
+                assert_eq!(r.iat_rva(), i.iat_rva());
+                assert_eq!(r.import_name().map(|n| &n.name.value), i.import_name().map(|n| &n.name.value));
+                assert_eq!(r.ordinal(), i.ordinal());
+            }
+        }
+
+        //Without the IAT directory, sections are scanned.
+        dump.data_dirs.value[DirectoryType::ImportAddressTable as usize].value.rva.value = 0;
+        let scanned = dump.reconstruct_imports(&modules).unwrap();
+        assert_eq!(scanned.iter().map(|id| id.value.imports.len()).sum::<usize>(), rebuilt.iter().map(|id| id.value.imports.len()).sum::<usize>());
+
+        //A section with raw data past the end of input is skipped.
+        let last = dump.sections.value.last_mut().unwrap();
+        last.value.sizeof_raw_data.value = 0x10_0000;
+        let skipped = dump.reconstruct_imports(&modules).unwrap();
+        assert_eq!(skipped, scanned);
+    }
+
+    #[test]
+    fn exports_of_loaded_module() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+        let module = LoadedModule::from_image("test.dll", 0x1_8000_0000, &pe);
+        assert_eq!(module.exports.len(), 2);
+
+        let export = &pe.exports.value.exports[0];
+        let mut map = AddressMap::new();
+        map.add(module);
+        let (name, found) = map.resolve(0x1_8000_0000 + export.address.value as u64).unwrap();
+        assert_eq!(name, "test.dll");
+        assert_eq!(found, &ImportedFunction::Name(export.name.value.clone()));
+        assert_eq!(map.resolve(0x1_8000_0000), None);
+    }
+}
@@ -0,0 +1,128 @@
+//! Bound import directory (`IMAGE_BOUND_IMPORT_DESCRIPTOR`).
+//!
+//! Lists modules whose addresses were written to the IAT ahead of loading, with the timestamps
+//! they were bound against. Module names are offsets from the start of the directory.
+
+use std::fmt::Display;
+
+use byteorder::{ByteOrder, LittleEndian};
+use serde::Serialize;
+
+use crate::{pe::{PeError, PeTimestamp, Result}, types::HeaderField};
+
+pub const DESCRIPTOR_LENGTH: usize = 8;
+
+/// A module the bound module forwards some of its exports to.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct BoundForwarderRef {
+    pub timestamp: HeaderField<PeTimestamp>,
+    pub name_offset: HeaderField<u16>,
+    pub name: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct BoundImportDescriptor {
+    pub timestamp: HeaderField<PeTimestamp>,
+    pub name_offset: HeaderField<u16>,
+    pub number_of_forwarder_refs: HeaderField<u16>,
+    pub name: String,
+    pub forwarder_refs: Vec<BoundForwarderRef>,
+}
+
+impl Display for BoundImportDescriptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{ {}, Timestamp: {}, Forwarders: {} }}", self.name, self.timestamp.value, self.forwarder_refs.len())
+    }
+}
+
+pub type BoundImportDirectory = Vec<HeaderField<BoundImportDescriptor>>;
+
+/// Read the (timestamp, name offset, third field) triple at `index` of `bytes`.
+fn read_entry(bytes: &[u8], index: usize, pos: u64) -> Option<(HeaderField<PeTimestamp>, HeaderField<u16>, HeaderField<u16>)> {
+    let start = index * DESCRIPTOR_LENGTH;
+    let entry = bytes.get(start..start + DESCRIPTOR_LENGTH)?;
+    let offset = pos + start as u64;
+    let field = |at: usize| offset + at as u64;
+
+    Some((
+        HeaderField::at_offset(PeTimestamp::from(LittleEndian::read_u32(&entry[0..4])), field(0)),
+        HeaderField::at_offset(LittleEndian::read_u16(&entry[4..6]), field(4)),
+        HeaderField::at_offset(LittleEndian::read_u16(&entry[6..8]), field(6)),
+    ))
+}
+
+/// Parse the whole directory from `bytes`, read at `pos`, up to the null descriptor.
+pub fn parse_directory(bytes: &[u8], pos: u64) -> Result<BoundImportDirectory> {
+    let name_at = |offset: u16| -> Result<String> {
+        //Names are in the directory, after the descriptors.
+        let tail = bytes.get(offset as usize..).unwrap_or_default();
+        let end = tail.iter().position(|&b| b == 0).ok_or(PeError::InvalidOffset(pos + offset as u64))?;
+        Ok(String::from_utf8_lossy(&tail[..end]).into_owned())
+    };
+
+    let mut dir = BoundImportDirectory::new();
+    let mut index = 0;
+    while let Some((timestamp, name_offset, number_of_forwarder_refs)) = read_entry(bytes, index, pos) {
+        if timestamp.value.raw() == 0 && name_offset.value == 0 {
+            break;
+        }
+
+        let mut desc = BoundImportDescriptor {
+            name: name_at(name_offset.value)?,
+            timestamp, name_offset, number_of_forwarder_refs,
+            forwarder_refs: Vec::new(),
+        };
+        let offset = u64::from(desc.timestamp.offset);
+
+        for i in 1..=desc.number_of_forwarder_refs.value as usize {
+            let (timestamp, name_offset, _reserved) = read_entry(bytes, index + i, pos).ok_or(PeError::BufferTooSmall {
+                target: "BoundForwarderRef".into(),
+                expected: ((index + i + 1) * DESCRIPTOR_LENGTH) as u64,
+                actual: bytes.len() as u64,
+            })?;
+            desc.forwarder_refs.push(BoundForwarderRef { name: name_at(name_offset.value)?, timestamp, name_offset });
+        }
+
+        index += 1 + desc.number_of_forwarder_refs.value as usize;
+        dir.push(HeaderField::at_offset(desc, offset));
+    }
+
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::FileOffset;
+
+    use super::parse_directory;
+
+    #[test]
+    fn parse_with_forwarders() {
+        #[rustfmt::skip]
+        let bytes: [u8; 48] = [
+            //KERNEL32.dll, one forwarder ref.
+            0x78, 0x56, 0x34, 0x12, 0x20, 0x00, 0x01, 0x00,
+            //Forwarder ref to NT.
+            0x21, 0x43, 0x65, 0x87, 0x2d, 0x00, 0x00, 0x00,
+            //NT, no forwarders.
+            0x11, 0x11, 0x11, 0x11, 0x2d, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            b'K', b'E', b'R', b'N', b'E', b'L', b'3', b'2', b'.', b'd', b'l', b'l', 0x00,
+            b'N', b'T', 0x00,
+        ];
+
+        let dir = parse_directory(&bytes, 0x200).unwrap();
+        assert_eq!(dir.len(), 2);
+
+        let kernel32 = &dir[0].value;
+        assert_eq!(kernel32.name, "KERNEL32.dll");
+        assert_eq!(kernel32.timestamp.value.raw(), 0x12345678);
+        assert_eq!(kernel32.forwarder_refs.len(), 1);
+        assert_eq!(kernel32.forwarder_refs[0].name, "NT");
+        assert_eq!(kernel32.forwarder_refs[0].timestamp.offset, FileOffset(0x208));
+
+        assert_eq!(dir[1].value.name, "NT");
+        assert_eq!(dir[1].offset, FileOffset(0x210));
+        assert!(dir[1].value.forwarder_refs.is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+//! Delay load import directory (`IMAGE_DELAYLOAD_DESCRIPTOR`).
+//!
+//! Old images (before VC7) store virtual addresses instead of rvas; `attributes` tells which.
+//! Both are parsed, addresses are converted to rvas using the image base.
+
+use std::io::Cursor;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::{new_header_field, pe::{optional::ImageType, section::{self, SectionLookup}, PeError, PeTimestamp, Result}, types::{BufReadExt, HeaderField}};
+
+use super::{parse_lookup_table, ImportLookup, NameCache};
+
+pub const DESCRIPTOR_LENGTH: usize = 32;
+/// Bit of `attributes` set if the descriptor holds rvas.
+pub const ATTRIBUTE_RVA_BASED: u32 = 1;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DelayImportDescriptor {
+    pub attributes: HeaderField<u32>,
+    pub name_rva: HeaderField<u32>,
+    pub module_handle_rva: HeaderField<u32>,
+    /// Delay load IAT, patched by the helper when the module is loaded.
+    pub iat_rva: HeaderField<u32>,
+    /// Import name table; same format as an import lookup table.
+    pub int_rva: HeaderField<u32>,
+    pub bound_iat_rva: HeaderField<u32>,
+    pub unload_iat_rva: HeaderField<u32>,
+    pub timestamp: HeaderField<PeTimestamp>,
+    pub name: Option<String>,
+    /// Imports in `int_rva`, with `iat_rva` set to their slot in the delay load IAT.
+    pub imports: Vec<ImportLookup>,
+}
+
+impl DelayImportDescriptor {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn parse_bytes(bytes: &[u8], pos: u64) -> Result<Self> {
+        if bytes.len() < DESCRIPTOR_LENGTH {
+            return Err(PeError::BufferTooSmall {
+                target: "DelayImportDescriptor".into(), expected: DESCRIPTOR_LENGTH as u64, actual: bytes.len() as u64
+            });
+        }
+
+        let mut cursor = Cursor::new(bytes);
+        let mut offset = pos;
+        let mut desc = Self::new();
+
+        desc.attributes = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        desc.name_rva = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        desc.module_handle_rva = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        desc.iat_rva = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        desc.int_rva = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        desc.bound_iat_rva = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        desc.unload_iat_rva = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        let dt = cursor.read_u32::<LittleEndian>()?;
+        desc.timestamp = HeaderField::at_offset(PeTimestamp::from(dt), offset);
+
+        Ok(desc)
+    }
+
+    /// Whether the descriptor holds rvas, rather than virtual addresses.
+    #[inline]
+    pub fn is_rva_based(&self) -> bool {
+        self.attributes.value & ATTRIBUTE_RVA_BASED != 0
+    }
+
+    /// The null descriptor ends the directory.
+    pub fn is_valid(&self) -> bool {
+        self.name_rva.value != 0 || self.iat_rva.value != 0 || self.int_rva.value != 0
+    }
+
+    /// Convert addresses of a descriptor which is not rva based, using `image_base`.
+    fn make_rvas(&mut self, image_base: u64) {
+        if self.is_rva_based() {
+            return;
+        }
+        for field in [&mut self.name_rva, &mut self.module_handle_rva, &mut self.iat_rva, &mut self.int_rva, &mut self.bound_iat_rva, &mut self.unload_iat_rva] {
+            if field.value != 0 {
+                field.value = (field.value as u64).wrapping_sub(image_base) as u32;
+            }
+        }
+    }
+
+    /// Read the module name and parse imports from the import name table.
+    pub fn parse_imports(&mut self, sections: &impl SectionLookup, image_type: ImageType, image_base: u64, reader: &mut impl BufReadExt, max_imports: u32) -> Result<()> {
+        self.parse_imports_cached(sections, image_type, image_base, reader, max_imports, &mut NameCache::default())
+    }
+
+    /// Same as `parse_imports`, reusing names read for other descriptors.
+    pub(crate) fn parse_imports_cached(&mut self, sections: &impl SectionLookup, image_type: ImageType, image_base: u64, reader: &mut impl BufReadExt, max_imports: u32, cache: &mut NameCache) -> Result<()> {
+        self.make_rvas(image_base);
+
+        let name_offset = section::rva_to_offset(sections, self.name_rva.value).ok_or(PeError::InvalidRVA(self.name_rva.value.into()))?;
+        self.name = Some(reader.read_string_at_offset(name_offset.into())?);
+
+        //Names of entries in old style tables are addresses too.
+        let name_bias = if self.is_rva_based() { 0 } else { image_base };
+        self.imports = parse_lookup_table(sections, image_type, reader, self.int_rva.value, self.iat_rva.value, name_bias, max_imports, cache)?;
+        Ok(())
+    }
+}
+
+pub type DelayImportDirectory = Vec<HeaderField<DelayImportDescriptor>>;
+
+/// Parse descriptors from `bytes` of the directory, up to the null descriptor. `pos` is the offset of `bytes`.
+pub fn parse_directory(bytes: &[u8], pos: u64) -> Result<DelayImportDirectory> {
+    let mut dir = DelayImportDirectory::new();
+    for (i, chunk) in bytes.chunks_exact(DESCRIPTOR_LENGTH).enumerate() {
+        let offset = pos + (i * DESCRIPTOR_LENGTH) as u64;
+        let desc = DelayImportDescriptor::parse_bytes(chunk, offset)?;
+        if !desc.is_valid() {
+            break;
+        }
+        dir.push(HeaderField::at_offset(desc, offset));
+    }
+    Ok(dir)
+}
@@ -1,16 +1,20 @@
 use byteorder::{LittleEndian, ReadBytesExt, ByteOrder};
-use chrono::{DateTime, Utc};
+use serde::Serialize;
 
-use crate::{new_header_field, types::{Header, HeaderField, BufReadExt}, Result};
-use std::{io::Cursor, fmt::Display, mem::size_of};
+use crate::{new_header_field, pe::Result, types::{BufReadExt, FileOffset, Header, HeaderField, Rva}};
+use std::{collections::HashMap, io::Cursor, fmt::Display, mem::size_of};
 use self::{x86::ImportLookup32, x64::ImportLookup64};
 
-use super::{optional::ImageType, section::{self, offset_to_rva, rva_to_offset, SectionTable}, PeError};
+use super::{limits::check_count, optional::ImageType, section::{self, offset_to_rva, rva_to_offset, IndexedSections, SectionLookup, SectionTable}, DirectoryType, PeError, PeImage, PeTimestamp};
 
 pub(crate) mod x86;
 pub(crate) mod x64;
+pub mod delay;
+pub mod bound;
+pub mod table;
+pub mod ordinals;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct ImportName {
     pub hint: HeaderField<u16>,
     pub name: HeaderField<String>,
@@ -23,7 +27,66 @@ impl Display for ImportName {
 }
 
 
-#[derive(Debug)]
+/// Hint/name entries read so far, by rva. Modules share names and tables may list one twice,
+/// so with one cache per parse each entry is read from the input once. Only the reads are
+/// saved: each import still gets its own copy of the name.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct NameCache {
+    names: HashMap<Rva, HeaderField<ImportName>>,
+}
+
+impl NameCache {
+    pub(crate) fn read(&mut self, sections: &impl SectionLookup, reader: &mut dyn BufReadExt, rva: Rva) -> Result<HeaderField<ImportName>> {
+        if let Some(iname) = self.names.get(&rva) {
+            return Ok(iname.clone());
+        }
+
+        let offset = section::rva_to_offset(sections, rva.0)
+            .ok_or(PeError::InvalidRVA(rva.into()))?;
+        let hint = reader.read_bytes_at_offset(offset.into(), 2)?;
+        let hint = LittleEndian::read_u16(&hint);
+        let name = reader.read_string_at_offset(offset as u64 + 2)?;
+        let iname = HeaderField {
+            value: ImportName {
+                hint: HeaderField { value: hint, offset: offset.into(), rva },
+                name: HeaderField { value: name, offset: FileOffset::from(offset) + 2, rva: rva + 2 },
+            },
+            offset: offset.into(),
+            rva,
+        };
+        self.names.insert(rva, iname.clone());
+        Ok(iname)
+    }
+}
+
+
+/// Function imported from a module, by name or by ordinal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(untagged)]
+pub enum ImportedFunction {
+    Ordinal(u16),
+    Name(String),
+}
+
+impl From<&ImportLookup> for ImportedFunction {
+    fn from(value: &ImportLookup) -> Self {
+        match value.import_name() {
+            Some(iname) => Self::Name(iname.name.value.clone()),
+            None => Self::Ordinal(value.ordinal().unwrap_or_default()),
+        }
+    }
+}
+
+impl Display for ImportedFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "{name}"),
+            Self::Ordinal(ordinal) => write!(f, "#{ordinal}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ImportLookup {
     X86(ImportLookup32),
     X64(ImportLookup64),
@@ -42,19 +105,75 @@ impl From<HeaderField<u64>> for ImportLookup{
 }
 
 impl ImportLookup {
-    pub fn update_name(&mut self, sections: &SectionTable, reader: &mut impl BufReadExt) -> Result<()> {
+    pub fn update_name(&mut self, sections: &impl SectionLookup, reader: &mut impl BufReadExt) -> Result<()> {
+        self.update_name_cached(sections, reader, &mut NameCache::default())
+    }
+
+    pub(crate) fn update_name_cached(&mut self, sections: &impl SectionLookup, reader: &mut impl BufReadExt, cache: &mut NameCache) -> Result<()> {
         match self {
             ImportLookup::X86(il) => {
-                il.update_name(sections, reader)?;
+                il.update_name_cached(sections, reader, cache)?;
             },
 
             ImportLookup::X64(il) => {
-                il.update_name(sections, reader)?;
+                il.update_name_cached(sections, reader, cache)?;
             },
         }
 
         Ok(())
     }
+
+    /// RVA of the IAT slot of this import; set by `ImportDescriptor::parse_imports`.
+    pub fn iat_rva(&self) -> u32 {
+        match self {
+            ImportLookup::X86(il) => il.iat_rva,
+            ImportLookup::X64(il) => il.iat_rva,
+        }
+    }
+
+    /// Hint and name, unless imported by ordinal.
+    pub fn import_name(&self) -> Option<&ImportName> {
+        match self {
+            ImportLookup::X86(il) => il.iname.as_ref().map(|iname| &iname.value),
+            ImportLookup::X64(il) => il.iname.as_ref().map(|iname| &iname.value),
+        }
+    }
+
+    /// Ordinal, if imported by ordinal.
+    pub fn ordinal(&self) -> Option<u16> {
+        match self {
+            ImportLookup::X86(il) => il.ordinal,
+            ImportLookup::X64(il) => il.ordinal,
+        }
+    }
+
+    /// Subtract `bias` from the name address, for entries holding a VA.
+    fn unbias_name(&mut self, bias: u64) {
+        let iname = match self {
+            ImportLookup::X86(il) => &mut il.iname,
+            ImportLookup::X64(il) => &mut il.iname,
+        };
+        if let Some(iname) = iname {
+            iname.rva = Rva(u64::from(iname.rva).wrapping_sub(bias) as u32 & 0x7FFF_FFFF);
+        }
+    }
+
+    /// Raw value of the lookup table entry.
+    pub fn value(&self) -> u64 {
+        match self {
+            ImportLookup::X86(il) => il.value.value.into(),
+            ImportLookup::X64(il) => il.value.value,
+        }
+    }
+
+    /// Whether an import by ordinal has bits set between the ordinal and the ordinal flag.
+    /// These bits must be zero; the loader ignores them.
+    pub fn has_reserved_bits(&self) -> bool {
+        match self {
+            ImportLookup::X86(il) => il.is_ordinal && il.value.value & 0x7FFF_0000 != 0,
+            ImportLookup::X64(il) => il.is_ordinal && il.value.value & 0x7FFF_FFFF_FFFF_0000 != 0,
+        }
+    }
 }
 
 impl Display for ImportLookup {
@@ -69,22 +188,67 @@ impl Display for ImportLookup {
 
 pub const IMPORT_DESCRIPTOR_SIZE: usize = 20;
 
-#[derive(Debug, Default)]
+/// An IAT slot whose value on disk differs from its entry in the import lookup table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct IatMismatch {
+    pub iat_rva: u32,
+    pub ilt_value: u64,
+    pub iat_value: u64,
+}
+
+/// Meaning of the timestamp of an import descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum ImportBinding {
+    /// Timestamp 0; the IAT is filled at load time.
+    NotBound,
+    /// IAT bound to the module with this timestamp.
+    BoundLegacy(PeTimestamp),
+    /// Timestamp `0xFFFFFFFF`; the IAT is bound, and the bound import directory has the timestamps.
+    BoundNew,
+}
+
+impl From<PeTimestamp> for ImportBinding {
+    fn from(value: PeTimestamp) -> Self {
+        match value.raw() {
+            0 => Self::NotBound,
+            u32::MAX => Self::BoundNew,
+            _ => Self::BoundLegacy(value),
+        }
+    }
+}
+
+impl Display for ImportBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotBound => write!(f, "not bound"),
+            Self::BoundLegacy(ts) => write!(f, "bound at {ts}"),
+            Self::BoundNew => write!(f, "bound, see bound imports"),
+        }
+    }
+}
+
+
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct ImportDescriptor {
     pub ilt: HeaderField<u32>,
-    pub timestamp: HeaderField<DateTime<Utc>>,
+    pub timestamp: HeaderField<PeTimestamp>,
     pub forwarder_chain: HeaderField<u32>,
     pub name_rva: HeaderField<u32>,
     pub first_thunk: HeaderField<u32>,
     pub name: Option<String>,
     pub imports: Vec<ImportLookup>,
+    /// IAT slots not matching the lookup table. Before loading they match, unless the IAT is
+    /// bound (see `is_bound`) or was tampered with.
+    pub iat_mismatches: Vec<IatMismatch>,
 }
 
 
 impl Display for ImportDescriptor {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{{ {}, ILT: {:#08x}, Imports: {}, Timestamp: {} }}",
-            self.name.as_ref().unwrap_or(&String::from("ERR")), self.ilt.value, self.imports.len(), self.timestamp.value.to_rfc3339()
+        write!(f, "{{ {}, ILT: {:#08x}, Imports: {}, Binding: {} }}",
+            self.name.as_ref().unwrap_or(&String::from("ERR")), self.ilt.value, self.imports.len(), self.binding()
         )
     }
 }
@@ -95,64 +259,85 @@ impl ImportDescriptor {
         Self::default()
     }
 
-    pub fn parse_imports(&mut self, sections: &SectionTable, image_type: ImageType, reader: &mut impl BufReadExt) -> Result<()> {
-        let mut rva = self.ilt.value;
-        let mut offset = section::rva_to_offset(sections, rva).ok_or(PeError::InvalidRVA(rva.into()))?;
+    pub fn parse_imports(&mut self, sections: &impl SectionLookup, image_type: ImageType, reader: &mut impl BufReadExt) -> Result<()> {
+        self.parse_imports_with_limit(sections, image_type, reader, u32::MAX)
+    }
 
-        match image_type {            
-            ImageType::PE32 => {                
-                loop {
-                    let val = reader.read_bytes_at_offset(offset.into(), 4)?;
-                    let value = LittleEndian::read_u32(&val);
-                    if value == 0 {
-                        break;
-                    }
-                    
-                    let mut import = ImportLookup::from(HeaderField { value, offset: offset.into(), rva: rva.into() });
-                    import.update_name(sections, reader)?;
+    /// Same as `parse_imports`, but fails with `TooMany` after `max_imports` entries.
+    pub fn parse_imports_with_limit(&mut self, sections: &impl SectionLookup, image_type: ImageType, reader: &mut impl BufReadExt, max_imports: u32) -> Result<()> {
+        self.parse_imports_cached(sections, image_type, reader, max_imports, &mut NameCache::default())
+    }
 
-                    self.imports.push(import);
+    /// Same as `parse_imports_with_limit`, reusing names read for other descriptors.
+    pub(crate) fn parse_imports_cached(&mut self, sections: &impl SectionLookup, image_type: ImageType, reader: &mut impl BufReadExt, max_imports: u32, cache: &mut NameCache) -> Result<()> {
+        self.imports = parse_lookup_table(sections, image_type, reader, self.ilt.value, self.first_thunk.value, 0, max_imports, cache)
+            .map_err(|err| match err {
+                PeError::InvalidHeader { reason, .. } => PeError::InvalidHeader { name: "ImportDescriptor".into(), offset: self.ilt.offset.into(), reason },
+                err => err,
+            })?;
 
-                    offset += 4;
-                    rva += 4;
-                }
-            }
-            
-            ImageType::PE64 => {
-                loop {
-                    let val = reader.read_bytes_at_offset(offset.into(), 8)?;
-                    let value = LittleEndian::read_u64(&val);
-                    if value == 0 {
-                        break;
-                    }
-                    
-                    let mut import = ImportLookup::from(HeaderField { value, offset: offset.into(), rva: rva.into() });
-                    import.update_name(sections, reader)?;
+        self.check_iat(sections, image_type, reader);
+        Ok(())
+    }
 
-                    self.imports.push(import);
+    /// Compare IAT slots with parsed lookup table entries, filling `iat_mismatches`.
+    /// Nothing is compared if the IAT is the lookup table, or can't be read.
+    fn check_iat(&mut self, sections: &impl SectionLookup, image_type: ImageType, reader: &mut impl BufReadExt) {
+        self.iat_mismatches.clear();
+        if self.first_thunk.value == self.ilt.value || self.imports.is_empty() {
+            return;
+        }
 
-                    offset += 8;
-                    rva += 8;
-                }
-            }
+        let entry_size = if image_type == ImageType::PE64 { 8 } else { 4 };
+        let Some(offset) = section::rva_to_offset(sections, self.first_thunk.value) else {
+            return;
+        };
+        let Ok(iat) = reader.read_bytes_at_offset(offset.into(), self.imports.len() * entry_size) else {
+            return;
+        };
+
+        self.iat_mismatches = iat.chunks_exact(entry_size)
+            .zip(&self.imports)
+            .map(|(slot, import)| {
+                let iat_value = if entry_size == 8 { LittleEndian::read_u64(slot) } else { LittleEndian::read_u32(slot).into() };
+                IatMismatch { iat_rva: import.iat_rva(), ilt_value: import.value(), iat_value }
+            })
+            .filter(|m| m.iat_value != m.ilt_value)
+            .collect();
+    }
 
-            _ => unimplemented!(), //TODO: Needs to change
-        }
-        Ok(())
+    /// Whether the IAT is bound, i.e. holds addresses resolved ahead of loading.
+    #[inline]
+    pub fn is_bound(&self) -> bool {
+        self.binding() != ImportBinding::NotBound
+    }
+
+    #[inline]
+    pub fn binding(&self) -> ImportBinding {
+        ImportBinding::from(self.timestamp.value)
     }
 
 
     pub fn fix_rvas(&mut self, sections: &SectionTable) -> Result<()> {
-        self.ilt.rva = offset_to_rva(sections, self.ilt.offset as u32).ok_or(PeError::InvalidOffset(self.ilt.offset))? as u64;
-        self.timestamp.rva = offset_to_rva(sections, self.timestamp.offset as u32).ok_or(PeError::InvalidOffset(self.timestamp.offset))? as u64;
-        self.forwarder_chain.rva = offset_to_rva(sections, self.forwarder_chain.offset as u32).ok_or(PeError::InvalidOffset(self.forwarder_chain.offset))? as u64;
-        self.name_rva.rva = offset_to_rva(sections, self.name_rva.offset as u32).ok_or(PeError::InvalidOffset(self.name_rva.offset))? as u64;
-        self.first_thunk.rva = offset_to_rva(sections, self.first_thunk.offset as u32).ok_or(PeError::InvalidOffset(self.first_thunk.offset))? as u64;
+        self.ilt.rva = offset_to_rva(sections, self.ilt.offset).ok_or(PeError::InvalidOffset(self.ilt.offset.into()))?.into();
+        self.timestamp.rva = offset_to_rva(sections, self.timestamp.offset).ok_or(PeError::InvalidOffset(self.timestamp.offset.into()))?.into();
+        self.forwarder_chain.rva = offset_to_rva(sections, self.forwarder_chain.offset).ok_or(PeError::InvalidOffset(self.forwarder_chain.offset.into()))?.into();
+        self.name_rva.rva = offset_to_rva(sections, self.name_rva.offset).ok_or(PeError::InvalidOffset(self.name_rva.offset.into()))?.into();
+        self.first_thunk.rva = offset_to_rva(sections, self.first_thunk.offset).ok_or(PeError::InvalidOffset(self.first_thunk.offset.into()))?.into();
         Ok(())
     }
 
 
-    pub fn update_name(&mut self, sections: &SectionTable, reader: &mut impl BufReadExt) -> Result<()> {
+    /// Set field rvas for a descriptor at `rva`, keeping their offsets.
+    fn set_rva(&mut self, rva: Rva) {
+        self.ilt.rva = rva;
+        self.timestamp.rva = rva + 4;
+        self.forwarder_chain.rva = rva + 8;
+        self.name_rva.rva = rva + 12;
+        self.first_thunk.rva = rva + 16;
+    }
+
+    pub fn update_name(&mut self, sections: &impl SectionLookup, reader: &mut impl BufReadExt) -> Result<()> {
         let offset = rva_to_offset(sections, self.name_rva.value).ok_or(PeError::InvalidRVA(self.name_rva.value.into()))?;
         self.name = Some(reader.read_string_at_offset(offset as u64)?);
         Ok(())
@@ -165,7 +350,7 @@ impl ImportDescriptor {
  
 
 impl Header for ImportDescriptor {
-    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> where Self: Sized {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> where Self: Sized {
         let mut cursor = Cursor::new(bytes);
         let mut offset = pos;
 
@@ -173,8 +358,7 @@ impl Header for ImportDescriptor {
         id.ilt = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
 
         let dt = cursor.read_u32::<LittleEndian>()?;
-        let ts = DateTime::<Utc>::from_timestamp(dt.into(), 0).ok_or(PeError::InvalidTimestamp(dt.into() ))?; //TODO: switch to import specific timestamp error?
-        id.timestamp = HeaderField {value: ts, offset: offset, rva: offset};
+        id.timestamp = HeaderField::at_offset(PeTimestamp::from(dt), offset);
         offset += size_of::<u32>() as u64;
 
         id.forwarder_chain = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
@@ -193,8 +377,100 @@ impl Header for ImportDescriptor {
 }
 
 
+/// Parse a null terminated import lookup table at `table_rva`, with names.
+/// The IAT parallels the table; slot `i` is at `iat_rva` + `i` * entry size.
+/// `name_bias` is subtracted from name addresses, for tables holding VAs instead of rvas.
+/// Hint/name entries are looked up in `cache` before they are read.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn parse_lookup_table(sections: &impl SectionLookup, image_type: ImageType, reader: &mut impl BufReadExt, table_rva: u32, iat_rva: u32, name_bias: u64, max_imports: u32, cache: &mut NameCache) -> Result<Vec<ImportLookup>> {
+    let mut rva = table_rva;
+    let mut offset = section::rva_to_offset(sections, rva).ok_or(PeError::InvalidRVA(rva.into()))?;
+    let mut imports = Vec::new();
+
+    match image_type {
+        ImageType::PE32 => {
+            loop {
+                let val = reader.read_bytes_at_offset(offset.into(), 4)?;
+                let value = LittleEndian::read_u32(&val);
+                if value == 0 {
+                    break;
+                }
+
+                check_count("Imports", imports.len() as u64 + 1, max_imports.into())?;
+                let mut import = ImportLookup::X86(ImportLookup32 {
+                    iat_rva: iat_rva.wrapping_add((imports.len() * 4) as u32),
+                    ..ImportLookup32::new(HeaderField { value, offset: offset.into(), rva: rva.into() })
+                });
+                import.unbias_name(name_bias);
+                import.update_name_cached(sections, reader, cache)?;
+                imports.push(import);
+
+                offset += 4;
+                rva += 4;
+            }
+        }
+
+        ImageType::PE64 => {
+            loop {
+                let val = reader.read_bytes_at_offset(offset.into(), 8)?;
+                let value = LittleEndian::read_u64(&val);
+                if value == 0 {
+                    break;
+                }
+
+                check_count("Imports", imports.len() as u64 + 1, max_imports.into())?;
+                let mut import = ImportLookup::X64(ImportLookup64 {
+                    iat_rva: iat_rva.wrapping_add((imports.len() * 8) as u32),
+                    ..ImportLookup64::new(HeaderField { value, offset: offset.into(), rva: rva.into() })
+                });
+                import.unbias_name(name_bias);
+                import.update_name_cached(sections, reader, cache)?;
+                imports.push(import);
+
+                offset += 8;
+                rva += 8;
+            }
+        }
+
+        _ => return Err(PeError::InvalidHeader {
+            name: "ImportLookupTable".into(),
+            offset: offset.into(),
+            reason: format!("imports can't be parsed for image type {image_type:?}"),
+        }),
+    }
+
+    Ok(imports)
+}
+
+
 pub type ImportDirectory = Vec<HeaderField<ImportDescriptor>>;
 
+/// Parse the import directory at `offset` (`rva` in the image), one descriptor at a time, until
+/// the null descriptor or `size` bytes. A bogus directory size is never allocated up front;
+/// more than `max_descriptors` descriptors fail with `TooMany`.
+pub fn parse_directory(reader: &mut impl BufReadExt, rva: Rva, offset: u64, size: u64, max_descriptors: u32) -> Result<ImportDirectory> {
+    let mut imp_dir = ImportDirectory::new();
+    let mut delta = 0u64;
+
+    //A directory without the null descriptor ends with its size.
+    while delta + IMPORT_DESCRIPTOR_SIZE as u64 <= size {
+        let desc_offset = offset + delta;
+        let bytes = reader.read_bytes_at_offset(desc_offset, IMPORT_DESCRIPTOR_SIZE)?;
+        let mut idesc = ImportDescriptor::parse_bytes(bytes, desc_offset)?;
+        if !idesc.is_valid() {
+            break;
+        }
+        check_count("ImportDirectory", imp_dir.len() as u64 + 1, max_descriptors.into())?;
+
+        let desc_rva = rva + delta as u32;
+        idesc.set_rva(desc_rva);
+        imp_dir.push(HeaderField { value: idesc, offset: FileOffset(desc_offset), rva: desc_rva });
+        delta += IMPORT_DESCRIPTOR_SIZE as u64;
+    }
+
+    Ok(imp_dir)
+}
+
 impl Header for ImportDirectory {
     fn parse_bytes(bytes: Vec<u8>, pos: u64) -> std::result::Result<Self, PeError> where Self: Sized {
         let mut imp_dir = Self::new();
@@ -202,14 +478,15 @@ impl Header for ImportDirectory {
         let mut slice_start = 0 as usize;
         let mut slice_end = slice_start + (IMPORT_DESCRIPTOR_SIZE as usize);
 
-        loop {
+        //A directory without the null descriptor ends with the buffer.
+        while slice_end <= bytes.len() {
             let buf = &bytes[slice_start..slice_end];
             
             let idesc = ImportDescriptor::parse_bytes(buf.to_vec(), curr_pos)?;
             if !idesc.is_valid(){
                 break;
             }
-            imp_dir.push(HeaderField { value: idesc, offset: curr_pos, rva: curr_pos });
+            imp_dir.push(HeaderField::at_offset(idesc, curr_pos));
 
             curr_pos += IMPORT_DESCRIPTOR_SIZE as u64;
             slice_start = slice_end;
@@ -219,26 +496,10 @@ impl Header for ImportDirectory {
         Ok(imp_dir)
     }
 
-    fn parse_buf(reader: &mut impl BufReadExt, pos: u64, offset: u64) -> crate::Result<Self> where Self: Sized {
-        let mut imp_dir = Self::new();
-        let mut delta = 0;
-
-        loop {
-            let bytes = reader.read_bytes_at_offset(offset + delta, IMPORT_DESCRIPTOR_SIZE)?;
-
-            let idesc = ImportDescriptor::parse_bytes(bytes, pos + delta)?;
-            
-            let old_offset = offset;
-            delta += IMPORT_DESCRIPTOR_SIZE as u64;
-
-            if !idesc.is_valid() {                
-                break; 
-            }
-
-            imp_dir.push(HeaderField { value: idesc, offset: old_offset, rva: old_offset });
-        }
-
-        Ok(imp_dir)
+    /// Parse descriptors from `offset` until the null descriptor; `pos` is the rva of the directory.
+    fn parse_buf(reader: &mut impl BufReadExt, pos: u64, offset: u64) -> crate::pe::Result<Self> where Self: Sized {
+        let rva = Rva::try_from(pos).map_err(|_| PeError::InvalidRVA(pos))?;
+        parse_directory(reader, rva, offset, u64::MAX, u32::MAX)
     }
 
     fn is_valid(&self) -> bool {
@@ -255,12 +516,75 @@ impl Header for ImportDirectory {
 }
 
 
+/// Iterator over import descriptors of a `PeImage`, read one at a time. Created by `PeImage::imports_stream`.
+/// Each descriptor is yielded with its name and imports, as in `PeImage::parse_import_directory`,
+/// but descriptors after the last one consumed are never read.
+pub struct ImportStream<'a> {
+    pe: &'a mut PeImage,
+    next: u32,
+    done: bool,
+    names: NameCache,
+}
+
+impl<'a> ImportStream<'a> {
+    pub(crate) fn new(pe: &'a mut PeImage) -> Self {
+        Self { pe, next: 0, done: false, names: NameCache::default() }
+    }
+
+    fn read_next(&mut self) -> Result<Option<HeaderField<ImportDescriptor>>> {
+        let Some(dir) = self.pe.directory(DirectoryType::Import) else {
+            return Ok(None);
+        };
+        let (dir_rva, dir_size) = (dir.rva.value, dir.size.value as u64);
+
+        //A directory without the null descriptor ends with its size.
+        let delta = self.next as u64 * IMPORT_DESCRIPTOR_SIZE as u64;
+        if delta + IMPORT_DESCRIPTOR_SIZE as u64 > dir_size {
+            return Ok(None);
+        }
+
+        let dir_offset = self.pe.rva_to_offset(dir_rva).ok_or(PeError::InvalidRVA(dir_rva.into()))?;
+        let offset = dir_offset as u64 + delta;
+        let bytes = self.pe.reader.read_bytes_at_offset(offset, IMPORT_DESCRIPTOR_SIZE)?;
+        let mut id = ImportDescriptor::parse_bytes(bytes, offset)?;
+        if !id.is_valid() {
+            return Ok(None);
+        }
+        check_count("ImportDirectory", self.next as u64 + 1, self.pe.limits.max_import_descriptors.into())?;
+
+        id.fix_rvas(&self.pe.sections.value)?;
+        let sections = IndexedSections::new(&self.pe.sections.value, &self.pe.section_map);
+        id.update_name(&sections, &mut self.pe.reader)?;
+        id.parse_imports_cached(&sections, self.pe.optional.value.get_image_type(), &mut self.pe.reader, self.pe.limits.max_imports_per_module, &mut self.names)?;
+
+        self.next += 1;
+        Ok(Some(HeaderField { value: id, offset: FileOffset(offset), rva: Rva(dir_rva) + delta as u32 }))
+    }
+}
+
+impl Iterator for ImportStream<'_> {
+    type Item = Result<HeaderField<ImportDescriptor>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let next = self.read_next();
+        if !matches!(next, Ok(Some(_))) {
+            self.done = true; //stop at end or after an error
+        }
+        next.transpose()
+    }
+}
+
+
 #[cfg(test)]
 mod test {
 
-    use crate::{pe::{import::ImportLookup, optional::ImageType, section::{parse_sections, rva_to_offset, SectionTable}}, types::Header, utils::{read_string_at_offset, FragmentReader}};
+    use crate::{pe::{import::ImportLookup, optional::ImageType, PeError, PeTimestamp, section::{parse_sections, rva_to_offset, SectionTable}}, types::{FileOffset, Header, HeaderField, Rva}, utils::{read_string_at_offset, FragmentReader}};
 
-    use super::{ImportDescriptor, ImportDirectory};
+    use super::{parse_directory, ImportBinding, ImportDescriptor, ImportDirectory, NameCache, IMPORT_DESCRIPTOR_SIZE};
 
     fn parse_section_header() -> SectionTable {
         parse_sections(&SECTION_RAW, 11, 0x188).unwrap()
@@ -270,15 +594,38 @@ mod test {
     fn test_parse_import_desc() {
         let id = ImportDescriptor::parse_bytes(IDATA_RAW.to_vec(), 0x3C00).unwrap();
         assert_eq!(id.ilt.value, 0xA050);
-        assert_eq!(id.ilt.offset, 0x3C00);
-        assert_eq!(id.timestamp.offset, 0x3C04);
-        assert_eq!(id.timestamp.value.to_rfc3339(), "1970-01-01T00:00:00+00:00");
+        assert_eq!(id.ilt.offset, FileOffset(0x3C00));
+        assert_eq!(id.timestamp.offset, FileOffset(0x3C04));
+        assert_eq!(id.timestamp.value.as_datetime().unwrap().to_rfc3339(), "1970-01-01T00:00:00+00:00");
         assert_eq!(id.forwarder_chain.value, 0);
-        assert_eq!(id.forwarder_chain.offset, 0x3C08);
+        assert_eq!(id.forwarder_chain.offset, FileOffset(0x3C08));
         assert_eq!(id.name_rva.value, 0xA6BC);
-        assert_eq!(id.name_rva.offset, 0x3C0C);
+        assert_eq!(id.name_rva.offset, FileOffset(0x3C0C));
         assert_eq!(id.first_thunk.value, 0xA1F8);
-        assert_eq!(id.first_thunk.offset, 0x3C10);
+        assert_eq!(id.first_thunk.offset, FileOffset(0x3C10));
+    }
+
+    #[test]
+    fn import_desc_with_bound_timestamp() {
+        let mut bytes = IDATA_RAW[..20].to_vec();
+        bytes[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let id = ImportDescriptor::parse_bytes(bytes, 0x3C00).unwrap();
+        assert_eq!(id.timestamp.value, PeTimestamp::Raw(u32::MAX));
+        assert_eq!(id.timestamp.value.raw(), u32::MAX);
+        assert!(id.timestamp.value.as_datetime().is_none());
+        assert_eq!(id.name_rva.value, 0xA6BC);
+        assert_eq!(id.binding(), ImportBinding::BoundNew);
+        assert!(id.is_bound());
+
+        let mut bytes = IDATA_RAW[..20].to_vec();
+        bytes[4..8].copy_from_slice(&0x563bbb57u32.to_le_bytes());
+        let id = ImportDescriptor::parse_bytes(bytes, 0x3C00).unwrap();
+        assert_eq!(id.binding(), ImportBinding::BoundLegacy(PeTimestamp::from(0x563bbb57)));
+        assert_eq!(id.binding().to_string(), "bound at 2015-11-05 20:25:59 UTC");
+
+        let id = ImportDescriptor::parse_bytes(IDATA_RAW[..20].to_vec(), 0x3C00).unwrap();
+        assert_eq!(id.binding(), ImportBinding::NotBound);
+        assert!(!id.is_bound());
     }
 
     #[test]
@@ -289,15 +636,15 @@ mod test {
         id.fix_rvas(&sections).unwrap();
 
         assert_eq!(id.ilt.value, 0xA050);
-        assert_eq!(id.ilt.rva, 0xA000);
-        assert_eq!(id.timestamp.rva, 0xA004);
-        assert_eq!(id.timestamp.value.to_rfc3339(), "1970-01-01T00:00:00+00:00");
+        assert_eq!(id.ilt.rva, Rva(0xA000));
+        assert_eq!(id.timestamp.rva, Rva(0xA004));
+        assert_eq!(id.timestamp.value.as_datetime().unwrap().to_rfc3339(), "1970-01-01T00:00:00+00:00");
         assert_eq!(id.forwarder_chain.value, 0);
-        assert_eq!(id.forwarder_chain.rva, 0xA008);
+        assert_eq!(id.forwarder_chain.rva, Rva(0xA008));
         assert_eq!(id.name_rva.value, 0xA6BC);
-        assert_eq!(id.name_rva.rva, 0xA00C);
+        assert_eq!(id.name_rva.rva, Rva(0xA00C));
         assert_eq!(id.first_thunk.value, 0xA1F8);
-        assert_eq!(id.first_thunk.rva, 0xA010);
+        assert_eq!(id.first_thunk.rva, Rva(0xA010));
 
         let name_offset = rva_to_offset(&sections, id.name_rva.value).unwrap() - sections[7].value.raw_data_ptr.value;
         id.name = Some(read_string_at_offset(&IDATA_RAW, name_offset as u64).unwrap());
@@ -328,6 +675,47 @@ mod test {
         assert_eq!(idir.len(), 3);
     }
 
+    #[test]
+    fn parse_idir_offsets() {
+        let idir = ImportDirectory::parse_bytes(IDATA_RAW.to_vec(), 0x3C00).unwrap();
+        let offsets: Vec<u64> = idir.iter().map(|id| id.offset.into()).collect();
+        assert_eq!(offsets, [0x3C00, 0x3C14, 0x3C28]);
+        assert_eq!(idir[1].value.name_rva.offset, FileOffset(0x3C20));
+    }
+
+    #[test]
+    fn parse_idir_from_reader() {
+        let mut reader = FragmentReader::new(IDATA_RAW.to_vec(), IDATA_RAW_OFFSET);
+        let idir = ImportDirectory::parse_buf(&mut reader, 0xA000, IDATA_RAW_OFFSET).unwrap();
+        assert_eq!(idir.len(), 3);
+
+        for (i, id) in idir.iter().enumerate() {
+            let delta = i * IMPORT_DESCRIPTOR_SIZE;
+            assert_eq!(id.offset, FileOffset(IDATA_RAW_OFFSET + delta as u64));
+            assert_eq!(id.rva, Rva(0xA000 + delta as u32));
+            assert_eq!(id.value.ilt.offset, id.offset);
+            assert_eq!(id.value.name_rva.offset, id.offset + 12);
+            assert_eq!(id.value.name_rva.rva, id.rva + 12);
+            assert_eq!(id.value.first_thunk.rva, id.rva + 16);
+        }
+    }
+
+    #[test]
+    fn parse_idir_bogus_size() {
+        let mut reader = FragmentReader::new(IDATA_RAW.to_vec(), IDATA_RAW_OFFSET);
+
+        //Stops at the null descriptor, without reading (or allocating) the claimed size.
+        let idir = parse_directory(&mut reader, Rva(0xA000), IDATA_RAW_OFFSET, u32::MAX.into(), 16).unwrap();
+        assert_eq!(idir.len(), 3);
+
+        //A directory without the null descriptor ends with its size.
+        let idir = parse_directory(&mut reader, Rva(0xA000), IDATA_RAW_OFFSET, 2 * IMPORT_DESCRIPTOR_SIZE as u64 + 4, 16).unwrap();
+        assert_eq!(idir.len(), 2);
+
+        let err = parse_directory(&mut reader, Rva(0xA000), IDATA_RAW_OFFSET, u32::MAX.into(), 2).unwrap_err();
+        assert!(matches!(err.root(), PeError::TooMany { .. }), "{err}");
+    }
+
     #[test]
     fn test_parse_idir_with_names() {
         let sections = parse_section_header();
@@ -407,6 +795,48 @@ mod test {
         }
     }
 
+    #[test]
+    fn names_read_once() {
+        let sections = parse_section_header();
+        let mut bytes = IDATA_RAW.to_vec();
+        //Second ADVAPI32 import names the same entry as the first.
+        bytes.copy_within(0x50..0x58, 0x58);
+        let mut reader = FragmentReader::new(bytes.clone(), IDATA_RAW_OFFSET);
+        let mut idir = ImportDirectory::parse_bytes(bytes, 0x3C00).unwrap();
+
+        let mut cache = NameCache::default();
+        idir[0].value.parse_imports_cached(&sections, ImageType::PE64, &mut reader, u32::MAX, &mut cache).unwrap();
+        let imports = &idir[0].value.imports;
+        assert_eq!(imports.len(), 3);
+        assert_eq!(imports[0].import_name().unwrap().name.value, "CryptAcquireContextA");
+        assert_eq!(imports[1].import_name().unwrap().name.value, "CryptAcquireContextA");
+        assert_eq!(imports[1].import_name().unwrap().name.offset, imports[0].import_name().unwrap().name.offset);
+        assert_eq!(cache.names.len(), 2);
+
+        //A reader without the names still resolves them from the cache.
+        let mut empty = FragmentReader::new(vec![0; IDATA_RAW.len()], IDATA_RAW_OFFSET);
+        idir[0].value.imports.clear();
+        idir[0].value.parse_imports_cached(&sections, ImageType::PE64, &mut reader, u32::MAX, &mut cache).unwrap();
+        for import in idir[0].value.imports.iter_mut() {
+            import.update_name_cached(&sections, &mut empty, &mut cache).unwrap();
+        }
+        assert_eq!(idir[0].value.imports[2].import_name().unwrap().name.value, "CryptReleaseContext");
+    }
+
+    #[test]
+    fn ordinal_reserved_bits() {
+        let lookup = |value: u32| ImportLookup::from(HeaderField { value, offset: FileOffset(0), rva: Rva(0) });
+        assert!(!lookup(0x8000_0010).has_reserved_bits());
+        assert!(lookup(0x8001_0010).has_reserved_bits());
+        //Name rvas may use those bits.
+        assert!(!lookup(0x0001_0010).has_reserved_bits());
+
+        let lookup = |value: u64| ImportLookup::from(HeaderField { value, offset: FileOffset(0), rva: Rva(0) });
+        assert!(!lookup(0x8000_0000_0000_0010).has_reserved_bits());
+        assert!(lookup(0x8000_0001_0000_0010).has_reserved_bits());
+        assert_eq!(lookup(0x8000_0001_0000_0010).value(), 0x8000_0001_0000_0010);
+    }
+
     //Raw data used for test
     const SECTION_RAW:[u8; 440] = [
         0x2E, 0x74, 0x65, 0x78, 0x74, 0x00, 0x00, 0x00, 0xE0, 0x20, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
@@ -571,4 +1001,4 @@ mod test {
         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
     ];
-}
\ No newline at end of file
+}
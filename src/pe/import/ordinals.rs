@@ -0,0 +1,147 @@
+//! Names of functions that modules commonly import by ordinal, as in the `ordlookup` tables of
+//! pefile. Only the Winsock table is kept; `ws2_32.dll` and `wsock32.dll` share it.
+
+/// Name of `ordinal` exported by `dll`, a module name with its extension; `None` if the module
+/// or the ordinal is not known. Module names are matched case insensitively.
+pub fn lookup(dll: &str, ordinal: u16) -> Option<&'static str> {
+    let table = match dll.to_ascii_lowercase().as_str() {
+        "ws2_32.dll" | "wsock32.dll" => WS2_32,
+        _ => return None,
+    };
+    table.binary_search_by_key(&ordinal, |&(ordinal, _)| ordinal).ok().map(|i| table[i].1)
+}
+
+/// Sorted by ordinal.
+const WS2_32: &[(u16, &str)] = &[
+    (1, "accept"),
+    (2, "bind"),
+    (3, "closesocket"),
+    (4, "connect"),
+    (5, "getpeername"),
+    (6, "getsockname"),
+    (7, "getsockopt"),
+    (8, "htonl"),
+    (9, "htons"),
+    (10, "ioctlsocket"),
+    (11, "inet_addr"),
+    (12, "inet_ntoa"),
+    (13, "listen"),
+    (14, "ntohl"),
+    (15, "ntohs"),
+    (16, "recv"),
+    (17, "recvfrom"),
+    (18, "select"),
+    (19, "send"),
+    (20, "sendto"),
+    (21, "setsockopt"),
+    (22, "shutdown"),
+    (23, "socket"),
+    (24, "GetAddrInfoW"),
+    (25, "GetNameInfoW"),
+    (26, "WSApSetPostRoutine"),
+    (27, "FreeAddrInfoW"),
+    (28, "WPUCompleteOverlappedRequest"),
+    (29, "WSAAccept"),
+    (30, "WSAAddressToStringA"),
+    (31, "WSAAddressToStringW"),
+    (32, "WSACloseEvent"),
+    (33, "WSAConnect"),
+    (34, "WSACreateEvent"),
+    (35, "WSADuplicateSocketA"),
+    (36, "WSADuplicateSocketW"),
+    (37, "WSAEnumNameSpaceProvidersA"),
+    (38, "WSAEnumNameSpaceProvidersW"),
+    (39, "WSAEnumNetworkEvents"),
+    (40, "WSAEnumProtocolsA"),
+    (41, "WSAEnumProtocolsW"),
+    (42, "WSAEventSelect"),
+    (43, "WSAGetOverlappedResult"),
+    (44, "WSAGetQOSByName"),
+    (45, "WSAGetServiceClassInfoA"),
+    (46, "WSAGetServiceClassInfoW"),
+    (47, "WSAGetServiceClassNameByClassIdA"),
+    (48, "WSAGetServiceClassNameByClassIdW"),
+    (49, "WSAHtonl"),
+    (50, "WSAHtons"),
+    (51, "gethostbyaddr"),
+    (52, "gethostbyname"),
+    (53, "getprotobyname"),
+    (54, "getprotobynumber"),
+    (55, "getservbyname"),
+    (56, "getservbyport"),
+    (57, "gethostname"),
+    (58, "WSAInstallServiceClassA"),
+    (59, "WSAInstallServiceClassW"),
+    (60, "WSAIoctl"),
+    (61, "WSAJoinLeaf"),
+    (62, "WSALookupServiceBeginA"),
+    (63, "WSALookupServiceBeginW"),
+    (64, "WSALookupServiceEnd"),
+    (65, "WSALookupServiceNextA"),
+    (66, "WSALookupServiceNextW"),
+    (67, "WSANSPIoctl"),
+    (68, "WSANtohl"),
+    (69, "WSANtohs"),
+    (70, "WSAProviderConfigChange"),
+    (71, "WSARecv"),
+    (72, "WSARecvDisconnect"),
+    (73, "WSARecvFrom"),
+    (74, "WSARemoveServiceClass"),
+    (75, "WSAResetEvent"),
+    (76, "WSASend"),
+    (77, "WSASendDisconnect"),
+    (78, "WSASendTo"),
+    (79, "WSASetEvent"),
+    (80, "WSASetServiceA"),
+    (81, "WSASetServiceW"),
+    (82, "WSASocketA"),
+    (83, "WSASocketW"),
+    (84, "WSAStringToAddressA"),
+    (85, "WSAStringToAddressW"),
+    (86, "WSAWaitForMultipleEvents"),
+    (87, "WSCDeinstallProvider"),
+    (88, "WSCEnableNSProvider"),
+    (89, "WSCEnumProtocols"),
+    (90, "WSCGetProviderPath"),
+    (91, "WSCInstallNameSpace"),
+    (92, "WSCInstallProvider"),
+    (93, "WSCUnInstallNameSpace"),
+    (94, "WSCUpdateProvider"),
+    (95, "WSCWriteNameSpaceOrder"),
+    (96, "WSCWriteProviderOrder"),
+    (97, "freeaddrinfo"),
+    (98, "getaddrinfo"),
+    (99, "getnameinfo"),
+    (101, "WSAAsyncSelect"),
+    (102, "WSAAsyncGetHostByAddr"),
+    (103, "WSAAsyncGetHostByName"),
+    (104, "WSAAsyncGetProtoByNumber"),
+    (105, "WSAAsyncGetProtoByName"),
+    (106, "WSAAsyncGetServByPort"),
+    (107, "WSAAsyncGetServByName"),
+    (108, "WSACancelAsyncRequest"),
+    (109, "WSASetBlockingHook"),
+    (110, "WSAUnhookBlockingHook"),
+    (111, "WSAGetLastError"),
+    (112, "WSASetLastError"),
+    (113, "WSACancelBlockingCall"),
+    (114, "WSAIsBlocking"),
+    (115, "WSAStartup"),
+    (116, "WSACleanup"),
+    (151, "__WSAFDIsSet"),
+    (500, "WEP"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{lookup, WS2_32};
+
+    #[test]
+    fn lookup_winsock() {
+        assert!(WS2_32.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(lookup("WS2_32.dll", 115), Some("WSAStartup"));
+        assert_eq!(lookup("wsock32.dll", 23), Some("socket"));
+        assert_eq!(lookup("ws2_32.dll", 100), None);
+        assert_eq!(lookup("kernel32.dll", 1), None);
+    }
+}
@@ -0,0 +1,115 @@
+//! One table of imports per module, merging the import, bound import and delay import directories.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::pe::{PeImage, PeTimestamp};
+
+use super::ImportedFunction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ImportMechanism {
+    /// Resolved by the loader when the image is loaded.
+    Static,
+    /// Static, with addresses written to the IAT ahead of loading.
+    Bound,
+    /// Resolved on first call, by the delay load helper.
+    Delay,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportEntry {
+    pub function: ImportedFunction,
+    pub mechanism: ImportMechanism,
+    /// RVA of the IAT slot; of the delay load IAT for `Delay`.
+    pub thunk_rva: u32,
+    /// Address in the IAT on disk, if it was bound.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub bound_address: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModuleImports {
+    pub module: String,
+    /// Timestamp of the module the IAT was bound against.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub bound_timestamp: Option<PeTimestamp>,
+    /// Modules the bound module forwards to, from the bound import directory.
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub bound_forwarders: Vec<String>,
+    pub imports: Vec<ImportEntry>,
+}
+
+impl Display for ModuleImports {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.module)?;
+        if let Some(timestamp) = &self.bound_timestamp {
+            write!(f, " (Bound: {timestamp})")?;
+        }
+        writeln!(f, ": [")?;
+        for import in &self.imports {
+            write!(f, "  {}, {:?} @ {:#08x}", import.function, import.mechanism, import.thunk_rva)?;
+            match import.bound_address {
+                Some(address) => writeln!(f, " -> {address:#x}")?,
+                None => writeln!(f)?,
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+fn module_entry<'a>(modules: &'a mut Vec<ModuleImports>, name: &str) -> &'a mut ModuleImports {
+    let index = match modules.iter().position(|m| m.module.eq_ignore_ascii_case(name)) {
+        Some(index) => index,
+        None => {
+            modules.push(ModuleImports { module: name.into(), bound_timestamp: None, bound_forwarders: Vec::new(), imports: Vec::new() });
+            modules.len() - 1
+        },
+    };
+    &mut modules[index]
+}
+
+/// Merge parsed import directories of `pe`, in the order modules first appear in
+/// the import directory, then in the delay import directory.
+pub(crate) fn merge(pe: &PeImage) -> Vec<ModuleImports> {
+    let mut modules = Vec::new();
+
+    for desc in pe.imports.value.iter().map(|id| &id.value) {
+        let name = desc.name.clone().unwrap_or_default();
+        let bound = pe.bound_imports.value.iter()
+            .map(|b| &b.value)
+            .find(|b| b.name.eq_ignore_ascii_case(&name));
+
+        let entry = module_entry(&mut modules, &name);
+        if let Some(bound) = bound {
+            entry.bound_timestamp = Some(bound.timestamp.value);
+            entry.bound_forwarders = bound.forwarder_refs.iter().map(|r| r.name.clone()).collect();
+        }
+
+        let is_bound = bound.is_some() || desc.is_bound();
+        entry.imports.extend(desc.imports.iter().map(|import| {
+            let thunk_rva = import.iat_rva();
+            ImportEntry {
+                function: ImportedFunction::from(import),
+                mechanism: if is_bound { ImportMechanism::Bound } else { ImportMechanism::Static },
+                thunk_rva,
+                bound_address: is_bound.then(|| {
+                    desc.iat_mismatches.iter().find(|m| m.iat_rva == thunk_rva).map(|m| m.iat_value)
+                }).flatten(),
+            }
+        }));
+    }
+
+    for desc in pe.delay_imports.value.iter().map(|d| &d.value) {
+        let entry = module_entry(&mut modules, desc.name.as_deref().unwrap_or_default());
+        entry.imports.extend(desc.imports.iter().map(|import| ImportEntry {
+            function: ImportedFunction::from(import),
+            mechanism: ImportMechanism::Delay,
+            thunk_rva: import.iat_rva(),
+            bound_address: None,
+        }));
+    }
+
+    modules
+}
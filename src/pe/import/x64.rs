@@ -1,17 +1,17 @@
 use std::fmt::Display;
 
-use byteorder::{LittleEndian, ByteOrder};
+use crate::{pe::section::SectionLookup, types::{BufReadExt, FileOffset, HeaderField, Rva}};
 
-use crate::{pe::{section::{self, SectionTable}, PeError}, types::{BufReadExt, HeaderField}};
+use super::{ImportName, NameCache};
 
-use super::ImportName;
-
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct ImportLookup64 {
     pub value: HeaderField<u64>,
     pub is_ordinal: bool,
     pub ordinal: Option<u16>,
     pub iname: Option<HeaderField<ImportName>>,
+    /// RVA of the IAT slot patched with the address of this import at load time.
+    pub iat_rva: u32,
 }
 
 impl Display for ImportLookup64 {
@@ -44,28 +44,25 @@ impl ImportLookup64 {
         }
         else {
             let iname_rva = (val as u32) & 0x7FFFFFFF;
-            name = Some(HeaderField{value: Default::default(), offset: 0, rva: iname_rva as u64});
+            name = Some(HeaderField{value: Default::default(), offset: FileOffset(0), rva: Rva(iname_rva)});
         }
 
         Self { 
             value: value, 
             is_ordinal: is_ordinal,
             ordinal: ordinal,
-            iname: name, 
+            iname: name,
+            iat_rva: 0,
         }
     }
 
-    pub fn update_name(&mut self, sections: &SectionTable, reader: &mut dyn BufReadExt) -> crate::Result<()> {
+    pub fn update_name(&mut self, sections: &impl SectionLookup, reader: &mut dyn BufReadExt) -> crate::pe::Result<()> {
+        self.update_name_cached(sections, reader, &mut NameCache::default())
+    }
+
+    pub(crate) fn update_name_cached(&mut self, sections: &impl SectionLookup, reader: &mut dyn BufReadExt, cache: &mut NameCache) -> crate::pe::Result<()> {
         if let Some(iname) = &mut self.iname {
-            let offset = section::rva_to_offset(sections, iname.rva as u32).ok_or(PeError::InvalidRVA(iname.rva))?;
-            let hint = reader.read_bytes_at_offset(offset.into(), 2)?;
-            let hint = LittleEndian::read_u16(&hint);
-            let name = reader.read_string_at_offset((offset+2).into())?;
-            iname.offset = offset.into();
-            iname.value = ImportName {
-                hint: HeaderField { value: hint, offset: offset.into(), rva: iname.rva },
-                name: HeaderField { value: name, offset: (offset+2).into(), rva: iname.rva+2 }
-            };
+            *iname = cache.read(sections, reader, iname.rva)?;
         }
         Ok(())
     }
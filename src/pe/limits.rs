@@ -0,0 +1,63 @@
+//! Limits on sizes and counts read from an image, so malformed input can't trigger huge
+//! allocations or unbounded loops.
+
+use serde::Serialize;
+
+use super::{rsrc, section, PeError, DEFAULT_MAX_ALLOC};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ParseLimits {
+    /// Max size of any single buffer allocated based on size fields in the image.
+    pub max_alloc: u64,
+    /// Max number of sections in the section table. The table must also fit in the input.
+    pub max_sections: u16,
+    /// Max `number_of_functions` (and `number_of_names`) of the export directory.
+    pub max_exports: u32,
+    /// Max number of import descriptors.
+    pub max_import_descriptors: u32,
+    /// Max number of functions imported from a single module.
+    pub max_imports_per_module: u32,
+    /// Max nesting of resource directories.
+    pub max_rsrc_depth: u32,
+    /// Max number of entries in the whole resource tree.
+    pub max_rsrc_entries: u32,
+}
+
+impl Default for ParseLimits {
+    /// Lenient limits; only sizes that can't be valid, or are far beyond what linkers produce, are rejected.
+    fn default() -> Self {
+        Self {
+            max_alloc: DEFAULT_MAX_ALLOC,
+            max_sections: section::DEFAULT_MAX_SECTIONS,
+            max_exports: u32::MAX,
+            max_import_descriptors: u32::MAX,
+            max_imports_per_module: u32::MAX,
+            max_rsrc_depth: rsrc::DEFAULT_MAX_DEPTH,
+            max_rsrc_entries: rsrc::DEFAULT_MAX_ENTRIES,
+        }
+    }
+}
+
+impl ParseLimits {
+    /// Tight limits for untrusted input. Valid images rarely come close to these.
+    /// Sections are allowed beyond the 96 older loaders took.
+    pub const fn strict() -> Self {
+        Self {
+            max_alloc: 16 * 1024 * 1024,
+            max_sections: 1024,
+            max_exports: 0x10000,
+            max_import_descriptors: 1024,
+            max_imports_per_module: 0x4000,
+            max_rsrc_depth: 8,
+            max_rsrc_entries: 0x4000,
+        }
+    }
+}
+
+/// Returns `TooMany` if `count` is more than `max`.
+pub fn check_count(target: &str, count: u64, max: u64) -> crate::pe::Result<()> {
+    if count > max {
+        return Err(PeError::TooMany { target: target.into(), count, max });
+    }
+    Ok(())
+}
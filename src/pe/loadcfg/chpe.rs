@@ -0,0 +1,275 @@
+//! Hybrid metadata of ARM64EC and ARM64X images (`IMAGE_ARM64EC_METADATA`).
+//!
+//! The code map splits the image into ranges of ARM64, ARM64EC and x64 code; redirection
+//! entries map x64 entry points to their ARM64EC implementation.
+
+use std::{fmt::Display, io::Cursor};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::Serialize;
+
+use crate::{new_header_field, pe::Result, types::{BufReadExt, FileOffset, HeaderField, Rva}};
+
+use super::super::{check_alloc, section::{self, SectionLookup}, PeError};
+
+/// Length of version 1 of `IMAGE_ARM64EC_METADATA`.
+pub const HEADER_LENGTH: u64 = 80;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum CodeType {
+    #[default]
+    Arm64,
+    Arm64EC,
+    X64,
+    Unknown,
+}
+
+impl From<u32> for CodeType {
+    fn from(value: u32) -> Self {
+        match value & 0x3 {
+            0 => Self::Arm64,
+            1 => Self::Arm64EC,
+            2 => Self::X64,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// An entry of the code map (`IMAGE_CHPE_RANGE_ENTRY`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct CodeRange {
+    pub start: u32,
+    pub length: u32,
+    pub code_type: CodeType,
+}
+
+impl CodeRange {
+    #[inline]
+    pub fn end(&self) -> u32 {
+        self.start.saturating_add(self.length)
+    }
+
+    #[inline]
+    pub fn contains(&self, rva: u32) -> bool {
+        rva >= self.start && rva < self.end()
+    }
+}
+
+impl Display for CodeRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{:#08x}..{:#08x}) {:?}", self.start, self.end(), self.code_type)
+    }
+}
+
+/// An entry of `IMAGE_ARM64EC_CODE_RANGE_ENTRY_POINT`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct EntryPointRange {
+    pub start: u32,
+    pub end: u32,
+    pub entry_point: u32,
+}
+
+/// An entry of `IMAGE_ARM64EC_REDIRECTION_ENTRY`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct Redirection {
+    pub source: u32,
+    pub destination: u32,
+}
+
+impl Display for Redirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#08x} -> {:#08x}", self.source, self.destination)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ChpeMetadata {
+    pub version: HeaderField<u32>,
+    pub code_map: HeaderField<u32>,
+    pub code_map_count: HeaderField<u32>,
+    pub code_ranges_to_entry_points: HeaderField<u32>,
+    pub redirection_metadata: HeaderField<u32>,
+    pub os_arm64x_dispatch_call_no_redirect: HeaderField<u32>,
+    pub os_arm64x_dispatch_ret: HeaderField<u32>,
+    pub os_arm64x_dispatch_call: HeaderField<u32>,
+    pub os_arm64x_dispatch_icall: HeaderField<u32>,
+    pub os_arm64x_dispatch_icall_cfg: HeaderField<u32>,
+    pub alternate_entry_point: HeaderField<u32>,
+    pub auxiliary_iat: HeaderField<u32>,
+    pub code_ranges_to_entry_points_count: HeaderField<u32>,
+    pub redirection_metadata_count: HeaderField<u32>,
+    pub get_x64_information_function_pointer: HeaderField<u32>,
+    pub set_x64_information_function_pointer: HeaderField<u32>,
+    pub extra_rfe_table: HeaderField<u32>,
+    pub extra_rfe_table_size: HeaderField<u32>,
+    pub os_arm64x_dispatch_fptr: HeaderField<u32>,
+    pub auxiliary_iat_copy: HeaderField<u32>,
+
+    pub code_ranges: Vec<HeaderField<CodeRange>>,
+    pub entry_points: Vec<HeaderField<EntryPointRange>>,
+    pub redirections: Vec<HeaderField<Redirection>>,
+}
+
+impl ChpeMetadata {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Parse the fixed part of the metadata from `bytes` read at `pos`.
+    pub fn parse_bytes(bytes: Vec<u8>, pos: u64) -> Result<Self> {
+        let bytes_len = bytes.len() as u64;
+        if bytes_len < HEADER_LENGTH {
+            return Err(PeError::BufferTooSmall { target: "ChpeMetadata".into(), expected: HEADER_LENGTH, actual: bytes_len });
+        }
+
+        let mut cursor = Cursor::new(bytes);
+        let mut offset = pos;
+
+        let mut md = Self::new();
+        md.version = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.code_map = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.code_map_count = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.code_ranges_to_entry_points = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.redirection_metadata = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.os_arm64x_dispatch_call_no_redirect = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.os_arm64x_dispatch_ret = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.os_arm64x_dispatch_call = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.os_arm64x_dispatch_icall = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.os_arm64x_dispatch_icall_cfg = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.alternate_entry_point = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.auxiliary_iat = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.code_ranges_to_entry_points_count = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.redirection_metadata_count = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.get_x64_information_function_pointer = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.set_x64_information_function_pointer = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.extra_rfe_table = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.extra_rfe_table_size = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.os_arm64x_dispatch_fptr = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        md.auxiliary_iat_copy = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+
+        Ok(md)
+    }
+
+    /// Set `rva` of fixed fields, `rva` being the rva of the structure.
+    pub fn fix_rvas(&mut self, rva: Rva) {
+        let base = self.version.offset;
+        macro_rules! fix {
+            ($($field:ident),+) => { $( self.$field.rva = rva + (self.$field.offset - base) as u32; )+ };
+        }
+        fix!(version, code_map, code_map_count, code_ranges_to_entry_points, redirection_metadata,
+            os_arm64x_dispatch_call_no_redirect, os_arm64x_dispatch_ret, os_arm64x_dispatch_call,
+            os_arm64x_dispatch_icall, os_arm64x_dispatch_icall_cfg, alternate_entry_point, auxiliary_iat,
+            code_ranges_to_entry_points_count, redirection_metadata_count, get_x64_information_function_pointer,
+            set_x64_information_function_pointer, extra_rfe_table, extra_rfe_table_size, os_arm64x_dispatch_fptr,
+            auxiliary_iat_copy);
+    }
+
+    /// Read `count` entries of `entry_size` bytes at `rva`, decoding each with `decode`.
+    fn parse_table<T>(sections: &impl SectionLookup, reader: &mut impl BufReadExt, rva: u32, count: u32,
+        entry_size: u64, max_alloc: u64, decode: impl Fn(&mut Cursor<Vec<u8>>) -> std::io::Result<T>) -> Result<Vec<HeaderField<T>>>
+    {
+        if rva == 0 || count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let size = count as u64 * entry_size;
+        check_alloc("ChpeMetadata table", size, max_alloc)?;
+        let offset = section::rva_to_offset(sections, rva).ok_or(PeError::InvalidRVA(rva.into()))?;
+        let mut cursor = Cursor::new(reader.read_bytes_at_offset(offset.into(), size as usize)?);
+
+        let mut entries = Vec::with_capacity(count as usize);
+        for i in 0..count as u64 {
+            let value = decode(&mut cursor)?;
+            entries.push(HeaderField { value, offset: FileOffset::from(offset) + i * entry_size, rva: Rva(rva) + (i * entry_size) as u32 });
+        }
+        Ok(entries)
+    }
+
+    /// Read code map, entry point ranges and redirection tables.
+    pub fn parse_tables(&mut self, sections: &impl SectionLookup, reader: &mut impl BufReadExt, max_alloc: u64) -> Result<()> {
+        self.code_ranges = Self::parse_table(sections, reader, self.code_map.value,
+            self.code_map_count.value, 8, max_alloc, |c| {
+                let start = c.read_u32::<LittleEndian>()?;
+                let length = c.read_u32::<LittleEndian>()?;
+                Ok(CodeRange { start: start & !0x3, length, code_type: CodeType::from(start) })
+            })?;
+
+        self.entry_points = Self::parse_table(sections, reader, self.code_ranges_to_entry_points.value,
+            self.code_ranges_to_entry_points_count.value, 12, max_alloc, |c| {
+                Ok(EntryPointRange {
+                    start: c.read_u32::<LittleEndian>()?,
+                    end: c.read_u32::<LittleEndian>()?,
+                    entry_point: c.read_u32::<LittleEndian>()?,
+                })
+            })?;
+
+        self.redirections = Self::parse_table(sections, reader, self.redirection_metadata.value,
+            self.redirection_metadata_count.value, 8, max_alloc, |c| {
+                Ok(Redirection { source: c.read_u32::<LittleEndian>()?, destination: c.read_u32::<LittleEndian>()? })
+            })?;
+
+        Ok(())
+    }
+
+    /// Type of code at `rva`, if it is in the code map.
+    pub fn code_type(&self, rva: u32) -> Option<CodeType> {
+        self.code_ranges.iter().find(|r| r.value.contains(rva)).map(|r| r.value.code_type)
+    }
+}
+
+impl Display for ChpeMetadata {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{Version: {}, CodeRanges: {}, EntryPoints: {}, Redirections: {}, AlternateEntryPoint: {:#08x}}}",
+            self.version.value, self.code_ranges.len(), self.entry_points.len(), self.redirections.len(),
+            self.alternate_entry_point.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pe::section::{SectionHeader, SectionTable}, types::{FileOffset, HeaderField, Rva}, utils::FragmentReader};
+
+    use super::{ChpeMetadata, CodeType, HEADER_LENGTH};
+
+    #[test]
+    fn parse_chpe_metadata() {
+        //Metadata at 0x100, code map at 0x200 and redirections at 0x210, all in one section.
+        let mut bytes = vec![0u8; 0x400];
+        let md = [1u32, 0x1200, 2, 0, 0x1210, 0, 0, 0, 0, 0, 0x1800, 0, 0, 1];
+        for (i, v) in md.iter().enumerate() {
+            bytes[0x100 + i * 4..0x104 + i * 4].copy_from_slice(&v.to_le_bytes());
+        }
+        for (i, v) in [0x1000u32 | 1, 0x800, 0x1800 | 2, 0x400, 0x1810, 0x1020].iter().enumerate() {
+            bytes[0x200 + i * 4..0x204 + i * 4].copy_from_slice(&v.to_le_bytes());
+        }
+
+        let section = SectionHeader {
+            virtual_address: HeaderField::at_offset(0x1000, 0),
+            virtual_size: HeaderField::at_offset(0x400, 0),
+            sizeof_raw_data: HeaderField::at_offset(0x400, 0),
+            ..Default::default()
+        };
+        let sections: SectionTable = vec![HeaderField::at_offset(section, 0)];
+
+        let mut md = ChpeMetadata::parse_bytes(bytes[0x100..0x100 + HEADER_LENGTH as usize].to_vec(), 0x100).unwrap();
+        md.fix_rvas(Rva(0x1100));
+        let mut reader = FragmentReader::new(bytes, 0);
+        md.parse_tables(&sections, &mut reader, 0x1000).unwrap();
+
+        assert_eq!(md.version.value, 1);
+        assert_eq!(md.alternate_entry_point.rva, Rva(0x1128));
+        assert_eq!(md.code_ranges.len(), 2);
+        assert_eq!(md.code_ranges[0].value.start, 0x1000);
+        assert_eq!(md.code_ranges[0].value.code_type, CodeType::Arm64EC);
+        assert_eq!(md.code_ranges[1].value.code_type, CodeType::X64);
+        assert_eq!(md.code_ranges[1].offset, FileOffset(0x208));
+        assert_eq!(md.code_type(0x1900), Some(CodeType::X64));
+        assert_eq!(md.code_type(0x2000), None);
+        assert_eq!(md.redirections.len(), 1);
+        assert_eq!(format!("{}", md.redirections[0].value), "0x001810 -> 0x001020");
+    }
+}
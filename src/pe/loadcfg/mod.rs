@@ -0,0 +1,304 @@
+//! Load configuration directory (`IMAGE_LOAD_CONFIG_DIRECTORY32/64`).
+//!
+//! The structure grew over Windows releases; `size` tells how much of it an image has.
+//! Fields past `size` are left as zero. Pointer sized fields are widened to `u64`.
+
+pub mod chpe;
+
+use std::{fmt::Display, io::Cursor};
+
+use bitflags::bitflags;
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::Serialize;
+
+use crate::{new_header_field, pe::Result, types::{HeaderField, Rva}, utils::flags_to_str};
+
+use super::PeError;
+
+/// Length of the latest known `IMAGE_LOAD_CONFIG_DIRECTORY32`.
+pub const HEADER_LENGTH_32: u64 = 0xC0;
+/// Length of the latest known `IMAGE_LOAD_CONFIG_DIRECTORY64`.
+pub const HEADER_LENGTH_64: u64 = 0x140;
+
+/// Bits of `GuardFlags` holding the CFG function table stride, not flags.
+pub const GUARD_CF_FUNCTION_TABLE_SIZE_MASK: u32 = 0xF000_0000;
+
+bitflags! {
+    /// `IMAGE_GUARD_*` flags of the load config.
+    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy, Serialize)]
+    pub struct GuardFlags: u32 {
+        const CF_INSTRUMENTED = 0x0000_0100;
+        const CFW_INSTRUMENTED = 0x0000_0200;
+        const CF_FUNCTION_TABLE_PRESENT = 0x0000_0400;
+        const SECURITY_COOKIE_UNUSED = 0x0000_0800;
+        const PROTECT_DELAYLOAD_IAT = 0x0000_1000;
+        const DELAYLOAD_IAT_IN_ITS_OWN_SECTION = 0x0000_2000;
+        const CF_EXPORT_SUPPRESSION_INFO_PRESENT = 0x0000_4000;
+        const CF_ENABLE_EXPORT_SUPPRESSION = 0x0000_8000;
+        const CF_LONGJUMP_TABLE_PRESENT = 0x0001_0000;
+        const RF_INSTRUMENTED = 0x0002_0000;
+        const RF_ENABLE = 0x0004_0000;
+        const RF_STRICT = 0x0008_0000;
+        const RETPOLINE_PRESENT = 0x0010_0000;
+        const EH_CONTINUATION_TABLE_PRESENT = 0x0040_0000;
+        const XFG_ENABLED = 0x0080_0000;
+        const CASTGUARD_PRESENT = 0x0100_0000;
+        const MEMCPY_PRESENT = 0x0200_0000;
+    }
+}
+
+impl Display for GuardFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", flags_to_str(self))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct LoadConfig {
+    pub size: HeaderField<u32>,
+    pub timestamp: HeaderField<u32>,
+    pub major_version: HeaderField<u16>,
+    pub minor_version: HeaderField<u16>,
+    pub global_flags_clear: HeaderField<u32>,
+    pub global_flags_set: HeaderField<u32>,
+    pub critical_section_default_timeout: HeaderField<u32>,
+    pub decommit_free_block_threshold: HeaderField<u64>,
+    pub decommit_total_free_threshold: HeaderField<u64>,
+    pub lock_prefix_table: HeaderField<u64>,
+    pub maximum_allocation_size: HeaderField<u64>,
+    pub virtual_memory_threshold: HeaderField<u64>,
+    pub process_affinity_mask: HeaderField<u64>,
+    pub process_heap_flags: HeaderField<u32>,
+    pub csd_version: HeaderField<u16>,
+    pub dependent_load_flags: HeaderField<u16>,
+    pub edit_list: HeaderField<u64>,
+    pub security_cookie: HeaderField<u64>,
+    pub se_handler_table: HeaderField<u64>,
+    pub se_handler_count: HeaderField<u64>,
+    pub guard_cf_check_function_pointer: HeaderField<u64>,
+    pub guard_cf_dispatch_function_pointer: HeaderField<u64>,
+    pub guard_cf_function_table: HeaderField<u64>,
+    pub guard_cf_function_count: HeaderField<u64>,
+    pub guard_flags: HeaderField<u32>,
+    pub code_integrity_flags: HeaderField<u16>,
+    pub code_integrity_catalog: HeaderField<u16>,
+    pub code_integrity_catalog_offset: HeaderField<u32>,
+    pub guard_address_taken_iat_entry_table: HeaderField<u64>,
+    pub guard_address_taken_iat_entry_count: HeaderField<u64>,
+    pub guard_long_jump_target_table: HeaderField<u64>,
+    pub guard_long_jump_target_count: HeaderField<u64>,
+    pub dynamic_value_reloc_table: HeaderField<u64>,
+    /// VA of `IMAGE_CHPE_METADATA_X86` or `IMAGE_ARM64EC_METADATA`.
+    pub chpe_metadata_pointer: HeaderField<u64>,
+    pub guard_rf_failure_routine: HeaderField<u64>,
+    pub guard_rf_failure_routine_function_pointer: HeaderField<u64>,
+    pub dynamic_value_reloc_table_offset: HeaderField<u32>,
+    pub dynamic_value_reloc_table_section: HeaderField<u16>,
+    pub guard_rf_verify_stack_pointer_function_pointer: HeaderField<u64>,
+    pub hot_patch_table_offset: HeaderField<u32>,
+    pub enclave_configuration_pointer: HeaderField<u64>,
+    pub volatile_metadata_pointer: HeaderField<u64>,
+    pub guard_eh_continuation_table: HeaderField<u64>,
+    pub guard_eh_continuation_count: HeaderField<u64>,
+    pub guard_xfg_check_function_pointer: HeaderField<u64>,
+    pub guard_xfg_dispatch_function_pointer: HeaderField<u64>,
+    pub guard_xfg_table_dispatch_function_pointer: HeaderField<u64>,
+    pub cast_guard_os_determined_failure_mode: HeaderField<u64>,
+    pub guard_memcpy_function_pointer: HeaderField<u64>,
+}
+
+/// Read a pointer sized value; 4 bytes for PE32 and 8 for PE32+.
+fn read_ptr(cursor: &mut Cursor<Vec<u8>>, offset: &mut u64, is_64: bool) -> Result<HeaderField<u64>> {
+    let pos = *offset;
+    let value = if is_64 { cursor.read_u64::<LittleEndian>()? } else { cursor.read_u32::<LittleEndian>()?.into() };
+    *offset += if is_64 { 8 } else { 4 };
+    Ok(HeaderField::at_offset(value, pos))
+}
+
+impl LoadConfig {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Length of the latest known structure for the image type.
+    #[inline]
+    pub fn max_length(is_64: bool) -> u64 {
+        if is_64 { HEADER_LENGTH_64 } else { HEADER_LENGTH_32 }
+    }
+
+    /// Parse a load config from `bytes` read at `pos`. `is_64` selects the PE32+ layout.
+    /// Only the first `size` bytes (as declared by the structure) are used.
+    pub fn parse_bytes(bytes: Vec<u8>, pos: u64, is_64: bool) -> Result<Self> {
+        let bytes_len = bytes.len() as u64;
+        if bytes_len < 4 {
+            return Err(PeError::BufferTooSmall { target: "LoadConfig".into(), expected: 4, actual: bytes_len });
+        }
+
+        let size = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64;
+        let used = size.min(bytes_len).min(Self::max_length(is_64)) as usize;
+
+        let mut buf = bytes[..used].to_vec();
+        buf.resize(Self::max_length(is_64) as usize, 0);
+        let mut cursor = Cursor::new(buf);
+        let mut offset = pos;
+
+        let mut lc = Self::new();
+        lc.size = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        lc.timestamp = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        lc.major_version = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+        lc.minor_version = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+        lc.global_flags_clear = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        lc.global_flags_set = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        lc.critical_section_default_timeout = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        lc.decommit_free_block_threshold = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.decommit_total_free_threshold = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.lock_prefix_table = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.maximum_allocation_size = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.virtual_memory_threshold = read_ptr(&mut cursor, &mut offset, is_64)?;
+
+        //Order of these two differs between layouts.
+        if is_64 {
+            lc.process_affinity_mask = read_ptr(&mut cursor, &mut offset, is_64)?;
+            lc.process_heap_flags = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        }
+        else {
+            lc.process_heap_flags = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+            lc.process_affinity_mask = read_ptr(&mut cursor, &mut offset, is_64)?;
+        }
+
+        lc.csd_version = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+        lc.dependent_load_flags = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+        lc.edit_list = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.security_cookie = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.se_handler_table = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.se_handler_count = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_cf_check_function_pointer = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_cf_dispatch_function_pointer = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_cf_function_table = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_cf_function_count = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_flags = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        lc.code_integrity_flags = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+        lc.code_integrity_catalog = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+        lc.code_integrity_catalog_offset = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        let _reserved = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        lc.guard_address_taken_iat_entry_table = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_address_taken_iat_entry_count = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_long_jump_target_table = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_long_jump_target_count = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.dynamic_value_reloc_table = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.chpe_metadata_pointer = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_rf_failure_routine = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_rf_failure_routine_function_pointer = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.dynamic_value_reloc_table_offset = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        lc.dynamic_value_reloc_table_section = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+        let _reserved2 = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+        lc.guard_rf_verify_stack_pointer_function_pointer = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.hot_patch_table_offset = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        let _reserved3 = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        lc.enclave_configuration_pointer = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.volatile_metadata_pointer = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_eh_continuation_table = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_eh_continuation_count = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_xfg_check_function_pointer = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_xfg_dispatch_function_pointer = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_xfg_table_dispatch_function_pointer = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.cast_guard_os_determined_failure_mode = read_ptr(&mut cursor, &mut offset, is_64)?;
+        lc.guard_memcpy_function_pointer = read_ptr(&mut cursor, &mut offset, is_64)?;
+
+        Ok(lc)
+    }
+
+    /// Set `rva` of all fields, `rva` being the rva of the structure.
+    pub fn fix_rvas(&mut self, rva: Rva) {
+        let base = self.size.offset;
+        macro_rules! fix {
+            ($($field:ident),+) => { $( self.$field.rva = rva + (self.$field.offset - base) as u32; )+ };
+        }
+        fix!(size, timestamp, major_version, minor_version, global_flags_clear, global_flags_set,
+            critical_section_default_timeout, decommit_free_block_threshold, decommit_total_free_threshold,
+            lock_prefix_table, maximum_allocation_size, virtual_memory_threshold, process_affinity_mask,
+            process_heap_flags, csd_version, dependent_load_flags, edit_list, security_cookie,
+            se_handler_table, se_handler_count, guard_cf_check_function_pointer,
+            guard_cf_dispatch_function_pointer, guard_cf_function_table, guard_cf_function_count,
+            guard_flags, code_integrity_flags, code_integrity_catalog, code_integrity_catalog_offset,
+            guard_address_taken_iat_entry_table, guard_address_taken_iat_entry_count,
+            guard_long_jump_target_table, guard_long_jump_target_count, dynamic_value_reloc_table,
+            chpe_metadata_pointer, guard_rf_failure_routine, guard_rf_failure_routine_function_pointer,
+            dynamic_value_reloc_table_offset, dynamic_value_reloc_table_section,
+            guard_rf_verify_stack_pointer_function_pointer, hot_patch_table_offset,
+            enclave_configuration_pointer, volatile_metadata_pointer, guard_eh_continuation_table,
+            guard_eh_continuation_count, guard_xfg_check_function_pointer,
+            guard_xfg_dispatch_function_pointer, guard_xfg_table_dispatch_function_pointer,
+            cast_guard_os_determined_failure_mode, guard_memcpy_function_pointer);
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.size.value >= 0x40
+    }
+
+    /// Decoded `guard_flags`, without the function table stride bits.
+    pub fn guard_flags(&self) -> GuardFlags {
+        GuardFlags::from_bits_retain(self.guard_flags.value & !GUARD_CF_FUNCTION_TABLE_SIZE_MASK)
+    }
+}
+
+impl Display for LoadConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{Size: {:#x}, SecurityCookie: {:#x}, GuardFlags: {:#010x}, GuardCFFunctions: {}, CHPEMetadata: {:#x}}}",
+            self.size.value, self.security_cookie.value, self.guard_flags.value,
+            self.guard_cf_function_count.value, self.chpe_metadata_pointer.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{FileOffset, Rva};
+
+    use super::{GuardFlags, LoadConfig};
+
+    fn raw_64() -> Vec<u8> {
+        let mut bytes = vec![0u8; 0x140];
+        bytes[0..4].copy_from_slice(&0x138u32.to_le_bytes());
+        bytes[0x58..0x60].copy_from_slice(&0x1_8001_3000u64.to_le_bytes());
+        bytes[0x88..0x90].copy_from_slice(&0x20u64.to_le_bytes());
+        bytes[0x90..0x94].copy_from_slice(&0x1000_0500u32.to_le_bytes());
+        bytes[0xC8..0xD0].copy_from_slice(&0x1_8000_c400u64.to_le_bytes());
+        bytes[0x138..0x140].copy_from_slice(&0xFFu64.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn parse_load_config_64() {
+        let mut lc = LoadConfig::parse_bytes(raw_64(), 0x10000, true).unwrap();
+        lc.fix_rvas(Rva(0x11000));
+        assert!(lc.is_valid());
+        assert_eq!(lc.security_cookie.value, 0x1_8001_3000);
+        assert_eq!(lc.security_cookie.offset, FileOffset(0x10058));
+        assert_eq!(lc.security_cookie.rva, Rva(0x11058));
+        assert_eq!(lc.guard_cf_function_count.value, 0x20);
+        assert_eq!(lc.guard_flags.value, 0x1000_0500);
+        assert_eq!(lc.guard_flags(), GuardFlags::CF_INSTRUMENTED | GuardFlags::CF_FUNCTION_TABLE_PRESENT);
+        assert_eq!(lc.chpe_metadata_pointer.value, 0x1_8000_c400);
+        assert_eq!(lc.chpe_metadata_pointer.offset, FileOffset(0x100C8));
+        //Beyond declared size.
+        assert_eq!(lc.guard_memcpy_function_pointer.value, 0);
+        assert_eq!(lc.guard_memcpy_function_pointer.offset, FileOffset(0x10138));
+    }
+
+    #[test]
+    fn parse_load_config_32() {
+        let mut bytes = vec![0u8; 0x48];
+        bytes[0..4].copy_from_slice(&0x48u32.to_le_bytes());
+        bytes[0x2C..0x30].copy_from_slice(&0x4u32.to_le_bytes());
+        bytes[0x3C..0x40].copy_from_slice(&0x1001_3000u32.to_le_bytes());
+        bytes[0x44..0x48].copy_from_slice(&0x3u32.to_le_bytes());
+
+        let lc = LoadConfig::parse_bytes(bytes, 0, false).unwrap();
+        assert_eq!(lc.process_heap_flags.value, 4);
+        assert_eq!(lc.security_cookie.value, 0x1001_3000);
+        assert_eq!(lc.se_handler_count.value, 3);
+        assert_eq!(lc.guard_flags.offset, FileOffset(0x58));
+        assert_eq!(lc.chpe_metadata_pointer.offset, FileOffset(0x7C));
+        assert_eq!(lc.guard_flags.value, 0);
+    }
+}
@@ -1,27 +1,122 @@
+/// Evaluate a parse step, logging its duration at `debug` and its failure at `warn`.
+macro_rules! timed {
+    ($name:literal, $step:expr) => {
+        {
+            let start = $crate::pe::Instant::now();
+            let result = $step;
+            match &result {
+                Ok(_) => tracing::debug!(elapsed = ?start.elapsed(), concat!("parsed ", $name)),
+                Err(err) => tracing::warn!(elapsed = ?start.elapsed(), err = err.chain(), concat!("failed to parse ", $name)),
+            }
+            result
+        }
+    };
+    //Also recorded in `$stats`, an `Option<ParseStats>`, if set.
+    ($stats:expr, $name:literal, $step:expr) => {
+        {
+            let before = $stats.as_ref().map(|stats| stats.snapshot());
+            let start = $crate::pe::Instant::now();
+            let result = timed!($name, $step);
+            if let (Some(stats), Some(before)) = ($stats.as_mut(), before) {
+                stats.record($name, before, start.elapsed(), result.is_err());
+            }
+            result
+        }
+    };
+}
+
+/// Bytes read at once from the start of an image for its headers; the usual `SizeOfHeaders`.
+/// Headers beyond it are read one by one.
+const HEADERS_READ_SIZE: usize = 0x400;
+
+/// Steps of `PeImage::parse_dynamic_headers`, for progress reports.
+const DYNAMIC_STEPS: u64 = 9;
+
+/// Clock for `timed!`. `std::time::Instant` panics on `wasm32-unknown-unknown`, where durations
+/// are logged as zero.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) use std::time::Instant;
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) struct Instant;
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+impl Instant {
+    pub(crate) fn now() -> Self {
+        Self
+    }
+
+    pub(crate) fn elapsed(&self) -> std::time::Duration {
+        std::time::Duration::ZERO
+    }
+}
+
 pub mod dos;
 pub mod file;
+pub mod coff;
+pub mod nt;
 pub mod optional;
 pub mod section;
 pub mod import;
 pub mod export;
 pub mod relocs;
 pub mod rsrc;
+pub mod te;
 pub mod ser;
+pub mod analysis;
+pub mod limits;
+pub mod status;
+pub mod loadcfg;
+pub mod debug;
+pub mod deps;
+pub mod builder;
+pub mod edit;
+pub mod apiset;
+pub mod version;
+pub mod hybrid;
+pub mod rich;
+pub mod yara;
+pub mod authenticode;
+pub mod hashes;
+pub mod security;
+pub mod visit;
+pub mod embedded;
+pub mod iat;
+pub mod stats;
+pub mod progress;
+pub mod search;
+pub mod report;
+pub mod tree;
+pub mod timestamps;
+#[cfg(feature="disasm")]
+pub mod disasm;
+#[cfg(feature="hardened")]
+pub mod hardened;
 
 use std::{
-    fmt::{Display, Write}, fs::File, io::{BufReader, Cursor}, string::{FromUtf16Error, FromUtf8Error}
+    collections::HashMap, fmt::{Display, Write}, fs::File, io::{BufReader, Cursor, Seek, SeekFrom},
+    ops::Range, string::{FromUtf16Error, FromUtf8Error}, sync::Arc,
 };
 
+use chrono::{DateTime, Utc};
 use derivative::Derivative;
+use serde::Serialize;
 
-use crate::{types::{BufReadExt, Header, HeaderField, ReadExtError}, Result};
+use crate::{types::{BufReadExt, FileOffset, Header, HeaderField, ReadExtError, Rva, Va}, utils::{strings::{self, FoundString}, OffsetReader}};
 
 use self::{
-    dos::DosHeader, export::ExportDirectory, file::FileHeader, import::ImportDirectory, 
-    optional::{ parse_data_directories, x64::OptionalHeader64, x86::OptionalHeader32, DataDirectory, DirectoryType, OptionalHeader },
-    relocs::Relocations, 
-    rsrc::ResourceDirectory, 
-    section::{rva_to_section, SectionHeader, SectionTable}
+    coff::CoffSymbolTable, dos::DosHeader, export::{Export, ExportDirectory, ExportPages, ExportStream}, file::{FileHeader, MachineType}, nt::NtHeaders, import::{bound::{self, BoundImportDirectory}, delay::{self, DelayImportDirectory}, table::ModuleImports, ImportDirectory, ImportStream}, 
+    optional::{ parse_data_directories, x64::OptionalHeader64, x86::OptionalHeader32, DataDirectory, DirectoryType, ImageType, OptionalHeader },
+    limits::{check_count, ParseLimits},
+    debug::DebugDirectory, deps::{DependencyReport, ExportMap}, hybrid::HybridView, loadcfg::{chpe::{self, ChpeMetadata}, LoadConfig},
+    relocs::{PatchedRange, Relocations}, 
+    rsrc::{ResourceData, ResourceDirectory, ResourceId, ResourceNode, ResourceType}, 
+    rich::RichHeader, yara::YaraString,
+    section::{IndexedSections, SectionHeader, SectionLookup, SectionMap, SectionRef, SectionTable},
+    progress::Progress,
+    stats::ParseStats,
+    status::{ParseState, ParseStatus},
+    version::VersionInfo,
 };
 
 /**
@@ -43,7 +138,7 @@ macro_rules! new_header_field {
             
             HeaderField{
                 value: v,
-                offset: old_offset,
+                offset: $crate::types::FileOffset(old_offset),
                 rva: $rva
             }
         }
@@ -52,11 +147,13 @@ macro_rules! new_header_field {
     ($value:expr, $offset:ident) => {
         {
             let old_offset = $offset;
-            new_header_field!($value, $offset, old_offset)
+            new_header_field!($value, $offset, $crate::types::Rva::of_header(old_offset))
         }
     };
 }
 
+/// Errors of PE parsing, shared by all submodules of `pe`. Other formats have their own
+/// (e.g. `ar::ArError`), and `rustbin::Error` wraps them all.
 #[derive(Debug, thiserror::Error)]
 pub enum PeError {
     #[error("not enough data for {target}; expected {expected}, got {actual}")]
@@ -118,9 +215,183 @@ pub enum PeError {
         value: u64,
         start: u64,
         end: u64,
+    },
+
+    #[error("resource directory at offset {0:08x} is already part of the tree")]
+    #[non_exhaustive]
+    ResourceLoop(u64),
+
+    #[error("resource directory at offset {offset:08x} is nested {depth} levels deep, more than allowed {max}")]
+    #[non_exhaustive]
+    ResourceTooDeep {
+        offset: u64,
+        depth: u32,
+        max: u32,
+    },
+
+    #[error("{target} has {count} entries, more than allowed {max}")]
+    #[non_exhaustive]
+    TooMany {
+        target: String,
+        count: u64,
+        max: u64,
+    },
+
+    #[error("{target} needs {size} bytes, more than allowed {max}")]
+    #[non_exhaustive]
+    AllocationTooLarge {
+        target: String,
+        size: u64,
+        max: u64,
+    },
+
+    #[error("image without relocations can't be moved from {from:#x} to {to:#x}")]
+    #[non_exhaustive]
+    NotRelocatable {
+        from: u64,
+        to: u64,
+    },
+
+    #[error("section table of {count} headers at offset {offset:#x} is invalid; {reason}")]
+    #[non_exhaustive]
+    InvalidSectionCount {
+        count: u16,
+        offset: u64,
+        reason: String,
+    },
+
+    #[error("no section named {0}")]
+    NoSection(String),
+
+    #[error("no import {0}")]
+    NoImport(String),
+
+    #[error("can't disassemble code of {0:?} images")]
+    UnsupportedMachine(MachineType),
+
+    /// Directory and structure errors of `PeImage` come wrapped in one or more `Context`s;
+    /// match on `PeError::root` for the error under them.
+    #[error("while parsing {target} at {offset:#x}")]
+    #[non_exhaustive]
+    Context {
+        target: String,
+        offset: u64,
+        #[source]
+        source: Box<PeError>,
+    },
+}
+
+impl PeError {
+    /// Wrap in `Context`, saying `target` at file `offset` was being parsed.
+    pub fn context(self, target: impl Into<String>, offset: u64) -> Self {
+        Self::Context { target: target.into(), offset, source: Box::new(self) }
+    }
+
+    /// The error under all `Context`s, to match on whether or not it was wrapped.
+    pub fn root(&self) -> &PeError {
+        match self {
+            Self::Context { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    /// The error followed by its sources, on one line.
+    /// E.g. `while parsing ImportDescriptor #12 at 0x3c40: invalid rva 0x0001f000`.
+    pub fn chain(&self) -> String {
+        let mut chain = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(err) = source {
+            chain.push_str(": ");
+            chain.push_str(&err.to_string());
+            source = err.source();
+        }
+        chain
+    }
+}
+
+/// Result of parsing PE (and TE) images.
+pub type Result<T> = std::result::Result<T, PeError>;
+
+/// Default limit for a single buffer allocated from a size read from the file.
+pub const DEFAULT_MAX_ALLOC: u64 = 64 * 1024 * 1024;
+
+/// Returns `AllocationTooLarge` if `size` is more than `max`.
+pub fn check_alloc(target: &str, size: u64, max: u64) -> Result<()> {
+    if size > max {
+        return Err(PeError::AllocationTooLarge { target: target.into(), size, max });
+    }
+    Ok(())
+}
+
+/// A 32-bit `TimeDateStamp` field.
+/// Values which are not a time, like `0xFFFFFFFF` of bound imports or timestomped images,
+/// are kept as `Raw` instead of failing the header they are part of.
+/// Serialized with both forms, see `ser::TimestampFormat::Both`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeTimestamp {
+    Valid(DateTime<Utc>),
+    Raw(u32),
+}
+
+impl Default for PeTimestamp {
+    fn default() -> Self {
+        Self::Valid(DateTime::default())
+    }
+}
+
+impl From<u32> for PeTimestamp {
+    fn from(value: u32) -> Self {
+        if value == u32::MAX {
+            return Self::Raw(value);
+        }
+        DateTime::<Utc>::from_timestamp(value.into(), 0).map_or(Self::Raw(value), Self::Valid)
     }
 }
 
+impl PeTimestamp {
+    /// Value as stored in the image.
+    pub fn raw(&self) -> u32 {
+        match self {
+            Self::Valid(dt) => dt.timestamp() as u32,
+            Self::Raw(value) => *value,
+        }
+    }
+
+    pub fn as_datetime(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Valid(dt) => Some(*dt),
+            Self::Raw(_) => None,
+        }
+    }
+}
+
+impl Serialize for PeTimestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        ser::TimestampValue::new(*self, ser::TimestampFormat::Both).serialize(serializer)
+    }
+}
+
+impl Display for PeTimestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Valid(dt) => write!(f, "{dt}"),
+            Self::Raw(value) => write!(f, "{value:#010x} (not a time)"),
+        }
+    }
+}
+
+
+/// A present data directory with where it is, from `PeImage::iter_directories`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectoryEntry<'a> {
+    pub member: DirectoryType,
+    pub rva: u32,
+    pub size: u32,
+    /// Section holding the directory, as in `PeImage::directory_section`.
+    pub section: Option<&'a SectionHeader>,
+    /// File offset of the directory, as in `PeImage::directory_file_offset`.
+    pub offset: Option<u32>,
+}
 
 pub const SECTION_HEADER_LENGTH: u64 = section::HEADER_LENGTH;
 
@@ -128,14 +399,54 @@ pub const SECTION_HEADER_LENGTH: u64 = section::HEADER_LENGTH;
 #[derivative(Debug)]
 pub struct PeImage {
     pub dos: HeaderField<DosHeader>,
+    /// Signature and layout of the NT headers, which hold `file` and `optional`.
+    pub nt: HeaderField<NtHeaders>,
     pub file: HeaderField<FileHeader>,
     pub optional: HeaderField<OptionalHeader>,
     pub data_dirs: HeaderField<Vec<HeaderField<DataDirectory>>>,
     pub sections: HeaderField<SectionTable>,
     pub imports: HeaderField<ImportDirectory>,
+    pub delay_imports: HeaderField<DelayImportDirectory>,
+    pub bound_imports: HeaderField<BoundImportDirectory>,
     pub exports: HeaderField<ExportDirectory>,
     pub relocations: HeaderField<Relocations>,
     pub resources: HeaderField<ResourceDirectory>,
+    pub load_config: HeaderField<LoadConfig>,
+    /// Hybrid metadata of ARM64EC/ARM64X images.
+    pub chpe: HeaderField<ChpeMetadata>,
+    pub debug: HeaderField<DebugDirectory>,
+    /// COFF symbol and string tables; parsed only by `parse_symbols`.
+    pub symbols: HeaderField<CoffSymbolTable>,
+
+    /// Offset of the image in the input, for images embedded in larger files. Offsets of
+    /// parsed fields are relative to the start of the image, like its own file offsets.
+    pub pos: u64,
+
+    /// What each `parse_*` step left behind.
+    pub status: ParseState,
+
+    #[derivative(Debug="ignore")]
+    section_map: SectionMap,
+
+    /// Raw content of sections read by `section_data`, by index.
+    #[derivative(Debug="ignore")]
+    section_cache: HashMap<usize, Arc<[u8]>>,
+
+    /// Limits on sizes and counts read from the image.
+    pub limits: ParseLimits,
+
+    #[derivative(Debug="ignore")]
+    stats: Option<ParseStats>,
+
+    #[derivative(Debug="ignore")]
+    progress: Progress,
+
+    /// Start of the image read by `parse_fixed_headers`, at `header_pos`; dropped once section
+    /// headers are parsed.
+    #[derivative(Debug="ignore")]
+    header_bytes: Vec<u8>,
+    #[derivative(Debug="ignore")]
+    header_pos: u64,
 
     #[derivative(Debug="ignore")]
     reader: Box<dyn BufReadExt>,
@@ -145,22 +456,51 @@ impl PeImage {
     pub fn new(reader: Box<dyn BufReadExt>) -> Self {
         Self { 
             dos: Default::default(), 
+            nt: Default::default(),
             file: Default::default(),
             optional: Default::default(),
             data_dirs: Default::default(),
             sections: Default::default(),
             imports: Default::default(),
+            delay_imports: Default::default(),
+            bound_imports: Default::default(),
             exports: Default::default(),
             relocations: Default::default(),
             resources: Default::default(),
+            load_config: Default::default(),
+            chpe: Default::default(),
+            debug: Default::default(),
+            symbols: Default::default(),
+            pos: 0,
+            status: Default::default(),
+            section_map: Default::default(),
+            section_cache: HashMap::new(),
+            limits: Default::default(),
+            stats: None,
+            progress: Default::default(),
+            header_bytes: Vec::new(),
+            header_pos: 0,
             reader
         }
     }
 
+    /// Sections paired with their prebuilt lookup index.
+    #[inline]
+    pub fn section_lookup(&self) -> IndexedSections<'_> {
+        IndexedSections::new(&self.sections.value, &self.section_map)
+    }
+
+    /// Rebuild the section lookup index and drop section data cached by `section_data`.
+    /// Needed only if `sections` was modified after parsing.
+    pub fn reindex_sections(&mut self) {
+        self.section_map = SectionMap::new(&self.sections.value);
+        self.section_cache.clear();
+    }
+
     pub fn directory_offset(&self, dir: DirectoryType) -> Option<u32> {
         if let Some(dir) = self.directory(dir) {
             let rva = dir.rva.value;
-            section::rva_to_offset(&self.sections.value, rva)
+            self.section_lookup().rva_to_offset(rva)
         }
         else {
             None
@@ -170,27 +510,116 @@ impl PeImage {
     pub fn directory_section(&self, dir: DirectoryType) -> Option<&SectionHeader> {
         if let Some(dir) = self.directory(dir) {
             let rva = dir.rva.value;
-            section::rva_to_section(&self.sections.value, rva)
+            self.section_lookup().section_for_rva(rva)
         }
         else {
             None
         }
     }
 
+    /// Present data directories, in table order, with the section and file offset of each.
+    pub fn iter_directories(&self) -> impl Iterator<Item = DirectoryEntry<'_>> + '_ {
+        self.data_dirs.value.iter()
+            .map(|dir| &dir.value)
+            .filter(|dir| dir.is_present())
+            .map(|dir| DirectoryEntry {
+                member: dir.member,
+                rva: dir.rva.value,
+                size: dir.size.value,
+                section: self.directory_section(dir.member),
+                offset: self.directory_file_offset(dir.member),
+            })
+    }
+
+    /// File offset of directory `dir`, where its data can be read from.
+    /// Unlike `directory_offset`, takes the rva of `Security` as the file offset it is, and maps
+    /// rvas within headers (e.g. of `BoundImport`), which are not in any section.
+    pub fn directory_file_offset(&self, dir: DirectoryType) -> Option<u32> {
+        let rva = self.directory(dir)?.rva.value;
+        if dir == DirectoryType::Security {
+            return Some(rva);
+        }
+        self.rva_to_offset(rva)
+            .or((rva < self.optional.value.sizeof_headers()).then_some(rva))
+    }
+
+    /// File range of the certificate table, if the `Security` directory is present.
+    /// The rva of that directory is a file offset, as the table is not mapped by the loader.
+    pub fn certificate_range(&self) -> Option<Range<u64>> {
+        let dir = self.directory(DirectoryType::Security)?;
+        let start = u64::from(dir.rva.value);
+        Some(start..start.checked_add(dir.size.value.into())?)
+    }
+
+    /// Up to `max_size` bytes from the start of directory `dir`, read at `directory_file_offset`.
+    pub fn read_directory_bytes(&mut self, dir: DirectoryType, max_size: usize) -> Result<Vec<u8>> {
+        let size = self.directory(dir).map_or(0, |dd| dd.size.value as usize).min(max_size);
+        let Some(offset) = self.directory_file_offset(dir) else {
+            return Err(PeError::NoSectionForRVA(self.directory(dir).map_or(0, |dd| dd.rva.value).into()));
+        };
+        check_alloc("DataDirectory", size as u64, self.limits.max_alloc)?;
+        Ok(self.reader.read_bytes_at_offset(offset.into(), size)?)
+    }
+
+    /// Add directory `dir` and its file offset to `err`, unless it already says what was parsed.
+    fn directory_error(&self, dir: DirectoryType, err: PeError) -> PeError {
+        match err {
+            PeError::Context { .. } => err,
+            err => {
+                let offset = self.directory_file_offset(dir)
+                    .or(self.directory(dir).map(|dd| dd.rva.value))
+                    .unwrap_or_default();
+                err.context(format!("{dir:?} directory"), offset.into())
+            },
+        }
+    }
+
+    /// Directory `dir` has both rva and size. See `DataDirectory::is_present`.
+    #[inline]
+    pub fn has_directory(&self, dir: DirectoryType) -> bool {
+        self.data_dirs.value.get(dir as usize).is_some_and(|dir| dir.value.is_present())
+    }
+
+    /// Directory `dir` if present; `None` also if the table is shorter, per `NumberOfRvaAndSizes`.
     #[inline]
     pub fn directory(&self, dir: DirectoryType) -> Option<&DataDirectory> {
-        let dir = &self.data_dirs.value[dir as usize].value;
-        if dir.rva.value == 0 {None} else {Some(&dir)}
+        self.data_dirs.value.get(dir as usize)
+            .map(|dir| &dir.value)
+            .filter(|dir| dir.is_present())
     }
 
     #[inline]
-    pub fn rva_to_offset(&self, rva: u32) -> Option<u32> {
-        section::rva_to_offset(&self.sections.value, rva)
+    pub fn rva_to_offset(&self, rva: impl Into<Rva>) -> Option<u32> {
+        self.section_lookup().rva_to_offset(rva.into().0)
     }
 
     #[inline]
-    pub fn offset_to_rva(&self, offset: u64) -> Option<u32> {
-        section::offset_to_rva(&self.sections.value, offset as u32)
+    pub fn offset_to_rva(&self, offset: impl Into<FileOffset>) -> Option<u32> {
+        self.section_lookup().offset_to_rva(u32::try_from(offset.into().0).ok()?)
+    }
+
+    /// File offset of `rva`, if a section maps it.
+    #[inline]
+    pub fn offset_of_rva(&self, rva: Rva) -> Option<FileOffset> {
+        self.rva_to_offset(rva).map(FileOffset::from)
+    }
+
+    /// RVA of file `offset`, if it is in the raw data of a section.
+    #[inline]
+    pub fn rva_of_offset(&self, offset: FileOffset) -> Option<Rva> {
+        self.offset_to_rva(offset).map(Rva)
+    }
+
+    /// RVA of `va` with the image base of the optional header.
+    #[inline]
+    pub fn rva_of_va(&self, va: Va) -> Option<Rva> {
+        va.to_rva(self.optional.value.image_base())
+    }
+
+    /// VA of `rva` with the image base of the optional header.
+    #[inline]
+    pub fn va_of_rva(&self, rva: Rva) -> Va {
+        rva.to_va(self.optional.value.image_base())
     }
 
     pub fn read_string_at_rva(&mut self, rva: u32) -> std::result::Result<String, PeError> {
@@ -198,121 +627,699 @@ impl PeImage {
         Ok(self.reader.read_string_at_offset(offset.into())?)
     }
 
+    pub fn read_bytes_at_rva(&mut self, rva: u32, size: usize) -> Result<Vec<u8>> {
+        let offset = self.rva_to_offset(rva).ok_or(PeError::InvalidRVA(rva.into()))?;
+        Ok(self.reader.read_bytes_at_offset(offset.into(), size)?)
+    }
+
+    /// `size` bytes at file `offset`, relative to the start of the image like `pos`.
+    pub fn read_bytes_at_offset(&mut self, offset: u64, size: usize) -> Result<Vec<u8>> {
+        Ok(self.reader.read_bytes_at_offset(offset, size)?)
+    }
+
+    /// Size of the input from the start of the image.
+    pub fn input_size(&mut self) -> Result<u64> {
+        Ok(self.reader.end_offset()?)
+    }
+
+    /// Raw content of section at `index` in section table, as stored in file.
+    pub fn read_section_data(&mut self, index: usize) -> Result<Vec<u8>> {
+        let section = &self.sections.value.get(index)
+            .ok_or(PeError::InvalidHeader { name: "Section".into(), offset: self.sections.offset.into(), reason: format!("no section at index {index}") })?
+            .value;
+        let offset = section.raw_data_ptr.value as u64;
+        let size = section.sizeof_raw_data.value as usize;
+        Ok(self.reader.read_bytes_at_offset(offset, size)?)
+    }
+
+    /// Raw content of a section, as `read_section_data`, read once and shared by later calls.
+    pub fn section_data<'a>(&mut self, section: impl Into<SectionRef<'a>>) -> Result<Arc<[u8]>> {
+        let index = match section.into() {
+            SectionRef::Index(index) => index,
+            SectionRef::Name(name) => self.sections.value.iter()
+                .position(|s| s.value.name_bytes() == name.as_bytes())
+                .ok_or_else(|| PeError::NoSection(name.to_string()))?,
+        };
+
+        if let Some(data) = self.section_cache.get(&index) {
+            return Ok(Arc::clone(data));
+        }
+        if let Some(section) = self.sections.value.get(index) {
+            check_alloc("Section", section.value.sizeof_raw_data.value.into(), self.limits.max_alloc)?;
+        }
+        let data: Arc<[u8]> = self.read_section_data(index)?.into();
+        self.section_cache.insert(index, Arc::clone(&data));
+        Ok(data)
+    }
+
+    /// ASCII and UTF-16LE strings of at least `min_len` characters in sections named in
+    /// `sections`, or in the whole file if it is empty.
+    pub fn find_strings(&mut self, min_len: usize, sections: &[&str]) -> Result<Vec<FoundString>> {
+        let mut found = Vec::new();
+        if sections.is_empty() {
+            let size = self.reader.end_offset()?;
+            check_alloc("File", size, self.limits.max_alloc)?;
+            let bytes = self.reader.read_bytes_at_offset(0, size as usize)?;
+            found = strings::scan(&bytes, 0, min_len);
+        }
+
+        for name in sections {
+            let bytes = self.section_data(*name)?;
+            let offset = self.sections.value.iter()
+                .find(|s| s.value.name_bytes() == name.as_bytes())
+                .map_or(0, |s| s.value.raw_data_ptr.value as u64);
+            found.extend(strings::scan(&bytes, offset, min_len));
+        }
+
+        for s in &mut found {
+            s.rva = self.offset_to_rva(s.offset);
+        }
+        Ok(found)
+    }
+
     #[inline]
     pub fn has_imports(&self) -> bool {
-        self.data_dirs.value[DirectoryType::Import as usize].value.rva.value != 0
+        self.has_directory(DirectoryType::Import)
     }
 
-    pub fn parse_import_directory(&mut self) -> std::result::Result<(), PeError> {
-        if !self.has_imports() {
-            return Ok(());
-        }
+    /// Parse the import directory and imported functions. Replaces imports parsed before.
+    pub fn parse_import_directory(&mut self) -> Result<()> {
+        self.imports = Default::default();
+        let result = self.read_imports().map_err(|err| self.directory_error(DirectoryType::Import, err));
+        self.status.imports = ParseStatus::of(self.has_imports(), &result);
+        result
+    }
 
-        let import_dd = &self.data_dirs.value[DirectoryType::Import as usize].value;
+    fn read_imports(&mut self) -> Result<()> {
+        let Some(import_dd) = self.directory(DirectoryType::Import) else {
+            return Ok(());
+        };
         let import_rva = import_dd.rva.value;
         let import_size = import_dd.size.value;
         let import_offset = self.rva_to_offset(import_rva).ok_or(PeError::InvalidRVA(import_rva.into()))?;
-        
-        //let mut reader = FragmentReader::new(&self.reader);
-        let bytes = self.reader.read_bytes_at_offset(import_offset as u64, import_size as usize)?;
-    
-        let mut imp_dir = ImportDirectory::parse_bytes(bytes, import_rva as u64)?;
+
+        let mut imp_dir = import::parse_directory(
+            &mut self.reader, import_rva.into(), import_offset.into(), import_size.into(), self.limits.max_import_descriptors
+        )?;
+        let sections = IndexedSections::new(&self.sections.value, &self.section_map);
+        let mut names = import::NameCache::default();
 
         for i in 0..imp_dir.len() {
             let id = &mut imp_dir[i].value;
-            id.update_name(&self.sections.value, &mut self.reader)?;
-            id.parse_imports(&self.sections.value, self.optional.value.get_image_type(), &mut self.reader)?;
+            id.update_name(&sections, &mut self.reader)
+                .and_then(|_| id.parse_imports_cached(&sections, self.optional.value.get_image_type(), &mut self.reader, self.limits.max_imports_per_module, &mut names))
+                .map_err(|err| err.context(format!("ImportDescriptor #{i}"), import_offset as u64 + (i * import::IMPORT_DESCRIPTOR_SIZE) as u64))?;
         }
-        self.imports = HeaderField{ value: imp_dir, offset:import_offset as u64, rva:import_rva as u64};
+        self.imports = HeaderField{ value: imp_dir, offset: import_offset.into(), rva: import_rva.into() };
         
         Ok(())
     }
 
     #[inline]
-    pub fn has_exports(&self) -> bool {
-        self.data_dirs.value[DirectoryType::Export as usize].value.rva.value != 0
+    pub fn has_delay_imports(&self) -> bool {
+        self.has_directory(DirectoryType::DelayImport)
     }
 
-    pub fn parse_exports(&mut self) -> Result<()> {
-        let dd_export = &self.data_dirs.value[DirectoryType::Export as usize].value;
-        if !self.has_exports() {
+    /// Parse delay load descriptors and their imports. Replaces delay imports parsed before.
+    pub fn parse_delay_imports(&mut self) -> Result<()> {
+        self.delay_imports = Default::default();
+        let result = self.read_delay_imports().map_err(|err| self.directory_error(DirectoryType::DelayImport, err));
+        self.status.delay_imports = ParseStatus::of(self.has_delay_imports(), &result);
+        result
+    }
+
+    fn read_delay_imports(&mut self) -> Result<()> {
+        let Some(dd) = self.directory(DirectoryType::DelayImport) else {
             return Ok(());
+        };
+        let (rva, size) = (dd.rva.value, dd.size.value);
+        let offset = self.rva_to_offset(rva).ok_or(PeError::NoSectionForRVA(rva.into()))?;
+        check_alloc("DelayImportDirectory", size.into(), self.limits.max_alloc)?;
+
+        let bytes = self.reader.read_bytes_at_offset(offset.into(), size as usize)?;
+        let mut dir = delay::parse_directory(&bytes, offset.into())?;
+        check_count("DelayImportDirectory", dir.len() as u64, self.limits.max_import_descriptors.into())?;
+
+        let image_type = self.optional.value.get_image_type();
+        let image_base = self.optional.value.image_base();
+        let sections = IndexedSections::new(&self.sections.value, &self.section_map);
+        let mut names = import::NameCache::default();
+        for (i, desc) in dir.iter_mut().enumerate() {
+            desc.rva = Rva(rva) + (desc.offset - FileOffset::from(offset)) as u32;
+            desc.value.parse_imports_cached(&sections, image_type, image_base, &mut self.reader, self.limits.max_imports_per_module, &mut names)
+                .map_err(|err| err.context(format!("DelayImportDescriptor #{i}"), desc.offset.into()))?;
         }
+        self.delay_imports = HeaderField { value: dir, offset: offset.into(), rva: rva.into() };
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn has_bound_imports(&self) -> bool {
+        self.has_directory(DirectoryType::BoundImport)
+    }
 
-        let export_rva = dd_export.rva.value;
+    /// Parse the bound import directory. Replaces descriptors parsed before.
+    pub fn parse_bound_imports(&mut self) -> Result<()> {
+        self.bound_imports = Default::default();
+        let result = self.read_bound_imports().map_err(|err| self.directory_error(DirectoryType::BoundImport, err));
+        self.status.bound_imports = ParseStatus::of(self.has_bound_imports(), &result);
+        result
+    }
+
+    fn read_bound_imports(&mut self) -> Result<()> {
+        let Some(dd) = self.directory(DirectoryType::BoundImport) else {
+            return Ok(());
+        };
+        let (rva, size) = (dd.rva.value, dd.size.value);
+        //Usually in the headers, after the section table.
+        let offset = self.directory_file_offset(DirectoryType::BoundImport)
+            .ok_or(PeError::NoSectionForRVA(rva.into()))?;
+        check_alloc("BoundImportDirectory", size.into(), self.limits.max_alloc)?;
+
+        let bytes = self.reader.read_bytes_at_offset(offset.into(), size as usize)?;
+        let dir = bound::parse_directory(&bytes, offset.into())?;
+        self.bound_imports = HeaderField { value: dir, offset: offset.into(), rva: rva.into() };
+
+        Ok(())
+    }
+
+    /// Static, bound and delay loaded imports merged into one table per module.
+    /// Uses whatever of those directories is parsed.
+    pub fn import_table(&self) -> Vec<ModuleImports> {
+        import::table::merge(self)
+    }
+
+    /// Check which parsed imports resolve against `exports` of dependencies. See `deps`.
+    pub fn resolve_imports(&self, exports: &ExportMap) -> DependencyReport {
+        deps::resolve(self, exports)
+    }
+
+    /// Iterate over import descriptors one at a time, read from the image as they are consumed.
+    /// Useful to stop early, e.g. when looking for a single module, without parsing the whole directory.
+    pub fn imports_stream(&mut self) -> ImportStream<'_> {
+        ImportStream::new(self)
+    }
+
+    #[inline]
+    pub fn has_exports(&self) -> bool {
+        self.has_directory(DirectoryType::Export)
+    }
+
+    /// Read and validate the export directory header, without any exports.
+    fn read_export_directory(&mut self) -> Result<Option<ExportDirectory>> {
+        let Some(export_rva) = self.directory(DirectoryType::Export).map(|dd| dd.rva.value) else {
+            return Ok(None);
+        };
         let export_offset = self.rva_to_offset(export_rva).ok_or(PeError::InvalidRVA(export_rva.into()))?;
 
         //let mut reader = FragmentReader::new(&self.reader);
         let bytes = self.reader.read_bytes_at_offset(export_offset.into(), export::HEADER_LENGTH as usize)?;
         
-        let mut export_dir = ExportDirectory::parse_bytes(bytes, export_offset.into())?;
+        let export_dir = ExportDirectory::parse_bytes(bytes, export_offset.into())?;
         if !export_dir.is_valid() {
             return Err(
                 PeError::InvalidHeader { name: "Export".into(), offset: export_offset.into(), reason: "structure is invalid".into() }
             );
         }
+        check_count("Export functions", export_dir.number_of_functions.value.into(), self.limits.max_exports.into())?;
+        check_count("Export names", export_dir.number_of_names.value.into(), self.limits.max_exports.into())?;
+        check_alloc("Export names", export_dir.number_of_names.value as u64 * 4, self.limits.max_alloc)?;
+        Ok(Some(export_dir))
+    }
 
-        export_dir.parse_exports(&self.sections.value, &mut self.reader)?;
+    /// Parse the export directory and all exports. Replaces exports parsed before.
+    pub fn parse_exports(&mut self) -> Result<()> {
+        self.exports = Default::default();
+        let result = self.read_exports().map_err(|err| self.directory_error(DirectoryType::Export, err));
+        self.status.exports = ParseStatus::of(self.has_exports(), &result);
+        result
+    }
+
+    fn read_exports(&mut self) -> Result<()> {
+        let Some(mut export_dir) = self.read_export_directory()? else {
+            return Ok(());
+        };
+        check_alloc("Export functions", export_dir.number_of_functions.value as u64 * 4, self.limits.max_alloc)?;
+
+        let sections = IndexedSections::new(&self.sections.value, &self.section_map);
+        export_dir.parse_exports(&sections, &mut self.reader)?;
         
+        let export_rva = self.directory(DirectoryType::Export).map_or(0, |dd| dd.rva.value);
         self.exports = HeaderField {
+            offset: export_dir.charatristics.offset,
             value: export_dir, 
-            offset: export_offset.into(), 
             rva: export_rva.into() 
         };
 
         Ok(())
     }
 
+    /// Number of exported functions. Reads only the export directory header if exports are not parsed.
+    pub fn export_count(&mut self) -> Result<u32> {
+        if self.exports.value.is_valid() {
+            return Ok(self.exports.value.export_count());
+        }
+        Ok(self.read_export_directory()?.map_or(0, |dir| dir.export_count()))
+    }
+
+    /// Exports at function indexes `start..start + count`. See `ExportDirectory::read_exports_page`.
+    pub fn exports_page(&mut self, start: u32, count: u32) -> Result<Vec<Export>> {
+        check_alloc("Export page", count as u64 * 4, self.limits.max_alloc)?;
+
+        let parsed;
+        let export_dir = if self.exports.value.is_valid() {
+            &self.exports.value
+        }
+        else {
+            match self.read_export_directory()? {
+                Some(dir) => { parsed = dir; &parsed },
+                None => return Ok(Vec::new()),
+            }
+        };
+
+        let sections = IndexedSections::new(&self.sections.value, &self.section_map);
+        export_dir.read_exports_page(&sections, &mut self.reader, start, count)
+    }
+
+    /// Iterate over all exports in pages of `page_size`, without parsing the whole export table.
+    pub fn export_pages(&mut self, page_size: u32) -> ExportPages<'_> {
+        ExportPages::new(self, page_size)
+    }
+
+    /// Iterate over exports one at a time, read from the image as they are consumed.
+    /// Works whether exports are parsed or not; see `ExportStream` for the order.
+    pub fn exports_stream(&mut self) -> ExportStream<'_> {
+        ExportStream::new(self)
+    }
+
     #[inline]
     pub fn has_relocations(&self) -> bool{
-        self.data_dirs.value[DirectoryType::Relocation as usize].value.rva.value != 0
+        self.has_directory(DirectoryType::Relocation)
     }
 
+    /// Parse all relocation blocks. Replaces relocations parsed before.
     pub fn parse_relocations(&mut self) -> Result<()> {
-        if !self.has_relocations() {
-            return Ok(());
-        }
+        self.relocations = Default::default();
+        let result = self.read_relocations().map_err(|err| self.directory_error(DirectoryType::Relocation, err));
+        self.status.relocations = ParseStatus::of(self.has_relocations(), &result);
+        result
+    }
 
-        let dd_relocs = &self.data_dirs.value[DirectoryType::Relocation as usize].value;
+    fn read_relocations(&mut self) -> Result<()> {
+        let Some(dd_relocs) = self.directory(DirectoryType::Relocation) else {
+            return Ok(());
+        };
         let relocs_rva = dd_relocs.rva.value;
-        let relocs_size = dd_relocs.size.value as usize;
-        let relocs_offset = self.rva_to_offset(relocs_rva.into()).ok_or(PeError::NoSectionForRVA(relocs_rva.into()))?;
-
-        //let mut reader = FragmentReader::new(&self.reader);
-        let bytes = self.reader.read_bytes_at_offset(relocs_offset.into(), relocs_size)?;
+        let relocs_size = dd_relocs.size.value as u64;
+        let relocs_offset = self.rva_to_offset(relocs_rva).ok_or(PeError::NoSectionForRVA(relocs_rva.into()))?;
 
-        let mut relocs = Relocations::parse_bytes(bytes, relocs_offset.into())?;
+        let mut relocs = Relocations::parse_stream_with_progress(&mut self.reader, relocs_offset.into(), relocs_size, self.limits.max_alloc, &mut self.progress)?;
+        relocs.set_machine(self.file.value.machine.value);
         relocs.fix_rvas(relocs_rva.into())?;
         self.relocations = HeaderField {value: relocs, offset: relocs_offset.into(), rva: relocs_rva.into()};
 
         Ok(())
     }
 
+    /// The image as the loader maps it: headers at 0 and each section at its RVA, zero filled up to
+    /// `SizeOfImage` (or the end of the last section, if beyond it) rounded up to the section alignment.
+    /// Offsets in the result are RVAs. Raw data past the end of input is left zeroed.
+    pub fn map_to_virtual_layout(&mut self) -> Result<Vec<u8>> {
+        let align = self.optional.value.section_alignment().max(1) as u64;
+        let align_up = |value: u64| value.div_ceil(align) * align;
+
+        let sections_end = self.sections.value.iter()
+            .map(|s| s.value.virtual_address.value as u64 + s.value.virtual_size.value.max(s.value.sizeof_raw_data.value) as u64)
+            .max()
+            .unwrap_or(0);
+        let size = align_up((self.optional.value.sizeof_image() as u64).max(sections_end));
+        check_alloc("Image", size, self.limits.max_alloc)?;
+        let mut image = vec![0u8; size as usize];
+
+        let file_len = self.reader.end_offset()?;
+        let copy = |image: &mut [u8], reader: &mut Box<dyn BufReadExt>, rva: u64, offset: u64, len: u64| -> Result<()> {
+            let len = len.min(file_len.saturating_sub(offset)).min(size.saturating_sub(rva));
+            if len > 0 {
+                let bytes = reader.read_bytes_at_offset(offset, len as usize)?;
+                image[rva as usize..(rva + len) as usize].copy_from_slice(&bytes);
+            }
+            Ok(())
+        };
+
+        copy(&mut image, &mut self.reader, 0, 0, self.optional.value.sizeof_headers().into())?;
+        for section in self.sections.value.iter().map(|s| &s.value) {
+            //Only raw data within the virtual size is mapped; a virtual size of 0 maps all of it.
+            let raw_size = match section.virtual_size.value {
+                0 => section.sizeof_raw_data.value,
+                virtual_size => virtual_size.min(section.sizeof_raw_data.value),
+            };
+            copy(&mut image, &mut self.reader, section.virtual_address.value.into(), section.raw_data_ptr.value.into(), raw_size.into())?;
+        }
+
+        Ok(image)
+    }
+
+    /// Contents of the image file with relocations applied for `new_base` and `ImageBase` set to it.
+    /// Returns the buffer and the ranges patched by relocations. Parses relocations if they are not parsed yet.
+    pub fn rebase(&mut self, new_base: u64) -> Result<(Vec<u8>, Vec<PatchedRange>)> {
+        let old_base = self.optional.value.image_base();
+        if matches!(self.optional.value, OptionalHeader::X86(_)) && new_base > u32::MAX.into() {
+            return Err(PeError::BeyondRange { name: "PE32 ImageBase".into(), typ: "Base".into(), value: new_base, start: 0, end: u32::MAX.into() });
+        }
+        if new_base != old_base && !self.has_relocations() {
+            return Err(PeError::NotRelocatable { from: old_base, to: new_base });
+        }
+        if !self.status.relocations.is_parsed() {
+            self.parse_relocations()?;
+        }
+
+        let len = self.reader.seek(SeekFrom::End(0))?;
+        check_alloc("Image", len, self.limits.max_alloc)?;
+        let mut image = self.reader.read_bytes_at_offset(0, len as usize)?;
+
+        let delta = new_base.wrapping_sub(old_base);
+        let sections = IndexedSections::new(&self.sections.value, &self.section_map);
+        let patched = self.relocations.value.apply(&mut image, &sections, delta)?;
+
+        let (base_offset, base_bytes) = match &self.optional.value {
+            OptionalHeader::X86(opt) => (opt.image_base.offset, (new_base as u32).to_le_bytes().to_vec()),
+            OptionalHeader::X64(opt) => (opt.image_base.offset, new_base.to_le_bytes().to_vec()),
+        };
+        let base_offset = u64::from(base_offset) as usize;
+        image.get_mut(base_offset..base_offset + base_bytes.len())
+            .ok_or(PeError::InvalidOffset(base_offset as u64))?
+            .copy_from_slice(&base_bytes);
+
+        Ok((image, patched))
+    }
+
+    #[inline]
+    pub fn has_load_config(&self) -> bool {
+        self.has_directory(DirectoryType::Configuration)
+    }
+
+    /// Parse the load config. Replaces the load config parsed before.
+    pub fn parse_load_config(&mut self) -> Result<()> {
+        self.load_config = Default::default();
+        let result = self.read_load_config().map_err(|err| self.directory_error(DirectoryType::Configuration, err));
+        self.status.load_config = ParseStatus::of(self.has_load_config(), &result);
+        result
+    }
+
+    fn read_load_config(&mut self) -> Result<()> {
+        let Some(lc_rva) = self.directory(DirectoryType::Configuration).map(|dd| dd.rva.value) else {
+            return Ok(());
+        };
+        let lc_offset = self.rva_to_offset(lc_rva).ok_or(PeError::NoSectionForRVA(lc_rva.into()))?;
+        let is_64 = self.optional.value.get_image_type() == ImageType::PE64;
+
+        //Size is the first field; read only what the image declares.
+        let size_buf = self.reader.read_bytes_at_offset(lc_offset.into(), 4)?;
+        let size = u32::from_le_bytes([size_buf[0], size_buf[1], size_buf[2], size_buf[3]]) as u64;
+        let size = size.clamp(4, LoadConfig::max_length(is_64));
+
+        let buf = self.reader.read_bytes_at_offset(lc_offset.into(), size as usize)?;
+        let mut lc = LoadConfig::parse_bytes(buf, lc_offset.into(), is_64)?;
+        lc.fix_rvas(lc_rva.into());
+        self.load_config = HeaderField { value: lc, offset: lc_offset.into(), rva: lc_rva.into() };
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn has_debug(&self) -> bool {
+        self.has_directory(DirectoryType::Debug)
+    }
+
+    /// Parse debug directory entries, and the data of the `CODEVIEW` and `EX_DLLCHARACTERISTICS` entries.
+    /// Replaces entries parsed before.
+    pub fn parse_debug_directory(&mut self) -> Result<()> {
+        self.debug = Default::default();
+        let result = self.read_debug_directory().map_err(|err| self.directory_error(DirectoryType::Debug, err));
+        self.status.debug = ParseStatus::of(self.has_debug(), &result);
+        result
+    }
+
+    fn read_debug_directory(&mut self) -> Result<()> {
+        let Some(dd_debug) = self.directory(DirectoryType::Debug) else {
+            return Ok(());
+        };
+        let debug_rva = dd_debug.rva.value;
+        let debug_size = dd_debug.size.value as u64;
+        let debug_offset = self.rva_to_offset(debug_rva).ok_or(PeError::NoSectionForRVA(debug_rva.into()))?;
+        check_alloc("DebugDirectory", debug_size, self.limits.max_alloc)?;
+
+        let buf = self.reader.read_bytes_at_offset(debug_offset.into(), debug_size as usize)?;
+        let mut debug = DebugDirectory::parse_entries(&buf, debug_offset.into())?;
+        debug.fix_rvas(debug_rva.into());
+
+        let ex = debug.entry(debug::DebugType::EX_DLLCHARACTERISTICS)
+            .filter(|ex| ex.size_of_data.value >= 4)
+            .map(|ex| (ex.pointer_to_raw_data.value, ex.address_of_raw_data.value));
+        if let Some((ex_offset, ex_rva)) = ex {
+            let data = self.reader.read_bytes_at_offset(ex_offset.into(), 4)?;
+            let value = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+            debug.ex_dll_charactristics = Some(HeaderField { value, offset: ex_offset.into(), rva: ex_rva.into() });
+        }
+
+        let cv = debug.entry(debug::DebugType::CODEVIEW)
+            .map(|cv| (cv.pointer_to_raw_data.value, cv.address_of_raw_data.value, cv.size_of_data.value));
+        if let Some((cv_offset, cv_rva, cv_size)) = cv {
+            //A malformed or unreadable PDB reference doesn't make the other entries unusable.
+            let codeview = check_alloc("CodeView", cv_size.into(), self.limits.max_alloc)
+                .and_then(|_| Ok(self.reader.read_bytes_at_offset(cv_offset.into(), cv_size as usize)?))
+                .and_then(|data| debug::CodeView::parse_bytes(&data));
+            match codeview {
+                Ok(codeview) => debug.codeview = codeview.map(|value| HeaderField { value, offset: cv_offset.into(), rva: cv_rva.into() }),
+                Err(err) => tracing::warn!(%err, "skipped CodeView data"),
+            }
+        }
+
+        self.debug = HeaderField { value: debug, offset: debug_offset.into(), rva: debug_rva.into() };
+        Ok(())
+    }
+
+    /// The file header points to a COFF symbol table.
+    #[inline]
+    pub fn has_symbols(&self) -> bool {
+        self.file.value.symbol_table_ptr.value != 0 && self.file.value.symbols.value != 0
+    }
+
+    /// Parse the COFF symbol table and string table. Replaces symbols parsed before.
+    /// Not part of `parse_file` and friends; in images, the pointer is often left stale.
+    pub fn parse_symbols(&mut self) -> Result<()> {
+        self.symbols = Default::default();
+        let offset = u64::from(self.dos.offset) + self.file.value.symbol_table_ptr.value as u64;
+        let result = self.read_symbols(offset).map_err(|err| match err {
+            PeError::Context { .. } => err,
+            err => err.context("CoffSymbolTable", offset),
+        });
+        self.status.symbols = ParseStatus::of(self.has_symbols(), &result);
+        result
+    }
+
+    fn read_symbols(&mut self, offset: u64) -> Result<()> {
+        if !self.has_symbols() {
+            return Ok(());
+        }
+
+        let count = self.file.value.symbols.value;
+        let table_len = count as u64 * coff::SYMBOL_LENGTH;
+        check_alloc("CoffSymbolTable", table_len, self.limits.max_alloc)?;
+
+        //The string table is cut at the end of the input; names in the missing part fail.
+        let end = self.reader.end_offset()?;
+        let strings_len = match self.reader.read_bytes_at_offset(offset + table_len, 4) {
+            Ok(size) => u32::from_le_bytes([size[0], size[1], size[2], size[3]]).max(4) as u64,
+            Err(_) => 0,
+        };
+        check_alloc("CoffStringTable", strings_len, self.limits.max_alloc)?;
+        let len = (table_len + strings_len).min(end.saturating_sub(offset)).max(table_len);
+
+        let bytes = self.reader.read_bytes_at_offset(offset, len as usize)?;
+        let table = CoffSymbolTable::parse_bytes(&bytes, count, offset)?;
+        self.symbols = HeaderField::at_offset(table, offset);
+        Ok(())
+    }
+
+    /// CHPE metadata is parsed only for ARM64EC/ARM64X (PE32+) images; x86 CHPE is not supported.
+    #[inline]
+    pub fn has_chpe(&self) -> bool {
+        self.load_config.value.chpe_metadata_pointer.value != 0 && self.optional.value.get_image_type() == ImageType::PE64
+    }
+
+    /// Native and x64-compatible views of an ARM64X or ARM64EC image; empty for other images.
+    /// Needs parsed imports, exports and CHPE metadata. See `hybrid` for how views are derived.
+    pub fn hybrid_views(&self) -> Vec<HybridView> {
+        hybrid::views(self)
+    }
+
+    /// Parse CHPE metadata. Parses the load config first, if it is not parsed yet.
+    /// Replaces metadata parsed before.
+    pub fn parse_chpe(&mut self) -> Result<()> {
+        if !self.status.load_config.is_done() {
+            self.parse_load_config()?;
+        }
+        self.chpe = Default::default();
+        let result = self.read_chpe().map_err(|err| match err {
+            PeError::Context { .. } => err,
+            err => {
+                let va = self.load_config.value.chpe_metadata_pointer.value;
+                let offset = va.checked_sub(self.optional.value.image_base())
+                    .and_then(|rva| u32::try_from(rva).ok())
+                    .and_then(|rva| self.rva_to_offset(rva))
+                    .unwrap_or_default();
+                err.context("ChpeMetadata", offset.into())
+            },
+        });
+        self.status.chpe = ParseStatus::of(self.has_chpe(), &result);
+        result
+    }
+
+    fn read_chpe(&mut self) -> Result<()> {
+        if !self.has_chpe() {
+            return Ok(());
+        }
+
+        let va = self.load_config.value.chpe_metadata_pointer.value;
+        let image_base = self.optional.value.image_base();
+        let rva = va.checked_sub(image_base)
+            .and_then(|rva| u32::try_from(rva).ok())
+            .ok_or(PeError::InvalidRVA(va))?;
+        let offset = self.rva_to_offset(rva).ok_or(PeError::NoSectionForRVA(rva.into()))?;
+
+        let buf = self.reader.read_bytes_at_offset(offset.into(), chpe::HEADER_LENGTH as usize)?;
+        let mut md = ChpeMetadata::parse_bytes(buf, offset.into())?;
+        md.fix_rvas(rva.into());
+        let sections = IndexedSections::new(&self.sections.value, &self.section_map);
+        md.parse_tables(&sections, &mut self.reader, self.limits.max_alloc)?;
+        self.chpe = HeaderField { value: md, offset: offset.into(), rva: rva.into() };
+
+        Ok(())
+    }
+
     #[inline]
     pub fn has_rsrc(&self) -> bool {
-        self.data_dirs.value[DirectoryType::Resource as usize].value.rva.value != 0
+        self.has_directory(DirectoryType::Resource)
     }
 
+    /// Parse the whole resource tree. Replaces resources parsed before.
     pub fn parse_resources(&mut self) -> Result<()> {
-        if !self.has_rsrc() {
-            return Ok(())
-        }
+        self.resources = Default::default();
+        let result = self.read_resources().map_err(|err| self.directory_error(DirectoryType::Resource, err));
+        self.status.resources = ParseStatus::of(self.has_rsrc(), &result);
+        result
+    }
 
-        let dd_rsrc = &self.data_dirs.value[DirectoryType::Resource as usize].value;
+    fn read_resources(&mut self) -> Result<()> {
+        let Some(dd_rsrc) = self.directory(DirectoryType::Resource) else {
+            return Ok(())
+        };
         let rsrc_rva = dd_rsrc.rva.value;
-        let rsrc_offset = self.rva_to_offset(rsrc_rva.into()).ok_or(PeError::NoSectionForRVA(rsrc_rva.into()))?;
-        let rsrc_section = rva_to_section(&self.sections.value, rsrc_rva)
+        let rsrc_offset = self.rva_to_offset(rsrc_rva).ok_or(PeError::NoSectionForRVA(rsrc_rva.into()))?;
+        let rsrc_section = IndexedSections::new(&self.sections.value, &self.section_map)
+            .section_for_rva(rsrc_rva)
             .ok_or(PeError::NoSectionForRVA(rsrc_rva.into()))?;
         
         let bytes = self.reader.read_bytes_at_offset(rsrc_offset.into(), rsrc::DIR_LENGTH as usize)?;
 
         let mut rsrc_dir = ResourceDirectory::parse_bytes(bytes, rsrc_offset.into())?;
-        rsrc_dir.parse_rsrc(rsrc_section, &mut self.reader)?;
+        rsrc_dir.parse_rsrc_with_progress(rsrc_section, &mut self.reader, self.limits.max_rsrc_depth, self.limits.max_rsrc_entries, &mut self.progress)?;
         self.resources = HeaderField{value: rsrc_dir, offset: rsrc_offset.into(), rva: rsrc_rva.into()};
 
         Ok(())
     }
 
+    /// Data entry of the resource with type `typ`, name or id `name` and language `lang`, or the
+    /// first language if `lang` is `None`. Resources must be parsed.
+    ///
+    /// `ResourceData::load_data` reads its bytes.
+    pub fn find_resource(&self, typ: impl Into<ResourceId>, name: impl Into<ResourceId>, lang: Option<u16>) -> Option<&ResourceData> {
+        self.resources.value.find_resource(typ, name, lang)
+    }
+
+    /// Parse the first `VERSION` resource, if the image has one. Resources must be parsed.
+    pub fn version_info(&mut self) -> Result<Option<VersionInfo>> {
+        match self.version_resource()? {
+            Some((offset, bytes)) => Ok(Some(VersionInfo::parse_bytes(&bytes, offset)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Offset and bytes of the first `VERSION` resource.
+    fn version_resource(&mut self) -> Result<Option<(u64, Vec<u8>)>> {
+        //type -> name -> language -> data
+        let mut node = self.resources.value.entries.iter()
+            .find(|e| e.resource_type() == Some(ResourceType::VERSION))
+            .map(|e| &e.data);
+        while let Some(ResourceNode::Dir(dir)) = node {
+            node = dir.entries.first().map(|e| &e.data);
+        }
+
+        let Some(ResourceNode::Data(data)) = node else {
+            return Ok(None);
+        };
+
+        let (rva, size) = (data.rva.value, data.size.value);
+        check_alloc("VersionInfo", size.into(), self.limits.max_alloc)?;
+        let offset = self.rva_to_offset(rva).ok_or(PeError::InvalidRVA(rva.into()))?;
+        let bytes = self.reader.read_bytes_at_offset(offset.into(), size as usize)?;
+
+        Ok(Some((offset.into(), bytes)))
+    }
+
+    /// Disassemble up to `count` instructions at `rva`, stopping at the end of section data.
+    #[cfg(feature="disasm")]
+    pub fn disassemble_at_rva(&mut self, rva: u32, count: usize) -> Result<Vec<disasm::Instruction>> {
+        let machine = self.file.value.machine.value;
+        let bitness = disasm::bitness(machine).ok_or(PeError::UnsupportedMachine(machine))?;
+        let section = self.section_lookup().section_for_rva(rva).ok_or(PeError::NoSectionForRVA(rva.into()))?;
+        let section_end = section.virtual_address.value.saturating_add(section.sizeof_raw_data.value);
+
+        let size = (section_end.saturating_sub(rva) as usize).min(count.saturating_mul(disasm::MAX_INSTRUCTION_LENGTH));
+        let bytes = self.read_bytes_at_rva(rva, size)?;
+        Ok(disasm::disassemble(&bytes, bitness, self.optional.value.image_base(), rva, count))
+    }
+
+    /// Disassemble up to `count` instructions at the entry point.
+    #[cfg(feature="disasm")]
+    pub fn disassemble_entry(&mut self, count: usize) -> Result<Vec<disasm::Instruction>> {
+        self.disassemble_at_rva(self.optional.value.entry_point(), count)
+    }
+
+    /// Disassemble up to `count` instructions at each parsed export, by name. Forwarded exports
+    /// have no code and are left out.
+    #[cfg(feature="disasm")]
+    pub fn disassemble_exports(&mut self, count: usize) -> Vec<(String, Result<Vec<disasm::Instruction>>)> {
+        let forwarders = self.directory(DirectoryType::Export)
+            .map(|dir| dir.rva.value..dir.rva.value.saturating_add(dir.size.value))
+            .unwrap_or_default();
+        let exports: Vec<(String, u32)> = self.exports.value.exports.iter()
+            .filter(|e| !forwarders.contains(&e.address.value))
+            .map(|e| (e.name.value.clone(), e.address.value))
+            .collect();
+
+        exports.into_iter()
+            .map(|(name, rva)| (name, self.disassemble_at_rva(rva, count)))
+            .collect()
+    }
+
+    /// Find the rich header between the DOS header and the PE header.
+    pub fn rich_header(&mut self) -> Result<Option<RichHeader>> {
+        let start = u64::from(self.dos.offset);
+        let end = start + self.dos.value.e_lfanew.value as u64;
+        check_alloc("RichHeader", end - start, self.limits.max_alloc)?;
+        let bytes = self.reader.read_bytes_at_offset(start, (end - start) as usize)?;
+        Ok(RichHeader::parse_bytes(&bytes, start))
+    }
+
+    /// Byte ranges of import, export and version strings and the rich header, to be written
+    /// as YARA strings with `yara::format_rule`. Directories must be parsed.
+    pub fn yara_strings(&mut self) -> Result<Vec<YaraString>> {
+        yara::collect(self)
+    }
+
     #[inline]
     pub fn format_resource_tree(&self, f: &mut dyn Write, seperator: &String, level: u8) -> std::fmt::Result {
         writeln!(f, "Resource Directory: {{")?;
@@ -322,6 +1329,7 @@ impl PeImage {
 
     pub fn format_basic_headers(&self, f: &mut dyn Write) -> std::fmt::Result {
         writeln!(f, "DosHeader: {}", self.dos.value)?;
+        writeln!(f, "NtHeaders: {}", self.nt.value)?;
         writeln!(f, "FileHeader: {}", self.file.value)?;
         writeln!(f, "OptionalHeader: {}", self.optional.value)?;
 
@@ -331,14 +1339,11 @@ impl PeImage {
     pub fn format_data_dirs(&self, f: &mut dyn Write) -> std::fmt::Result {
         //Data directories
         writeln!(f, "DataDirectories: [")?;
-        for dir in &self.data_dirs.value {
-            if dir.value.rva.value != 0 {
-                write!(f, "  {}, ", dir)?;
-                let section = self.directory_section(dir.value.member);
-                if let Some(sec) = section {
-                    writeln!(f, " Section: {},", sec.name_str().unwrap_or_else(|err| format!("{err}")))?;
-                }
-                println!("");
+        for dir in self.iter_directories() {
+            write!(f, "  {{ {:?}, RVA: {:08x}, Size: {:08x} }}, ", dir.member, dir.rva, dir.size)?;
+            match dir.section {
+                Some(sec) => writeln!(f, " Section: {},", sec.name_lossy())?,
+                None => writeln!(f)?,
             }
         }
         writeln!(f, "]")
@@ -376,6 +1381,10 @@ impl PeImage {
             writeln!(f, "Export Directory: {{")?;
             let export_dir = &self.exports.value;
             writeln!(f, "  DLL Name: {}", export_dir.name)?;
+            writeln!(f, "  Timestamp: {}", export_dir.timestamp.value)?;
+            writeln!(f, "  Version: {}.{}", export_dir.major_version.value, export_dir.minor_version.value)?;
+            writeln!(f, "  Ordinal Base: {}", export_dir.base.value)?;
+            writeln!(f, "  Functions: {}, Names: {}", export_dir.number_of_functions.value, export_dir.number_of_names.value)?;
             writeln!(f, "  Exports: [")?;
             
             for export in &export_dir.exports {
@@ -389,6 +1398,34 @@ impl PeImage {
         Ok(())
     }
 
+    pub fn format_load_config(&self, f: &mut dyn Write) -> std::fmt::Result {
+        if self.has_load_config() {
+            writeln!(f, "LoadConfig: {}", self.load_config.value)?;
+        }
+        if self.has_chpe() {
+            let chpe = &self.chpe.value;
+            writeln!(f, "CHPE Metadata: {chpe}")?;
+            writeln!(f, "  Code ranges: [")?;
+            for range in &chpe.code_ranges {
+                writeln!(f, "    {}", range.value)?;
+            }
+            writeln!(f, "  ]")?;
+            writeln!(f, "  Redirections: [")?;
+            for redirect in &chpe.redirections {
+                writeln!(f, "    {}", redirect.value)?;
+            }
+            writeln!(f, "  ]")?;
+        }
+        Ok(())
+    }
+
+    pub fn format_hybrid_views(&self, f: &mut dyn Write) -> std::fmt::Result {
+        for view in self.hybrid_views() {
+            writeln!(f, "{view}")?;
+        }
+        Ok(())
+    }
+
     pub fn format_relocations(&self, f: &mut dyn Write) -> std::fmt::Result {
         if self.has_relocations() && self.relocations.value.is_valid() {
             writeln!(f, "Relocation Directory: [")?;
@@ -409,39 +1446,55 @@ impl PeImage {
     pub(crate) fn parse_fixed_headers(&mut self, pos: u64) -> Result<u64> {
         let mut offset = pos;
 
-        let mut buf = self.reader.read_bytes_at_offset(pos, dos::HEADER_LENGTH as usize)?;
-        self.dos = HeaderField{ value: DosHeader::parse_bytes(buf, pos)?, offset: offset, rva: offset };
+        //One read instead of one per header; inputs too small for it take the slow path.
+        self.header_bytes = self.reader.read_bytes_at_offset(pos, HEADERS_READ_SIZE).unwrap_or_default();
+        self.header_pos = pos;
+
+        let mut buf = self.read_header_bytes(pos, dos::HEADER_LENGTH as usize)?;
+        self.dos = HeaderField::at_offset(DosHeader::parse_bytes(buf, pos)?, offset);
         offset += self.dos.value.e_lfanew.value as u64;
 
-        buf = self.reader.read_bytes_at_offset(offset, file::HEADER_LENGTH as usize)?;
-        self.file = HeaderField{ value: FileHeader::parse_bytes(buf, offset)?, offset: offset, rva: offset};
+        //Inputs holding the headers in the first read are large enough.
+        if offset + file::HEADER_LENGTH > self.header_pos + self.header_bytes.len() as u64 {
+            let end = self.reader.end_offset()?;
+            self.dos.value.validate_lfanew(end.saturating_sub(pos))?;
+        }
+
+        buf = self.read_header_bytes(offset, file::HEADER_LENGTH as usize)?;
+        let file_hdr = FileHeader::parse_bytes(buf.clone(), offset)?;
+        let nt = NtHeaders::parse_bytes(&buf, offset, file_hdr.optional_header_size.value)?;
+        self.nt = HeaderField::at_offset(nt, offset);
+        self.file = HeaderField::at_offset(file_hdr, offset);
         offset += file::HEADER_LENGTH;
 
-        buf = self.reader.read_bytes_at_offset(offset, self.file.value.optional_header_size.value as usize)?;
+        buf = self.read_header_bytes(offset, self.file.value.optional_header_size.value as usize)?;
+
+        //Layout is picked by magic, not by size; ARM and ARM64 images don't always use the usual sizes.
+        let opt_size = buf.len() as u64;
+        let magic = if buf.len() >= 2 { ImageType::from(u16::from_le_bytes([buf[0], buf[1]])) } else { ImageType::UNKNOWN };
 
-        match buf.len() {
-            //(optional::x86::HEADER_LENGTH + DATA_DIR_LENGTH * 16)
-            0xE0 => {
+        match magic {
+            //Data directories follow, as many as NumberOfRvaAndSizes says and the header holds.
+            ImageType::PE32 | ImageType::ROM if opt_size >= optional::x86::HEADER_LENGTH => {
                 let opt = OptionalHeader32::parse_bytes(buf.clone(), offset)?;
-                self.optional = HeaderField{ value: OptionalHeader::X86(opt), offset: offset, rva: offset};
-                offset += optional::x86::HEADER_LENGTH;
+                let dir_count = opt.number_of_rva_and_sizes.value;
+                self.optional = HeaderField::at_offset(OptionalHeader::X86(opt), offset);
 
+                let dir_offset = offset + optional::x86::HEADER_LENGTH;
                 let dir_buf = &buf[optional::x86::HEADER_LENGTH as usize..];
-                let dirs = parse_data_directories(&dir_buf, 16, offset)?;
-                self.data_dirs = HeaderField{ value: dirs, offset: offset, rva: offset};
-                offset += 16 * 8;
+                let dirs = parse_data_directories(dir_buf, dir_count, dir_offset)?;
+                self.data_dirs = HeaderField::at_offset(dirs, dir_offset);
             },
 
-            //(optional::x64::HEADER_LENGTH + DATA_DIR_LENGTH * 16)
-            0xF0 => {
+            ImageType::PE64 if opt_size >= optional::x64::HEADER_LENGTH => {
                 let opt = OptionalHeader64::parse_bytes(buf.clone(), offset)?;
-                self.optional = HeaderField {value: OptionalHeader::X64(opt), offset: offset, rva: offset};
-                offset += optional::x64::HEADER_LENGTH;
+                let dir_count = opt.number_of_rva_and_sizes.value;
+                self.optional = HeaderField::at_offset(OptionalHeader::X64(opt), offset);
 
+                let dir_offset = offset + optional::x64::HEADER_LENGTH;
                 let dir_buf = &buf[optional::x64::HEADER_LENGTH as usize..];
-                let dirs = parse_data_directories(&dir_buf, 16, offset)?;
-                self.data_dirs = HeaderField{ value: dirs, offset: offset, rva: offset};
-                offset += 16 * 8;
+                let dirs = parse_data_directories(dir_buf, dir_count, dir_offset)?;
+                self.data_dirs = HeaderField::at_offset(dirs, dir_offset);
             },
 
             _ => {
@@ -449,19 +1502,35 @@ impl PeImage {
             }
         }
 
+        //Section table follows the optional header, whatever its declared size.
+        offset += opt_size;
+
         Ok(offset)
     }
 
+    /// `size` bytes at `offset`, from `header_bytes` if it holds them.
+    fn read_header_bytes(&mut self, offset: u64, size: usize) -> Result<Vec<u8>> {
+        let start = offset.checked_sub(self.header_pos).map(|start| start as usize);
+        match start.and_then(|start| self.header_bytes.get(start..start.checked_add(size)?)) {
+            Some(bytes) => Ok(bytes.to_vec()),
+            None => Ok(self.reader.read_bytes_at_offset(offset, size)?),
+        }
+    }
+
     /// Parse section headers. 
     /// These are fixed sized contigious values, and size is known from OptionalHeader.
     pub(crate) fn parse_sections(&mut self, pos: u64) -> Result<u64> {
         let mut offset = pos;
         let sec_count = self.file.value.sections.value;
+        let end = self.reader.end_offset()?;
+        section::check_section_count(sec_count, offset, self.limits.max_sections, end)?;
         let size = section::HEADER_LENGTH * sec_count as u64;
         
-        let buf = self.reader.read_bytes_at_offset(offset, size as usize)?;
+        let buf = self.read_header_bytes(offset, size as usize)?;
+        self.header_bytes = Vec::new();
         let sections = section::parse_sections(&buf, sec_count, offset)?;
-        self.sections = HeaderField{ value:sections, offset: offset, rva: offset};
+        self.sections = HeaderField::at_offset(sections, offset);
+        self.reindex_sections();
         
         offset += size;
 
@@ -471,16 +1540,58 @@ impl PeImage {
     /// Parse headers whose contents may be scattered.
     /// Content offsets are derived from parsed header values.
     pub(crate) fn parse_dynamic_headers(&mut self) -> Result<()> {
-        self.parse_import_directory()?;
-        self.parse_exports()?;
-        self.parse_relocations()?;
-        self.parse_resources()?;
+        self.progress.reset();
+        self.parse_step(0, "imports");
+        timed!(self.stats, "imports", self.parse_import_directory())?;
+        self.parse_step(1, "delay imports");
+        timed!(self.stats, "delay imports", self.parse_delay_imports())?;
+        self.parse_step(2, "bound imports");
+        timed!(self.stats, "bound imports", self.parse_bound_imports())?;
+        self.parse_step(3, "exports");
+        timed!(self.stats, "exports", self.parse_exports())?;
+        self.parse_step(4, "relocations");
+        timed!(self.stats, "relocations", self.parse_relocations())?;
+        self.parse_step(5, "resources");
+        timed!(self.stats, "resources", self.parse_resources())?;
+        //Load config and CHPE metadata are optional; their failures are logged and kept in `status`.
+        self.parse_step(6, "load config");
+        let _ = timed!(self.stats, "load config", self.parse_load_config());
+        self.parse_step(7, "debug directory");
+        timed!(self.stats, "debug directory", self.parse_debug_directory())?;
+        self.parse_step(8, "CHPE metadata");
+        let _ = timed!(self.stats, "CHPE metadata", self.parse_chpe());
+        //The last step is done.
+        self.progress.update(1, 1);
         Ok(())
     }
 
-    pub(crate) fn parse_all_headers(&mut self, pos: u64) -> Result<()> {
-        let offset = self.parse_fixed_headers(pos)?;
-        self.parse_sections(offset)?;
+    /// Report the start of step `index` of `parse_dynamic_headers` to the progress observer.
+    fn parse_step(&mut self, index: u64, stage: &str) {
+        self.progress.stage(stage);
+        self.progress.step(index, DYNAMIC_STEPS);
+    }
+
+    /// Parse only fixed headers, data directories and section headers of the image at `pos`.
+    /// Directories can then be parsed one by one, e.g. `parse_exports`; `status` tells what is parsed.
+    pub fn parse_headers_only(&mut self, pos: u64) -> Result<()> {
+        let _span = tracing::info_span!("parse PE headers", pos).entered();
+        if pos != 0 {
+            //Pointers in the image are relative to its start.
+            let reader = std::mem::replace(&mut self.reader, Box::new(Cursor::new(Vec::new())));
+            self.reader = Box::new(OffsetReader::new(reader, pos));
+            self.pos += pos;
+        }
+        let offset = timed!(self.stats, "fixed headers", self.parse_fixed_headers(0))?;
+        timed!(self.stats, "sections", self.parse_sections(offset))?;
+        tracing::debug!(machine = ?self.file.value.machine.value, sections = self.sections.value.len(), "headers parsed");
+        Ok(())
+    }
+
+    /// Parse all supported headers from `pos`.
+    /// Use with `new` to change settings like `limits` before parsing.
+    pub fn parse_all_headers(&mut self, pos: u64) -> Result<()> {
+        let _span = tracing::info_span!("parse PE", pos).entered();
+        self.parse_headers_only(pos)?;
         self.parse_dynamic_headers()?;
         Ok(())
     }
@@ -489,8 +1600,8 @@ impl PeImage {
     /// In case of error while reading or parsing file, a `dyn Error` is returned.  
     /// Params:
     /// - `f`: input file handle
-    /// - `pos`: starting `pos`ition of PE content in file. Use `0` (other values are not tested).
-    pub fn parse_file(file: File, pos: u64) -> crate::Result<Self> where Self: Sized {
+    /// - `pos`: offset of the image in the input; see `PeImage::pos`.
+    pub fn parse_file(file: File, pos: u64) -> Result<Self> where Self: Sized {
         let reader = Box::new(BufReader::new(file));
         let mut pe = Self::new(reader);
         
@@ -503,8 +1614,8 @@ impl PeImage {
     /// In case of error while reading or parsing, a `dyn Error` is returned.
     /// Params:
     /// - `bytes`: `Vec` of `u8`
-    /// - `pos`: starting `pos`ition of PE content in `bytes`. Use `0` (other values are not tested).
-    pub fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> where Self: Sized {
+    /// - `pos`: offset of the image in the input; see `PeImage::pos`.
+    pub fn parse_bytes(bytes: Vec<u8>, pos: u64) -> Result<Self> where Self: Sized {
         let reader = Box::new(Cursor::new(bytes));
         let mut pe = Self::new(reader);
 
@@ -518,8 +1629,8 @@ impl PeImage {
     /// In case of error while reading or parsing, a `dyn Error` is returned.  
     /// **Params:**
     /// - `reader`: readable source in `Box`, must implement `BuffReadExt` from this crate.
-    /// - `pos`: starting `pos`ition of PE content. Use `0` (other values are not tested).
-    pub fn parse_readable(reader: Box<dyn BufReadExt>, pos: u64) -> crate::Result<Self> where Self: Sized {
+    /// - `pos`: offset of the image in the input; see `PeImage::pos`.
+    pub fn parse_readable(reader: Box<dyn BufReadExt>, pos: u64) -> Result<Self> where Self: Sized {
         let mut pe = Self::new(reader);
         
         pe.parse_all_headers(pos)?;
@@ -556,25 +1667,7 @@ impl TryFrom<Box<dyn BufReadExt>> for PeImage{
 
 impl Display for PeImage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-       
-        //Basic headers
-        self.format_basic_headers(f)?;
-        //Data dirs
-        self.format_data_dirs(f)?;
-        //Sections
-        self.format_sections(f)?;
-        //Imports
-        if self.has_imports() { self.format_imports(f)?; }
-        //Exports
-        if self.has_exports() { self.format_exports(f)?; }
-        //Relocations
-        if self.has_relocations() { self.format_relocations(f)?; }
-        //Resources
-        if self.has_rsrc() && self.resources.value.is_valid() {
-            self.format_resource_tree(f, &String::from("  "), 1)?;
-        }
-
-        Ok(())
+        self.format_report(f, &report::ReportSection::ALL)
     }
 }
 
@@ -585,11 +1678,12 @@ mod tests {
     use std::io::Cursor;
 
     use crate::{
-        pe::{optional::{DirectoryType, ImageType, OptionalHeader, MAX_DIRS}, section::Flags},
-        types::{Header, BufReadExt},
+        pe::{optional::{DirectoryType, ImageType, OptionalHeader, MAX_DIRS}, section::Flags, ParseStatus},
+        types::{BufReadExt, FileOffset, Header, Rva},
+        utils::FragmentReader,
     };
 
-    use super::PeImage;
+    use super::{PeError, PeImage};
 
     const RAW_BYTES_64: [u8; 704] = [
         0x4D, 0x5A, 0x90, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00,
@@ -648,13 +1742,13 @@ mod tests {
         let offset = pe.parse_fixed_headers(0).unwrap();
         pe.parse_sections(offset).unwrap();
         assert!(pe.dos.value.is_valid());
-        assert_eq!(pe.dos.offset, 0);
-        assert_eq!(pe.dos.rva, 0);
+        assert_eq!(pe.dos.offset, FileOffset(0));
+        assert_eq!(pe.dos.rva, Rva(0));
         assert!(pe.file.value.is_valid());
-        assert_eq!(pe.file.offset, 0xf0);
-        assert_eq!(pe.file.rva, 0xf0);
-        assert_eq!(pe.optional.offset, 0x108);
-        assert_eq!(pe.optional.rva, 0x108);
+        assert_eq!(pe.file.offset, FileOffset(0xf0));
+        assert_eq!(pe.file.rva, Rva(0xf0));
+        assert_eq!(pe.optional.offset, FileOffset(0x108));
+        assert_eq!(pe.optional.rva, Rva(0x108));
         
         if let OptionalHeader::X64(opt) = pe.optional.value {
             assert_eq!(opt.magic.value, ImageType::PE64);
@@ -663,9 +1757,9 @@ mod tests {
             assert!(false, "Didn't expect OptionalHeader32");
         }
 
-        assert_eq!(pe.data_dirs.offset, 0x178);
+        assert_eq!(pe.data_dirs.offset, FileOffset(0x178));
         assert_eq!(pe.data_dirs.value.len(), MAX_DIRS as usize);
-        assert_eq!(pe.data_dirs.value[DirectoryType::ImportAddressTable as usize].offset, 0x1d8);
+        assert_eq!(pe.data_dirs.value[DirectoryType::ImportAddressTable as usize].offset, FileOffset(0x1d8));
         assert_eq!(pe.data_dirs.value[DirectoryType::ImportAddressTable as usize].value.rva.value, 0x00044000);
         assert_eq!(pe.data_dirs.value[DirectoryType::ImportAddressTable as usize].value.size.value, 0x00000308);
         /*
@@ -701,6 +1795,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_headers_beyond_first_read() {
+        //NT headers moved past the bytes read at once.
+        let mut bytes = RAW_BYTES_64.to_vec();
+        bytes.splice(0x40..0x40, vec![0u8; super::HEADERS_READ_SIZE]);
+        bytes[0x3c..0x40].copy_from_slice(&(0xf0 + super::HEADERS_READ_SIZE as u32).to_le_bytes());
+
+        let mut pe = PeImage::new(Box::new(Cursor::new(bytes)));
+        pe.parse_headers_only(0).unwrap();
+        assert_eq!(pe.file.offset, FileOffset(0xf0 + super::HEADERS_READ_SIZE as u64));
+        assert_eq!(pe.sections.value.len(), 5);
+        assert_eq!(pe.sections.value[4].value.name_str().unwrap(), ".reloc");
+    }
+
+    #[test]
+    fn lfanew_beyond_input() {
+        let mut bytes = RAW_BYTES_64.to_vec();
+        bytes[0x3c..0x40].copy_from_slice(&0x10_0000u32.to_le_bytes());
+
+        let mut pe = PeImage::new(Box::new(Cursor::new(bytes)));
+        let err = pe.parse_headers_only(0).unwrap_err();
+        assert!(matches!(err.root(), PeError::InvalidHeader { name, offset: 0x3c, .. } if name == "DosHeader"), "{err}");
+    }
+
+    #[test]
+    fn typed_address_conversions() {
+        let path = std::env::current_dir().unwrap().join("test-data").join("test.dll");
+        let pe = PeImage::parse_bytes(std::fs::read(path).unwrap(), 0).unwrap();
+        let export = pe.exports.value.exports[0].address.value;
+
+        let offset = pe.offset_of_rva(Rva(export)).unwrap();
+        assert_eq!(offset, FileOffset::from(pe.rva_to_offset(export).unwrap()));
+        assert_eq!(pe.rva_of_offset(offset), Some(Rva(export)));
+        assert_eq!(pe.rva_of_va(pe.va_of_rva(Rva(export))), Some(Rva(export)));
+        assert_eq!(pe.offset_of_rva(Rva(u32::MAX)), None);
+        assert_eq!(pe.file.rva, Rva::of_header(pe.file.offset.0));
+    }
+
+    #[test]
+    fn parse_headers_at_pos() {
+        let mut bytes = vec![0u8; 0x200];
+        bytes.extend(RAW_BYTES_64);
+        let reader = Box::new(FragmentReader::new(bytes, 0x1000));
+        let mut pe = PeImage::new(reader);
+        pe.parse_headers_only(0x1200).unwrap();
+        assert_eq!(pe.pos, 0x1200);
+        assert_eq!(pe.dos.offset, FileOffset(0));
+        assert_eq!(pe.file.offset, FileOffset(0xf0));
+        assert_eq!(pe.sections.value.len(), 5);
+        assert_eq!(pe.sections.value[0].value.name_str().unwrap(), ".text");
+    }
+
     #[test]
     fn read_string_at_offset() {
         //let pe = PeImage::parse_bytes(RAW_BYTES_64.to_vec(), 0).unwrap();
@@ -773,13 +1919,13 @@ mod tests {
         pe.parse_sections(offset).unwrap();
 
         assert!(pe.dos.value.is_valid());
-        assert_eq!(pe.dos.offset, 0);
-        assert_eq!(pe.dos.rva, 0);
+        assert_eq!(pe.dos.offset, FileOffset(0));
+        assert_eq!(pe.dos.rva, Rva(0));
         assert!(pe.file.value.is_valid());
-        assert_eq!(pe.file.offset, 0x110);
-        assert_eq!(pe.file.rva, 0x110);
-        assert_eq!(pe.optional.offset, 0x128);
-        assert_eq!(pe.optional.rva, 0x128);
+        assert_eq!(pe.file.offset, FileOffset(0x110));
+        assert_eq!(pe.file.rva, Rva(0x110));
+        assert_eq!(pe.optional.offset, FileOffset(0x128));
+        assert_eq!(pe.optional.rva, Rva(0x128));
 
         if let OptionalHeader::X86(opt) = pe.optional.value {
             assert!(opt.is_valid());
@@ -788,9 +1934,9 @@ mod tests {
             assert!(false, "Didn't expect OptionalHeader64");
         }
 
-        assert_eq!(pe.data_dirs.offset, 0x188);
+        assert_eq!(pe.data_dirs.offset, FileOffset(0x188));
         assert_eq!(pe.data_dirs.value.len(), MAX_DIRS as usize);
-        assert_eq!(pe.data_dirs.value[DirectoryType::ImportAddressTable as usize].offset, 0x1e8);
+        assert_eq!(pe.data_dirs.value[DirectoryType::ImportAddressTable as usize].offset, FileOffset(0x1e8));
         assert_eq!(pe.data_dirs.value[DirectoryType::ImportAddressTable as usize].value.rva.value,  0x0000D000);
         assert_eq!(pe.data_dirs.value[DirectoryType::ImportAddressTable as usize].value.size.value, 0x00000174);
 
@@ -814,6 +1960,124 @@ mod tests {
         }
     }
 
+    #[test]
+    fn optional_header_by_magic() {
+        //PE32 optional header with a declared size of PE32+ one.
+        let mut bytes = RAW_BYTES_32.to_vec();
+        let lfanew = u32::from_le_bytes(bytes[0x3c..0x40].try_into().unwrap()) as usize;
+        bytes[lfanew + 20..lfanew + 22].copy_from_slice(&0xF0u16.to_le_bytes());
+
+        let mut pe = PeImage::new(Box::new(Cursor::new(bytes)));
+        let offset = pe.parse_fixed_headers(0).unwrap();
+        assert!(matches!(pe.optional.value, OptionalHeader::X86(_)));
+        assert_eq!(FileOffset(offset), pe.optional.offset + 0xF0);
+        assert_eq!(pe.data_dirs.offset, pe.optional.offset + 0x60);
+    }
+
+    #[test]
+    fn parse_arm64_images() {
+        use std::{env, fs};
+        use crate::pe::{file::MachineType, relocs::RelocType};
+
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+        let lfanew = u32::from_le_bytes(bytes[0x3c..0x40].try_into().unwrap()) as usize;
+
+        let mut x64 = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        x64.parse_all_headers(0).unwrap();
+
+        for machine in [MachineType::ARM64, MachineType::ARM64EC, MachineType::ARM64X] {
+            let mut arm = bytes.clone();
+            arm[lfanew + 4..lfanew + 6].copy_from_slice(&(machine as u16).to_le_bytes());
+
+            let mut pe = PeImage::parse_bytes(arm, 0).unwrap();
+            pe.parse_all_headers(0).unwrap();
+            assert_eq!(pe.file.value.machine.value, machine);
+            assert!(pe.file.value.machine.value.is_arm64());
+            assert_eq!(pe.optional.value.get_image_type(), ImageType::PE64);
+
+            assert_eq!(pe.imports.value.len(), x64.imports.value.len());
+            for (arm_id, x64_id) in pe.imports.value.iter().zip(x64.imports.value.iter()) {
+                assert_eq!(arm_id.value.imports.len(), x64_id.value.imports.len());
+            }
+
+            let mut relocs = pe.relocations.value.blocks.iter().flat_map(|rb| rb.value.relocs.iter());
+            assert!(relocs.clone().count() > 0);
+            assert!(relocs.all(|r| matches!(r.value.rtype, RelocType::DIR64 | RelocType::ABSOLUTE)));
+        }
+    }
+
+    #[test]
+    fn error_context_chain() {
+        let err = PeError::InvalidRVA(0x1f000)
+            .context("ImportDescriptor #12", 0x3c40)
+            .context("Import directory", 0x3c00);
+        assert!(matches!(err.root(), PeError::InvalidRVA(0x1f000)));
+        assert_eq!(err.to_string(), "while parsing Import directory at 0x3c00");
+        assert_eq!(err.chain(), "while parsing Import directory at 0x3c00: while parsing ImportDescriptor #12 at 0x3c40: invalid rva 0x0001f000");
+
+        let err = PeError::from(crate::types::ReadExtError::Truncated { offset: 0x10, size: 4 });
+        assert_eq!(err.chain(), err.to_string());
+    }
+
+    #[test]
+    fn parse_load_config() {
+        use std::{env, fs};
+
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+
+        assert!(pe.has_load_config());
+        let lc = &pe.load_config.value;
+        assert_eq!(pe.load_config.rva, Rva(0x113e0));
+        assert_eq!(lc.size.value, 0x70);
+        assert_eq!(lc.security_cookie.value, 0x1_8001_3000);
+        assert_eq!(lc.security_cookie.rva, Rva(0x113e0 + 0x58));
+        assert_eq!(lc.guard_flags.value, 0);
+        assert!(lc.guard_flags().is_empty());
+        assert!(!pe.has_chpe());
+
+        assert!(pe.has_debug());
+        assert!(!pe.debug.value.entries.is_empty());
+        assert!(pe.debug.value.ex_dll_flags().is_none());
+    }
+
+    #[test]
+    fn bogus_chpe_pointer() {
+        use std::{env, fs};
+
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut bytes = fs::read(path).unwrap();
+        let pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        let lc_offset = pe.load_config.offset.0 as usize;
+
+        //Extend the load config to CHPEMetadataPointer and point it outside the image.
+        bytes[lc_offset..lc_offset + 4].copy_from_slice(&0xd0u32.to_le_bytes());
+        let pointer = pe.optional.value.image_base() + 0x7fff_0000;
+        bytes[lc_offset + 0xc8..lc_offset + 0xd0].copy_from_slice(&pointer.to_le_bytes());
+
+        let pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        assert_eq!(pe.load_config.value.chpe_metadata_pointer.value, pointer);
+        assert!(pe.status.load_config.is_parsed());
+        assert!(matches!(&pe.status.chpe, ParseStatus::Failed(err) if err.contains("ChpeMetadata")), "{:?}", pe.status.chpe);
+        assert!(pe.has_exports());
+    }
+
+    #[test]
+    fn version_info_product_key() {
+        use std::{env, fs};
+
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+
+        let info = pe.version_info().unwrap().unwrap();
+        assert_eq!(info.fixed.unwrap().file_version(), "2.42.0.0");
+        assert_eq!(info.string_tables[0].lang, "040904B0");
+        assert_eq!(info.get("OriginalFilename"), Some("libgthread-2.0-0.dll"));
+        assert_eq!(info.translations, vec![(0x409, 1200)]);
+        assert_eq!(pe.analyze().unwrap().product_key.unwrap(), "the glib developer community|glib|libgthread-2.0-0");
+    }
+
     #[test]
     fn section_of_directories() {
         let reader = Box::new(Cursor::new(RAW_BYTES_32.to_vec()));
@@ -829,4 +2093,247 @@ mod tests {
         assert_eq!(pe.directory_section(DirectoryType::Configuration).unwrap().name_str().unwrap(), ".rdata");
         assert_eq!(pe.directory_section(DirectoryType::ImportAddressTable).unwrap().name_str().unwrap(), ".rdata");
     }
+
+    #[test]
+    fn iterate_directories() {
+        let reader = Box::new(Cursor::new(RAW_BYTES_32.to_vec()));
+        let mut pe = PeImage::new(reader);
+        let offset = pe.parse_fixed_headers(0).unwrap();
+        pe.parse_sections(offset).unwrap();
+
+        let dirs: Vec<_> = pe.iter_directories().collect();
+        assert_eq!(dirs.len(), pe.data_dirs.value.iter().filter(|dir| dir.value.is_present()).count());
+        for entry in &dirs {
+            assert_eq!(entry.section.map(|s| s.virtual_address.value), pe.directory_section(entry.member).map(|s| s.virtual_address.value));
+            assert_eq!(entry.offset, pe.directory_file_offset(entry.member));
+        }
+
+        let import = dirs.iter().find(|entry| entry.member == DirectoryType::Import).unwrap();
+        assert_eq!(import.section.unwrap().name_str().unwrap(), ".rdata");
+        assert_eq!(import.offset, pe.rva_to_offset(import.rva));
+        let security = dirs.iter().find(|entry| entry.member == DirectoryType::Security).unwrap();
+        assert_eq!(security.offset, Some(security.rva));
+    }
+
+    #[test]
+    fn format_test_dll_directories() {
+        use std::{env, fs};
+
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+
+        let members: Vec<_> = pe.iter_directories().map(|dir| dir.member).collect();
+        assert_eq!(members, vec![
+            DirectoryType::Export, DirectoryType::Import, DirectoryType::Resource, DirectoryType::Exception,
+            DirectoryType::Security, DirectoryType::Relocation, DirectoryType::Debug, DirectoryType::Configuration,
+            DirectoryType::ImportAddressTable,
+        ]);
+
+        let mut text = String::new();
+        pe.format_data_dirs(&mut text).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), members.len() + 2);
+        assert_eq!(lines[1], "  { Export, RVA: 00011d30, Size: 00000085 },  Section: .rdata,");
+        assert_eq!(lines[3], "  { Resource, RVA: 00018000, Size: 000005a8 },  Section: .rsrc,");
+        assert_eq!(lines[9], "  { ImportAddressTable, RVA: 0000c000, Size: 00000218 },  Section: .rdata,");
+    }
+
+    #[test]
+    fn rebase_applies_relocations() {
+        use std::{env, fs};
+        use crate::pe::relocs;
+
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        let old_base = pe.optional.value.image_base();
+        let new_base = old_base + 0x7_0000_0000;
+
+        let (image, patched) = pe.rebase(new_base).unwrap();
+        assert_eq!(image.len(), bytes.len());
+        assert!(!patched.is_empty());
+        for range in &patched {
+            assert_eq!(range.rtype, relocs::RelocType::DIR64);
+            let at = range.offset as usize;
+            let read = |buf: &[u8]| u64::from_le_bytes(buf[at..at + 8].try_into().unwrap());
+            assert_eq!(read(&image), read(&bytes).wrapping_add(0x7_0000_0000));
+        }
+
+        let rebased = PeImage::parse_bytes(image, 0).unwrap();
+        assert_eq!(rebased.optional.value.image_base(), new_base);
+
+        //Same base; nothing but the header is rewritten, with the same value.
+        let (image, _) = pe.rebase(old_base).unwrap();
+        assert_eq!(image, bytes);
+    }
+
+    #[test]
+    fn rebase_pe32_beyond_32_bits() {
+        use crate::pe::{builder::PeBuilder, file::MachineType};
+
+        let bytes = PeBuilder::new(MachineType::I386).build().unwrap();
+        let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        let err = pe.rebase(0x1_0000_0000).unwrap_err();
+        assert!(matches!(err.root(), PeError::BeyondRange { value: 0x1_0000_0000, .. }), "{err}");
+    }
+
+    #[test]
+    fn map_virtual_layout() {
+        use std::{env, fs};
+
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+
+        let image = pe.map_to_virtual_layout().unwrap();
+        assert_eq!(image.len() as u32, pe.optional.value.sizeof_image());
+        assert_eq!(image[..0x400], bytes[..0x400]);
+
+        let exports = pe.directory(DirectoryType::Export).unwrap();
+        let (rva, size) = (exports.rva.value, exports.size.value as usize);
+        assert_eq!(image[rva as usize..][..size], pe.read_bytes_at_rva(rva, size).unwrap());
+
+        //Virtual size beyond raw data is zero filled.
+        let data = pe.sections.value.iter().map(|s| &s.value).find(|s| s.virtual_size.value > s.sizeof_raw_data.value).unwrap();
+        let tail = (data.virtual_address.value + data.sizeof_raw_data.value) as usize;
+        assert!(image[tail..(data.virtual_address.value + data.virtual_size.value) as usize].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn parse_directories_one_by_one() {
+        use std::{env, fs, io::Cursor};
+        use crate::pe::status::ParseStatus;
+
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut pe = PeImage::new(Box::new(Cursor::new(fs::read(path).unwrap())));
+        pe.parse_headers_only(0).unwrap();
+        assert_eq!(pe.sections.value.len(), 6);
+        assert!(pe.imports.value.is_empty());
+        assert_eq!(pe.status.exports, ParseStatus::NotParsed);
+
+        pe.parse_exports().unwrap();
+        pe.parse_exports().unwrap();
+        assert_eq!(pe.exports.value.exports.len(), 2);
+        assert!(pe.status.exports.is_parsed());
+        assert_eq!(pe.status.imports, ParseStatus::NotParsed);
+
+        pe.parse_chpe().unwrap();
+        assert!(pe.status.load_config.is_parsed());
+        assert_eq!(pe.status.chpe, ParseStatus::Absent);
+
+        //A failed step leaves its part empty.
+        pe.data_dirs.value[DirectoryType::Relocation as usize].value.rva.value = 0xFFFF_0000;
+        assert!(pe.parse_relocations().is_err());
+        assert!(matches!(pe.status.relocations, ParseStatus::Failed(_)));
+        assert!(pe.relocations.value.blocks.is_empty());
+    }
+
+    #[test]
+    fn import_export_streams() {
+        use std::{env, fs, io::Cursor};
+
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+        let parsed = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+
+        //Only fixed headers and sections; nothing from data directories.
+        let mut pe = PeImage::new(Box::new(Cursor::new(bytes)));
+        let offset = pe.parse_fixed_headers(0).unwrap();
+        pe.parse_sections(offset).unwrap();
+
+        let modules: Vec<_> = pe.imports_stream().map(|id| id.unwrap().value.name.unwrap()).collect();
+        let expected: Vec<_> = parsed.imports.value.iter().map(|id| id.value.name.clone().unwrap()).collect();
+        assert_eq!(modules, expected);
+
+        let first = pe.imports_stream().next().unwrap().unwrap();
+        assert_eq!(first.value.imports.len(), parsed.imports.value[0].value.imports.len());
+        assert_eq!(first.rva, Rva(parsed.data_dirs.value[DirectoryType::Import as usize].value.rva.value));
+        assert!(pe.imports.value.is_empty());
+
+        let exports: Vec<_> = pe.exports_stream().collect::<Result<_, _>>().unwrap();
+        assert_eq!(exports.len(), parsed.exports.value.exports.len());
+        for (streamed, export) in exports.iter().zip(&parsed.exports.value.exports) {
+            assert_eq!(streamed.name.value, export.name.value);
+            assert_eq!(streamed.address.value, export.address.value);
+        }
+        assert!(!pe.exports.value.is_valid());
+    }
+
+    #[test]
+    fn arm64x_hybrid_views() {
+        use std::{env, fs};
+
+        use crate::{pe::{file::MachineType, hybrid::HybridArch, loadcfg::chpe::{CodeRange, CodeType}}, types::HeaderField};
+
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+        assert!(pe.hybrid_views().is_empty());
+
+        //Pretend the x64 image is ARM64X: first export is ARM64 code, second is x64.
+        let addresses: Vec<u32> = pe.exports.value.exports.iter().map(|e| e.address.value).collect();
+        pe.file.value.machine.value = MachineType::ARM64;
+        pe.load_config.value.chpe_metadata_pointer.value = 1;
+        let chpe = &mut pe.chpe.value;
+        chpe.alternate_entry_point.value = 0x1234;
+        chpe.auxiliary_iat.value = 0x20000;
+        chpe.code_ranges = vec![
+            HeaderField { value: CodeRange { start: addresses[0], length: 1, code_type: CodeType::Arm64 }, ..Default::default() },
+            HeaderField { value: CodeRange { start: addresses[1], length: 1, code_type: CodeType::X64 }, ..Default::default() },
+        ];
+
+        let views = pe.hybrid_views();
+        assert_eq!(views.len(), 2);
+        let (native, ec) = (&views[0], &views[1]);
+
+        assert_eq!(native.arch, HybridArch::Arm64);
+        assert_eq!(native.entry_point, pe.optional.value.entry_point());
+        assert_eq!(native.exports.len(), 1);
+        assert_eq!(native.exports[0].address, addresses[0]);
+
+        assert_eq!(ec.arch, HybridArch::Arm64EC);
+        assert_eq!(ec.entry_point, 0x1234);
+        assert_eq!(ec.exports.len(), 1);
+        assert_eq!(ec.exports[0].code_type, Some(CodeType::X64));
+
+        //Same imports, resolved through different tables.
+        let iat = pe.directory(DirectoryType::ImportAddressTable).unwrap().rva.value;
+        assert_eq!(native.imports.len(), ec.imports.len());
+        assert!(!native.imports.is_empty());
+        for (n, e) in native.imports.iter().zip(&ec.imports) {
+            assert_eq!(n.name, e.name);
+            assert_eq!(e.iat_rva - 0x20000, n.iat_rva - iat);
+        }
+    }
+
+    #[test]
+    fn short_directory_table() {
+        use crate::pe::{builder::PeBuilder, file::MachineType, report::ReportSection, ser::{full::FullPeImage, min::MinPeImage}};
+
+        let built = PeBuilder::new(MachineType::AMD64)
+            .with_code(vec![0xC3], 0)
+            .with_import("KERNEL32.dll", &["ExitProcess"])
+            .build()
+            .unwrap();
+        let parsed = PeImage::parse_bytes(built.clone(), 0).unwrap();
+        let OptionalHeader::X64(opt) = &parsed.optional.value else { panic!("not PE32+") };
+        let count_offset = opt.number_of_rva_and_sizes.offset.0 as usize;
+
+        for count in [0u32, 1, 2, 0x20] {
+            let mut bytes = built.clone();
+            bytes[count_offset..count_offset + 4].copy_from_slice(&count.to_le_bytes());
+            let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+
+            assert_eq!(pe.data_dirs.value.len(), count.min(MAX_DIRS.into()) as usize);
+            assert_eq!(pe.has_imports(), count >= 2);
+            assert_eq!(pe.imports.value.is_empty(), count < 2);
+            assert!(!pe.has_rsrc() && !pe.has_relocations() && !pe.has_debug() && !pe.has_load_config());
+            assert!(pe.directory(DirectoryType::Reserved).is_none());
+            assert_eq!(pe.export_count().unwrap(), 0);
+
+            let mut report = String::new();
+            pe.format_report(&mut report, &ReportSection::ALL).unwrap();
+            let _ = (MinPeImage::from(&pe), FullPeImage::from(&pe), pe.to_tree());
+            pe.analyze().unwrap();
+        }
+    }
 }
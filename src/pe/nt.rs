@@ -0,0 +1,90 @@
+//! NT headers (`IMAGE_NT_HEADERS`): the `PE\0\0` signature, the file header and the optional header.
+//!
+//! The file and optional headers are kept in `PeImage::file` and `PeImage::optional`; this is
+//! where they are and the signature which precedes them.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::types::HeaderField;
+
+use super::{file, PeError, Result};
+
+/// `PE\0\0` as a little endian dword.
+pub const SIGNATURE: u32 = 0x00004550;
+pub const SIGNATURE_LENGTH: u64 = 4;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct NtHeaders {
+    pub signature: HeaderField<u32>,
+    pub file_header_offset: u64,
+    pub optional_header_offset: u64,
+    /// Length of the signature, file header and optional header, as declared by the file
+    /// header. The section table follows.
+    pub size: u64,
+}
+
+impl NtHeaders {
+    /// Parse the signature from `bytes`, read at `pos`; the declared optional header size
+    /// comes from the file header which follows.
+    pub fn parse_bytes(bytes: &[u8], pos: u64, optional_header_size: u16) -> Result<Self> {
+        let Some(signature) = bytes.get(..SIGNATURE_LENGTH as usize) else {
+            return Err(PeError::BufferTooSmall { target: "NtHeaders".into(), expected: SIGNATURE_LENGTH, actual: bytes.len() as u64 });
+        };
+
+        let signature = u32::from_le_bytes([signature[0], signature[1], signature[2], signature[3]]);
+        if signature != SIGNATURE {
+            return Err(PeError::InvalidHeader {
+                name: "NtHeaders".into(),
+                offset: pos,
+                reason: format!("signature {signature:#010x} is not PE\\0\\0"),
+            });
+        }
+
+        //The file header length includes the signature.
+        Ok(Self {
+            signature: HeaderField::at_offset(signature, pos),
+            file_header_offset: pos + SIGNATURE_LENGTH,
+            optional_header_offset: pos + file::HEADER_LENGTH,
+            size: file::HEADER_LENGTH + optional_header_size as u64,
+        })
+    }
+
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.signature.value == SIGNATURE
+    }
+}
+
+impl Display for NtHeaders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{Signature: '{}', Offset: {:#x}, Size: {:#x}}}",
+            std::str::from_utf8(&self.signature.value.to_le_bytes()).unwrap_or("ERR").trim_end_matches('\0'),
+            self.signature.offset, self.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::FileOffset;
+    use crate::pe::PeError;
+
+    use super::NtHeaders;
+
+    #[test]
+    fn parse_signature() {
+        let nt = NtHeaders::parse_bytes(b"PE\0\0\x64\x86", 0x80, 0xf0).unwrap();
+        assert!(nt.is_valid());
+        assert_eq!(nt.signature.offset, FileOffset(0x80));
+        assert_eq!(nt.file_header_offset, 0x84);
+        assert_eq!(nt.optional_header_offset, 0x98);
+        assert_eq!(nt.size, 0x108);
+        assert_eq!(nt.to_string(), "{Signature: 'PE', Offset: 0x80, Size: 0x108}");
+
+        let err = NtHeaders::parse_bytes(b"NE\0\0", 0x80, 0).unwrap_err();
+        assert!(matches!(err.root(), PeError::InvalidHeader { offset: 0x80, .. }));
+        assert!(err.to_string().contains("signature 0x0000454e is not PE\\0\\0"));
+        assert!(matches!(NtHeaders::parse_bytes(b"PE", 0, 0), Err(PeError::BufferTooSmall { .. })));
+    }
+}
@@ -18,15 +18,31 @@ use self::x64::OptionalHeader64 as OptionalHeader64;
 pub const HEADER_LENGTH_64: u64 = x64::HEADER_LENGTH;
 pub const HEADER_LENGTH_32: u64 = x86::HEADER_LENGTH;
 pub const DATA_DIRS_LENGTH: u64 = 128;
-pub const MAX_DIRS: u8 = 15;
+/// Number of data directories defined; entries beyond these are ignored, as by the loader.
+pub const MAX_DIRS: u8 = 16;
+pub const DATA_DIR_LENGTH: u64 = 8;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct DataDirectory {
     pub member: DirectoryType,
     pub rva: HeaderField<u32>,
     pub size: HeaderField<u32>,
 }
 
+impl DataDirectory {
+    /// Has both an rva and a size. Entries with only one of them are not parsed.
+    #[inline]
+    pub fn is_present(&self) -> bool {
+        self.rva.value != 0 && self.size.value != 0
+    }
+
+    /// Has exactly one of rva and size.
+    #[inline]
+    pub fn is_half_empty(&self) -> bool {
+        (self.rva.value == 0) != (self.size.value == 0)
+    }
+}
+
 impl Display for DataDirectory {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{{ {:?}, RVA: {:08x}, Size: {:08x} }}", self.member, self.rva.value, self.size.value)
@@ -36,6 +52,7 @@ impl Display for DataDirectory {
 
 #[derive(Debug, Default, PartialEq, Serialize, Clone, Copy)]
 #[repr(u8)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 pub enum DirectoryType {
     Export = 0,
     Import,
@@ -45,13 +62,14 @@ pub enum DirectoryType {
     Relocation,
     Debug,
     Architecture,
-    Reserved,
+    GlobalPtr,
     TLS,
     Configuration,
     BoundImport,
     ImportAddressTable,
     DelayImport,
     DotNetMetadata,
+    Reserved,
     #[default]
     UNKNOWN = 255,
 }
@@ -67,13 +85,14 @@ impl From<u8> for DirectoryType{
            5  => Self::Relocation,
            6  => Self::Debug,
            7  => Self::Architecture,
-           8  => Self::Reserved,
+           8  => Self::GlobalPtr,
            9  => Self::TLS,
            10 => Self::Configuration,
            11 => Self::BoundImport,
            12 => Self::ImportAddressTable,
            13 => Self::DelayImport,
            14 => Self::DotNetMetadata,
+           15 => Self::Reserved,
            _  => Self::UNKNOWN,
         }
     }
@@ -82,6 +101,7 @@ impl From<u8> for DirectoryType{
 
 #[derive(Debug, Default, PartialEq, Serialize, Clone, Copy)]
 #[repr(u16)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 pub enum ImageType {
     #[default]
     UNKNOWN = 0,
@@ -104,6 +124,7 @@ impl From<u16> for ImageType {
 
 #[derive(Debug, Default, PartialEq, Serialize, Clone, Copy)]
 #[repr(u16)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 pub enum SubSystem {
     #[default]
     UNKNOWN = 0,
@@ -132,7 +153,7 @@ impl From<u16> for SubSystem{
             9 => Self::WINDOWS_CE_GUI,
             10 => Self::EFI_APPLICATION,
             11 => Self::EFI_BOOT_SERVICE_DRIVER,
-            12 => Self::EFI_BOOT_SERVICE_DRIVER,
+            12 => Self::EFI_RUNTIME_DRIVER,
             13 => Self::EFI_ROM,
             14 => Self::XBOX,
             16 => Self:: WINDOWS_BOOT_APPLICATION,
@@ -165,7 +186,7 @@ impl Display for Flags {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum OptionalHeader {
     X86(OptionalHeader32),
     X64(OptionalHeader64),
@@ -186,6 +207,16 @@ impl Default for OptionalHeader {
     }
 }
 
+/// Field of the same type in both headers.
+macro_rules! common_field {
+    ($opt:expr, $field:ident) => {
+        match $opt {
+            OptionalHeader::X86(o) => o.$field,
+            OptionalHeader::X64(o) => o.$field,
+        }
+    };
+}
+
 impl OptionalHeader {
     pub fn get_image_type(&self) -> ImageType {
         match self {
@@ -200,23 +231,98 @@ impl OptionalHeader {
             OptionalHeader::X64(o) => o.is_valid(),
         }
     }
+
+    pub fn entry_point(&self) -> u32 {
+        common_field!(self, address_of_entry_point).value
+    }
+
+    /// Preferred base address; PE32 bases are widened.
+    pub fn image_base(&self) -> u64 {
+        match self {
+            OptionalHeader::X86(o) => o.image_base.value.into(),
+            OptionalHeader::X64(o) => o.image_base.value,
+        }
+    }
+
+    pub fn subsystem(&self) -> SubSystem {
+        common_field!(self, subsystem).value
+    }
+
+    pub fn dll_charactristics(&self) -> u16 {
+        common_field!(self, dll_charactristics).value
+    }
+
+    /// `dll_charactristics` as flags; `None` if unknown bits are set.
+    pub fn flags(&self) -> Option<Flags> {
+        Flags::from_bits(self.dll_charactristics())
+    }
+
+    pub fn section_alignment(&self) -> u32 {
+        common_field!(self, section_alignment).value
+    }
+
+    pub fn file_alignment(&self) -> u32 {
+        common_field!(self, file_alignment).value
+    }
+
+    pub fn sizeof_image(&self) -> u32 {
+        common_field!(self, sizeof_image).value
+    }
+
+    pub fn sizeof_headers(&self) -> u32 {
+        common_field!(self, sizeof_headers).value
+    }
+
+    /// Checksum with its offset, which the checksum and Authenticode hashes skip.
+    pub fn checksum(&self) -> HeaderField<u32> {
+        common_field!(self, checksum)
+    }
+
+    pub fn number_of_rva_and_sizes(&self) -> u32 {
+        common_field!(self, number_of_rva_and_sizes).value
+    }
+}
+
+/// `CheckSum` of the optional header as `CheckSumMappedFile` computes it: 16-bit one's
+/// complement sum of `bytes`, without the checksum at `checksum_at`, plus the file size.
+pub fn image_checksum(bytes: &[u8], checksum_at: usize) -> u32 {
+    let mut sum: u64 = 0;
+    for (i, word) in bytes.chunks(2).enumerate() {
+        if (checksum_at..checksum_at + 4).contains(&(i * 2)) {
+            continue;
+        }
+        let word = match word {
+            [lo, hi] => u16::from_le_bytes([*lo, *hi]),
+            [lo] => *lo as u16,
+            _ => unreachable!(),
+        };
+        sum += word as u64;
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    (sum as u32 & 0xFFFF) + bytes.len() as u32
 }
 
-pub fn parse_data_directories(bytes: &[u8], count: u8, pos: u64) -> crate::Result<Vec<HeaderField<DataDirectory>>> {
-    //let mut hdr = Some(oh);
-    let size = if count > MAX_DIRS {MAX_DIRS} else {count};
-    let mut data_dirs = Vec::with_capacity(15);
+/// Parse `count` (`NumberOfRvaAndSizes`) data directories from `bytes`, up to `MAX_DIRS`.
+/// Only entries fully in `bytes` are parsed, so the table may have fewer than `MAX_DIRS` entries.
+pub fn parse_data_directories(bytes: &[u8], count: u32, pos: u64) -> crate::pe::Result<Vec<HeaderField<DataDirectory>>> {
+    let in_bytes = (bytes.len() as u64 / DATA_DIR_LENGTH) as u32;
+    let size = count.min(MAX_DIRS.into()).min(in_bytes) as u8;
+    if size as u32 != count {
+        tracing::debug!(count, parsed = size, "data directories truncated");
+    }
+
+    let mut data_dirs = Vec::with_capacity(size.into());
     let mut cursor = Cursor::new(bytes);
     let mut offset = pos;
     
     for i in 0..size {
         let old_offset = offset;
-        let rva = HeaderField { value: cursor.read_u32::<LittleEndian>()?, offset: offset, rva: offset };
+        let rva = HeaderField::at_offset(cursor.read_u32::<LittleEndian>()?, offset);
         offset = offset + 4;
-        let size = HeaderField { value: cursor.read_u32::<LittleEndian>()?, offset: offset, rva: offset };
+        let size = HeaderField::at_offset(cursor.read_u32::<LittleEndian>()?, offset);
         offset = offset + 4;
         let data_dir = DataDirectory { member: DirectoryType::from(i), rva, size };
-        data_dirs.push(HeaderField { value:data_dir, offset: old_offset, rva: old_offset });
+        data_dirs.push(HeaderField::at_offset(data_dir, old_offset));
     }
     Ok(data_dirs)
 }
@@ -224,9 +330,10 @@ pub fn parse_data_directories(bytes: &[u8], count: u8, pos: u64) -> crate::Resul
 
 #[cfg(test)]
 mod tests {
+    use crate::types::FileOffset;
     use crate::pe::optional::DirectoryType;
 
-    use super::{parse_data_directories, MAX_DIRS};
+    use super::{parse_data_directories, Flags, OptionalHeader, SubSystem, MAX_DIRS};
 
     const RAW_BYTES: [u8; 128] = [
         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xDC, 0x26, 0x01, 0x00, 0x50, 0x00, 0x00, 0x00,
@@ -245,12 +352,12 @@ mod tests {
         let dirs = parse_data_directories(&RAW_BYTES, 0x10, start).unwrap();
         let rvas= [
             0, 0x000126DC, 0x00016000, 0, 0x0001A000, 0x0001D000, 0x00011D80, 
-            0, 0, 0, 0x00011DF0, 0, 0x0000D000, 0, 0
+            0, 0, 0, 0x00011DF0, 0, 0x0000D000, 0, 0, 0
         ];
 
         let sizes = [
             0, 0x00000050, 0x000064E8, 0, 0x00001EB8, 0x00000F98, 0x00000070,
-            0, 0, 0, 0x00000040, 0, 0x00000174, 0, 0
+            0, 0, 0, 0x00000040, 0, 0x00000174, 0, 0, 0
         ];
 
         let members = [
@@ -262,23 +369,65 @@ mod tests {
             DirectoryType::Relocation,
             DirectoryType::Debug,
             DirectoryType::Architecture,
-            DirectoryType::Reserved,
+            DirectoryType::GlobalPtr,
             DirectoryType::TLS,
             DirectoryType::Configuration,
             DirectoryType::BoundImport,
             DirectoryType::ImportAddressTable,
             DirectoryType::DelayImport,
             DirectoryType::DotNetMetadata,
+            DirectoryType::Reserved,
         ];
 
         for i in 0..MAX_DIRS as usize {
             let dir = &dirs[i];
-            assert_eq!(dir.offset, start + (8 * (i as u64)));
+            assert_eq!(dir.offset, FileOffset(start + (8 * (i as u64))));
             assert_eq!(dir.value.member, members[i]);
             assert_eq!(dir.value.rva.value, rvas[i]);
-            assert_eq!(dir.value.rva.offset, start + (8 * (i as u64)));            
+            assert_eq!(dir.value.rva.offset, FileOffset(start + (8 * (i as u64))));            
             assert_eq!(dir.value.size.value, sizes[i]);
-            assert_eq!(dir.value.size.offset, start + (8 * (i as u64)) + 4);
+            assert_eq!(dir.value.size.offset, FileOffset(start + (8 * (i as u64)) + 4));
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn count_from_header() {
+        //Fewer entries than defined.
+        let dirs = parse_data_directories(&RAW_BYTES, 3, 0x188).unwrap();
+        assert_eq!(dirs.len(), 3);
+        assert_eq!(dirs[2].value.member, DirectoryType::Resource);
+
+        //More entries than defined, or than the optional header holds.
+        assert_eq!(parse_data_directories(&RAW_BYTES, 0x20, 0x188).unwrap().len(), MAX_DIRS as usize);
+        assert_eq!(parse_data_directories(&RAW_BYTES[..44], 0x10, 0x188).unwrap().len(), 5);
+        assert!(parse_data_directories(&RAW_BYTES, 0, 0x188).unwrap().is_empty());
+    }
+
+    #[test]
+    fn common_fields() {
+        let mut x86 = OptionalHeader::X86(Default::default());
+        let mut x64 = OptionalHeader::X64(Default::default());
+        if let OptionalHeader::X86(o) = &mut x86 {
+            o.image_base.value = 0x40_0000;
+            o.address_of_entry_point.value = 0x1234;
+            o.subsystem.value = SubSystem::WINDOWS_GUI;
+            o.dll_charactristics.value = 0x140;
+        }
+        if let OptionalHeader::X64(o) = &mut x64 {
+            o.image_base.value = 0x1_4000_0000;
+            o.address_of_entry_point.value = 0x1234;
+            o.subsystem.value = SubSystem::WINDOWS_GUI;
+            o.dll_charactristics.value = 0x140;
+            o.checksum.offset = FileOffset(0x158);
+        }
+
+        assert_eq!(x86.image_base(), 0x40_0000);
+        assert_eq!(x64.image_base(), 0x1_4000_0000);
+        for opt in [&x86, &x64] {
+            assert_eq!(opt.entry_point(), 0x1234);
+            assert_eq!(opt.subsystem(), SubSystem::WINDOWS_GUI);
+            assert_eq!(opt.flags(), Some(Flags::DYNAMIC_BASE | Flags::NX_COMPAT));
+        }
+        assert_eq!(x64.checksum().offset, FileOffset(0x158));
+    }
+}
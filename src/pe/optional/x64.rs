@@ -8,7 +8,7 @@ use super::{Flags, ImageType, SubSystem};
 
 pub const HEADER_LENGTH: u64 = 112;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct OptionalHeader64 {
     pub magic: HeaderField<ImageType>,
     pub major_linker_ver: HeaderField<u8>,
@@ -39,6 +39,8 @@ pub struct OptionalHeader64 {
     pub sizeof_heap_commit: HeaderField<u64>,
     pub loader_flags: HeaderField<u32>,
     pub number_of_rva_and_sizes: HeaderField<u32>,
+    /// Subsystem as stored; `subsystem` is `UNKNOWN` for values it doesn't list.
+    pub subsystem_raw: u16,
 }
 
 impl OptionalHeader64 {
@@ -48,7 +50,7 @@ impl OptionalHeader64 {
 }
 
 impl Header for OptionalHeader64 {
-    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> {
         let bytes_len = bytes.len() as u64;
 
         if bytes_len < HEADER_LENGTH {
@@ -97,7 +99,8 @@ impl Header for OptionalHeader64 {
         hdr.sizeof_headers =
             new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
         hdr.checksum = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
-        hdr.subsystem = new_header_field!(SubSystem::from(cursor.read_u16::<LittleEndian>()?), offset);
+        hdr.subsystem_raw = cursor.read_u16::<LittleEndian>()?;
+        hdr.subsystem = new_header_field!(SubSystem::from(hdr.subsystem_raw), offset);
         //offset += 1; //sizeof(SubSystem) is 1!!??
         hdr.dll_charactristics =
             new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
@@ -136,7 +139,7 @@ impl Display for OptionalHeader64 {
 mod tests {
     use crate::{
         pe::optional::{Flags, ImageType, SubSystem},
-        types::Header,
+        types::{FileOffset, Header, Rva},
     };
 
     use super::OptionalHeader64;
@@ -156,10 +159,10 @@ mod tests {
         let opt = OptionalHeader64::parse_bytes(RAW_BYTES.to_vec(), 0x110).unwrap();
         assert!(opt.is_valid());
         assert_eq!(opt.magic.value, ImageType::PE64);
-        assert_eq!(opt.magic.offset, 0x110);
+        assert_eq!(opt.magic.offset, FileOffset(0x110));
         assert_eq!(opt.major_linker_ver.value, 0x0c);
         assert_eq!(opt.minor_linker_ver.value, 0x00);
-        assert_eq!(opt.minor_linker_ver.offset, 0x113);
+        assert_eq!(opt.minor_linker_ver.offset, FileOffset(0x113));
         assert_eq!(opt.sizeof_code.value, 0xae00);
         assert_eq!(opt.sizeof_initiailized_data.value, 0xb600);
         assert_eq!(opt.sizeof_uninitiailized_data.value, 0);
@@ -189,7 +192,7 @@ mod tests {
         assert_eq!(opt.sizeof_heap_commit.value, 0x0000000000001000);
         assert_eq!(opt.loader_flags.value, 0);
         assert_eq!(opt.number_of_rva_and_sizes.value, 0x10);
-        assert_eq!(opt.number_of_rva_and_sizes.offset, 0x17c);
-        assert_eq!(opt.number_of_rva_and_sizes.rva, 0x17c);
+        assert_eq!(opt.number_of_rva_and_sizes.offset, FileOffset(0x17c));
+        assert_eq!(opt.number_of_rva_and_sizes.rva, Rva(0x17c));
     }
 }
@@ -8,7 +8,7 @@ use super::{Flags, ImageType, SubSystem};
 
 pub const HEADER_LENGTH: u64 = 96;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct OptionalHeader32 {
     pub magic: HeaderField<ImageType>,
     pub major_linker_ver: HeaderField<u8>,
@@ -40,6 +40,8 @@ pub struct OptionalHeader32 {
     pub sizeof_heap_commit: HeaderField<u32>,
     pub loader_flags: HeaderField<u32>,
     pub number_of_rva_and_sizes: HeaderField<u32>,
+    /// Subsystem as stored; `subsystem` is `UNKNOWN` for values it doesn't list.
+    pub subsystem_raw: u16,
 }
 
 impl OptionalHeader32 {
@@ -49,7 +51,7 @@ impl OptionalHeader32 {
 }
 
 impl Header for OptionalHeader32 {
-    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> {
         let bytes_len = bytes.len() as u64;
 
         if bytes_len < HEADER_LENGTH {
@@ -86,7 +88,8 @@ impl Header for OptionalHeader32 {
         hdr.sizeof_image = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
         hdr.sizeof_headers = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
         hdr.checksum = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
-        hdr.subsystem = new_header_field!(SubSystem::from(cursor.read_u16::<LittleEndian>()?), offset);
+        hdr.subsystem_raw = cursor.read_u16::<LittleEndian>()?;
+        hdr.subsystem = new_header_field!(SubSystem::from(hdr.subsystem_raw), offset);
         //offset += 1; //sizeof(SubSystem) is 1!!
         hdr.dll_charactristics = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
         hdr.sizeof_stack_reserve = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
@@ -117,7 +120,7 @@ impl Display for OptionalHeader32 {
 
 #[cfg(test)]
 mod test {
-    use crate::types::Header;
+    use crate::types::{FileOffset, Header, Rva};
 
     use super::super::{Flags, ImageType, SubSystem};
     use super::OptionalHeader32;
@@ -137,7 +140,7 @@ mod test {
         let opt = OptionalHeader32::parse_bytes(RAW_BYTES.to_vec(), 0x128).unwrap();
         assert!(opt.is_valid());
         assert_eq!(opt.magic.value, ImageType::PE32);
-        assert_eq!(opt.magic.offset, 0x128);
+        assert_eq!(opt.magic.offset, FileOffset(0x128));
         assert_eq!(opt.major_linker_ver.value, 0x0e);
         assert_eq!(opt.minor_linker_ver.value, 0x00);
         assert_eq!(opt.sizeof_code.value, 0xbc00);
@@ -170,7 +173,7 @@ mod test {
         assert_eq!(opt.sizeof_heap_commit.value, 0x00001000);
         assert_eq!(opt.loader_flags.value, 0);
         assert_eq!(opt.number_of_rva_and_sizes.value, 0x10);
-        assert_eq!(opt.number_of_rva_and_sizes.offset, 0x184);
-        assert_eq!(opt.number_of_rva_and_sizes.rva, 0x184);
+        assert_eq!(opt.number_of_rva_and_sizes.offset, FileOffset(0x184));
+        assert_eq!(opt.number_of_rva_and_sizes.rva, Rva(0x184));
     }
 }
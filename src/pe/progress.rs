@@ -0,0 +1,191 @@
+//! Progress of long operations on an image, for GUI or TUI wrappers.
+//!
+//! An observer set with `PeImage::set_progress` is told when each stage of an operation starts
+//! and how much of the operation is done. Parsing reports a stage per directory, relocations
+//! per block and resources per entry of the root directory; hashing reports as chunks of the
+//! file are read. Closures taking the percent are observers too.
+
+use super::PeImage;
+
+/// Observer of the progress of parsing and hashing an image.
+pub trait ParseProgress {
+    /// `stage` of the running operation starts, e.g. `resources` while parsing.
+    fn stage(&mut self, _stage: &str) {}
+
+    /// `percent` of the running operation is done, from 0 to 100. Not repeated for the same
+    /// value; an operation ends with 100.
+    fn percent(&mut self, percent: u8);
+}
+
+impl<F: FnMut(u8)> ParseProgress for F {
+    fn percent(&mut self, percent: u8) {
+        self(percent)
+    }
+}
+
+/// Optional observer with the last percent reported to it.
+#[derive(Default)]
+pub(crate) struct Progress {
+    observer: Option<Box<dyn ParseProgress>>,
+    last: Option<u8>,
+    /// Index of the running step and number of steps, if the operation has steps; `update`
+    /// then reports progress within the step.
+    step: Option<(u64, u64)>,
+}
+
+impl Progress {
+    pub(crate) fn set(&mut self, observer: Option<Box<dyn ParseProgress>>) -> Option<Box<dyn ParseProgress>> {
+        self.reset();
+        std::mem::replace(&mut self.observer, observer)
+    }
+
+    pub(crate) fn stage(&mut self, stage: &str) {
+        if let Some(observer) = &mut self.observer {
+            observer.stage(stage);
+        }
+    }
+
+    /// Start step `index` of `steps`; see `update`.
+    pub(crate) fn step(&mut self, index: u64, steps: u64) {
+        self.step = Some((index, steps));
+        self.update(0, 1);
+    }
+
+    /// Report `done` of `total` units, of the running step if there is one; `total` of 0 is done.
+    pub(crate) fn update(&mut self, done: u64, total: u64) {
+        let Some(observer) = &mut self.observer else {
+            return;
+        };
+        let (done, total) = match (total, self.step) {
+            (0, None) => (1, 1),
+            (0, Some((index, steps))) => ((index + 1) as u128, steps as u128),
+            (total, None) => (done.min(total) as u128, total as u128),
+            (total, Some((index, steps))) => (index as u128 * total as u128 + done.min(total) as u128, steps as u128 * total as u128),
+        };
+        let percent = match total {
+            0 => 100,
+            total => (done * 100 / total) as u8,
+        };
+        if self.last != Some(percent) {
+            self.last = Some(percent);
+            observer.percent(percent);
+        }
+    }
+
+    /// Start of a new operation.
+    pub(crate) fn reset(&mut self) {
+        self.last = None;
+        self.step = None;
+    }
+}
+
+impl PeImage {
+    /// Report progress of parsing and hashing to `observer` from now on, e.g. set after `new`
+    /// and before parsing. Returns the observer set before.
+    pub fn set_progress(&mut self, observer: Box<dyn ParseProgress>) -> Option<Box<dyn ParseProgress>> {
+        self.progress.set(Some(observer))
+    }
+
+    /// Stop reporting progress; returns the observer set with `set_progress`.
+    pub fn take_progress(&mut self) -> Option<Box<dyn ParseProgress>> {
+        self.progress.set(None)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::pe::{builder::PeBuilder, file::MachineType, PeImage};
+
+    use super::{ParseProgress, Progress};
+
+    #[derive(Default)]
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl ParseProgress for Recorder {
+        fn stage(&mut self, stage: &str) {
+            self.0.borrow_mut().push(stage.into());
+        }
+
+        fn percent(&mut self, percent: u8) {
+            self.0.borrow_mut().push(format!("{percent}%"));
+        }
+    }
+
+    #[test]
+    fn percent_is_not_repeated() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut progress = Progress::default();
+        progress.update(1, 2);
+        assert!(progress.set(Some(Box::new(Recorder(events.clone())))).is_none());
+
+        progress.stage("hashes");
+        for done in [0, 1, 1, 2, 3, 4, 5] {
+            progress.update(done, 4);
+        }
+        progress.reset();
+        progress.update(0, 0);
+        assert_eq!(*events.borrow(), vec!["hashes", "0%", "25%", "50%", "75%", "100%", "100%"]);
+    }
+
+    #[test]
+    fn percent_within_steps() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut progress = Progress::default();
+        progress.set(Some(Box::new(Recorder(events.clone()))));
+
+        progress.step(1, 4);
+        progress.update(1, 2);
+        progress.update(2, 2);
+        progress.step(2, 4);
+        progress.update(0, 0);
+        progress.step(3, 4);
+        progress.update(1, 1);
+        assert_eq!(*events.borrow(), vec!["25%", "37%", "50%", "75%", "100%"]);
+    }
+
+    #[test]
+    fn relocations_and_resources_report_entries() {
+        let path = std::env::current_dir().unwrap().join("test-data").join("test.dll");
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut pe = PeImage::new(Box::new(std::io::Cursor::new(std::fs::read(path).unwrap())));
+        pe.set_progress(Box::new(Recorder(events.clone())));
+        pe.parse_all_headers(0).unwrap();
+
+        //Relocations report per block and resources per root entry; equal percents are reported once.
+        assert_eq!(*events.borrow(), vec![
+            "imports", "0%", "delay imports", "11%", "bound imports", "22%", "exports", "33%",
+            "relocations", "44%", "45%", "49%", "53%", "55%", "resources", "61%", "66%",
+            "load config", "debug directory", "77%", "CHPE metadata", "88%", "100%",
+        ]);
+    }
+
+    #[test]
+    fn parse_and_hash_stages() {
+        let bytes = PeBuilder::new(MachineType::AMD64).with_import("KERNEL32.dll", &["ExitProcess"]).build().unwrap();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut pe = PeImage::new(Box::new(std::io::Cursor::new(bytes)));
+        pe.set_progress(Box::new(Recorder(events.clone())));
+        pe.parse_all_headers(0).unwrap();
+
+        let stages: Vec<_> = events.borrow().iter().filter(|e| !e.ends_with('%')).cloned().collect();
+        assert_eq!(stages.first().map(String::as_str), Some("imports"));
+        assert_eq!(stages.last().map(String::as_str), Some("CHPE metadata"));
+        assert_eq!(stages.len(), 9);
+        assert_eq!(events.borrow().last().map(String::as_str), Some("100%"));
+
+        events.borrow_mut().clear();
+        pe.file_hashes(false).unwrap();
+        assert_eq!(*events.borrow(), vec!["hashes", "0%", "100%"]);
+
+        let percents = Rc::new(RefCell::new(Vec::new()));
+        let seen = percents.clone();
+        assert!(pe.set_progress(Box::new(move |p| seen.borrow_mut().push(p))).is_some());
+        pe.file_hashes(false).unwrap();
+        assert_eq!(*percents.borrow(), vec![0, 100]);
+        assert!(pe.take_progress().is_some());
+        assert!(pe.take_progress().is_none());
+    }
+}
@@ -1,13 +1,15 @@
-use std::{io::{Error, Cursor, Read}, fmt::Display};
-use byteorder::{ReadBytesExt, LittleEndian};
+use std::{io::Cursor, fmt::Display};
+use byteorder::{ByteOrder, ReadBytesExt, LittleEndian};
 use serde::Serialize;
 
-use crate::{new_header_field, types::{Header, HeaderField}};
+use crate::{new_header_field, types::{BufReadExt, Header, HeaderField, Rva}, utils::FragmentReader};
+
+use super::{check_alloc, file::MachineType, progress::Progress, section::SectionLookup, PeError};
 
 pub const HEADER_LENGTH: u64 = 8;
 
 #[repr(u8)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub enum I86Type {
     ABSOLUTE = 0x00,
     DIR16 = 0x01,
@@ -44,7 +46,7 @@ impl From<u8> for I86Type {
 }
 
 #[repr(u8)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub enum X64Type {
     ABSOLUTE = 0x00,
     ADDR64 = 0x01,
@@ -95,6 +97,7 @@ impl From<u8> for X64Type {
 #[allow(non_camel_case_types)]
 #[repr(u8)]
 #[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 pub enum RelocType {
     // The base relocation is skipped.
     ABSOLUTE = 0x00,
@@ -181,34 +184,90 @@ impl From<u8> for RelocType {
     }
 }
 
+impl RelocType {
+    /// Type of a base relocation in an image of `machine`.
+    /// Types 5 and 7 only have a meaning for some machines, `from` leaves them `UNKNOWN`.
+    pub fn from_machine(value: u8, machine: MachineType) -> Self {
+        match value {
+            0x05 if machine.is_arm32() => Self::ARM_MOV_32,
+            0x07 if matches!(machine, MachineType::THUMB | MachineType::ARMNT) => Self::THUMB_MOV_32,
+            _ => Self::from(value),
+        }
+    }
+
+    /// Type as stored in the upper 4 bits of a relocation.
+    pub fn value(&self) -> u8 {
+        match self {
+            Self::ABSOLUTE => 0x00,
+            Self::HIGH => 0x01,
+            Self::LOW => 0x02,
+            Self::HIGHLOW => 0x03,
+            Self::HIGHADJ => 0x04,
+            Self::ARM_MOV_32 => 0x05,
+            Self::RESERVED => 0x06,
+            Self::THUMB_MOV_32 => 0x07,
+            Self::RISCV_LOW12 => 0x08,
+            Self::MIPS_JMP_ADDR16 => 0x09,
+            Self::DIR64 => 0x0A,
+            Self::UNKNOWN(value) => *value,
+        }
+    }
+
+    /// Number of bytes patched by a relocation of this type; 0 if it patches nothing or is unknown.
+    pub fn patch_size(&self) -> u32 {
+        match self {
+            Self::HIGH | Self::LOW | Self::HIGHADJ => 2,
+            Self::HIGHLOW | Self::RISCV_LOW12 | Self::MIPS_JMP_ADDR16 => 4,
+            //A MOVW/MOVT pair.
+            Self::ARM_MOV_32 | Self::THUMB_MOV_32 => 8,
+            Self::DIR64 => 8,
+            Self::ABSOLUTE | Self::RESERVED | Self::UNKNOWN(_) => 0,
+        }
+    }
+}
+
 impl Display for RelocType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, Serialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
 #[serde(rename="relocation")]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 pub struct Reloc {
     //pub(crate) raw : u16,
     #[serde(rename="type")]
     pub rtype : RelocType,
+    /// Offset in the page of the block.
     #[serde(rename="offset")]
     pub rva : u16,
+    /// RVA patched by the relocation: `virtual_address` of the block plus `rva`.
+    #[serde(rename="rva")]
+    pub target_rva : u32,
 }
 
 impl Reloc {
     pub fn new (value: u16) -> Self {
         let rtype = ((value & 0xF000) >> 12) as u8;
-        let offset = (value & 0x0FFF) as u16;
+        let offset = value & 0x0FFF;
         Self {
             //raw: value,
             rtype: RelocType::from(rtype),
-            rva: offset.into()
+            rva: offset,
+            target_rva: offset.into(),
         }
     }
 
-    pub fn fix_rvas(&mut self, _va: u32) { }
+    /// Set `target_rva` for a block at `va`.
+    pub fn fix_rvas(&mut self, va: u32) {
+        self.target_rva = va.wrapping_add(self.rva.into());
+    }
+
+    /// The relocation as stored: type in the upper 4 bits, offset in the lower 12.
+    pub fn raw(&self) -> u16 {
+        ((self.rtype.value() as u16) << 12) | (self.rva & 0x0FFF)
+    }
 }
 
 impl Display for Reloc {
@@ -218,7 +277,7 @@ impl Display for Reloc {
 }
 
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
 pub struct RelocBlock {
     #[serde(rename="virtual_address")]
     pub va : HeaderField<u32>,
@@ -234,7 +293,8 @@ impl Display for RelocBlock {
 }
 
 impl RelocBlock {
-    pub fn fix_rvas(&mut self, rva: u64) {
+    /// Set rvas of the header and entries for a block at `rva` in the directory.
+    pub fn fix_rvas(&mut self, rva: Rva) {
         self.va.rva = rva;
         self.size.rva = rva + 4;
 
@@ -246,15 +306,12 @@ impl RelocBlock {
         }
     }
 
-    pub fn parse_relocs(&mut self, bytes: &[u8], pos: u64) -> crate::Result<()> {
+    pub fn parse_relocs(&mut self, bytes: &[u8], pos: u64) -> crate::pe::Result<()> {
         let bytes_len = bytes.len() as u64;
-        let rb_size = self.size.value as u64 - HEADER_LENGTH;
+        let rb_size = (self.size.value as u64).saturating_sub(HEADER_LENGTH);
         if bytes_len < rb_size {
             return Err(
-                Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("Not enough data. Expected {}, Found {}", rb_size, bytes_len)
-                ).into()
+                PeError::BufferTooSmall { target: "RelocBlock".into(), expected: rb_size, actual: bytes_len }
             );
         }
 
@@ -269,7 +326,7 @@ impl RelocBlock {
             let mut reloc = Reloc::new(val);
             reloc.fix_rvas(self.va.value);
 
-            self.relocs.push(HeaderField { value: reloc, offset: reloc_pos, rva: reloc_pos });
+            self.relocs.push(HeaderField::at_offset(reloc, reloc_pos));
             reloc_pos += 2;
         }
         
@@ -278,15 +335,12 @@ impl RelocBlock {
 }
 
 impl Header for RelocBlock {
-    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> {
         let bytes_len = bytes.len() as u64;
 
         if bytes_len < HEADER_LENGTH {
             return Err(
-                Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("Not enough data. Expected {}, Found {}", HEADER_LENGTH, bytes_len)
-                ).into()
+                PeError::BufferTooSmall { target: "RelocBlock".into(), expected: HEADER_LENGTH, actual: bytes_len }
             );
         }
 
@@ -302,7 +356,7 @@ impl Header for RelocBlock {
     }
 
     fn is_valid(&self) -> bool {
-        let items = ((self.size.value - 8) / 2) as usize;
+        let items = (self.size.value.saturating_sub(8) / 2) as usize;
         self.relocs.len() == items
     }
 
@@ -312,59 +366,204 @@ impl Header for RelocBlock {
 }
 
 
-#[derive(Debug, Default, Serialize)]
+/// Bytes changed by applying a relocation.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PatchedRange {
+    pub rva: u32,
+    /// Offset in the patched buffer.
+    pub offset: u64,
+    pub size: u32,
+    #[serde(rename="type")]
+    pub rtype: RelocType,
+}
+
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
 pub struct Relocations {
     pub blocks: Vec<HeaderField<RelocBlock>>
 }
 
 impl Relocations {
-    pub fn fix_rvas(&mut self, rva: u64) -> crate::Result<()> {
-        let mut rb_rva = rva;
-        
-        for i in 0..self.blocks.len(){
-            let rb = &mut self.blocks[i];
+    /// Set rvas of blocks for a directory at `rva`. Each block is placed by its offset from the
+    /// first one, so blocks keep their own rvas however they were sized.
+    pub fn fix_rvas(&mut self, rva: Rva) -> crate::pe::Result<()> {
+        let Some(start) = self.blocks.first().map(|rb| rb.offset) else {
+            return Ok(());
+        };
+
+        for rb in self.blocks.iter_mut() {
+            let rb_rva = rva + rb.offset.0.saturating_sub(start.0) as u32;
             rb.rva = rb_rva;
-            rb.value.fix_rvas(rva);
-            rb_rva += rb.value.size.value as u64;
+            rb.value.fix_rvas(rb_rva);
         }
 
         Ok(())
     }
 }
 
+impl Relocations {
+    /// Relocations patching any byte in `rva_start..rva_end`, as (absolute RVA, type).
+    /// `ABSOLUTE` entries only pad blocks and are skipped. Types of unknown size match if they start in the range.
+    pub fn relocs_in_range(&self, rva_start: u32, rva_end: u32) -> impl Iterator<Item = (u32, RelocType)> + '_ {
+        let (start, end) = (rva_start as u64, rva_end as u64);
+        self.blocks.iter()
+            //A block covers one 4K page, fixups at its end may spill into the next one.
+            .filter(move |rb| (rb.value.va.value as u64) < end && rb.value.va.value as u64 + 0x1000 + 8 > start)
+            .flat_map(|rb| {
+                let va = rb.value.va.value;
+                rb.value.relocs.iter().map(move |reloc| (va.wrapping_add(reloc.value.rva as u32), reloc.value.rtype))
+            })
+            .filter(move |(rva, rtype)| {
+                let rva = *rva as u64;
+                *rtype != RelocType::ABSOLUTE && rva < end && rva + rtype.patch_size().max(1) as u64 > start
+            })
+    }
+
+    /// Add `delta` to the target of every relocation in `image`, a buffer with the file layout of the image.
+    /// `HIGH`, `LOW`, `HIGHLOW` and `DIR64` are applied, `ABSOLUTE` is skipped, and other types fail
+    /// the same way they would fail a loader.
+    pub fn apply(&self, image: &mut [u8], sections: &impl SectionLookup, delta: u64) -> crate::pe::Result<Vec<PatchedRange>> {
+        let mut patched = Vec::new();
+
+        for rb in &self.blocks {
+            let va = rb.value.va.value;
+            for reloc in &rb.value.relocs {
+                let rtype = reloc.value.rtype;
+                if rtype == RelocType::ABSOLUTE {
+                    continue;
+                }
+
+                let rva = va.wrapping_add(reloc.value.rva as u32);
+                let size = rtype.patch_size();
+                if !matches!(rtype, RelocType::HIGH | RelocType::LOW | RelocType::HIGHLOW | RelocType::DIR64) {
+                    return Err(PeError::InvalidHeader {
+                        name: "Relocation".into(),
+                        offset: reloc.offset.into(),
+                        reason: format!("can't apply relocation of type {rtype} at rva {rva:#x}"),
+                    });
+                }
+
+                let offset = sections.rva_to_offset(rva).ok_or(PeError::NoSectionForRVA(rva.into()))? as usize;
+                let image_len = image.len() as u64;
+                let target = image.get_mut(offset..offset + size as usize).ok_or(PeError::BeyondRange {
+                    name: "image".into(),
+                    typ: "relocation target".into(),
+                    value: offset as u64,
+                    start: 0,
+                    end: image_len,
+                })?;
+
+                match rtype {
+                    RelocType::HIGH => {
+                        let value = LittleEndian::read_u16(target).wrapping_add((delta >> 16) as u16);
+                        LittleEndian::write_u16(target, value);
+                    },
+                    RelocType::LOW => {
+                        let value = LittleEndian::read_u16(target).wrapping_add(delta as u16);
+                        LittleEndian::write_u16(target, value);
+                    },
+                    RelocType::HIGHLOW => {
+                        let value = LittleEndian::read_u32(target).wrapping_add(delta as u32);
+                        LittleEndian::write_u32(target, value);
+                    },
+                    _ => {
+                        let value = LittleEndian::read_u64(target).wrapping_add(delta);
+                        LittleEndian::write_u64(target, value);
+                    },
+                }
+
+                patched.push(PatchedRange { rva, offset: offset as u64, size, rtype });
+            }
+        }
+
+        Ok(patched)
+    }
+
+    /// Resolve machine specific relocation types of all blocks for `machine`.
+    pub fn set_machine(&mut self, machine: MachineType) {
+        for rb in self.blocks.iter_mut() {
+            for reloc in rb.value.relocs.iter_mut() {
+                if let RelocType::UNKNOWN(value) = reloc.value.rtype {
+                    reloc.value.rtype = RelocType::from_machine(value, machine);
+                }
+            }
+        }
+    }
+
+    /// Parse relocation directory of `size` bytes at `offset`, one block at a time.
+    /// Each block is read and checked on its own, so a bogus size field in one block
+    /// can't make the whole directory (or more) be allocated up front.
+    pub fn parse_stream(reader: &mut impl BufReadExt, offset: u64, size: u64, max_alloc: u64) -> crate::pe::Result<Self> {
+        Self::parse_stream_with_progress(reader, offset, size, max_alloc, &mut Progress::default())
+    }
+
+    /// Same as `parse_stream`, reporting the bytes parsed after each block to `progress`.
+    pub(crate) fn parse_stream_with_progress(reader: &mut impl BufReadExt, offset: u64, size: u64, max_alloc: u64, progress: &mut Progress) -> crate::pe::Result<Self> {
+        let mut relocs = Relocations::default();
+        let mut consumed = 0u64;
+
+        while consumed + HEADER_LENGTH <= size {
+            let block_offset = offset + consumed;
+            let buf = reader.read_bytes_at_offset(block_offset, HEADER_LENGTH as usize)?;
+            let mut rb = RelocBlock::parse_bytes(buf, block_offset)?;
+            let rb_size = rb.size.value as u64;
+
+            //Some linkers pad the directory with an empty block.
+            if rb.va.value == 0 && rb_size == 0 {
+                break;
+            }
+
+            if rb_size < HEADER_LENGTH || rb_size > size - consumed {
+                return Err(PeError::InvalidHeader {
+                    name: "RelocBlock".into(),
+                    offset: block_offset,
+                    reason: format!("block size {rb_size:#x} is out of range [{HEADER_LENGTH:#x}..{:#x}]", size - consumed),
+                });
+            }
+            check_alloc("RelocBlock", rb_size - HEADER_LENGTH, max_alloc)?;
+
+            let rbytes = reader.read_bytes_at_offset(block_offset + HEADER_LENGTH, (rb_size - HEADER_LENGTH) as usize)?;
+            rb.parse_relocs(&rbytes, block_offset + HEADER_LENGTH)?;
+            relocs.blocks.push(HeaderField::at_offset(rb, block_offset));
+
+            consumed += rb_size;
+            progress.update(consumed, size);
+        }
+
+        Ok(relocs)
+    }
+}
+
 impl Header for Relocations {
-    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> {
         let bytes_len = bytes.len() as u64;
 
         if bytes_len < HEADER_LENGTH {
             return Err(
-                Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("Not enough data. Expected {HEADER_LENGTH}, Found {bytes_len}")
-                ).into()
+                PeError::BufferTooSmall { target: "Relocations".into(), expected: HEADER_LENGTH, actual: bytes_len }
             );
         }
 
-        let mut cursor = Cursor::new(bytes);
-        let mut offset = pos;
+        let mut reader = FragmentReader::new(bytes, pos);
+        Self::parse_stream(&mut reader, pos, bytes_len, bytes_len)
+    }
 
+    /// Parse blocks from `offset` until an empty block or end of data.
+    fn parse_buf(reader: &mut impl BufReadExt, pos: u64, offset: u64) -> crate::pe::Result<Self> {
         let mut relocs = Relocations::default();
-        let mut consumed = 0u64;
-
-        while consumed < bytes_len {            
-            let mut rb = RelocBlock::default();
-            rb.va = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
-            rb.size = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
-    
-            let r_size = (rb.size.value as u64  - HEADER_LENGTH) as usize;
-            let mut rbytes = vec![0 as u8; r_size];
-            cursor.read_exact(&mut rbytes)?;
-
-            consumed += rb.size.value as u64;
-
-            rb.parse_relocs(&rbytes, offset + HEADER_LENGTH)?;
-            relocs.blocks.push(HeaderField { value: rb, offset: offset, rva: offset });
-            offset += r_size as u64;
+        let mut delta = 0u64;
+
+        while let Ok(buf) = reader.read_bytes_at_offset(offset + delta, HEADER_LENGTH as usize) {
+            let mut rb = RelocBlock::parse_bytes(buf, pos + delta)?;
+            let rb_size = rb.size.value as u64;
+            if rb_size < HEADER_LENGTH {
+                break;
+            }
+
+            let rbytes = reader.read_bytes_at_offset(offset + delta + HEADER_LENGTH, (rb_size - HEADER_LENGTH) as usize)?;
+            rb.parse_relocs(&rbytes, pos + delta + HEADER_LENGTH)?;
+            relocs.blocks.push(HeaderField::at_offset(rb, pos + delta));
+            delta += rb_size;
         }
 
         Ok(relocs)
@@ -381,7 +580,7 @@ impl Header for Relocations {
 
 #[cfg(test)]
 mod tests {
-    use crate::{types::Header, pe::relocs::RelocType};
+    use crate::{types::{FileOffset, Header, Rva}, pe::{file::MachineType, relocs::RelocType, PeError}, utils::FragmentReader};
 
     use super::{RelocBlock, Relocations};
 
@@ -407,11 +606,11 @@ mod tests {
 
         assert_eq!(rb.relocs.len(), 2);
         
-        assert_eq!(rb.relocs[0].offset, 0x4808);
+        assert_eq!(rb.relocs[0].offset, FileOffset(0x4808));
         assert_eq!(rb.relocs[0].value.rtype, RelocType::DIR64);
         assert_eq!(rb.relocs[0].value.rva, 0x00b8);
 
-        assert_eq!(rb.relocs[1].offset, 0x480A);
+        assert_eq!(rb.relocs[1].offset, FileOffset(0x480A));
         assert_eq!(rb.relocs[1].value.rtype, RelocType::DIR64);
         assert_eq!(rb.relocs[1].value.rva, 0x00c0);
     }
@@ -423,25 +622,26 @@ mod tests {
         
         let mut rb = RelocBlock::parse_bytes(rb_bytes.to_vec(), 0x4800).unwrap();
         rb.parse_relocs(&rbytes, 0x4808).unwrap();
-        rb.fix_rvas(0x0000d000);
+        rb.fix_rvas(Rva(0x0000d000));
 
         assert_eq!(rb.va.value, 0x00003000);
-        assert_eq!(rb.va.rva, 0x0000d000);
-        assert_eq!(rb.va.offset, 0x4800);
+        assert_eq!(rb.va.rva, Rva(0x0000d000));
+        assert_eq!(rb.va.offset, FileOffset(0x4800));
         
         assert_eq!(rb.size.value, 0x0C);
-        assert_eq!(rb.size.rva, 0x0000d004);
-        assert_eq!(rb.size.offset, 0x4804);
+        assert_eq!(rb.size.rva, Rva(0x0000d004));
+        assert_eq!(rb.size.offset, FileOffset(0x4804));
         
         assert_eq!(rb.relocs.len(), 2);
 
-        assert_eq!(rb.relocs[0].rva, 0x0000d008);
-        assert_eq!(rb.relocs[0].offset, 0x4808);
+        assert_eq!(rb.relocs[0].rva, Rva(0x0000d008));
+        assert_eq!(rb.relocs[0].offset, FileOffset(0x4808));
         assert_eq!(rb.relocs[0].value.rtype, RelocType::DIR64);
         assert_eq!(rb.relocs[0].value.rva, 0x00b8);
+        assert_eq!(rb.relocs[0].value.target_rva, 0x30b8);
 
-        assert_eq!(rb.relocs[1].rva, 0x0000d00a);
-        assert_eq!(rb.relocs[1].offset, 0x480a);
+        assert_eq!(rb.relocs[1].rva, Rva(0x0000d00a));
+        assert_eq!(rb.relocs[1].offset, FileOffset(0x480a));
         assert_eq!(rb.relocs[1].value.rtype, RelocType::DIR64);
         assert_eq!(rb.relocs[1].value.rva, 0x00c0);
     }
@@ -459,7 +659,7 @@ mod tests {
         ];
         
         let mut relocs = Relocations::parse_bytes(bytes.to_vec(), 0x4800).unwrap();
-        relocs.fix_rvas(0x0000d000).unwrap();
+        relocs.fix_rvas(Rva(0x0000d000)).unwrap();
 
         assert_eq!(relocs.blocks.len(), 4);
         assert_eq!(relocs.blocks[0].value.relocs.len(), 2);
@@ -467,8 +667,15 @@ mod tests {
         assert_eq!(relocs.blocks[2].value.relocs.len(), 22);
         assert_eq!(relocs.blocks[3].value.relocs.len(), 4);
 
+        let block_rvas: Vec<u64> = relocs.blocks.iter().map(|rb| rb.rva.into()).collect();
+        assert_eq!(block_rvas, [0xd000, 0xd00c, 0xd020, 0xd054]);
+
         let rb4 = &relocs.blocks[3].value;
         assert_eq!(rb4.va.value, 0x0000b000);
+        assert_eq!(rb4.va.rva, Rva(0xd054));
+        assert_eq!(rb4.size.rva, Rva(0xd058));
+        assert_eq!(rb4.relocs[1].rva, Rva(0xd05e));
+        assert_eq!(rb4.relocs[1].value.target_rva, 0xb030);
 
         assert_eq!(rb4.relocs[0].value.rtype, RelocType::DIR64);
         assert_eq!(rb4.relocs[0].value.rva, 0x00000018);
@@ -482,4 +689,70 @@ mod tests {
         assert_eq!(rb4.relocs[3].value.rtype, RelocType::ABSOLUTE);
         assert_eq!(rb4.relocs[3].value.rva, 0x00000000);
     }
+
+    #[test]
+    fn relocs_in_range() {
+        //DIR64 @ 0x3ffc, 0x4000, 0x4010 and padding.
+        let bytes = [
+            0x00u8, 0x30, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00, 0xFC, 0xAF, 0x00, 0x00,
+            0x00, 0x40, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00, 0x00, 0xA0, 0x10, 0xA0,
+        ];
+        let relocs = Relocations::parse_bytes(bytes.to_vec(), 0).unwrap();
+
+        let found = |start, end| relocs.relocs_in_range(start, end).map(|(rva, _)| rva).collect::<Vec<_>>();
+        assert_eq!(found(0x4000, 0x4010), vec![0x3ffc, 0x4000]);
+        assert_eq!(found(0x4003, 0x4004), vec![0x3ffc, 0x4000]);
+        assert_eq!(found(0x4008, 0x4010), Vec::<u32>::new());
+        assert_eq!(found(0x3000, 0x3ffc), Vec::<u32>::new());
+        assert_eq!(found(0, u32::MAX), vec![0x3ffc, 0x4000, 0x4010]);
+        assert!(relocs.relocs_in_range(0, u32::MAX).all(|(_, rtype)| rtype == RelocType::DIR64));
+    }
+
+    #[test]
+    fn arm_reloc_types() {
+        //ARM_MOV32 @ 0x10, THUMB_MOV32 @ 0x20, HIGHLOW @ 0x30
+        let bytes = [0x00u8, 0x10, 0x00, 0x00, 0x0E, 0x00, 0x00, 0x00, 0x10, 0x50, 0x20, 0x70, 0x30, 0x30];
+
+        let mut relocs = Relocations::parse_bytes(bytes.to_vec(), 0).unwrap();
+        let types = |relocs: &Relocations| relocs.blocks[0].value.relocs.iter().map(|r| r.value.rtype).collect::<Vec<_>>();
+        assert_eq!(types(&relocs), vec![RelocType::UNKNOWN(5), RelocType::UNKNOWN(7), RelocType::HIGHLOW]);
+
+        relocs.set_machine(MachineType::ARM64);
+        assert_eq!(types(&relocs), vec![RelocType::UNKNOWN(5), RelocType::UNKNOWN(7), RelocType::HIGHLOW]);
+
+        relocs.set_machine(MachineType::ARMNT);
+        assert_eq!(types(&relocs), vec![RelocType::ARM_MOV_32, RelocType::THUMB_MOV_32, RelocType::HIGHLOW]);
+        assert_eq!(format!("{}", relocs.blocks[0].value.relocs[1].value), "THUMB_MOV_32 @ 0x000020");
+
+        assert_eq!(RelocType::from_machine(7, MachineType::ARM), RelocType::UNKNOWN(7));
+    }
+
+    #[test]
+    fn parse_stream_stops_at_padding() {
+        let bytes = [
+            0x00u8, 0x30, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00, 0xB8, 0xA0, 0xC0, 0xA0,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut reader = FragmentReader::new(bytes.to_vec(), 0x4800);
+
+        let relocs = Relocations::parse_stream(&mut reader, 0x4800, bytes.len() as u64, 0x1000).unwrap();
+        assert_eq!(relocs.blocks.len(), 1);
+        assert_eq!(relocs.blocks[0].offset, FileOffset(0x4800));
+        assert_eq!(relocs.blocks[0].value.relocs[1].offset, FileOffset(0x480a));
+    }
+
+    #[test]
+    fn parse_stream_rejects_bad_sizes() {
+        //Block size is beyond directory size.
+        let bytes = [0x00u8, 0x30, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0x7F, 0xB8, 0xA0, 0xC0, 0xA0];
+        let mut reader = FragmentReader::new(bytes.to_vec(), 0);
+        let err = Relocations::parse_stream(&mut reader, 0, bytes.len() as u64, 0x1000).unwrap_err();
+        assert!(matches!(err.root(), PeError::InvalidHeader { .. }));
+
+        //Block fits in directory but not in allowed allocation.
+        let bytes = [0x00u8, 0x30, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00, 0xB8, 0xA0, 0xC0, 0xA0];
+        let mut reader = FragmentReader::new(bytes.to_vec(), 0);
+        let err = Relocations::parse_stream(&mut reader, 0, bytes.len() as u64, 2).unwrap_err();
+        assert!(matches!(err.root(), PeError::AllocationTooLarge { size: 4, max: 2, .. }));
+    }
 }
@@ -0,0 +1,122 @@
+//! Text output of chosen parts of an image, in a chosen order.
+//!
+//! `Display for PeImage` writes `ReportSection::ALL`; `PeImage::format_report` writes any subset,
+//! e.g. only sections and imports.
+
+use std::fmt::Write;
+
+use crate::types::Header;
+
+use super::PeImage;
+
+/// Part of the text output, written by one of the `PeImage::format_*` functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReportSection {
+    /// DOS, NT, file and optional headers.
+    Headers,
+    DataDirectories,
+    Sections,
+    Imports,
+    Exports,
+    Relocations,
+    /// Load config and CHPE metadata.
+    LoadConfig,
+    HybridViews,
+    Resources,
+    /// Every timestamp, classified; see `PeImage::timestamps`.
+    Timestamps,
+}
+
+impl ReportSection {
+    /// Every part, in the order of `Display for PeImage`.
+    pub const ALL: [ReportSection; 10] = [
+        Self::Headers,
+        Self::DataDirectories,
+        Self::Sections,
+        Self::Imports,
+        Self::Exports,
+        Self::Relocations,
+        Self::LoadConfig,
+        Self::HybridViews,
+        Self::Resources,
+        Self::Timestamps,
+    ];
+
+    /// Short name of the part, as taken by `rustbin --only`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Headers => "headers",
+            Self::DataDirectories => "data-dirs",
+            Self::Sections => "sections",
+            Self::Imports => "imports",
+            Self::Exports => "exports",
+            Self::Relocations => "relocs",
+            Self::LoadConfig => "load-config",
+            Self::HybridViews => "hybrid-views",
+            Self::Resources => "resources",
+            Self::Timestamps => "timestamps",
+        }
+    }
+
+    /// Part with the short `name`, see `ReportSection::name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|part| part.name() == name)
+    }
+}
+
+impl PeImage {
+    /// Write `parts` in the given order. Directories the image doesn't have are skipped.
+    pub fn format_report(&self, f: &mut dyn Write, parts: &[ReportSection]) -> std::fmt::Result {
+        for part in parts {
+            match part {
+                ReportSection::Headers => self.format_basic_headers(f)?,
+                ReportSection::DataDirectories => self.format_data_dirs(f)?,
+                ReportSection::Sections => self.format_sections(f)?,
+                ReportSection::Imports => if self.has_imports() { self.format_imports(f)? },
+                ReportSection::Exports => if self.has_exports() { self.format_exports(f)? },
+                ReportSection::Relocations => if self.has_relocations() { self.format_relocations(f)? },
+                ReportSection::LoadConfig => self.format_load_config(f)?,
+                ReportSection::HybridViews => self.format_hybrid_views(f)?,
+                ReportSection::Resources => if self.has_rsrc() && self.resources.value.is_valid() {
+                    self.format_resource_tree(f, &String::from("  "), 1)?
+                },
+                ReportSection::Timestamps => self.format_timestamps(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::pe::PeImage;
+
+    use super::ReportSection;
+
+    #[test]
+    fn report_in_order() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+
+        let mut all = String::new();
+        pe.format_report(&mut all, &ReportSection::ALL).unwrap();
+        assert_eq!(all, pe.to_string());
+
+        let mut out = String::new();
+        pe.format_report(&mut out, &[ReportSection::Imports, ReportSection::Sections]).unwrap();
+        assert!(out.starts_with("Import Directory: ["));
+        assert!(out.find("Sections: [").unwrap() > out.find("Import Directory").unwrap());
+        assert!(!out.contains("DosHeader"));
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for part in ReportSection::ALL {
+            assert_eq!(ReportSection::from_name(part.name()), Some(part));
+        }
+        assert_eq!(ReportSection::from_name("data-dirs"), Some(ReportSection::DataDirectories));
+        assert_eq!(ReportSection::from_name("DataDirectories"), None);
+    }
+}
@@ -0,0 +1,88 @@
+//! Rich header, left by Microsoft linkers between the DOS stub and the PE header.
+//!
+//! Entries are XORed with a key stored after the `Rich` marker; the header starts with `DanS`
+//! XORed with the same key. Undocumented, so only what is widely agreed on is decoded.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+/// `DanS` as a little endian dword.
+const DANS: u32 = 0x536E6144;
+const RICH: &[u8; 4] = b"Rich";
+
+/// Tool (`product_id`, `build`) and number of objects it produced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RichEntry {
+    pub product_id: u16,
+    pub build: u16,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RichHeader {
+    /// Offset of `DanS`.
+    pub offset: u64,
+    pub key: u32,
+    pub entries: Vec<RichEntry>,
+    /// Bytes from `DanS` up to and including the key, as stored.
+    #[serde(skip)]
+    pub raw: Vec<u8>,
+}
+
+impl RichHeader {
+    /// Find the rich header in `bytes`, read from offset `pos` and ending before the PE header.
+    pub fn parse_bytes(bytes: &[u8], pos: u64) -> Option<Self> {
+        let dword = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+        let rich = (0..bytes.len().saturating_sub(7)).step_by(4).rev()
+            .find(|&i| &bytes[i..i + 4] == RICH)?;
+        let key = dword(rich + 4);
+        let start = (0..rich).step_by(4).rev().find(|&i| dword(i) ^ key == DANS)?;
+
+        //`DanS` is followed by three dwords of padding, then (comp id, count) pairs.
+        let entries = (start + 16..rich).step_by(8)
+            .filter(|i| i + 8 <= rich)
+            .map(|i| {
+                let comp_id = dword(i) ^ key;
+                RichEntry { product_id: (comp_id >> 16) as u16, build: comp_id as u16, count: dword(i + 4) ^ key }
+            })
+            .collect();
+
+        Some(Self { offset: pos + start as u64, key, entries, raw: bytes[start..rich + 8].to_vec() })
+    }
+}
+
+impl Display for RichHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{ Offset: {:#x}, Key: {:#010x}, Entries: {} }}", self.offset, self.key, self.entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RichEntry, RichHeader};
+
+    #[test]
+    fn decode_entries() {
+        let key = 0x1234_5678u32;
+        let mut bytes = vec![0u8; 0x80];
+        for dword in [0x536E6144, 0, 0, 0, 0x0104_7809, 3, 0x0093_7809, 12] {
+            bytes.extend((dword ^ key).to_le_bytes());
+        }
+        bytes.extend(b"Rich");
+        bytes.extend(key.to_le_bytes());
+        bytes.extend([0u8; 8]);
+
+        let rich = RichHeader::parse_bytes(&bytes, 0x100).unwrap();
+        assert_eq!(rich.offset, 0x180);
+        assert_eq!(rich.key, key);
+        assert_eq!(rich.entries, vec![
+            RichEntry { product_id: 0x0104, build: 0x7809, count: 3 },
+            RichEntry { product_id: 0x0093, build: 0x7809, count: 12 },
+        ]);
+        assert_eq!(rich.raw.len(), 40);
+
+        assert_eq!(RichHeader::parse_bytes(&bytes[..0x80], 0), None);
+    }
+}
@@ -1,21 +1,26 @@
 #![allow(non_camel_case_types)]
 
-use std::{fmt::{Display, Write}, io::{Cursor, SeekFrom}, mem::size_of};
+use std::{collections::HashSet, fmt::{Display, Write}, io::{Cursor, SeekFrom}, mem::size_of};
 
 use byteorder::{ReadBytesExt, LittleEndian};
-use chrono::{DateTime, Utc};
 use serde::Serialize;
 
-use crate::{new_header_field, types::{Header, HeaderField, BufReadExt}, Result};
+use crate::{new_header_field, pe::Result, types::{FileOffset, Header, HeaderField, BufReadExt}};
 
-use super::{section::{offset_to_rva, SectionHeader, SectionTable}, PeError};
+use super::{check_alloc, progress::Progress, section::{offset_to_rva, SectionHeader, SectionTable}, PeError, PeTimestamp, DEFAULT_MAX_ALLOC};
 
 pub const DIR_LENGTH: u64 = 16;
 pub const ENTRY_LENGTH: u64 = 8;
 pub const DATA_LENGTH: u64 = 16;
 
+/// Default nesting limit. Windows uses 3 levels (type, name, language).
+pub const DEFAULT_MAX_DEPTH: u32 = 16;
+/// Default limit for the number of entries in the whole tree.
+pub const DEFAULT_MAX_ENTRIES: u32 = 0x10000;
+
 #[repr(u8)]
 #[derive(Debug, Default, PartialEq, Clone, Copy, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 pub enum ResourceType {
     #[default]
     CURSOR = 1,
@@ -72,18 +77,90 @@ impl From<u32> for ResourceType {
 }
 
 
-#[derive(Debug, Default, Serialize)]
+impl From<ResourceType> for u32 {
+    fn from(value: ResourceType) -> Self {
+        match value {
+            ResourceType::CURSOR => 1,
+            ResourceType::BITMAP => 2,
+            ResourceType::ICON => 3,
+            ResourceType::MENU => 4,
+            ResourceType::DIALOG => 5,
+            ResourceType::STRING => 6,
+            ResourceType::FONTDIR => 7,
+            ResourceType::FONT => 8,
+            ResourceType::ACCELERATOR => 9,
+            ResourceType::RC_DATA => 10,
+            ResourceType::MESSAGE_TABLE => 11,
+            ResourceType::GROUP_CURSOR => 12,
+            ResourceType::GROUP_ICON => 14,
+            ResourceType::VERSION => 16,
+            ResourceType::DLG_INCLUDE => 17,
+            ResourceType::PLUG_PLAY => 19,
+            ResourceType::VXD => 20,
+            ResourceType::ANIMATED_CURSOR => 21,
+            ResourceType::ANIMATED_ICON => 22,
+            ResourceType::HTML => 23,
+            ResourceType::MANIFEST => 24,
+            ResourceType::UNKNOWN(id) => id,
+        }
+    }
+}
+
+
+/// Key of a resource directory entry: an integer id or a name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceId {
+    Id(u32),
+    /// Compared case insensitively, as Windows stores names in upper case.
+    Name(String),
+}
+
+impl From<u32> for ResourceId {
+    fn from(value: u32) -> Self {
+        Self::Id(value)
+    }
+}
+
+impl From<ResourceType> for ResourceId {
+    fn from(value: ResourceType) -> Self {
+        Self::Id(value.into())
+    }
+}
+
+impl From<&str> for ResourceId {
+    fn from(value: &str) -> Self {
+        Self::Name(value.into())
+    }
+}
+
+impl From<String> for ResourceId {
+    fn from(value: String) -> Self {
+        Self::Name(value)
+    }
+}
+
+impl Display for ResourceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "#{id}"),
+            Self::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
 pub struct ResourceString {
     pub length: HeaderField<u16>,
     pub value: HeaderField<String>,
 }
 
 impl ResourceString {
-    pub fn fix_rvas(&mut self, sections: &SectionTable) -> crate::Result<()> {
-        self.length.rva = offset_to_rva(sections, self.length.offset as u32)
+    pub fn fix_rvas(&mut self, sections: &SectionTable) -> crate::pe::Result<()> {
+        self.length.rva = offset_to_rva(sections, self.length.offset)
             .ok_or(PeError::NoSectionForOffset(self.length.offset.into()))?
             .into();
-        self.value.rva = offset_to_rva(sections, self.value.offset as u32)
+        self.value.rva = offset_to_rva(sections, self.value.offset)
             .ok_or(PeError::NoSectionForOffset(self.value.offset.into()))?
             .into();
 
@@ -92,27 +169,27 @@ impl ResourceString {
 }
 
 impl Header for ResourceString {
-    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> where Self: Sized {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> where Self: Sized {
         let mut hdr = Self::default();
         let offset = pos;
         
         let mut cursor = Cursor::new(bytes);
         hdr.value.value = cursor.read_wchar_string_at_offset(0)?;
-        hdr.value.offset = offset + 2;
+        hdr.value.offset = FileOffset(offset + 2);
         hdr.length.value = hdr.value.value.len() as u16;
-        hdr.length.offset = offset;
+        hdr.length.offset = FileOffset(offset);
 
         Ok(hdr)
     }
 
-    fn parse_buf(reader: &mut impl BufReadExt, pos: u64, offset: u64) -> crate::Result<Self> where Self: Sized {
+    fn parse_buf(reader: &mut impl BufReadExt, pos: u64, offset: u64) -> crate::pe::Result<Self> where Self: Sized {
         let mut hdr = Self::default();
         let mut field_pos = pos;
         reader.seek(SeekFrom::Start(offset))?;
 
         hdr.length = new_header_field!(reader.read_u16::<LittleEndian>()?, field_pos);
         hdr.value.value = reader.read_wchar_string_at_offset(offset + 2)?;
-        hdr.value.offset = field_pos + 2;
+        hdr.value.offset = FileOffset(field_pos + 2);
 
         Ok(hdr)
     }
@@ -133,7 +210,7 @@ impl Display for ResourceString {
 }
 
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
 pub struct ResourceData {
     pub rva: HeaderField<u32>,
     pub size: HeaderField<u32>,
@@ -145,16 +222,31 @@ pub struct ResourceData {
 }
 
 impl ResourceData {
-    pub fn load_data(&mut self, section: &SectionHeader, reader: &mut dyn BufReadExt) -> crate::Result<&mut Self> {
+    /// Data entry holding `data`, not yet part of an image.
+    pub fn new(data: Vec<u8>, code_page: u32) -> Self {
+        Self {
+            size: HeaderField { value: data.len() as u32, ..Default::default() },
+            code_page: HeaderField { value: code_page, ..Default::default() },
+            value: HeaderField { value: data, ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    pub fn load_data(&mut self, section: &SectionHeader, reader: &mut dyn BufReadExt) -> crate::pe::Result<&mut Self> {
+        self.load_data_with_limit(section, reader, DEFAULT_MAX_ALLOC)
+    }
+
+    /// Same as `load_data`, but fails with `AllocationTooLarge` if data is more than `max_alloc` bytes.
+    pub fn load_data_with_limit(&mut self, section: &SectionHeader, reader: &mut dyn BufReadExt, max_alloc: u64) -> crate::pe::Result<&mut Self> {
         let section_offset = section.raw_data_ptr.value as u64;
         let section_len = section.virtual_size.value as u64;
 
         let rv_offset = self.rva.value as i64 - section.virtual_address.value as i64; //relative virtual offset.
         if rv_offset <= 0 { // must be in resource section?
-            let section_endva = (section.virtual_address.value + section.virtual_size.value) as u64;
+            let section_endva = section.virtual_address.value as u64 + section.virtual_size.value as u64;
             return Err(
                 PeError::BeyondRange {
-                    name: format!("{} section", section.name_str()?),
+                    name: format!("{} section", section.name_lossy()),
                     typ: "rva".into(), 
                     value: self.rva.value.into(), 
                     start: section.virtual_address.value.into(), 
@@ -168,7 +260,7 @@ impl ResourceData {
         if offset > section_end_offset { // must be in resource section?
             return Err(
                 PeError::BeyondRange {
-                    name: format!("{} section", section.name_str()?),
+                    name: format!("{} section", section.name_lossy()),
                     typ: "offset".into(), 
                     value: offset.into(), 
                     start: section.raw_data_ptr.value.into(), 
@@ -177,26 +269,41 @@ impl ResourceData {
             )
         }
 
+        let data_end = offset + self.size.value as u64;
+        let section_raw_end = section_offset + section.sizeof_raw_data.value as u64;
+        if data_end > section_raw_end {
+            return Err(
+                PeError::BeyondRange {
+                    name: format!("{} section", section.name_lossy()),
+                    typ: "data end".into(),
+                    value: data_end,
+                    start: section_offset,
+                    end: section_raw_end,
+                }
+            )
+        }
+        check_alloc("ResourceData", self.size.value.into(), max_alloc)?;
+
         let data = reader.read_bytes_at_offset(offset, self.size.value as usize)?;
-        self.value = HeaderField{value: data, offset: offset, rva: self.rva.value.into()};
+        self.value = HeaderField{value: data, offset: FileOffset(offset), rva: self.rva.value.into()};
 
         Ok(self)
     }
 
-    pub fn fix_rvas(&mut self, sections: &SectionTable) -> crate::Result<()> {
-        self.rva.rva = offset_to_rva(sections, self.rva.offset as u32)
+    pub fn fix_rvas(&mut self, sections: &SectionTable) -> crate::pe::Result<()> {
+        self.rva.rva = offset_to_rva(sections, self.rva.offset)
             .ok_or(PeError::InvalidOffset(self.rva.offset.into()))?
             .into();
 
-        self.size.rva = offset_to_rva(sections, self.size.offset as u32)
+        self.size.rva = offset_to_rva(sections, self.size.offset)
             .ok_or(PeError::InvalidOffset(self.size.offset.into()))?
             .into();
 
-        self.code_page.rva = offset_to_rva(sections, self.code_page.offset as u32)
+        self.code_page.rva = offset_to_rva(sections, self.code_page.offset)
             .ok_or(PeError::InvalidOffset(self.code_page.value.into()))?
             .into();
 
-        self.reserved.rva = offset_to_rva(sections, self.reserved.offset as u32)
+        self.reserved.rva = offset_to_rva(sections, self.reserved.offset)
             .ok_or(PeError::InvalidOffset(self.reserved.offset.into()))?
             .into();
         
@@ -205,7 +312,7 @@ impl ResourceData {
 }
 
 impl Header for ResourceData {
-    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> where Self: Sized {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> where Self: Sized {
         let mut offset = pos;
         let mut hdr = Self::default();
         
@@ -235,9 +342,8 @@ impl Display for ResourceData {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ResourceNode {
-    Str(ResourceString),
     Data(ResourceData),
     Dir(ResourceDirectory)
 }
@@ -250,10 +356,9 @@ impl Default for ResourceNode {
 
 
 impl ResourceNode {
-    pub fn fix_rvas(&mut self, sections: &SectionTable) -> crate::Result<()> {
+    pub fn fix_rvas(&mut self, sections: &SectionTable) -> crate::pe::Result<()> {
         match self {
             Self::Data(data) => data.fix_rvas(sections),
-            Self::Str(rstr) => rstr.fix_rvas(sections),
             Self::Dir(dir) => dir.fix_rvas(sections),
         }
     }
@@ -261,11 +366,14 @@ impl ResourceNode {
 
 impl Display for ResourceNode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self)
+        match self {
+            Self::Data(data) => write!(f, "{data}"),
+            Self::Dir(dir) => write!(f, "{dir}"),
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DataType {
     STRING,
     DATA,
@@ -273,46 +381,72 @@ pub enum DataType {
 }
 
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct ResourceEntry {
+    /// Named by a string, kept in `name`; `id` is `None`.
     pub is_string: bool,
     pub is_data: bool,
-    pub id: ResourceType,
+    /// Integer id: the resource type in the root directory, the name or language below it.
+    pub id: Option<u32>,
+    pub name: Option<ResourceString>,
     pub name_offset: HeaderField<u32>,
     pub data_offset: HeaderField<u32>,
     pub data: ResourceNode,
 }
 
 impl ResourceEntry {
-    fn parse_rsrc(&mut self, section: &SectionHeader, reader: &mut impl BufReadExt)-> crate::Result<&mut Self> where Self: Sized {
+    /// `id` as a resource type; only meaningful for entries of the root directory.
+    pub fn resource_type(&self) -> Option<ResourceType> {
+        self.id.map(ResourceType::from)
+    }
+
+    /// Name or integer id of the entry.
+    pub fn key(&self) -> ResourceId {
+        match (&self.name, self.id) {
+            (Some(name), _) => ResourceId::Name(name.value.value.clone()),
+            (None, id) => ResourceId::Id(id.unwrap_or_default()),
+        }
+    }
+
+    /// Whether the entry is keyed by `id`.
+    pub fn matches(&self, id: &ResourceId) -> bool {
+        match id {
+            ResourceId::Id(id) => self.id == Some(*id),
+            ResourceId::Name(name) => self.name.as_ref()
+                .is_some_and(|n| n.value.value.to_uppercase() == name.to_uppercase()),
+        }
+    }
+
+    pub fn parse_rsrc(&mut self, section: &SectionHeader, reader: &mut impl BufReadExt)-> crate::pe::Result<&mut Self> where Self: Sized {
+        let mut progress = Progress::default();
+        let mut state = TreeState::new(DEFAULT_MAX_DEPTH, DEFAULT_MAX_ENTRIES, &mut progress);
+        self.parse_tree(section, reader, &mut state)
+    }
+
+    fn parse_tree(&mut self, section: &SectionHeader, reader: &mut impl BufReadExt, state: &mut TreeState)-> crate::pe::Result<&mut Self> where Self: Sized {
         const OFFSET_MASK: u32 = 0x7fffffff;
         let section_offset = section.raw_data_ptr.value as u64;
 
+        if self.is_string {
+            let pos = section_offset + (self.name_offset.value & OFFSET_MASK) as u64;
+            let name = reader.read_wchar_string_at_offset(pos)?;
+            self.name = Some(ResourceString {
+                length: HeaderField::at_offset(name.encode_utf16().count() as u16, pos),
+                value: HeaderField::at_offset(name, pos + 2),
+            });
+        }
+
+        let pos = section_offset + (self.data_offset.value & OFFSET_MASK) as u64;
         if self.is_data {
-            let offset = (self.data_offset.value & OFFSET_MASK) as u64;
-            let pos = section_offset + offset;
             let bytes = reader.read_bytes_at_offset(pos, DATA_LENGTH as usize)?;
             let data = ResourceData::parse_bytes(bytes, pos)?;
 
             self.data = ResourceNode::Data(data);
         }
-        else if self.is_string {
-            let offset = (self.name_offset.value & OFFSET_MASK) as u64;
-            let pos = section_offset + offset;
-            let rstr = reader.read_wchar_string_at_offset(pos)?;
-            let data = ResourceString { 
-                length: HeaderField { value: rstr.len() as u16, offset: pos, rva: pos }, 
-                value: HeaderField { value: rstr, offset: pos + 2, rva: pos + 2 }
-            };
-
-            self.data = ResourceNode::Str(data);
-        }
         else {
-            let offset = (self.data_offset.value & OFFSET_MASK) as u64;
-            let pos = section_offset + offset;
             let bytes = reader.read_bytes_at_offset(pos, DIR_LENGTH as usize)?;
             let mut data = ResourceDirectory::parse_bytes(bytes, pos)?;
-            data.parse_rsrc(section, reader)?;
+            data.parse_tree(section, reader, state)?;
 
             self.data = ResourceNode::Dir(data);
         }
@@ -320,15 +454,18 @@ impl ResourceEntry {
         Ok(self)
     }
 
-    pub fn fix_rvas(&mut self, sections: &SectionTable) -> crate::Result<()> {
-        self.name_offset.rva = offset_to_rva(sections, self.name_offset.offset as u32)
+    pub fn fix_rvas(&mut self, sections: &SectionTable) -> crate::pe::Result<()> {
+        self.name_offset.rva = offset_to_rva(sections, self.name_offset.offset)
             .ok_or(PeError::InvalidOffset(self.name_offset.offset.into()))?
             .into();
         
-        self.data_offset.rva = offset_to_rva(sections, self.data_offset.offset as u32)
+        self.data_offset.rva = offset_to_rva(sections, self.data_offset.offset)
             .ok_or(PeError::InvalidOffset(self.data_offset.offset.into()))?
             .into();
 
+        if let Some(name) = &mut self.name {
+            name.fix_rvas(sections)?;
+        }
         self.data.fix_rvas(sections)?;
 
         Ok(())
@@ -336,7 +473,7 @@ impl ResourceEntry {
 }
 
 impl Header for ResourceEntry {
-    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> where Self: Sized {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> where Self: Sized {
         let mut hdr = Self::default();
         let mut offset = pos;
 
@@ -346,13 +483,10 @@ impl Header for ResourceEntry {
         hdr.name_offset = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
         hdr.data_offset = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
 
-        if hdr.name_offset.value & 0x80000000 == 0 {
-            hdr.is_string = false;
-            hdr.id = ResourceType::from(hdr.name_offset.value & 0x7fffffff);
-        }
-        else {
-            hdr.is_string = true;
-            hdr.id = ResourceType::from(0);
+        //The name itself is read with the tree, it is elsewhere in the section.
+        hdr.is_string = hdr.name_offset.value & 0x80000000 != 0;
+        if !hdr.is_string {
+            hdr.id = Some(hdr.name_offset.value);
         }
 
         hdr.is_data = hdr.data_offset.value & 0x80000000 == 0;
@@ -371,15 +505,29 @@ impl Header for ResourceEntry {
 
 impl Display for ResourceEntry {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{{ IsString: {}, IsData: {}, ID: {:?}, NameOffset: {:08x}, DataOffset: {:08x} }}", self.is_string, self.is_data, self.id, self.name_offset.value, self.data_offset.value)
+        match (&self.name, self.id) {
+            (Some(name), _) => write!(f, "{{ Name: \"{name}\", ")?,
+            (None, Some(id)) => write!(f, "{{ ID: {id}, ")?,
+            (None, None) => write!(f, "{{ Name: ?, ")?,
+        }
+        write!(f, "IsData: {}, NameOffset: {:08x}, DataOffset: {:08x} }}", self.is_data, self.name_offset.value, self.data_offset.value)
+    }
+}
+
+
+/// Entries sort by name, case insensitively, before ids.
+fn sort_key(entry: &ResourceEntry) -> (bool, String, u32) {
+    match &entry.name {
+        Some(name) => (false, name.value.value.to_uppercase(), 0),
+        None => (true, String::new(), entry.id.unwrap_or_default()),
     }
 }
 
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct ResourceDirectory {
     pub charactristics: HeaderField<u32>,
-    pub timestamp: HeaderField<DateTime<Utc>>,
+    pub timestamp: HeaderField<PeTimestamp>,
     pub major_version: HeaderField<u16>,
     pub minor_version: HeaderField<u16>,
     pub named_entry_count: HeaderField<u16>,
@@ -389,50 +537,308 @@ pub struct ResourceDirectory {
 
 impl Display for ResourceDirectory {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{{ Charactristics: {:#08x}, Timestamp: {:?}, MajorVersion: {}, MinorVersion: {}, NumberOfNamedEntries: {}, NumberOfIdEntries: {} }}",
+        write!(f, "{{ Charactristics: {:#08x}, Timestamp: {}, MajorVersion: {}, MinorVersion: {}, NumberOfNamedEntries: {}, NumberOfIdEntries: {} }}",
             self.charactristics.value, self.timestamp.value, self.major_version.value, self.minor_version, self.named_entry_count, self.id_entry_count
         )
     }
 }
 
+/// Book keeping while walking a resource tree, so a crafted tree can't recurse or allocate without bound.
+struct TreeState<'a> {
+    /// Directories from the root to the one being parsed.
+    path: HashSet<u64>,
+    depth: u32,
+    max_depth: u32,
+    entries_left: u32,
+    progress: &'a mut Progress,
+}
+
+impl<'a> TreeState<'a> {
+    fn new(max_depth: u32, max_entries: u32, progress: &'a mut Progress) -> Self {
+        Self { path: HashSet::new(), depth: 0, max_depth, entries_left: max_entries, progress }
+    }
+}
+
 impl ResourceDirectory {
-    pub fn parse_rsrc(&mut self, section: &SectionHeader, reader: &mut impl BufReadExt) -> crate::Result<()> {
+    /// First entry keyed by `id`.
+    pub fn entry(&self, id: &ResourceId) -> Option<&ResourceEntry> {
+        self.entries.iter().find(|e| e.matches(id))
+    }
+
+    /// Data of a resource in the standard type, name and language tree. Without `lang`, the
+    /// first language is taken.
+    pub fn find_resource(&self, typ: impl Into<ResourceId>, name: impl Into<ResourceId>, lang: Option<u16>) -> Option<&ResourceData> {
+        let ResourceNode::Dir(names) = &self.entry(&typ.into())?.data else {
+            return None;
+        };
+        let ResourceNode::Dir(langs) = &names.entry(&name.into())?.data else {
+            return None;
+        };
+        let entry = match lang {
+            Some(lang) => langs.entry(&ResourceId::Id(lang.into()))?,
+            None => langs.entries.first()?,
+        };
+
+        match &entry.data {
+            ResourceNode::Data(data) => Some(data),
+            ResourceNode::Dir(_) => None,
+        }
+    }
+
+    /// Add data of the resource `typ`, `name` and `lang`, replacing data already there.
+    /// Entries are kept sorted as Windows looks them up: names, case insensitively, then ids.
+    pub fn insert(&mut self, typ: impl Into<ResourceId>, name: impl Into<ResourceId>, lang: u16, data: Vec<u8>) {
+        let langs = self.subdir(typ.into()).subdir(name.into());
+        let entry = langs.entry_or_insert(ResourceId::Id(lang.into()));
+        let code_page = match &entry.data {
+            ResourceNode::Data(old) => old.code_page.value,
+            ResourceNode::Dir(_) => 0,
+        };
+        entry.is_data = true;
+        entry.data = ResourceNode::Data(ResourceData::new(data, code_page));
+    }
+
+    /// Remove the resource `typ` and `name` in `lang`, or in all languages without `lang`.
+    /// Directories left empty are removed too. Returns whether anything was removed.
+    pub fn remove(&mut self, typ: impl Into<ResourceId>, name: impl Into<ResourceId>, lang: Option<u16>) -> bool {
+        let (typ, name) = (typ.into(), name.into());
+        let Some(ResourceNode::Dir(names)) = self.entries.iter_mut().find(|e| e.matches(&typ)).map(|e| &mut e.data) else {
+            return false;
+        };
+        let Some(ResourceNode::Dir(langs)) = names.entries.iter_mut().find(|e| e.matches(&name)).map(|e| &mut e.data) else {
+            return false;
+        };
+
+        let count = langs.entries.len();
+        langs.entries.retain(|e| lang.is_some_and(|lang| !e.matches(&ResourceId::Id(lang.into()))));
+        let removed = langs.entries.len() != count;
+        langs.update_counts();
+
+        if langs.entries.is_empty() {
+            names.entries.retain(|e| !e.matches(&name));
+            names.update_counts();
+        }
+        if names.entries.is_empty() {
+            self.entries.retain(|e| !e.matches(&typ));
+            self.update_counts();
+        }
+        removed
+    }
+
+    /// Directory under the entry keyed by `id`, added if needed.
+    fn subdir(&mut self, id: ResourceId) -> &mut ResourceDirectory {
+        let entry = self.entry_or_insert(id);
+        if let ResourceNode::Data(_) = entry.data {
+            entry.data = ResourceNode::Dir(Default::default());
+        }
+        entry.is_data = false;
+        match &mut entry.data {
+            ResourceNode::Dir(dir) => dir,
+            ResourceNode::Data(_) => unreachable!(),
+        }
+    }
+
+    fn entry_or_insert(&mut self, id: ResourceId) -> &mut ResourceEntry {
+        if let Some(index) = self.entries.iter().position(|e| e.matches(&id)) {
+            return &mut self.entries[index];
+        }
+
+        let mut entry = ResourceEntry::default();
+        match id {
+            ResourceId::Id(id) => entry.id = Some(id),
+            ResourceId::Name(name) => {
+                entry.is_string = true;
+                entry.name = Some(ResourceString {
+                    length: HeaderField { value: name.encode_utf16().count() as u16, ..Default::default() },
+                    value: HeaderField { value: name, ..Default::default() },
+                });
+            },
+        }
+        let key = sort_key(&entry);
+        let index = self.entries.partition_point(|e| sort_key(e) < key);
+        self.entries.insert(index, entry);
+        self.update_counts();
+        &mut self.entries[index]
+    }
+
+    fn update_counts(&mut self) {
+        let named = self.entries.iter().filter(|e| e.name.is_some()).count() as u16;
+        self.named_entry_count.value = named;
+        self.id_entry_count.value = self.entries.len() as u16 - named;
+    }
+
+    /// Bytes of the tree as a resource section at `rva`. Directory tables come first, in
+    /// breadth first order, then names, data entries and the data of each entry, aligned to 8.
+    /// Data is the `value` of each `ResourceData`, which must be loaded.
+    pub fn to_bytes(&self, rva: u32) -> Vec<u8> {
+        let mut dirs = vec![self];
+        let mut next = 0;
+        while next < dirs.len() {
+            let children = dirs[next].entries.iter().filter_map(|e| match &e.data {
+                ResourceNode::Dir(dir) => Some(dir),
+                ResourceNode::Data(_) => None,
+            });
+            dirs.extend(children);
+            next += 1;
+        }
+
+        let entries = || dirs.iter().flat_map(|d| &d.entries);
+        let tables_len: u64 = dirs.iter().map(|d| DIR_LENGTH + d.entries.len() as u64 * ENTRY_LENGTH).sum();
+
+        let mut strings = Vec::new();
+        let mut name_offsets = Vec::new();
+        for entry in entries() {
+            name_offsets.push(entry.name.as_ref().map(|name| {
+                let offset = tables_len as u32 + strings.len() as u32;
+                let units: Vec<u16> = name.value.value.encode_utf16().collect();
+                strings.extend((units.len() as u16).to_le_bytes());
+                strings.extend(units.iter().flat_map(|u| u.to_le_bytes()));
+                offset
+            }));
+        }
+        strings.resize(strings.len().next_multiple_of(8), 0);
+
+        let data_entries_start = tables_len + strings.len() as u64;
+        let data_count = entries().filter(|e| matches!(e.data, ResourceNode::Data(_))).count() as u64;
+        let blobs_start = data_entries_start + data_count * DATA_LENGTH;
+
+        let mut out = Vec::with_capacity(blobs_start as usize);
+        let mut blobs = Vec::new();
+        let mut data_entries = Vec::new();
+        let mut dir_offset = DIR_LENGTH + dirs[0].entries.len() as u64 * ENTRY_LENGTH;
+        let mut names = name_offsets.into_iter();
+
+        for dir in &dirs {
+            let named = dir.entries.iter().filter(|e| e.name.is_some()).count() as u16;
+            out.extend(dir.charactristics.value.to_le_bytes());
+            out.extend(dir.timestamp.value.raw().to_le_bytes());
+            out.extend(dir.major_version.value.to_le_bytes());
+            out.extend(dir.minor_version.value.to_le_bytes());
+            out.extend(named.to_le_bytes());
+            out.extend((dir.entries.len() as u16 - named).to_le_bytes());
+
+            for entry in &dir.entries {
+                let name = match names.next().flatten() {
+                    Some(offset) => 0x8000_0000 | offset,
+                    None => entry.id.unwrap_or_default(),
+                };
+                let target = match &entry.data {
+                    ResourceNode::Dir(child) => {
+                        let offset = 0x8000_0000 | dir_offset as u32;
+                        dir_offset += DIR_LENGTH + child.entries.len() as u64 * ENTRY_LENGTH;
+                        offset
+                    },
+                    ResourceNode::Data(data) => {
+                        let offset = (data_entries_start + data_entries.len() as u64) as u32;
+                        let data_rva = rva + blobs_start as u32 + blobs.len() as u32;
+                        for value in [data_rva, data.value.value.len() as u32, data.code_page.value, 0] {
+                            data_entries.extend(value.to_le_bytes());
+                        }
+                        blobs.extend(&data.value.value);
+                        blobs.resize(blobs.len().next_multiple_of(8), 0);
+                        offset
+                    },
+                };
+                out.extend(name.to_le_bytes());
+                out.extend(target.to_le_bytes());
+            }
+        }
+
+        out.extend(strings);
+        out.extend(data_entries);
+        out.extend(blobs);
+        out
+    }
+
+    pub fn parse_rsrc(&mut self, section: &SectionHeader, reader: &mut impl BufReadExt) -> crate::pe::Result<()> {
+        self.parse_rsrc_with_limits(section, reader, DEFAULT_MAX_DEPTH, DEFAULT_MAX_ENTRIES)
+    }
+
+    /// Same as `parse_rsrc`, but with explicit limits on nesting depth and total number of entries.
+    /// A directory that contains itself fails with `ResourceLoop`; directories shared by several entries are parsed for each.
+    pub fn parse_rsrc_with_limits(&mut self, section: &SectionHeader, reader: &mut impl BufReadExt, max_depth: u32, max_entries: u32) -> crate::pe::Result<()> {
+        self.parse_rsrc_with_progress(section, reader, max_depth, max_entries, &mut Progress::default())
+    }
+
+    /// Same as `parse_rsrc_with_limits`, reporting the entries of the root parsed to `progress`.
+    pub(crate) fn parse_rsrc_with_progress(&mut self, section: &SectionHeader, reader: &mut impl BufReadExt, max_depth: u32, max_entries: u32, progress: &mut Progress) -> crate::pe::Result<()> {
+        let mut state = TreeState::new(max_depth, max_entries, progress);
+        self.parse_tree(section, reader, &mut state)
+    }
+
+    fn parse_tree(&mut self, section: &SectionHeader, reader: &mut impl BufReadExt, state: &mut TreeState) -> crate::pe::Result<()> {
+        let dir_offset = u64::from(self.charactristics.offset);
+
+        if !state.path.insert(dir_offset) {
+            return Err(PeError::ResourceLoop(dir_offset));
+        }
+        if state.depth >= state.max_depth {
+            return Err(PeError::ResourceTooDeep { offset: dir_offset, depth: state.depth + 1, max: state.max_depth });
+        }
+
         let entry_count:u32 = self.named_entry_count.value as u32 + self.id_entry_count.value as u32; 
 
+        let entries_end = dir_offset + DIR_LENGTH + entry_count as u64 * ENTRY_LENGTH;
+        let section_len = section.sizeof_raw_data.value.max(section.virtual_size.value);
+        let section_end = section.raw_data_ptr.value as u64 + section_len as u64;
+        if entries_end > section_end {
+            return Err(PeError::InvalidHeader {
+                name: "ResourceDirectory".into(),
+                offset: dir_offset,
+                reason: format!("{entry_count} entries end at {entries_end:08x}, beyond section end {section_end:08x}"),
+            });
+        }
+        if entry_count > state.entries_left {
+            return Err(PeError::InvalidHeader {
+                name: "ResourceDirectory".into(),
+                offset: dir_offset,
+                reason: format!("{entry_count} more entries exceed the limit for the resource tree"),
+            });
+        }
+        state.entries_left -= entry_count;
+        self.entries.reserve(entry_count as usize);
+
+        let is_root = state.depth == 0;
+        state.depth += 1;
         for i in 0..entry_count {
-            let pos = self.charactristics.offset + DIR_LENGTH + (i * ENTRY_LENGTH as u32) as u64;
+            let pos = dir_offset + DIR_LENGTH + (i * ENTRY_LENGTH as u32) as u64;
             //let offset = section_offset + self.charactristics.offset + DIR_LENGTH + (i + ENTRY_LENGTH as u16) as u64;
             let buf = reader.read_bytes_at_offset(pos, ENTRY_LENGTH as usize)?;
             let mut entry = ResourceEntry::parse_bytes(buf, pos)?;
-            entry.parse_rsrc(section, reader)?;
+            entry.parse_tree(section, reader, state)?;
             self.entries.push(entry);
+            if is_root {
+                state.progress.update((i + 1).into(), entry_count.into());
+            }
         }
+        state.depth -= 1;
+        state.path.remove(&dir_offset);
 
         Ok(())
     }
 
     pub fn fix_rvas(&mut self, sections: &SectionTable) -> Result<()> {
-        self.charactristics.rva = offset_to_rva(sections, self.charactristics.offset as u32)
+        self.charactristics.rva = offset_to_rva(sections, self.charactristics.offset)
             .ok_or(PeError::InvalidOffset(self.charactristics.offset.into()))?
             .into();
 
-        self.timestamp.rva = offset_to_rva(sections, self.timestamp.offset as u32)
+        self.timestamp.rva = offset_to_rva(sections, self.timestamp.offset)
             .ok_or(PeError::InvalidOffset(self.timestamp.offset.into()))?
             .into();
 
-        self.major_version.rva = offset_to_rva(sections, self.major_version.offset as u32)
+        self.major_version.rva = offset_to_rva(sections, self.major_version.offset)
             .ok_or(PeError::InvalidOffset(self.major_version.offset.into()))?
             .into();
 
-        self.minor_version.rva = offset_to_rva(sections, self.minor_version.offset as u32)
+        self.minor_version.rva = offset_to_rva(sections, self.minor_version.offset)
             .ok_or(PeError::InvalidOffset(self.minor_version.offset.into()))?
             .into();
 
-        self.named_entry_count.rva = offset_to_rva(sections, self.named_entry_count.offset as u32)
+        self.named_entry_count.rva = offset_to_rva(sections, self.named_entry_count.offset)
             .ok_or(PeError::InvalidOffset(self.named_entry_count.offset.into()))?
             .into();
 
-        self.id_entry_count.rva = offset_to_rva(sections, self.id_entry_count.offset as u32)
+        self.id_entry_count.rva = offset_to_rva(sections, self.id_entry_count.offset)
             .ok_or(PeError::InvalidOffset(self.id_entry_count.offset.into()))?
             .into();
 
@@ -445,7 +851,7 @@ impl ResourceDirectory {
 }
 
 impl Header for ResourceDirectory {
-    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> where Self: Sized {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> where Self: Sized {
         let bytes_len = bytes.len() as u64;
         let mut offset = pos;
 
@@ -462,8 +868,7 @@ impl Header for ResourceDirectory {
         hdr.charactristics = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
         
         let data = cursor.read_u32::<LittleEndian>()?;
-        let ts = DateTime::<Utc>::from_timestamp(data.into(), 0).ok_or(PeError::InvalidTimestamp(data.into()))?; //TODO: map to RsrcParseError?
-        hdr.timestamp = HeaderField {value: ts, offset:offset, rva: offset};
+        hdr.timestamp = HeaderField::at_offset(PeTimestamp::from(data), offset);
         offset += size_of::<u32>() as u64;
 
         hdr.major_version = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
@@ -485,15 +890,22 @@ impl Header for ResourceDirectory {
 
 
 pub(crate) fn display_rsrc_tree(dir: &ResourceDirectory, f: &mut dyn Write, seperator: &String, level: u8) -> std::fmt::Result {
+    display_dir(dir, f, seperator, level, true)
+}
+
+fn display_dir(dir: &ResourceDirectory, f: &mut dyn Write, seperator: &String, level: u8, is_root: bool) -> std::fmt::Result {
     writeln!(f, "{} Dir: {}", seperator.repeat(level.into()), dir)?;
 
     for entry in &dir.entries {
-        writeln!(f, "{} Entry: {}", seperator.repeat((level + 1).into()), entry)?;
+        //Types are only known for the root entries.
+        match entry.resource_type().filter(|_| is_root) {
+            Some(typ) => writeln!(f, "{} Entry: {:?} {}", seperator.repeat((level + 1).into()), typ, entry)?,
+            None => writeln!(f, "{} Entry: {}", seperator.repeat((level + 1).into()), entry)?,
+        }
         let prefix = seperator.repeat((level + 2).into());
         match &entry.data {
-            ResourceNode::Str(str) => writeln!(f, "{prefix} Str: {str}")?,
             ResourceNode::Data(data) => writeln!(f, "{prefix} Data: {data}")?,
-            ResourceNode::Dir(dir) => display_rsrc_tree(&dir, f, seperator, level+3)?
+            ResourceNode::Dir(dir) => display_dir(dir, f, seperator, level+3, false)?
         }
     }
 
@@ -1,6 +1,6 @@
-use crate::{pe::{rsrc::{display_rsrc_tree, ResourceNode, ResourceType, DATA_LENGTH, ENTRY_LENGTH}, section::{parse_sections, section_by_name, SectionHeader}}, types::{Header, HeaderField}, utils::FragmentReader};
+use crate::{pe::{rsrc::{display_rsrc_tree, DIR_LENGTH, ResourceNode, ResourceType, DATA_LENGTH, ENTRY_LENGTH}, section::{parse_sections, section_by_name, SectionHeader}, PeError}, types::{FileOffset, Header, HeaderField, Rva}, utils::FragmentReader};
 
-use crate::pe::rsrc::{ResourceDirectory, ResourceData, ResourceEntry, ResourceString};
+use crate::pe::rsrc::{ResourceDirectory, ResourceData, ResourceEntry, ResourceId, ResourceString};
 
 #[test]
 fn parse_rsrc_table() {
@@ -11,17 +11,17 @@ fn parse_rsrc_table() {
     let rst = ResourceDirectory::parse_bytes(rsrc_tbl_bytes.to_vec(), 0).unwrap();
 
     assert_eq!(rst.charactristics.value, 0);
-    assert_eq!(rst.charactristics.offset, 0);
-    assert_eq!(rst.timestamp.value.format("%Y-%m-%d %H:%M:%S").to_string(), "1970-01-01 00:00:00");
-    assert_eq!(rst.timestamp.offset, 0x04);
+    assert_eq!(rst.charactristics.offset, FileOffset(0));
+    assert_eq!(rst.timestamp.value.as_datetime().unwrap().format("%Y-%m-%d %H:%M:%S").to_string(), "1970-01-01 00:00:00");
+    assert_eq!(rst.timestamp.offset, FileOffset(0x04));
     assert_eq!(rst.major_version.value, 0x0004);
-    assert_eq!(rst.major_version.offset, 0x08);
+    assert_eq!(rst.major_version.offset, FileOffset(0x08));
     assert_eq!(rst.minor_version.value, 0);
-    assert_eq!(rst.minor_version.offset, 0x0a);
+    assert_eq!(rst.minor_version.offset, FileOffset(0x0a));
     assert_eq!(rst.named_entry_count.value, 0x0000);
-    assert_eq!(rst.named_entry_count.offset, 0x0c);
+    assert_eq!(rst.named_entry_count.offset, FileOffset(0x0c));
     assert_eq!(rst.id_entry_count.value, 0x000a);
-    assert_eq!(rst.id_entry_count.offset, 0x0e);
+    assert_eq!(rst.id_entry_count.offset, FileOffset(0x0e));
 }
 
 #[test]
@@ -31,9 +31,9 @@ fn parse_rsrc_string() {
     let rstr = ResourceString::parse_bytes(bytes.to_vec(), 0).unwrap();
     
     assert_eq!(rstr.length.value, 4);
-    assert_eq!(rstr.length.offset, 0x0);
+    assert_eq!(rstr.length.offset, FileOffset(0x0));
     assert_eq!(rstr.value.value, "ABCD");
-    assert_eq!(rstr.value.offset, 0x2);
+    assert_eq!(rstr.value.offset, FileOffset(0x2));
 }
 
 #[test]
@@ -44,8 +44,8 @@ fn rstr_fix_rva() {
 
     rstr.fix_rvas(&sections).unwrap();
 
-    assert_eq!(rstr.length.rva, 0x00018002);
-    assert_eq!(rstr.value.rva, 0x00018004);
+    assert_eq!(rstr.length.rva, Rva(0x00018002));
+    assert_eq!(rstr.value.rva, Rva(0x00018004));
 }
 
 #[test]
@@ -56,13 +56,13 @@ fn parse_rsrc_data() {
     let data = ResourceData::parse_bytes(bytes.to_vec(), SECTION_OFFSET + pos).unwrap();
     
     assert_eq!(data.rva.value, 0x000180a0);
-    assert_eq!(data.rva.offset, 0x00013880);
+    assert_eq!(data.rva.offset, FileOffset(0x00013880));
     assert_eq!(data.size.value, 0x388);
-    assert_eq!(data.size.offset, 0x00013884);
+    assert_eq!(data.size.offset, FileOffset(0x00013884));
     assert_eq!(data.code_page.value, 0x0);
-    assert_eq!(data.code_page.offset, 0x00013888);
+    assert_eq!(data.code_page.offset, FileOffset(0x00013888));
     assert_eq!(data.reserved.value, 0);
-    assert_eq!(data.reserved.offset, 0x0001388c);
+    assert_eq!(data.reserved.offset, FileOffset(0x0001388c));
 }
 
 #[test]
@@ -75,13 +75,26 @@ fn load_data() {
     let mut reader = FragmentReader::new(RAW_BYTES.to_vec(), SECTION_OFFSET);
     data.load_data(&get_rsrc_section(), &mut reader).unwrap();
 
-    assert_eq!(data.value.offset, 0x000138a0);
-    assert_eq!(data.value.rva, data.rva.value as u64);
+    assert_eq!(data.value.offset, FileOffset(0x000138a0));
+    assert_eq!(data.value.rva, Rva(data.rva.value));
     
     let value16 = &data.value.value[0..16];
     assert_eq!(value16, data_start);
 }
 
+#[test]
+fn load_data_section_near_u32_max() {
+    let mut section = get_rsrc_section();
+    section.virtual_address.value = 0xFFFF_F000;
+    section.virtual_size.value = 0x2000;
+
+    let mut data = ResourceData::new(Vec::new(), 0);
+    data.rva.value = 0x1000;
+    let mut reader = FragmentReader::new(RAW_BYTES.to_vec(), SECTION_OFFSET);
+    let err = data.load_data(&section, &mut reader).unwrap_err();
+    assert!(matches!(err.root(), PeError::BeyondRange { end: 0x1_0000_1000, .. }));
+}
+
 #[test]
 fn rdata_fix_rvas() {
     let pos = 0x090;
@@ -91,10 +104,10 @@ fn rdata_fix_rvas() {
 
     data.fix_rvas(&sections).unwrap();
 
-    assert_eq!(data.rva.rva, 0x00018090);
-    assert_eq!(data.size.rva, 0x00018094);
-    assert_eq!(data.code_page.rva, 0x00018098);
-    assert_eq!(data.reserved.rva, 0x0001809c);
+    assert_eq!(data.rva.rva, Rva(0x00018090));
+    assert_eq!(data.size.rva, Rva(0x00018094));
+    assert_eq!(data.code_page.rva, Rva(0x00018098));
+    assert_eq!(data.reserved.rva, Rva(0x0001809c));
 }
 
 #[test]
@@ -106,11 +119,11 @@ fn parse_rsrc_entry() {
 
     assert_eq!(entry.is_string, false);
     assert_eq!(entry.is_data, false);
-    assert_eq!(entry.id, ResourceType::VERSION);
+    assert_eq!(entry.resource_type(), Some(ResourceType::VERSION));
     assert_eq!(entry.data_offset.value, 0x80000020);
     assert_eq!(entry.name_offset.value, 0x00000010);
-    assert_eq!(entry.name_offset.offset, 0x00013810);
-    assert_eq!(entry.data_offset.offset, 0x00013814)
+    assert_eq!(entry.name_offset.offset, FileOffset(0x00013810));
+    assert_eq!(entry.data_offset.offset, FileOffset(0x00013814))
 }
 
 #[test]
@@ -122,7 +135,7 @@ fn parse_rsrc_entry_with_data() {
 
     assert_eq!(entry.is_string, false);
     assert_eq!(entry.is_data, true);
-    assert_eq!(entry.id, ResourceType::UNKNOWN(1033));
+    assert_eq!(entry.id, Some(1033));
 
     let mut reader = FragmentReader::new(RAW_BYTES.to_vec(), SECTION_OFFSET);
     entry.parse_rsrc(&get_rsrc_section(), &mut reader).unwrap();
@@ -145,19 +158,19 @@ fn rsrc_entry_fix_rvas() {
 
     let mut entry = ResourceEntry::parse_bytes(bytes.to_vec(), SECTION_OFFSET + pos).unwrap();
     let sections = parse_sections(&RAW_SECTIONS, 6, RAW_SECTION_OFFSET).unwrap();
-    let rsrc_section = section_by_name(&sections, ".rsrc".into()).unwrap().unwrap();
+    let rsrc_section = section_by_name(&sections, ".rsrc").unwrap();
     entry.parse_rsrc(&rsrc_section, &mut reader).unwrap();
     
     entry.fix_rvas(&sections).unwrap();
 
-    assert_eq!(entry.name_offset.rva, 0x00018078);
-    assert_eq!(entry.data_offset.rva, 0x0001807c);
+    assert_eq!(entry.name_offset.rva, Rva(0x00018078));
+    assert_eq!(entry.data_offset.rva, Rva(0x0001807c));
 
     if let ResourceNode::Data(data) = &entry.data {
-        assert_eq!(data.rva.rva, 0x00018090);
-        assert_eq!(data.size.rva, 0x00018094);
-        assert_eq!(data.code_page.rva, 0x00018098);
-        assert_eq!(data.reserved.rva, 0x0001809c);
+        assert_eq!(data.rva.rva, Rva(0x00018090));
+        assert_eq!(data.size.rva, Rva(0x00018094));
+        assert_eq!(data.code_page.rva, Rva(0x00018098));
+        assert_eq!(data.reserved.rva, Rva(0x0001809c));
     }
 }
 
@@ -173,17 +186,17 @@ fn parse_rsrc_tree() {
 
     //1st tree
     let e1 = &mut rsrc_tbl.entries[0];
-    assert_eq!(e1.id, ResourceType::VERSION);
+    assert_eq!(e1.resource_type(), Some(ResourceType::VERSION));
     if let ResourceNode::Dir(dir) = &mut e1.data {
         assert_eq!(dir.id_entry_count.value, 1);
         assert_eq!(dir.entries.len(), 1);
         let e = &mut dir.entries[0];
-        assert_eq!(e.id, ResourceType::CURSOR);
+        assert_eq!(e.id, Some(1));
         if let ResourceNode::Dir(dir) = &mut e.data {
             assert_eq!(dir.id_entry_count.value, 1);
             assert_eq!(dir.entries.len(), 1);
             let e = &mut dir.entries[0];
-            assert_eq!(e.id, ResourceType::UNKNOWN(1033));
+            assert_eq!(e.id, Some(1033));
             if let ResourceNode::Data(data) = &mut e.data {
                 data.load_data(&section, &mut reader).unwrap();
                 assert_eq!(data.value.value.len(), data.size.value as usize);
@@ -205,17 +218,17 @@ fn parse_rsrc_tree() {
 
     //2nd tree
     let e2 = &mut rsrc_tbl.entries[1];
-    assert_eq!(e2.id, ResourceType::MANIFEST);
+    assert_eq!(e2.resource_type(), Some(ResourceType::MANIFEST));
     if let ResourceNode::Dir(dir) = &mut e2.data {
         assert_eq!(dir.id_entry_count.value, 1);
         assert_eq!(dir.entries.len(), 1);
         let e = &mut dir.entries[0];
-        assert_eq!(e.id, ResourceType::BITMAP);
+        assert_eq!(e.id, Some(2));
         if let ResourceNode::Dir(dir) = &mut e.data {
             assert_eq!(dir.id_entry_count.value, 1);
             assert_eq!(dir.entries.len(), 1);
             let e = &mut dir.entries[0];
-            assert_eq!(e.id, ResourceType::UNKNOWN(1033));
+            assert_eq!(e.id, Some(1033));
             if let ResourceNode::Data(data) = &mut e.data {
                 data.load_data(&section, &mut reader).unwrap();
                 assert_eq!(data.value.value.len(), data.size.value as usize);
@@ -236,6 +249,27 @@ fn parse_rsrc_tree() {
     }
 }
 
+#[test]
+fn find_resource_by_type_name_lang() {
+    let mut reader = FragmentReader::new(RAW_BYTES.to_vec(), SECTION_OFFSET);
+    let mut rsrc_tbl = ResourceDirectory::parse_bytes(RAW_BYTES.to_vec(), SECTION_OFFSET).unwrap();
+    rsrc_tbl.parse_rsrc(&get_rsrc_section(), &mut reader).unwrap();
+
+    let data = rsrc_tbl.find_resource(ResourceType::VERSION, 1, Some(1033)).unwrap();
+    assert_eq!(data.rva.value, 0x000180a0);
+    assert_eq!(data.size.value, 0x388);
+
+    let data = rsrc_tbl.find_resource(24, 2, None).unwrap();
+    assert_eq!(data.rva.value, 0x00018428);
+
+    assert!(rsrc_tbl.find_resource(ResourceType::VERSION, 1, Some(1031)).is_none());
+    assert!(rsrc_tbl.find_resource(ResourceType::VERSION, 2, None).is_none());
+    assert!(rsrc_tbl.find_resource(ResourceType::ICON, 1, None).is_none());
+    assert!(rsrc_tbl.find_resource("VERSION", 1, None).is_none());
+    assert_eq!(u32::from(ResourceType::UNKNOWN(1033)), 1033);
+    assert_eq!(u32::from(ResourceType::MANIFEST), 24);
+}
+
 #[test]
 fn print_tree() {
     let mut reader = FragmentReader::new(RAW_BYTES.to_vec(), SECTION_OFFSET);
@@ -250,6 +284,113 @@ fn print_tree() {
     println!("{rsrc_buf}");
 }
 
+/// Builds a tree where each directory (at `0x20 * n`) has one subdirectory entry pointing to `next(n)`.
+fn crafted_tree(dirs: u32, next: impl Fn(u32) -> u32) -> Vec<u8> {
+    let mut bytes = vec![0u8; SECTION_RAW_SIZE as usize];
+    for n in 0..dirs {
+        let at = (n * 0x20) as usize;
+        bytes[at + 14] = 1; //id_entry_count
+        bytes[at + 16..at + 20].copy_from_slice(&1u32.to_le_bytes());
+        bytes[at + 20..at + 24].copy_from_slice(&(0x8000_0000 | (next(n) * 0x20)).to_le_bytes());
+    }
+    bytes
+}
+
+#[test]
+fn rsrc_loop() {
+    //0 -> 1 -> 2 -> 1
+    let bytes = crafted_tree(3, |n| if n == 2 { 1 } else { n + 1 });
+    let mut reader = FragmentReader::new(bytes.clone(), SECTION_OFFSET);
+    let mut root = ResourceDirectory::parse_bytes(bytes, SECTION_OFFSET).unwrap();
+
+    let err = root.parse_rsrc(&get_rsrc_section(), &mut reader).unwrap_err();
+    assert!(matches!(err.root(), PeError::ResourceLoop(offset) if *offset == SECTION_OFFSET + 0x20), "{err}");
+}
+
+#[test]
+fn rsrc_shared_directory() {
+    //Both entries of the root point to the empty directory at 0x40.
+    let mut bytes = vec![0u8; SECTION_RAW_SIZE as usize];
+    bytes[14] = 2; //id_entry_count
+    for (at, id) in [(16, 1u32), (24, 2)] {
+        bytes[at..at + 4].copy_from_slice(&id.to_le_bytes());
+        bytes[at + 4..at + 8].copy_from_slice(&0x8000_0040u32.to_le_bytes());
+    }
+    let mut reader = FragmentReader::new(bytes.clone(), SECTION_OFFSET);
+    let mut root = ResourceDirectory::parse_bytes(bytes, SECTION_OFFSET).unwrap();
+
+    root.parse_rsrc(&get_rsrc_section(), &mut reader).unwrap();
+    assert_eq!(root.entries.len(), 2);
+    for entry in &root.entries {
+        assert!(matches!(&entry.data, ResourceNode::Dir(dir) if dir.charactristics.offset == FileOffset(SECTION_OFFSET + 0x40)));
+    }
+}
+
+#[test]
+fn rsrc_too_deep() {
+    let bytes = crafted_tree(8, |n| n + 1);
+    let mut reader = FragmentReader::new(bytes.clone(), SECTION_OFFSET);
+    let mut root = ResourceDirectory::parse_bytes(bytes, SECTION_OFFSET).unwrap();
+
+    let err = root.parse_rsrc_with_limits(&get_rsrc_section(), &mut reader, 4, 100).unwrap_err();
+    assert!(matches!(err.root(), PeError::ResourceTooDeep { depth: 5, max: 4, .. }), "{err}");
+}
+
+#[test]
+fn rsrc_entry_count_caps() {
+    let mut bytes = vec![0u8; SECTION_RAW_SIZE as usize];
+    bytes[14..16].copy_from_slice(&0xFFFFu16.to_le_bytes());
+    let mut reader = FragmentReader::new(bytes.clone(), SECTION_OFFSET);
+    let mut root = ResourceDirectory::parse_bytes(bytes, SECTION_OFFSET).unwrap();
+    let err = root.parse_rsrc(&get_rsrc_section(), &mut reader).unwrap_err();
+    assert!(matches!(err.root(), PeError::InvalidHeader { .. }), "{err}");
+
+    let mut reader = FragmentReader::new(RAW_BYTES.to_vec(), SECTION_OFFSET);
+    let mut root = ResourceDirectory::parse_bytes(RAW_BYTES[..DIR_LENGTH as usize].to_vec(), SECTION_OFFSET).unwrap();
+    let err = root.parse_rsrc_with_limits(&get_rsrc_section(), &mut reader, 16, 4).unwrap_err();
+    assert!(matches!(err.root(), PeError::InvalidHeader { .. }), "{err}");
+}
+
+#[test]
+fn named_entry_keeps_subtree() {
+    //root: "MUI" -> dir at 0x20: 1033 -> data at 0x40
+    let mut bytes = vec![0u8; SECTION_RAW_SIZE as usize];
+    bytes[12] = 1; //named_entry_count
+    bytes[16..20].copy_from_slice(&0x8000_0060u32.to_le_bytes());
+    bytes[20..24].copy_from_slice(&0x8000_0020u32.to_le_bytes());
+    bytes[0x2e] = 1; //id_entry_count
+    bytes[0x30..0x34].copy_from_slice(&1033u32.to_le_bytes());
+    bytes[0x34..0x38].copy_from_slice(&0x40u32.to_le_bytes());
+    bytes[0x40..0x44].copy_from_slice(&0x18100u32.to_le_bytes());
+    bytes[0x44..0x48].copy_from_slice(&4u32.to_le_bytes());
+    bytes[0x60..0x68].copy_from_slice(&[3, 0, b'M', 0, b'U', 0, b'I', 0]);
+
+    let mut reader = FragmentReader::new(bytes.clone(), SECTION_OFFSET);
+    let mut root = ResourceDirectory::parse_bytes(bytes, SECTION_OFFSET).unwrap();
+    root.parse_rsrc(&get_rsrc_section(), &mut reader).unwrap();
+
+    let entry = &root.entries[0];
+    assert!(entry.is_string);
+    assert_eq!(entry.id, None);
+    assert_eq!(entry.resource_type(), None);
+    let name = entry.name.as_ref().unwrap();
+    assert_eq!(name.value.value, "MUI");
+    assert_eq!(name.length.value, 3);
+    assert_eq!(name.value.offset, FileOffset(SECTION_OFFSET + 0x62));
+    assert_eq!(entry.to_string(), "{ Name: \"MUI\", IsData: false, NameOffset: 80000060, DataOffset: 80000020 }");
+
+    let ResourceNode::Dir(dir) = &entry.data else {
+        panic!("Unexpected type. DIR was expected; Found: {:?}", &entry.data);
+    };
+    assert_eq!(dir.entries[0].id, Some(1033));
+    assert!(dir.entries[0].name.is_none());
+    assert!(matches!(&dir.entries[0].data, ResourceNode::Data(data) if data.size.value == 4));
+
+    //Named entries are matched case insensitively.
+    assert!(entry.matches(&"mui".into()));
+    assert!(!entry.matches(&ResourceId::Id(0)));
+}
+
 const SECTION_VA: u64 = 0x00018000;
 const SECTION_OFFSET: u64 = 0x00013800;
 const SECTION_RAW_SIZE: u64 = 0x00000600;
@@ -384,3 +525,39 @@ const RAW_SECTIONS: [u8; 240] = [
 ];
 
 const RAW_SECTION_OFFSET: u64 = 0x200;
+
+#[test]
+fn insert_keeps_entries_sorted() {
+    let mut rsrc = ResourceDirectory::default();
+    rsrc.insert(ResourceType::RC_DATA, 9, 0, vec![1]);
+    rsrc.insert(ResourceType::RC_DATA, "beta", 0, vec![2]);
+    rsrc.insert(ResourceType::RC_DATA, 2, 0, vec![3]);
+    rsrc.insert(ResourceType::RC_DATA, "ALPHA", 0, vec![4]);
+    rsrc.insert(ResourceType::ICON, 1, 0x409, vec![5]);
+    rsrc.insert(ResourceType::RC_DATA, "Beta", 0, vec![6]);
+
+    assert_eq!(rsrc.entries.iter().map(|e| e.key()).collect::<Vec<_>>(), vec![ResourceId::Id(3), ResourceId::Id(10)]);
+    let ResourceNode::Dir(names) = &rsrc.entries[1].data else { panic!("RC_DATA is not a directory") };
+    let keys: Vec<_> = names.entries.iter().map(|e| e.key().to_string()).collect();
+    assert_eq!(keys, vec!["ALPHA", "beta", "#2", "#9"]);
+    assert_eq!((names.named_entry_count.value, names.id_entry_count.value), (2, 2));
+    assert_eq!(rsrc.find_resource(ResourceType::RC_DATA, "BETA", None).unwrap().value.value, vec![6]);
+
+    assert!(rsrc.remove(ResourceType::ICON, 1, None));
+    assert_eq!(rsrc.id_entry_count.value, 1);
+}
+
+#[test]
+fn tree_to_bytes() {
+    let mut rsrc = ResourceDirectory::default();
+    rsrc.insert(ResourceType::RC_DATA, "AB", 0, vec![1, 2, 3]);
+    let bytes = rsrc.to_bytes(0x3000);
+
+    //Three directories of one entry, the name padded to 8, a data entry, the data.
+    assert_eq!(bytes.len(), 3 * 24 + 8 + 16 + 8);
+    assert_eq!(&bytes[16..24], &[10, 0, 0, 0, 24, 0, 0, 0x80]);
+    assert_eq!(&bytes[24 + 16..24 + 24], &[72, 0, 0, 0x80, 48, 0, 0, 0x80]);
+    assert_eq!(&bytes[72..78], &[2, 0, b'A', 0, b'B', 0]);
+    assert_eq!(&bytes[80..88], &[0x60, 0x30, 0, 0, 3, 0, 0, 0]);
+    assert_eq!(&bytes[96..99], &[1, 2, 3]);
+}
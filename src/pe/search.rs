@@ -0,0 +1,168 @@
+//! Byte patterns and references to addresses in the sections of an image, for signature and
+//! cross reference tools.
+//!
+//! Patterns take a mask of the bits that must match, so bytes or nibbles can be wildcards;
+//! `parse_pattern` reads them from text like `48 8B ?? ?5`.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use super::{optional::ImageType, relocs::RelocType, PeImage, Result};
+
+/// A match of `PeImage::find_bytes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ByteMatch {
+    /// File offset of the first byte.
+    pub offset: u64,
+    /// `None` in raw data past the virtual size of the section.
+    pub rva: Option<u32>,
+}
+
+impl Display for ByteMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.rva {
+            Some(rva) => write!(f, "{:08x} {rva:08x}", self.offset),
+            None => write!(f, "{:08x} --------", self.offset),
+        }
+    }
+}
+
+/// An absolute address of a target, found by `PeImage::find_refs_to_rva`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Reference {
+    /// File offset of the address.
+    pub offset: u64,
+    pub rva: Option<u32>,
+    /// Bytes of the address: 4 in PE32 and 8 in PE32+ images.
+    pub size: u8,
+    /// Whether a base relocation patches the address. If the image has relocations, addresses
+    /// without one are likely other data with the same value.
+    pub relocated: bool,
+}
+
+/// Pattern and mask of hex bytes separated by spaces, e.g. `48 8B ?? ?5`; `?` is a wildcard
+/// nibble. `None` if a byte is not two hex digits or wildcards.
+pub fn parse_pattern(text: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+    let nibble = |c: char| match c {
+        '?' => Some((0, 0)),
+        c => c.to_digit(16).map(|d| (d as u8, 0xF)),
+    };
+    text.split_whitespace()
+        .map(|byte| {
+            let mut chars = byte.chars();
+            let (hi, lo) = (nibble(chars.next()?)?, nibble(chars.next()?)?);
+            if chars.next().is_some() {
+                return None;
+            }
+            Some((hi.0 << 4 | lo.0, hi.1 << 4 | lo.1))
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(|bytes| bytes.into_iter().unzip())
+}
+
+/// Positions in `bytes` where `pattern` matches under `mask`, overlapping ones included. See
+/// `PeImage::find_bytes`.
+pub fn find_in<'a>(bytes: &'a [u8], pattern: &'a [u8], mask: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    let matches = move |window: &[u8]| {
+        window.iter().zip(pattern).enumerate().all(|(i, (b, p))| {
+            let m = mask.get(i).copied().unwrap_or(0xFF);
+            b & m == p & m
+        })
+    };
+    let windows = match pattern.is_empty() {
+        true => [].windows(1),
+        false => bytes.windows(pattern.len()),
+    };
+    windows.enumerate().filter(move |(_, window)| matches(window)).map(|(i, _)| i)
+}
+
+impl PeImage {
+    /// Matches of `pattern` in the raw data of every section. Bytes of `mask` select the bits
+    /// of `pattern` that must match, 0x00 being a wildcard byte; bytes of `pattern` past the end
+    /// of `mask`, e.g. all with an empty mask, must match exactly.
+    pub fn find_bytes(&mut self, pattern: &[u8], mask: &[u8]) -> Result<Vec<ByteMatch>> {
+        let mut found = Vec::new();
+        for index in 0..self.sections.value.len() {
+            let base = self.sections.value[index].value.raw_data_ptr.value as u64;
+            let bytes = self.section_data(index)?;
+            found.extend(find_in(&bytes, pattern, mask).map(|i| base + i as u64));
+        }
+
+        //Raw data of sections may overlap.
+        found.sort_unstable();
+        found.dedup();
+        Ok(found.into_iter().map(|offset| ByteMatch { offset, rva: self.offset_to_rva(offset) }).collect())
+    }
+
+    /// Absolute addresses of `rva` at the preferred image base in the raw data of sections,
+    /// e.g. pointers in data and operands of x86 instructions. Addresses patched by base
+    /// relocations are marked; see `Reference::relocated`.
+    pub fn find_refs_to_rva(&mut self, rva: u32) -> Result<Vec<Reference>> {
+        let va = self.optional.value.image_base().wrapping_add(rva.into());
+        let needle = match self.optional.value.get_image_type() {
+            ImageType::PE64 => va.to_le_bytes().to_vec(),
+            _ => (va as u32).to_le_bytes().to_vec(),
+        };
+        let size = needle.len() as u8;
+
+        let found = self.find_bytes(&needle, &[])?;
+        Ok(found.into_iter()
+            .map(|m| {
+                let relocated = m.rva.is_some_and(|rva| {
+                    self.relocations.value.relocs_in_range(rva, rva.saturating_add(size.into()))
+                        .any(|(at, rtype)| at == rva && rtype != RelocType::ABSOLUTE && rtype.patch_size() == u32::from(size))
+                });
+                Reference { offset: m.offset, rva: m.rva, size, relocated }
+            })
+            .collect())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::pe::{builder::PeBuilder, file::MachineType, PeImage};
+
+    use super::{find_in, parse_pattern};
+
+    #[test]
+    fn patterns_with_wildcards() {
+        assert_eq!(parse_pattern("48 8b ?? ?5"), Some((vec![0x48, 0x8B, 0x00, 0x05], vec![0xFF, 0xFF, 0x00, 0x0F])));
+        assert_eq!(parse_pattern(""), Some((vec![], vec![])));
+        assert!(parse_pattern("4").is_none());
+        assert!(parse_pattern("123").is_none());
+        assert!(parse_pattern("zz").is_none());
+
+        let bytes = [0x48, 0x8B, 0x01, 0x15, 0x48, 0x8B, 0x02, 0x25, 0x48];
+        let (pattern, mask) = parse_pattern("48 8B ?? ?5").unwrap();
+        assert_eq!(find_in(&bytes, &pattern, &mask).collect::<Vec<_>>(), vec![0, 4]);
+        assert_eq!(find_in(&bytes, &[0x48], &[]).collect::<Vec<_>>(), vec![0, 4, 8]);
+        assert_eq!(find_in(&[0xAA; 4], &[0xAA, 0xAA], &[]).count(), 3);
+        assert_eq!(find_in(&bytes, &[], &[]).count(), 0);
+        assert_eq!(find_in(&[0x48], &[0x48, 0x8B], &[]).count(), 0);
+    }
+
+    #[test]
+    fn find_code_and_refs() {
+        //mov eax, [0x401000 + 0x10]; ret
+        let code = vec![0xA1, 0x10, 0x10, 0x40, 0x00, 0xC3];
+        let bytes = PeBuilder::new(MachineType::I386).with_code(code, 0).build().unwrap();
+        let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        let text = pe.sections.value[0].value.clone();
+        assert_eq!(pe.optional.value.image_base(), 0x400000);
+        assert_eq!(text.virtual_address.value, 0x1000);
+
+        let found = pe.find_bytes(&[0xA1, 0, 0, 0x40, 0, 0xC3], &[0xFF, 0, 0, 0xFF, 0xFF, 0xFF]).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].offset, text.raw_data_ptr.value as u64);
+        assert_eq!(found[0].rva, Some(0x1000));
+
+        let refs = pe.find_refs_to_rva(0x1010).unwrap();
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs[0].rva, Some(0x1001));
+        assert_eq!(refs[0].size, 4);
+        assert!(!refs[0].relocated);
+        assert!(pe.find_refs_to_rva(0x2000).unwrap().is_empty());
+    }
+}
@@ -5,12 +5,16 @@ use bitflags::bitflags;
 use byteorder::{ReadBytesExt, LittleEndian};
 use serde::Serialize;
 
-use crate::{new_header_field, types::{Header, HeaderField}, utils::flags_to_str};
+use crate::{new_header_field, types::{FileOffset, Header, HeaderField}, utils::flags_to_str};
 
 use super::{optional::{DataDirectory, DirectoryType}, PeError};
 
 pub const HEADER_LENGTH: u64 = 40;
 
+/// Default max number of sections. Windows XP loaded at most 96 sections, later versions load
+/// up to the 65535 `NumberOfSections` can hold; linkers rarely produce more than a few hundred.
+pub const DEFAULT_MAX_SECTIONS: u16 = 0x2000;
+
 bitflags! {
     #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy, Serialize)]
     pub struct Flags: u32 {
@@ -40,13 +44,86 @@ bitflags! {
 }
 
 
+impl Flags {
+    #[inline]
+    pub fn is_code(&self) -> bool {
+        self.contains(Self::CODE)
+    }
+
+    #[inline]
+    pub fn is_executable(&self) -> bool {
+        self.contains(Self::MEM_EXECUTE)
+    }
+
+    #[inline]
+    pub fn is_readable(&self) -> bool {
+        self.contains(Self::MEM_READ)
+    }
+
+    #[inline]
+    pub fn is_writable(&self) -> bool {
+        self.contains(Self::MEM_WRITE)
+    }
+
+    #[inline]
+    pub fn is_shared(&self) -> bool {
+        self.contains(Self::MEM_SHARED)
+    }
+
+    #[inline]
+    pub fn is_discardable(&self) -> bool {
+        self.contains(Self::MEM_DISCARDABLE)
+    }
+}
+
 impl Display for Flags {
+    /// Names of set flags, then any unknown bits in hex.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", flags_to_str(self))
+        let mut names = vec![flags_to_str(self)];
+        let unknown = self.bits() & !Self::all().bits();
+        if unknown != 0 {
+            names.push(format!("{unknown:#010x}"));
+        }
+        names.retain(|name| !name.is_empty());
+        write!(f, "{}", names.join(" | "))
     }
 }
 
-#[derive(Debug, Default)]
+/// Bits of characteristics holding `IMAGE_SCN_ALIGN_*`; a number, not flags.
+pub const ALIGN_MASK: u32 = 0x00F00000;
+
+/// Alignment of section data (`IMAGE_SCN_ALIGN_*`), from 1 to 8192 bytes. Only valid for object files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Alignment(u8);
+
+impl Alignment {
+    /// Decode the alignment nibble of section `characteristics`; `None` if not set or invalid.
+    pub fn from_characteristics(characteristics: u32) -> Option<Self> {
+        match (characteristics & ALIGN_MASK) >> 20 {
+            n @ 1..=14 => Some(Self(n as u8)),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn bytes(&self) -> u32 {
+        1 << (self.0 - 1)
+    }
+}
+
+impl Display for Alignment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ALIGN_{}BYTES", self.bytes())
+    }
+}
+
+impl Serialize for Alignment {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.bytes())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct SectionHeader {
     pub name: HeaderField<[u8; 8]>,
     pub virtual_size: HeaderField<u32>, //Not using Misc.PhysicalAddress
@@ -61,52 +138,86 @@ pub struct SectionHeader {
 }
 
 impl SectionHeader {
+    /// Flags of characteristics, without the alignment. `None` if there are unknown bits.
     pub fn flags(&self) -> Option<Flags> {
-        Flags::from_bits(self.charactristics.value)
+        Flags::from_bits(self.charactristics.value & !ALIGN_MASK)
     }
 
+    #[inline]
+    pub fn alignment(&self) -> Option<Alignment> {
+        Alignment::from_characteristics(self.charactristics.value)
+    }
+
+    /// End of the virtual range, included; computed in u64, so it does not wrap at `u32::MAX`.
     pub fn contains_rva(&self, rva: u32) -> bool {
-        let end_va = self.virtual_address.value + self.virtual_size.value;
-        rva >= self.virtual_address.value && rva <= end_va
+        let start = self.virtual_address.value as u64;
+        let end = start + self.virtual_size.value as u64;
+        (start..=end).contains(&(rva as u64))
     }
 
     pub fn contains_va(&self, va: u64, base: u64) -> bool {
-        let rva = va - base;
-        self.contains_rva(rva as u32)
+        va.checked_sub(base)
+            .and_then(|rva| u32::try_from(rva).ok())
+            .is_some_and(|rva| self.contains_rva(rva))
     }
 
+    /// `None` if `rva` is not in the section or its offset does not fit in 32 bits.
     pub fn rva_to_offset(&self, rva: u32) -> Option<u32> {
         if !self.contains_rva(rva) {
             return None; 
         }
 
-        let offset = rva - self.virtual_address.value + self.raw_data_ptr.value;
-        Some(offset)
+        let offset = (rva - self.virtual_address.value) as u64 + self.raw_data_ptr.value as u64;
+        u32::try_from(offset).ok()
     }
 
+    /// `None` if `offset` is not in the raw data of the section or its rva does not fit in 32 bits.
     pub fn offset_to_rva(&self, offset: u32) -> Option<u32> {        
         if self.contains_offset(offset) {
             let section_offset = offset - self.raw_data_ptr.value;
-            let rva = self.virtual_address.value + section_offset;
-            return Some(rva);
+            let rva = self.virtual_address.value as u64 + section_offset as u64;
+            return u32::try_from(rva).ok();
         }
         None
     }
 
     pub fn contains_offset(&self, offset: u32) -> bool {
-        if self.raw_data_ptr.value <= offset{
-            if self.raw_data_ptr.value + self.sizeof_raw_data.value > offset{
-                return true;
-            }
-        }
-        false
+        let start = self.raw_data_ptr.value as u64;
+        let end = start + self.sizeof_raw_data.value as u64;
+        (start..end).contains(&(offset as u64))
     }
 
+    /// Name as UTF-8; fails for names with other bytes, which are legal. See `name_bytes` and `name_lossy`.
     pub fn name_str(&self) -> std::result::Result<String, FromUtf8Error> {
         let str = String::from_utf8(self.name.value.to_vec())?;
         Ok(str.trim_matches(char::from(0)).to_string())
     }
 
+    /// Name as stored, without the trailing NUL padding.
+    pub fn name_bytes(&self) -> Vec<u8> {
+        let name = &self.name.value;
+        let len = name.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+        name[..len].to_vec()
+    }
+
+    /// Name for display; bytes other than printable ASCII are escaped as `\xNN`, and `\` as `\\`.
+    pub fn name_lossy(&self) -> String {
+        let mut name = String::new();
+        for b in self.name_bytes() {
+            match b {
+                b'\\' => name.push_str("\\\\"),
+                b if !is_escaped(b) => name.push(b as char),
+                b => name.push_str(&format!("\\x{b:02x}")),
+            }
+        }
+        name
+    }
+
+    /// `true` if `name_lossy` escapes any byte of the name.
+    pub fn has_escaped_name(&self) -> bool {
+        self.name_bytes().into_iter().any(|b| b == b'\\' || is_escaped(b))
+    }
+
     pub fn directories(&self, dirs: &Vec<HeaderField<DataDirectory>>) -> Vec<DirectoryType> {
         let mut dtypes = Vec::<DirectoryType>::new();
         for dir in dirs {
@@ -119,8 +230,13 @@ impl SectionHeader {
     }
 }
 
+/// Whether `name_lossy` writes byte `b` as `\xNN`.
+fn is_escaped(b: u8) -> bool {
+    !(b.is_ascii_graphic() || b == b' ')
+}
+
 impl Header for SectionHeader {
-    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::Result<Self> {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> {
         let bytes_len = bytes.len() as u64;
 
         if bytes_len < HEADER_LENGTH {
@@ -134,7 +250,7 @@ impl Header for SectionHeader {
         let mut offset = pos;
 
         let mut name: [u8; 8] = [0; 8];
-        cursor.read(&mut name)?;
+        cursor.read_exact(&mut name)?;
         hdr.name = new_header_field!(name, offset);
         hdr.virtual_size = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
         hdr.virtual_address = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
@@ -160,15 +276,54 @@ impl Header for SectionHeader {
 
 impl Display for SectionHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{{ {}, RVA: {:#08x}, Size: {:#08x}, RawAddr: {:#08x}, RawSize: {:#08x}, Flags: {} }}", 
-            self.name_str().unwrap_or("Err".into()), self.virtual_address.value, self.virtual_size.value, 
-            self.raw_data_ptr.value, self.sizeof_raw_data.value, self.flags().unwrap_or(Flags::UNKNOWN))
+        write!(f, "{{ {}, RVA: {:#08x}, Size: {:#08x}, RawAddr: {:#08x}, RawSize: {:#08x}, Flags: {}", 
+            self.name_lossy(), self.virtual_address.value, self.virtual_size.value, 
+            self.raw_data_ptr.value, self.sizeof_raw_data.value, Flags::from_bits_retain(self.charactristics.value & !ALIGN_MASK))?;
+        if let Some(alignment) = self.alignment() {
+            write!(f, ", Alignment: {}", alignment.bytes())?;
+        }
+        write!(f, " }}")
     }
 }
 
 pub type SectionTable = Vec<HeaderField<SectionHeader>>;
 
-pub fn parse_sections(bytes: &[u8], count: u16, pos: u64) -> crate::Result<SectionTable> {
+/// A section by its index in the section table or by its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionRef<'a> {
+    Index(usize),
+    /// Name as in the header, without padding; the first section of that name.
+    Name(&'a str),
+}
+
+impl From<usize> for SectionRef<'_> {
+    fn from(value: usize) -> Self {
+        Self::Index(value)
+    }
+}
+
+impl<'a> From<&'a str> for SectionRef<'a> {
+    fn from(value: &'a str) -> Self {
+        Self::Name(value)
+    }
+}
+
+/// Check `count` section headers at `offset` against `max` and against `end`, the size of the input.
+/// Fails with `InvalidSectionCount` before anything is allocated for the table.
+pub fn check_section_count(count: u16, offset: u64, max: u16, end: u64) -> crate::pe::Result<()> {
+    let reason = if count > max {
+        format!("more than allowed {max}")
+    }
+    else if offset.saturating_add(HEADER_LENGTH * count as u64) > end {
+        format!("only {} bytes remain in input", end.saturating_sub(offset))
+    }
+    else {
+        return Ok(());
+    };
+    Err(PeError::InvalidSectionCount { count, offset, reason })
+}
+
+pub fn parse_sections(bytes: &[u8], count: u16, pos: u64) -> crate::pe::Result<SectionTable> {
     let mut sections = Vec::with_capacity(count as usize);
     let bytes_len = bytes.len() as u64;
     let expected = HEADER_LENGTH * count as u64;
@@ -189,52 +344,216 @@ pub fn parse_sections(bytes: &[u8], count: u16, pos: u64) -> crate::Result<Secti
         offset += HEADER_LENGTH;
         slice_start = slice_end;
         slice_end += HEADER_LENGTH;
-        sections.push(HeaderField { value: section, offset: slice_start, rva: slice_start}); 
+        sections.push(HeaderField::at_offset(section, slice_start)); 
     }
     Ok(sections)
 }
 
-pub fn rva_to_offset(sections: &SectionTable, rva: u32) -> Option<u32> {
-    for s in sections {
-        if let Some(offset) = s.value.rva_to_offset(rva) {
-            return Some(offset);
-        }
+/// Lookup of the section containing an rva or a file offset.
+/// 
+/// Implemented for a plain `SectionTable` (linear scan) and for `IndexedSections`
+/// (binary search over a prebuilt `SectionMap`), so that parsers can take either.
+pub trait SectionLookup {
+    fn section_for_rva(&self, rva: u32) -> Option<&SectionHeader>;
+    fn section_for_offset(&self, offset: u32) -> Option<&SectionHeader>;
+
+    fn rva_to_offset(&self, rva: u32) -> Option<u32> {
+        self.section_for_rva(rva)?.rva_to_offset(rva)
+    }
+
+    fn offset_to_rva(&self, offset: u32) -> Option<u32> {
+        self.section_for_offset(offset)?.offset_to_rva(offset)
     }
-    None
 }
 
-pub fn rva_to_section(sections: &SectionTable, rva: u32) -> Option<&SectionHeader> {
-    for s in sections {
-        if s.value.contains_rva(rva) {
-            return Some(&s.value);    
-        }
+impl SectionLookup for SectionTable {
+    fn section_for_rva(&self, rva: u32) -> Option<&SectionHeader> {
+        self.iter().map(|s| &s.value).find(|s| s.contains_rva(rva))
+    }
+
+    fn section_for_offset(&self, offset: u32) -> Option<&SectionHeader> {
+        self.iter().map(|s| &s.value).find(|s| s.contains_offset(offset))
     }
-    None
 }
 
-pub fn offset_to_rva(sections: &SectionTable, offset: u32) -> Option<u32> {
-    for s in sections {
-        if let Some(rva) = s.value.offset_to_rva(offset) {
-            return Some(rva);
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Span {
+    start: u64,
+    end: u64, //inclusive
+    target: u64, //start of the same section in the other address space.
+    index: usize,
+    max_end: u64, //max `end` of all spans up to and including this one.
+}
+
+/// Sorted interval index over a `SectionTable`.
+/// 
+/// Spans are sorted by start, so a lookup is a binary search followed by a short
+/// backward walk for overlapping spans. Results are identical to the linear scan,
+/// including which section wins when sections overlap (the first one in the table).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SectionMap {
+    by_rva: Vec<Span>,
+    by_offset: Vec<Span>,
+}
+
+impl SectionMap {
+    pub fn new(sections: &SectionTable) -> Self {
+        let rva_spans = sections.iter().enumerate()
+            .map(|(index, s)| {
+                let start = s.value.virtual_address.value as u64;
+                (start, start + s.value.virtual_size.value as u64, s.value.raw_data_ptr.value as u64, index)
+            });
+
+        //`contains_offset` excludes the end, so empty raw ranges are left out.
+        let offset_spans = sections.iter().enumerate()
+            .filter(|(_, s)| s.value.sizeof_raw_data.value > 0)
+            .map(|(index, s)| {
+                let start = s.value.raw_data_ptr.value as u64;
+                (start, start + s.value.sizeof_raw_data.value as u64 - 1, s.value.virtual_address.value as u64, index)
+            });
+
+        Self {
+            by_rva: Self::build(rva_spans),
+            by_offset: Self::build(offset_spans),
         }
     }
-    None
-}
 
-pub fn section_by_name(sections: &SectionTable, name: String) -> crate::Result<Option<&SectionHeader>> {
-    for section in sections.iter() {
-        if section.value.name_str()? == name {
-            return Ok(Some(&section.value));
+    fn build(spans: impl Iterator<Item = (u64, u64, u64, usize)>) -> Vec<Span> {
+        let mut spans: Vec<Span> = spans
+            .map(|(start, end, target, index)| Span { start, end, target, index, max_end: end })
+            .collect();
+        spans.sort_by_key(|s| (s.start, s.index));
+
+        let mut max_end = 0;
+        for span in spans.iter_mut() {
+            max_end = max_end.max(span.end);
+            span.max_end = max_end;
         }
+        spans
+    }
+
+    fn find(spans: &[Span], value: u64) -> Option<&Span> {
+        let mut pos = spans.partition_point(|s| s.start <= value);
+        let mut found: Option<&Span> = None;
+
+        while pos > 0 {
+            pos -= 1;
+            let span = &spans[pos];
+            if span.max_end < value {
+                break;
+            }
+            if span.end >= value && found.is_none_or(|f| span.index < f.index) {
+                found = Some(span);
+            }
+        }
+        found
+    }
+
+    /// Index in the `SectionTable` of the section containing `rva`.
+    pub fn section_index_for_rva(&self, rva: u32) -> Option<usize> {
+        Self::find(&self.by_rva, rva.into()).map(|s| s.index)
     }
-    return Ok(None)
+
+    /// Index in the `SectionTable` of the section containing file `offset`.
+    pub fn section_index_for_offset(&self, offset: u32) -> Option<usize> {
+        Self::find(&self.by_offset, offset.into()).map(|s| s.index)
+    }
+
+    pub fn rva_to_offset(&self, rva: u32) -> Option<u32> {
+        let span = Self::find(&self.by_rva, rva.into())?;
+        u32::try_from(rva as u64 - span.start + span.target).ok()
+    }
+
+    pub fn offset_to_rva(&self, offset: u32) -> Option<u32> {
+        let span = Self::find(&self.by_offset, offset.into())?;
+        u32::try_from(offset as u64 - span.start + span.target).ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_rva.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_rva.is_empty()
+    }
+}
+
+/// A `SectionTable` paired with its `SectionMap`.
+/// The map must have been built from the same table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexedSections<'a> {
+    pub table: &'a SectionTable,
+    pub map: &'a SectionMap,
+}
+
+impl<'a> IndexedSections<'a> {
+    pub fn new(table: &'a SectionTable, map: &'a SectionMap) -> Self {
+        Self { table, map }
+    }
+
+    /// Same as `SectionLookup::section_for_rva`, but borrows from the table instead of `self`.
+    pub fn section_for_rva(&self, rva: u32) -> Option<&'a SectionHeader> {
+        let index = self.map.section_index_for_rva(rva)?;
+        self.table.get(index).map(|s| &s.value)
+    }
+
+    /// Same as `SectionLookup::section_for_offset`, but borrows from the table instead of `self`.
+    pub fn section_for_offset(&self, offset: u32) -> Option<&'a SectionHeader> {
+        let index = self.map.section_index_for_offset(offset)?;
+        self.table.get(index).map(|s| &s.value)
+    }
+}
+
+impl SectionLookup for IndexedSections<'_> {
+    fn section_for_rva(&self, rva: u32) -> Option<&SectionHeader> {
+        IndexedSections::section_for_rva(self, rva)
+    }
+
+    fn section_for_offset(&self, offset: u32) -> Option<&SectionHeader> {
+        IndexedSections::section_for_offset(self, offset)
+    }
+
+    fn rva_to_offset(&self, rva: u32) -> Option<u32> {
+        self.map.rva_to_offset(rva)
+    }
+
+    fn offset_to_rva(&self, offset: u32) -> Option<u32> {
+        self.map.offset_to_rva(offset)
+    }
+}
+
+pub fn rva_to_offset(sections: &(impl SectionLookup + ?Sized), rva: u32) -> Option<u32> {
+    sections.rva_to_offset(rva)
+}
+
+pub fn rva_to_section(sections: &(impl SectionLookup + ?Sized), rva: u32) -> Option<&SectionHeader> {
+    sections.section_for_rva(rva)
+}
+
+/// Rva of file `offset`; `None` for offsets beyond 32 bits, which no section can hold.
+pub fn offset_to_rva(sections: &(impl SectionLookup + ?Sized), offset: impl Into<FileOffset>) -> Option<u32> {
+    sections.offset_to_rva(u32::try_from(offset.into().0).ok()?)
+}
+
+/// First section named `name`, given as `&str` or as bytes without the NUL padding.
+pub fn section_by_name(sections: &SectionTable, name: impl AsRef<[u8]>) -> Option<&SectionHeader> {
+    sections_by_name(sections, name).next()
+}
+
+/// Every section named `name`, in table order; names are not unique, e.g. in packed images.
+pub fn sections_by_name(sections: &SectionTable, name: impl AsRef<[u8]>) -> impl Iterator<Item = &SectionHeader> {
+    sections.iter()
+        .map(|s| &s.value)
+        .filter(move |s| s.name_bytes() == name.as_ref())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{types::Header, pe::section::{rva_to_offset, offset_to_rva}};
+    use crate::{types::{FileOffset, Header, HeaderField}, pe::section::{rva_to_offset, offset_to_rva}};
 
-    use super::{parse_sections, section_by_name, Flags, SectionHeader, HEADER_LENGTH};
+    use crate::pe::PeError;
+
+    use super::{check_section_count, parse_sections, section_by_name, sections_by_name, Alignment, Flags, DEFAULT_MAX_SECTIONS, IndexedSections, SectionHeader, SectionLookup, SectionMap, SectionTable, HEADER_LENGTH};
 
     const RAW_BYTES: [u8; 240] = [
         0x2E, 0x74, 0x65, 0x78, 0x74, 0x00, 0x00, 0x00, 0xEB, 0xBB, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
@@ -260,23 +579,23 @@ mod tests {
         let sh = SectionHeader::parse_bytes(bytes.to_vec(), 0x208).unwrap();
         assert!(sh.is_valid());
         assert_eq!(sh.name_str().unwrap(), String::from(".text"));
-        assert_eq!(sh.name.offset, 0x208);
+        assert_eq!(sh.name.offset, FileOffset(0x208));
         assert_eq!(sh.virtual_size.value, 0xbbeb);
-        assert_eq!(sh.virtual_size.offset, 0x210);
+        assert_eq!(sh.virtual_size.offset, FileOffset(0x210));
         assert_eq!(sh.virtual_address.value, 0x00001000);
-        assert_eq!(sh.virtual_address.offset, 0x214);
+        assert_eq!(sh.virtual_address.offset, FileOffset(0x214));
         assert_eq!(sh.sizeof_raw_data.value, 0x0000bc00);
-        assert_eq!(sh.sizeof_raw_data.offset, 0x218);
+        assert_eq!(sh.sizeof_raw_data.offset, FileOffset(0x218));
         assert_eq!(sh.raw_data_ptr.value, 0x00000400);
-        assert_eq!(sh.raw_data_ptr.offset, 0x21c);
+        assert_eq!(sh.raw_data_ptr.offset, FileOffset(0x21c));
         assert_eq!(sh.relocs_ptr.value, 0);
-        assert_eq!(sh.relocs_ptr.offset, 0x220);
+        assert_eq!(sh.relocs_ptr.offset, FileOffset(0x220));
         assert_eq!(sh.line_num_ptr.value, 0);
-        assert_eq!(sh.line_num_ptr.offset, 0x224);
+        assert_eq!(sh.line_num_ptr.offset, FileOffset(0x224));
         assert_eq!(sh.relocs_count.value, 0);
-        assert_eq!(sh.relocs_count.offset, 0x228);
+        assert_eq!(sh.relocs_count.offset, FileOffset(0x228));
         assert_eq!(sh.line_num_count.value, 0);
-        assert_eq!(sh.line_num_count.offset, 0x22a);
+        assert_eq!(sh.line_num_count.offset, FileOffset(0x22a));
         assert_eq!(sh.flags().unwrap(), Flags::CODE | Flags::MEM_EXECUTE | Flags::MEM_READ);
     }
 
@@ -331,26 +650,180 @@ mod tests {
     fn section_from_name() {
         let sections = parse_sections(&RAW_BYTES, 6, 0x208).unwrap();
         
-        let sh = section_by_name(&sections, ".text".into()).unwrap().unwrap();
+        let sh = section_by_name(&sections, ".text").unwrap();
         
         assert_eq!(sh.name_str().unwrap(), String::from(".text"));
-        assert_eq!(sh.name.offset, 0x208);
+        assert_eq!(sh.name.offset, FileOffset(0x208));
         assert_eq!(sh.virtual_size.value, 0xbbeb);
-        assert_eq!(sh.virtual_size.offset, 0x210);
+        assert_eq!(sh.virtual_size.offset, FileOffset(0x210));
         assert_eq!(sh.virtual_address.value, 0x00001000);
-        assert_eq!(sh.virtual_address.offset, 0x214);
+        assert_eq!(sh.virtual_address.offset, FileOffset(0x214));
         assert_eq!(sh.sizeof_raw_data.value, 0x0000bc00);
-        assert_eq!(sh.sizeof_raw_data.offset, 0x218);
+        assert_eq!(sh.sizeof_raw_data.offset, FileOffset(0x218));
         assert_eq!(sh.raw_data_ptr.value, 0x00000400);
-        assert_eq!(sh.raw_data_ptr.offset, 0x21c);
+        assert_eq!(sh.raw_data_ptr.offset, FileOffset(0x21c));
         assert_eq!(sh.relocs_ptr.value, 0);
-        assert_eq!(sh.relocs_ptr.offset, 0x220);
+        assert_eq!(sh.relocs_ptr.offset, FileOffset(0x220));
         assert_eq!(sh.line_num_ptr.value, 0);
-        assert_eq!(sh.line_num_ptr.offset, 0x224);
+        assert_eq!(sh.line_num_ptr.offset, FileOffset(0x224));
         assert_eq!(sh.relocs_count.value, 0);
-        assert_eq!(sh.relocs_count.offset, 0x228);
+        assert_eq!(sh.relocs_count.offset, FileOffset(0x228));
         assert_eq!(sh.line_num_count.value, 0);
-        assert_eq!(sh.line_num_count.offset, 0x22a);
+        assert_eq!(sh.line_num_count.offset, FileOffset(0x22a));
+        assert_eq!(sh.flags().unwrap(), Flags::CODE | Flags::MEM_EXECUTE | Flags::MEM_READ);
+    }
+
+    #[test]
+    fn non_utf8_section_names() {
+        let make = |name: [u8; 8], va: u32| HeaderField {
+            value: SectionHeader {
+                name: HeaderField { value: name, ..Default::default() },
+                virtual_address: HeaderField { value: va, ..Default::default() },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let sections: SectionTable = vec![
+            make(*b"UPX0\0\0\0\0", 0x1000),
+            make([0xff, b'p', b'k', 0, 0, 0, 0, 0], 0x2000),
+            make(*b"UPX0\0\0\0\0", 0x3000),
+        ];
+
+        let packed = &sections[1].value;
+        assert!(packed.name_str().is_err());
+        assert_eq!(packed.name_bytes(), [0xff, b'p', b'k']);
+        assert_eq!(packed.name_lossy(), "\\xffpk");
+        assert!(packed.has_escaped_name());
+        assert!(packed.to_string().starts_with("{ \\xffpk,"));
+
+        let slash = make(*b"a\\x41\0\0\0", 0x4000);
+        assert_eq!(slash.value.name_lossy(), "a\\\\x41");
+        assert!(slash.value.has_escaped_name());
+        assert!(!sections[0].value.has_escaped_name());
+
+        assert_eq!(section_by_name(&sections, [0xff, b'p', b'k']).unwrap().virtual_address.value, 0x2000);
+        assert_eq!(section_by_name(&sections, "UPX0").unwrap().virtual_address.value, 0x1000);
+        assert_eq!(sections_by_name(&sections, b"UPX0").count(), 2);
+        assert!(section_by_name(&sections, "UPX").is_none());
+    }
+
+    #[test]
+    fn section_map_matches_linear_scan() {
+        let sections = parse_sections(&RAW_BYTES, 6, 0x208).unwrap();
+        let map = SectionMap::new(&sections);
+        let indexed = IndexedSections::new(&sections, &map);
+        assert_eq!(map.len(), 6);
+
+        let mut probes = vec![0u32, 0x209B, 0x149B, u32::MAX];
+        for sec in &sections {
+            let sh = &sec.value;
+            for base in [sh.virtual_address.value, sh.virtual_address.value + sh.virtual_size.value, sh.raw_data_ptr.value, sh.raw_data_ptr.value + sh.sizeof_raw_data.value] {
+                probes.extend([base.saturating_sub(1), base, base + 1]);
+            }
+        }
+
+        for probe in probes {
+            assert_eq!(indexed.rva_to_offset(probe), rva_to_offset(&sections, probe), "rva {probe:#x}");
+            assert_eq!(indexed.offset_to_rva(probe), offset_to_rva(&sections, probe), "offset {probe:#x}");
+        }
+    }
+
+    fn section(va: u32, vsize: u32, ptr: u32, raw: u32) -> HeaderField<SectionHeader> {
+        HeaderField {
+            value: SectionHeader {
+                virtual_address: HeaderField { value: va, ..Default::default() },
+                virtual_size: HeaderField { value: vsize, ..Default::default() },
+                raw_data_ptr: HeaderField { value: ptr, ..Default::default() },
+                sizeof_raw_data: HeaderField { value: raw, ..Default::default() },
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn section_map_overlapping_prefers_first() {
+        //2nd section is nested inside the 1st, 3rd starts where the 1st ends.
+        let sections: SectionTable = vec![
+            section(0x1000, 0x3000, 0x400, 0x3000),
+            section(0x2000, 0x100, 0x3400, 0x200),
+            section(0x4000, 0x1000, 0x3600, 0x200),
+        ];
+        let map = SectionMap::new(&sections);
+
+        assert_eq!(map.section_index_for_rva(0x2010), Some(0));
+        assert_eq!(map.section_index_for_rva(0x4000), Some(0));
+        assert_eq!(map.section_index_for_rva(0x4001), Some(2));
+        assert_eq!(map.section_index_for_rva(0x0fff), None);
+        assert_eq!(map.section_index_for_offset(0x3400), Some(1));
+        assert_eq!(map.section_index_for_offset(0x3800), None);
+    }
+
+    #[test]
+    fn values_near_u32_max() {
+        //Ranges of the 1st section run past u32::MAX; rvas of the raw data of the 2nd do.
+        let sections: SectionTable = vec![
+            section(0xFFFF_F000, 0x2000, 0xFFFF_FE00, 0x400),
+            section(0xFFFF_FF00, 0x100, 0x400, 0x1000),
+        ];
+        let high = &sections[0].value;
+        assert!(high.contains_rva(u32::MAX));
+        assert!(high.contains_offset(u32::MAX));
+        assert!(!high.contains_offset(0x100));
+        assert_eq!(high.rva_to_offset(0xFFFF_F000), Some(0xFFFF_FE00));
+        assert_eq!(high.rva_to_offset(0xFFFF_F400), None);
+        assert_eq!(high.offset_to_rva(u32::MAX), Some(0xFFFF_F1FF));
+        assert_eq!(sections[1].value.offset_to_rva(0x600), None);
+
+        let low = section(0x1000, 0x1000, 0x400, 0x200).value;
+        assert!(low.contains_va(0x40_1000, 0x40_0000));
+        assert!(!low.contains_va(0x1_0040_1000, 0x40_0000));
+        assert_eq!(offset_to_rva(&vec![section(0x1000, 0x1000, 0x400, 0x200)], 0x1_0000_0400u64), None);
+
+        let map = SectionMap::new(&sections);
+        let indexed = IndexedSections::new(&sections, &map);
+        for probe in [0, 0x400, 0x600, 0xFFFF_F000, 0xFFFF_F400, 0xFFFF_FE00, 0xFFFF_FF80, u32::MAX] {
+            assert_eq!(indexed.rva_to_offset(probe), rva_to_offset(&sections, probe), "rva {probe:#x}");
+            assert_eq!(indexed.offset_to_rva(probe), offset_to_rva(&sections, probe), "offset {probe:#x}");
+        }
+    }
+
+    #[test]
+    fn flag_helpers_and_names() {
+        let text = Flags::CODE | Flags::MEM_EXECUTE | Flags::MEM_READ;
+        assert!(text.is_code() && text.is_executable() && text.is_readable());
+        assert!(!text.is_writable() && !text.is_shared() && !text.is_discardable());
+        assert_eq!(text.to_string(), "CODE | MEM_EXECUTE | MEM_READ");
+
+        let odd = Flags::from_bits_retain(0xC0000041);
+        assert_eq!(odd.to_string(), "INITIALIZED_DATA | MEM_READ | MEM_WRITE | 0x00000001");
+        assert_eq!(Flags::empty().to_string(), "");
+    }
+
+    #[test]
+    fn alignment_is_not_a_flag() {
+        //.text of an object file, 16 byte aligned.
+        let mut sh = SectionHeader::default();
+        sh.charactristics.value = 0x60500020;
         assert_eq!(sh.flags().unwrap(), Flags::CODE | Flags::MEM_EXECUTE | Flags::MEM_READ);
+        assert_eq!(sh.alignment().unwrap().bytes(), 16);
+        assert!(sh.to_string().ends_with("Flags: CODE | MEM_EXECUTE | MEM_READ, Alignment: 16 }"));
+        assert_eq!(sh.alignment().unwrap().to_string(), "ALIGN_16BYTES");
+
+        assert_eq!(Alignment::from_characteristics(0x00100000).unwrap().bytes(), 1);
+        assert_eq!(Alignment::from_characteristics(0x00E00000).unwrap().bytes(), 8192);
+        assert_eq!(Alignment::from_characteristics(0x00F00000), None);
+        assert_eq!(Alignment::from_characteristics(0x60000020), None);
+    }
+
+    #[test]
+    fn section_count_is_checked() {
+        assert!(check_section_count(200, 0x188, DEFAULT_MAX_SECTIONS, 0x188 + 200 * HEADER_LENGTH).is_ok());
+
+        let err = check_section_count(0xFFFF, 0x188, DEFAULT_MAX_SECTIONS, u64::MAX).unwrap_err();
+        assert!(matches!(err.root(), PeError::InvalidSectionCount { count: 0xFFFF, offset: 0x188, .. }), "{err}");
+
+        let err = check_section_count(200, 0x188, DEFAULT_MAX_SECTIONS, 0x1000).unwrap_err();
+        assert_eq!(err.to_string(), "section table of 200 headers at offset 0x188 is invalid; only 3704 bytes remain in input");
     }
 }
@@ -0,0 +1,199 @@
+//! Just enough of a DER reader to walk PKCS#7 and X.509 structures.
+//!
+//! Values are borrowed from the input; offsets are kept so errors point into the file.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use crate::pe::{PeError, Result};
+
+pub(crate) const BOOLEAN: u8 = 0x01;
+pub(crate) const INTEGER: u8 = 0x02;
+pub(crate) const BIT_STRING: u8 = 0x03;
+pub(crate) const OCTET_STRING: u8 = 0x04;
+pub(crate) const OID: u8 = 0x06;
+pub(crate) const UTF8_STRING: u8 = 0x0c;
+pub(crate) const PRINTABLE_STRING: u8 = 0x13;
+pub(crate) const T61_STRING: u8 = 0x14;
+pub(crate) const IA5_STRING: u8 = 0x16;
+pub(crate) const UTC_TIME: u8 = 0x17;
+pub(crate) const GENERALIZED_TIME: u8 = 0x18;
+pub(crate) const BMP_STRING: u8 = 0x1e;
+pub(crate) const SEQUENCE: u8 = 0x30;
+pub(crate) const SET: u8 = 0x31;
+/// Constructed context-specific tags `[0]`, `[1]` and `[3]`.
+pub(crate) const CONTEXT_0: u8 = 0xa0;
+pub(crate) const CONTEXT_1: u8 = 0xa1;
+pub(crate) const CONTEXT_3: u8 = 0xa3;
+
+/// One tag-length-value; `offset` is the file offset of the tag.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Tlv<'a> {
+    pub tag: u8,
+    pub offset: u64,
+    /// Tag, length and value.
+    pub raw: &'a [u8],
+    pub value: &'a [u8],
+}
+
+impl<'a> Tlv<'a> {
+    /// Reader over the content of a constructed value.
+    pub fn reader(&self) -> DerReader<'a> {
+        DerReader { bytes: self.value, offset: self.offset + (self.raw.len() - self.value.len()) as u64 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DerReader<'a> {
+    bytes: &'a [u8],
+    offset: u64,
+}
+
+impl<'a> DerReader<'a> {
+    /// Reader over `bytes` found at file `offset`.
+    pub fn new(bytes: &'a [u8], offset: u64) -> Self {
+        Self { bytes, offset }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.bytes.first().copied()
+    }
+
+    fn error(&self, reason: &str) -> PeError {
+        PeError::InvalidHeader { name: "DER".into(), offset: self.offset, reason: reason.into() }
+    }
+
+    /// Next value of any tag. Only definite lengths of up to 4 bytes are accepted.
+    pub fn read(&mut self) -> Result<Tlv<'a>> {
+        let (&tag, rest) = self.bytes.split_first().ok_or_else(|| self.error("unexpected end of data"))?;
+        if tag & 0x1f == 0x1f {
+            return Err(self.error("multi-byte tags are not supported"));
+        }
+        let (&first, rest) = rest.split_first().ok_or_else(|| self.error("missing length"))?;
+
+        let (len, rest) = if first < 0x80 {
+            (first as usize, rest)
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > 4 || rest.len() < count {
+                return Err(self.error("unsupported length"));
+            }
+            let len = rest[..count].iter().fold(0usize, |len, b| len << 8 | *b as usize);
+            (len, &rest[count..])
+        };
+
+        if rest.len() < len {
+            return Err(self.error("value is beyond end of data"));
+        }
+        let header_len = self.bytes.len() - rest.len();
+        let tlv = Tlv { tag, offset: self.offset, raw: &self.bytes[..header_len + len], value: &rest[..len] };
+        self.bytes = &rest[len..];
+        self.offset += (header_len + len) as u64;
+        Ok(tlv)
+    }
+
+    /// Next value, which must have `tag`.
+    pub fn expect(&mut self, tag: u8) -> Result<Tlv<'a>> {
+        let tlv = self.read()?;
+        if tlv.tag != tag {
+            return Err(PeError::InvalidHeader {
+                name: "DER".into(),
+                offset: tlv.offset,
+                reason: format!("expected tag {tag:#04x}, found {:#04x}", tlv.tag),
+            });
+        }
+        Ok(tlv)
+    }
+
+    /// Next value if it has `tag`.
+    pub fn optional(&mut self, tag: u8) -> Result<Option<Tlv<'a>>> {
+        match self.peek_tag() {
+            Some(t) if t == tag => Ok(Some(self.read()?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Dotted form of an object identifier.
+pub(crate) fn oid(bytes: &[u8]) -> String {
+    let mut arcs: Vec<u64> = Vec::new();
+    let mut arc = 0u64;
+    for b in bytes {
+        arc = arc << 7 | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            if arcs.is_empty() {
+                let first = (arc / 40).min(2);
+                arcs.push(first);
+                arcs.push(arc - first * 40);
+            } else {
+                arcs.push(arc);
+            }
+            arc = 0;
+        }
+    }
+    arcs.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(".")
+}
+
+/// Text of a directory string; `None` for other tags.
+pub(crate) fn string(tlv: &Tlv) -> Option<String> {
+    match tlv.tag {
+        UTF8_STRING | PRINTABLE_STRING | IA5_STRING => Some(String::from_utf8_lossy(tlv.value).into_owned()),
+        //Latin-1 in practice.
+        T61_STRING => Some(tlv.value.iter().map(|&b| b as char).collect()),
+        BMP_STRING => {
+            let words: Vec<u16> = tlv.value.chunks_exact(2).map(|w| u16::from_be_bytes([w[0], w[1]])).collect();
+            Some(String::from_utf16_lossy(&words))
+        },
+        _ => None,
+    }
+}
+
+/// `UTCTime` or `GeneralizedTime`, in UTC. Fractions of seconds are dropped.
+pub(crate) fn time(tlv: &Tlv) -> Option<DateTime<Utc>> {
+    let text = std::str::from_utf8(tlv.value).ok()?;
+    let text = text.strip_suffix('Z')?;
+    let text = text.split('.').next()?;
+
+    let full = match tlv.tag {
+        //Years 50 to 99 are 19xx.
+        UTC_TIME => {
+            let year: u32 = text.get(..2)?.parse().ok()?;
+            format!("{}{text}", if year < 50 { "20" } else { "19" })
+        },
+        GENERALIZED_TIME => text.to_string(),
+        _ => return None,
+    };
+
+    NaiveDateTime::parse_from_str(&full, "%Y%m%d%H%M%S").ok().map(|t| t.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{oid, time, DerReader, OID, SEQUENCE, UTC_TIME};
+
+    #[test]
+    fn read_values() {
+        //SEQUENCE { OID 1.2.840.113549.1.7.2, UTCTime 230815204731Z }
+        let mut bytes = vec![0x30, 0x1a, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02, 0x17, 0x0d];
+        bytes.extend(b"230815204731Z");
+
+        let mut reader = DerReader::new(&bytes, 0x100);
+        let seq = reader.expect(SEQUENCE).unwrap();
+        assert!(reader.is_empty());
+
+        let mut inner = seq.reader();
+        let id = inner.expect(OID).unwrap();
+        assert_eq!(id.offset, 0x102);
+        assert_eq!(oid(id.value), "1.2.840.113549.1.7.2");
+        let signed = inner.expect(UTC_TIME).unwrap();
+        assert_eq!(time(&signed).unwrap().to_string(), "2023-08-15 20:47:31 UTC");
+        assert!(inner.read().is_err());
+
+        //Long form length beyond the data.
+        assert!(DerReader::new(&[0x30, 0x82, 0x10, 0x00, 0x00], 0).read().is_err());
+    }
+}
@@ -0,0 +1,728 @@
+//! Certificate table of the security directory and what its Authenticode signatures say.
+//!
+//! PKCS#7 signatures are walked to list digest algorithms, certificates, signers and their
+//! timestamps. Nothing is verified; use the result to report, not to trust.
+
+pub(crate) mod der;
+#[cfg(feature="verify")]
+pub mod verify;
+
+use std::fmt::Display;
+
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use self::der::{DerReader, Tlv, BIT_STRING, BOOLEAN, CONTEXT_0, CONTEXT_1, CONTEXT_3, INTEGER, OCTET_STRING, OID, SEQUENCE, SET};
+
+use super::{authenticode::HashAlgorithm, check_alloc, PeError, PeImage, Result};
+
+/// Length of the `WIN_CERTIFICATE` header.
+pub const CERTIFICATE_HEADER_LENGTH: u32 = 8;
+
+const OID_SIGNED_DATA: &str = "1.2.840.113549.1.7.2";
+const OID_SIGNING_TIME: &str = "1.2.840.113549.1.9.5";
+const OID_COUNTER_SIGNATURE: &str = "1.2.840.113549.1.9.6";
+const OID_RFC3161_TIMESTAMP: &str = "1.3.6.1.4.1.311.3.3.1";
+const OID_NESTED_SIGNATURE: &str = "1.3.6.1.4.1.311.2.4.1";
+const OID_SPC_INDIRECT_DATA: &str = "1.3.6.1.4.1.311.2.1.4";
+const OID_TST_INFO: &str = "1.2.840.113549.1.9.16.1.4";
+const OID_COMMON_NAME: &str = "2.5.4.3";
+const OID_BASIC_CONSTRAINTS: &str = "2.5.29.19";
+const OID_EXT_KEY_USAGE: &str = "2.5.29.37";
+
+/// Signatures nested deeper than this, in nested signatures or countersignatures, are rejected.
+pub const MAX_NESTING: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CertificateType {
+    X509,
+    PkcsSignedData,
+    Reserved,
+    TsStackSigned,
+    Unknown(u16),
+}
+
+impl From<u16> for CertificateType {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => Self::X509,
+            2 => Self::PkcsSignedData,
+            3 => Self::Reserved,
+            4 => Self::TsStackSigned,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum DigestAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+    /// Dotted OID of any other algorithm.
+    Other(String),
+}
+
+impl From<&str> for DigestAlgorithm {
+    fn from(oid: &str) -> Self {
+        match oid {
+            "1.2.840.113549.2.5" => Self::Md5,
+            "1.3.14.3.2.26" => Self::Sha1,
+            "2.16.840.1.101.3.4.2.1" => Self::Sha256,
+            "2.16.840.1.101.3.4.2.2" => Self::Sha384,
+            "2.16.840.1.101.3.4.2.3" => Self::Sha512,
+            _ => Self::Other(oid.to_string()),
+        }
+    }
+}
+
+impl DigestAlgorithm {
+    /// Algorithm to compute the same digest with, if it is supported.
+    pub fn hash_algorithm(&self) -> Option<HashAlgorithm> {
+        match self {
+            Self::Md5 => Some(HashAlgorithm::Md5),
+            Self::Sha1 => Some(HashAlgorithm::Sha1),
+            Self::Sha256 => Some(HashAlgorithm::Sha256),
+            Self::Sha384 => Some(HashAlgorithm::Sha384),
+            Self::Sha512 => Some(HashAlgorithm::Sha512),
+            Self::Other(_) => None,
+        }
+    }
+}
+
+impl Display for DigestAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Md5 => write!(f, "MD5"),
+            Self::Sha1 => write!(f, "SHA1"),
+            Self::Sha256 => write!(f, "SHA256"),
+            Self::Sha384 => write!(f, "SHA384"),
+            Self::Sha512 => write!(f, "SHA512"),
+            Self::Other(oid) => write!(f, "{oid}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Certificate {
+    /// Distinguished name like `C=US, O=Example, CN=Example`, in encoded order.
+    pub subject: String,
+    pub issuer: String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub common_name: Option<String>,
+    /// Hex of the serial number.
+    pub serial: String,
+    pub not_before: Option<DateTime<Utc>>,
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum TimestampKind {
+    /// PKCS#9 countersignature.
+    Pkcs9,
+    /// RFC 3161 time-stamp token.
+    Rfc3161,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct Timestamp {
+    pub kind: TimestampKind,
+    /// Common name of the time-stamping authority.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub common_name: Option<String>,
+    pub digest_algorithm: DigestAlgorithm,
+    pub time: Option<DateTime<Utc>>,
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:?}, {})", self.common_name.as_deref().unwrap_or("?"), self.kind, self.digest_algorithm)?;
+        if let Some(time) = self.time {
+            write!(f, " @ {time}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct Signer {
+    /// Issuer of the signing certificate.
+    pub issuer: String,
+    pub serial: String,
+    /// Common name of the signing certificate, if it is in the signature.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub common_name: Option<String>,
+    pub digest_algorithm: DigestAlgorithm,
+    /// `signingTime` attribute, which is not trusted and seldom present.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub signing_time: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub timestamps: Vec<Timestamp>,
+}
+
+impl Signer {
+    /// Time of the first timestamp, else the `signingTime` attribute.
+    pub fn signed_at(&self) -> Option<DateTime<Utc>> {
+        self.timestamps.iter().find_map(|t| t.time).or(self.signing_time)
+    }
+}
+
+impl Display for Signer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.common_name.as_deref().unwrap_or("?"), self.digest_algorithm)?;
+        if let Some(time) = self.signing_time {
+            write!(f, ", SigningTime: {time}")?;
+        }
+        for ts in &self.timestamps {
+            write!(f, ", Timestamp: {ts}")?;
+        }
+        Ok(())
+    }
+}
+
+/// What a PKCS#7 `SignedData` says, without checking any of it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SignedData {
+    pub digest_algorithms: Vec<DigestAlgorithm>,
+    /// Algorithm and digest of the image from `SpcIndirectDataContent`, to compare with
+    /// `PeImage::authentihash`.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub image_digest: Option<(DigestAlgorithm, Vec<u8>)>,
+    pub certificates: Vec<Certificate>,
+    pub signers: Vec<Signer>,
+    /// Signatures nested in unsigned attributes, as in dual signed files.
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub nested: Vec<SignedData>,
+}
+
+impl SignedData {
+    /// Signers of this signature followed by those of nested signatures.
+    pub fn all_signers(&self) -> Vec<&Signer> {
+        let mut signers: Vec<&Signer> = self.signers.iter().collect();
+        for nested in &self.nested {
+            signers.extend(nested.all_signers());
+        }
+        signers
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WinCertificate {
+    /// File offset of the `WIN_CERTIFICATE` header.
+    pub offset: u64,
+    pub length: u32,
+    pub revision: u16,
+    pub cert_type: CertificateType,
+    /// Parsed content of a `PkcsSignedData` entry; `None` for other types or if malformed.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub signature: Option<SignedData>,
+}
+
+impl Display for WinCertificate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Certificate @ {:#x}, Length: {:#x}, Revision: {:#06x}, Type: {:?}", self.offset, self.length, self.revision, self.cert_type)?;
+        if let Some(sig) = &self.signature {
+            let algorithms: Vec<String> = sig.digest_algorithms.iter().map(|a| a.to_string()).collect();
+            writeln!(f, "  DigestAlgorithms: {}", algorithms.join(", "))?;
+            for signer in sig.all_signers() {
+                writeln!(f, "  Signer: {signer}")?;
+            }
+            for cert in &sig.certificates {
+                writeln!(f, "  Certificate: {}", cert.subject)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Hex of `bytes`, without leading zero bytes of a DER integer.
+fn hex(bytes: &[u8]) -> String {
+    let trimmed = match bytes.iter().position(|&b| b != 0) {
+        Some(start) => &bytes[start..],
+        None => bytes.get(bytes.len().saturating_sub(1)..).unwrap_or_default(),
+    };
+    trimmed.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Distinguished name in encoded order and its last common name.
+fn name(tlv: &Tlv) -> Result<(String, Option<String>)> {
+    let mut parts = Vec::new();
+    let mut common_name = None;
+
+    let mut rdns = tlv.reader();
+    while !rdns.is_empty() {
+        let mut rdn = rdns.expect(SET)?.reader();
+        while !rdn.is_empty() {
+            let mut attr = rdn.expect(SEQUENCE)?.reader();
+            let typ = der::oid(attr.expect(OID)?.value);
+            let value = der::string(&attr.read()?).unwrap_or_default();
+            let key = match typ.as_str() {
+                OID_COMMON_NAME => "CN",
+                "2.5.4.6" => "C",
+                "2.5.4.7" => "L",
+                "2.5.4.8" => "ST",
+                "2.5.4.10" => "O",
+                "2.5.4.11" => "OU",
+                "2.5.4.5" => "serialNumber",
+                "1.2.840.113549.1.9.1" => "E",
+                other => other,
+            };
+            if typ == OID_COMMON_NAME {
+                common_name = Some(value.clone());
+            }
+            parts.push(format!("{key}={value}"));
+        }
+    }
+
+    Ok((parts.join(", "), common_name))
+}
+
+/// OID of an `AlgorithmIdentifier`.
+fn algorithm(reader: &mut DerReader) -> Result<DigestAlgorithm> {
+    let mut alg = reader.expect(SEQUENCE)?.reader();
+    Ok(DigestAlgorithm::from(der::oid(alg.expect(OID)?.value).as_str()))
+}
+
+/// A certificate with the raw parts signers and issued certificates refer to.
+#[cfg_attr(not(feature="verify"), allow(dead_code))]
+struct ParsedCertificate<'a> {
+    cert: Certificate,
+    /// Whole encoding of the certificate.
+    raw: &'a [u8],
+    /// Whole encoding of `tbsCertificate`, which the issuer signs.
+    tbs: &'a [u8],
+    issuer: &'a [u8],
+    subject: &'a [u8],
+    serial: &'a [u8],
+    signature_algorithm: String,
+    /// Content of the `signatureValue` bit string.
+    signature: &'a [u8],
+    key_algorithm: String,
+    /// Content of the `subjectPublicKey` bit string.
+    public_key: &'a [u8],
+    /// `cA` of the basic constraints; `false` without them.
+    ca: bool,
+    /// OIDs of the extended key usage; `None` without the extension.
+    key_purposes: Option<Vec<String>>,
+}
+
+/// Content of a bit string without the count of unused bits.
+fn bit_string<'a>(tlv: &Tlv<'a>) -> &'a [u8] {
+    tlv.value.get(1..).unwrap_or_default()
+}
+
+fn parse_certificate<'a>(tlv: &Tlv<'a>) -> Result<ParsedCertificate<'a>> {
+    let mut outer = tlv.reader();
+    let tbs_tlv = outer.expect(SEQUENCE)?;
+    let signature_algorithm = der::oid(outer.expect(SEQUENCE)?.reader().expect(OID)?.value);
+    let signature = bit_string(&outer.expect(BIT_STRING)?);
+
+    let mut tbs = tbs_tlv.reader();
+    tbs.optional(CONTEXT_0)?;
+    let serial = tbs.expect(INTEGER)?;
+    tbs.expect(SEQUENCE)?;
+    let issuer = tbs.expect(SEQUENCE)?;
+    let mut validity = tbs.expect(SEQUENCE)?.reader();
+    let not_before = der::time(&validity.read()?);
+    let not_after = der::time(&validity.read()?);
+    let subject = tbs.expect(SEQUENCE)?;
+    let mut key_info = tbs.expect(SEQUENCE)?.reader();
+    let key_algorithm = der::oid(key_info.expect(SEQUENCE)?.reader().expect(OID)?.value);
+    let public_key = bit_string(&key_info.expect(BIT_STRING)?);
+
+    //Unique identifiers [1] and [2] are primitive and skipped.
+    tbs.optional(0x81)?;
+    tbs.optional(0x82)?;
+    let mut ca = false;
+    let mut key_purposes = None;
+    if let Some(extensions) = tbs.optional(CONTEXT_3)? {
+        let mut list = extensions.reader().expect(SEQUENCE)?.reader();
+        while !list.is_empty() {
+            let mut ext = list.expect(SEQUENCE)?.reader();
+            let typ = der::oid(ext.expect(OID)?.value);
+            ext.optional(BOOLEAN)?;
+            let value = ext.expect(OCTET_STRING)?;
+            match typ.as_str() {
+                OID_BASIC_CONSTRAINTS => {
+                    let mut constraints = value.reader().expect(SEQUENCE)?.reader();
+                    ca = constraints.optional(BOOLEAN)?.is_some_and(|b| b.value.first().is_some_and(|&v| v != 0));
+                },
+                OID_EXT_KEY_USAGE => {
+                    let mut usages = value.reader().expect(SEQUENCE)?.reader();
+                    let mut purposes = Vec::new();
+                    while !usages.is_empty() {
+                        purposes.push(der::oid(usages.expect(OID)?.value));
+                    }
+                    key_purposes = Some(purposes);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    let (subject_name, common_name) = name(&subject)?;
+    let cert = Certificate {
+        subject: subject_name,
+        issuer: name(&issuer)?.0,
+        common_name,
+        serial: hex(serial.value),
+        not_before,
+        not_after,
+    };
+    Ok(ParsedCertificate {
+        cert,
+        raw: tlv.raw,
+        tbs: tbs_tlv.raw,
+        issuer: issuer.value,
+        subject: subject.value,
+        serial: serial.value,
+        signature_algorithm,
+        signature,
+        key_algorithm,
+        public_key,
+        ca,
+        key_purposes,
+    })
+}
+
+/// Values of the attributes in a `SET OF Attribute`, with their type.
+fn attributes<'a>(tlv: &Tlv<'a>) -> Result<Vec<(String, Tlv<'a>)>> {
+    let mut found = Vec::new();
+    let mut attrs = tlv.reader();
+    while !attrs.is_empty() {
+        let mut attr = attrs.expect(SEQUENCE)?.reader();
+        let typ = der::oid(attr.expect(OID)?.value);
+        let mut values = attr.expect(SET)?.reader();
+        while !values.is_empty() {
+            found.push((typ.clone(), values.read()?));
+        }
+    }
+    Ok(found)
+}
+
+/// Fail if a signature at `depth`, starting at `offset`, is nested deeper than `MAX_NESTING`.
+fn check_nesting(depth: u32, offset: u64) -> Result<()> {
+    if depth > MAX_NESTING {
+        return Err(PeError::InvalidHeader {
+            name: "SignedData".into(),
+            offset,
+            reason: format!("nested more than {MAX_NESTING} levels deep"),
+        });
+    }
+    Ok(())
+}
+
+/// Parse a `SignerInfo` at nesting `depth`. Countersignatures and time-stamp tokens become
+/// timestamps; nested signatures are added to `nested`.
+fn parse_signer(tlv: &Tlv, certs: &[ParsedCertificate], nested: &mut Vec<SignedData>, depth: u32) -> Result<Signer> {
+    check_nesting(depth, tlv.offset)?;
+    let mut reader = tlv.reader();
+    reader.expect(INTEGER)?;
+
+    let mut signer = Signer {
+        issuer: String::new(),
+        serial: String::new(),
+        common_name: None,
+        digest_algorithm: DigestAlgorithm::Other(String::new()),
+        signing_time: None,
+        timestamps: Vec::new(),
+    };
+
+    //Signers of version 3 may refer to a subject key identifier instead.
+    if let Some(sid) = reader.optional(SEQUENCE)? {
+        let mut sid = sid.reader();
+        let issuer = sid.expect(SEQUENCE)?;
+        let serial = sid.expect(INTEGER)?;
+        signer.issuer = name(&issuer)?.0;
+        signer.serial = hex(serial.value);
+        signer.common_name = certs.iter()
+            .find(|c| c.issuer == issuer.value && c.serial == serial.value)
+            .and_then(|c| c.cert.common_name.clone());
+    } else {
+        reader.read()?;
+    }
+    signer.digest_algorithm = algorithm(&mut reader)?;
+
+    if let Some(signed_attrs) = reader.optional(CONTEXT_0)? {
+        for (typ, value) in attributes(&signed_attrs)? {
+            if typ == OID_SIGNING_TIME {
+                signer.signing_time = der::time(&value);
+            }
+        }
+    }
+
+    reader.expect(SEQUENCE)?;
+    reader.expect(OCTET_STRING)?;
+
+    if let Some(unsigned_attrs) = reader.optional(CONTEXT_1)? {
+        for (typ, value) in attributes(&unsigned_attrs)? {
+            match typ.as_str() {
+                OID_COUNTER_SIGNATURE => {
+                    let counter = parse_signer(&value, certs, nested, depth + 1)?;
+                    signer.timestamps.push(Timestamp {
+                        kind: TimestampKind::Pkcs9,
+                        common_name: counter.common_name,
+                        digest_algorithm: counter.digest_algorithm,
+                        time: counter.signing_time,
+                    });
+                },
+                OID_RFC3161_TIMESTAMP => {
+                    let (token, tst_time) = parse_content_info(&value, depth + 1)?;
+                    if let Some(tsa) = token.signers.first() {
+                        signer.timestamps.push(Timestamp {
+                            kind: TimestampKind::Rfc3161,
+                            common_name: tsa.common_name.clone(),
+                            digest_algorithm: tsa.digest_algorithm.clone(),
+                            time: tst_time.or(tsa.signing_time),
+                        });
+                    }
+                },
+                OID_NESTED_SIGNATURE => nested.push(parse_content_info(&value, depth + 1)?.0),
+                _ => {},
+            }
+        }
+    }
+
+    Ok(signer)
+}
+
+/// Parse a `ContentInfo` holding `SignedData` at nesting `depth`. Also returns `genTime` if the
+/// content is a time-stamp token.
+fn parse_content_info(tlv: &Tlv, depth: u32) -> Result<(SignedData, Option<DateTime<Utc>>)> {
+    check_nesting(depth, tlv.offset)?;
+    let mut info = tlv.reader();
+    let typ = info.expect(OID)?;
+    if der::oid(typ.value) != OID_SIGNED_DATA {
+        return Err(PeError::InvalidHeader {
+            name: "SignedData".into(),
+            offset: typ.offset,
+            reason: format!("content type is {}", der::oid(typ.value)),
+        });
+    }
+    let content = info.expect(CONTEXT_0)?;
+    let mut signed = content.reader().expect(SEQUENCE)?.reader();
+
+    let mut data = SignedData::default();
+    signed.expect(INTEGER)?;
+    let mut algorithms = signed.expect(SET)?.reader();
+    while !algorithms.is_empty() {
+        data.digest_algorithms.push(algorithm(&mut algorithms)?);
+    }
+
+    //Authenticode puts SpcIndirectDataContent here directly; CMS wraps content in an
+    //OCTET STRING.
+    let mut encap = signed.expect(SEQUENCE)?.reader();
+    let content_type = der::oid(encap.expect(OID)?.value);
+    let mut tst_time = None;
+    if let Some(explicit) = encap.optional(CONTEXT_0)? {
+        let mut inner = explicit.reader();
+        let mut content = inner.read()?;
+        if content.tag == OCTET_STRING {
+            content = content.reader().expect(SEQUENCE)?;
+        }
+
+        match content_type.as_str() {
+            OID_SPC_INDIRECT_DATA => {
+                let mut spc = content.reader();
+                spc.expect(SEQUENCE)?;
+                let mut digest_info = spc.expect(SEQUENCE)?.reader();
+                let alg = algorithm(&mut digest_info)?;
+                data.image_digest = Some((alg, digest_info.expect(OCTET_STRING)?.value.to_vec()));
+            },
+            OID_TST_INFO => {
+                //version, policy, messageImprint, serialNumber, genTime
+                let mut tst = content.reader();
+                tst.expect(INTEGER)?;
+                tst.expect(OID)?;
+                tst.expect(SEQUENCE)?;
+                tst.expect(INTEGER)?;
+                tst_time = der::time(&tst.read()?);
+            },
+            _ => {},
+        }
+    }
+
+    let mut certs = Vec::new();
+    if let Some(list) = signed.optional(CONTEXT_0)? {
+        let mut list = list.reader();
+        while !list.is_empty() {
+            //Attribute certificates and other choices are skipped.
+            let cert = list.read()?;
+            if cert.tag == SEQUENCE {
+                certs.push(parse_certificate(&cert)?);
+            }
+        }
+    }
+    signed.optional(CONTEXT_1)?;
+
+    let mut signers = signed.expect(SET)?.reader();
+    while !signers.is_empty() {
+        let signer = parse_signer(&signers.expect(SEQUENCE)?, &certs, &mut data.nested, depth)?;
+        data.signers.push(signer);
+    }
+
+    data.certificates = certs.into_iter().map(|c| c.cert).collect();
+    Ok((data, tst_time))
+}
+
+/// Parse PKCS#7 `bytes` of a certificate entry found at file `offset`.
+pub fn parse_signed_data(bytes: &[u8], offset: u64) -> Result<SignedData> {
+    let content_info = DerReader::new(bytes, offset).expect(SEQUENCE)?;
+    Ok(parse_content_info(&content_info, 0)?.0)
+}
+
+impl PeImage {
+    /// Entries of the certificate table, without parsed signatures, and their content.
+    pub(crate) fn certificate_entries(&mut self) -> Result<Vec<(WinCertificate, Vec<u8>)>> {
+        let Some(range) = self.certificate_range() else {
+            return Ok(Vec::new());
+        };
+        let (start, size) = (range.start, range.end - range.start);
+        check_alloc("CertificateTable", size, self.limits.max_alloc)?;
+        let bytes = self.reader.read_bytes_at_offset(start, size as usize)?;
+
+        let mut entries = Vec::new();
+        let mut pos = 0usize;
+        while pos + CERTIFICATE_HEADER_LENGTH as usize <= bytes.len() {
+            let length = LittleEndian::read_u32(&bytes[pos..]);
+            let revision = LittleEndian::read_u16(&bytes[pos + 4..]);
+            let cert_type = CertificateType::from(LittleEndian::read_u16(&bytes[pos + 6..]));
+            let offset = start + pos as u64;
+            if length < CERTIFICATE_HEADER_LENGTH || pos + length as usize > bytes.len() {
+                tracing::warn!(offset, length, "certificate entry is beyond the certificate table");
+                break;
+            }
+
+            let content = bytes[pos + CERTIFICATE_HEADER_LENGTH as usize..pos + length as usize].to_vec();
+            entries.push((WinCertificate { offset, length, revision, cert_type, signature: None }, content));
+
+            //Entries are 8 byte aligned.
+            pos += (length as usize).next_multiple_of(8);
+        }
+
+        Ok(entries)
+    }
+
+    /// Entries of the certificate table. Malformed signatures are logged and left unparsed.
+    pub fn certificates(&mut self) -> Result<Vec<WinCertificate>> {
+        let mut certs = Vec::new();
+        for (mut cert, content) in self.certificate_entries()? {
+            if cert.cert_type == CertificateType::PkcsSignedData {
+                match parse_signed_data(&content, cert.offset + CERTIFICATE_HEADER_LENGTH as u64) {
+                    Ok(sig) => cert.signature = Some(sig),
+                    Err(err) => tracing::warn!(err = err.chain(), "skipped malformed signature"),
+                }
+            }
+            certs.push(cert);
+        }
+        Ok(certs)
+    }
+
+    /// Signers of all signatures in the certificate table, nested ones included.
+    pub fn signers(&mut self) -> Result<Vec<Signer>> {
+        Ok(self.certificates()?.iter()
+            .filter_map(|c| c.signature.as_ref())
+            .flat_map(|s| s.all_signers().into_iter().cloned())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::pe::{authenticode::HashAlgorithm, PeImage};
+
+    use super::{parse_signed_data, CertificateType, DigestAlgorithm, TimestampKind, MAX_NESTING};
+
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        match content.len() {
+            len @ 0..=0x7f => out.push(len as u8),
+            len => {
+                let len = (len as u32).to_be_bytes();
+                let skip = len.iter().take_while(|&&b| b == 0).count();
+                out.push(0x80 | (4 - skip) as u8);
+                out.extend(&len[skip..]);
+            },
+        }
+        out.extend(content);
+        out
+    }
+
+    /// `SignedData` with one signer, whose unsigned attributes hold `nested`, if any.
+    fn signed_data(nested: Option<Vec<u8>>) -> Vec<u8> {
+        const SIGNED_DATA: &[u8] = &[0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02];
+        const NESTED_SIGNATURE: &[u8] = &[0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x04, 0x01];
+        const SHA256: &[u8] = &[0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+
+        let algorithm = tlv(0x30, SHA256);
+        let mut signer = [tlv(0x02, &[1]), tlv(0x80, &[]), algorithm.clone(), algorithm, tlv(0x04, &[])].concat();
+        if let Some(nested) = nested {
+            signer.extend(tlv(0xa1, &tlv(0x30, &[NESTED_SIGNATURE, &tlv(0x31, &nested)].concat())));
+        }
+        let signed = [tlv(0x02, &[1]), tlv(0x31, &[]), tlv(0x30, SIGNED_DATA), tlv(0x31, &tlv(0x30, &signer))].concat();
+        tlv(0x30, &[SIGNED_DATA, &tlv(0xa0, &tlv(0x30, &signed))].concat())
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nest = |levels| (0..levels).fold(signed_data(None), |inner, _| signed_data(Some(inner)));
+
+        let sig = parse_signed_data(&nest(MAX_NESTING), 0).unwrap();
+        assert_eq!(sig.all_signers().len(), MAX_NESTING as usize + 1);
+
+        let err = parse_signed_data(&nest(MAX_NESTING + 1), 0).unwrap_err();
+        assert!(err.to_string().contains("nested more than 8 levels deep"), "{err}");
+    }
+
+    #[test]
+    fn signature_of_test_dll() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+
+        let certs = pe.certificates().unwrap();
+        assert_eq!(certs.len(), 1);
+        assert_eq!(certs[0].offset, 0x14400);
+        assert_eq!(certs[0].length, 0x2d68);
+        assert_eq!(certs[0].revision, 0x200);
+        assert_eq!(certs[0].cert_type, CertificateType::PkcsSignedData);
+
+        let sig = certs[0].signature.as_ref().unwrap();
+        assert_eq!(sig.digest_algorithms, [DigestAlgorithm::Sha256]);
+        assert_eq!(sig.certificates.len(), 6);
+        assert_eq!(sig.certificates[5].subject, "C=US, ST=Washington, L=Bellevue, O=Valve Corp., CN=Valve Corp.");
+        assert!(sig.nested.is_empty());
+
+        let (alg, digest) = sig.image_digest.as_ref().unwrap();
+        assert_eq!(*alg, DigestAlgorithm::Sha256);
+        assert_eq!(*digest, pe.authentihash(HashAlgorithm::Sha256).unwrap());
+
+        let signers = pe.signers().unwrap();
+        assert_eq!(signers.len(), 1);
+        let signer = &signers[0];
+        assert_eq!(signer.common_name.as_deref(), Some("Valve Corp."));
+        assert_eq!(signer.digest_algorithm, DigestAlgorithm::Sha256);
+        assert_eq!(signer.signing_time, None);
+        assert_eq!(signer.timestamps.len(), 1);
+        assert_eq!(signer.timestamps[0].kind, TimestampKind::Pkcs9);
+        assert_eq!(signer.timestamps[0].common_name.as_deref(), Some("DigiCert Timestamp 2023"));
+        assert_eq!(signer.signed_at().unwrap().to_string(), "2023-08-15 20:47:31 UTC");
+
+        //A damaged signature is skipped, the entry is kept.
+        let mut bytes = bytes;
+        bytes[0x14408] = 0x31;
+        let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        let certs = pe.certificates().unwrap();
+        assert_eq!(certs.len(), 1);
+        assert!(certs[0].signature.is_none());
+    }
+}
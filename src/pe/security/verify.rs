@@ -0,0 +1,616 @@
+//! Authenticode verification: image digest, signer signature and certificate chain up to a
+//! trusted root. Needs the `verify` feature.
+//!
+//! Only RSA keys with PKCS#1 v1.5 signatures are checked. A timestamp picks the time certificates
+//! must be valid at only if its own signature and chain verify. Revocation is not checked.
+
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
+use rsa::{pkcs1::DecodeRsaPublicKey, Pkcs1v15Sign, RsaPublicKey};
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Sha256, Sha384, Sha512};
+
+use crate::pe::{authenticode::HashAlgorithm, PeImage, Result};
+
+use super::{
+    algorithm, attributes, check_nesting, der::{self, DerReader, Tlv, CONTEXT_0, CONTEXT_1, INTEGER, OCTET_STRING, OID, SEQUENCE, SET},
+    parse_certificate, CertificateType, DigestAlgorithm, ParsedCertificate,
+    OID_COUNTER_SIGNATURE, OID_NESTED_SIGNATURE, OID_RFC3161_TIMESTAMP, OID_SIGNING_TIME,
+};
+
+/// Certificates checked between a signer and a root before giving up.
+pub const MAX_CHAIN_LENGTH: usize = 8;
+
+const OID_MESSAGE_DIGEST: &str = "1.2.840.113549.1.9.4";
+const OID_RSA_ENCRYPTION: &str = "1.2.840.113549.1.1.1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum VerifyStatus {
+    /// Digests and signatures match and the chain ends at a trusted root.
+    Valid,
+    /// Digests and signatures match, but no trusted root was reached or an algorithm is not
+    /// supported.
+    Untrusted,
+    /// The image is not signed, or a digest, signature or validity period doesn't match.
+    Invalid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Verification {
+    /// Worst status of all signatures, nested ones included.
+    pub status: VerifyStatus,
+    /// Common name of the first signer.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub signer: Option<String>,
+    /// What was checked, in order, with the reason of any failure.
+    pub details: Vec<String>,
+}
+
+impl Verification {
+    fn new() -> Self {
+        Self { status: VerifyStatus::Valid, signer: None, details: Vec::new() }
+    }
+
+    /// Record `detail`, lowering the status to `status` if it is worse.
+    fn note(&mut self, status: VerifyStatus, detail: impl Into<String>) {
+        self.status = self.status.max(status);
+        self.details.push(detail.into());
+    }
+}
+
+impl Display for Verification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Signature: {:?}, Signer: {}", self.status, self.signer.as_deref().unwrap_or("?"))?;
+        for detail in &self.details {
+            writeln!(f, "  {detail}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check an RSA PKCS#1 v1.5 `signature` of `data` with `key`, a DER `RSAPublicKey`.
+/// `Err` says why it can't be checked.
+fn verify_rsa(key: &[u8], digest: &DigestAlgorithm, data: &[u8], signature: &[u8]) -> std::result::Result<bool, String> {
+    let key = RsaPublicKey::from_pkcs1_der(key).map_err(|err| format!("bad RSA key; {err}"))?;
+    let (scheme, algorithm) = match digest {
+        DigestAlgorithm::Sha1 => (Pkcs1v15Sign::new::<Sha1>(), HashAlgorithm::Sha1),
+        DigestAlgorithm::Sha256 => (Pkcs1v15Sign::new::<Sha256>(), HashAlgorithm::Sha256),
+        DigestAlgorithm::Sha384 => (Pkcs1v15Sign::new::<Sha384>(), HashAlgorithm::Sha384),
+        DigestAlgorithm::Sha512 => (Pkcs1v15Sign::new::<Sha512>(), HashAlgorithm::Sha512),
+        other => return Err(format!("digest {other} is not supported")),
+    };
+    Ok(key.verify(scheme, &algorithm.digest(data), signature).is_ok())
+}
+
+/// Check that `issuer` signed `cert`.
+fn verify_issued(cert: &ParsedCertificate, issuer: &ParsedCertificate) -> std::result::Result<bool, String> {
+    if issuer.key_algorithm != OID_RSA_ENCRYPTION {
+        return Err(format!("key algorithm {} is not supported", issuer.key_algorithm));
+    }
+    let digest = match cert.signature_algorithm.as_str() {
+        "1.2.840.113549.1.1.5" => DigestAlgorithm::Sha1,
+        "1.2.840.113549.1.1.11" => DigestAlgorithm::Sha256,
+        "1.2.840.113549.1.1.12" => DigestAlgorithm::Sha384,
+        "1.2.840.113549.1.1.13" => DigestAlgorithm::Sha512,
+        other => return Err(format!("signature algorithm {other} is not supported")),
+    };
+    verify_rsa(issuer.public_key, &digest, cert.tbs, cert.signature)
+}
+
+fn cn<'a>(cert: &'a ParsedCertificate) -> &'a str {
+    cert.cert.common_name.as_deref().unwrap_or("?")
+}
+
+fn in_period(cert: &ParsedCertificate, time: DateTime<Utc>) -> bool {
+    cert.cert.not_before.is_none_or(|t| t <= time) && cert.cert.not_after.is_none_or(|t| time <= t)
+}
+
+/// Extended key usage the first certificate of a chain must have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyPurpose {
+    CodeSigning,
+    TimeStamping,
+}
+
+impl KeyPurpose {
+    fn oid(&self) -> &'static str {
+        match self {
+            Self::CodeSigning => "1.3.6.1.5.5.7.3.3",
+            Self::TimeStamping => "1.3.6.1.5.5.7.3.8",
+        }
+    }
+}
+
+/// When certificates must be valid: at a verified timestamp or now.
+#[derive(Debug, Clone, Copy)]
+struct CheckTime {
+    time: DateTime<Utc>,
+    stamped: bool,
+}
+
+impl CheckTime {
+    fn new(stamped: Option<DateTime<Utc>>) -> Self {
+        match stamped {
+            Some(time) => Self { time, stamped: true },
+            None => Self { time: Utc::now(), stamped: false },
+        }
+    }
+}
+
+impl Display for CheckTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.stamped {
+            true => write!(f, "at signing time"),
+            false => write!(f, "now"),
+        }
+    }
+}
+
+/// Follow issuers from `leaf` through `certs` to one of `roots`. `leaf` must have the extended
+/// key usage `purpose`, every issuer must be a CA, and all must be valid at `at`.
+fn verify_chain(leaf: &ParsedCertificate, certs: &[ParsedCertificate], roots: &[ParsedCertificate], purpose: KeyPurpose, at: CheckTime, result: &mut Verification) {
+    match &leaf.key_purposes {
+        Some(purposes) if purposes.iter().any(|p| p == purpose.oid()) => {},
+        _ => result.note(VerifyStatus::Invalid, format!("Certificate of {} is not for {purpose:?}", cn(leaf))),
+    }
+    match in_period(leaf, at.time) {
+        true => result.note(VerifyStatus::Valid, format!("Certificate of {} is valid {at}", cn(leaf))),
+        false => result.note(VerifyStatus::Invalid, format!("Certificate of {} is not valid {at} ({})", cn(leaf), at.time)),
+    }
+
+    //Checks of an issuer beyond its signature.
+    let check_issuer = |issuer: &ParsedCertificate, result: &mut Verification| {
+        if !issuer.ca {
+            result.note(VerifyStatus::Invalid, format!("Chain: {} is not a CA", cn(issuer)));
+        }
+        if !in_period(issuer, at.time) {
+            result.note(VerifyStatus::Invalid, format!("Chain: certificate of {} is not valid {at} ({})", cn(issuer), at.time));
+        }
+    };
+
+    let mut current = leaf;
+    let mut path = vec![cn(leaf).to_string()];
+
+    for _ in 0..MAX_CHAIN_LENGTH {
+        if roots.iter().any(|r| r.raw == current.raw) {
+            result.note(VerifyStatus::Valid, format!("Chain: {} (trusted root)", path.join(" <- ")));
+            return;
+        }
+
+        if let Some(root) = roots.iter().find(|r| r.subject == current.issuer) {
+            match verify_issued(current, root) {
+                Ok(true) => {
+                    check_issuer(root, result);
+                    path.push(cn(root).to_string());
+                    result.note(VerifyStatus::Valid, format!("Chain: {} (trusted root)", path.join(" <- ")));
+                },
+                Ok(false) => result.note(VerifyStatus::Invalid, format!("Chain: signature of {} by trusted root {} doesn't match", cn(current), cn(root))),
+                Err(reason) => result.note(VerifyStatus::Untrusted, format!("Chain: can't check {}; {reason}", cn(current))),
+            }
+            return;
+        }
+
+        if current.subject == current.issuer {
+            result.note(VerifyStatus::Untrusted, format!("Chain: {} (untrusted root)", path.join(" <- ")));
+            return;
+        }
+
+        let candidates: Vec<&ParsedCertificate> = certs.iter()
+            .filter(|c| c.subject == current.issuer && c.raw != current.raw)
+            .collect();
+        let mut issuer = None;
+        for candidate in &candidates {
+            match verify_issued(current, candidate) {
+                Ok(true) => {
+                    issuer = Some(*candidate);
+                    break;
+                },
+                Ok(false) => {},
+                Err(reason) => {
+                    result.note(VerifyStatus::Untrusted, format!("Chain: can't check {}; {reason}", cn(current)));
+                    return;
+                },
+            }
+        }
+
+        match issuer {
+            Some(issuer) => {
+                check_issuer(issuer, result);
+                path.push(cn(issuer).to_string());
+                current = issuer;
+            },
+            None if candidates.is_empty() => {
+                result.note(VerifyStatus::Untrusted, format!("Chain: {}; issuer {} is missing", path.join(" <- "), current.cert.issuer));
+                return;
+            },
+            None => {
+                result.note(VerifyStatus::Invalid, format!("Chain: signature of {} by its issuer doesn't match", cn(current)));
+                return;
+            },
+        }
+    }
+
+    result.note(VerifyStatus::Untrusted, format!("Chain: {} is longer than {MAX_CHAIN_LENGTH}", path.join(" <- ")));
+}
+
+/// Certificates of a `SignedData` read up to its signers; CRLs are skipped.
+fn certificates<'a>(signed: &mut DerReader<'a>) -> Result<Vec<ParsedCertificate<'a>>> {
+    let mut certs = Vec::new();
+    if let Some(list) = signed.optional(CONTEXT_0)? {
+        let mut list = list.reader();
+        while !list.is_empty() {
+            let cert = list.read()?;
+            if cert.tag == SEQUENCE {
+                certs.push(parse_certificate(&cert)?);
+            }
+        }
+    }
+    signed.optional(CONTEXT_1)?;
+    Ok(certs)
+}
+
+/// A `SignerInfo` identified by issuer and serial number.
+struct SignerInfo<'a> {
+    issuer: &'a [u8],
+    serial: &'a [u8],
+    digest: DigestAlgorithm,
+    signed_attrs: Tlv<'a>,
+    signature: &'a [u8],
+    unsigned_attrs: Option<Tlv<'a>>,
+}
+
+impl<'a> SignerInfo<'a> {
+    /// `None` if the signer is identified by key identifier, which is not supported.
+    fn parse(tlv: &Tlv<'a>) -> Result<Option<Self>> {
+        let mut signer = tlv.reader();
+        signer.expect(INTEGER)?;
+        let Some(sid) = signer.optional(SEQUENCE)? else {
+            return Ok(None);
+        };
+        let mut sid = sid.reader();
+        let (issuer, serial) = (sid.expect(SEQUENCE)?.value, sid.expect(INTEGER)?.value);
+        let digest = algorithm(&mut signer)?;
+        let signed_attrs = signer.expect(CONTEXT_0)?;
+        signer.expect(SEQUENCE)?;
+        let signature = signer.expect(OCTET_STRING)?.value;
+        let unsigned_attrs = signer.optional(CONTEXT_1)?;
+        Ok(Some(Self { issuer, serial, digest, signed_attrs, signature, unsigned_attrs }))
+    }
+
+    /// Value of the signed attribute `typ`.
+    fn signed_attr(&self, typ: &str) -> Result<Option<Tlv<'a>>> {
+        Ok(attributes(&self.signed_attrs)?.into_iter().find(|(t, _)| t == typ).map(|(_, value)| value))
+    }
+}
+
+/// Check the `messageDigest` of `signer` against `content`, and its signature by its certificate
+/// in `certs`, which is returned. `None` if the certificate is missing.
+fn verify_signer<'c, 'a>(signer: &SignerInfo, content: &[u8], certs: &'c [ParsedCertificate<'a>], result: &mut Verification) -> Result<Option<&'c ParsedCertificate<'a>>> {
+    let Some(cert) = certs.iter().find(|c| c.issuer == signer.issuer && c.serial == signer.serial) else {
+        result.note(VerifyStatus::Invalid, "Signer certificate is missing");
+        return Ok(None);
+    };
+
+    let digest = &signer.digest;
+    let message_digest = signer.signed_attr(OID_MESSAGE_DIGEST)?.map(|value| value.value);
+    match digest.hash_algorithm() {
+        Some(alg) if message_digest == Some(alg.digest(content).as_slice()) => result.note(VerifyStatus::Valid, format!("Content digest ({digest}) matches")),
+        Some(_) => result.note(VerifyStatus::Invalid, format!("Content digest ({digest}) doesn't match")),
+        None => result.note(VerifyStatus::Untrusted, format!("Content digest {digest} is not supported")),
+    }
+
+    //Signed attributes are signed as a SET, not with their implicit [0] tag.
+    let mut attrs = signer.signed_attrs.raw.to_vec();
+    attrs[0] = SET;
+    let checked = if cert.key_algorithm == OID_RSA_ENCRYPTION {
+        verify_rsa(cert.public_key, digest, &attrs, signer.signature)
+    } else {
+        Err(format!("key algorithm {} is not supported", cert.key_algorithm))
+    };
+    match checked {
+        Ok(true) => result.note(VerifyStatus::Valid, format!("Signature of {} matches", cn(cert))),
+        Ok(false) => result.note(VerifyStatus::Invalid, format!("Signature of {} doesn't match", cn(cert))),
+        Err(reason) => result.note(VerifyStatus::Untrusted, format!("Signature of {} can't be checked; {reason}", cn(cert))),
+    }
+    Ok(Some(cert))
+}
+
+/// Verify a PKCS#9 countersignature of `signature`; `certs` are those of the countersigned
+/// `SignedData`. Returns its `signingTime`.
+fn verify_countersignature(tlv: &Tlv, signature: &[u8], certs: &[ParsedCertificate], roots: &[ParsedCertificate], result: &mut Verification) -> Result<Option<DateTime<Utc>>> {
+    let Some(counter) = SignerInfo::parse(tlv)? else {
+        result.note(VerifyStatus::Untrusted, "Signer is identified by key identifier, which is not supported");
+        return Ok(None);
+    };
+    let time = counter.signed_attr(OID_SIGNING_TIME)?.and_then(|value| der::time(&value));
+    let Some(cert) = verify_signer(&counter, signature, certs, result)? else {
+        return Ok(None);
+    };
+    let Some(time) = time else {
+        result.note(VerifyStatus::Invalid, "Signing time is missing");
+        return Ok(None);
+    };
+    verify_chain(cert, certs, roots, KeyPurpose::TimeStamping, CheckTime::new(Some(time)), result);
+    Ok(Some(time))
+}
+
+/// Verify an RFC 3161 time-stamp token of `signature`. Returns its `genTime`.
+fn verify_token(content_info: &Tlv, signature: &[u8], roots: &[ParsedCertificate], result: &mut Verification) -> Result<Option<DateTime<Utc>>> {
+    let mut info = content_info.reader();
+    info.expect(OID)?;
+    let mut signed = info.expect(CONTEXT_0)?.reader().expect(SEQUENCE)?.reader();
+    signed.expect(INTEGER)?;
+    signed.expect(SET)?;
+
+    //TSTInfo: version, policy, messageImprint, serialNumber, genTime
+    let mut encap = signed.expect(SEQUENCE)?.reader();
+    encap.expect(OID)?;
+    let tst_info = encap.expect(CONTEXT_0)?.reader().expect(OCTET_STRING)?;
+    let mut tst = tst_info.reader().expect(SEQUENCE)?.reader();
+    tst.expect(INTEGER)?;
+    tst.expect(OID)?;
+    let mut imprint = tst.expect(SEQUENCE)?.reader();
+    let imprint_algorithm = algorithm(&mut imprint)?;
+    let imprinted = imprint.expect(OCTET_STRING)?.value;
+    tst.expect(INTEGER)?;
+    let time = der::time(&tst.read()?);
+
+    match imprint_algorithm.hash_algorithm() {
+        Some(alg) if alg.digest(signature) == imprinted => result.note(VerifyStatus::Valid, format!("Imprint ({imprint_algorithm}) matches")),
+        Some(_) => result.note(VerifyStatus::Invalid, format!("Imprint ({imprint_algorithm}) doesn't match")),
+        None => result.note(VerifyStatus::Untrusted, format!("Imprint {imprint_algorithm} is not supported")),
+    }
+
+    let certs = certificates(&mut signed)?;
+    let Some(tsa) = SignerInfo::parse(&signed.expect(SET)?.reader().expect(SEQUENCE)?)? else {
+        result.note(VerifyStatus::Untrusted, "Signer is identified by key identifier, which is not supported");
+        return Ok(None);
+    };
+    let Some(cert) = verify_signer(&tsa, tst_info.value, &certs, result)? else {
+        return Ok(None);
+    };
+    let Some(time) = time else {
+        result.note(VerifyStatus::Invalid, "Time is missing");
+        return Ok(None);
+    };
+    verify_chain(cert, &certs, roots, KeyPurpose::TimeStamping, CheckTime::new(Some(time)), result);
+    Ok(Some(time))
+}
+
+/// Time of the first timestamp of `signer` that verifies, with its checks as details. Each that
+/// doesn't makes the result `Untrusted`, since certificates are then checked at the current time.
+fn verify_timestamp(signer: &SignerInfo, certs: &[ParsedCertificate], roots: &[ParsedCertificate], result: &mut Verification) -> Result<Option<DateTime<Utc>>> {
+    let Some(unsigned_attrs) = &signer.unsigned_attrs else {
+        return Ok(None);
+    };
+
+    for (typ, value) in attributes(unsigned_attrs)? {
+        let mut checked = Verification::new();
+        let time = match typ.as_str() {
+            OID_COUNTER_SIGNATURE => verify_countersignature(&value, signer.signature, certs, roots, &mut checked),
+            OID_RFC3161_TIMESTAMP => verify_token(&value, signer.signature, roots, &mut checked),
+            _ => continue,
+        };
+        let time = time.unwrap_or_else(|err| {
+            checked.note(VerifyStatus::Invalid, format!("malformed; {}", err.chain()));
+            None
+        });
+
+        result.details.extend(checked.details.iter().map(|detail| format!("Timestamp: {detail}")));
+        match time {
+            Some(time) if checked.status == VerifyStatus::Valid => {
+                result.note(VerifyStatus::Valid, format!("Timestamp: signed at {time}"));
+                return Ok(Some(time));
+            },
+            _ => result.note(VerifyStatus::Untrusted, format!("Timestamp is {:?}; not used", checked.status)),
+        }
+    }
+    Ok(None)
+}
+
+/// Verify the `SignedData` in `content_info`, at nesting `depth`, and the signatures nested in it.
+fn verify_signed_data(pe: &mut PeImage, content_info: &Tlv, roots: &[ParsedCertificate], depth: u32, result: &mut Verification) -> Result<()> {
+    check_nesting(depth, content_info.offset)?;
+    let mut info = content_info.reader();
+    info.expect(OID)?;
+    let mut signed = info.expect(CONTEXT_0)?.reader().expect(SEQUENCE)?.reader();
+    signed.expect(INTEGER)?;
+    signed.expect(SET)?;
+
+    //SpcIndirectDataContent: its value is what the messageDigest attribute covers.
+    let mut encap = signed.expect(SEQUENCE)?.reader();
+    encap.expect(OID)?;
+    let content = encap.expect(CONTEXT_0)?.reader().expect(SEQUENCE)?;
+    let mut spc = content.reader();
+    spc.expect(SEQUENCE)?;
+    let mut digest_info = spc.expect(SEQUENCE)?.reader();
+    let image_algorithm = algorithm(&mut digest_info)?;
+    let image_digest = digest_info.expect(OCTET_STRING)?.value;
+
+    match image_algorithm.hash_algorithm() {
+        Some(alg) if pe.authentihash(alg)? == image_digest => result.note(VerifyStatus::Valid, format!("Image digest ({image_algorithm}) matches")),
+        Some(_) => result.note(VerifyStatus::Invalid, format!("Image digest ({image_algorithm}) doesn't match")),
+        None => result.note(VerifyStatus::Untrusted, format!("Image digest {image_algorithm} is not supported")),
+    }
+
+    let certs = certificates(&mut signed)?;
+
+    //Authenticode has exactly one signer.
+    let Some(signer) = SignerInfo::parse(&signed.expect(SET)?.reader().expect(SEQUENCE)?)? else {
+        result.note(VerifyStatus::Untrusted, "Signer is identified by key identifier, which is not supported");
+        return Ok(());
+    };
+    let Some(cert) = verify_signer(&signer, content.value, &certs, result)? else {
+        return Ok(());
+    };
+    if result.signer.is_none() {
+        result.signer = cert.cert.common_name.clone();
+    }
+
+    //Time stamped signatures stay valid after the certificate expires.
+    let signed_at = verify_timestamp(&signer, &certs, roots, result)?;
+    verify_chain(cert, &certs, roots, KeyPurpose::CodeSigning, CheckTime::new(signed_at), result);
+
+    if let Some(unsigned_attrs) = &signer.unsigned_attrs {
+        for (typ, value) in attributes(unsigned_attrs)? {
+            if typ == OID_NESTED_SIGNATURE {
+                verify_signed_data(pe, &value, roots, depth + 1, result)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl PeImage {
+    /// Verify Authenticode signatures against `trust_roots`, DER encoded certificates.
+    ///
+    /// Malformed signatures are `Invalid`; `Err` is only returned if the file or a root can't
+    /// be read.
+    pub fn verify_signature(&mut self, trust_roots: &[&[u8]]) -> Result<Verification> {
+        let mut roots = Vec::with_capacity(trust_roots.len());
+        for root in trust_roots {
+            let tlv = DerReader::new(root, 0).expect(SEQUENCE)?;
+            roots.push(parse_certificate(&tlv)?);
+        }
+
+        let mut result = Verification::new();
+        let entries: Vec<_> = self.certificate_entries()?.into_iter()
+            .filter(|(cert, _)| cert.cert_type == CertificateType::PkcsSignedData)
+            .collect();
+        if entries.is_empty() {
+            result.note(VerifyStatus::Invalid, "Image is not signed");
+            return Ok(result);
+        }
+
+        for (cert, content) in entries {
+            let offset = cert.offset + super::CERTIFICATE_HEADER_LENGTH as u64;
+            let checked = DerReader::new(&content, offset).expect(SEQUENCE)
+                .and_then(|ci| verify_signed_data(self, &ci, &roots, 0, &mut result));
+            if let Err(err) = checked {
+                result.note(VerifyStatus::Invalid, format!("Signature @ {offset:#x} is malformed; {}", err.chain()));
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::pe::{security::{der::{DerReader, SEQUENCE}, parse_certificate, ParsedCertificate}, PeImage};
+
+    use super::{certificates, verify_chain, CheckTime, KeyPurpose, Verification, VerifyStatus};
+
+    fn root_g4() -> Vec<u8> {
+        let path = env::current_dir().unwrap().join("test-data").join("digicert-trusted-root-g4.der");
+        fs::read(path).unwrap()
+    }
+
+    #[test]
+    fn verify_test_dll() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut bytes = fs::read(path).unwrap();
+        let root = root_g4();
+
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        let result = pe.verify_signature(&[&root]).unwrap();
+        assert_eq!(result.status, VerifyStatus::Valid, "{result}");
+        assert_eq!(result.signer.as_deref(), Some("Valve Corp."));
+        assert_eq!(result.details.last().unwrap(),
+            "Chain: Valve Corp. <- DigiCert Trusted G4 Code Signing RSA4096 SHA384 2021 CA1 <- DigiCert Trusted Root G4 (trusted root)");
+
+        assert!(result.details.contains(&"Timestamp: Chain: DigiCert Timestamp 2023 <- DigiCert Trusted G4 RSA4096 SHA256 TimeStamping CA <- DigiCert Trusted Root G4 (trusted root)".to_string()));
+
+        //Without roots the timestamp is not trusted, so the expired signer is checked now.
+        let result = pe.verify_signature(&[]).unwrap();
+        assert_eq!(result.status, VerifyStatus::Invalid, "{result}");
+        assert!(result.details.contains(&"Timestamp is Untrusted; not used".to_string()));
+        assert!(result.details.iter().any(|d| d.starts_with("Certificate of Valve Corp. is not valid now")));
+
+        //Changed code.
+        bytes[0x400] ^= 0xff;
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        let result = pe.verify_signature(&[&root]).unwrap();
+        assert_eq!(result.status, VerifyStatus::Invalid);
+        assert!(result.details.contains(&"Image digest (SHA256) doesn't match".to_string()));
+        bytes[0x400] ^= 0xff;
+
+        //Changed last byte of the signer's encrypted digest, before the unsigned attributes.
+        bytes[0x14408 + 10807] ^= 0xff;
+        let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        let result = pe.verify_signature(&[&root]).unwrap();
+        assert_eq!(result.status, VerifyStatus::Invalid, "{result}");
+        assert!(result.details.contains(&"Signature of Valve Corp. doesn't match".to_string()));
+    }
+
+    #[test]
+    fn chain_constraints() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+        let (_, content) = pe.certificate_entries().unwrap().remove(0);
+        let root = root_g4();
+        let root = parse_certificate(&DerReader::new(&root, 0).expect(SEQUENCE).unwrap()).unwrap();
+
+        let mut info = DerReader::new(&content, 0).expect(SEQUENCE).unwrap().reader();
+        info.read().unwrap();
+        let mut signed = info.read().unwrap().reader().expect(SEQUENCE).unwrap().reader();
+        for _ in 0..3 {
+            signed.read().unwrap();
+        }
+        let certs = certificates(&mut signed).unwrap();
+        let leaf = certs.iter().find(|c| c.cert.common_name.as_deref() == Some("Valve Corp.")).unwrap();
+        let ca = certs.iter().find(|c| c.subject == leaf.issuer).unwrap();
+        assert!(!leaf.ca && ca.ca && root.ca);
+        assert!(leaf.key_purposes.as_ref().unwrap().iter().any(|p| p == KeyPurpose::CodeSigning.oid()));
+
+        let at = CheckTime::new(leaf.cert.not_before);
+        let mut result = Verification::new();
+        verify_chain(leaf, &certs, std::slice::from_ref(&root), KeyPurpose::CodeSigning, at, &mut result);
+        assert_eq!(result.status, VerifyStatus::Valid, "{result}");
+
+        //Wrong purpose.
+        let mut result = Verification::new();
+        verify_chain(leaf, &certs, std::slice::from_ref(&root), KeyPurpose::TimeStamping, at, &mut result);
+        assert_eq!(result.status, VerifyStatus::Invalid);
+        assert_eq!(result.details[0], "Certificate of Valve Corp. is not for TimeStamping");
+
+        //Intermediate before its validity.
+        let early = CheckTime::new(ca.cert.not_before.map(|t| t - chrono::Duration::days(1)));
+        let mut result = Verification::new();
+        verify_chain(leaf, &certs, std::slice::from_ref(&root), KeyPurpose::CodeSigning, early, &mut result);
+        assert_eq!(result.status, VerifyStatus::Invalid);
+        assert!(result.details.iter().any(|d| d.starts_with(&format!("Chain: certificate of {} is not valid", super::cn(ca)))));
+
+        //Issuer without basic constraints.
+        let not_ca = ParsedCertificate {
+            cert: ca.cert.clone(),
+            signature_algorithm: ca.signature_algorithm.clone(),
+            key_algorithm: ca.key_algorithm.clone(),
+            key_purposes: ca.key_purposes.clone(),
+            ca: false,
+            ..*ca
+        };
+        let mut result = Verification::new();
+        verify_chain(leaf, &[not_ca], std::slice::from_ref(&root), KeyPurpose::CodeSigning, at, &mut result);
+        assert_eq!(result.status, VerifyStatus::Invalid);
+        assert!(result.details.contains(&format!("Chain: {} is not a CA", super::cn(ca))));
+    }
+
+    #[test]
+    fn verify_unsigned() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+        let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        //Drop the security directory.
+        pe.data_dirs.value[crate::pe::optional::DirectoryType::Security as usize].value.size.value = 0;
+
+        let result = pe.verify_signature(&[]).unwrap();
+        assert_eq!(result.status, VerifyStatus::Invalid);
+        assert_eq!(result.details, ["Image is not signed"]);
+    }
+}
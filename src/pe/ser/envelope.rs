@@ -0,0 +1,137 @@
+//! Envelope of a serialized report: which version of rustbin made it, from which file and with
+//! which options, so reports can be reproduced and compared.
+//!
+//! Fields are serialized in the order they are declared and lists in the order of the file, so
+//! a report of the same file with the same options is the same bytes. Only `generated` changes
+//! between runs; leave it out for reports which are diffed.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+
+use crate::pe::{hashes::FileHashes, limits::ParseLimits, PeImage, Result};
+
+use super::{full::FullOptions, min::MinOptions};
+
+/// Version of the envelope layout; changes when its fields do.
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// The file a report is of.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileInfo {
+    /// File name, without its directory.
+    pub name: String,
+    pub size: u64,
+    #[serde(flatten)]
+    pub hashes: FileHashes,
+}
+
+impl FileInfo {
+    pub fn from_bytes(name: &str, bytes: &[u8]) -> Self {
+        Self { name: name.into(), size: bytes.len() as u64, hashes: FileHashes::of_bytes(bytes, true) }
+    }
+
+    /// Info of the whole input `pe` was parsed from, hashed through its reader.
+    pub fn of_image(name: &str, pe: &mut PeImage) -> Result<Self> {
+        Ok(Self { name: name.into(), size: pe.reader.input_end()?, hashes: pe.file_hashes(true)? })
+    }
+}
+
+/// Options a report was serialized with.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag="level", rename_all="lowercase")]
+pub enum ReportOptions {
+    Minimal(MinOptions),
+    Full(FullOptions),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Envelope<T> {
+    pub envelope_version: u32,
+    pub rustbin_version: &'static str,
+    /// When the report was made, RFC 3339 in UTC.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub generated: Option<String>,
+    pub file: FileInfo,
+    pub limits: ParseLimits,
+    pub options: ReportOptions,
+    pub report: T,
+}
+
+impl<T: Serialize> Envelope<T> {
+    /// Envelope of `report`, parsed with default limits.
+    pub fn new(report: T, file: FileInfo, options: ReportOptions) -> Self {
+        Self {
+            envelope_version: ENVELOPE_VERSION,
+            rustbin_version: env!("CARGO_PKG_VERSION"),
+            generated: None,
+            file,
+            limits: Default::default(),
+            options,
+            report,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: ParseLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn generated_at(mut self, time: DateTime<Utc>) -> Self {
+        self.generated = Some(time.to_rfc3339_opts(SecondsFormat::Secs, true));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::{Envelope, FileInfo, ReportOptions};
+
+    #[cfg(feature="json")]
+    #[test]
+    fn reports_are_reproducible() {
+        use std::{env, fs};
+
+        use crate::pe::{ser::min::{MinOptions, MinPeImage}, PeImage};
+
+        let enveloped = |bytes: &[u8]| {
+            let pe = PeImage::parse_bytes(bytes.to_vec(), 0).unwrap();
+            let options = MinOptions::default();
+            let report = MinPeImage::with_options(&pe, &options);
+            let envelope = Envelope::new(report, FileInfo::from_bytes("test.dll", bytes), ReportOptions::Minimal(options))
+                .with_limits(pe.limits);
+            serde_json::to_string_pretty(&envelope).unwrap()
+        };
+
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+
+        let json = enveloped(&bytes);
+        assert_eq!(json, enveloped(&bytes));
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["envelope_version"], 1);
+        assert_eq!(value["rustbin_version"], env!("CARGO_PKG_VERSION"));
+        assert!(value.get("generated").is_none());
+        assert_eq!(value["file"]["size"], bytes.len());
+        assert_eq!(value["file"]["sha256"].as_str().unwrap().len(), 64);
+        assert!(value["file"]["ssdeep"].as_str().unwrap().starts_with("1536:"));
+        assert_eq!(value["options"]["level"], "minimal");
+        assert_eq!(value["options"]["timestamps"], "both");
+
+        //Keys keep their declared order.
+        let keys = ["\"envelope_version\"", "\"rustbin_version\"", "\"file\"", "\"limits\"", "\"options\"", "\"report\""];
+        let positions: Vec<usize> = keys.iter().map(|k| json.find(k).unwrap()).collect();
+        assert!(positions.is_sorted());
+    }
+
+    #[test]
+    fn generated_time() {
+        let time = DateTime::from_timestamp(1_642_391_237, 0).unwrap();
+        let envelope = Envelope::new((), FileInfo::from_bytes("empty", &[]), ReportOptions::Full(Default::default()))
+            .generated_at(time);
+        assert_eq!(envelope.generated.as_deref(), Some("2022-01-17T03:47:17Z"));
+        assert_eq!(envelope.file.hashes.md5, "d41d8cd98f00b204e9800998ecf8427e");
+    }
+}
@@ -0,0 +1,1045 @@
+//! Serializers keeping everything known about a field: value, offset, rva and the bytes it was read from.
+//! Strings are kept without their terminator; raw bytes are serialized as a hex string.
+//! `FullPeImage::with_options` reads raw bytes from the input; `From` has no reader and
+//! encodes them from the parsed values instead.
+
+use serde::{Serialize, Serializer};
+
+use crate::{pe::{
+    dos::DosHeader,
+    export::{Export, ExportDirectory},
+    file::{FileHeader, MachineType},
+    nt::NtHeaders,
+    import::{IatMismatch, ImportBinding, ImportDescriptor, ImportLookup, ImportName},
+    optional::{x64::OptionalHeader64, x86::OptionalHeader32, DataDirectory, DirectoryType, ImageType, OptionalHeader, SubSystem},
+    relocs::{Reloc, RelocBlock, RelocType},
+    rsrc::{ResourceData, ResourceDirectory, ResourceEntry, ResourceNode, ResourceString, ResourceType},
+    section::{Alignment, SectionHeader},
+    PeImage, PeTimestamp}, types::{FileOffset, HeaderField}};
+
+/// Bytes of a value as stored in the image.
+pub trait RawBytes {
+    fn raw_bytes(&self) -> Vec<u8>;
+}
+
+macro_rules! impl_raw_bytes {
+    ($($t:ty),*) => {
+        $(impl RawBytes for $t {
+            fn raw_bytes(&self) -> Vec<u8> { self.to_le_bytes().to_vec() }
+        })*
+    };
+}
+
+impl_raw_bytes!(u8, u16, u32, u64);
+
+impl RawBytes for String {
+    fn raw_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl RawBytes for PeTimestamp {
+    fn raw_bytes(&self) -> Vec<u8> {
+        self.raw().to_le_bytes().to_vec()
+    }
+}
+
+impl RawBytes for ImageType {
+    fn raw_bytes(&self) -> Vec<u8> {
+        (*self as u16).to_le_bytes().to_vec()
+    }
+}
+
+impl<const N: usize> RawBytes for [u8; N] {
+    fn raw_bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl<const N: usize> RawBytes for [u16; N] {
+    fn raw_bytes(&self) -> Vec<u8> {
+        self.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+}
+
+fn as_hex<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&bytes.iter().map(|b| format!("{b:02x}")).collect::<String>())
+}
+
+
+/// `HeaderField` with the bytes the value was read from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct FieldEx<T> {
+    pub value: T,
+    pub offset: u64,
+    pub rva: u64,
+    #[serde(serialize_with="as_hex")]
+    #[cfg_attr(feature="schema", schemars(with="String"))]
+    pub raw: Vec<u8>,
+}
+
+impl<T> FieldEx<T> {
+    /// For values decoded from something other than their stored form, e.g. enums.
+    pub fn with_raw<U>(field: &HeaderField<U>, value: T, raw: Vec<u8>) -> Self {
+        Self { value, offset: field.offset.into(), rva: field.rva.into(), raw }
+    }
+}
+
+impl<T> From<&HeaderField<T>> for FieldEx<T> where T: RawBytes + Clone {
+    fn from(field: &HeaderField<T>) -> Self {
+        Self { value: field.value.clone(), offset: field.offset.into(), rva: field.rva.into(), raw: field.value.raw_bytes() }
+    }
+}
+
+/// Values holding `FieldEx`s, whose raw bytes `FullPeImage::with_options` reads from the input.
+trait RawFields {
+    /// Call `visit` with the offset and raw bytes of each `FieldEx`.
+    fn raw_fields(&mut self, visit: &mut dyn FnMut(u64, &mut Vec<u8>));
+}
+
+impl<T> RawFields for FieldEx<T> {
+    fn raw_fields(&mut self, visit: &mut dyn FnMut(u64, &mut Vec<u8>)) {
+        visit(self.offset, &mut self.raw);
+    }
+}
+
+impl<T: RawFields> RawFields for Vec<T> {
+    fn raw_fields(&mut self, visit: &mut dyn FnMut(u64, &mut Vec<u8>)) {
+        self.iter_mut().for_each(|item| item.raw_fields(visit));
+    }
+}
+
+impl<T: RawFields> RawFields for Option<T> {
+    fn raw_fields(&mut self, visit: &mut dyn FnMut(u64, &mut Vec<u8>)) {
+        if let Some(value) = self {
+            value.raw_fields(visit);
+        }
+    }
+}
+
+macro_rules! impl_raw_fields {
+    ($($t:ty { $($field:ident),* })*) => {
+        $(impl RawFields for $t {
+            fn raw_fields(&mut self, visit: &mut dyn FnMut(u64, &mut Vec<u8>)) {
+                $(self.$field.raw_fields(visit);)*
+            }
+        })*
+    };
+}
+
+/// Default number of bytes from the start of each directory kept in `DataDirectoryEx::head`.
+pub const DEFAULT_DIRECTORY_BYTES: usize = 16;
+
+/// Options of `FullPeImage::with_options`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct FullOptions {
+    /// Number of bytes read from the start of each present directory; `0` reads none.
+    pub directory_bytes: usize,
+}
+
+impl Default for FullOptions {
+    fn default() -> Self {
+        Self { directory_bytes: DEFAULT_DIRECTORY_BYTES }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct FullPeImage {
+    pub dos_header: DosHeaderEx,
+    pub nt_headers: NtHeadersEx,
+    pub file_header: FileHeaderEx,
+    pub optional_header: OptionalHeaderEx,
+    pub data_directories: Vec<DataDirectoryEx>,
+    pub sections: Vec<SectionHeaderEx>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub import_directories: Option<Vec<ImportDescriptorEx>>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub export_directory: Option<ExportDirectoryEx>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub relocations: Option<Vec<RelocBlockEx>>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub resources: Option<ResourceDirectoryEx>,
+}
+
+impl From<&PeImage> for FullPeImage {
+    fn from(value: &PeImage) -> Self {
+        Self {
+            dos_header: DosHeaderEx::from(&value.dos.value),
+            nt_headers: NtHeadersEx::from(&value.nt.value),
+            file_header: FileHeaderEx::from(&value.file.value),
+            optional_header: OptionalHeaderEx::from(&value.optional.value),
+
+            data_directories: value.data_dirs.value
+                .iter()
+                .map(|dir| {
+                    let mut dir_ex = DataDirectoryEx::from(&dir.value);
+                    dir_ex.file_offset = value.directory_file_offset(dir.value.member);
+                    dir_ex.section = dir_ex.file_offset
+                        .and_then(|offset| value.section_lookup().section_for_offset(offset))
+                        .map(|s| s.name_lossy());
+                    dir_ex
+                })
+                .collect(),
+
+            sections: value.sections.value
+                .iter()
+                .map(|s| SectionHeaderEx::from(&s.value))
+                .collect(),
+
+            import_directories: if value.has_imports() {
+                Some(
+                    value.imports.value
+                    .iter()
+                    .map(|id| ImportDescriptorEx::from(&id.value))
+                    .collect()
+                )} else { Option::None },
+
+            export_directory: if value.has_exports() {
+                    Some(ExportDirectoryEx::from(&value.exports.value))
+                } else { Option::None },
+
+            relocations: if value.has_relocations() {
+                Some(
+                    value.relocations.value.blocks
+                    .iter()
+                    .map(|rb| RelocBlockEx::from(&rb.value))
+                    .collect()
+                )} else { Option::None },
+
+            resources: if value.has_rsrc() {
+                    Some(ResourceDirectoryEx::from(&value.resources.value))
+                } else { Option::None },
+        }
+    }
+}
+
+impl FullPeImage {
+    /// Like `from`, with raw bytes of fields read from `pe` at their offsets and the first
+    /// `options.directory_bytes` of each directory. Fields which can not be read keep their
+    /// value's bytes and directories which can not be read are left without `head`.
+    pub fn with_options(pe: &mut PeImage, options: &FullOptions) -> Self {
+        let mut full = Self::from(&*pe);
+        full.raw_fields(&mut |offset, raw| {
+            if raw.is_empty() {
+                return;
+            }
+            match pe.reader.read_bytes_at_offset(offset, raw.len()) {
+                Ok(bytes) => *raw = bytes,
+                Err(err) => tracing::debug!(offset, %err, "failed to read raw bytes of field"),
+            }
+        });
+        if options.directory_bytes == 0 {
+            return full;
+        }
+
+        for dir in full.data_directories.iter_mut().filter(|dir| dir.file_offset.is_some()) {
+            match pe.read_directory_bytes(dir.member, options.directory_bytes) {
+                Ok(bytes) => dir.head = bytes,
+                Err(err) => tracing::warn!(directory = ?dir.member, %err, "failed to read directory bytes"),
+            }
+        }
+        full
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="dos_header")]
+pub struct DosHeaderEx {
+    pub e_magic: FieldEx<u16>,
+    pub e_cblp: FieldEx<u16>,
+    pub e_cp: FieldEx<u16>,
+    pub e_crlc: FieldEx<u16>,
+    pub e_cparhdr: FieldEx<u16>,
+    pub e_minalloc: FieldEx<u16>,
+    pub e_maxalloc: FieldEx<u16>,
+    pub e_ss: FieldEx<u16>,
+    pub e_sp: FieldEx<u16>,
+    pub e_csum: FieldEx<u16>,
+    pub e_ip: FieldEx<u16>,
+    pub e_cs: FieldEx<u16>,
+    pub e_lfarlc: FieldEx<u16>,
+    pub e_ovno: FieldEx<u16>,
+    pub e_res: FieldEx<[u16; 4]>,
+    pub e_oemid: FieldEx<u16>,
+    pub e_oeminfo: FieldEx<u16>,
+    pub e_res2: FieldEx<[u16; 10]>,
+    pub e_lfanew: FieldEx<u32>,
+}
+
+impl From<&DosHeader> for DosHeaderEx {
+    fn from(value: &DosHeader) -> Self {
+        Self {
+            e_magic: FieldEx::from(&value.e_magic),
+            e_cblp: FieldEx::from(&value.e_cblp),
+            e_cp: FieldEx::from(&value.e_cp),
+            e_crlc: FieldEx::from(&value.e_crlc),
+            e_cparhdr: FieldEx::from(&value.e_cparhdr),
+            e_minalloc: FieldEx::from(&value.e_minalloc),
+            e_maxalloc: FieldEx::from(&value.e_maxalloc),
+            e_ss: FieldEx::from(&value.e_ss),
+            e_sp: FieldEx::from(&value.e_sp),
+            e_csum: FieldEx::from(&value.e_csum),
+            e_ip: FieldEx::from(&value.e_ip),
+            e_cs: FieldEx::from(&value.e_cs),
+            e_lfarlc: FieldEx::from(&value.e_lfarlc),
+            e_ovno: FieldEx::from(&value.e_ovno),
+            e_res: FieldEx::from(&value.e_res),
+            e_oemid: FieldEx::from(&value.e_oemid),
+            e_oeminfo: FieldEx::from(&value.e_oeminfo),
+            e_res2: FieldEx::from(&value.e_res2),
+            e_lfanew: FieldEx::from(&value.e_lfanew),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="nt_headers")]
+pub struct NtHeadersEx {
+    pub signature: FieldEx<u32>,
+    pub file_header_offset: u64,
+    pub optional_header_offset: u64,
+    pub size: u64,
+}
+
+impl From<&NtHeaders> for NtHeadersEx {
+    fn from(value: &NtHeaders) -> Self {
+        Self {
+            signature: FieldEx::from(&value.signature),
+            file_header_offset: value.file_header_offset,
+            optional_header_offset: value.optional_header_offset,
+            size: value.size,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="file_header")]
+pub struct FileHeaderEx {
+    pub magic: FieldEx<u32>,
+    pub machine: FieldEx<MachineType>,
+    #[serde(rename="number_of_sections")]
+    pub sections: FieldEx<u16>,
+    pub timestamp: FieldEx<PeTimestamp>,
+    #[serde(rename="pointer_to_symbol_table")]
+    pub symbol_table_ptr: FieldEx<u32>,
+    #[serde(rename="number_of_symbols")]
+    pub symbols: FieldEx<u32>,
+    #[serde(rename="size_of_optional_header")]
+    pub optional_header_size: FieldEx<u16>,
+    pub charactristics: FieldEx<u16>,
+}
+
+impl From<&FileHeader> for FileHeaderEx {
+    fn from(value: &FileHeader) -> Self {
+        Self {
+            magic: FieldEx::from(&value.magic),
+            machine: FieldEx::with_raw(&value.machine, value.machine.value, value.machine_raw.raw_bytes()),
+            sections: FieldEx::from(&value.sections),
+            timestamp: FieldEx::from(&value.timestamp),
+            symbol_table_ptr: FieldEx::from(&value.symbol_table_ptr),
+            symbols: FieldEx::from(&value.symbols),
+            optional_header_size: FieldEx::from(&value.optional_header_size),
+            charactristics: FieldEx::from(&value.charactristics),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="optional_header")]
+pub struct OptionalHeader32Ex {
+    pub magic: FieldEx<ImageType>,
+    pub major_linker_ver: FieldEx<u8>,
+    pub minor_linker_ver: FieldEx<u8>,
+    pub sizeof_code: FieldEx<u32>,
+    pub sizeof_initiailized_data: FieldEx<u32>,
+    pub sizeof_uninitiailized_data: FieldEx<u32>,
+    pub address_of_entry_point: FieldEx<u32>,
+    pub base_of_code: FieldEx<u32>,
+    pub base_of_data: FieldEx<u32>,
+    pub image_base: FieldEx<u32>,
+    pub section_alignment: FieldEx<u32>,
+    pub file_alignment: FieldEx<u32>,
+    pub major_os_version: FieldEx<u16>,
+    pub minor_os_version: FieldEx<u16>,
+    pub major_image_version: FieldEx<u16>,
+    pub minor_image_version: FieldEx<u16>,
+    pub major_subsystem_version: FieldEx<u16>,
+    pub minor_subsystem_version: FieldEx<u16>,
+    pub win32_version: FieldEx<u32>,
+    pub sizeof_image: FieldEx<u32>,
+    pub sizeof_headers: FieldEx<u32>,
+    pub checksum: FieldEx<u32>,
+    pub subsystem: FieldEx<SubSystem>,
+    pub dll_charactristics: FieldEx<u16>,
+    pub sizeof_stack_reserve: FieldEx<u32>,
+    pub sizeof_stack_commit: FieldEx<u32>,
+    pub sizeof_heap_reserve: FieldEx<u32>,
+    pub sizeof_heap_commit: FieldEx<u32>,
+    pub loader_flags: FieldEx<u32>,
+    pub number_of_rva_and_sizes: FieldEx<u32>,
+}
+
+impl From<&OptionalHeader32> for OptionalHeader32Ex {
+    fn from(value: &OptionalHeader32) -> Self {
+        Self {
+            magic: FieldEx::from(&value.magic),
+            major_linker_ver: FieldEx::from(&value.major_linker_ver),
+            minor_linker_ver: FieldEx::from(&value.minor_linker_ver),
+            sizeof_code: FieldEx::from(&value.sizeof_code),
+            sizeof_initiailized_data: FieldEx::from(&value.sizeof_initiailized_data),
+            sizeof_uninitiailized_data: FieldEx::from(&value.sizeof_uninitiailized_data),
+            address_of_entry_point: FieldEx::from(&value.address_of_entry_point),
+            base_of_code: FieldEx::from(&value.base_of_code),
+            base_of_data: FieldEx::from(&value.base_of_data),
+            image_base: FieldEx::from(&value.image_base),
+            section_alignment: FieldEx::from(&value.section_alignment),
+            file_alignment: FieldEx::from(&value.file_alignment),
+            major_os_version: FieldEx::from(&value.major_os_version),
+            minor_os_version: FieldEx::from(&value.minor_os_version),
+            major_image_version: FieldEx::from(&value.major_image_version),
+            minor_image_version: FieldEx::from(&value.minor_image_version),
+            major_subsystem_version: FieldEx::from(&value.major_subsystem_version),
+            minor_subsystem_version: FieldEx::from(&value.minor_subsystem_version),
+            win32_version: FieldEx::from(&value.win32_version),
+            sizeof_image: FieldEx::from(&value.sizeof_image),
+            sizeof_headers: FieldEx::from(&value.sizeof_headers),
+            checksum: FieldEx::from(&value.checksum),
+            subsystem: FieldEx::with_raw(&value.subsystem, value.subsystem.value, value.subsystem_raw.raw_bytes()),
+            dll_charactristics: FieldEx::from(&value.dll_charactristics),
+            sizeof_stack_reserve: FieldEx::from(&value.sizeof_stack_reserve),
+            sizeof_stack_commit: FieldEx::from(&value.sizeof_stack_commit),
+            sizeof_heap_reserve: FieldEx::from(&value.sizeof_heap_reserve),
+            sizeof_heap_commit: FieldEx::from(&value.sizeof_heap_commit),
+            loader_flags: FieldEx::from(&value.loader_flags),
+            number_of_rva_and_sizes: FieldEx::from(&value.number_of_rva_and_sizes),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="optional_header")]
+pub struct OptionalHeader64Ex {
+    pub magic: FieldEx<ImageType>,
+    pub major_linker_ver: FieldEx<u8>,
+    pub minor_linker_ver: FieldEx<u8>,
+    pub sizeof_code: FieldEx<u32>,
+    pub sizeof_initiailized_data: FieldEx<u32>,
+    pub sizeof_uninitiailized_data: FieldEx<u32>,
+    pub address_of_entry_point: FieldEx<u32>,
+    pub base_of_code: FieldEx<u32>,
+    pub image_base: FieldEx<u64>,
+    pub section_alignment: FieldEx<u32>,
+    pub file_alignment: FieldEx<u32>,
+    pub major_os_version: FieldEx<u16>,
+    pub minor_os_version: FieldEx<u16>,
+    pub major_image_version: FieldEx<u16>,
+    pub minor_image_version: FieldEx<u16>,
+    pub major_subsystem_version: FieldEx<u16>,
+    pub minor_subsystem_version: FieldEx<u16>,
+    pub win32_version: FieldEx<u32>,
+    pub sizeof_image: FieldEx<u32>,
+    pub sizeof_headers: FieldEx<u32>,
+    pub checksum: FieldEx<u32>,
+    pub subsystem: FieldEx<SubSystem>,
+    pub dll_charactristics: FieldEx<u16>,
+    pub sizeof_stack_reserve: FieldEx<u64>,
+    pub sizeof_stack_commit: FieldEx<u64>,
+    pub sizeof_heap_reserve: FieldEx<u64>,
+    pub sizeof_heap_commit: FieldEx<u64>,
+    pub loader_flags: FieldEx<u32>,
+    pub number_of_rva_and_sizes: FieldEx<u32>,
+}
+
+impl From<&OptionalHeader64> for OptionalHeader64Ex {
+    fn from(value: &OptionalHeader64) -> Self {
+        Self {
+            magic: FieldEx::from(&value.magic),
+            major_linker_ver: FieldEx::from(&value.major_linker_ver),
+            minor_linker_ver: FieldEx::from(&value.minor_linker_ver),
+            sizeof_code: FieldEx::from(&value.sizeof_code),
+            sizeof_initiailized_data: FieldEx::from(&value.sizeof_initiailized_data),
+            sizeof_uninitiailized_data: FieldEx::from(&value.sizeof_uninitiailized_data),
+            address_of_entry_point: FieldEx::from(&value.address_of_entry_point),
+            base_of_code: FieldEx::from(&value.base_of_code),
+            image_base: FieldEx::from(&value.image_base),
+            section_alignment: FieldEx::from(&value.section_alignment),
+            file_alignment: FieldEx::from(&value.file_alignment),
+            major_os_version: FieldEx::from(&value.major_os_version),
+            minor_os_version: FieldEx::from(&value.minor_os_version),
+            major_image_version: FieldEx::from(&value.major_image_version),
+            minor_image_version: FieldEx::from(&value.minor_image_version),
+            major_subsystem_version: FieldEx::from(&value.major_subsystem_version),
+            minor_subsystem_version: FieldEx::from(&value.minor_subsystem_version),
+            win32_version: FieldEx::from(&value.win32_version),
+            sizeof_image: FieldEx::from(&value.sizeof_image),
+            sizeof_headers: FieldEx::from(&value.sizeof_headers),
+            checksum: FieldEx::from(&value.checksum),
+            subsystem: FieldEx::with_raw(&value.subsystem, value.subsystem.value, value.subsystem_raw.raw_bytes()),
+            dll_charactristics: FieldEx::from(&value.dll_charactristics),
+            sizeof_stack_reserve: FieldEx::from(&value.sizeof_stack_reserve),
+            sizeof_stack_commit: FieldEx::from(&value.sizeof_stack_commit),
+            sizeof_heap_reserve: FieldEx::from(&value.sizeof_heap_reserve),
+            sizeof_heap_commit: FieldEx::from(&value.sizeof_heap_commit),
+            loader_flags: FieldEx::from(&value.loader_flags),
+            number_of_rva_and_sizes: FieldEx::from(&value.number_of_rva_and_sizes),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="optional_header")]
+pub enum OptionalHeaderEx {
+    #[serde(untagged)]
+    X86(OptionalHeader32Ex),
+    #[serde(untagged)]
+    X64(OptionalHeader64Ex),
+}
+
+impl From<&OptionalHeader> for OptionalHeaderEx {
+    fn from(value: &OptionalHeader) -> Self {
+        match value {
+            OptionalHeader::X86(opt) => Self::X86(OptionalHeader32Ex::from(opt)),
+            OptionalHeader::X64(opt) => Self::X64(OptionalHeader64Ex::from(opt)),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="data_directory")]
+pub struct DataDirectoryEx {
+    #[serde(rename="type")]
+    pub member: DirectoryType,
+    pub rva: FieldEx<u32>,
+    pub size: FieldEx<u32>,
+    /// Where the directory is in the file; the rva of `Security` is already a file offset.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub file_offset: Option<u32>,
+    /// Name of the section containing `file_offset`.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub section: Option<String>,
+    /// First bytes of the directory, as stored in the file. See `FullOptions::directory_bytes`.
+    #[serde(serialize_with="as_hex", skip_serializing_if="Vec::is_empty")]
+    #[cfg_attr(feature="schema", schemars(with="String"))]
+    pub head: Vec<u8>,
+}
+
+impl From<&DataDirectory> for DataDirectoryEx {
+    fn from(value: &DataDirectory) -> Self {
+        Self {
+            member: value.member,
+            rva: FieldEx::from(&value.rva),
+            size: FieldEx::from(&value.size),
+            file_offset: None,
+            section: None,
+            head: Vec::new(),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="section")]
+pub struct SectionHeaderEx {
+    pub name: FieldEx<String>,
+    pub virtual_size: FieldEx<u32>,
+    pub virtual_address: FieldEx<u32>,
+    #[serde(rename="size_of_raw_data")]
+    pub sizeof_raw_data: FieldEx<u32>,
+    #[serde(rename="pointer_to_raw_data")]
+    pub raw_data_ptr: FieldEx<u32>,
+    #[serde(rename="pointer_to_relocations")]
+    pub relocs_ptr: FieldEx<u32>,
+    #[serde(rename="pointer_to_line_numbers")]
+    pub line_num_ptr: FieldEx<u32>,
+    #[serde(rename="number_of_relocations")]
+    pub relocs_count: FieldEx<u16>,
+    #[serde(rename="number_of_line_numbers")]
+    pub line_num_count: FieldEx<u16>,
+    pub charactristics: FieldEx<u32>,
+    /// `IMAGE_SCN_ALIGN_*` of `charactristics`, in bytes.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub alignment: Option<Alignment>,
+}
+
+impl From<&SectionHeader> for SectionHeaderEx {
+    fn from(value: &SectionHeader) -> Self {
+        let name = String::from_utf8_lossy(&value.name.value).trim_end_matches('\0').to_string();
+        Self {
+            name: FieldEx::with_raw(&value.name, name, value.name.value.raw_bytes()),
+            virtual_size: FieldEx::from(&value.virtual_size),
+            virtual_address: FieldEx::from(&value.virtual_address),
+            sizeof_raw_data: FieldEx::from(&value.sizeof_raw_data),
+            raw_data_ptr: FieldEx::from(&value.raw_data_ptr),
+            relocs_ptr: FieldEx::from(&value.relocs_ptr),
+            line_num_ptr: FieldEx::from(&value.line_num_ptr),
+            relocs_count: FieldEx::from(&value.relocs_count),
+            line_num_count: FieldEx::from(&value.line_num_count),
+            charactristics: FieldEx::from(&value.charactristics),
+            alignment: value.alignment(),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="import_name")]
+pub struct ImportNameEx {
+    pub hint: FieldEx<u16>,
+    pub name: FieldEx<String>,
+}
+
+impl From<&ImportName> for ImportNameEx {
+    fn from(value: &ImportName) -> Self {
+        Self { hint: FieldEx::from(&value.hint), name: FieldEx::from(&value.name) }
+    }
+}
+
+
+/// An entry of the import lookup table; 4 bytes in PE32, 8 bytes in PE32+ images.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="import_lookup")]
+pub struct ImportLookupEx {
+    pub value: FieldEx<u64>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub ordinal: Option<u16>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub name: Option<ImportNameEx>,
+    /// RVA of the IAT slot patched at load time.
+    pub iat_rva: u32,
+}
+
+impl From<&ImportLookup> for ImportLookupEx {
+    fn from(value: &ImportLookup) -> Self {
+        match value {
+            ImportLookup::X86(lookup) => Self {
+                value: FieldEx::with_raw(&lookup.value, lookup.value.value as u64, lookup.value.value.raw_bytes()),
+                ordinal: lookup.ordinal,
+                name: lookup.iname.as_ref().map(|iname| ImportNameEx::from(&iname.value)),
+                iat_rva: lookup.iat_rva,
+            },
+            ImportLookup::X64(lookup) => Self {
+                value: FieldEx::from(&lookup.value),
+                ordinal: lookup.ordinal,
+                name: lookup.iname.as_ref().map(|iname| ImportNameEx::from(&iname.value)),
+                iat_rva: lookup.iat_rva,
+            },
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="import_descriptor")]
+pub struct ImportDescriptorEx {
+    #[serde(rename="original_first_thunk")]
+    pub ilt: FieldEx<u32>,
+    pub timestamp: FieldEx<PeTimestamp>,
+    /// What `timestamp` means.
+    pub binding: ImportBinding,
+    pub forwarder_chain: FieldEx<u32>,
+    pub name_rva: FieldEx<u32>,
+    pub first_thunk: FieldEx<u32>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub name: Option<String>,
+    pub imports: Vec<ImportLookupEx>,
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub iat_mismatches: Vec<IatMismatch>,
+}
+
+impl From<&ImportDescriptor> for ImportDescriptorEx {
+    fn from(value: &ImportDescriptor) -> Self {
+        Self {
+            ilt: FieldEx::from(&value.ilt),
+            timestamp: FieldEx::from(&value.timestamp),
+            binding: value.binding(),
+            forwarder_chain: FieldEx::from(&value.forwarder_chain),
+            name_rva: FieldEx::from(&value.name_rva),
+            first_thunk: FieldEx::from(&value.first_thunk),
+            name: value.name.clone(),
+            imports: value.imports
+                .iter()
+                .map(ImportLookupEx::from)
+                .collect(),
+            iat_mismatches: value.iat_mismatches.clone(),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="export")]
+pub struct ExportEx {
+    pub name: FieldEx<String>,
+    pub address: FieldEx<u32>,
+    pub ordinal: FieldEx<u16>,
+}
+
+impl From<&Export> for ExportEx {
+    fn from(value: &Export) -> Self {
+        let mut export = Self {
+            name: FieldEx::from(&value.name),
+            address: FieldEx::from(&value.address),
+            ordinal: FieldEx::from(&value.ordinal),
+        };
+        //Exports without a name have no name or ordinal table entry to read.
+        if value.name.offset == FileOffset(0) {
+            export.name.raw.clear();
+            export.ordinal.raw.clear();
+        }
+        export
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="export_directory")]
+pub struct ExportDirectoryEx {
+    pub charatristics: FieldEx<u32>,
+    pub timestamp: FieldEx<PeTimestamp>,
+    pub major_version: FieldEx<u16>,
+    pub minor_version: FieldEx<u16>,
+    pub name_rva: FieldEx<u32>,
+    pub base: FieldEx<u32>,
+    pub number_of_functions: FieldEx<u32>,
+    pub number_of_names: FieldEx<u32>,
+    pub address_of_functions: FieldEx<u32>,
+    pub address_of_names: FieldEx<u32>,
+    pub address_of_name_ordinals: FieldEx<u32>,
+    pub name: String,
+    pub exports: Vec<ExportEx>,
+}
+
+impl From<&ExportDirectory> for ExportDirectoryEx {
+    fn from(value: &ExportDirectory) -> Self {
+        Self {
+            charatristics: FieldEx::from(&value.charatristics),
+            timestamp: FieldEx::from(&value.timestamp),
+            major_version: FieldEx::from(&value.major_version),
+            minor_version: FieldEx::from(&value.minor_version),
+            name_rva: FieldEx::from(&value.name_rva),
+            base: FieldEx::from(&value.base),
+            number_of_functions: FieldEx::from(&value.number_of_functions),
+            number_of_names: FieldEx::from(&value.number_of_names),
+            address_of_functions: FieldEx::from(&value.address_of_functions),
+            address_of_names: FieldEx::from(&value.address_of_names),
+            address_of_name_ordinals: FieldEx::from(&value.address_of_name_ordinals),
+            name: value.name.clone(),
+            exports: value.exports
+                .iter()
+                .map(ExportEx::from)
+                .collect(),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="relocation")]
+pub struct RelocEx {
+    #[serde(rename="type")]
+    pub rtype: RelocType,
+    #[serde(rename="offset")]
+    pub rva: u16,
+    /// RVA patched by the relocation.
+    #[serde(rename="rva")]
+    pub target_rva: u32,
+    /// Whole entry; type and offset share the two bytes.
+    pub entry: FieldEx<u16>,
+}
+
+impl From<&HeaderField<Reloc>> for RelocEx {
+    fn from(value: &HeaderField<Reloc>) -> Self {
+        let raw = value.value.raw();
+        Self {
+            rtype: value.value.rtype,
+            rva: value.value.rva,
+            target_rva: value.value.target_rva,
+            entry: FieldEx::with_raw(value, raw, raw.raw_bytes()),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="relocation_block")]
+pub struct RelocBlockEx {
+    pub virtual_address: FieldEx<u32>,
+    pub size: FieldEx<u32>,
+    pub relocations: Vec<RelocEx>,
+}
+
+impl From<&RelocBlock> for RelocBlockEx {
+    fn from(value: &RelocBlock) -> Self {
+        Self {
+            virtual_address: FieldEx::from(&value.va),
+            size: FieldEx::from(&value.size),
+            relocations: value.relocs
+                .iter()
+                .map(RelocEx::from)
+                .collect(),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="resource_data")]
+pub struct ResourceDataEx {
+    pub rva: FieldEx<u32>,
+    pub size: FieldEx<u32>,
+    pub code_page: FieldEx<u32>,
+}
+
+impl From<&ResourceData> for ResourceDataEx {
+    fn from(value: &ResourceData) -> Self {
+        Self {
+            rva: FieldEx::from(&value.rva),
+            size: FieldEx::from(&value.size),
+            code_page: FieldEx::from(&value.code_page),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="resource_string")]
+pub struct ResourceStringEx {
+    pub length: FieldEx<u16>,
+    /// Raw bytes are UTF-16LE, as stored.
+    pub value: FieldEx<String>,
+}
+
+impl From<&ResourceString> for ResourceStringEx {
+    fn from(value: &ResourceString) -> Self {
+        let raw = value.value.value.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+        Self {
+            length: FieldEx::from(&value.length),
+            value: FieldEx::with_raw(&value.value, value.value.value.clone(), raw),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum ResourceNodeEx {
+    Data(ResourceDataEx),
+    Dir(ResourceDirectoryEx),
+}
+
+impl ResourceNodeEx {
+    fn at_level(value: &ResourceNode, level: u8) -> Self {
+        match value {
+            ResourceNode::Data(data) => Self::Data(ResourceDataEx::from(data)),
+            ResourceNode::Dir(dir) => Self::Dir(ResourceDirectoryEx::at_level(dir, level)),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="Entry")]
+pub struct ResourceEntryEx {
+    /// Only for entries of the root directory.
+    #[serde(rename="type", skip_serializing_if="Option::is_none")]
+    pub resource_type: Option<ResourceType>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub id: Option<u32>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub name: Option<ResourceStringEx>,
+    pub name_offset: FieldEx<u32>,
+    pub data_offset: FieldEx<u32>,
+    #[serde(flatten)]
+    pub data: ResourceNodeEx,
+}
+
+impl ResourceEntryEx {
+    fn at_level(value: &ResourceEntry, level: u8) -> Self {
+        Self {
+            resource_type: value.resource_type().filter(|_| level == 0),
+            id: value.id,
+            name: value.name.as_ref().map(ResourceStringEx::from),
+            name_offset: FieldEx::from(&value.name_offset),
+            data_offset: FieldEx::from(&value.data_offset),
+            data: ResourceNodeEx::at_level(&value.data, level + 1),
+        }
+    }
+}
+
+impl From<&ResourceEntry> for ResourceEntryEx {
+    fn from(value: &ResourceEntry) -> Self {
+        Self::at_level(value, 0)
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="resource_directory")]
+pub struct ResourceDirectoryEx {
+    pub charactristics: FieldEx<u32>,
+    pub timestamp: FieldEx<PeTimestamp>,
+    pub major_version: FieldEx<u16>,
+    pub minor_version: FieldEx<u16>,
+    #[serde(rename="number_of_named_entries")]
+    pub named_entry_count: FieldEx<u16>,
+    #[serde(rename="number_of_id_entries")]
+    pub id_entry_count: FieldEx<u16>,
+    pub entries: Vec<ResourceEntryEx>,
+}
+
+impl ResourceDirectoryEx {
+    fn at_level(value: &ResourceDirectory, level: u8) -> Self {
+        Self {
+            charactristics: FieldEx::from(&value.charactristics),
+            timestamp: FieldEx::from(&value.timestamp),
+            major_version: FieldEx::from(&value.major_version),
+            minor_version: FieldEx::from(&value.minor_version),
+            named_entry_count: FieldEx::from(&value.named_entry_count),
+            id_entry_count: FieldEx::from(&value.id_entry_count),
+            entries: value.entries
+                .iter()
+                .map(|e| ResourceEntryEx::at_level(e, level))
+                .collect(),
+        }
+    }
+}
+
+impl From<&ResourceDirectory> for ResourceDirectoryEx {
+    fn from(value: &ResourceDirectory) -> Self {
+        Self::at_level(value, 0)
+    }
+}
+
+impl_raw_fields! {
+    FullPeImage { dos_header, nt_headers, file_header, optional_header, data_directories, sections, import_directories, export_directory, relocations, resources }
+    DosHeaderEx { e_magic, e_cblp, e_cp, e_crlc, e_cparhdr, e_minalloc, e_maxalloc, e_ss, e_sp, e_csum, e_ip, e_cs, e_lfarlc, e_ovno, e_res, e_oemid, e_oeminfo, e_res2, e_lfanew }
+    NtHeadersEx { signature }
+    FileHeaderEx { magic, machine, sections, timestamp, symbol_table_ptr, symbols, optional_header_size, charactristics }
+    OptionalHeader32Ex { magic, major_linker_ver, minor_linker_ver, sizeof_code, sizeof_initiailized_data, sizeof_uninitiailized_data, address_of_entry_point, base_of_code, base_of_data, image_base, section_alignment, file_alignment, major_os_version, minor_os_version, major_image_version, minor_image_version, major_subsystem_version, minor_subsystem_version, win32_version, sizeof_image, sizeof_headers, checksum, subsystem, dll_charactristics, sizeof_stack_reserve, sizeof_stack_commit, sizeof_heap_reserve, sizeof_heap_commit, loader_flags, number_of_rva_and_sizes }
+    OptionalHeader64Ex { magic, major_linker_ver, minor_linker_ver, sizeof_code, sizeof_initiailized_data, sizeof_uninitiailized_data, address_of_entry_point, base_of_code, image_base, section_alignment, file_alignment, major_os_version, minor_os_version, major_image_version, minor_image_version, major_subsystem_version, minor_subsystem_version, win32_version, sizeof_image, sizeof_headers, checksum, subsystem, dll_charactristics, sizeof_stack_reserve, sizeof_stack_commit, sizeof_heap_reserve, sizeof_heap_commit, loader_flags, number_of_rva_and_sizes }
+    DataDirectoryEx { rva, size }
+    SectionHeaderEx { name, virtual_size, virtual_address, sizeof_raw_data, raw_data_ptr, relocs_ptr, line_num_ptr, relocs_count, line_num_count, charactristics }
+    ImportNameEx { hint, name }
+    ImportLookupEx { value, name }
+    ImportDescriptorEx { ilt, timestamp, forwarder_chain, name_rva, first_thunk, imports }
+    ExportEx { name, address, ordinal }
+    ExportDirectoryEx { charatristics, timestamp, major_version, minor_version, name_rva, base, number_of_functions, number_of_names, address_of_functions, address_of_names, address_of_name_ordinals, exports }
+    RelocEx { entry }
+    RelocBlockEx { virtual_address, size, relocations }
+    ResourceDataEx { rva, size, code_page }
+    ResourceStringEx { length, value }
+    ResourceEntryEx { name, name_offset, data_offset, data }
+    ResourceDirectoryEx { charactristics, timestamp, major_version, minor_version, named_entry_count, id_entry_count, entries }
+}
+
+impl RawFields for OptionalHeaderEx {
+    fn raw_fields(&mut self, visit: &mut dyn FnMut(u64, &mut Vec<u8>)) {
+        match self {
+            Self::X86(opt) => opt.raw_fields(visit),
+            Self::X64(opt) => opt.raw_fields(visit),
+        }
+    }
+}
+
+impl RawFields for ResourceNodeEx {
+    fn raw_fields(&mut self, visit: &mut dyn FnMut(u64, &mut Vec<u8>)) {
+        match self {
+            Self::Data(data) => data.raw_fields(visit),
+            Self::Dir(dir) => dir.raw_fields(visit),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::Cursor};
+
+    use serde_test::{assert_ser_tokens, Token};
+
+    use crate::{pe::{relocs::{RelocBlock, RelocType}, PeImage}, types::{Header, HeaderField}};
+
+    use super::{FieldEx, FullOptions, FullPeImage, RelocBlockEx};
+
+    #[test]
+    fn field_raw_bytes_as_hex() {
+        let field = HeaderField::at_offset(0x5a4du16, 0);
+        assert_ser_tokens(&FieldEx::from(&field), &[
+            Token::Struct { name: "FieldEx", len: 4 },
+            Token::Str("value"), Token::U16(0x5a4d),
+            Token::Str("offset"), Token::U64(0),
+            Token::Str("rva"), Token::U64(0),
+            Token::Str("raw"), Token::Str("4d5a"),
+            Token::StructEnd,
+        ]);
+    }
+
+    #[test]
+    fn raw_bytes_read_from_input() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut bytes = fs::read(path).unwrap();
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        let options = FullOptions { directory_bytes: 0 };
+
+        //Every field is read where its value was parsed from.
+        let full = FullPeImage::with_options(&mut pe, &options);
+        assert_eq!(full, FullPeImage::from(&pe));
+        assert!(full.relocations.is_some() && full.export_directory.is_some());
+
+        bytes[2] = 0xff;
+        pe.reader = Box::new(Cursor::new(bytes));
+        let full = FullPeImage::with_options(&mut pe, &options);
+        assert_eq!(full.dos_header.e_cblp.value, 0x90);
+        assert_eq!(full.dos_header.e_cblp.raw, vec![0xff, 0x00]);
+    }
+
+    #[test]
+    fn reloc_block_raw_entries() {
+        let bytes = vec![0x00, 0x10, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00];
+        let mut block = RelocBlock::parse_bytes(bytes, 0x400).unwrap();
+        block.parse_relocs(&[0x10, 0xA0, 0x18, 0xA0], 0x408).unwrap();
+        let block_ex = RelocBlockEx::from(&block);
+
+        assert_eq!(block_ex.virtual_address.value, 0x1000);
+        assert_eq!(block_ex.virtual_address.raw, vec![0x00, 0x10, 0x00, 0x00]);
+        assert_eq!(block_ex.size.offset, 0x404);
+        assert_eq!(block_ex.relocations.len(), 2);
+
+        let second = &block_ex.relocations[1];
+        assert_eq!(second.rtype, RelocType::DIR64);
+        assert_eq!(second.rva, 0x18);
+        assert_eq!(second.target_rva, 0x1018);
+        assert_eq!(second.entry.value, 0xA018);
+        assert_eq!(second.entry.raw, vec![0x18, 0xA0]);
+        assert_eq!(second.entry.offset, 0x40A);
+    }
+}
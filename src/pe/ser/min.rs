@@ -1,22 +1,54 @@
-use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+
 use serde::Serialize;
 
 use crate::pe::{
+    analysis::AnalysisReport,
     dos::DosHeader, 
     export::ExportDirectory, 
     file::{self, FileHeader, MachineType}, 
-    import::{x64::ImportLookup64, x86::ImportLookup32, ImportDescriptor, ImportLookup}, 
+    nt::NtHeaders,
+    debug::ExDllFlags,
+    hybrid::HybridView,
+    import::{x64::ImportLookup64, x86::ImportLookup32, IatMismatch, ImportBinding, ImportDescriptor, ImportLookup}, 
+    loadcfg::{chpe::{ChpeMetadata, CodeRange, EntryPointRange, Redirection}, GuardFlags},
     optional::{self, x64::OptionalHeader64, x86::OptionalHeader32, OptionalHeader}, 
     rsrc::{ResourceDirectory, ResourceEntry, ResourceNode, ResourceType}, 
     section::{self, SectionHeader}, 
+    te::{TeHeader, TeImage},
     PeImage};
 
-use super::{DataDirValue, ExportValue, RelocBlockValue, ResourceDataValue, ResourceStringValue};
+use super::{DataDirValue, ExportValue, RelocBlockValue, ResourceDataValue, TimestampFormat, TimestampValue, Truncated};
+
+/// Default maximum number of exports serialized by `MinExportDirectory::from`.
+pub const DEFAULT_MAX_SERIALIZED_EXPORTS: usize = 0x4000;
+
+/// Options of the `Min*` conversions, for the `with_options` constructors.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MinOptions {
+    /// Add on-disk values next to decoded enums and flags (`machine_raw`, `subsystem_raw`, ...),
+    /// so values unknown to the decoders can be recovered.
+    pub raw_values: bool,
+    /// Maximum number of exports serialized.
+    pub max_exports: usize,
+    /// Form of serialized timestamps.
+    pub timestamps: TimestampFormat,
+    /// Add every field of the DOS header, not only `e_lfanew`.
+    pub dos_fields: bool,
+}
+
+impl Default for MinOptions {
+    fn default() -> Self {
+        Self { raw_values: false, max_exports: DEFAULT_MAX_SERIALIZED_EXPORTS, timestamps: TimestampFormat::default(), dos_fields: false }
+    }
+}
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 pub struct MinPeImage {
     pub dos_header: MinDosHeader,
+    pub nt_headers: MinNtHeaders,
     pub file_hedaer: MinFileHeader,
     pub optional_header: MinOptionalHeader,
     pub data_directories: Vec<DataDirValue>,
@@ -24,19 +56,40 @@ pub struct MinPeImage {
     #[serde(skip_serializing_if="Option::is_none")]
     pub import_directories: Option<Vec<MinImportDescriptor>>,
     #[serde(skip_serializing_if="Option::is_none")]
+    pub import_counts: Option<ImportCounts>,
+    #[serde(skip_serializing_if="Option::is_none")]
     pub export_directory: Option<MinExportDirectory>,
     #[serde(skip_serializing_if="Option::is_none")]
     pub relocations: Option<Vec<RelocBlockValue>>,
     #[serde(skip_serializing_if="Option::is_none")]
     pub resources: Option<MinRsrcDirectory>,
+    /// Control flow guard and related mitigations, from the load config.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub guard_flags: Option<GuardFlags>,
+    /// Extended DLL characteristics (CET compatibility), from the debug directory.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub ex_dll_charactristics: Option<ExDllFlags>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub chpe: Option<MinChpeMetadata>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub hybrid_views: Option<Vec<HybridView>>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub analysis: Option<AnalysisReport>,
 }
 
 impl From<&PeImage> for MinPeImage {
     fn from(value: &PeImage) -> Self {
+        Self::with_options(value, &MinOptions::default())
+    }
+}
+
+impl MinPeImage {
+    pub fn with_options(value: &PeImage, options: &MinOptions) -> Self {
         Self { 
-            dos_header: MinDosHeader::from(&value.dos.value),
-            file_hedaer: MinFileHeader::from(&value.file.value),
-            optional_header: MinOptionalHeader::from(&value.optional.value),
+            dos_header: MinDosHeader::with_options(&value.dos.value, options),
+            nt_headers: MinNtHeaders::from(&value.nt.value),
+            file_hedaer: MinFileHeader::with_options(&value.file.value, options),
+            optional_header: MinOptionalHeader::with_options(&value.optional.value, options),
             
             data_directories: value.data_dirs.value
                 .iter()
@@ -57,8 +110,12 @@ impl From<&PeImage> for MinPeImage {
                     .collect()
                 )} else { Option::None },
 
+            import_counts: if value.has_imports() {
+                    Some(ImportCounts::of(value.imports.value.iter().map(|id| &id.value)))
+                } else { Option::None },
+
             export_directory: if value.has_exports() {
-                    Some(MinExportDirectory::from(&value.exports.value))
+                    Some(MinExportDirectory::with_options(&value.exports.value, options))
                 } else { Option::None },
             
             relocations: if value.has_relocations() { 
@@ -71,32 +128,216 @@ impl From<&PeImage> for MinPeImage {
 
             resources: if value.has_rsrc() {
                     Some( MinRsrcDirectory::from(&value.resources.value))
-                } else { Option::None }
+                } else { Option::None },
+
+            guard_flags: if value.has_load_config() {
+                    Some(value.load_config.value.guard_flags())
+                } else { Option::None },
+
+            ex_dll_charactristics: value.debug.value.ex_dll_flags(),
+
+            chpe: if value.has_chpe() {
+                    Some(MinChpeMetadata::from(&value.chpe.value))
+                } else { Option::None },
+
+            hybrid_views: if value.has_chpe() {
+                    Some(value.hybrid_views())
+                } else { Option::None },
+
+            analysis: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct MinTeImage {
+    pub te_header: MinTeHeader,
+    pub sections: Vec<MinSectionHeader>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub relocations: Option<Vec<RelocBlockValue>>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub analysis: Option<AnalysisReport>,
+}
+
+impl From<&TeImage> for MinTeImage {
+    fn from(value: &TeImage) -> Self {
+        Self::with_options(value, &MinOptions::default())
+    }
+}
+
+impl MinTeImage {
+    pub fn with_options(value: &TeImage, options: &MinOptions) -> Self {
+        Self {
+            te_header: MinTeHeader::with_options(&value.header.value, options),
+
+            sections: value.sections.value
+                .iter()
+                .map(|s| MinSectionHeader::from(&s.value))
+                .collect(),
+
+            relocations: if value.has_relocations() {
+                Some(
+                    value.relocations.value.blocks
+                    .iter()
+                    .map(|rb| RelocBlockValue::from(&rb.value))
+                    .collect()
+                )} else { Option::None },
+
+            analysis: None,
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="te_header")]
+pub struct MinTeHeader {
+    pub magic: String,
+    pub machine: MachineType,
+    pub sections: u8,
+    pub subsystem: optional::SubSystem,
+    pub stripped_size: u16,
+    pub address_of_entry_point: u32,
+    pub base_of_code: u32,
+    pub image_base: u64,
+    pub data_directories: Vec<DataDirValue>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub machine_raw: Option<u16>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub subsystem_raw: Option<u8>,
+}
+
+impl From<&TeHeader> for MinTeHeader {
+    fn from(value: &TeHeader) -> Self {
+        Self::with_options(value, &MinOptions::default())
+    }
+}
+
+impl MinTeHeader {
+    pub fn with_options(value: &TeHeader, options: &MinOptions) -> Self {
+        Self {
+            magic: std::str::from_utf8(&value.signature.value.to_le_bytes()).unwrap_or("ERR").to_owned(),
+            machine: value.machine.value,
+            sections: value.number_of_sections.value,
+            subsystem: value.subsystem.value,
+            stripped_size: value.stripped_size.value,
+            address_of_entry_point: value.address_of_entry_point.value,
+            base_of_code: value.base_of_code.value,
+            image_base: value.image_base.value,
+            data_directories: [&value.relocations, &value.debug]
+                .into_iter()
+                .filter(|dir| dir.size.value > 0)
+                .map(DataDirValue::from)
+                .collect(),
+            machine_raw: options.raw_values.then_some(value.machine_raw),
+            subsystem_raw: options.raw_values.then_some(value.subsystem_raw),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="dos_header")]
 pub struct MinDosHeader {
     pub magic: String,
     pub e_lfanew: u32,    
+    /// Other fields, with `MinOptions::dos_fields`.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub fields: Option<MinDosFields>,
 }
 
 impl From<&DosHeader> for MinDosHeader {
     fn from(value: &DosHeader) -> Self {
+        Self::with_options(value, &MinOptions::default())
+    }
+}
+
+impl MinDosHeader {
+    pub fn with_options(value: &DosHeader, options: &MinOptions) -> Self {
         Self { 
             magic: std::str::from_utf8(&value.e_magic.value.to_le_bytes())
                     .unwrap_or("ERR")
                     .trim_matches('\0') //has trailing NULL bytes
                     .to_string(),
             e_lfanew: value.e_lfanew.value, 
+            fields: options.dos_fields.then(|| MinDosFields::from(value)),
+        }
+    }
+}
+
+/// Fields of the DOS header other than `e_magic` and `e_lfanew`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="dos_fields")]
+pub struct MinDosFields {
+    pub e_cblp: u16,
+    pub e_cp: u16,
+    pub e_crlc: u16,
+    pub e_cparhdr: u16,
+    pub e_minalloc: u16,
+    pub e_maxalloc: u16,
+    pub e_ss: u16,
+    pub e_sp: u16,
+    pub e_csum: u16,
+    pub e_ip: u16,
+    pub e_cs: u16,
+    pub e_lfarlc: u16,
+    pub e_ovno: u16,
+    pub e_res: [u16; 4],
+    pub e_oemid: u16,
+    pub e_oeminfo: u16,
+    pub e_res2: [u16; 10],
+}
+
+impl From<&DosHeader> for MinDosFields {
+    fn from(value: &DosHeader) -> Self {
+        Self {
+            e_cblp: value.e_cblp.value,
+            e_cp: value.e_cp.value,
+            e_crlc: value.e_crlc.value,
+            e_cparhdr: value.e_cparhdr.value,
+            e_minalloc: value.e_minalloc.value,
+            e_maxalloc: value.e_maxalloc.value,
+            e_ss: value.e_ss.value,
+            e_sp: value.e_sp.value,
+            e_csum: value.e_csum.value,
+            e_ip: value.e_ip.value,
+            e_cs: value.e_cs.value,
+            e_lfarlc: value.e_lfarlc.value,
+            e_ovno: value.e_ovno.value,
+            e_res: value.e_res.value,
+            e_oemid: value.e_oemid.value,
+            e_oeminfo: value.e_oeminfo.value,
+            e_res2: value.e_res2.value,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="nt_headers")]
+pub struct MinNtHeaders {
+    pub signature: String,
+    pub size: u64,
+}
+
+impl From<&NtHeaders> for MinNtHeaders {
+    fn from(value: &NtHeaders) -> Self {
+        Self {
+            signature: std::str::from_utf8(&value.signature.value.to_le_bytes())
+                    .unwrap_or("ERR")
+                    .trim_matches('\0')
+                    .to_string(),
+            size: value.size,
         }
     }
 }
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="file_header")]
 pub struct MinFileHeader {
     pub magic: String,
@@ -104,7 +345,7 @@ pub struct MinFileHeader {
     pub machine: MachineType,
     #[serde(rename="number_of_sections")]
     pub sections: u16,
-    pub timestamp: DateTime<Utc>,
+    pub timestamp: TimestampValue,
     #[serde(skip_serializing)]
     #[serde(rename="pointer_to_symbol_table")]
     pub sym_ptr: u32,
@@ -114,10 +355,27 @@ pub struct MinFileHeader {
     #[serde(rename="size_of_optional_header")]
     pub optional_header_size: u16,
     pub charactristics: file::Flags,
+    pub is_dll: bool,
+    pub is_executable: bool,
+    pub is_system: bool,
+    pub is_large_address_aware: bool,
+    pub relocs_stripped: bool,
+    pub debug_stripped: bool,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub machine_raw: Option<u16>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub charactristics_raw: Option<u16>,
 }
 
 impl From<&FileHeader> for MinFileHeader {
     fn from(value: &FileHeader) -> Self {
+        Self::with_options(value, &MinOptions::default())
+    }
+}
+
+impl MinFileHeader {
+    pub fn with_options(value: &FileHeader, options: &MinOptions) -> Self {
+        let flags = file::Flags::from_bits_truncate(value.charactristics.value);
         Self { 
             magic: std::str::from_utf8(&value.magic.value.to_le_bytes())
                     .unwrap_or("ERR")
@@ -125,17 +383,26 @@ impl From<&FileHeader> for MinFileHeader {
                     .to_string(), 
             machine: value.machine.value, 
             sections: value.sections.value, 
-            timestamp: value.timestamp.value, 
+            timestamp: TimestampValue::new(value.timestamp.value, options.timestamps),
             sym_ptr: value.symbol_table_ptr.value, 
             symbols: value.symbols.value, 
             optional_header_size: value.optional_header_size.value, 
-            charactristics: file::Flags::from_bits_truncate(value.charactristics.value),
+            charactristics: flags,
+            is_dll: flags.is_dll(),
+            is_executable: flags.is_executable(),
+            is_system: flags.is_system(),
+            is_large_address_aware: flags.is_large_address_aware(),
+            relocs_stripped: flags.stripped_relocs(),
+            debug_stripped: flags.stripped_debug(),
+            machine_raw: options.raw_values.then_some(value.machine_raw),
+            charactristics_raw: options.raw_values.then_some(value.charactristics.value),
         }
     }
 }
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="optional_header")]
 pub struct MinOptionalHeader32 {
     pub magic: optional::ImageType,
@@ -158,10 +425,20 @@ pub struct MinOptionalHeader32 {
     pub subsystem: optional::SubSystem,
     pub dll_charactristics: optional::Flags,
     pub number_of_rva_and_sizes: u32,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub subsystem_raw: Option<u16>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub dll_charactristics_raw: Option<u16>,
 }
 
 impl From<&OptionalHeader32> for MinOptionalHeader32 {
     fn from(value: &OptionalHeader32) -> Self {
+        Self::with_options(value, &MinOptions::default())
+    }
+}
+
+impl MinOptionalHeader32 {
+    pub fn with_options(value: &OptionalHeader32, options: &MinOptions) -> Self {
         Self { 
             magic: value.magic.value, 
             major_linker_version: value.major_linker_ver.value, 
@@ -182,12 +459,15 @@ impl From<&OptionalHeader32> for MinOptionalHeader32 {
             checksum: value.checksum.value, 
             subsystem: value.subsystem.value, 
             dll_charactristics: optional::Flags::from_bits_retain(value.dll_charactristics.value), 
-            number_of_rva_and_sizes:  value.number_of_rva_and_sizes.value
+            number_of_rva_and_sizes:  value.number_of_rva_and_sizes.value,
+            subsystem_raw: options.raw_values.then_some(value.subsystem_raw),
+            dll_charactristics_raw: options.raw_values.then_some(value.dll_charactristics.value),
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="optional_header")]
 pub struct MinOptionalHeader64 {
     pub magic: optional::ImageType,
@@ -209,10 +489,20 @@ pub struct MinOptionalHeader64 {
     pub subsystem: optional::SubSystem,
     pub dll_charactristics: optional::Flags,
     pub number_of_rva_and_sizes: u32,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub subsystem_raw: Option<u16>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub dll_charactristics_raw: Option<u16>,
 }
 
 impl From<&OptionalHeader64> for MinOptionalHeader64 {
     fn from(value: &OptionalHeader64) -> Self {
+        Self::with_options(value, &MinOptions::default())
+    }
+}
+
+impl MinOptionalHeader64 {
+    pub fn with_options(value: &OptionalHeader64, options: &MinOptions) -> Self {
         Self { 
             magic: value.magic.value, 
             major_linker_version: value.major_linker_ver.value, 
@@ -232,12 +522,15 @@ impl From<&OptionalHeader64> for MinOptionalHeader64 {
             checksum: value.checksum.value, 
             subsystem: value.subsystem.value, 
             dll_charactristics: optional::Flags::from_bits_retain(value.dll_charactristics.value), 
-            number_of_rva_and_sizes:  value.number_of_rva_and_sizes.value
+            number_of_rva_and_sizes:  value.number_of_rva_and_sizes.value,
+            subsystem_raw: options.raw_values.then_some(value.subsystem_raw),
+            dll_charactristics_raw: options.raw_values.then_some(value.dll_charactristics.value),
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="optional_header")]
 pub enum MinOptionalHeader {
     #[serde(untagged)]
@@ -249,17 +542,28 @@ pub enum MinOptionalHeader {
 
 impl From<&OptionalHeader> for MinOptionalHeader {
     fn from(value: &OptionalHeader) -> Self {
+        Self::with_options(value, &MinOptions::default())
+    }
+}
+
+impl MinOptionalHeader {
+    pub fn with_options(value: &OptionalHeader, options: &MinOptions) -> Self {
         match value {
-            OptionalHeader::X86(opt) => Self::X86(MinOptionalHeader32::from(opt)),
-            OptionalHeader::X64(opt) => Self::X64(MinOptionalHeader64::from(opt)),
+            OptionalHeader::X86(opt) => Self::X86(MinOptionalHeader32::with_options(opt, options)),
+            OptionalHeader::X64(opt) => Self::X64(MinOptionalHeader64::with_options(opt, options)),
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="section")]
 pub struct MinSectionHeader {
+    /// Printable ASCII; other bytes are escaped as `\xNN`.
     pub name: String,
+    /// Name bytes in hex, only for names with escaped bytes.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub name_raw: Option<String>,
     pub virtual_size: u32,
     pub virtual_address: u32,
     #[serde(rename="size_of_raw_data")]
@@ -267,20 +571,23 @@ pub struct MinSectionHeader {
     #[serde(rename="pointer_to_raw_data")]
     pub raw_data_ptr: u32,
     pub charactristics: section::Flags,
+    /// `IMAGE_SCN_ALIGN_*` in bytes, kept out of `charactristics`.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub alignment: Option<section::Alignment>,
 }
 
 impl From<&SectionHeader> for MinSectionHeader {
     fn from(value: &SectionHeader) -> Self {
         Self { 
-            name: String::from_utf8(value.name.value.to_vec())
-                    .unwrap_or("ERR".to_string())
-                    .trim_end_matches('\0') //section name usually has trailing NULL bytes.
-                    .to_string(), 
+            name: value.name_lossy(),
+            name_raw: value.has_escaped_name()
+                .then(|| value.name_bytes().iter().map(|b| format!("{b:02x}")).collect()),
             virtual_size: value.virtual_size.value,
             virtual_address: value.virtual_address.value,
             sizeof_raw_data: value.sizeof_raw_data.value,
             raw_data_ptr: value.raw_data_ptr.value,
-            charactristics: section::Flags::from_bits_retain(value.charactristics.value),
+            charactristics: section::Flags::from_bits_retain(value.charactristics.value & !section::ALIGN_MASK),
+            alignment: value.alignment(),
         }
     }
 }
@@ -290,7 +597,8 @@ impl From<&SectionHeader> for MinSectionHeader {
 /** **V**alue **O**nly variant of `ImportLookup`s.  
   For every member, takes only `value` form `HeaderField`. 
 */
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(untagged)]
 pub enum ImportLookupVO {
     Ordinal(u16),
@@ -329,85 +637,187 @@ impl From<&ImportLookup> for ImportLookupVO {
 }
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="import_descriptor")]
 pub struct MinImportDescriptor {
     pub dll_name: String,
+    pub binding: ImportBinding,
     //#[serde(flatten)]
     pub functions: Vec<ImportLookupVO>,
+    /// RVA of the IAT slot of each of `functions`, in the same order.
+    pub iat_rvas: Vec<u32>,
+    pub counts: ImportCounts,
+    /// IAT slots not matching the lookup table on disk.
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub iat_mismatches: Vec<IatMismatch>,
 }
 
 impl From<&ImportDescriptor> for MinImportDescriptor {
     fn from(value: &ImportDescriptor) -> Self {
         Self { 
             dll_name: value.name.clone().unwrap_or(String::from("ERR")), 
+            binding: value.binding(),
             functions: value.imports
                 .iter()
                 .map(|i| ImportLookupVO::from(i))
-                .collect()
+                .collect(),
+            iat_rvas: value.imports
+                .iter()
+                .map(|i| i.iat_rva())
+                .collect(),
+            counts: ImportCounts::of([value]),
+            iat_mismatches: value.iat_mismatches.clone(),
+        }
+    }
+}
+
+
+/// Import counts of one module or of the whole image.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="import_counts")]
+pub struct ImportCounts {
+    /// All imported functions, repeats included.
+    pub total: usize,
+    /// Distinct functions. The same name imported from two modules counts twice.
+    pub unique: usize,
+    pub by_name: usize,
+    pub by_ordinal: usize,
+}
+
+impl ImportCounts {
+    /// Counts imports of `descriptors`, comparing module names ignoring case.
+    pub fn of<'a>(descriptors: impl IntoIterator<Item = &'a ImportDescriptor>) -> Self {
+        let mut counts = Self::default();
+        let mut seen = HashSet::new();
+
+        for desc in descriptors {
+            let module = desc.name.as_deref().unwrap_or_default().to_ascii_lowercase();
+            for import in &desc.imports {
+                let function = ImportLookupVO::from(import);
+                match function {
+                    ImportLookupVO::Ordinal(_) => counts.by_ordinal += 1,
+                    ImportLookupVO::Name(_) => counts.by_name += 1,
+                }
+                counts.total += 1;
+                if seen.insert((module.clone(), function)) {
+                    counts.unique += 1;
+                }
+            }
         }
+
+        counts
     }
 }
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="export_directory")]
 pub struct MinExportDirectory {
-    pub timestamp: DateTime<Utc>,
+    pub timestamp: TimestampValue,
+    pub major_version: u16,
+    pub minor_version: u16,
     pub name: String, 
+    /// Ordinal of the first entry in the export address table.
+    pub base: u32,
+    pub number_of_functions: u32,
+    pub number_of_names: u32,
     pub exports: Vec<ExportValue>,
+    /// Set if `exports` holds only the first few exports.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub truncated: Option<Truncated>,
 }
 
-impl From<&ExportDirectory> for MinExportDirectory {
-    fn from(value: &ExportDirectory) -> Self {
+impl MinExportDirectory {
+    /// Same as `from`, but serializes at most `max_exports` exports.
+    pub fn with_limit(value: &ExportDirectory, max_exports: usize) -> Self {
+        Self::with_options(value, &MinOptions { max_exports, ..Default::default() })
+    }
+
+    pub fn with_options(value: &ExportDirectory, options: &MinOptions) -> Self {
+        let max_exports = options.max_exports;
+        let total = value.exports.len();
         Self { 
-            timestamp: value.timestamp.value, 
+            timestamp: TimestampValue::new(value.timestamp.value, options.timestamps), 
+            major_version: value.major_version.value,
+            minor_version: value.minor_version.value,
             name: value.name.clone(), 
+            base: value.base.value,
+            number_of_functions: value.number_of_functions.value,
+            number_of_names: value.number_of_names.value,
             exports: value.exports
                 .iter()
+                .take(max_exports)
                 .map(|ex| ExportValue::from(ex))
                 .collect(),
-            }
+            truncated: if total > max_exports { Some(Truncated { shown: max_exports, total }) } else { None },
+        }
+    }
+}
+
+impl From<&ExportDirectory> for MinExportDirectory {
+    fn from(value: &ExportDirectory) -> Self {
+        Self::with_limit(value, DEFAULT_MAX_SERIALIZED_EXPORTS)
     }
 }
 
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 //#[serde(untagged)]
 pub enum MinRsrcNode {
-    Str(ResourceStringValue),
     Data(ResourceDataValue),
     Dir(MinRsrcDirectory)
 }
 
-impl From<&ResourceNode> for MinRsrcNode {
-    fn from(value: &ResourceNode) -> Self {
+impl MinRsrcNode {
+    fn at_level(value: &ResourceNode, level: u8) -> Self {
         match value {
-            ResourceNode::Str(str) => Self::Str(ResourceStringValue::from(str)),
             ResourceNode::Data(data) => Self::Data(ResourceDataValue::from(data)),
-            ResourceNode::Dir(dir) => Self::Dir(MinRsrcDirectory::from(dir)),
+            ResourceNode::Dir(dir) => Self::Dir(MinRsrcDirectory::at_level(dir, level)),
         }
     }
 }
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="Entry")]
 pub struct MinRsrcEntry {
-    pub id: ResourceType,
+    /// Only for entries of the root directory.
+    #[serde(rename="type", skip_serializing_if="Option::is_none")]
+    pub resource_type: Option<ResourceType>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub id: Option<u32>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub name: Option<String>,
     #[serde(flatten)]
     pub data: MinRsrcNode,
 }
 
+impl MinRsrcEntry {
+    fn at_level(rsrc_entry: &ResourceEntry, level: u8) -> Self {
+        Self {
+            resource_type: rsrc_entry.resource_type().filter(|_| level == 0),
+            id: rsrc_entry.id,
+            name: rsrc_entry.name.as_ref().map(|name| name.value.value.clone()),
+            data: MinRsrcNode::at_level(&rsrc_entry.data, level + 1),
+        }
+    }
+}
+
 impl From<&ResourceEntry> for MinRsrcEntry {
     fn from(rsrc_entry: &ResourceEntry) -> Self {
-        Self { id: rsrc_entry.id, data: MinRsrcNode::from(&rsrc_entry.data) }
+        Self::at_level(rsrc_entry, 0)
     }
 }
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="resource_directory")]
 pub struct MinRsrcDirectory {
     #[serde(rename="number_of_named_entries")]
@@ -417,19 +827,47 @@ pub struct MinRsrcDirectory {
     pub entries: Vec<MinRsrcEntry>,
 }
 
-
-impl From<&ResourceDirectory> for MinRsrcDirectory {
-    fn from(rsrc_dir: &ResourceDirectory) -> Self {
+impl MinRsrcDirectory {
+    fn at_level(rsrc_dir: &ResourceDirectory, level: u8) -> Self {
         Self { 
             named_entry_count: rsrc_dir.named_entry_count.value, 
             id_entry_count: rsrc_dir.id_entry_count.value, 
             entries:  rsrc_dir.entries
                 .iter()
-                .map(|e| MinRsrcEntry::from(e))
+                .map(|e| MinRsrcEntry::at_level(e, level))
                 .collect(),
         }
     }
 }
 
+impl From<&ResourceDirectory> for MinRsrcDirectory {
+    fn from(rsrc_dir: &ResourceDirectory) -> Self {
+        Self::at_level(rsrc_dir, 0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="chpe_metadata")]
+pub struct MinChpeMetadata {
+    pub version: u32,
+    pub alternate_entry_point: u32,
+    pub code_ranges: Vec<CodeRange>,
+    pub entry_points: Vec<EntryPointRange>,
+    pub redirections: Vec<Redirection>,
+}
+
+impl From<&ChpeMetadata> for MinChpeMetadata {
+    fn from(value: &ChpeMetadata) -> Self {
+        Self {
+            version: value.version.value,
+            alternate_entry_point: value.alternate_entry_point.value,
+            code_ranges: value.code_ranges.iter().map(|r| r.value).collect(),
+            entry_points: value.entry_points.iter().map(|e| e.value).collect(),
+            redirections: value.redirections.iter().map(|r| r.value).collect(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests;
@@ -2,22 +2,46 @@ use serde_test::{assert_ser_tokens, Configure, Token};
 
 use crate::{
     pe::{
-        dos::DosHeader, export::ExportDirectory, file::FileHeader, import::ImportDirectory, 
-        optional::{self, ImageType}, rsrc::ResourceDirectory, section::{parse_sections, SectionHeader}
+        dos::DosHeader, export::ExportDirectory, file::{FileHeader, MachineType}, import::ImportDirectory, 
+        optional::{self, ImageType}, rsrc::ResourceDirectory, section::{parse_sections, SectionHeader}, PeTimestamp
     }, 
     types::{Header, HeaderField}, 
     utils::FragmentReader
 };
 
+use crate::pe::ser::{TimestampFormat, TimestampValue};
 use crate::pe::ser::min::{
-    MinDosHeader, MinExportDirectory, MinFileHeader, MinOptionalHeader, MinOptionalHeader32, 
-    MinOptionalHeader64, MinImportDescriptor, MinSectionHeader
+    MinOptions, MinDosHeader, MinExportDirectory, MinFileHeader, MinOptionalHeader, MinOptionalHeader32, 
+    MinOptionalHeader64, MinImportDescriptor, MinSectionHeader, ImportCounts
 };
 
 const RAW_DOS_BYTES: [u8; 64] = [0x4D, 0x5A, 0x90, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0xFF, 0xFF, 
                                 0x00, 0x00, 0xB8, 0x00, 00, 00, 00, 00, 00, 00, 0x40, 00, 00, 00, 00, 00, 00, 00, 
                                 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 
                                 00, 00, 00, 00, 00, 00, 00, 0xF8, 00, 00, 00];
+#[test]
+fn serialize_timestamps() {
+    let valid = PeTimestamp::from(0x61e4e6c5);
+    let both = |epoch, iso| [
+        Token::Struct { name: "timestamp", len: 2 },
+        Token::Str("epoch"), Token::U32(epoch),
+        Token::Str("iso8601"), iso,
+        Token::StructEnd,
+    ];
+
+    let mut tokens = both(0x61e4e6c5, Token::Some).to_vec();
+    tokens.insert(5, Token::Str("2022-01-17T03:47:17Z"));
+    assert_ser_tokens(&valid, &tokens);
+    assert_ser_tokens(&TimestampValue::new(valid, TimestampFormat::Iso8601), &[Token::Str("2022-01-17T03:47:17Z")]);
+    assert_ser_tokens(&TimestampValue::new(valid, TimestampFormat::Epoch), &[Token::U32(0x61e4e6c5)]);
+
+    //Not a time; serialized as stored.
+    let raw = PeTimestamp::from(u32::MAX);
+    assert_ser_tokens(&raw, &both(u32::MAX, Token::None));
+    assert_ser_tokens(&TimestampValue::new(raw, TimestampFormat::Iso8601), &[Token::U32(u32::MAX)]);
+    assert_ser_tokens(&TimestampValue::new(raw, TimestampFormat::Epoch), &[Token::U32(u32::MAX)]);
+}
+
 #[test]
 fn serialize_dos(){
     let buf = RAW_DOS_BYTES;
@@ -55,6 +79,22 @@ fn min_dos_to_json() {
     assert!(jstr.contains("\"e_lfanew\": 248"));
 }
 
+#[test]
+fn serialize_dos_fields() {
+    let dos_header = DosHeader::parse_bytes(RAW_DOS_BYTES.to_vec(), 0).unwrap();
+    let min_dos = MinDosHeader::with_options(&dos_header, &MinOptions { dos_fields: true, ..Default::default() });
+
+    let fields = min_dos.fields.as_ref().unwrap();
+    assert_eq!(fields.e_cblp, 0x90);
+    assert_eq!(fields.e_cp, 3);
+    assert_eq!(fields.e_cparhdr, 4);
+    assert_eq!(fields.e_maxalloc, 0xFFFF);
+    assert_eq!(fields.e_sp, 0xB8);
+    assert_eq!(fields.e_lfarlc, 0x40);
+    assert_eq!(fields.e_res2, [0; 10]);
+    assert!(MinDosHeader::from(&dos_header).fields.is_none());
+}
+
 const RAW_FILE_BYTES: [u8; 24] = [
     0x50, 0x45, 0x00, 0x00, 0x64, 0x86, 0x05, 0x00, 0xA5, 0xE6, 0xE4, 0x61, 0x00, 0x00, 0x00, 0x00,
     0x00, 0x00, 0x00, 0x00, 0xF0, 0x00, 0x22, 0x00 ];
@@ -67,7 +107,7 @@ fn serialize_file() {
     let min_file = MinFileHeader::from(&file_hdr);
 
     assert_ser_tokens(&min_file.readable(), &[
-        Token::Struct { name: "file_header", len: 6 },
+        Token::Struct { name: "file_header", len: 12 },
         
         Token::String("magic"),
         Token::String("PE"),
@@ -79,7 +119,10 @@ fn serialize_file() {
         Token::U16(5),
 
         Token::String("timestamp"),
-        Token::String("2022-01-17T03:46:45Z"),
+        Token::Struct { name: "timestamp", len: 2 },
+        Token::String("epoch"), Token::U32(0x61e4e6a5),
+        Token::String("iso8601"), Token::Some, Token::String("2022-01-17T03:46:45Z"),
+        Token::StructEnd,
 
         Token::String("size_of_optional_header"),
         Token::U16(240),
@@ -88,6 +131,61 @@ fn serialize_file() {
         Token::NewtypeStruct { name: "Flags" },
         Token::Str("EXECUTABLE | LARGE_ADDRESS_AWARE"),
 
+        Token::String("is_dll"), Token::Bool(false),
+        Token::String("is_executable"), Token::Bool(true),
+        Token::String("is_system"), Token::Bool(false),
+        Token::String("is_large_address_aware"), Token::Bool(true),
+        Token::String("relocs_stripped"), Token::Bool(false),
+        Token::String("debug_stripped"), Token::Bool(false),
+
+        Token::StructEnd,
+    ])
+}
+
+#[test]
+fn serialize_file_raw_values() {
+    let mut bytes = RAW_FILE_BYTES.to_vec();
+    bytes[4..6].copy_from_slice(&0x1234u16.to_le_bytes());
+    let file_hdr = FileHeader::parse_bytes(bytes, 0).unwrap();
+
+    let min_file = MinFileHeader::from(&file_hdr);
+    assert_eq!(min_file.machine, MachineType::UNKNOWN);
+    assert_eq!(min_file.machine_raw, None);
+
+    let min_file = MinFileHeader::with_options(&file_hdr, &MinOptions { raw_values: true, ..Default::default() });
+    assert_eq!(min_file.machine_raw, Some(0x1234));
+    assert_eq!(min_file.charactristics_raw, Some(file_hdr.charactristics.value));
+
+    assert_ser_tokens(&min_file.readable(), &[
+        Token::Struct { name: "file_header", len: 14 },
+        Token::String("magic"),
+        Token::String("PE"),
+        Token::String("machine_type"),
+        Token::UnitVariant { name: "MachineType", variant: "UNKNOWN" },
+        Token::String("number_of_sections"),
+        Token::U16(5),
+        Token::String("timestamp"),
+        Token::Struct { name: "timestamp", len: 2 },
+        Token::String("epoch"), Token::U32(0x61e4e6a5),
+        Token::String("iso8601"), Token::Some, Token::String("2022-01-17T03:46:45Z"),
+        Token::StructEnd,
+        Token::String("size_of_optional_header"),
+        Token::U16(240),
+        Token::String("charactristics"),
+        Token::NewtypeStruct { name: "Flags" },
+        Token::Str("EXECUTABLE | LARGE_ADDRESS_AWARE"),
+        Token::String("is_dll"), Token::Bool(false),
+        Token::String("is_executable"), Token::Bool(true),
+        Token::String("is_system"), Token::Bool(false),
+        Token::String("is_large_address_aware"), Token::Bool(true),
+        Token::String("relocs_stripped"), Token::Bool(false),
+        Token::String("debug_stripped"), Token::Bool(false),
+        Token::String("machine_raw"),
+        Token::Some,
+        Token::U16(0x1234),
+        Token::String("charactristics_raw"),
+        Token::Some,
+        Token::U16(0x22),
         Token::StructEnd,
     ])
 }
@@ -327,6 +425,62 @@ fn parse_test_sections() -> Vec<HeaderField<SectionHeader>> {
     parse_sections(&RAW_SECTION_BYTES, 6, 0x200).unwrap()
 }
 
+#[test]
+fn serialize_section_alignment() {
+    let mut sh = SectionHeader::default();
+    sh.name.value = *b".text\0\0\0";
+    sh.charactristics.value = 0x60500020;
+
+    assert_ser_tokens(&MinSectionHeader::from(&sh).readable(), &[
+        Token::Struct { name: "section", len: 7 },
+        Token::String("name"),
+        Token::String(".text"),
+        Token::String("virtual_size"),
+        Token::U32(0),
+        Token::String("virtual_address"),
+        Token::U32(0),
+        Token::String("size_of_raw_data"),
+        Token::U32(0),
+        Token::String("pointer_to_raw_data"),
+        Token::U32(0),
+        Token::String("charactristics"),
+        Token::NewtypeStruct { name: "Flags" },
+        Token::Str("CODE | MEM_EXECUTE | MEM_READ"),
+        Token::String("alignment"),
+        Token::Some,
+        Token::U32(16),
+        Token::StructEnd,
+    ]);
+}
+
+#[test]
+fn serialize_escaped_section_name() {
+    let mut sh = SectionHeader::default();
+    sh.name.value = [b'.', 0xe9, b'x', 0, 0, 0, 0, 0];
+    sh.charactristics.value = 0x40000040;
+
+    assert_ser_tokens(&MinSectionHeader::from(&sh).readable(), &[
+        Token::Struct { name: "section", len: 7 },
+        Token::String("name"),
+        Token::String(".\\xe9x"),
+        Token::String("name_raw"),
+        Token::Some,
+        Token::String("2ee978"),
+        Token::String("virtual_size"),
+        Token::U32(0),
+        Token::String("virtual_address"),
+        Token::U32(0),
+        Token::String("size_of_raw_data"),
+        Token::U32(0),
+        Token::String("pointer_to_raw_data"),
+        Token::U32(0),
+        Token::String("charactristics"),
+        Token::NewtypeStruct { name: "Flags" },
+        Token::Str("INITIALIZED_DATA | MEM_READ"),
+        Token::StructEnd,
+    ]);
+}
+
 #[test]
 fn serialize_sections() {
     let sections = parse_test_sections();
@@ -450,7 +604,7 @@ fn sections_to_json() {
 }
 
 //Tests for imports
-fn parse_and_validate_imports() -> crate::Result<Vec<MinImportDescriptor>> {
+fn parse_and_validate_imports() -> crate::pe::Result<Vec<MinImportDescriptor>> {
     let sections = parse_test_sections();
     assert_eq!(sections.len(), 6);
 
@@ -481,20 +635,36 @@ fn serialize_imports() {
 
     let mut tokens = vec![
         Token::Seq { len: Some(2) },
-        Token::Struct { name: "import_descriptor", len: 2 },
+        Token::Struct { name: "import_descriptor", len: 5 },
         Token::String("dll_name"),
         Token::String("libglib-2.0-0.dll"),
+        Token::String("binding"),
+        Token::UnitVariant { name: "ImportBinding", variant: "NotBound" },
 
         Token::String("functions"),
         Token::Seq { len: Some(2) },
         Token::String("g_log"),
         Token::String("g_assertion_message_expr"),
         Token::SeqEnd,
+        Token::String("iat_rvas"),
+        Token::Seq { len: Some(2) },
+        Token::U32(0xc200),
+        Token::U32(0xc208),
+        Token::SeqEnd,
+        Token::String("counts"),
+        Token::Struct { name: "import_counts", len: 4 },
+        Token::String("total"), Token::U64(2),
+        Token::String("unique"), Token::U64(2),
+        Token::String("by_name"), Token::U64(2),
+        Token::String("by_ordinal"), Token::U64(0),
+        Token::StructEnd,
         Token::StructEnd,
 
-        Token::Struct { name: "import_descriptor", len: 2 },
+        Token::Struct { name: "import_descriptor", len: 5 },
         Token::String("dll_name"),
         Token::String("KERNEL32.dll"),
+        Token::String("binding"),
+        Token::UnitVariant { name: "ImportBinding", variant: "NotBound" },
         Token::String("functions"),
         Token::Seq { len: Some(63) },
     ];
@@ -518,6 +688,21 @@ fn serialize_imports() {
     }
 
     tokens.push(Token::SeqEnd);
+    tokens.push(Token::String("iat_rvas"));
+    tokens.push(Token::Seq { len: Some(63) });
+    for i in 0..kernel_fns.len() as u32 {
+        tokens.push(Token::U32(0xc000 + i * 8));
+    }
+    tokens.push(Token::SeqEnd);
+    tokens.extend([
+        Token::String("counts"),
+        Token::Struct { name: "import_counts", len: 4 },
+        Token::String("total"), Token::U64(63),
+        Token::String("unique"), Token::U64(63),
+        Token::String("by_name"), Token::U64(63),
+        Token::String("by_ordinal"), Token::U64(0),
+        Token::StructEnd,
+    ]);
     tokens.push(Token::StructEnd);
     tokens.push(Token::SeqEnd);
 
@@ -525,6 +710,48 @@ fn serialize_imports() {
 }
 
 
+#[test]
+fn import_counts() {
+    use crate::pe::import::{ImportDescriptor, ImportLookup};
+    use crate::types::HeaderField;
+
+    let named = |name: &str| {
+        let mut import = ImportLookup::from(HeaderField::at_offset(0x1000u32, 0));
+        if let ImportLookup::X86(il) = &mut import {
+            il.iname.as_mut().unwrap().value.name.value = name.to_string();
+        }
+        import
+    };
+    let ordinal = |ord: u32| ImportLookup::from(HeaderField::at_offset(0x80000000 | ord, 0));
+
+    let kernel32 = ImportDescriptor {
+        name: Some("KERNEL32.dll".into()),
+        imports: vec![named("Sleep"), named("ExitProcess"), named("Sleep"), ordinal(5)],
+        ..Default::default()
+    };
+
+    let kernel32_upper = ImportDescriptor {
+        name: Some("kernel32.DLL".into()),
+        imports: vec![named("Sleep"), ordinal(5), ordinal(6)],
+        ..Default::default()
+    };
+
+    let user32 = ImportDescriptor {
+        name: Some("USER32.dll".into()),
+        imports: vec![named("Sleep")],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        MinImportDescriptor::from(&kernel32).counts,
+        ImportCounts { total: 4, unique: 3, by_name: 3, by_ordinal: 1 }
+    );
+    assert_eq!(
+        ImportCounts::of([&kernel32, &kernel32_upper, &user32]),
+        ImportCounts { total: 8, unique: 5, by_name: 5, by_ordinal: 3 }
+    );
+}
+
 #[cfg(feature="json")]
 #[test]
 fn imports_to_json() {
@@ -558,14 +785,24 @@ fn serialize_exports() {
     let min_exports = MinExportDirectory::from(&exports);
 
     assert_ser_tokens(&min_exports, &[
-        Token::Struct { name: "export_directory", len: 3 },
+        Token::Struct { name: "export_directory", len: 8 },
         
         Token::String("timestamp"),
-        Token::String("2015-11-05T20:25:59Z"),
+        Token::Struct { name: "timestamp", len: 2 },
+        Token::String("epoch"), Token::U32(0x563bbb57),
+        Token::String("iso8601"), Token::Some, Token::String("2015-11-05T20:25:59Z"),
+        Token::StructEnd,
+
+        Token::String("major_version"), Token::U16(0),
+        Token::String("minor_version"), Token::U16(0),
 
         Token::String("name"),
         Token::String("libgthread-2.0-0.dll"),
 
+        Token::String("base"), Token::U32(1),
+        Token::String("number_of_functions"), Token::U32(2),
+        Token::String("number_of_names"), Token::U32(2),
+
         Token::String("exports"),
         Token::Seq { len: Some(2) },
         
@@ -592,6 +829,61 @@ fn serialize_exports() {
     ])
 }
 
+#[test]
+fn serialize_exports_truncated() {
+    let sections = parse_test_sections();
+    let mut exports = ExportDirectory::parse_bytes(RAW_EXPORT_BYTES.to_vec(), EXPORT_OFFSET).unwrap();
+    let mut reader = FragmentReader::new(RAW_EXPORT_BYTES.to_vec(), EXPORT_OFFSET);
+    exports.parse_exports(&sections, &mut reader).unwrap();
+
+    let min_exports = MinExportDirectory::with_limit(&exports, 1);
+
+    assert_ser_tokens(&min_exports, &[
+        Token::Struct { name: "export_directory", len: 9 },
+        
+        Token::String("timestamp"),
+        Token::Struct { name: "timestamp", len: 2 },
+        Token::String("epoch"), Token::U32(0x563bbb57),
+        Token::String("iso8601"), Token::Some, Token::String("2015-11-05T20:25:59Z"),
+        Token::StructEnd,
+
+        Token::String("major_version"), Token::U16(0),
+        Token::String("minor_version"), Token::U16(0),
+
+        Token::String("name"),
+        Token::String("libgthread-2.0-0.dll"),
+
+        Token::String("base"), Token::U32(1),
+        Token::String("number_of_functions"), Token::U32(2),
+        Token::String("number_of_names"), Token::U32(2),
+
+        Token::String("exports"),
+        Token::Seq { len: Some(1) },
+        
+        Token::Struct { name: "export", len: 3 },
+        Token::String("name"),
+        Token::String("g_thread_init"),
+        Token::String("rva"),
+        Token::U32(0x1000),
+        Token::String("ordinal"),
+        Token::U16(0),
+        Token::StructEnd,
+
+        Token::SeqEnd,
+
+        Token::String("truncated"),
+        Token::Some,
+        Token::Struct { name: "truncated", len: 2 },
+        Token::String("shown"),
+        Token::U64(1),
+        Token::String("total"),
+        Token::U64(2),
+        Token::StructEnd,
+
+        Token::StructEnd,
+    ])
+}
+
 #[cfg(feature="json")]
 #[test]
 fn export_to_json() {
@@ -1,10 +1,74 @@
-use serde::Serialize;
+use chrono::SecondsFormat;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
 
-use super::{export::Export, optional::{DataDirectory, DirectoryType}, relocs::{Reloc, RelocBlock}, rsrc::{ResourceData, ResourceString}};
+use super::{export::Export, optional::{DataDirectory, DirectoryType}, relocs::{Reloc, RelocBlock}, rsrc::ResourceData, PeTimestamp};
 
+pub mod envelope;
+pub mod full;
 pub mod min;
+#[cfg(feature="schema")]
+pub mod schema;
+pub mod triage;
+
+/// Marker for a list cut short during serialization.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(rename="truncated")]
+pub struct Truncated {
+    /// Number of items serialized.
+    pub shown: usize,
+    /// Number of items available.
+    pub total: usize,
+}
+
+/// Forms of a serialized timestamp.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all="lowercase")]
+pub enum TimestampFormat {
+    /// `{ "epoch": 1642391237, "iso8601": "2022-01-17T03:47:17Z" }`; `iso8601` is `null` if the value is not a time.
+    #[default]
+    Both,
+    /// `"2022-01-17T03:47:17Z"`, or the stored number if the value is not a time.
+    Iso8601,
+    /// Seconds since the Unix epoch, as stored.
+    Epoch,
+}
+
+/// A `PeTimestamp` serialized in the chosen `format`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimestampValue {
+    pub value: PeTimestamp,
+    pub format: TimestampFormat,
+}
+
+impl TimestampValue {
+    pub fn new(value: PeTimestamp, format: TimestampFormat) -> Self {
+        Self { value, format }
+    }
+}
+
+impl Serialize for TimestampValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let iso = self.value.as_datetime().map(|dt| dt.to_rfc3339_opts(SecondsFormat::Secs, true));
+        match self.format {
+            TimestampFormat::Both => {
+                let mut ts = serializer.serialize_struct("timestamp", 2)?;
+                ts.serialize_field("epoch", &self.value.raw())?;
+                ts.serialize_field("iso8601", &iso)?;
+                ts.end()
+            },
+            TimestampFormat::Iso8601 => match iso {
+                Some(iso) => serializer.serialize_str(&iso),
+                None => serializer.serialize_u32(self.value.raw()),
+            },
+            TimestampFormat::Epoch => serializer.serialize_u32(self.value.raw()),
+        }
+    }
+}
 
-#[derive(Debug, Serialize)]
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="data_directory")]
 pub struct DataDirValue {
     #[serde(rename="type")]
@@ -20,7 +84,8 @@ impl From<&DataDirectory> for DataDirValue {
 }
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="export")]
 pub struct ExportValue {
     pub name: String,
@@ -40,7 +105,8 @@ impl From<&Export> for ExportValue {
 }
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="relocation_block")]
 pub struct RelocBlockValue {
     pub virtual_address : u32,
@@ -63,7 +129,8 @@ impl From<&RelocBlock> for RelocBlockValue {
 }
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
 #[serde(rename="resource_data")]
 pub struct ResourceDataValue {
     pub rva: u32,
@@ -82,23 +149,6 @@ impl From<&ResourceData> for ResourceDataValue {
 }
 
 
-#[derive(Debug, Serialize)]
-#[serde(rename="resource_string")]
-pub struct ResourceStringValue {
-    pub length: u16,
-    pub value: String,
-}
-
-impl From<&ResourceString> for ResourceStringValue {
-    fn from(value: &ResourceString) -> Self {
-        Self { 
-            length: value.length.value,
-            value: value.value.value.clone(),
-        }
-    }
-}
-
-
 #[cfg(test)]
 mod tests {
     use serde_test::{assert_ser_tokens, Token};
@@ -249,18 +299,22 @@ mod tests {
             Token::String("relocations"),
             Token::Seq { len: Some(2) },
 
-            Token::Struct { name: "relocation", len: 2 },
+            Token::Struct { name: "relocation", len: 3 },
             Token::String("type"),
             Token::UnitVariant { name: "RelocType", variant: "DIR64" },
             Token::String("offset"),
             Token::U16(0x2c8),
+            Token::String("rva"),
+            Token::U32(0x112c8),
             Token::StructEnd,
 
-            Token::Struct { name: "relocation", len: 2 },
+            Token::Struct { name: "relocation", len: 3 },
             Token::String("type"),
             Token::UnitVariant { name: "RelocType", variant: "DIR64" },
             Token::String("offset"),
             Token::U16(0x438),
+            Token::String("rva"),
+            Token::U32(0x11438),
             Token::StructEnd,
 
             Token::SeqEnd,
@@ -0,0 +1,162 @@
+//! JSON Schema of `MinPeImage`, `FullPeImage` and `TriagePeImage` output, for validating
+//! reports and generating typed bindings.
+//!
+//! Schemas are versioned with the crate: `$id` ends with the crate version, which is also in
+//! `x-rustbin-version`. Types serialized by hand (timestamps, flags, alignment) describe
+//! their schema here.
+
+use std::borrow::Cow;
+
+use schemars::{generate::SchemaSettings, json_schema, JsonSchema, Schema, SchemaGenerator};
+
+use crate::pe::{debug::ExDllFlags, file, loadcfg::GuardFlags, optional, section, PeTimestamp};
+
+use super::{full::FullPeImage, min::MinPeImage, triage::TriagePeImage, TimestampValue};
+
+/// Version of the schemas, the crate version.
+pub const SCHEMA_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Base of the `$id` of the schemas.
+pub const SCHEMA_BASE: &str = "https://github.com/sunilkr/rustbin/schema";
+
+/// Output a schema describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaKind {
+    Minimal,
+    Full,
+    Triage,
+}
+
+impl SchemaKind {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Minimal => "min",
+            Self::Full => "full",
+            Self::Triage => "triage",
+        }
+    }
+}
+
+/// Schema of the JSON output of `kind`, as serialized.
+pub fn schema(kind: SchemaKind) -> Schema {
+    let generator = SchemaSettings::draft2020_12().for_serialize().into_generator();
+    let mut schema = match kind {
+        SchemaKind::Minimal => generator.into_root_schema_for::<MinPeImage>(),
+        SchemaKind::Full => generator.into_root_schema_for::<FullPeImage>(),
+        SchemaKind::Triage => generator.into_root_schema_for::<TriagePeImage>(),
+    };
+    schema.insert("$id".into(), format!("{SCHEMA_BASE}/{SCHEMA_VERSION}/{}.json", kind.name()).into());
+    schema.insert("x-rustbin-version".into(), SCHEMA_VERSION.into());
+    schema
+}
+
+/// Flags serialized as names joined by ` | `, e.g. `"DLL | EXECUTABLE"`.
+macro_rules! flags_schema {
+    ($($t:ty => $name:literal),+) => {
+        $(impl JsonSchema for $t {
+            fn schema_name() -> Cow<'static, str> {
+                $name.into()
+            }
+
+            fn json_schema(_: &mut SchemaGenerator) -> Schema {
+                json_schema!({ "type": "string", "description": "Names of set flags, joined by ` | `." })
+            }
+        })+
+    };
+}
+
+flags_schema!(
+    file::Flags => "FileFlags",
+    optional::Flags => "DllFlags",
+    section::Flags => "SectionFlags",
+    GuardFlags => "GuardFlags",
+    ExDllFlags => "ExDllFlags"
+);
+
+impl JsonSchema for section::Alignment {
+    fn schema_name() -> Cow<'static, str> {
+        "Alignment".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({ "type": "integer", "minimum": 1, "description": "Alignment in bytes." })
+    }
+}
+
+impl JsonSchema for PeTimestamp {
+    fn schema_name() -> Cow<'static, str> {
+        "Timestamp".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "object",
+            "properties": {
+                "epoch": { "type": "integer", "minimum": 0 },
+                "iso8601": { "type": ["string", "null"], "format": "date-time" },
+            },
+            "required": ["epoch", "iso8601"],
+        })
+    }
+}
+
+impl JsonSchema for TimestampValue {
+    fn schema_name() -> Cow<'static, str> {
+        "TimestampValue".into()
+    }
+
+    /// Any of the forms of `TimestampFormat`.
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "anyOf": [
+                generator.subschema_for::<PeTimestamp>(),
+                { "type": "string", "format": "date-time" },
+                { "type": "integer", "minimum": 0 },
+            ],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::pe::{ser::{full::FullPeImage, min::{MinOptions, MinPeImage}, triage::TriagePeImage, TimestampFormat}, PeImage};
+
+    use super::{schema, SchemaKind, SCHEMA_VERSION};
+
+    #[test]
+    fn schemas_are_versioned() {
+        for kind in [SchemaKind::Minimal, SchemaKind::Full, SchemaKind::Triage] {
+            let schema = schema(kind);
+            assert_eq!(schema.get("x-rustbin-version").unwrap(), SCHEMA_VERSION);
+            assert!(schema.get("$id").unwrap().as_str().unwrap().contains(SCHEMA_VERSION));
+            assert!(schema.get("properties").unwrap().get("sections").is_some());
+        }
+        let min = schema(SchemaKind::Minimal);
+        let required = min.get("required").unwrap().as_array().unwrap();
+        assert!(required.iter().any(|r| r == "dos_header"));
+        assert!(!required.iter().any(|r| r == "import_directories"));
+    }
+
+    #[test]
+    fn schema_describes_output() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+
+        //Every top-level key of the output is described.
+        let options = MinOptions { timestamps: TimestampFormat::Iso8601, ..Default::default() };
+        let outputs = [
+            (SchemaKind::Minimal, serde_json::to_value(MinPeImage::with_options(&pe, &options)).unwrap()),
+            (SchemaKind::Full, serde_json::to_value(FullPeImage::from(&pe)).unwrap()),
+            (SchemaKind::Triage, serde_json::to_value(TriagePeImage::new(&mut pe, TimestampFormat::Both).unwrap()).unwrap()),
+        ];
+        for (kind, output) in outputs {
+            let schema = schema(kind);
+            let properties = schema.get("properties").unwrap().as_object().unwrap();
+            for key in output.as_object().unwrap().keys() {
+                assert!(properties.contains_key(key), "{kind:?}: {key}");
+            }
+        }
+    }
+}
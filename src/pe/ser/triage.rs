@@ -0,0 +1,168 @@
+//! Compact summary of an image for triage and SIEM ingestion, where the minimal and full
+//! formats are too verbose.
+//!
+//! Every field is always present, `null` if it does not apply, so each report has the same
+//! shape. The layout is versioned by `TRIAGE_VERSION`.
+
+use serde::Serialize;
+
+use crate::pe::{
+    analysis::entropy,
+    optional::{DirectoryType, SubSystem},
+    section,
+    timestamps::{TimestampClass, TimestampSource},
+    PeImage, Result,
+};
+
+use super::{TimestampFormat, TimestampValue};
+
+/// Version of the triage layout; changes when its fields do.
+pub const TRIAGE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct TriagePeImage {
+    pub triage_version: u32,
+    /// Size of the whole input, which the hashes are of.
+    pub size: u64,
+    pub md5: String,
+    pub sha1: String,
+    pub sha256: String,
+    pub ssdeep: Option<String>,
+    /// See `PeImage::imphash`.
+    pub imphash: Option<String>,
+    pub subsystem: SubSystem,
+    /// Timestamps of the image itself, not of imported modules.
+    pub timestamps: Vec<TriageTimestamp>,
+    pub sections: Vec<TriageSection>,
+    /// Imported and delay imported modules, in table order, without repeats.
+    pub imports: Vec<String>,
+    /// Number of exported functions.
+    pub exports: usize,
+    /// Whether the image has a certificate table; the signature is not verified.
+    pub signed: bool,
+    /// Number of anomalies found by `PeImage::analyze`.
+    pub anomalies: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct TriageTimestamp {
+    pub source: TimestampSource,
+    pub value: TimestampValue,
+    pub class: TimestampClass,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub struct TriageSection {
+    pub name: String,
+    pub virtual_size: u32,
+    pub raw_size: u32,
+    /// Of the raw data, in bits per byte.
+    pub entropy: f64,
+    /// `r`, `w` and `x` for readable, writable and executable, `-` where not set.
+    pub access: String,
+}
+
+impl TriagePeImage {
+    /// Summary of `pe`, hashing its input and running `PeImage::analyze`. Timestamps are
+    /// serialized in `timestamps` form.
+    pub fn new(pe: &mut PeImage, timestamps: TimestampFormat) -> Result<Self> {
+        let hashes = pe.file_hashes(true)?;
+        let size = pe.reader.input_end()?;
+
+        let anomalies = match pe.analyze() {
+            Ok(report) => report.anomalies.len(),
+            Err(err) => {
+                tracing::warn!(%err, "triage counts only header anomalies; analysis failed");
+                pe.validate().len()
+            },
+        };
+
+        let mut sections = Vec::with_capacity(pe.sections.value.len());
+        for index in 0..pe.sections.value.len() {
+            //Unreadable raw data only leaves its entropy at 0.
+            let entropy = pe.section_data(index).map_or(0.0, |data| entropy::shannon(&data));
+            let header = &pe.sections.value[index].value;
+            let flags = section::Flags::from_bits_truncate(header.charactristics.value);
+            let access = [(section::Flags::MEM_READ, 'r'), (section::Flags::MEM_WRITE, 'w'), (section::Flags::MEM_EXECUTE, 'x')]
+                .iter()
+                .map(|(flag, c)| if flags.contains(*flag) { *c } else { '-' })
+                .collect();
+            sections.push(TriageSection {
+                name: header.name_lossy(),
+                virtual_size: header.virtual_size.value,
+                raw_size: header.sizeof_raw_data.value,
+                entropy,
+                access,
+            });
+        }
+
+        let mut imports: Vec<String> = Vec::new();
+        let names = pe.imports.value.iter().map(|d| &d.value.name)
+            .chain(pe.delay_imports.value.iter().map(|d| &d.value.name))
+            .flatten();
+        for name in names {
+            if !imports.iter().any(|i| i.eq_ignore_ascii_case(name)) {
+                imports.push(name.clone());
+            }
+        }
+
+        Ok(Self {
+            triage_version: TRIAGE_VERSION,
+            size,
+            md5: hashes.md5,
+            sha1: hashes.sha1,
+            sha256: hashes.sha256,
+            ssdeep: hashes.ssdeep,
+            imphash: pe.imphash(),
+            subsystem: pe.optional.value.subsystem(),
+            timestamps: pe.timestamps().into_iter()
+                .filter(|t| t.source.is_own())
+                .map(|t| TriageTimestamp { source: t.source, value: TimestampValue::new(t.value, timestamps), class: t.class })
+                .collect(),
+            sections,
+            imports,
+            exports: match pe.has_exports() {
+                true => pe.exports.value.exports.len(),
+                false => 0,
+            },
+            signed: pe.has_directory(DirectoryType::Security),
+            anomalies,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::pe::{builder::PeBuilder, file::MachineType, ser::TimestampFormat, PeImage};
+
+    use super::{TriagePeImage, TRIAGE_VERSION};
+
+    #[test]
+    fn triage_of_built_image() {
+        let bytes = PeBuilder::new(MachineType::AMD64)
+            .with_code(vec![0xC3], 0)
+            .with_import("KERNEL32.dll", &["ExitProcess"])
+            .with_timestamp(0x6000_0000)
+            .build()
+            .unwrap();
+        let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+        let triage = TriagePeImage::new(&mut pe, TimestampFormat::Epoch).unwrap();
+
+        assert_eq!(triage.triage_version, TRIAGE_VERSION);
+        assert_eq!(triage.size, bytes.len() as u64);
+        assert_eq!(triage.imports, vec!["KERNEL32.dll"]);
+        assert!(triage.imphash.is_some());
+        assert_eq!(triage.exports, 0);
+        assert!(!triage.signed);
+        assert_eq!(triage.sections[0].access, "r-x");
+
+        let json = serde_json::to_value(&triage).unwrap();
+        assert_eq!(json["timestamps"][0]["value"], 0x6000_0000);
+        assert!(json["ssdeep"].is_string());
+        assert!(json.get("imphash").is_some());
+    }
+}
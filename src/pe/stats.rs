@@ -0,0 +1,193 @@
+//! Durations and reads of each parse step, to find inputs that are slow or costly to parse.
+//!
+//! Collected only if enabled with `PeImage::enable_stats` before parsing; reads are counted by a
+//! wrapper around the reader of the image.
+
+use std::{cell::Cell, fmt::Display, io::{BufRead, Read, Seek, SeekFrom}, rc::Rc, time::Duration};
+
+use serde::Serialize;
+
+use crate::types::{BufReadExt, ReadExtError};
+
+use super::PeImage;
+
+/// Counts shared by the reader wrapper and `ParseStats`.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Counters {
+    bytes: Cell<u64>,
+    allocations: Cell<u64>,
+}
+
+impl Counters {
+    fn add(&self, bytes: u64, allocations: u64) {
+        self.bytes.set(self.bytes.get() + bytes);
+        self.allocations.set(self.allocations.get() + allocations);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StepStats {
+    pub name: &'static str,
+    pub duration: Duration,
+    pub bytes_read: u64,
+    /// Buffers allocated for reads; each read at an offset allocates one.
+    pub allocations: u64,
+    pub failed: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ParseStats {
+    pub steps: Vec<StepStats>,
+    #[serde(skip)]
+    counters: Rc<Counters>,
+}
+
+impl ParseStats {
+    /// Bytes read and buffers allocated so far.
+    pub(crate) fn snapshot(&self) -> (u64, u64) {
+        (self.counters.bytes.get(), self.counters.allocations.get())
+    }
+
+    pub(crate) fn record(&mut self, name: &'static str, before: (u64, u64), duration: Duration, failed: bool) {
+        let (bytes, allocations) = self.snapshot();
+        self.steps.push(StepStats {
+            name,
+            duration,
+            bytes_read: bytes - before.0,
+            allocations: allocations - before.1,
+            failed,
+        });
+    }
+
+    /// Bytes read by all steps, and by reads outside of steps like `read_bytes_at_rva`.
+    pub fn bytes_read(&self) -> u64 {
+        self.counters.bytes.get()
+    }
+
+    pub fn allocations(&self) -> u64 {
+        self.counters.allocations.get()
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(|s| s.duration).sum()
+    }
+}
+
+impl Display for ParseStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Parse stats:")?;
+        writeln!(f, "  {:<16} {:>12} {:>12} {:>8}", "Step", "Duration", "Bytes", "Allocs")?;
+        for step in &self.steps {
+            writeln!(
+                f, "  {:<16} {:>12} {:>12} {:>8}{}",
+                step.name, format!("{:.3?}", step.duration), step.bytes_read, step.allocations,
+                if step.failed { "  failed" } else { "" }
+            )?;
+        }
+        write!(
+            f, "  {:<16} {:>12} {:>12} {:>8}",
+            "Total", format!("{:.3?}", self.duration()), self.bytes_read(), self.allocations()
+        )
+    }
+}
+
+/// Reader counting what is read through it.
+struct CountingReader {
+    inner: Box<dyn BufReadExt>,
+    counters: Rc<Counters>,
+}
+
+impl Read for CountingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.counters.add(count as u64, 0);
+        Ok(count)
+    }
+}
+
+impl BufRead for CountingReader {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.counters.add(amt as u64, 0);
+        self.inner.consume(amt)
+    }
+}
+
+impl Seek for CountingReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl BufReadExt for CountingReader {
+    fn read_string_at_offset(&mut self, offset: u64) -> Result<String, ReadExtError> {
+        let value = self.inner.read_string_at_offset(offset)?;
+        self.counters.add(value.len() as u64 + 1, 1);
+        Ok(value)
+    }
+
+    fn read_bytes_at_offset(&mut self, offset: u64, size: usize) -> Result<Vec<u8>, ReadExtError> {
+        let value = self.inner.read_bytes_at_offset(offset, size)?;
+        self.counters.add(value.len() as u64, 1);
+        Ok(value)
+    }
+
+    fn read_wchar_string_at_offset(&mut self, offset: u64) -> Result<String, ReadExtError> {
+        let value = self.inner.read_wchar_string_at_offset(offset)?;
+        self.counters.add(2 + value.encode_utf16().count() as u64 * 2, 1);
+        Ok(value)
+    }
+
+    fn end_offset(&mut self) -> Result<u64, ReadExtError> {
+        self.inner.end_offset()
+    }
+}
+
+impl PeImage {
+    /// Collect `parse_stats` from now on. Call before parsing, e.g. after `new`.
+    pub fn enable_stats(&mut self) {
+        if self.stats.is_some() {
+            return;
+        }
+        let stats = ParseStats::default();
+        let inner = std::mem::replace(&mut self.reader, Box::new(std::io::Cursor::new(Vec::new())));
+        self.reader = Box::new(CountingReader { inner, counters: stats.counters.clone() });
+        self.stats = Some(stats);
+    }
+
+    /// Stats of parse steps so far; `None` unless enabled with `enable_stats`.
+    pub fn parse_stats(&self) -> Option<&ParseStats> {
+        self.stats.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::Cursor};
+
+    use crate::pe::PeImage;
+
+    #[test]
+    fn stats_of_test_dll() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+
+        let mut pe = PeImage::new(Box::new(Cursor::new(bytes)));
+        assert!(pe.parse_stats().is_none());
+        pe.enable_stats();
+        pe.parse_all_headers(0).unwrap();
+
+        let stats = pe.parse_stats().unwrap();
+        let names: Vec<&str> = stats.steps.iter().map(|s| s.name).collect();
+        assert_eq!(&names[..3], ["fixed headers", "sections", "imports"]);
+        assert!(stats.steps.iter().all(|s| !s.failed));
+
+        let imports = &stats.steps[2];
+        assert!(imports.bytes_read > 0 && imports.allocations > 0);
+        assert_eq!(stats.bytes_read(), stats.steps.iter().map(|s| s.bytes_read).sum::<u64>());
+        assert!(stats.to_string().contains("imports"));
+    }
+}
@@ -0,0 +1,52 @@
+//! What has been parsed from an image, for callers composing `PeImage::parse_*` steps themselves.
+
+use serde::Serialize;
+
+use super::PeError;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub enum ParseStatus {
+    #[default]
+    NotParsed,
+    /// The image does not have this part.
+    Absent,
+    Parsed,
+    /// The last attempt failed, with this error and its sources.
+    Failed(String),
+}
+
+impl ParseStatus {
+    pub(crate) fn of<T>(present: bool, result: &Result<T, PeError>) -> Self {
+        match result {
+            Err(err) => Self::Failed(err.chain()),
+            Ok(_) if !present => Self::Absent,
+            Ok(_) => Self::Parsed,
+        }
+    }
+
+    /// Whether parsing was attempted, successfully or not.
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        *self != Self::NotParsed
+    }
+
+    #[inline]
+    pub fn is_parsed(&self) -> bool {
+        *self == Self::Parsed
+    }
+}
+
+/// Status of each part parsed by `PeImage::parse_*` after the fixed headers and sections.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct ParseState {
+    pub imports: ParseStatus,
+    pub delay_imports: ParseStatus,
+    pub bound_imports: ParseStatus,
+    pub exports: ParseStatus,
+    pub relocations: ParseStatus,
+    pub resources: ParseStatus,
+    pub load_config: ParseStatus,
+    pub debug: ParseStatus,
+    pub chpe: ParseStatus,
+    pub symbols: ParseStatus,
+}
@@ -0,0 +1,326 @@
+//! Terse Executable (TE) header used by UEFI PEI modules.
+//!
+//! A TE image is a PE32(+) with the DOS, file and optional headers replaced by one
+//! small header; the section table follows it directly. `stripped_size` is the number
+//! of bytes removed from the original image, so an original file offset `o` is found
+//! at `o - stripped_size + HEADER_LENGTH` in the TE image. Section headers and rvas
+//! are kept unchanged, so `TeImage` uses the same section and entry point model as
+//! `PeImage` and translates offsets on lookup.
+
+use std::{fmt::{Display, Write}, fs::File, io::{BufReader, Cursor}};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use derivative::Derivative;
+
+use crate::{new_header_field, pe::Result, types::{BufReadExt, Header, HeaderField}};
+
+use super::{
+    file::MachineType, 
+    optional::{DataDirectory, DirectoryType, SubSystem}, 
+    relocs::Relocations, 
+    section::{self, IndexedSections, SectionLookup, SectionMap, SectionTable}, 
+    limits::ParseLimits,
+    PeError
+};
+
+pub const HEADER_LENGTH: u64 = 40;
+pub const TE_SIGNATURE: u16 = 0x5A56; //"VZ"
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TeHeader {
+    pub signature: HeaderField<u16>,
+    pub machine: HeaderField<MachineType>,
+    pub number_of_sections: HeaderField<u8>,
+    pub subsystem: HeaderField<SubSystem>,
+    pub stripped_size: HeaderField<u16>,
+    pub address_of_entry_point: HeaderField<u32>,
+    pub base_of_code: HeaderField<u32>,
+    pub image_base: HeaderField<u64>,
+    pub relocations: DataDirectory,
+    pub debug: DataDirectory,
+    /// Machine as stored; `machine` is `UNKNOWN` for types it doesn't list.
+    pub machine_raw: u16,
+    /// Subsystem as stored; `subsystem` is `UNKNOWN` for values it doesn't list.
+    pub subsystem_raw: u8,
+}
+
+impl TeHeader {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Difference between an offset in the original PE and the same byte in the TE image.
+    pub fn adjustment(&self) -> i64 {
+        self.stripped_size.value as i64 - HEADER_LENGTH as i64
+    }
+
+    #[inline]
+    pub fn has_relocations(&self) -> bool {
+        self.relocations.rva.value != 0 && self.relocations.size.value != 0
+    }
+}
+
+/// Returns `true` if `bytes` start with a TE signature.
+pub fn is_te(bytes: &[u8]) -> bool {
+    bytes.len() >= 2 && u16::from_le_bytes([bytes[0], bytes[1]]) == TE_SIGNATURE
+}
+
+impl Header for TeHeader {
+    fn parse_bytes(bytes: Vec<u8>, pos: u64) -> crate::pe::Result<Self> {
+        let bytes_len = bytes.len() as u64;
+
+        if bytes_len < HEADER_LENGTH {
+            return Err (
+                PeError::BufferTooSmall { target: "TeHeader".into(), expected: HEADER_LENGTH, actual: bytes_len }
+            );
+        }
+
+        let mut cursor = Cursor::new(bytes);
+        let mut offset = pos;
+        let mut te = Self::new();
+
+        te.signature = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+
+        let data = cursor.read_u16::<LittleEndian>()?;
+        te.machine = HeaderField::at_offset(MachineType::from(data), offset);
+        te.machine_raw = data;
+        offset += 2;
+
+        te.number_of_sections = new_header_field!(cursor.read_u8()?, offset);
+
+        let data = cursor.read_u8()?;
+        te.subsystem = HeaderField::at_offset(SubSystem::from(data as u16), offset);
+        te.subsystem_raw = data;
+        offset += 1;
+
+        te.stripped_size = new_header_field!(cursor.read_u16::<LittleEndian>()?, offset);
+        te.address_of_entry_point = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        te.base_of_code = new_header_field!(cursor.read_u32::<LittleEndian>()?, offset);
+        te.image_base = new_header_field!(cursor.read_u64::<LittleEndian>()?, offset);
+        te.relocations = DataDirectory {
+            member: DirectoryType::Relocation,
+            rva: new_header_field!(cursor.read_u32::<LittleEndian>()?, offset),
+            size: new_header_field!(cursor.read_u32::<LittleEndian>()?, offset),
+        };
+        te.debug = DataDirectory {
+            member: DirectoryType::Debug,
+            rva: new_header_field!(cursor.read_u32::<LittleEndian>()?, offset),
+            size: new_header_field!(cursor.read_u32::<LittleEndian>()?, offset),
+        };
+
+        Ok(te)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.signature.value == TE_SIGNATURE
+    }
+
+    fn length() -> usize { HEADER_LENGTH as usize }
+}
+
+impl Display for TeHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{Signature: '{}', Machine: {:?}, Sections: {}, Subsystem: {:?}, StrippedSize: {:#x}, EntryPoint: {:#08x}, ImageBase: {:#x}}}",
+            std::str::from_utf8(&self.signature.value.to_le_bytes()).unwrap_or("ERR"),
+            self.machine.value, self.number_of_sections.value, self.subsystem.value,
+            self.stripped_size.value, self.address_of_entry_point.value, self.image_base.value)
+    }
+}
+
+/// A parsed TE image; the counterpart of `PeImage` for UEFI PEI modules.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct TeImage {
+    pub header: HeaderField<TeHeader>,
+    pub sections: HeaderField<SectionTable>,
+    pub relocations: HeaderField<Relocations>,
+
+    #[derivative(Debug="ignore")]
+    section_map: SectionMap,
+
+    /// Limits on sizes and counts read from the image.
+    pub limits: ParseLimits,
+
+    #[derivative(Debug="ignore")]
+    reader: Box<dyn BufReadExt>,
+}
+
+impl TeImage {
+    pub fn new(reader: Box<dyn BufReadExt>) -> Self {
+        Self {
+            header: Default::default(),
+            sections: Default::default(),
+            relocations: Default::default(),
+            section_map: Default::default(),
+            limits: Default::default(),
+            reader,
+        }
+    }
+
+    #[inline]
+    pub fn entry_point(&self) -> u32 {
+        self.header.value.address_of_entry_point.value
+    }
+
+    #[inline]
+    pub fn image_base(&self) -> u64 {
+        self.header.value.image_base.value
+    }
+
+    #[inline]
+    pub fn section_lookup(&self) -> IndexedSections<'_> {
+        IndexedSections::new(&self.sections.value, &self.section_map)
+    }
+
+    /// Offset in the TE image of `rva`.
+    pub fn rva_to_offset(&self, rva: u32) -> Option<u32> {
+        let offset = self.section_lookup().rva_to_offset(rva)? as i64 - self.header.value.adjustment();
+        u32::try_from(offset).ok()
+    }
+
+    /// Rva of `offset` in the TE image.
+    pub fn offset_to_rva(&self, offset: u64) -> Option<u32> {
+        let original = u32::try_from(offset as i64 + self.header.value.adjustment()).ok()?;
+        self.section_lookup().offset_to_rva(original)
+    }
+
+    pub fn read_bytes_at_rva(&mut self, rva: u32, size: usize) -> Result<Vec<u8>> {
+        let offset = self.rva_to_offset(rva).ok_or(PeError::InvalidRVA(rva.into()))?;
+        Ok(self.reader.read_bytes_at_offset(offset.into(), size)?)
+    }
+
+    #[inline]
+    pub fn has_relocations(&self) -> bool {
+        self.header.value.has_relocations()
+    }
+
+    pub fn parse_relocations(&mut self) -> Result<()> {
+        if !self.has_relocations() {
+            return Ok(());
+        }
+
+        let relocs_rva = self.header.value.relocations.rva.value;
+        let relocs_size = self.header.value.relocations.size.value as u64;
+        let relocs_offset = self.rva_to_offset(relocs_rva).ok_or(PeError::NoSectionForRVA(relocs_rva.into()))?;
+
+        let mut relocs = Relocations::parse_stream(&mut self.reader, relocs_offset.into(), relocs_size, self.limits.max_alloc)?;
+        relocs.set_machine(self.header.value.machine.value);
+        relocs.fix_rvas(relocs_rva.into())?;
+        self.relocations = HeaderField {value: relocs, offset: relocs_offset.into(), rva: relocs_rva.into()};
+
+        Ok(())
+    }
+
+    /// Parse TE header, section table and relocations from `pos`.
+    pub fn parse_all_headers(&mut self, pos: u64) -> Result<()> {
+        let _span = tracing::info_span!("parse TE", pos).entered();
+        let buf = self.reader.read_bytes_at_offset(pos, HEADER_LENGTH as usize)?;
+        let header = TeHeader::parse_bytes(buf, pos)?;
+        if !header.is_valid() {
+            return Err(PeError::InvalidHeader {
+                name: "TeHeader".into(),
+                offset: pos,
+                reason: format!("signature {:#06x} is not 'VZ'", header.signature.value),
+            });
+        }
+        self.header = HeaderField::at_offset(header, pos);
+
+        let offset = pos + HEADER_LENGTH;
+        let count = self.header.value.number_of_sections.value as u16;
+        let end = self.reader.end_offset()?;
+        section::check_section_count(count, offset, self.limits.max_sections, end)?;
+        let buf = self.reader.read_bytes_at_offset(offset, (section::HEADER_LENGTH * count as u64) as usize)?;
+        self.sections = HeaderField::at_offset(section::parse_sections(&buf, count, offset)?, offset);
+        self.section_map = SectionMap::new(&self.sections.value);
+        tracing::debug!(machine = ?self.header.value.machine.value, sections = count, "headers parsed");
+
+        timed!("relocations", self.parse_relocations())
+    }
+
+    /// Parse a TE image from file.
+    pub fn parse_file(file: File, pos: u64) -> Result<Self> {
+        Self::parse_readable(Box::new(BufReader::new(file)), pos)
+    }
+
+    /// Parse a TE image from an in-memory buffer holding the entire image.
+    pub fn parse_bytes(bytes: Vec<u8>, pos: u64) -> Result<Self> {
+        Self::parse_readable(Box::new(Cursor::new(bytes)), pos)
+    }
+
+    pub fn parse_readable(reader: Box<dyn BufReadExt>, pos: u64) -> Result<Self> {
+        let mut te = Self::new(reader);
+        te.parse_all_headers(pos)?;
+        Ok(te)
+    }
+
+    pub fn format_basic_headers(&self, f: &mut dyn Write) -> std::fmt::Result {
+        writeln!(f, "TeHeader: {}", self.header.value)?;
+        writeln!(f, "  RelocationDirectory: {}", self.header.value.relocations)?;
+        writeln!(f, "  DebugDirectory: {}", self.header.value.debug)
+    }
+
+    pub fn format_sections(&self, f: &mut dyn Write) -> std::fmt::Result {
+        writeln!(f, "Sections: [")?;
+        for sec in &self.sections.value {
+            writeln!(f, "  {sec}")?;
+        }
+        writeln!(f, "]")
+    }
+
+    pub fn format_relocations(&self, f: &mut dyn Write) -> std::fmt::Result {
+        if self.has_relocations() && self.relocations.value.is_valid() {
+            writeln!(f, "Relocation Directory: [")?;
+            for rb in &self.relocations.value.blocks {
+                writeln!(f, "  [{rb}")?;
+                for rc in &rb.value.relocs {
+                    writeln!(f, "    {}", rc.value)?;
+                }
+                writeln!(f, "  ]")?;
+            }
+            writeln!(f, "]")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pe::{file::MachineType, optional::SubSystem}, types::{FileOffset, Header}};
+
+    use super::{is_te, TeHeader};
+
+    const RAW_TE: [u8; 40] = [
+        0x56, 0x5A, 0x64, 0x86, 0x03, 0x0B, 0x20, 0x01, 0x40, 0x04, 0x00, 0x00, 0x40, 0x02, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn parse_te_header() {
+        assert!(is_te(&RAW_TE));
+        let te = TeHeader::parse_bytes(RAW_TE.to_vec(), 0).unwrap();
+
+        assert!(te.is_valid());
+        assert_eq!(te.machine.value, MachineType::AMD64);
+        assert_eq!(te.machine.offset, FileOffset(2));
+        assert_eq!(te.number_of_sections.value, 3);
+        assert_eq!(te.subsystem.value, SubSystem::EFI_BOOT_SERVICE_DRIVER);
+        assert_eq!(te.stripped_size.value, 0x120);
+        assert_eq!(te.adjustment(), 0x120 - 40);
+        assert_eq!(te.address_of_entry_point.value, 0x440);
+        assert_eq!(te.address_of_entry_point.offset, FileOffset(8));
+        assert_eq!(te.base_of_code.value, 0x240);
+        assert_eq!(te.image_base.value, 0xFF00_0000);
+        assert!(te.has_relocations());
+        assert_eq!(te.relocations.rva.value, 0x3000);
+        assert_eq!(te.relocations.size.offset, FileOffset(28));
+        assert_eq!(te.debug.rva.offset, FileOffset(32));
+        assert_eq!(te.debug.rva.value, 0);
+    }
+
+    #[test]
+    fn parse_te_too_small() {
+        assert!(!is_te(&[0x4D, 0x5A]));
+        assert!(TeHeader::parse_bytes(RAW_TE[..30].to_vec(), 0).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! Every `TimeDateStamp` of an image in one list, classified to spot timestomping.
+//!
+//! A linker writes the same time to the file header, the export and debug directories and,
+//! often, the resource directory. A changed file header leaves the others behind, so times of
+//! the image which are far from the rest are reported as outliers. Times of import descriptors
+//! and bound imports are of other modules and are not compared. With a `REPRO` debug entry,
+//! times are hashes of the image and are not compared either.
+
+use std::fmt::Display;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+
+use super::{debug::DebugType, rsrc::{ResourceDirectory, ResourceNode}, PeImage, PeTimestamp};
+
+/// Distance from the median of the times of the image, beyond which a time is an outlier.
+pub const OUTLIER_DAYS: i64 = 30;
+
+/// Structure a timestamp is read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum TimestampSource {
+    FileHeader,
+    ExportDirectory,
+    /// `0xFFFFFFFF` or the time of the module an import descriptor is bound to.
+    ImportDescriptor,
+    DelayImport,
+    /// Time of the module an import is bound to.
+    BoundImport,
+    DebugDirectory,
+    ResourceDirectory,
+    LoadConfig,
+}
+
+impl TimestampSource {
+    /// The time is of this image, not of an imported module.
+    pub fn is_own(&self) -> bool {
+        !matches!(self, Self::ImportDescriptor | Self::DelayImport | Self::BoundImport)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+pub enum TimestampClass {
+    Valid,
+    /// Not set; common for resource and import directories.
+    Zero,
+    /// `0xFFFFFFFF`, e.g. of bound import descriptors.
+    Max,
+    /// Later than the time of the check.
+    Future,
+    /// More than `OUTLIER_DAYS` from the median of the times of the image.
+    Outlier,
+    /// A hash of a reproducible build, not a time.
+    Reproducible,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImageTimestamp {
+    pub source: TimestampSource,
+    /// Module of imports, type of debug entries.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub name: Option<String>,
+    pub value: PeTimestamp,
+    pub offset: u64,
+    pub class: TimestampClass,
+}
+
+impl ImageTimestamp {
+    fn new(source: TimestampSource, name: Option<String>, value: PeTimestamp, offset: u64) -> Self {
+        Self { source, name, value, offset, class: TimestampClass::Valid }
+    }
+}
+
+impl Display for ImageTimestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f, "{:<18} {:<24} {:#010x}  {:<32} {:?}",
+            format!("{:?}", self.source), self.name.as_deref().unwrap_or(""), self.offset, self.value.to_string(), self.class
+        )
+    }
+}
+
+/// Set `class` of each timestamp, with `now` as the time of the check.
+pub fn classify(timestamps: &mut [ImageTimestamp], is_repro: bool, now: DateTime<Utc>) {
+    for ts in timestamps.iter_mut() {
+        ts.class = match ts.value {
+            PeTimestamp::Raw(u32::MAX) => TimestampClass::Max,
+            value if value.raw() == 0 => TimestampClass::Zero,
+            _ if is_repro && ts.source.is_own() => TimestampClass::Reproducible,
+            PeTimestamp::Valid(time) if time > now => TimestampClass::Future,
+            _ => TimestampClass::Valid,
+        };
+    }
+
+    let mut own: Vec<DateTime<Utc>> = timestamps.iter()
+        .filter(|ts| ts.source.is_own() && ts.class == TimestampClass::Valid)
+        .filter_map(|ts| ts.value.as_datetime())
+        .collect();
+    if own.len() < 2 {
+        return;
+    }
+    own.sort();
+    let median = own[(own.len() - 1) / 2];
+
+    for ts in timestamps.iter_mut().filter(|ts| ts.source.is_own() && ts.class == TimestampClass::Valid) {
+        if ts.value.as_datetime().is_some_and(|time| (time - median).abs() > TimeDelta::days(OUTLIER_DAYS)) {
+            ts.class = TimestampClass::Outlier;
+        }
+    }
+}
+
+/// Times of `dir` and its subdirectories; only the root if they are not set.
+fn rsrc_timestamps(dir: &ResourceDirectory, is_root: bool, out: &mut Vec<ImageTimestamp>) {
+    if is_root || dir.timestamp.value.raw() != 0 {
+        out.push(ImageTimestamp::new(TimestampSource::ResourceDirectory, None, dir.timestamp.value, dir.timestamp.offset.into()));
+    }
+    for entry in &dir.entries {
+        if let ResourceNode::Dir(sub) = &entry.data {
+            rsrc_timestamps(sub, false, out);
+        }
+    }
+}
+
+impl PeImage {
+    /// Timestamps of the headers and parsed directories, in that order, classified at the
+    /// current time.
+    pub fn timestamps(&self) -> Vec<ImageTimestamp> {
+        self.timestamps_at(Utc::now())
+    }
+
+    /// `timestamps`, classified at `now`.
+    pub fn timestamps_at(&self, now: DateTime<Utc>) -> Vec<ImageTimestamp> {
+        use TimestampSource::*;
+
+        let file = &self.file.value.timestamp;
+        let mut timestamps = vec![ImageTimestamp::new(FileHeader, None, file.value, file.offset.into())];
+
+        if self.has_exports() {
+            let ts = &self.exports.value.timestamp;
+            timestamps.push(ImageTimestamp::new(ExportDirectory, None, ts.value, ts.offset.into()));
+        }
+        if self.has_imports() {
+            timestamps.extend(self.imports.value.iter()
+                .map(|id| ImageTimestamp::new(ImportDescriptor, id.value.name.clone(), id.value.timestamp.value, id.value.timestamp.offset.into())));
+        }
+        if self.has_delay_imports() {
+            timestamps.extend(self.delay_imports.value.iter()
+                .map(|id| ImageTimestamp::new(DelayImport, id.value.name.clone(), id.value.timestamp.value, id.value.timestamp.offset.into())));
+        }
+        if self.has_bound_imports() {
+            timestamps.extend(self.bound_imports.value.iter()
+                .map(|bi| ImageTimestamp::new(BoundImport, Some(bi.value.name.clone()), bi.value.timestamp.value, bi.value.timestamp.offset.into())));
+        }
+        if self.has_debug() {
+            timestamps.extend(self.debug.value.entries.iter()
+                .map(|de| ImageTimestamp::new(DebugDirectory, Some(format!("{:?}", de.value.debug_type.value)), de.value.timestamp.value, de.value.timestamp.offset.into())));
+        }
+        if self.has_rsrc() {
+            rsrc_timestamps(&self.resources.value, true, &mut timestamps);
+        }
+        if self.has_load_config() {
+            let ts = &self.load_config.value.timestamp;
+            timestamps.push(ImageTimestamp::new(LoadConfig, None, PeTimestamp::from(ts.value), ts.offset.into()));
+        }
+
+        let is_repro = self.has_debug() && self.debug.value.entries.iter().any(|de| de.value.debug_type.value == DebugType::REPRO);
+        classify(&mut timestamps, is_repro, now);
+        timestamps
+    }
+
+    pub fn format_timestamps(&self, f: &mut dyn std::fmt::Write) -> std::fmt::Result {
+        writeln!(f, "Timestamps: [")?;
+        writeln!(f, "  {:<18} {:<24} {:<10}  {:<32} Class", "Source", "Name", "Offset", "Value")?;
+        for ts in self.timestamps() {
+            writeln!(f, "  {ts}")?;
+        }
+        writeln!(f, "]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use chrono::DateTime;
+
+    use crate::pe::{PeImage, PeTimestamp};
+
+    use super::{classify, ImageTimestamp, TimestampClass, TimestampSource};
+
+    #[test]
+    fn timestamps_of_test_dll() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+        let timestamps = pe.timestamps();
+
+        let file = &timestamps[0];
+        assert_eq!(file.source, TimestampSource::FileHeader);
+        assert_eq!(file.value, pe.file.value.timestamp.value);
+        assert_eq!(file.offset, u64::from(pe.file.value.timestamp.offset));
+
+        let imports: Vec<&ImageTimestamp> = timestamps.iter().filter(|ts| ts.source == TimestampSource::ImportDescriptor).collect();
+        assert_eq!(imports.len(), 2);
+        assert!(imports.iter().all(|ts| ts.class == TimestampClass::Zero));
+        assert!(timestamps.iter().any(|ts| ts.source == TimestampSource::ExportDirectory));
+        assert!(!timestamps.iter().any(|ts| ts.class == TimestampClass::Outlier));
+
+        let mut text = String::new();
+        pe.format_timestamps(&mut text).unwrap();
+        assert!(text.starts_with("Timestamps: ["));
+        assert_eq!(text.lines().count(), timestamps.len() + 3);
+    }
+
+    #[test]
+    fn classify_stomped() {
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let ts = |source, value: u32| ImageTimestamp::new(source, None, PeTimestamp::from(value), 0);
+        let mut timestamps = vec![
+            ts(TimestampSource::FileHeader, 0x2a42_5e19),
+            ts(TimestampSource::ExportDirectory, 1_642_391_237),
+            ts(TimestampSource::DebugDirectory, 1_642_391_237),
+            ts(TimestampSource::ResourceDirectory, 0),
+            ts(TimestampSource::ImportDescriptor, u32::MAX),
+            ts(TimestampSource::BoundImport, 0x2a42_5e19),
+            ts(TimestampSource::LoadConfig, 1_800_000_000),
+        ];
+        classify(&mut timestamps, false, now);
+        let classes: Vec<TimestampClass> = timestamps.iter().map(|ts| ts.class).collect();
+        assert_eq!(classes, [
+            TimestampClass::Outlier, TimestampClass::Valid, TimestampClass::Valid, TimestampClass::Zero,
+            TimestampClass::Max, TimestampClass::Valid, TimestampClass::Future,
+        ]);
+
+        classify(&mut timestamps, true, now);
+        assert_eq!(timestamps[0].class, TimestampClass::Reproducible);
+        assert_eq!(timestamps[5].class, TimestampClass::Valid);
+    }
+}
@@ -0,0 +1,318 @@
+//! Parsed image as a tree of named values, for viewers which show any part of it the same way.
+//!
+//! `PeImage::to_tree` builds it from what has been parsed; directories the image doesn't have
+//! are left out. Nodes carry the file offset and size of what they show, where it has one.
+
+use std::{fmt::Display, mem::size_of_val};
+
+use serde::Serialize;
+
+use crate::types::HeaderField;
+
+use super::{
+    dos, file, import::{ImportLookup, IMPORT_DESCRIPTOR_SIZE}, optional::{DirectoryType, OptionalHeader},
+    rsrc::{ResourceDirectory, ResourceNode}, PeImage, SECTION_HEADER_LENGTH,
+};
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct TreeNode {
+    pub name: String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub value: Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub offset: Option<u64>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), ..Default::default() }
+    }
+
+    pub fn with_value(mut self, value: impl Display) -> Self {
+        self.value = Some(value.to_string());
+        self
+    }
+
+    /// Place the node at `offset` in the file, spanning `size` bytes.
+    pub fn at(mut self, offset: u64, size: u64) -> Self {
+        self.offset = Some(offset);
+        self.size = Some(size);
+        self
+    }
+
+    /// `at`, if `offset` is known; the node is not placed otherwise.
+    pub fn at_some(self, offset: Option<u64>, size: u64) -> Self {
+        match offset {
+            Some(offset) => self.at(offset, size),
+            None => self,
+        }
+    }
+
+    pub fn with_children(mut self, children: Vec<TreeNode>) -> Self {
+        self.children = children;
+        self
+    }
+
+    /// Node at `path`, names of nodes below this one; the first match is taken at each level.
+    pub fn find(&self, path: &[&str]) -> Option<&TreeNode> {
+        match path.split_first() {
+            None => Some(self),
+            Some((name, rest)) => self.children.iter().find(|c| c.name == *name)?.find(rest),
+        }
+    }
+
+    /// Number of nodes, this one included.
+    pub fn count(&self) -> usize {
+        1 + self.children.iter().map(|c| c.count()).sum::<usize>()
+    }
+
+    #[cfg(feature="json")]
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+/// Leaf of a header field shown as `value`, spanning `size` bytes.
+fn leaf<T>(name: &str, field: &HeaderField<T>, value: impl Display, size: usize) -> TreeNode {
+    TreeNode::new(name).with_value(value).at(field.offset.into(), size as u64)
+}
+
+/// Leaves of integer fields, in hex.
+macro_rules! hex_leaves {
+    ($hdr:expr, $($field:ident),+) => {
+        vec![$( leaf(stringify!($field), &$hdr.$field, format!("{:#x}", $hdr.$field.value), size_of_val(&$hdr.$field.value)) ),+]
+    };
+}
+
+/// Leaves of the fields both optional headers have.
+macro_rules! optional_leaves {
+    ($opt:expr) => {{
+        let mut leaves = vec![leaf("magic", &$opt.magic, format!("{:?}", $opt.magic.value), 2)];
+        leaves.extend(hex_leaves!($opt, major_linker_ver, minor_linker_ver, sizeof_code, sizeof_initiailized_data,
+            sizeof_uninitiailized_data, address_of_entry_point, base_of_code, image_base, section_alignment,
+            file_alignment, major_os_version, minor_os_version, major_image_version, minor_image_version,
+            major_subsystem_version, minor_subsystem_version, win32_version, sizeof_image, sizeof_headers, checksum));
+        leaves.push(leaf("subsystem", &$opt.subsystem, format!("{:?}", $opt.subsystem.value), 2));
+        leaves.extend(hex_leaves!($opt, dll_charactristics, sizeof_stack_reserve, sizeof_stack_commit,
+            sizeof_heap_reserve, sizeof_heap_commit, loader_flags, number_of_rva_and_sizes));
+        leaves
+    }};
+}
+
+impl PeImage {
+    /// Headers, sections and parsed directories as a tree.
+    pub fn to_tree(&self) -> TreeNode {
+        let mut root = TreeNode::new("PE Image").with_children(vec![
+            self.headers_tree(),
+            self.data_dirs_tree(),
+            self.sections_tree(),
+        ]);
+        if self.has_imports() {
+            root.children.push(self.imports_tree());
+        }
+        if self.has_exports() {
+            root.children.push(self.exports_tree());
+        }
+        if self.has_relocations() {
+            root.children.push(self.relocations_tree());
+        }
+        if self.has_rsrc() && !self.resources.value.entries.is_empty() {
+            root.children.push(TreeNode::new("Resources").with_children(self.rsrc_tree(&self.resources.value, true)));
+        }
+        root
+    }
+
+    fn dir_size(&self, dir: DirectoryType) -> u64 {
+        self.directory(dir).map_or(0, |dir| dir.size.value.into())
+    }
+
+    fn headers_tree(&self) -> TreeNode {
+        let dos = &self.dos.value;
+        let file = &self.file.value;
+        let optional = match &self.optional.value {
+            OptionalHeader::X86(opt) => {
+                let mut leaves = optional_leaves!(opt);
+                let at = leaves.iter().position(|l| l.name == "base_of_code").map_or(leaves.len(), |i| i + 1);
+                leaves.insert(at, leaf("base_of_data", &opt.base_of_data, format!("{:#x}", opt.base_of_data.value), 4));
+                leaves
+            },
+            OptionalHeader::X64(opt) => optional_leaves!(opt),
+        };
+
+        TreeNode::new("Headers").with_children(vec![
+            TreeNode::new("DOS Header").at(self.dos.offset.into(), dos::HEADER_LENGTH).with_children(hex_leaves!(dos, e_magic, e_lfanew)),
+            TreeNode::new("File Header").at(self.file.offset.into(), file::HEADER_LENGTH).with_children({
+                let mut leaves = hex_leaves!(file, magic);
+                leaves.push(leaf("machine", &file.machine, format!("{:?}", file.machine.value), 2));
+                leaves.extend(hex_leaves!(file, sections));
+                leaves.push(leaf("timestamp", &file.timestamp, file.timestamp.value, 4));
+                leaves.extend(hex_leaves!(file, symbol_table_ptr, symbols, optional_header_size, charactristics));
+                leaves
+            }),
+            TreeNode::new("Optional Header")
+                .at(self.optional.offset.into(), file.optional_header_size.value.into())
+                .with_children(optional),
+        ])
+    }
+
+    fn data_dirs_tree(&self) -> TreeNode {
+        let dirs = self.data_dirs.value.iter()
+            .filter(|dir| dir.value.rva.value != 0)
+            .map(|dir| TreeNode::new(format!("{:?}", dir.value.member))
+                .at(dir.offset.into(), 8)
+                .with_children(hex_leaves!(dir.value, rva, size)))
+            .collect();
+        TreeNode::new("Data Directories").with_children(dirs)
+    }
+
+    fn sections_tree(&self) -> TreeNode {
+        let sections = self.sections.value.iter()
+            .map(|sec| {
+                let sh = &sec.value;
+                let leaves = hex_leaves!(sh, virtual_size, virtual_address, sizeof_raw_data, raw_data_ptr, charactristics);
+                TreeNode::new(sh.name_lossy()).at(sh.name.offset.into(), SECTION_HEADER_LENGTH).with_children(leaves)
+            })
+            .collect();
+        TreeNode::new("Sections").with_children(sections)
+    }
+
+    fn imports_tree(&self) -> TreeNode {
+        let modules = self.imports.value.iter()
+            .map(|id| {
+                let desc = &id.value;
+                let imports = desc.imports.iter()
+                    .map(|import| {
+                        let name = match (import.import_name(), import.ordinal()) {
+                            (Some(iname), _) => iname.name.value.clone(),
+                            (None, ordinal) => format!("#{}", ordinal.unwrap_or_default()),
+                        };
+                        let offset = self.rva_to_offset(import.iat_rva()).map(u64::from);
+                        let size = if matches!(import, ImportLookup::X64(_)) { 8 } else { 4 };
+                        TreeNode::new(name).with_value(format!("{:#x}", import.value())).at_some(offset, size)
+                    })
+                    .collect();
+                TreeNode::new(desc.name.clone().unwrap_or_default())
+                    .at(id.offset.into(), IMPORT_DESCRIPTOR_SIZE as u64)
+                    .with_children(imports)
+            })
+            .collect();
+        TreeNode::new("Imports").at(self.imports.offset.into(), self.dir_size(DirectoryType::Import))
+            .with_children(modules)
+    }
+
+    fn exports_tree(&self) -> TreeNode {
+        let exports = &self.exports.value;
+        let nodes = exports.exports.iter()
+            .map(|export| TreeNode::new(export.name.value.clone())
+                .with_value(format!("{:#x}", export.address.value))
+                .at(export.address.offset.into(), 4)
+                .with_children(vec![leaf("ordinal", &export.ordinal, export.ordinal.value, 2)]))
+            .collect();
+        TreeNode::new("Exports").with_value(&exports.name)
+            .at(self.exports.offset.into(), self.dir_size(DirectoryType::Export))
+            .with_children(nodes)
+    }
+
+    fn relocations_tree(&self) -> TreeNode {
+        let blocks = self.relocations.value.blocks.iter()
+            .map(|rb| TreeNode::new(format!("{:#x}", rb.value.va.value))
+                .with_value(format!("{} relocations", rb.value.relocs.len()))
+                .at(rb.offset.into(), rb.value.size.value.into()))
+            .collect();
+        TreeNode::new("Relocations")
+            .at(self.relocations.offset.into(), self.dir_size(DirectoryType::Relocation))
+            .with_children(blocks)
+    }
+
+    fn rsrc_tree(&self, dir: &ResourceDirectory, is_root: bool) -> Vec<TreeNode> {
+        dir.entries.iter()
+            .map(|entry| {
+                //Types are only known for the root entries.
+                let name = match entry.resource_type().filter(|_| is_root) {
+                    Some(typ) => format!("{typ:?}"),
+                    None => entry.key().to_string(),
+                };
+                let node = TreeNode::new(name).at(entry.name_offset.offset.into(), 8);
+                match &entry.data {
+                    ResourceNode::Dir(sub) => node.with_children(self.rsrc_tree(sub, false)),
+                    ResourceNode::Data(data) => {
+                        let offset = self.rva_to_offset(data.rva.value).map(u64::from);
+                        node.with_children(vec![
+                            TreeNode::new("Data").with_value(format!("code page {}", data.code_page.value))
+                                .at_some(offset, data.size.value.into()),
+                        ])
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::pe::{builder::PeBuilder, file::MachineType, import::ImportLookup, PeImage};
+
+    #[test]
+    fn tree_of_test_dll() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+        let tree = pe.to_tree();
+
+        let names: Vec<&str> = tree.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["Headers", "Data Directories", "Sections", "Imports", "Exports", "Relocations", "Resources"]);
+
+        let lfanew = tree.find(&["Headers", "DOS Header", "e_lfanew"]).unwrap();
+        assert_eq!(lfanew.value.as_deref(), Some(format!("{:#x}", pe.dos.value.e_lfanew.value).as_str()));
+        assert_eq!((lfanew.offset, lfanew.size), (Some(0x3c), Some(4)));
+        assert_eq!(tree.find(&["Headers", "File Header", "machine"]).unwrap().value.as_deref(), Some("AMD64"));
+
+        let text = tree.find(&["Sections", ".text"]).unwrap();
+        assert_eq!(text.offset, Some(pe.sections.value[0].value.name.offset.into()));
+        assert_eq!(text.size, Some(40));
+
+        let kernel32 = tree.find(&["Imports", "KERNEL32.dll"]).unwrap();
+        assert_eq!(kernel32.children.len(), pe.imports.value[1].value.imports.len());
+        assert_eq!(tree.find(&["Exports"]).unwrap().children.len(), 2);
+        assert!(tree.count() > 50);
+    }
+
+    #[test]
+    fn unmapped_import_has_no_offset() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let mut pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+        let ImportLookup::X64(import) = &mut pe.imports.value[1].value.imports[0] else { panic!("not PE32+") };
+        import.iat_rva = 0xffff_0000;
+
+        let kernel32 = pe.to_tree().find(&["Imports", "KERNEL32.dll"]).unwrap().clone();
+        assert_eq!((kernel32.children[0].offset, kernel32.children[0].size), (None, None));
+        assert!(kernel32.children[1].offset.is_some());
+    }
+
+    #[test]
+    fn base_of_data_of_pe32() {
+        let bytes = PeBuilder::new(MachineType::I386).build().unwrap();
+        let pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        let optional = pe.to_tree().find(&["Headers", "Optional Header"]).unwrap().clone();
+        let names: Vec<&str> = optional.children.iter().map(|c| c.name.as_str()).collect();
+        let at = names.iter().position(|&n| n == "base_of_code").unwrap();
+        assert_eq!(names[at + 1], "base_of_data");
+        assert_eq!(names[at + 2], "image_base");
+    }
+
+    #[cfg(feature="json")]
+    #[test]
+    fn tree_to_json() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&pe.to_tree().to_json().unwrap()).unwrap();
+        assert_eq!(json["name"], "PE Image");
+        assert_eq!(json["children"][0]["children"][0]["name"], "DOS Header");
+        assert!(json["children"][0].get("value").is_none());
+    }
+}
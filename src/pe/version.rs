@@ -0,0 +1,308 @@
+//! Version information resource (`VS_VERSIONINFO`).
+//!
+//! The resource is a tree of blocks, each with a length, a UTF-16 key, an optional value and
+//! children. Only the parts needed to describe a file are kept: the fixed file info, string
+//! tables and translations.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::pe::Result;
+
+use super::PeError;
+
+/// Signature of `VS_FIXEDFILEINFO`.
+pub const FIXED_INFO_SIGNATURE: u32 = 0xFEEF04BD;
+/// Length of `VS_FIXEDFILEINFO`.
+pub const FIXED_INFO_LENGTH: usize = 52;
+
+/// Blocks can't nest deeper than VS_VERSIONINFO -> StringFileInfo -> StringTable -> String.
+const MAX_DEPTH: u32 = 4;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct FixedFileInfo {
+    pub file_version_ms: u32,
+    pub file_version_ls: u32,
+    pub product_version_ms: u32,
+    pub product_version_ls: u32,
+    pub file_flags_mask: u32,
+    pub file_flags: u32,
+    pub file_os: u32,
+    pub file_type: u32,
+    pub file_subtype: u32,
+}
+
+fn format_version(ms: u32, ls: u32) -> String {
+    format!("{}.{}.{}.{}", ms >> 16, ms & 0xFFFF, ls >> 16, ls & 0xFFFF)
+}
+
+impl FixedFileInfo {
+    pub fn file_version(&self) -> String {
+        format_version(self.file_version_ms, self.file_version_ls)
+    }
+
+    pub fn product_version(&self) -> String {
+        format_version(self.product_version_ms, self.product_version_ls)
+    }
+
+    fn parse_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < FIXED_INFO_LENGTH {
+            return None;
+        }
+        let dword = |i: usize| u32::from_le_bytes([bytes[i * 4], bytes[i * 4 + 1], bytes[i * 4 + 2], bytes[i * 4 + 3]]);
+        if dword(0) != FIXED_INFO_SIGNATURE {
+            return None;
+        }
+
+        Some(Self {
+            file_version_ms: dword(2),
+            file_version_ls: dword(3),
+            product_version_ms: dword(4),
+            product_version_ls: dword(5),
+            file_flags_mask: dword(6),
+            file_flags: dword(7),
+            file_os: dword(8),
+            file_type: dword(9),
+            file_subtype: dword(10),
+        })
+    }
+}
+
+/// A `StringTable` block; `lang` is its key, language and code page as 8 hex digits.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct StringTable {
+    pub lang: String,
+    pub strings: Vec<(String, String)>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct VersionInfo {
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub fixed: Option<FixedFileInfo>,
+    pub string_tables: Vec<StringTable>,
+    /// (language, code page) pairs from `VarFileInfo\Translation`.
+    pub translations: Vec<(u16, u16)>,
+}
+
+/// A generic block of the version resource.
+struct Block {
+    key: String,
+    value: Vec<u8>,
+    is_text: bool,
+    children: Vec<Block>,
+}
+
+#[inline]
+fn align4(pos: usize) -> usize {
+    (pos + 3) & !3
+}
+
+fn invalid(pos: u64, reason: String) -> PeError {
+    PeError::InvalidHeader { name: "VersionInfo".into(), offset: pos, reason }
+}
+
+/// Decode UTF-16 (LE) `bytes`, dropping everything from the first NUL.
+fn utf16_str(bytes: &[u8]) -> String {
+    let words: Vec<u16> = bytes.chunks_exact(2)
+        .map(|w| u16::from_le_bytes([w[0], w[1]]))
+        .take_while(|&w| w != 0)
+        .collect();
+    String::from_utf16_lossy(&words)
+}
+
+/// Parse the block at `start`; `pos` is the offset of `bytes` in the image, used in errors.
+fn parse_block(bytes: &[u8], start: usize, pos: u64, depth: u32) -> Result<Block> {
+    let at = pos + start as u64;
+    if depth > MAX_DEPTH {
+        return Err(invalid(at, format!("blocks nested deeper than {MAX_DEPTH}")));
+    }
+    if start + 6 > bytes.len() {
+        return Err(invalid(at, "block header is truncated".into()));
+    }
+
+    let word = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]) as usize;
+    let length = word(start);
+    let value_length = word(start + 2);
+    let is_text = word(start + 4) == 1;
+    let end = start + length;
+    if length < 6 || end > bytes.len() {
+        return Err(invalid(at, format!("block length {length:#x} is out of range")));
+    }
+
+    let key_start = start + 6;
+    let key_len = bytes[key_start..end].chunks_exact(2).position(|w| w == [0, 0])
+        .ok_or_else(|| invalid(at, "block key is not terminated".into()))?;
+    let key = utf16_str(&bytes[key_start..key_start + key_len * 2]);
+
+    //Text values are counted in words.
+    let value_start = align4(key_start + key_len * 2 + 2).min(end);
+    let value_size = if is_text { value_length * 2 } else { value_length };
+    let value_end = (value_start + value_size).min(end);
+    let value = bytes[value_start..value_end].to_vec();
+
+    let mut children = Vec::new();
+    let mut child = align4(value_end);
+    while child + 6 <= end {
+        let block = parse_block(&bytes[..end], child, pos, depth + 1)?;
+        let child_len = word(child);
+        children.push(block);
+        child = align4(child + child_len);
+    }
+
+    Ok(Block { key, value, is_text, children })
+}
+
+impl VersionInfo {
+    /// Parse a `VS_VERSIONINFO` resource from `bytes`, read from offset `pos`.
+    pub fn parse_bytes(bytes: &[u8], pos: u64) -> Result<Self> {
+        let root = parse_block(bytes, 0, pos, 1)?;
+        if root.key != "VS_VERSION_INFO" {
+            return Err(invalid(pos, format!("unexpected root key '{}'", root.key)));
+        }
+
+        let mut info = Self { fixed: FixedFileInfo::parse_bytes(&root.value), ..Default::default() };
+
+        for child in &root.children {
+            match child.key.as_str() {
+                "StringFileInfo" => for table in &child.children {
+                    let strings = table.children.iter()
+                        .map(|s| {
+                            let value = if s.is_text { utf16_str(&s.value) } else { String::from_utf8_lossy(&s.value).into() };
+                            (s.key.clone(), value)
+                        })
+                        .collect();
+                    info.string_tables.push(StringTable { lang: table.key.clone(), strings });
+                },
+
+                "VarFileInfo" => for var in child.children.iter().filter(|v| v.key == "Translation") {
+                    info.translations.extend(var.value.chunks_exact(4)
+                        .map(|t| (u16::from_le_bytes([t[0], t[1]]), u16::from_le_bytes([t[2], t[3]]))));
+                },
+
+                _ => {}
+            }
+        }
+
+        Ok(info)
+    }
+
+    /// Value of string `key` from the first string table which has it.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.string_tables.iter()
+            .flat_map(|t| t.strings.iter())
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// `CompanyName|ProductName|InternalName`, each trimmed, lowercased and with inner
+    /// whitespace collapsed, to group files by the product they claim to be part of.
+    /// `None` if all three are missing or empty.
+    pub fn product_key(&self) -> Option<String> {
+        let parts: Vec<String> = ["CompanyName", "ProductName", "InternalName"].iter()
+            .map(|key| self.get(key).unwrap_or_default().split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase())
+            .collect();
+
+        if parts.iter().all(|p| p.is_empty()) { None } else { Some(parts.join("|")) }
+    }
+}
+
+impl Display for VersionInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "VersionInfo: {{")?;
+        if let Some(fixed) = &self.fixed {
+            writeln!(f, "  FileVersion: {}, ProductVersion: {}", fixed.file_version(), fixed.product_version())?;
+        }
+        for table in &self.string_tables {
+            writeln!(f, "  StringTable {}: [", table.lang)?;
+            for (key, value) in &table.strings {
+                writeln!(f, "    {key}: {value}")?;
+            }
+            writeln!(f, "  ]")?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VersionInfo, FIXED_INFO_SIGNATURE};
+
+    /// Encode a block with a text (`Some`) or binary value.
+    fn block(key: &str, text: Option<&str>, binary: &[u8], children: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = vec![0u8; 6];
+        out.extend(key.encode_utf16().chain([0]).flat_map(|w| w.to_le_bytes()));
+        out.resize(out.len().next_multiple_of(4), 0);
+
+        let value_length = match text {
+            Some(text) => {
+                let words: Vec<u16> = text.encode_utf16().chain([0]).collect();
+                out.extend(words.iter().flat_map(|w| w.to_le_bytes()));
+                words.len()
+            },
+            None => {
+                out.extend(binary);
+                binary.len()
+            },
+        };
+
+        for child in children {
+            out.resize(out.len().next_multiple_of(4), 0);
+            out.extend(child);
+        }
+
+        let length = out.len() as u16;
+        out[0..2].copy_from_slice(&length.to_le_bytes());
+        out[2..4].copy_from_slice(&(value_length as u16).to_le_bytes());
+        out[4..6].copy_from_slice(&(text.is_some() as u16).to_le_bytes());
+        out
+    }
+
+    /// A `VS_VERSIONINFO` with version 1.2.3.4 and the given strings.
+    fn version_resource(strings: &[(&str, &str)]) -> Vec<u8> {
+        let mut fixed = vec![0u8; 52];
+        fixed[0..4].copy_from_slice(&FIXED_INFO_SIGNATURE.to_le_bytes());
+        fixed[8..12].copy_from_slice(&0x0001_0002u32.to_le_bytes());
+        fixed[12..16].copy_from_slice(&0x0003_0004u32.to_le_bytes());
+
+        let strings: Vec<Vec<u8>> = strings.iter().map(|(k, v)| block(k, Some(v), &[], &[])).collect();
+        let table = block("040904b0", None, &[], &strings);
+        let sfi = block("StringFileInfo", None, &[], &[table]);
+        let translation = block("Translation", None, &[0x09, 0x04, 0xb0, 0x04], &[]);
+        let vfi = block("VarFileInfo", None, &[], &[translation]);
+
+        block("VS_VERSION_INFO", None, &fixed, &[sfi, vfi])
+    }
+
+    #[test]
+    fn parse_version_info() {
+        let bytes = version_resource(&[("CompanyName", "Contoso Ltd."), ("ProductName", "Widget"), ("FileDescription", "A widget")]);
+        let info = VersionInfo::parse_bytes(&bytes, 0).unwrap();
+
+        assert_eq!(info.fixed.unwrap().file_version(), "1.2.3.4");
+        assert_eq!(info.string_tables.len(), 1);
+        assert_eq!(info.string_tables[0].lang, "040904b0");
+        assert_eq!(info.get("ProductName"), Some("Widget"));
+        assert_eq!(info.get("InternalName"), None);
+        assert_eq!(info.translations, vec![(0x409, 0x4b0)]);
+    }
+
+    #[test]
+    fn version_product_key() {
+        let bytes = version_resource(&[("CompanyName", "  Contoso   Ltd. "), ("ProductName", "WIDGET"), ("InternalName", "widget.dll")]);
+        let info = VersionInfo::parse_bytes(&bytes, 0).unwrap();
+        assert_eq!(info.product_key().unwrap(), "contoso ltd.|widget|widget.dll");
+
+        let bytes = version_resource(&[("FileDescription", "nothing to group by")]);
+        assert_eq!(VersionInfo::parse_bytes(&bytes, 0).unwrap().product_key(), None);
+    }
+
+    #[test]
+    fn version_info_malformed() {
+        let mut bytes = version_resource(&[("CompanyName", "Contoso")]);
+        bytes[0..2].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        assert!(VersionInfo::parse_bytes(&bytes, 0x100).is_err());
+
+        assert!(VersionInfo::parse_bytes(&[0u8; 4], 0).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Parse events for consumers that index many files and don't need a whole `PeImage`.
+//!
+//! Headers and section headers are parsed first; imports and exports are then read one at a
+//! time and dropped after their callback, so memory does not grow with the tables.
+
+use std::{fs::File, io::{BufReader, Cursor}, ops::ControlFlow};
+
+use crate::types::{BufReadExt, HeaderField};
+
+use super::{
+    dos::DosHeader, export::Export, file::FileHeader, import::{ImportDescriptor, ImportLookup},
+    optional::{DataDirectory, OptionalHeader}, section::SectionHeader, PeError, PeImage, Result,
+};
+
+/// Callbacks for parts of a PE image, in file order of the headers, then imports and exports.
+/// Every callback defaults to doing nothing; return `ControlFlow::Break` to stop parsing.
+#[allow(unused_variables)]
+pub trait PeVisitor {
+    fn on_dos(&mut self, dos: &HeaderField<DosHeader>) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    fn on_file_header(&mut self, file: &HeaderField<FileHeader>) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    fn on_optional_header(&mut self, optional: &HeaderField<OptionalHeader>) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// Each entry of the data directories, present or not.
+    fn on_data_directory(&mut self, dir: &HeaderField<DataDirectory>) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    fn on_section(&mut self, section: &HeaderField<SectionHeader>) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// An import descriptor, before `on_import` is called for each of its imports.
+    fn on_import_module(&mut self, descriptor: &HeaderField<ImportDescriptor>) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    fn on_import(&mut self, descriptor: &HeaderField<ImportDescriptor>, import: &ImportLookup) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    fn on_export(&mut self, export: &Export) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// A directory failed to parse; its remaining events are skipped. Errors of headers are
+    /// returned by `visit` instead.
+    fn on_error(&mut self, err: &PeError) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+}
+
+/// Stop `visit` if a callback breaks.
+macro_rules! emit {
+    ($call:expr) => {
+        if $call.is_break() {
+            return Ok(());
+        }
+    };
+}
+
+/// Parse the image from `reader` at `pos`, calling `visitor` for each part. Errors of fixed
+/// headers and section headers are returned.
+pub fn visit_readable(reader: Box<dyn BufReadExt>, pos: u64, visitor: &mut dyn PeVisitor) -> Result<()> {
+    let mut pe = PeImage::new(reader);
+    pe.parse_headers_only(pos)?;
+    pe.visit(visitor)
+}
+
+/// `visit_readable` for a file.
+pub fn visit_file(file: File, pos: u64, visitor: &mut dyn PeVisitor) -> Result<()> {
+    visit_readable(Box::new(BufReader::new(file)), pos, visitor)
+}
+
+/// `visit_readable` for bytes of the whole image.
+pub fn visit_bytes(bytes: Vec<u8>, pos: u64, visitor: &mut dyn PeVisitor) -> Result<()> {
+    visit_readable(Box::new(Cursor::new(bytes)), pos, visitor)
+}
+
+impl PeImage {
+    /// Call `visitor` for headers and sections already parsed, then for imports and exports as
+    /// they are read. Parsed directories are not used or changed.
+    pub fn visit(&mut self, visitor: &mut dyn PeVisitor) -> Result<()> {
+        emit!(visitor.on_dos(&self.dos));
+        emit!(visitor.on_file_header(&self.file));
+        emit!(visitor.on_optional_header(&self.optional));
+        for dir in &self.data_dirs.value {
+            emit!(visitor.on_data_directory(dir));
+        }
+        for section in &self.sections.value {
+            emit!(visitor.on_section(section));
+        }
+
+        for descriptor in self.imports_stream() {
+            match descriptor {
+                Ok(descriptor) => {
+                    emit!(visitor.on_import_module(&descriptor));
+                    for import in &descriptor.value.imports {
+                        emit!(visitor.on_import(&descriptor, import));
+                    }
+                },
+                Err(err) => emit!(visitor.on_error(&err)),
+            }
+        }
+
+        for export in self.exports_stream() {
+            match export {
+                Ok(export) => emit!(visitor.on_export(&export)),
+                Err(err) => emit!(visitor.on_error(&err)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, ops::ControlFlow};
+
+    use crate::{pe::{export::Export, import::{ImportDescriptor, ImportLookup}, section::SectionHeader, PeImage}, types::HeaderField};
+
+    use super::{visit_bytes, PeVisitor};
+
+    #[derive(Default)]
+    struct Counter {
+        sections: Vec<String>,
+        modules: usize,
+        imports: usize,
+        exports: usize,
+        max_exports: Option<usize>,
+    }
+
+    impl PeVisitor for Counter {
+        fn on_section(&mut self, section: &HeaderField<SectionHeader>) -> ControlFlow<()> {
+            self.sections.push(section.value.name_lossy());
+            ControlFlow::Continue(())
+        }
+
+        fn on_import_module(&mut self, _: &HeaderField<ImportDescriptor>) -> ControlFlow<()> {
+            self.modules += 1;
+            ControlFlow::Continue(())
+        }
+
+        fn on_import(&mut self, _: &HeaderField<ImportDescriptor>, _: &ImportLookup) -> ControlFlow<()> {
+            self.imports += 1;
+            ControlFlow::Continue(())
+        }
+
+        fn on_export(&mut self, _: &Export) -> ControlFlow<()> {
+            self.exports += 1;
+            match self.max_exports {
+                Some(max) if self.exports >= max => ControlFlow::Break(()),
+                _ => ControlFlow::Continue(()),
+            }
+        }
+    }
+
+    #[test]
+    fn events_match_parsed_image() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+        let pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+
+        let mut counter = Counter::default();
+        visit_bytes(bytes.clone(), 0, &mut counter).unwrap();
+        let names: Vec<String> = pe.sections.value.iter().map(|s| s.value.name_str().unwrap()).collect();
+        assert_eq!(counter.sections, names);
+        assert_eq!(counter.modules, pe.imports.value.len());
+        assert_eq!(counter.imports, pe.imports.value.iter().map(|i| i.value.imports.len()).sum::<usize>());
+        assert_eq!(counter.exports, pe.exports.value.exports.len());
+
+        //Stopping early skips the rest.
+        let mut counter = Counter { max_exports: Some(2), ..Default::default() };
+        visit_bytes(bytes, 0, &mut counter).unwrap();
+        assert_eq!(counter.exports, 2);
+    }
+}
@@ -0,0 +1,211 @@
+//! Byte ranges of high-signal structures, formatted as YARA strings.
+//!
+//! Names of imported modules and functions, export names, version strings and the rich header
+//! are taken as stored in the file, so the patterns match the image they came from.
+
+use std::fmt::{Display, Write};
+
+use serde::Serialize;
+
+use super::{PeImage, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum YaraStringKind {
+    ImportModule,
+    ImportName,
+    ExportName,
+    VersionString,
+    RichHeader,
+}
+
+impl YaraStringKind {
+    fn prefix(&self) -> &'static str {
+        match self {
+            Self::ImportModule => "module",
+            Self::ImportName => "import",
+            Self::ExportName => "export",
+            Self::VersionString => "version",
+            Self::RichHeader => "rich",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct YaraString {
+    pub kind: YaraStringKind,
+    /// What the bytes are, e.g. `KERNEL32.dll!CreateFileW` or `CompanyName`.
+    pub label: String,
+    /// File offset of the first byte.
+    pub offset: u64,
+    /// Bytes as stored, without the terminating NUL.
+    pub bytes: Vec<u8>,
+    /// Whether `bytes` is UTF-16LE text.
+    pub wide: bool,
+}
+
+impl YaraString {
+    pub(crate) fn text(kind: YaraStringKind, label: String, offset: u64, text: &str) -> Self {
+        Self { kind, label, offset, bytes: text.as_bytes().to_vec(), wide: false }
+    }
+
+    /// Offset past the last byte.
+    #[inline]
+    pub fn end(&self) -> u64 {
+        self.offset + self.bytes.len() as u64
+    }
+
+    /// Pattern of the string: text with `ascii` or `wide` if printable, hex otherwise.
+    pub fn pattern(&self) -> String {
+        let text = if self.wide {
+            let words: Vec<u16> = self.bytes.chunks_exact(2).map(|w| u16::from_le_bytes([w[0], w[1]])).collect();
+            String::from_utf16(&words).ok()
+        } else {
+            String::from_utf8(self.bytes.clone()).ok()
+        };
+
+        match text {
+            Some(text) if self.kind != YaraStringKind::RichHeader && text.chars().all(|c| c == ' ' || c.is_ascii_graphic()) => {
+                let escaped = text.replace('\\', "\\\\").replace('"', "\\\"");
+                format!("\"{escaped}\" {}", if self.wide { "wide" } else { "ascii" })
+            },
+            _ => {
+                let hex: Vec<String> = self.bytes.iter().map(|b| format!("{b:02X}")).collect();
+                format!("{{ {} }}", hex.join(" "))
+            },
+        }
+    }
+}
+
+impl Display for YaraString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} // {:?} {} @ {:#x}..{:#x}", self.pattern(), self.kind, self.label, self.offset, self.end())
+    }
+}
+
+/// Identifier of a rule: characters other than alphanumerics and `_` are replaced, and a
+/// leading digit is prefixed.
+pub fn rule_name(name: &str) -> String {
+    let mut rule: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    if !rule.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        rule.insert(0, '_');
+    }
+    rule
+}
+
+/// Format `strings` as a rule matching any of them in a PE file.
+pub fn format_rule(name: &str, strings: &[YaraString]) -> String {
+    let mut out = String::new();
+    let mut counts = [0usize; 5];
+
+    //Writing to a String doesn't fail.
+    let _ = writeln!(out, "rule {} {{", rule_name(name));
+    if !strings.is_empty() {
+        let _ = writeln!(out, "  strings:");
+        for s in strings {
+            let count = &mut counts[s.kind as usize];
+            let _ = writeln!(out, "    ${}_{} = {s}", s.kind.prefix(), *count);
+            *count += 1;
+        }
+    }
+    let _ = writeln!(out, "  condition:");
+    let _ = write!(out, "    uint16(0) == 0x5A4D");
+    if !strings.is_empty() {
+        let _ = write!(out, " and any of them");
+    }
+    let _ = write!(out, "\n}}");
+    out
+}
+
+/// UTF-16LE bytes of `text`.
+fn wide(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+/// Collect strings from parsed imports, delay imports and exports, the version resource and
+/// the rich header. Strings with the same bytes are kept once.
+pub(crate) fn collect(pe: &mut PeImage) -> Result<Vec<YaraString>> {
+    let mut strings = Vec::new();
+
+    for desc in pe.imports.value.iter().map(|d| &d.value) {
+        let module = desc.name.clone().unwrap_or_default();
+        if let Some(offset) = pe.rva_to_offset(desc.name_rva.value) {
+            strings.push(YaraString::text(YaraStringKind::ImportModule, module.clone(), offset.into(), &module));
+        }
+        strings.extend(desc.imports.iter().filter_map(|il| il.import_name()).map(|iname| {
+            let name = &iname.name;
+            YaraString::text(YaraStringKind::ImportName, format!("{module}!{}", name.value), name.offset.into(), &name.value)
+        }));
+    }
+
+    for desc in pe.delay_imports.value.iter().map(|d| &d.value) {
+        let module = desc.name.clone().unwrap_or_default();
+        strings.extend(desc.imports.iter().filter_map(|il| il.import_name()).map(|iname| {
+            let name = &iname.name;
+            YaraString::text(YaraStringKind::ImportName, format!("{module}!{}", name.value), name.offset.into(), &name.value)
+        }));
+    }
+
+    strings.extend(pe.exports.value.exports.iter().map(|export| {
+        let name = &export.name;
+        YaraString::text(YaraStringKind::ExportName, name.value.clone(), name.offset.into(), &name.value)
+    }));
+
+    if let Some((offset, bytes)) = pe.version_resource()? {
+        let info = super::version::VersionInfo::parse_bytes(&bytes, offset)?;
+        for (key, value) in info.string_tables.iter().flat_map(|t| t.strings.iter()) {
+            let value = wide(value);
+            if value.is_empty() {
+                continue;
+            }
+            //Values follow their keys; search from the key to skip equal text before it.
+            let from = find(&bytes, &wide(key), 0).unwrap_or_default();
+            if let Some(at) = find(&bytes, &value, from) {
+                strings.push(YaraString { kind: YaraStringKind::VersionString, label: key.clone(), offset: offset + at as u64, bytes: value, wide: true });
+            }
+        }
+    }
+
+    if let Some(rich) = pe.rich_header()? {
+        strings.push(YaraString { kind: YaraStringKind::RichHeader, label: format!("{} entries", rich.entries.len()), offset: rich.offset, bytes: rich.raw, wide: false });
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    strings.retain(|s| seen.insert(s.bytes.clone()));
+    Ok(strings)
+}
+
+/// Index of `needle` in `haystack`, starting at `from`.
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack.get(from..)?.windows(needle.len()).position(|w| w == needle).map(|i| from + i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_rule, rule_name, YaraString, YaraStringKind};
+
+    #[test]
+    fn format_patterns() {
+        let strings = vec![
+            YaraString::text(YaraStringKind::ImportName, "KERNEL32.dll!CreateFileW".into(), 0x2a4, "CreateFileW"),
+            YaraString { kind: YaraStringKind::VersionString, label: "CompanyName".into(), offset: 0x1000, bytes: super::wide("Say \"hi\""), wide: true },
+            YaraString { kind: YaraStringKind::RichHeader, label: "0 entries".into(), offset: 0x80, bytes: vec![0x44, 0x61, 0x6e, 0x53], wide: false },
+            YaraString::text(YaraStringKind::ImportName, "KERNEL32.dll!ExitProcess".into(), 0x2b2, "ExitProcess"),
+        ];
+
+        let rule = format_rule("7z.dll", &strings);
+        assert_eq!(rule, [
+            "rule _7z_dll {",
+            "  strings:",
+            "    $import_0 = \"CreateFileW\" ascii // ImportName KERNEL32.dll!CreateFileW @ 0x2a4..0x2af",
+            "    $version_0 = \"Say \\\"hi\\\"\" wide // VersionString CompanyName @ 0x1000..0x1010",
+            "    $rich_0 = { 44 61 6E 53 } // RichHeader 0 entries @ 0x80..0x84",
+            "    $import_1 = \"ExitProcess\" ascii // ImportName KERNEL32.dll!ExitProcess @ 0x2b2..0x2bd",
+            "  condition:",
+            "    uint16(0) == 0x5A4D and any of them",
+            "}",
+        ].join("\n"));
+
+        assert_eq!(rule_name("test"), "test");
+        assert_eq!(format_rule("x", &[]), "rule x {\n  condition:\n    uint16(0) == 0x5A4D\n}");
+    }
+}
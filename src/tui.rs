@@ -0,0 +1,292 @@
+//! Interactive terminal viewer of a parsed `PeImage`. Needs the `tui` feature; run by
+//! `rustbin tui <file>`.
+//!
+//! Panes show the text report of headers, sections, imports and exports, and resources, and a
+//! hex view of the input. The hex view reads only the rows on screen, so large files open fast.
+
+use std::io;
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::{Style, Stylize},
+    widgets::{Block, List, ListState, Paragraph, Tabs},
+    DefaultTerminal, Frame,
+};
+
+use crate::pe::{report::ReportSection, PeImage};
+
+/// Bytes in a row of the hex view.
+pub const HEX_ROW: u64 = 16;
+
+const HELP: &str = " Tab/1-5: pane  Up/Down PgUp/PgDn Home/End: scroll  Enter: data of section  q: quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pane {
+    Headers,
+    Sections,
+    Imports,
+    Resources,
+    Hex,
+}
+
+impl Pane {
+    pub const ALL: [Pane; 5] = [Self::Headers, Self::Sections, Self::Imports, Self::Resources, Self::Hex];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Headers => "Headers",
+            Self::Sections => "Sections",
+            Self::Imports => "Imports/Exports",
+            Self::Resources => "Resources",
+            Self::Hex => "Hex",
+        }
+    }
+}
+
+/// Lines of a pane with the file offset each leads to in the hex view, if any.
+#[derive(Debug, Default)]
+struct TextPane {
+    lines: Vec<String>,
+    targets: Vec<Option<u64>>,
+    state: ListState,
+}
+
+impl TextPane {
+    fn new(lines: Vec<String>, targets: Vec<Option<u64>>) -> Self {
+        let lines = match lines.is_empty() {
+            true => vec!["(none)".to_string()],
+            false => lines,
+        };
+        Self { lines, targets, state: ListState::default().with_selected(Some(0)) }
+    }
+
+    fn of_report(pe: &PeImage, parts: &[ReportSection]) -> Self {
+        let mut text = String::new();
+        //Writing to a `String` does not fail.
+        let _ = pe.format_report(&mut text, parts);
+        Self::new(text.lines().map(str::to_string).collect(), Vec::new())
+    }
+
+    fn target(&self) -> Option<u64> {
+        self.state.selected().and_then(|i| self.targets.get(i).copied().flatten())
+    }
+}
+
+/// State of the viewer; `run` draws it and feeds it keys.
+pub struct Viewer<'a> {
+    pe: &'a mut PeImage,
+    title: String,
+    pane: Pane,
+    panes: [TextPane; 4],
+    /// File offset of the first row of the hex view.
+    hex_offset: u64,
+    input_size: u64,
+    /// Rows in the body at the last draw, to page by.
+    page: u16,
+    quit: bool,
+}
+
+impl<'a> Viewer<'a> {
+    pub fn new(pe: &'a mut PeImage, title: &str) -> Self {
+        let (lines, targets) = pe.sections.value.iter()
+            .map(|s| (s.value.to_string(), Some(s.value.raw_data_ptr.value.into())))
+            .unzip();
+        let panes = [
+            TextPane::of_report(pe, &[ReportSection::Headers, ReportSection::DataDirectories]),
+            TextPane::new(lines, targets),
+            TextPane::of_report(pe, &[ReportSection::Imports, ReportSection::Exports]),
+            TextPane::of_report(pe, &[ReportSection::Resources]),
+        ];
+        let input_size = pe.input_size().unwrap_or_default();
+        Self { pe, title: title.to_string(), pane: Pane::Headers, panes, hex_offset: 0, input_size, page: 16, quit: false }
+    }
+
+    pub fn pane(&self) -> Pane {
+        self.pane
+    }
+
+    pub fn hex_offset(&self) -> u64 {
+        self.hex_offset
+    }
+
+    /// Whether `q` or `Esc` was pressed.
+    pub fn quit(&self) -> bool {
+        self.quit
+    }
+
+    fn text_pane(&mut self) -> Option<&mut TextPane> {
+        let index = Pane::ALL.iter().position(|p| *p == self.pane)?;
+        self.panes.get_mut(index)
+    }
+
+    fn switch(&mut self, by: isize) {
+        let index = Pane::ALL.iter().position(|p| *p == self.pane).unwrap_or_default() as isize;
+        self.pane = Pane::ALL[(index + by).rem_euclid(Pane::ALL.len() as isize) as usize];
+    }
+
+    /// Move the hex view to the row of `offset`, up to the last row.
+    fn seek(&mut self, offset: i128) {
+        let last_row = self.input_size.saturating_sub(1) / HEX_ROW * HEX_ROW;
+        self.hex_offset = (offset.clamp(0, last_row as i128) as u64) / HEX_ROW * HEX_ROW;
+    }
+
+    /// Act on a pressed key.
+    pub fn handle_key(&mut self, key: KeyCode) {
+        let page = self.page.max(1);
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Tab | KeyCode::Right => self.switch(1),
+            KeyCode::BackTab | KeyCode::Left => self.switch(-1),
+            KeyCode::Char(c @ '1'..='5') => self.pane = Pane::ALL[c as usize - '1' as usize],
+            KeyCode::Enter => {
+                if let Some(offset) = self.text_pane().and_then(|p| p.target()) {
+                    self.pane = Pane::Hex;
+                    self.seek(offset.into());
+                }
+            },
+            _ if self.pane == Pane::Hex => {
+                let row = HEX_ROW as i128;
+                let offset = self.hex_offset as i128;
+                match key {
+                    KeyCode::Up => self.seek(offset - row),
+                    KeyCode::Down => self.seek(offset + row),
+                    KeyCode::PageUp => self.seek(offset - page as i128 * row),
+                    KeyCode::PageDown => self.seek(offset + page as i128 * row),
+                    KeyCode::Home => self.seek(0),
+                    KeyCode::End => self.seek(self.input_size as i128 - page as i128 * row),
+                    _ => {},
+                }
+            },
+            _ => if let Some(pane) = self.text_pane() {
+                let state = &mut pane.state;
+                match key {
+                    KeyCode::Up => state.select_previous(),
+                    KeyCode::Down => state.select_next(),
+                    KeyCode::PageUp => state.scroll_up_by(page),
+                    KeyCode::PageDown => state.scroll_down_by(page),
+                    KeyCode::Home => state.select_first(),
+                    KeyCode::End => state.select_last(),
+                    _ => {},
+                }
+            },
+        }
+    }
+
+    /// Rows of the hex view from `hex_offset`, as many as fit in `rows`.
+    fn hex_rows(&mut self, rows: u16) -> Vec<String> {
+        let start = self.hex_offset.min(self.input_size);
+        let size = (rows as u64 * HEX_ROW).min(self.input_size - start);
+        match self.pe.read_bytes_at_offset(start, size as usize) {
+            Ok(bytes) => hex_lines(&bytes, start),
+            Err(err) => vec![format!("failed to read {size:#x} bytes at {start:#x}: {}", err.chain())],
+        }
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        let [tabs, body, help] = Layout::vertical([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)])
+            .areas(frame.area());
+        self.page = body.height.saturating_sub(2);
+
+        let selected = Pane::ALL.iter().position(|p| *p == self.pane);
+        let titles = Pane::ALL.iter().enumerate().map(|(i, p)| format!("{} {}", i + 1, p.title()));
+        frame.render_widget(Tabs::new(titles).select(selected).highlight_style(Style::new().reversed()), tabs);
+        frame.render_widget(Paragraph::new(HELP).dim(), help);
+
+        let block = Block::bordered().title(format!(" {} ", self.title));
+        match self.text_pane() {
+            Some(pane) => {
+                let list = List::new(pane.lines.iter().map(String::as_str))
+                    .block(block)
+                    .highlight_style(Style::new().reversed());
+                frame.render_stateful_widget(list, body, &mut pane.state);
+            },
+            None => self.draw_hex(frame, body, block),
+        }
+    }
+
+    fn draw_hex(&mut self, frame: &mut Frame, area: Rect, block: Block) {
+        let rows = self.hex_rows(area.height.saturating_sub(2));
+        let block = block.title_bottom(format!(" {:#x} of {:#x} ", self.hex_offset, self.input_size));
+        frame.render_widget(Paragraph::new(rows.join("\n")).block(block), area);
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.handle_key(key.code);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lines of a hex dump of `bytes` read at `offset`: offset, hex bytes and printable ASCII.
+pub fn hex_lines(bytes: &[u8], offset: u64) -> Vec<String> {
+    bytes.chunks(HEX_ROW as usize).enumerate()
+        .map(|(i, row)| {
+            let hex: Vec<String> = row.iter().map(|b| format!("{b:02x}")).collect();
+            let ascii: String = row.iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            format!("{:08x}  {:<47}  {ascii}", offset + (i as u64) * HEX_ROW, hex.join(" "))
+        })
+        .collect()
+}
+
+/// Show `pe` in the terminal until `q` is pressed; `title` names it, e.g. by its path.
+pub fn run(pe: &mut PeImage, title: &str) -> io::Result<()> {
+    let mut viewer = Viewer::new(pe, title);
+    let mut terminal = ratatui::init();
+    let result = viewer.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{backend::TestBackend, crossterm::event::KeyCode, Terminal};
+
+    use crate::pe::{builder::PeBuilder, file::MachineType, PeImage};
+
+    use super::{hex_lines, Pane, Viewer};
+
+    #[test]
+    fn hex_dump_rows() {
+        let bytes: Vec<u8> = (0x40..0x54).collect();
+        assert_eq!(hex_lines(&bytes, 0x100), vec![
+            "00000100  40 41 42 43 44 45 46 47 48 49 4a 4b 4c 4d 4e 4f  @ABCDEFGHIJKLMNO",
+            "00000110  50 51 52 53                                      PQRS",
+        ]);
+    }
+
+    #[test]
+    fn section_opens_in_hex_view() {
+        let bytes = PeBuilder::new(MachineType::AMD64).with_code(vec![0xC3; 0x10], 0).build().unwrap();
+        let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+        let text = pe.sections.value[0].value.raw_data_ptr.value;
+        let mut viewer = Viewer::new(&mut pe, "test");
+
+        viewer.handle_key(KeyCode::Tab);
+        assert_eq!(viewer.pane(), Pane::Sections);
+        viewer.handle_key(KeyCode::Enter);
+        assert_eq!(viewer.pane(), Pane::Hex);
+        assert_eq!(viewer.hex_offset(), text as u64);
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 12)).unwrap();
+        terminal.draw(|frame| viewer.draw(frame)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains(&format!("{text:08x}  c3 c3")));
+
+        viewer.handle_key(KeyCode::Home);
+        assert_eq!(viewer.hex_offset(), 0);
+        viewer.handle_key(KeyCode::Char('1'));
+        assert_eq!(viewer.pane(), Pane::Headers);
+        viewer.handle_key(KeyCode::Char('q'));
+        assert!(viewer.quit());
+    }
+}
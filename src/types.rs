@@ -9,11 +9,14 @@ use serde::Serialize;
 
 use crate::pe::PeError;
 
+pub mod addr;
+pub use addr::{FileOffset, RawFileOffset, RawRva, RawVa, Rva, Va};
+
 #[derive(Debug, Default, PartialEq, Clone, Copy, Serialize)]
 pub struct HeaderField<T> {
     pub value: T,
-    pub offset: u64,
-    pub rva: u64,
+    pub offset: FileOffset,
+    pub rva: Rva,
 }
 
 // impl<T> Debug for HeaderField<T> where T: Debug {
@@ -22,6 +25,15 @@ pub struct HeaderField<T> {
 //     }
 // }
 
+impl<T> HeaderField<T> {
+    /// `value` at file `offset`, with `rva` the same as the offset, as for fields of the headers
+    /// and of parts of the file the loader does not map (see `Rva::of_header`).
+    #[inline]
+    pub fn at_offset(value: T, offset: u64) -> Self {
+        Self { value, offset: FileOffset(offset), rva: Rva::of_header(offset) }
+    }
+}
+
 impl<T> Display for HeaderField<T> where T: Display {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.value)
@@ -44,29 +56,53 @@ pub trait Header {
 }
 
 
+/// Input of the parsers: random access reads by file offset.
+///
+/// Implemented for `BufReader`, `Cursor` and, in `utils`, for fragments of an input
+/// (`FragmentReader`), sources read in ranges (`chunked::ChunkedReader`), HTTP servers
+/// (`http`, feature `http`) and memory-mapped files (`mmap`, feature `mmap`). Other inputs
+/// need `BufRead` and `Seek`; the provided methods use them.
 pub trait BufReadExt : BufRead + Seek {
     //#[allow(unused_variables)]
     fn read_string_at_offset(&mut self, offset: u64) -> Result<String, ReadExtError>{
         let mut buf:Vec<u8> = Vec::new();
         self.seek(SeekFrom::Start(offset))?;
         self.read_until(b'\0', &mut buf)?;
-        Ok(String::from_utf8(buf[..(buf.len()-1)].to_vec())?)
+        if buf.last() == Some(&0) {
+            buf.pop();
+        }
+        Ok(String::from_utf8(buf)?)
     }
 
     //#[allow(unused_variables)]
     fn read_bytes_at_offset(&mut self, offset: u64, size: usize) -> Result<Vec<u8>, ReadExtError> {
         let mut buf:Vec<u8> = vec![0; size];
         self.seek(SeekFrom::Start(offset))?;
-        self.read_exact(&mut buf)?;
+        self.read_exact(&mut buf).map_err(|err| ReadExtError::from_read(err, offset, size as u64))?;
         Ok(buf)
     }
 
+    /// Offset just past the last readable byte, i.e. the size of the input.
+    fn end_offset(&mut self) -> Result<u64, ReadExtError> {
+        Ok(self.seek(SeekFrom::End(0))?)
+    }
+
+    /// `read_bytes_at_offset` of the whole input, for readers that are a view of a part of it.
+    fn read_input_at(&mut self, offset: u64, size: usize) -> Result<Vec<u8>, ReadExtError> {
+        self.read_bytes_at_offset(offset, size)
+    }
+
+    /// `end_offset` of the whole input. See `read_input_at`.
+    fn input_end(&mut self) -> Result<u64, ReadExtError> {
+        self.end_offset()
+    }
+
     //#[allow(unused_variables)]
     fn read_wchar_string_at_offset(&mut self, offset: u64) -> Result<String, ReadExtError> {
         self.seek( SeekFrom::Start(offset))?;
-        let len = self.read_u16::<LittleEndian>()?;
+        let len = self.read_u16::<LittleEndian>().map_err(|err| ReadExtError::from_read(err, offset, 2))?;
         let mut buf = vec![0u16; len.into()];
-        self.read_u16_into::<LittleEndian>(&mut buf)?;
+        self.read_u16_into::<LittleEndian>(&mut buf).map_err(|err| ReadExtError::from_read(err, offset + 2, len as u64 * 2))?;
         Ok(String::from_utf16(&buf)?)
     }
 }
@@ -75,7 +111,32 @@ impl<T> BufReadExt for BufReader<T> where T: Read + Seek { }
 
 impl<T> BufReadExt for Cursor<T> where T: AsRef<[u8]> { }
 
-impl BufReadExt for Box<dyn BufReadExt + '_> { }
+/// Forwards to the boxed reader, so its own implementations are used.
+impl BufReadExt for Box<dyn BufReadExt + '_> {
+    fn read_string_at_offset(&mut self, offset: u64) -> Result<String, ReadExtError> {
+        (**self).read_string_at_offset(offset)
+    }
+
+    fn read_bytes_at_offset(&mut self, offset: u64, size: usize) -> Result<Vec<u8>, ReadExtError> {
+        (**self).read_bytes_at_offset(offset, size)
+    }
+
+    fn end_offset(&mut self) -> Result<u64, ReadExtError> {
+        (**self).end_offset()
+    }
+
+    fn read_input_at(&mut self, offset: u64, size: usize) -> Result<Vec<u8>, ReadExtError> {
+        (**self).read_input_at(offset, size)
+    }
+
+    fn input_end(&mut self) -> Result<u64, ReadExtError> {
+        (**self).input_end()
+    }
+
+    fn read_wchar_string_at_offset(&mut self, offset: u64) -> Result<String, ReadExtError> {
+        (**self).read_wchar_string_at_offset(offset)
+    }
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum ReadExtError {
@@ -90,4 +151,19 @@ pub enum ReadExtError {
 
     #[error("offset {offset} is less than base {base}")]
     OffsetBelowBase {base: u64, offset: u64},
+
+    #[error("{size} bytes at offset {offset:#x} go beyond end of data")]
+    Truncated {offset: u64, size: u64},
+}
+
+impl ReadExtError {
+    /// Error for a failed read of `size` bytes at `offset`; running out of data is `Truncated`, not an I/O error.
+    pub(crate) fn from_read(err: std::io::Error, offset: u64, size: u64) -> Self {
+        if err.kind() == std::io::ErrorKind::UnexpectedEof {
+            Self::Truncated { offset, size }
+        }
+        else {
+            Self::Seek(err)
+        }
+    }
 }
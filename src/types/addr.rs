@@ -0,0 +1,180 @@
+//! Typed addresses, so an RVA is not passed where a file offset is expected.
+//!
+//! `HeaderField::offset` is a `FileOffset` and `HeaderField::rva` an `Rva`; the `PeImage`
+//! conversions (`offset_of_rva`, `rva_of_offset`, `rva_of_va`, `va_of_rva`) take and return them.
+//! Each converts to and from its integer with `From`, and formats like it, e.g. with `{:08x}`.
+//! Adding to an address or taking the distance between two wraps around, like the integers
+//! did in the parsers before; malformed input must not panic in debug builds.
+
+use std::{fmt::{Display, LowerHex, UpperHex}, ops::{Add, AddAssign, Sub}};
+
+use serde::Serialize;
+
+/// Address relative to the image base, as in data directories and section headers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(transparent)]
+pub struct Rva(pub u32);
+
+/// Offset in the file (or, for images at a non-zero `pos`, in the image).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(transparent)]
+pub struct FileOffset(pub u64);
+
+/// Virtual address, i.e. the image base plus an RVA.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[cfg_attr(feature="schema", derive(schemars::JsonSchema))]
+#[serde(transparent)]
+pub struct Va(pub u64);
+
+macro_rules! address {
+    ($name:ident, $int:ty) => {
+        impl From<$int> for $name {
+            fn from(value: $int) -> Self {
+                Self(value)
+            }
+        }
+
+        impl From<$name> for $int {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{:#x}", self.0)
+            }
+        }
+
+        impl LowerHex for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                LowerHex::fmt(&self.0, f)
+            }
+        }
+
+        impl UpperHex for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                UpperHex::fmt(&self.0, f)
+            }
+        }
+
+        impl Add<$int> for $name {
+            type Output = Self;
+
+            fn add(self, delta: $int) -> Self {
+                Self(self.0.wrapping_add(delta))
+            }
+        }
+
+        impl AddAssign<$int> for $name {
+            fn add_assign(&mut self, delta: $int) {
+                self.0 = self.0.wrapping_add(delta);
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $int;
+
+            fn sub(self, other: Self) -> $int {
+                self.0.wrapping_sub(other.0)
+            }
+        }
+
+        impl $name {
+            #[inline]
+            pub fn checked_add(self, delta: $int) -> Option<Self> {
+                self.0.checked_add(delta).map(Self)
+            }
+        }
+    };
+}
+
+address!(Rva, u32);
+address!(FileOffset, u64);
+address!(Va, u64);
+
+/// Integer an `Rva` converts to and from; the type of RVAs in the signatures before `Rva`.
+pub type RawRva = u32;
+/// Integer a `FileOffset` converts to and from; the type of `HeaderField::offset` before `FileOffset`.
+pub type RawFileOffset = u64;
+/// Integer a `Va` converts to and from.
+pub type RawVa = u64;
+
+impl From<Rva> for u64 {
+    fn from(value: Rva) -> Self {
+        value.0.into()
+    }
+}
+
+impl TryFrom<u64> for Rva {
+    type Error = std::num::TryFromIntError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        u32::try_from(value).map(Self)
+    }
+}
+
+impl From<u32> for FileOffset {
+    fn from(value: u32) -> Self {
+        Self(value.into())
+    }
+}
+
+impl Rva {
+    /// RVA of the headers at file `offset`; the loader maps them at the image base as they are.
+    /// Offsets past 4 GiB, which no image can address, saturate instead of wrapping around.
+    #[inline]
+    pub fn of_header(offset: u64) -> Self {
+        Self(u32::try_from(offset).unwrap_or(u32::MAX))
+    }
+
+    /// VA of this RVA in an image loaded at `image_base`.
+    #[inline]
+    pub fn to_va(self, image_base: u64) -> Va {
+        Va(image_base.wrapping_add(self.0.into()))
+    }
+}
+
+impl Va {
+    /// RVA in an image loaded at `image_base`; `None` below the base or beyond 4 GiB from it.
+    #[inline]
+    pub fn to_rva(self, image_base: u64) -> Option<Rva> {
+        self.0.checked_sub(image_base)
+            .and_then(|rva| u32::try_from(rva).ok())
+            .map(Rva)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_test::{assert_ser_tokens, Token};
+
+    use super::{FileOffset, Rva, Va};
+
+    #[test]
+    fn convert_addresses() {
+        let rva = Rva::from(0x1234u32);
+        assert_eq!(rva.to_va(0x1_4000_0000), Va(0x1_4000_1234));
+        assert_eq!(Va(0x1_4000_1234).to_rva(0x1_4000_0000), Some(rva));
+        assert_eq!(Va(0x1000).to_rva(0x1_4000_0000), None);
+        assert_eq!(Va(0x2_4000_0000).to_rva(0x1_4000_0000), None);
+
+        assert_eq!(u64::from(rva), 0x1234);
+        assert_eq!(FileOffset::from(0x400u32), FileOffset(0x400));
+        assert_eq!(FileOffset(0x400) + 8, FileOffset(0x408));
+        assert_eq!(FileOffset(0x408) - FileOffset(0x400), 8);
+        assert_eq!(rva.checked_add(u32::MAX), None);
+        assert!(Rva::try_from(0x1_0000_0000u64).is_err());
+        assert_eq!(rva.to_string(), "0x1234");
+        assert_eq!(format!("{rva:08x}"), "00001234");
+        assert_ser_tokens(&FileOffset(0x400), &[Token::U64(0x400)]);
+    }
+
+    #[test]
+    fn header_rva_saturates() {
+        assert_eq!(Rva::of_header(0x400), Rva(0x400));
+        assert_eq!(Rva::of_header(0x1_0000_0400), Rva(u32::MAX));
+    }
+}
@@ -4,6 +4,21 @@ use byteorder::{LittleEndian, ReadBytesExt};
 
 use crate::types::{BufReadExt, ReadExtError};
 
+pub mod strings;
+pub mod chunked;
+#[cfg(feature="mmap")]
+pub mod mmap;
+#[cfg(feature="http")]
+pub mod http;
+
+/// Bytes of a larger input, e.g. a region of a memory dump or a block read from a file.
+///
+/// `content[0]` is at offset `base` of the input; every offset given to the reader, including
+/// `Seek` positions, is an offset in the input. Reads before `base` fail with `OffsetBelowBase`
+/// and reads past the end of `content` with `Truncated`.
+///
+/// To carve an image from a larger buffer, parse the reader with `pos` set to the offset of the
+/// image (see `examples/carve.rs`).
 pub struct FragmentReader {
     cursor: Cursor<Vec<u8>>,
     pub base: u64,
@@ -15,9 +30,22 @@ impl FragmentReader {
         Self { cursor, base }
     }
 
+    /// Offset just past the last byte of content.
+    pub fn end(&self) -> u64 {
+        self.base + self.cursor.get_ref().len() as u64
+    }
+
+    pub fn content(&self) -> &[u8] {
+        self.cursor.get_ref()
+    }
+
+    pub fn into_content(self) -> Vec<u8> {
+        self.cursor.into_inner()
+    }
+
     fn adjust_offset(&self, offset: u64) -> std::result::Result<u64, ReadExtError> {
         if offset < self.base {
-            return Err(ReadExtError::OffsetBelowBase { base: self.base, offset: offset })
+            return Err(ReadExtError::OffsetBelowBase { base: self.base, offset })
         }
         Ok(offset - self.base)
     }
@@ -39,37 +67,145 @@ impl BufRead for FragmentReader {
     }
 }
 
+/// Positions are offsets in the input, not in `content`.
 impl Seek for FragmentReader {
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
-        self.cursor.seek(pos)
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.end().checked_add_signed(delta),
+            SeekFrom::Current(delta) => (self.base + self.cursor.position()).checked_add_signed(delta),
+        };
+        let offset = offset.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "seek before start"))?;
+        let relative = self.adjust_offset(offset).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+        self.cursor.set_position(relative);
+        Ok(offset)
     }
 }
 
 impl BufReadExt for FragmentReader {
     fn read_string_at_offset(&mut self, offset: u64) -> std::result::Result<std::string::String, ReadExtError> {
-        let new_offset = self.adjust_offset(offset)?;
+        self.adjust_offset(offset)?;
+        if offset >= self.end() {
+            return Err(ReadExtError::Truncated { offset, size: 1 });
+        }
         let mut buf:Vec<u8> = Vec::new();
-        self.seek(SeekFrom::Start(new_offset))?;
+        self.seek(SeekFrom::Start(offset))?;
         self.read_until(b'\0', &mut buf)?;
-        Ok(String::from_utf8(buf[..(buf.len()-1)].to_vec())?)    
+        if buf.last() == Some(&0) {
+            buf.pop();
+        }
+        Ok(String::from_utf8(buf)?)
     }
 
     fn read_bytes_at_offset(&mut self, offset: u64, size: usize) -> Result<Vec<u8>, ReadExtError> {
-        let new_offset = self.adjust_offset(offset)?;
+        self.adjust_offset(offset)?;
         let mut buf:Vec<u8> = vec![0; size];
-        self.seek(SeekFrom::Start(new_offset))?;
-        self.read_exact(&mut buf)?;
+        self.seek(SeekFrom::Start(offset))?;
+        self.read_exact(&mut buf).map_err(|err| ReadExtError::from_read(err, offset, size as u64))?;
         Ok(buf)
     }
 
     fn read_wchar_string_at_offset(&mut self, offset: u64) -> Result<String, ReadExtError> {
-        let new_offset = self.adjust_offset(offset)?;
-        self.seek( SeekFrom::Start(new_offset))?;
-        let len = self.read_u16::<LittleEndian>()?;
+        self.adjust_offset(offset)?;
+        self.seek( SeekFrom::Start(offset))?;
+        let len = self.read_u16::<LittleEndian>().map_err(|err| ReadExtError::from_read(err, offset, 2))?;
         let mut buf = vec![0u16; len.into()];
-        self.read_u16_into::<LittleEndian>(&mut buf)?;
+        self.read_u16_into::<LittleEndian>(&mut buf).map_err(|err| ReadExtError::from_read(err, offset + 2, len as u64 * 2))?;
         Ok(String::from_utf16(&buf)?)
     }
+
+    fn end_offset(&mut self) -> Result<u64, ReadExtError> {
+        Ok(self.end())
+    }
+}
+
+
+/// View of `inner` from `base` on: offset 0 of the view is offset `base` of `inner`. Used to
+/// parse images embedded at a non-zero offset at their own file offsets.
+pub struct OffsetReader {
+    inner: Box<dyn BufReadExt>,
+    base: u64,
+}
+
+impl OffsetReader {
+    pub fn new(inner: Box<dyn BufReadExt>, base: u64) -> Self {
+        Self { inner, base }
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    pub fn into_inner(self) -> Box<dyn BufReadExt> {
+        self.inner
+    }
+
+    fn shift(&self, offset: u64) -> std::io::Result<u64> {
+        offset.checked_add(self.base).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "offset overflows"))
+    }
+
+    /// Offset in the view of `offset` in `inner`.
+    fn unshift(&self, offset: u64) -> std::io::Result<u64> {
+        offset.checked_sub(self.base).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "seek before start"))
+    }
+}
+
+impl Read for OffsetReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl BufRead for OffsetReader {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+impl Seek for OffsetReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let inner = match pos {
+            SeekFrom::Start(offset) => self.inner.seek(SeekFrom::Start(self.shift(offset)?))?,
+            SeekFrom::End(_) | SeekFrom::Current(_) => self.inner.seek(pos)?,
+        };
+        self.unshift(inner)
+    }
+}
+
+impl BufReadExt for OffsetReader {
+    fn read_string_at_offset(&mut self, offset: u64) -> Result<String, ReadExtError> {
+        let offset = self.shift(offset)?;
+        self.inner.read_string_at_offset(offset)
+    }
+
+    fn read_bytes_at_offset(&mut self, offset: u64, size: usize) -> Result<Vec<u8>, ReadExtError> {
+        let shifted = self.shift(offset)?;
+        self.inner.read_bytes_at_offset(shifted, size).map_err(|err| match err {
+            ReadExtError::Truncated { size, .. } => ReadExtError::Truncated { offset, size },
+            err => err,
+        })
+    }
+
+    fn read_wchar_string_at_offset(&mut self, offset: u64) -> Result<String, ReadExtError> {
+        let offset = self.shift(offset)?;
+        self.inner.read_wchar_string_at_offset(offset)
+    }
+
+    fn end_offset(&mut self) -> Result<u64, ReadExtError> {
+        Ok(self.inner.end_offset()?.saturating_sub(self.base))
+    }
+
+    fn read_input_at(&mut self, offset: u64, size: usize) -> Result<Vec<u8>, ReadExtError> {
+        self.inner.read_input_at(offset, size)
+    }
+
+    fn input_end(&mut self) -> Result<u64, ReadExtError> {
+        self.inner.input_end()
+    }
 }
 
 
@@ -93,9 +229,11 @@ pub(crate) fn flags_to_str<T>(value: &T) -> String
 
 #[cfg(test)]
 mod tests {
-    use std::str::FromStr;
+    use std::{io::{Seek, SeekFrom}, str::FromStr};
 
-    use super::{FragmentReader, BufReadExt};
+    use crate::types::ReadExtError;
+
+    use super::{FragmentReader, BufReadExt, OffsetReader};
 
     #[test]
     fn test_read_wchar_string_at_offset() {
@@ -103,4 +241,41 @@ mod tests {
         let str = reader.read_wchar_string_at_offset(0).unwrap();
         assert_eq!(str, String::from_str("AAAA").unwrap());
     }
+
+    #[test]
+    fn read_past_end_is_truncated() {
+        let mut reader = FragmentReader::new(vec![0u8; 0x10], 0x100);
+        let err = reader.read_bytes_at_offset(0x108, 0x10).unwrap_err();
+        assert!(matches!(err, ReadExtError::Truncated { offset: 0x108, size: 0x10 }));
+
+        let mut reader = FragmentReader::new([0x04u8, 0x00, 0x41, 0x00].to_vec(), 0);
+        let err = reader.read_wchar_string_at_offset(0).unwrap_err();
+        assert!(matches!(err, ReadExtError::Truncated { offset: 2, size: 8 }));
+    }
+
+    #[test]
+    fn offsets_from_view() {
+        let inner = FragmentReader::new(b"junkMZ\x00PE".to_vec(), 0);
+        let mut reader = OffsetReader::new(Box::new(inner), 4);
+        assert_eq!(reader.read_bytes_at_offset(0, 2).unwrap(), b"MZ");
+        assert_eq!(reader.read_string_at_offset(3).unwrap(), "PE");
+        assert_eq!(reader.end_offset().unwrap(), 5);
+        assert_eq!(reader.seek(SeekFrom::End(-2)).unwrap(), 3);
+        assert!(matches!(reader.read_bytes_at_offset(4, 2), Err(ReadExtError::Truncated { offset: 4, size: 2 })));
+    }
+
+    #[test]
+    fn offsets_from_base() {
+        let mut reader = FragmentReader::new(b"\x00\x00MZ\x00PE".to_vec(), 0x1000);
+        assert_eq!(reader.read_bytes_at_offset(0x1002, 2).unwrap(), b"MZ");
+        assert_eq!(reader.read_string_at_offset(0x1005).unwrap(), "PE");
+        assert_eq!(reader.end_offset().unwrap(), 0x1007);
+        assert_eq!(reader.seek(SeekFrom::End(-2)).unwrap(), 0x1005);
+
+        let err = reader.read_bytes_at_offset(0xfff, 2).unwrap_err();
+        assert!(matches!(err, ReadExtError::OffsetBelowBase { base: 0x1000, offset: 0xfff }));
+        assert!(reader.seek(SeekFrom::Start(0x10)).is_err());
+        let err = reader.read_string_at_offset(0x1007).unwrap_err();
+        assert!(matches!(err, ReadExtError::Truncated { offset: 0x1007, size: 1 }));
+    }
 }
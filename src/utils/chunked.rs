@@ -0,0 +1,175 @@
+//! `BufReadExt` over sources read in ranges, like remote files or paged memory.
+//!
+//! `ChunkedReader` fetches fixed size chunks from a `RangeSource` on demand and keeps the most
+//! recent ones, so parsing touches only the parts of the input the headers point to.
+
+use std::{collections::VecDeque, io::{self, BufRead, Read, Seek, SeekFrom}};
+
+use crate::types::BufReadExt;
+
+pub const DEFAULT_CHUNK_SIZE: usize = 0x10000;
+pub const DEFAULT_MAX_CHUNKS: usize = 16;
+
+/// Input read by ranges.
+pub trait RangeSource {
+    /// Size of the input.
+    fn size(&mut self) -> io::Result<u64>;
+
+    /// Read up to `size` bytes at `offset`; fewer only at the end of input.
+    fn read_range(&mut self, offset: u64, size: usize) -> io::Result<Vec<u8>>;
+}
+
+/// In-memory source, mostly for tests.
+impl RangeSource for Vec<u8> {
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(Vec::len(self) as u64)
+    }
+
+    fn read_range(&mut self, offset: u64, size: usize) -> io::Result<Vec<u8>> {
+        let start = (offset as usize).min(Vec::len(self));
+        let end = start.saturating_add(size).min(Vec::len(self));
+        Ok(self[start..end].to_vec())
+    }
+}
+
+pub struct ChunkedReader<S: RangeSource> {
+    source: S,
+    len: u64,
+    pos: u64,
+    chunk_size: usize,
+    max_chunks: usize,
+    /// Start offset and content of cached chunks, most recent last.
+    chunks: VecDeque<(u64, Vec<u8>)>,
+}
+
+impl<S: RangeSource> ChunkedReader<S> {
+    /// Reader with chunks of `DEFAULT_CHUNK_SIZE`, keeping `DEFAULT_MAX_CHUNKS`.
+    pub fn new(source: S) -> io::Result<Self> {
+        Self::with_chunk_size(source, DEFAULT_CHUNK_SIZE, DEFAULT_MAX_CHUNKS)
+    }
+
+    pub fn with_chunk_size(mut source: S, chunk_size: usize, max_chunks: usize) -> io::Result<Self> {
+        if chunk_size == 0 || max_chunks == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk size and count must not be 0"));
+        }
+        let len = source.size()?;
+        Ok(Self { source, len, pos: 0, chunk_size, max_chunks, chunks: VecDeque::new() })
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn into_source(self) -> S {
+        self.source
+    }
+
+    /// Index in `chunks` of the chunk holding `pos`, fetching it if needed.
+    fn chunk_at(&mut self, pos: u64) -> io::Result<usize> {
+        let start = pos - pos % self.chunk_size as u64;
+        if let Some(index) = self.chunks.iter().position(|(s, _)| *s == start) {
+            return Ok(index);
+        }
+
+        let data = self.source.read_range(start, self.chunk_size)?;
+        if self.chunks.len() == self.max_chunks {
+            self.chunks.pop_front();
+        }
+        self.chunks.push_back((start, data));
+        Ok(self.chunks.len() - 1)
+    }
+}
+
+impl<S: RangeSource> Read for ChunkedReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl<S: RangeSource> BufRead for ChunkedReader<S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.len {
+            return Ok(&[]);
+        }
+        let pos = self.pos;
+        let index = self.chunk_at(pos)?;
+        let (start, data) = &self.chunks[index];
+        let skip = ((pos - start) as usize).min(data.len());
+        Ok(&data[skip..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = self.pos.saturating_add(amt as u64).min(self.len);
+    }
+}
+
+impl<S: RangeSource> Seek for ChunkedReader<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = pos.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before start"))?;
+        Ok(self.pos)
+    }
+}
+
+impl<S: RangeSource> BufReadExt for ChunkedReader<S> {
+    fn end_offset(&mut self) -> Result<u64, crate::types::ReadExtError> {
+        Ok(self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io};
+
+    use crate::{pe::PeImage, types::{BufReadExt, ReadExtError}};
+
+    use super::{ChunkedReader, RangeSource};
+
+    /// Counts the ranges read.
+    struct Counted(Vec<u8>, usize);
+
+    impl RangeSource for Counted {
+        fn size(&mut self) -> io::Result<u64> {
+            Ok(self.0.len() as u64)
+        }
+
+        fn read_range(&mut self, offset: u64, size: usize) -> io::Result<Vec<u8>> {
+            self.1 += 1;
+            self.0.read_range(offset, size)
+        }
+    }
+
+    #[test]
+    fn reads_across_chunks() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let mut reader = ChunkedReader::with_chunk_size(Counted(bytes, 0), 0x10, 2).unwrap();
+
+        assert_eq!(reader.read_bytes_at_offset(0x0c, 8).unwrap(), (0x0c..0x14).collect::<Vec<u8>>());
+        assert_eq!(reader.source().1, 2);
+        assert_eq!(reader.read_bytes_at_offset(0x10, 4).unwrap(), vec![0x10, 0x11, 0x12, 0x13]);
+        assert_eq!(reader.source().1, 2);
+        assert_eq!(reader.end_offset().unwrap(), 0x100);
+        assert!(matches!(reader.read_bytes_at_offset(0xfe, 4), Err(ReadExtError::Truncated { offset: 0xfe, size: 4 })));
+    }
+
+    #[test]
+    fn parse_chunked() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+        let expected = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+
+        let reader = ChunkedReader::with_chunk_size(bytes, 0x1000, 4).unwrap();
+        let pe = PeImage::parse_readable(Box::new(reader), 0).unwrap();
+        assert_eq!(pe.sections.value.len(), expected.sections.value.len());
+        assert_eq!(pe.imports.value.len(), expected.imports.value.len());
+        assert_eq!(pe.exports.value.exports.len(), expected.exports.value.exports.len());
+    }
+}
@@ -0,0 +1,115 @@
+//! Images on HTTP servers, read with range requests as parsing needs them.
+//!
+//! The server must report `Content-Length` for `HEAD` and answer ranges with
+//! `206 Partial Content`.
+
+use std::io::{self, Read};
+
+use super::chunked::{ChunkedReader, RangeSource};
+
+pub struct HttpSource {
+    agent: ureq::Agent,
+    url: String,
+}
+
+impl HttpSource {
+    pub fn new(url: &str) -> Self {
+        Self { agent: ureq::Agent::new(), url: url.into() }
+    }
+
+    /// Source using `agent`, e.g. one with a proxy or timeouts.
+    pub fn with_agent(agent: ureq::Agent, url: &str) -> Self {
+        Self { agent, url: url.into() }
+    }
+}
+
+fn http_error(err: ureq::Error) -> io::Error {
+    io::Error::other(err)
+}
+
+impl RangeSource for HttpSource {
+    fn size(&mut self) -> io::Result<u64> {
+        let response = self.agent.head(&self.url).call().map_err(http_error)?;
+        response.header("Content-Length")
+            .and_then(|len| len.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))
+    }
+
+    fn read_range(&mut self, offset: u64, size: usize) -> io::Result<Vec<u8>> {
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        let range = format!("bytes={}-{}", offset, offset + size as u64 - 1);
+        let response = self.agent.get(&self.url).set("Range", &range).call().map_err(http_error)?;
+        if response.status() != 206 {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, format!("range request answered with {}", response.status())));
+        }
+
+        let mut data = Vec::with_capacity(size);
+        response.into_reader().take(size as u64).read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+pub type HttpReader = ChunkedReader<HttpSource>;
+
+/// Reader for the image at `url`, with default chunks.
+pub fn open(url: &str) -> io::Result<HttpReader> {
+    ChunkedReader::new(HttpSource::new(url))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::{BufRead, BufReader, Write}, net::TcpListener, thread};
+
+    use crate::pe::PeImage;
+
+    /// Serve `bytes` with range requests on a local port; returns the URL.
+    fn serve(bytes: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/test.dll", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut reader = BufReader::new(&stream);
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    request.push(line.trim().to_lowercase());
+                }
+
+                let range = request.iter()
+                    .find_map(|h| h.strip_prefix("range: bytes="))
+                    .and_then(|r| r.split_once('-'))
+                    .map(|(s, e)| (s.parse::<usize>().unwrap(), e.parse::<usize>().unwrap()));
+                let head = format!("HTTP/1.1 {} OK\r\nConnection: close\r\n", if range.is_some() { 206 } else { 200 });
+                let body = match range {
+                    Some((start, end)) => &bytes[start.min(bytes.len())..(end + 1).min(bytes.len())],
+                    None => &bytes[..],
+                };
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes()).unwrap();
+                if !request[0].starts_with("head") {
+                    stream.write_all(body).unwrap();
+                }
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn parse_remote() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+        let url = serve(bytes);
+
+        let reader = super::open(&url).unwrap();
+        let pe = PeImage::parse_readable(Box::new(reader), 0).unwrap();
+        assert!(!pe.imports.value.is_empty());
+    }
+}
@@ -0,0 +1,35 @@
+//! Memory-mapped files, to parse large images without reading them whole.
+//!
+//! `Cursor<Mmap>` is a `BufReadExt`, so the result of `map_file` can be passed to
+//! `PeImage::parse_readable` directly.
+
+use std::{fs::File, io::Cursor};
+
+use memmap2::Mmap;
+
+pub type MmapReader = Cursor<Mmap>;
+
+/// Map `file` read-only.
+///
+/// # Safety
+/// The file must not be changed or truncated while it is mapped; see `memmap2::Mmap::map`.
+pub unsafe fn map_file(file: &File) -> std::io::Result<MmapReader> {
+    Ok(Cursor::new(Mmap::map(file)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs::File};
+
+    use crate::pe::PeImage;
+
+    use super::map_file;
+
+    #[test]
+    fn parse_mapped() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let reader = unsafe { map_file(&File::open(path).unwrap()).unwrap() };
+        let pe = PeImage::parse_readable(Box::new(reader), 0).unwrap();
+        assert!(!pe.exports.value.exports.is_empty());
+    }
+}
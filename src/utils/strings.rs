@@ -0,0 +1,107 @@
+//! ASCII and UTF-16LE strings in raw bytes, like `strings -a` and `strings -el`.
+//!
+//! `PeImage::find_strings` scans the file or some of its sections and fills in RVAs.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+pub const DEFAULT_MIN_LENGTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Encoding {
+    Ascii,
+    Utf16le,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FoundString {
+    pub value: String,
+    pub encoding: Encoding,
+    /// File offset of the first byte.
+    pub offset: u64,
+    /// `None` outside sections.
+    pub rva: Option<u32>,
+}
+
+impl Display for FoundString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let encoding = match self.encoding {
+            Encoding::Ascii => 'A',
+            Encoding::Utf16le => 'U',
+        };
+        match self.rva {
+            Some(rva) => write!(f, "{:08x} {rva:08x} {encoding} {}", self.offset, self.value),
+            None => write!(f, "{:08x} -------- {encoding} {}", self.offset, self.value),
+        }
+    }
+}
+
+/// Printable text: ASCII graphic characters, space and tab.
+#[inline]
+fn is_text(byte: u8) -> bool {
+    byte == b'\t' || (0x20..0x7f).contains(&byte)
+}
+
+/// Runs of at least `min_len` text characters in `bytes`, as ASCII and as UTF-16LE, ordered
+/// by offset. `base` is the file offset of `bytes`; RVAs are left out.
+pub fn scan(bytes: &[u8], base: u64, min_len: usize) -> Vec<FoundString> {
+    let min_len = min_len.max(1);
+    let mut found = Vec::new();
+
+    let mut start = 0;
+    for (i, byte) in bytes.iter().chain([&0]).enumerate() {
+        if is_text(*byte) {
+            continue;
+        }
+        if i - start >= min_len {
+            //Text bytes are ASCII, so valid UTF-8.
+            let value = String::from_utf8_lossy(&bytes[start..i]).into_owned();
+            found.push(FoundString { value, encoding: Encoding::Ascii, offset: base + start as u64, rva: None });
+        }
+        start = i + 1;
+    }
+
+    //Wide strings at either alignment.
+    for align in 0..2 {
+        let words: Vec<&[u8]> = bytes.get(align..).unwrap_or_default().chunks_exact(2).collect();
+        let mut start = 0;
+        for (i, word) in words.iter().chain([&[0u8, 0].as_slice()]).enumerate() {
+            if is_text(word[0]) && word[1] == 0 {
+                continue;
+            }
+            if i - start >= min_len {
+                let value = words[start..i].iter().map(|w| w[0] as char).collect();
+                found.push(FoundString { value, encoding: Encoding::Utf16le, offset: base + (align + start * 2) as u64, rva: None });
+            }
+            start = i + 1;
+        }
+    }
+
+    found.sort_by_key(|s| s.offset);
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{scan, Encoding};
+
+    #[test]
+    fn ascii_and_wide() {
+        let mut bytes = b"\x01abc\0hello world\0\x02".to_vec();
+        bytes.extend([0xff, b'W', 0, b'i', 0, b'd', 0, b'e', 0, 0, 0]);
+        bytes.extend(b"tail");
+
+        let found = scan(&bytes, 0x400, 4);
+        let found: Vec<(&str, Encoding, u64)> = found.iter().map(|s| (s.value.as_str(), s.encoding, s.offset)).collect();
+        assert_eq!(found, [
+            ("hello world", Encoding::Ascii, 0x405),
+            ("Wide", Encoding::Utf16le, 0x413),
+            ("tail", Encoding::Ascii, 0x41d),
+        ]);
+
+        assert_eq!(scan(&bytes, 0, 3)[0].value, "abc");
+        assert_eq!(scan(&bytes, 0, 3)[0].to_string(), "00000001 -------- A abc");
+        assert!(scan(b"", 0, 4).is_empty());
+    }
+}
@@ -0,0 +1,53 @@
+//! JavaScript API for `wasm32-unknown-unknown`, e.g. for a PE viewer in the browser. Needs the
+//! `wasm` feature; bindings are generated with `wasm-bindgen`.
+//!
+//! Images are parsed from bytes and returned as plain objects in the minimal or full format of
+//! `pe::ser`; errors are thrown as `Error` with the chained message.
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::pe::{ser::{full::FullPeImage, min::MinPeImage}, PeImage};
+
+/// Parse `bytes` as a PE image; the error is the chained message.
+pub(crate) fn parse(bytes: &[u8]) -> Result<PeImage, String> {
+    PeImage::parse_bytes(bytes.to_vec(), 0).map_err(|err| err.chain())
+}
+
+/// `value` as a JavaScript object; maps become objects rather than `Map`s.
+fn to_js(value: &impl Serialize) -> Result<JsValue, JsError> {
+    value
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|err| JsError::new(&err.to_string()))
+}
+
+/// `parsePe(bytes)`: headers, sections and directories of the image in the minimal format.
+#[wasm_bindgen(js_name = parsePe)]
+pub fn parse_pe(bytes: &[u8]) -> Result<JsValue, JsError> {
+    let pe = parse(bytes).map_err(|err| JsError::new(&err))?;
+    to_js(&MinPeImage::from(&pe))
+}
+
+/// `parsePeFull(bytes)`: the image in the full format, with offsets and RVAs of every field.
+#[wasm_bindgen(js_name = parsePeFull)]
+pub fn parse_pe_full(bytes: &[u8]) -> Result<JsValue, JsError> {
+    let pe = parse(bytes).map_err(|err| JsError::new(&err))?;
+    to_js(&FullPeImage::from(&pe))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::parse;
+
+    #[test]
+    fn parse_bytes() {
+        let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+        let bytes = fs::read(path).unwrap();
+
+        let pe = parse(&bytes).unwrap();
+        assert!(!pe.sections.value.is_empty());
+        assert!(!parse(&bytes[..0x40]).unwrap_err().is_empty());
+    }
+}
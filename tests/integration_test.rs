@@ -31,4 +31,585 @@
      //eprintln!("{jstr}");
      assert!(jstr.contains("dos_header"));
  }
- 
\ No newline at end of file
+
+#[cfg(feature="json")]
+#[test]
+fn pe_to_full_json() {
+    use std::{env, fs::OpenOptions};
+
+    use rustbin::pe::{ser::full::FullPeImage, PeImage};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let file = OpenOptions::new().read(true).open(path).unwrap();
+
+    let mut pe = PeImage::parse_file(file, 0).unwrap();
+    pe.parse_import_directory().unwrap();
+    pe.parse_exports().unwrap();
+    pe.parse_relocations().unwrap();
+    pe.parse_resources().unwrap();
+
+    let full_pe = FullPeImage::from(&pe);
+    assert_eq!(full_pe.dos_header.e_magic.raw, b"MZ");
+    assert_eq!(full_pe.file_header.magic.raw, b"PE\0\0");
+    assert_eq!(full_pe.nt_headers.signature.raw, b"PE\0\0");
+    assert_eq!(full_pe.nt_headers.file_header_offset, full_pe.file_header.machine.offset);
+    assert!(full_pe.export_directory.is_some());
+    assert!(full_pe.relocations.as_ref().is_some_and(|blocks| !blocks.is_empty()));
+    assert!(full_pe.resources.is_some());
+
+    let jstr = serde_json::to_string_pretty(&full_pe).unwrap();
+    assert!(jstr.contains("\"raw\": \"4d5a\""));
+}
+
+#[cfg(feature="json")]
+#[test]
+fn full_json_directory_provenance() {
+    use std::{env, fs};
+
+    use rustbin::pe::{optional::DirectoryType, ser::full::{FullOptions, FullPeImage}, PeImage};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let mut pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+
+    let full_pe = FullPeImage::with_options(&mut pe, &FullOptions { directory_bytes: 8 });
+    let import = &full_pe.data_directories[DirectoryType::Import as usize];
+    assert_eq!(import.file_offset, pe.directory_offset(DirectoryType::Import));
+    assert_eq!(import.section.as_deref(), Some(".rdata"));
+    assert_eq!(import.head, pe.read_bytes_at_rva(import.rva.value, 8).unwrap());
+
+    //Certificates are in the overlay; the rva is the file offset.
+    let security = &full_pe.data_directories[DirectoryType::Security as usize];
+    assert_eq!(security.file_offset, Some(security.rva.value));
+    assert_eq!(security.section, None);
+    assert_eq!(security.head[..4], security.size.value.to_le_bytes());
+
+    let empty = &full_pe.data_directories[DirectoryType::Architecture as usize];
+    assert!(empty.file_offset.is_none() && empty.head.is_empty());
+
+    let full_pe = FullPeImage::from(&pe);
+    assert!(full_pe.data_directories.iter().all(|dir| dir.head.is_empty()));
+}
+
+//TE image made by stripping headers of test.dll; must be picked up by `ParseAs::PE`.
+#[test]
+fn te_from_stripped_pe() {
+    use std::{env, fs::{self, OpenOptions}};
+
+    use rustbin::{parse_file, pe::{te, PeImage}, ParseAs, ParsedAs};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let bytes = fs::read(&path).unwrap();
+    let pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+
+    //Everything before the section table.
+    let stripped = pe.dos.value.e_lfanew.value as usize + 24 + pe.file.value.optional_header_size.value as usize;
+    let reloc_dir = &pe.data_dirs.value[5].value;
+
+    let mut te_bytes = Vec::new();
+    te_bytes.extend(te::TE_SIGNATURE.to_le_bytes());
+    te_bytes.extend((pe.file.value.machine.value as u16).to_le_bytes());
+    te_bytes.push(pe.sections.value.len() as u8);
+    te_bytes.push(10); //EFI_APPLICATION
+    te_bytes.extend((stripped as u16).to_le_bytes());
+    te_bytes.extend(0x1000u32.to_le_bytes());
+    te_bytes.extend(0x1000u32.to_le_bytes());
+    te_bytes.extend(0x1_8000_0000u64.to_le_bytes());
+    te_bytes.extend(reloc_dir.rva.value.to_le_bytes());
+    te_bytes.extend(reloc_dir.size.value.to_le_bytes());
+    te_bytes.extend([0u8; 8]);
+    te_bytes.extend(&bytes[stripped..]);
+
+    let te_path = env::temp_dir().join("rustbin_te_from_stripped_pe.efi");
+    fs::write(&te_path, &te_bytes).unwrap();
+    let file = OpenOptions::new().read(true).open(&te_path).unwrap();
+    let parsed = parse_file(file, ParseAs::PE).unwrap();
+    fs::remove_file(&te_path).unwrap();
+
+    let ParsedAs::TE(te) = parsed else { panic!("TE image parsed as PE") };
+    assert_eq!(te.entry_point(), 0x1000);
+    assert_eq!(te.sections.value.len(), pe.sections.value.len());
+    assert_eq!(te.sections.value[0].value.name_str().unwrap(), pe.sections.value[0].value.name_str().unwrap());
+
+    let rva = pe.sections.value[1].value.virtual_address.value + 0x10;
+    let te_offset = te.rva_to_offset(rva).unwrap();
+    assert_eq!(te_offset as usize + stripped - te::HEADER_LENGTH as usize, pe.rva_to_offset(rva).unwrap() as usize);
+    assert_eq!(te.offset_to_rva(te_offset.into()), Some(rva));
+
+    assert!(te.has_relocations());
+    assert_eq!(te.relocations.value.blocks.len(), pe.relocations.value.blocks.len());
+}
+
+#[test]
+fn export_pages_match_parsed_count() {
+    use std::{env, fs};
+
+    use rustbin::pe::PeImage;
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let mut pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+    let count = pe.export_count().unwrap();
+
+    let paged: Vec<_> = pe.export_pages(1).collect::<Result<Vec<_>, _>>().unwrap().into_iter().flatten().collect();
+    assert_eq!(count, 2);
+    assert_eq!(paged.len(), count as usize);
+    assert_eq!(pe.exports.value.export_count(), count);
+}
+
+#[test]
+fn parse_limits_are_enforced() {
+    use std::{env, fs, io::Cursor};
+
+    use rustbin::pe::{PeError, PeImage};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let mut pe = PeImage::new(Box::new(Cursor::new(fs::read(path).unwrap())));
+    pe.limits.max_exports = 1;
+
+    let err = pe.parse_all_headers(0).unwrap_err();
+    assert!(matches!(err.root(), PeError::TooMany { count: 2, max: 1, .. }), "{err}");
+    assert!(matches!(err, PeError::Context { offset: 0x10f30, .. }), "{err}");
+    assert_eq!(err.chain(), "while parsing Export directory at 0x10f30: Export functions has 2 entries, more than allowed 1");
+}
+
+#[test]
+fn resolve_imports_from_search_path() {
+    use std::{env, fs};
+
+    use rustbin::pe::{deps::{ExportMap, ImportStatus, ModuleExports}, import::ImportedFunction, PeImage};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let mut pe = PeImage::parse_bytes(fs::read(&path).unwrap(), 0).unwrap();
+
+    let exports = ModuleExports::from_image(&mut pe).unwrap();
+    assert!(exports.names.contains("g_thread_init_with_errorcheck_mutexes"));
+    assert!(exports.ordinals.contains(&(pe.exports.value.base.value as u16)));
+    assert!(exports.forwarders.is_empty());
+
+    //test.dll standing in for one of its own dependencies.
+    let search_path = env::temp_dir().join(format!("rustbin-deps-{}", std::process::id()));
+    fs::create_dir_all(&search_path).unwrap();
+    fs::copy(&path, search_path.join("LIBGLIB-2.0-0.DLL")).unwrap();
+
+    let mut map = ExportMap::load(["libglib-2.0-0.dll", "KERNEL32.dll"], std::slice::from_ref(&search_path));
+    fs::remove_dir_all(&search_path).unwrap();
+    assert_eq!(map.len(), 1);
+
+    let report = pe.resolve_imports(&map);
+    assert_eq!(report.missing_modules, vec!["KERNEL32.dll"]);
+    assert!(!report.is_complete());
+    let g_log = report.imports.iter().find(|i| i.function == ImportedFunction::Name("g_log".into())).unwrap();
+    assert_eq!(g_log.status, ImportStatus::MissingSymbol);
+
+    map.insert("libglib-2.0-0.dll", ModuleExports::from_names(["g_log", "g_assertion_message_expr"]));
+    map.insert("kernel32.dll", ModuleExports::from_names(pe.imports.value[1].value.get_imports_str()));
+    let report = pe.resolve_imports(&map);
+    assert!(report.is_complete());
+    assert_eq!(report.resolved().count(), report.imports.len());
+}
+
+#[test]
+fn dependency_tree_detects_cycles() {
+    use std::{env, fs};
+
+    use rustbin::pe::deps::{DependencyTree, NodeKind, TreeOptions};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+
+    //Copies of test.dll stand in for both of its dependencies, importing each other.
+    let search_path = env::temp_dir().join(format!("rustbin-tree-{}", std::process::id()));
+    fs::create_dir_all(&search_path).unwrap();
+    fs::copy(&path, search_path.join("libglib-2.0-0.dll")).unwrap();
+    fs::copy(&path, search_path.join("kernel32.dll")).unwrap();
+
+    let options = TreeOptions { search_paths: vec![search_path.clone()], ..Default::default() };
+    let tree = DependencyTree::build(&path, &options).unwrap();
+
+    let root = &tree.root;
+    assert_eq!(root.name, "test.dll");
+    let kinds: Vec<_> = root.children.iter().map(|c| (c.name.as_str(), c.kind.clone())).collect();
+    assert_eq!(kinds, vec![("libglib-2.0-0.dll", NodeKind::Module), ("KERNEL32.dll", NodeKind::Seen)]);
+
+    let glib = &root.children[0];
+    assert_eq!(glib.children[0].kind, NodeKind::Cycle);
+    assert_eq!(glib.children[1].kind, NodeKind::Module);
+    assert!(glib.children[1].children.iter().all(|c| c.kind == NodeKind::Cycle));
+    //Neither copy exports what test.dll imports.
+    assert!(!tree.is_complete());
+
+    let options = TreeOptions { max_depth: 0, ..options };
+    let tree = DependencyTree::build(&path, &options).unwrap();
+    fs::remove_dir_all(&search_path).unwrap();
+    assert!(tree.root.children.iter().all(|c| c.kind == NodeKind::TooDeep));
+}
+
+#[test]
+fn import_table_merges_directories() {
+    use std::{env, fs};
+
+    use rustbin::pe::{import::table::ImportMechanism, PeImage};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let mut pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+    pe.parse_import_directory().unwrap();
+    pe.parse_delay_imports().unwrap();
+    pe.parse_bound_imports().unwrap();
+    assert!(pe.status.delay_imports.is_done());
+    assert!(pe.status.bound_imports.is_done());
+
+    let table = pe.import_table();
+    assert_eq!(table.len(), pe.imports.value.len());
+    for (module, desc) in table.iter().zip(pe.imports.value.iter()) {
+        assert_eq!(Some(&module.module), desc.value.name.as_ref());
+        assert_eq!(module.imports.len(), desc.value.imports.len());
+        assert!(module.imports.iter().all(|i| i.mechanism == ImportMechanism::Static && i.bound_address.is_none()));
+        assert_eq!(module.imports[0].thunk_rva, desc.value.first_thunk.value);
+    }
+    assert!(table[0].to_string().starts_with(&format!("{}: [", table[0].module)));
+}
+
+#[test]
+fn data_directory_count_from_optional_header() {
+    use std::{env, fs};
+
+    use rustbin::pe::{optional::DirectoryType, PeImage};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let mut bytes = fs::read(path).unwrap();
+    let lfanew = u32::from_le_bytes(bytes[0x3c..0x40].try_into().unwrap()) as usize;
+    let opt = lfanew + 24;
+    let count_at = opt + if bytes[opt + 1] == 0x02 { 108 } else { 92 };
+    bytes[count_at..count_at + 4].copy_from_slice(&2u32.to_le_bytes());
+
+    let pe = PeImage::parse_bytes(bytes, 0).unwrap();
+    assert_eq!(pe.data_dirs.value.len(), 2);
+    assert!(pe.has_imports());
+    assert!(pe.directory(DirectoryType::Resource).is_none());
+    assert!(pe.directory(DirectoryType::Reserved).is_none());
+    assert!(!pe.has_rsrc() && !pe.has_relocations());
+}
+
+#[test]
+fn yara_strings_match_file_bytes() {
+    use std::{env, fs};
+
+    use rustbin::pe::{yara::{format_rule, YaraStringKind}, PeImage};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let bytes = fs::read(path).unwrap();
+    let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+    pe.parse_import_directory().unwrap();
+    pe.parse_exports().unwrap();
+    pe.parse_resources().unwrap();
+
+    let rich = pe.rich_header().unwrap().unwrap();
+    assert_eq!(&bytes[rich.offset as usize + rich.raw.len() - 8..][..4], b"Rich");
+    assert!(!rich.entries.is_empty());
+
+    let strings = pe.yara_strings().unwrap();
+    for s in &strings {
+        assert_eq!(&bytes[s.offset as usize..s.end() as usize], s.bytes.as_slice(), "{s}");
+    }
+    for kind in [YaraStringKind::ImportModule, YaraStringKind::ImportName, YaraStringKind::RichHeader] {
+        assert!(strings.iter().any(|s| s.kind == kind), "{kind:?}");
+    }
+
+    let rule = format_rule("test.dll", &strings);
+    assert!(rule.starts_with("rule test_dll {\n  strings:\n"));
+    assert!(rule.contains("$rich_0 = { "));
+}
+
+#[test]
+fn nt_signature_is_validated() {
+    use std::{env, fs};
+
+    use rustbin::pe::{nt, PeError, PeImage};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let mut bytes = fs::read(path).unwrap();
+    let lfanew = u32::from_le_bytes(bytes[0x3c..0x40].try_into().unwrap()) as usize;
+
+    let pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+    assert!(pe.nt.value.is_valid());
+    assert_eq!(u64::from(pe.nt.offset), lfanew as u64);
+    assert_eq!(pe.nt.value.size, nt::SIGNATURE_LENGTH + 20 + pe.file.value.optional_header_size.value as u64);
+    assert_eq!(pe.sections.offset, pe.nt.offset + pe.nt.value.size);
+
+    bytes[lfanew + 1] = b'X';
+    let err = PeImage::parse_bytes(bytes, 0).unwrap_err();
+    assert!(matches!(err.root(), PeError::InvalidHeader { name, offset, .. } if name == "NtHeaders" && *offset == lfanew as u64), "{err}");
+}
+
+#[test]
+fn coff_symbols_from_file_header() {
+    use std::{env, fs};
+
+    use rustbin::pe::{coff::StorageClass, PeImage};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let mut bytes = fs::read(path).unwrap();
+    let lfanew = u32::from_le_bytes(bytes[0x3c..0x40].try_into().unwrap()) as usize;
+
+    let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+    assert!(!pe.has_symbols());
+    pe.parse_symbols().unwrap();
+    assert!(!pe.status.symbols.is_parsed() && pe.status.symbols.is_done());
+
+    //Append a table of two symbols and point the file header to it.
+    let table = bytes.len() as u32;
+    bytes.extend(b"DllMain\0");
+    bytes.extend([0x10, 0, 0, 0, 1, 0, 0x20, 0, 2, 0]);
+    bytes.extend([0, 0, 0, 0, 4, 0, 0, 0]);
+    bytes.extend([0x20, 0, 0, 0, 1, 0, 0x20, 0, 3, 0]);
+    bytes.extend(20u32.to_le_bytes());
+    bytes.extend(b"g_thread_helper\0");
+    bytes[lfanew + 12..lfanew + 16].copy_from_slice(&table.to_le_bytes());
+    bytes[lfanew + 16..lfanew + 20].copy_from_slice(&2u32.to_le_bytes());
+
+    let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+    pe.parse_symbols().unwrap();
+    assert!(pe.status.symbols.is_parsed());
+    let symbols = &pe.symbols.value.symbols;
+    assert_eq!(symbols.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), vec!["DllMain", "g_thread_helper"]);
+    assert_eq!(symbols[1].storage_class.value, StorageClass::STATIC);
+    assert_eq!(u64::from(pe.symbols.offset), table as u64);
+
+    //Without the string table, long names fail, with where the table is.
+    bytes.truncate(bytes.len() - 20);
+    let mut pe = PeImage::parse_bytes(bytes, 0).unwrap();
+    let err = pe.parse_symbols().unwrap_err();
+    assert!(err.chain().starts_with(&format!("while parsing CoffSymbolTable at {table:#x}")), "{}", err.chain());
+}
+
+#[test]
+fn archive_detected_and_members_parsed() {
+    use std::{env, fs::{self, OpenOptions}};
+
+    use rustbin::{ar::MemberKind, parse_file, parse_path, pe::file::MachineType, ParseAs, ParsedAs};
+
+    //AMD64 object with one section and no symbols.
+    let mut obj = vec![0x64, 0x86, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    obj.extend(b".text\0\0\0");
+    obj.extend([0u8; 32]);
+
+    let mut bytes = b"!<arch>\n".to_vec();
+    bytes.extend(format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", "main.obj/", 0, 0, 0, 644, obj.len()).as_bytes());
+    bytes.extend(&obj);
+
+    let dir = env::temp_dir();
+    let (lib_path, obj_path) = (dir.join("rustbin_archive.lib"), dir.join("rustbin_archive.obj"));
+    fs::write(&lib_path, &bytes).unwrap();
+    fs::write(&obj_path, &obj).unwrap();
+    let parsed = parse_file(OpenOptions::new().read(true).open(&lib_path).unwrap(), ParseAs::PE).unwrap();
+    let parsed_obj = parse_path(&obj_path, ParseAs::Coff).unwrap();
+    fs::remove_file(&lib_path).unwrap();
+    fs::remove_file(&obj_path).unwrap();
+
+    let ParsedAs::Archive(mut archive) = parsed else { panic!("archive not detected") };
+    assert_eq!(archive.members.len(), 1);
+    assert_eq!((archive.members[0].name.as_str(), archive.members[0].kind), ("main.obj", MemberKind::Object));
+
+    let member = archive.parse_object(0).unwrap();
+    assert_eq!(member.file.value.machine.value, MachineType::AMD64);
+    assert_eq!(u64::from(member.file.offset), 8 + 60);
+    assert_eq!(member.section_name(0).as_deref(), Some(".text"));
+
+    let ParsedAs::Coff(obj) = parsed_obj else { panic!("object not parsed as COFF") };
+    assert_eq!(u64::from(obj.sections.offset), 20);
+}
+
+#[test]
+fn strings_in_file_and_sections() {
+    use std::{env, fs};
+
+    use rustbin::{pe::{PeError, PeImage}, utils::strings::Encoding};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let bytes = fs::read(path).unwrap();
+    let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+
+    let all = pe.find_strings(6, &[]).unwrap();
+    assert!(all.iter().any(|s| s.value.contains("This program cannot be run in DOS mode") && s.rva.is_none()));
+    assert!(all.iter().any(|s| s.value == "VS_VERSION_INFO" && s.encoding == Encoding::Utf16le));
+
+    let rdata = pe.find_strings(6, &[".rdata"]).unwrap();
+    assert!(!rdata.is_empty());
+    for s in &rdata {
+        assert!(s.value.len() >= 6);
+        assert_eq!(pe.rva_to_offset(s.rva.unwrap()), Some(s.offset as u32));
+        if s.encoding == Encoding::Ascii {
+            assert_eq!(&bytes[s.offset as usize..][..s.value.len()], s.value.as_bytes());
+        }
+    }
+
+    assert!(matches!(pe.find_strings(6, &[".nope"]), Err(PeError::NoSection(_))));
+}
+
+#[test]
+fn parsed_values_compare_and_clone() {
+    use std::{env, fs};
+
+    use rustbin::pe::PeImage;
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let bytes = fs::read(path).unwrap();
+    let mut first = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+    let mut second = PeImage::parse_bytes(bytes, 0).unwrap();
+    for pe in [&mut first, &mut second] {
+        pe.parse_import_directory().unwrap();
+        pe.parse_exports().unwrap();
+        pe.parse_resources().unwrap();
+    }
+
+    assert_eq!(first.imports, second.imports);
+    assert_eq!(first.exports, second.exports);
+    assert_eq!(first.resources, second.resources);
+
+    let mut imports = first.imports.value.clone();
+    assert_eq!(imports, second.imports.value);
+    imports[0].value.name = Some("other.dll".into());
+    assert_ne!(imports, second.imports.value);
+}
+
+#[test]
+fn section_data_is_cached() {
+    use std::{env, fs, sync::Arc};
+
+    use rustbin::pe::{PeError, PeImage};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let mut pe = PeImage::parse_bytes(fs::read(path).unwrap(), 0).unwrap();
+    let index = pe.sections.value.iter().position(|s| s.value.name_lossy() == ".rdata").unwrap();
+
+    let by_name = pe.section_data(".rdata").unwrap();
+    let by_index = pe.section_data(index).unwrap();
+    assert!(Arc::ptr_eq(&by_name, &by_index));
+    assert_eq!(by_name.len(), pe.sections.value[index].value.sizeof_raw_data.value as usize);
+    assert_eq!(&by_name[..], &pe.read_section_data(index).unwrap()[..]);
+
+    pe.reindex_sections();
+    assert!(!Arc::ptr_eq(&by_name, &pe.section_data(index).unwrap()));
+    assert!(matches!(pe.section_data(".nope"), Err(PeError::NoSection(_))));
+    assert!(pe.section_data(pe.sections.value.len()).is_err());
+}
+
+#[cfg(feature="disasm")]
+#[test]
+fn disassemble_entry_and_exports() {
+    use std::env;
+
+    use rustbin::pe::PeImage;
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let mut pe = PeImage::parse_bytes(std::fs::read(path).unwrap(), 0).unwrap();
+    pe.parse_exports().unwrap();
+
+    let entry = pe.disassemble_entry(4).unwrap();
+    assert_eq!(entry.len(), 4);
+    assert_eq!(entry[0].rva, 0x1210);
+    assert_eq!(entry[0].va, 0x180001210);
+    assert_eq!(entry[0].text, "mov [rsp+8],rbx");
+    assert_eq!(entry[1].rva, entry[0].rva + entry[0].bytes.len() as u32);
+
+    let exports = pe.disassemble_exports(2);
+    assert_eq!(exports.len(), pe.exports.value.exports.len());
+    let (name, first) = &exports[0];
+    assert_eq!(name, "g_thread_init");
+    assert_eq!(first.as_ref().unwrap()[0].text, "test rcx,rcx");
+}
+
+#[test]
+fn edit_imports() {
+    use std::env;
+
+    use rustbin::pe::edit::{imports::IMPORT_SECTION, PeEditor};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let bytes = std::fs::read(path).unwrap();
+    let mut editor = PeEditor::new(bytes).unwrap();
+    let module_names = |editor: &PeEditor| editor.image().imports.value.iter().map(|d| d.value.name.clone().unwrap()).collect::<Vec<_>>();
+    let modules = module_names(&editor);
+    let sections = editor.image().sections.value.len();
+    let (dll, function) = {
+        let first = &editor.image().imports.value[0].value;
+        (first.name.clone().unwrap(), first.imports[0].import_name().unwrap().name.value.clone())
+    };
+    let slot = editor.import_slot(&dll, &function).unwrap();
+
+    editor.add_import("WS2_32.dll", "WSAStartup").unwrap();
+    let pe = editor.image();
+    assert_eq!(pe.sections.value.len(), sections + 1);
+    assert_eq!(pe.sections.value.last().unwrap().value.name_lossy(), IMPORT_SECTION);
+    let mut added = modules.clone();
+    added.push("WS2_32.dll".into());
+    assert_eq!(module_names(&editor), added);
+    assert_eq!(editor.import_slot(&dll, &function), Some(slot));
+
+    editor.remove_import("WS2_32.dll", "WSAStartup").unwrap();
+    editor.remove_import(&dll, &function).unwrap();
+    assert_eq!(editor.image().sections.value.len(), sections + 1);
+    assert_eq!(modules, vec!["libglib-2.0-0.dll", "KERNEL32.dll"]);
+    assert_eq!(module_names(&editor), modules);
+    assert!(editor.import_slot(&dll, &function).is_none());
+}
+
+#[test]
+fn strip_build_details() {
+    use std::env;
+
+    use rustbin::pe::{edit::PeEditor, optional::DirectoryType};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let bytes = std::fs::read(path).unwrap();
+    let len = bytes.len();
+    let mut editor = PeEditor::new(bytes).unwrap();
+    let pdb = editor.image().debug.value.codeview.as_ref().unwrap().value.pdb_path.clone();
+
+    assert!(editor.strip_debug_directory().unwrap());
+    assert!(!editor.image().has_directory(DirectoryType::Debug));
+    assert!(!editor.bytes().windows(pdb.len()).any(|w| w == pdb.as_bytes()));
+
+    assert!(editor.strip_rich_header().unwrap());
+    assert!(!editor.strip_rich_header().unwrap());
+
+    assert!(editor.zero_timestamps().unwrap() > 0);
+    assert!(editor.image().timestamps().iter().filter(|t| t.source.is_own()).all(|t| t.value.raw() == 0));
+    assert_eq!(editor.bytes().len(), len);
+}
+
+#[test]
+fn normalize_keeps_linker_sizes() {
+    use std::env;
+
+    use rustbin::pe::PeImage;
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let bytes = std::fs::read(path).unwrap();
+    let mut pe = PeImage::parse_bytes(bytes.clone(), 0).unwrap();
+
+    let normalized = PeImage::parse_bytes(pe.normalize().unwrap(), 0).unwrap();
+    assert_eq!(normalized.optional.value.sizeof_image(), pe.optional.value.sizeof_image());
+    assert_eq!(normalized.optional.value.sizeof_headers(), pe.optional.value.sizeof_headers());
+    assert_eq!(normalized.sections.value, pe.sections.value);
+    assert_ne!(normalized.optional.value.checksum().value, 0);
+}
+
+#[test]
+fn relocated_refs_to_rva() {
+    use std::env;
+
+    use rustbin::pe::{relocs::RelocType, PeImage};
+
+    let path = env::current_dir().unwrap().join("test-data").join("test.dll");
+    let mut pe = PeImage::parse_bytes(std::fs::read(path).unwrap(), 0).unwrap();
+
+    let (at, _) = pe.relocations.value.relocs_in_range(0, u32::MAX)
+        .find(|(_, rtype)| *rtype == RelocType::DIR64)
+        .unwrap();
+    let va = u64::from_le_bytes(pe.read_bytes_at_rva(at, 8).unwrap().try_into().unwrap());
+    let target = (va - pe.optional.value.image_base()) as u32;
+
+    let refs = pe.find_refs_to_rva(target).unwrap();
+    let reference = refs.iter().find(|r| r.rva == Some(at)).unwrap();
+    assert_eq!(reference.size, 8);
+    assert!(reference.relocated);
+}
@@ -0,0 +1,88 @@
+//! Golden-file tests of the CLI output for `test-data/test.dll`.
+//! Changes to formatting or serialization show up as diffs of `tests/snapshots/*.snap`.
+//! Run with `UPDATE_SNAPSHOTS=1` to accept the new output.
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+fn snapshot_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("snapshots").join(format!("{name}.snap"))
+}
+
+fn run_cli(args: &[&str]) -> String {
+    let target = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test-data").join("test.dll");
+    let output = Command::new(env!("CARGO_BIN_EXE_rustbin"))
+        .arg(target)
+        .args(args)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "rustbin {args:?} failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap().replace("\r\n", "\n")
+}
+
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = snapshot_path(name);
+
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("missing snapshot {path:?}, run with UPDATE_SNAPSHOTS=1 to create it"))
+        .replace("\r\n", "\n");
+
+    if expected != actual {
+        let new_path = path.with_extension("snap.new");
+        fs::write(&new_path, actual).unwrap();
+
+        let (line, (old, new)) = expected.lines().chain(std::iter::repeat(""))
+            .zip(actual.lines().chain(std::iter::repeat("")))
+            .enumerate()
+            .find(|(_, (old, new))| old != new)
+            .unwrap();
+        panic!("snapshot {name} changed at line {}:\n- {old}\n+ {new}\nnew output is in {new_path:?}, run with UPDATE_SNAPSHOTS=1 to accept it", line + 1);
+    }
+}
+
+#[test]
+fn text_display() {
+    assert_snapshot("text_display", &run_cli(&["-f", "text", "-l", "display"]));
+}
+
+#[cfg(feature="json")]
+#[test]
+fn json_minimal() {
+    assert_snapshot("json_minimal", &run_cli(&["-f", "json", "-l", "minimal"]));
+}
+
+#[cfg(feature="json")]
+#[test]
+fn json_full() {
+    assert_snapshot("json_full", &run_cli(&["-f", "json", "-l", "full"]));
+}
+
+#[test]
+fn stdin_target_and_binary_output() {
+    use std::{io::Write, process::Stdio};
+
+    let target = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test-data").join("test.dll");
+    let bytes = fs::read(&target).unwrap();
+    let run_stdin = |args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rustbin"))
+            .arg("-")
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(&bytes).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "rustbin - {args:?} failed");
+        output.stdout
+    };
+
+    let text = String::from_utf8(run_stdin(&["-f", "text", "-l", "display"])).unwrap().replace("\r\n", "\n");
+    assert_eq!(text, run_cli(&["-f", "text", "-l", "display"]));
+    assert_eq!(run_stdin(&["--output-binary"]), bytes);
+}